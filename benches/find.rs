@@ -7,7 +7,7 @@ use std::{
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use fxhash::FxBuildHasher;
 use indexmap::set::IndexSet;
-use packed_simd_2::u8x32;
+use packed_simd_2::{u8x32, u8x64};
 use rayon::prelude::*;
 
 use common::Record;
@@ -102,6 +102,149 @@ fn array_simd_ordered_find(data: &[Record], hays: &[Record]) -> usize {
     found
 }
 
+/// Sorted cursor over the saved hashes stored as array of structs - the account index is loaded
+/// together with each compared hash
+fn cursor_tuples(data: &[Record], hays: &[(Record, usize)]) -> usize {
+    let mut decided = 0;
+    let mut current = 0;
+    for x in data {
+        let packed_x = u8x32::from_slice_unaligned(x).lex_ord();
+        while let Some((hay, index)) = hays.get(current) {
+            match packed_x.cmp(&u8x32::from_slice_unaligned(hay).lex_ord()) {
+                Ordering::Less => break,
+                Ordering::Equal | Ordering::Greater => {
+                    decided += index;
+                    current += 1;
+                }
+            }
+        }
+    }
+
+    decided
+}
+
+/// Sorted cursor over the saved hashes stored as structure of arrays - the comparisons only touch
+/// the contiguous hashes and the position of the cursor is the account index
+fn cursor_arrays(data: &[Record], hays: &[Record]) -> usize {
+    let mut decided = 0;
+    let mut current = 0;
+    for x in data {
+        let packed_x = u8x32::from_slice_unaligned(x).lex_ord();
+        while let Some(hay) = hays.get(current) {
+            match packed_x.cmp(&u8x32::from_slice_unaligned(hay).lex_ord()) {
+                Ordering::Less => break,
+                Ordering::Equal | Ordering::Greater => {
+                    decided += current;
+                    current += 1;
+                }
+            }
+        }
+    }
+
+    decided
+}
+
+/// Order of the line to both saved hashes using a single 512 bit compare against the broadcast line.
+/// The pair is loaded directly, because the saved hashes are contiguous.
+fn pair_orders(pair: &[Record], line: &Record, broadcast: u8x64) -> [Ordering; 2] {
+    let not_equal = !u8x64::from_slice_unaligned(pair.as_flattened())
+        .eq(broadcast)
+        .bitmask();
+    let order = |index: usize| {
+        let half = (not_equal >> (index * 32)) & u64::from(u32::MAX);
+        if half == 0 {
+            Ordering::Equal
+        } else {
+            let lane = half.trailing_zeros() as usize;
+            line[lane].cmp(&pair[index][lane])
+        }
+    };
+
+    [order(0), order(1)]
+}
+
+/// Like `cursor_arrays`, but once the line passed the current saved hash, the following ones are
+/// compared two per 512 bit instruction against the broadcast line
+fn cursor_pairs(data: &[Record], hays: &[Record]) -> usize {
+    let mut decided = 0;
+    let mut current = 0;
+    for x in data {
+        let packed_x = u8x32::from_slice_unaligned(x).lex_ord();
+        match hays.get(current) {
+            Some(hay) if packed_x < u8x32::from_slice_unaligned(hay).lex_ord() => continue,
+            Some(_) => {
+                decided += current;
+                current += 1;
+            }
+            None => break,
+        }
+
+        let mut doubled = [0; 64];
+        doubled[..32].copy_from_slice(x);
+        doubled[32..].copy_from_slice(x);
+        let broadcast = u8x64::from_slice_unaligned(&doubled);
+
+        'line: while current < hays.len() {
+            for pair in hays[current..hays.len().min(current + 4)].chunks(2) {
+                let orders = if pair.len() == 2 {
+                    pair_orders(pair, x, broadcast)
+                } else {
+                    [packed_x.cmp(&u8x32::from_slice_unaligned(&pair[0]).lex_ord()); 2]
+                };
+
+                for order in &orders[..pair.len()] {
+                    if *order == Ordering::Less {
+                        break 'line;
+                    }
+
+                    decided += current;
+                    current += 1;
+                }
+            }
+        }
+    }
+
+    decided
+}
+
+/// Like `cursor_arrays`, but once the line passed the current saved hash, batches of four saved
+/// hashes are skipped by comparing only their last hash
+fn cursor_skip(data: &[Record], hays: &[Record]) -> usize {
+    let mut decided = 0;
+    let mut current = 0;
+    for x in data {
+        let packed_x = u8x32::from_slice_unaligned(x).lex_ord();
+        match hays.get(current) {
+            Some(hay) if packed_x < u8x32::from_slice_unaligned(hay).lex_ord() => continue,
+            Some(_) => {
+                decided += current;
+                current += 1;
+            }
+            None => break,
+        }
+
+        while let Some(last) = hays.get(current + 3) {
+            if packed_x < u8x32::from_slice_unaligned(last).lex_ord() {
+                break;
+            }
+
+            decided += (current..current + 4).sum::<usize>();
+            current += 4;
+        }
+
+        while let Some(hay) = hays.get(current) {
+            if packed_x < u8x32::from_slice_unaligned(hay).lex_ord() {
+                break;
+            }
+
+            decided += current;
+            current += 1;
+        }
+    }
+
+    decided
+}
+
 /// Struct which uses 512 bit SIMD for identity comparisons
 ///
 /// We disregard clippy here, because this structs only one field and eq is only our custom impl
@@ -192,6 +335,66 @@ fn find_benchmark(c: &mut Criterion) {
     group.finish()
 }
 
+fn layout_benchmark(c: &mut Criterion) {
+    const DATA_SIZE: usize = 100_000;
+
+    let mut group = c.benchmark_group("Layout");
+
+    let mut data_sorted = common::create_scrambled_data(DATA_SIZE);
+    data_sorted.sort_unstable();
+
+    for &hay_size in &[1_000, 10_000, 100_000] {
+        let mut hays = common::create_scrambled_data(hay_size);
+        hays.sort_unstable();
+
+        let tuples: Vec<(Record, usize)> = hays.iter().copied().zip(0..).collect();
+
+        let id = BenchmarkId::new("AoS (Tuples)", hay_size);
+        group.bench_with_input(id, &tuples, |b, hays| {
+            b.iter(|| cursor_tuples(&data_sorted, hays));
+        });
+
+        let id = BenchmarkId::new("SoA (Arrays)", hay_size);
+        group.bench_with_input(id, &hays, |b, hays| {
+            b.iter(|| cursor_arrays(&data_sorted, hays));
+        });
+    }
+
+    group.finish()
+}
+
+fn batch_benchmark(c: &mut Criterion) {
+    const DATA_SIZE: usize = 10_000;
+
+    let mut group = c.benchmark_group("Batch");
+
+    let mut data_sorted = common::create_scrambled_data(DATA_SIZE);
+    data_sorted.sort_unstable();
+
+    // large vaults have multiple saved hashes between two corpus lines
+    for &hay_size in &[1_000, 100_000, 1_000_000] {
+        let mut hays = common::create_scrambled_data(hay_size);
+        hays.sort_unstable();
+
+        let id = BenchmarkId::new("Single", hay_size);
+        group.bench_with_input(id, &hays, |b, hays| {
+            b.iter(|| cursor_arrays(&data_sorted, hays));
+        });
+
+        let id = BenchmarkId::new("SIMD (512 Pair)", hay_size);
+        group.bench_with_input(id, &hays, |b, hays| {
+            b.iter(|| cursor_pairs(&data_sorted, hays));
+        });
+
+        let id = BenchmarkId::new("Skip", hay_size);
+        group.bench_with_input(id, &hays, |b, hays| {
+            b.iter(|| cursor_skip(&data_sorted, hays));
+        });
+    }
+
+    group.finish()
+}
+
 // generate main method
-criterion_group!(benches, find_benchmark);
+criterion_group!(benches, find_benchmark, layout_benchmark, batch_benchmark);
 criterion_main!(benches);
@@ -1,3 +1,5 @@
+use std::convert::TryInto;
+
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use crossbeam_channel::{bounded, Receiver};
 use crossbeam_utils::thread;
@@ -81,6 +83,27 @@ fn hash_func(input_bytes: &[u8]) -> Digest {
     digest(&SHA1_FOR_LEGACY_USE_ONLY, input_bytes)
 }
 
+/// sequential hashing with ring - the default backend
+fn backend_ring(data: &[Record]) -> Vec<[u8; 20]> {
+    data.iter()
+        .map(|x| hash_func(x).as_ref().try_into().unwrap())
+        .collect()
+}
+
+/// sequential hashing with the pure Rust backend
+#[cfg(feature = "sha1-smol")]
+fn backend_smol(data: &[Record]) -> Vec<[u8; 20]> {
+    data.iter()
+        .map(|x| sha1_smol::Sha1::from(x).digest().bytes())
+        .collect()
+}
+
+/// sequential hashing with the OpenSSL backend
+#[cfg(feature = "sha1-openssl")]
+fn backend_openssl(data: &[Record]) -> Vec<[u8; 20]> {
+    data.iter().map(|x| openssl::sha::sha1(x)).collect()
+}
+
 /// create random bytes of data with each exactly 32 characters in size
 fn create_scrambled_data(size: usize) -> Vec<Record> {
     (0..size)
@@ -129,6 +152,28 @@ fn hashing_benchmark(c: &mut Criterion) {
     group.finish()
 }
 
+/// Compare the SHA-1 backends selectable by features. Enable them all to compare them:
+/// `cargo bench --bench hashing --features sha1-smol,sha1-openssl -- Backends`
+fn backend_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Backends");
+
+    let size = *common::SIZE_ARRAY.last().unwrap();
+    let data = create_scrambled_data(size);
+    let mut bench = |name: &str, fut: fn(&[Record]) -> Vec<[u8; 20]>| {
+        group.bench_with_input(BenchmarkId::new(name, size), &data[..], |b, input| {
+            b.iter_with_large_drop(|| fut(input));
+        });
+    };
+
+    bench("ring", backend_ring);
+    #[cfg(feature = "sha1-smol")]
+    bench("sha1_smol", backend_smol);
+    #[cfg(feature = "sha1-openssl")]
+    bench("openssl", backend_openssl);
+
+    group.finish()
+}
+
 // generate main method
-criterion_group!(benches, hashing_benchmark);
+criterion_group!(benches, hashing_benchmark, backend_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,100 @@
+use std::{fs, io::Write};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use data_encoding::HEXUPPER;
+use memmap2::{Mmap, MmapOptions};
+use rand::prelude::*;
+
+/// Large enough that the mapping spans many huge pages (2 MB on x86_64)
+const LINES: usize = 4_000_000;
+
+/// Hash list in the `HASH:count` format of the HIBP downloads
+fn create_hash_list() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut data = Vec::with_capacity(LINES * 48);
+    for _ in 0..LINES {
+        let hash: [u8; 20] = rng.gen();
+        writeln!(
+            data,
+            "{}:{}",
+            HEXUPPER.encode(&hash),
+            rng.gen_range(1..10_000)
+        )
+        .unwrap();
+    }
+
+    data
+}
+
+/// Sum the counts of the lines starting with the byte like a linear scan touching each page
+fn scan(data: &[u8], first: u8) -> u64 {
+    let mut sum = 0;
+    for line in data.split(|&byte| byte == b'\n') {
+        if line.first() == Some(&first) {
+            sum += line[41..]
+                .iter()
+                .fold(0, |count, digit| count * 10 + u64::from(digit - b'0'));
+        }
+    }
+
+    sum
+}
+
+#[cfg(unix)]
+fn advise(map: &Mmap, advice: i32) {
+    let ret = unsafe { libc::madvise(map.as_ptr() as *mut libc::c_void, map.len(), advice) };
+    if ret != 0 {
+        eprintln!(
+            "Advice {} is not supported {}",
+            advice,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// End-to-end scan of a mapped hash list with the advices of the hash database mapping. The file
+/// is in the page cache after the first iteration, so the difference is the page fault and TLB
+/// overhead. Huge pages only help on filesystems with large folios (ex: XFS, tmpfs).
+fn mapping_benchmark(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("pwned-check-bench-{}", std::process::id()));
+    fs::write(&path, create_hash_list()).unwrap();
+    let file = fs::File::open(&path).unwrap();
+
+    let mut group = c.benchmark_group("Mapping");
+    group.sample_size(20);
+
+    #[allow(unused_mut)]
+    let mut advices = vec![("Default", None)];
+    #[cfg(unix)]
+    advices.push(("Sequential", Some(libc::MADV_SEQUENTIAL)));
+    #[cfg(target_os = "linux")]
+    advices.push(("Sequential (DontDump)", Some(libc::MADV_DONTDUMP)));
+    #[cfg(target_os = "linux")]
+    advices.push(("Sequential (HugePage)", Some(libc::MADV_HUGEPAGE)));
+
+    for (name, advice) in advices {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                // mapped for each scan like each run of pwned-check
+                let map = unsafe { MmapOptions::new().map(&file) }.unwrap();
+
+                #[cfg(unix)]
+                if let Some(advice) = advice {
+                    if advice != libc::MADV_SEQUENTIAL {
+                        advise(&map, libc::MADV_SEQUENTIAL);
+                    }
+
+                    advise(&map, advice);
+                }
+
+                scan(&map, b'A')
+            });
+        });
+    }
+
+    group.finish();
+    fs::remove_file(path).unwrap();
+}
+
+criterion_group!(benches, mapping_benchmark);
+criterion_main!(benches);
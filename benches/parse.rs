@@ -0,0 +1,211 @@
+use std::cmp::Ordering;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use data_encoding::HEXUPPER;
+use memchr::memchr_iter;
+use packed_simd_2::{shuffle, u8x16, u8x32};
+
+mod common;
+
+/// Hash database lines like `HASH:count` of the scrambled records
+fn create_corpus(size: usize) -> Vec<u8> {
+    let mut records = common::create_scrambled_data(size);
+    records.sort_unstable();
+
+    let mut corpus = Vec::new();
+    for (count, record) in records.iter().enumerate() {
+        corpus.extend_from_slice(HEXUPPER.encode(&record[..20]).as_bytes());
+        corpus.extend_from_slice(format!(":{}\n", count).as_bytes());
+    }
+
+    corpus
+}
+
+fn decode_data_encoding(hex: &[u8], output: &mut [u8]) -> bool {
+    HEXUPPER.decode_mut(hex, output).is_ok()
+}
+
+/// Same algorithm as `find::hex` - two overlapping 32 character blocks for SHA-1
+fn decode_simd(hex: &[u8], output: &mut [u8]) -> bool {
+    decode_block(&hex[..32], &mut output[..16]) && decode_block(&hex[8..40], &mut output[4..20])
+}
+
+fn decode_block(hex: &[u8], output: &mut [u8]) -> bool {
+    let chars = u8x32::from_slice_unaligned(hex);
+    let digits = chars - u8x32::splat(b'0');
+    let letters = chars - u8x32::splat(b'A');
+    let is_digit = digits.lt(u8x32::splat(10));
+    let is_letter = letters.lt(u8x32::splat(6));
+    if !(is_digit | is_letter).all() {
+        return false;
+    }
+
+    let nibbles = is_digit.select(digits, letters + u8x32::splat(10));
+    let high: u8x16 = shuffle!(
+        nibbles,
+        [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30]
+    );
+    let low: u8x16 = shuffle!(
+        nibbles,
+        [1, 3, 5, 7, 9, 11, 13, 15, 17, 19, 21, 23, 25, 27, 29, 31]
+    );
+
+    ((high << 4) | low).write_to_slice_unaligned(output);
+    true
+}
+
+/// Split the lines and decode each hash like the linear scan
+fn scan(corpus: &[u8], decode: fn(&[u8], &mut [u8]) -> bool) -> usize {
+    let mut hash = [0; 32];
+    let mut valid = 0;
+    let mut line_start = 0;
+    for line_end in memchr_iter(b'\n', corpus) {
+        if decode(&corpus[line_start..line_start + 40], &mut hash[..20]) {
+            valid += usize::from(hash[0]);
+        }
+
+        line_start = line_end + 1;
+    }
+
+    valid
+}
+
+/// Uppercase hex check without decoding - two overlapping 32 character blocks for SHA-1
+fn is_upper_hex(hex: &[u8]) -> bool {
+    let valid = |block: &[u8]| {
+        let chars = u8x32::from_slice_unaligned(block);
+        let digit = (chars - u8x32::splat(b'0')).lt(u8x32::splat(10));
+        let letter = (chars - u8x32::splat(b'A')).lt(u8x32::splat(6));
+        (digit | letter).all()
+    };
+
+    valid(&hex[..32]) && valid(&hex[8..40])
+}
+
+/// Lexicographic order of 40 hex characters using two overlapping 32 byte compares
+fn cmp_hex(line: &[u8], saved: &[u8]) -> Ordering {
+    let block = |hex: &[u8], start: usize| u8x32::from_slice_unaligned(&hex[start..start + 32]);
+    block(line, 0)
+        .lex_ord()
+        .cmp(&block(saved, 0).lex_ord())
+        .then_with(|| block(line, 8).lex_ord().cmp(&block(saved, 8).lex_ord()))
+}
+
+/// Sorted cursor over the saved hashes - decoding each line first
+fn match_decoded(corpus: &[u8], saved: &[[u8; 32]]) -> usize {
+    let mut hash = [0; 32];
+    let mut current = 0;
+    let mut found = 0;
+    let mut line_start = 0;
+    for line_end in memchr_iter(b'\n', corpus) {
+        let line = &corpus[line_start..line_end];
+        line_start = line_end + 1;
+        if !decode_simd(&line[..40], &mut hash[..20]) {
+            break;
+        }
+
+        let packed = u8x32::from_slice_unaligned(&hash).lex_ord();
+        while let Some(next) = saved.get(current) {
+            match packed.cmp(&u8x32::from_slice_unaligned(next).lex_ord()) {
+                Ordering::Less => break,
+                Ordering::Equal => found += 1,
+                Ordering::Greater => {}
+            }
+
+            current += 1;
+        }
+    }
+
+    found
+}
+
+/// Sorted cursor over the hex encoded saved hashes - only validating each line
+fn match_hex(corpus: &[u8], saved: &[[u8; 40]]) -> usize {
+    let mut current = 0;
+    let mut found = 0;
+    let mut line_start = 0;
+    for line_end in memchr_iter(b'\n', corpus) {
+        let line = &corpus[line_start..line_end];
+        line_start = line_end + 1;
+        if !is_upper_hex(&line[..40]) {
+            break;
+        }
+
+        while let Some(next) = saved.get(current) {
+            match cmp_hex(line, next) {
+                Ordering::Less => break,
+                Ordering::Equal => found += 1,
+                Ordering::Greater => {}
+            }
+
+            current += 1;
+        }
+    }
+
+    found
+}
+
+fn compare_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Compare");
+
+    let corpus = create_corpus(1_000_000);
+    let lines: Vec<&[u8]> = corpus.split(|&b| b == b'\n').collect();
+    for &saved_size in &[10, 1_000, 100_000] {
+        // every n-th line as saved hash, so the cursor finds some of them
+        let step = lines.len() / saved_size;
+        let hex: Vec<[u8; 40]> = lines
+            .iter()
+            .step_by(step)
+            .take(saved_size)
+            .map(|line| {
+                let mut hex = [0; 40];
+                hex.copy_from_slice(&line[..40]);
+                hex
+            })
+            .collect();
+        let decoded: Vec<[u8; 32]> = hex
+            .iter()
+            .map(|hex| {
+                let mut hash = [0; 32];
+                HEXUPPER.decode_mut(hex, &mut hash[..20]).unwrap();
+                hash
+            })
+            .collect();
+
+        let id = BenchmarkId::new("Decoded", saved_size);
+        group.bench_with_input(id, &decoded, |b, saved| {
+            b.iter(|| match_decoded(&corpus, saved));
+        });
+
+        let id = BenchmarkId::new("Hex", saved_size);
+        group.bench_with_input(id, &hex, |b, saved| {
+            b.iter(|| match_hex(&corpus, saved));
+        });
+    }
+
+    group.finish()
+}
+
+fn hex_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Hex");
+
+    for &size in &common::SIZE_ARRAY {
+        let corpus = create_corpus(size * 10);
+
+        let id = BenchmarkId::new("data-encoding", size * 10);
+        group.bench_with_input(id, &corpus, |b, corpus| {
+            b.iter(|| scan(corpus, decode_data_encoding));
+        });
+
+        let id = BenchmarkId::new("SIMD", size * 10);
+        group.bench_with_input(id, &corpus, |b, corpus| {
+            b.iter(|| scan(corpus, decode_simd));
+        });
+    }
+
+    group.finish()
+}
+
+// generate main method
+criterion_group!(benches, hex_benchmark, compare_benchmark);
+criterion_main!(benches);
@@ -1,5 +1,9 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use packed_simd_2::u8x32;
+use std::{cmp::Ordering, convert::TryInto};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use packed_simd_2::u8x64;
+use packed_simd_2::{u8x16, u8x32};
 use rand::prelude::*;
 use rayon::prelude::*;
 
@@ -27,6 +31,136 @@ fn simd_equal_threaded(data: &[Record], hay: &Record) -> bool {
         .any(|x| u8x32::from_slice_unaligned(x).eq(hay).all())
 }
 
+/// lexicographic order using two 128 bit instructions like on NEON
+fn ord_halves(data: &[Record], hay: &Record) -> usize {
+    data.iter()
+        .filter(|x| {
+            let head = u8x16::from_slice_unaligned(&x[..16])
+                .lex_ord()
+                .cmp(&u8x16::from_slice_unaligned(&hay[..16]).lex_ord());
+            let order = head.then_with(|| {
+                u8x16::from_slice_unaligned(&x[16..])
+                    .lex_ord()
+                    .cmp(&u8x16::from_slice_unaligned(&hay[16..]).lex_ord())
+            });
+            order == Ordering::Less
+        })
+        .count()
+}
+
+/// lexicographic order of two big-endian 128 bit integers - the fallback for ARM
+fn ord_integer(data: &[Record], hay: &Record) -> usize {
+    let half = |record: &Record, start: usize| {
+        u128::from_be_bytes(record[start..start + 16].try_into().unwrap())
+    };
+
+    let hay = (half(hay, 0), half(hay, 16));
+    data.iter()
+        .filter(|x| (half(x, 0), half(x, 16)) < hay)
+        .count()
+}
+
+/// lexicographic order using a single 256 bit instruction like on AVX2
+fn ord_full(data: &[Record], hay: &Record) -> usize {
+    let hay = u8x32::from_slice_unaligned(hay).lex_ord();
+    data.iter()
+        .filter(|x| u8x32::from_slice_unaligned(*x).lex_ord() < hay)
+        .count()
+}
+
+type OrdFn = unsafe fn(&[Record], &Record) -> usize;
+
+#[inline(always)]
+fn cmp_full(left: &Record, right: &Record) -> Ordering {
+    u8x32::from_slice_unaligned(left)
+        .lex_ord()
+        .cmp(&u8x32::from_slice_unaligned(right).lex_ord())
+}
+
+/// the loop with the inlined compare - compiled for the target features of the caller
+#[inline(always)]
+fn ord_loop(data: &[Record], hay: &Record) -> usize {
+    data.iter()
+        .filter(|x| cmp_full(hay, x) == Ordering::Greater)
+        .count()
+}
+
+unsafe fn ord_loop_baseline(data: &[Record], hay: &Record) -> usize {
+    ord_loop(data, hay)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn ord_loop_avx2(data: &[Record], hay: &Record) -> usize {
+    ord_loop(data, hay)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512bw")]
+unsafe fn ord_loop_avx512(data: &[Record], hay: &Record) -> usize {
+    ord_loop(data, hay)
+}
+
+/// two records per 512 bit compare against the broadcast hay
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512bw")]
+unsafe fn ord_loop_pair(data: &[Record], hay: &Record) -> usize {
+    let mut packed_hay = [0; 64];
+    packed_hay[..32].copy_from_slice(hay);
+    packed_hay[32..].copy_from_slice(hay);
+    let broadcast = u8x64::from_slice_unaligned(&packed_hay);
+
+    let order = |pair: &[Record], not_equal: u64, index: usize| {
+        let half = (not_equal >> (index * 32)) & u64::from(u32::MAX);
+        if half == 0 {
+            Ordering::Equal
+        } else {
+            let lane = half.trailing_zeros() as usize;
+            hay[lane].cmp(&pair[index][lane])
+        }
+    };
+
+    let pairs = data.chunks_exact(2);
+    let remainder = ord_loop(pairs.remainder(), hay);
+    pairs
+        .map(|pair| {
+            let not_equal = !u8x64::from_slice_unaligned(pair.as_flattened())
+                .eq(broadcast)
+                .bitmask();
+            (0..2)
+                .filter(|&index| order(pair, not_equal, index) == Ordering::Greater)
+                .count()
+        })
+        .sum::<usize>()
+        + remainder
+}
+
+/// the loop selected once through a function pointer like the scans do - black_box keeps the call
+/// indirect
+fn ord_dispatch(data: &[Record], hay: &Record, scan: OrdFn) -> usize {
+    // Safety: only called with functions of detected features
+    unsafe { black_box(scan)(data, hay) }
+}
+
+fn ord_dispatch_baseline(data: &[Record], hay: &Record) -> usize {
+    ord_dispatch(data, hay, ord_loop_baseline)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn ord_dispatch_avx2(data: &[Record], hay: &Record) -> usize {
+    ord_dispatch(data, hay, ord_loop_avx2)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn ord_dispatch_avx512(data: &[Record], hay: &Record) -> usize {
+    ord_dispatch(data, hay, ord_loop_avx512)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn ord_dispatch_pair(data: &[Record], hay: &Record) -> usize {
+    ord_dispatch(data, hay, ord_loop_pair)
+}
+
 fn simd_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("SIMD");
 
@@ -58,6 +192,24 @@ fn simd_benchmark(c: &mut Criterion) {
         gen_bench!("Threaded", normal_equal_threaded);
         gen_bench!("SIMD", simd_equal);
         gen_bench!("SIMD-Threaded", simd_equal_threaded);
+
+        gen_bench!("SIMD-Ord (128)", ord_halves);
+        gen_bench!("Ord (128 Integer)", ord_integer);
+        gen_bench!("SIMD-Ord (256)", ord_full);
+
+        // loops selected at runtime - compiled for the detected features like the scans
+        gen_bench!("Dispatch (Baseline)", ord_dispatch_baseline);
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                gen_bench!("Dispatch (256 AVX2)", ord_dispatch_avx2);
+            }
+
+            if is_x86_feature_detected!("avx512bw") {
+                gen_bench!("Dispatch (512 AVX-512)", ord_dispatch_avx512);
+                gen_bench!("Dispatch (512 Pair AVX-512)", ord_dispatch_pair);
+            }
+        }
     }
 
     // recommended but not necessary
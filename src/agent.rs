@@ -0,0 +1,42 @@
+use std::{sync::OnceLock, time::Duration};
+
+use log::debug;
+use ureq::AgentBuilder;
+
+/// The HIBP API requires a descriptive User-Agent - the project URL identifies the client for its
+/// operator
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "pwned-check/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/games647/pwned_check)"
+);
+
+/// Timeout for connecting and each read or write - a download itself could take hours
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// User-Agent of all requests - set once at the start from the arguments or the config
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+
+/// Use the User-Agent (ex: of an organization embedding the online mode) for all clients created
+/// afterwards. Only the first call has an effect.
+pub fn set_user_agent(user_agent: String) {
+    if USER_AGENT.set(user_agent).is_err() {
+        debug!("Ignoring the User-Agent, because it's already set");
+    }
+}
+
+/// Configured User-Agent or the default one
+pub fn user_agent() -> &'static str {
+    USER_AGENT.get().map_or(DEFAULT_USER_AGENT, String::as_str)
+}
+
+/// Builder of the HTTP clients with the configured User-Agent and the timeouts. All clients (ex:
+/// the online mode and the Vault client) should be created from it, so the requests are identified
+/// and bounded the same way.
+pub fn agent_builder() -> AgentBuilder {
+    AgentBuilder::new()
+        .user_agent(user_agent())
+        .timeout_connect(REQUEST_TIMEOUT)
+        .timeout_read(REQUEST_TIMEOUT)
+        .timeout_write(REQUEST_TIMEOUT)
+}
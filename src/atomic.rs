@@ -0,0 +1,312 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(any(feature = "http", feature = "index"))]
+use std::fs::TryLockError;
+
+#[cfg(any(feature = "http", feature = "index"))]
+use log::warn;
+
+/// Number of temporary files created by this process - part of their names
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// File that replaces the target only after all data was written. The data goes to a temporary
+/// file in the same directory, which is synced and renamed over the target on `commit`. A crash or
+/// a full disk (ENOSPC) leaves the previous file or none, but never a truncated one that later runs
+/// would trust. The temporary file is deleted if it's dropped without a commit.
+#[derive(Debug)]
+pub struct AtomicFile {
+    target: PathBuf,
+    temp: PathBuf,
+    /// Only taken by the commit
+    file: Option<File>,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn create(target: &Path) -> io::Result<Self> {
+        Self::open(target, false)
+    }
+
+    /// Only readable by the current user (ex: keys)
+    #[cfg(feature = "crypto")]
+    pub fn create_private(target: &Path) -> io::Result<Self> {
+        Self::open(target, true)
+    }
+
+    fn open(target: &Path, private: bool) -> io::Result<Self> {
+        let temp = temp_path(target);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        #[cfg(not(unix))]
+        let _ = private;
+
+        Ok(AtomicFile {
+            target: target.to_path_buf(),
+            file: Some(options.open(&temp)?),
+            temp,
+            committed: false,
+        })
+    }
+
+    /// Flush the data to the disk and replace the target
+    pub fn commit(mut self) -> io::Result<()> {
+        // closed before the rename, because Windows cannot rename open files
+        let file = self.file()?;
+        file.sync_all()?;
+        self.file = None;
+
+        fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        sync_parent(&self.target)
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("File is already committed"))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.file = None;
+            // best effort - the temporary name is never read
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Write the file using the closure and replace the target only if it succeeded
+pub fn atomic_write<T, E: From<io::Error>>(
+    target: &Path,
+    write: impl FnOnce(&mut AtomicFile) -> Result<T, E>,
+) -> Result<T, E> {
+    let mut file = AtomicFile::create(target)?;
+    let result = write(&mut file)?;
+    file.commit()?;
+    Ok(result)
+}
+
+/// Hidden name next to the target - unique per process and file, so concurrent runs and threads
+/// never share it
+fn temp_path(target: &Path) -> PathBuf {
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".{}-{}.tmp", process::id(), counter));
+    target.with_file_name(name)
+}
+
+/// Exclusive lock of a target for long running writers like downloads and conversions. Locking the
+/// target itself wouldn't serialize them, because each one renames a new file over it. Therefore a
+/// hidden lock file next to it is locked. It's kept afterwards - deleting it could let a waiting
+/// writer lock a file that no longer exists. Readers don't need it, because they keep the file that
+/// was renamed over.
+#[cfg(any(feature = "http", feature = "index"))]
+#[derive(Debug)]
+pub struct WriterLock {
+    /// Released by the OS when it's closed
+    _file: File,
+}
+
+#[cfg(any(feature = "http", feature = "index"))]
+impl WriterLock {
+    /// Fails with `WouldBlock` if another process writes the target. File systems without lock
+    /// support are only logged.
+    pub fn acquire(target: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(target))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "Output is written by another process",
+                ));
+            }
+            Err(TryLockError::Error(err)) => warn!(
+                "Failed to lock {:?} - concurrent writers aren't detected {}",
+                target, err
+            ),
+        }
+
+        Ok(WriterLock { _file: file })
+    }
+}
+
+#[cfg(any(feature = "http", feature = "index"))]
+fn lock_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".lock");
+    target.with_file_name(name)
+}
+
+/// Persist the rename itself - otherwise a crash could still restore the old directory entry
+#[cfg(unix)]
+fn sync_parent(target: &Path) -> io::Result<()> {
+    let parent = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+/// Windows doesn't support opening directories for syncing
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    fn target(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("pwned-check-atomic-{}-{}", process::id(), name))
+    }
+
+    /// Temporary files of the target that weren't deleted
+    fn has_temp_files(target: &Path) -> bool {
+        let prefix = format!(".{}.", target.file_name().unwrap().to_str().unwrap());
+        fs::read_dir(target.parent().unwrap())
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+    }
+
+    #[test]
+    fn test_commit_replaces() {
+        let path = target("commit");
+        fs::write(&path, "old").unwrap();
+
+        atomic_write(&path, |file| file.write_all(b"new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!has_temp_files(&path));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_keeps_old() {
+        let path = target("failed");
+        fs::write(&path, "old").unwrap();
+
+        let result: io::Result<()> = atomic_write(&path, |file| {
+            file.write_all(b"partial")?;
+            Err(io::Error::other("No space left on device"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!has_temp_files(&path));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dropped_without_commit() {
+        let path = target("dropped");
+        {
+            let mut file = AtomicFile::create(&path).unwrap();
+            file.write_all(b"data").unwrap();
+        }
+
+        assert!(!path.exists());
+        assert!(!has_temp_files(&path));
+    }
+
+    #[cfg(all(unix, feature = "crypto"))]
+    #[test]
+    fn test_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = target("private");
+        let mut file = AtomicFile::create_private(&path).unwrap();
+        file.write_all(b"key").unwrap();
+        file.commit().unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_temp_path() {
+        let temp = temp_path(Path::new("dir/corpus.txt"));
+        assert_eq!(temp.parent(), Some(Path::new("dir")));
+
+        let name = temp.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!(".corpus.txt.{}-", process::id())));
+        assert!(name.ends_with(".tmp"));
+
+        // writers of the same target in one process
+        assert_ne!(temp_path(Path::new("dir/corpus.txt")), temp);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let path = target("concurrent");
+        let mut first = AtomicFile::create(&path).unwrap();
+        let mut second = AtomicFile::create(&path).unwrap();
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+
+        first.commit().unwrap();
+        second.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(!has_temp_files(&path));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(any(feature = "http", feature = "index"))]
+    #[test]
+    fn test_writer_lock() {
+        let path = target("locked");
+        {
+            let _lock = WriterLock::acquire(&path).unwrap();
+            assert_matches!(
+                WriterLock::acquire(&path),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock
+            );
+        }
+
+        // released by the drop
+        WriterLock::acquire(&path).unwrap();
+        fs::remove_file(lock_path(&path)).unwrap();
+    }
+}
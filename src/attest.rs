@@ -0,0 +1,357 @@
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+    time::SystemTime,
+};
+
+use data_encoding::{BASE64, HEXLOWER};
+use ring::{
+    digest::{Context, SHA256},
+    signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{bundle::public_key_hex, date::format_rfc3339};
+
+/// Media type of the signed payload - an in-toto statement
+const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+const PREDICATE_TYPE: &str = "https://github.com/games647/pwned_check/attestation/v1";
+
+/// Signed evidence of an audit in the DSSE envelope format used by artifact stores (ex: Sigstore,
+/// in-toto). The payload is an in-toto statement with the scanned hash lists as subjects.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    payload_type: String,
+    /// Base64 of the statement - signed exactly as it's stored
+    payload: String,
+    signatures: Vec<Signature>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Signature {
+    /// Hex of the public key - same as printed for the verification
+    keyid: String,
+    /// Base64 of the Ed25519 signature
+    sig: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    statement_type: String,
+    pub subject: Vec<Subject>,
+    predicate_type: String,
+    pub predicate: Predicate,
+}
+
+/// Scanned hash list - local files are identified by their digest, online and remote sources by
+/// their URL and snapshot, because hashing them would download them again
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Subject {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<SubjectDigest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<SubjectAnnotations>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubjectDigest {
+    pub sha256: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubjectAnnotations {
+    /// RFC 3339 publishing time of the list
+    pub snapshot: String,
+}
+
+/// Details of the run - without any account data
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Predicate {
+    pub tool: String,
+    pub version: String,
+    /// User and host that ran the audit
+    pub runner: String,
+    /// RFC 3339 time of the start and end
+    pub started: String,
+    pub finished: String,
+    pub checked: usize,
+    pub pwned: usize,
+    pub clean: usize,
+    /// False if the scan was cancelled
+    pub complete: bool,
+}
+
+impl Statement {
+    pub fn new(subject: Vec<Subject>, predicate: Predicate) -> Self {
+        Statement {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject,
+            predicate_type: PREDICATE_TYPE.to_string(),
+            predicate,
+        }
+    }
+}
+
+impl Subject {
+    pub fn file(name: String, sha256: String) -> Self {
+        Subject {
+            name,
+            uri: None,
+            digest: Some(SubjectDigest { sha256 }),
+            annotations: None,
+        }
+    }
+
+    /// Snapshot is none for live APIs and lists without metadata
+    pub fn remote(name: String, uri: String, snapshot: Option<SystemTime>) -> Self {
+        Subject {
+            name,
+            uri: Some(uri),
+            digest: None,
+            annotations: snapshot.map(|snapshot| SubjectAnnotations {
+                snapshot: format_rfc3339(snapshot),
+            }),
+        }
+    }
+}
+
+impl Predicate {
+    pub fn new(started: SystemTime, checked: usize, pwned: usize, complete: bool) -> Self {
+        Predicate {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            runner: runner(),
+            started: format_rfc3339(started),
+            finished: format_rfc3339(SystemTime::now()),
+            checked,
+            pwned,
+            clean: checked - pwned,
+            complete,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AttestError {
+    Json(serde_json::Error),
+    /// Payload or signature isn't valid base64
+    InvalidEncoding,
+    UnknownPayloadType(String),
+    /// No signature of the expected key verified the payload
+    InvalidSignature,
+}
+
+impl Display for AttestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AttestError::Json(err) => write!(f, "{}", err),
+            AttestError::InvalidEncoding => write!(f, "Invalid base64 encoding"),
+            AttestError::UnknownPayloadType(payload_type) => {
+                write!(f, "Unknown payload type {}", payload_type)
+            }
+            AttestError::InvalidSignature => write!(f, "Signature doesn't match the public key"),
+        }
+    }
+}
+
+impl Error for AttestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AttestError::Json(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for AttestError {
+    fn from(err: serde_json::Error) -> Self {
+        AttestError::Json(err)
+    }
+}
+
+/// Sign the statement and wrap it into the envelope
+pub fn sign(statement: &Statement, key: &Ed25519KeyPair) -> Result<Envelope, AttestError> {
+    let payload = serde_json::to_vec(statement)?;
+    let signature = key.sign(&pre_authentication(PAYLOAD_TYPE, &payload));
+    Ok(Envelope {
+        payload_type: PAYLOAD_TYPE.to_string(),
+        payload: BASE64.encode(&payload),
+        signatures: vec![Signature {
+            keyid: public_key_hex(key),
+            sig: BASE64.encode(signature.as_ref()),
+        }],
+    })
+}
+
+/// Verify the envelope with the public key of the auditing machine and return the statement
+pub fn verify(envelope: &[u8], public_key: &[u8]) -> Result<Statement, AttestError> {
+    let envelope: Envelope = serde_json::from_slice(envelope)?;
+    if envelope.payload_type != PAYLOAD_TYPE {
+        return Err(AttestError::UnknownPayloadType(envelope.payload_type));
+    }
+
+    let payload = decode_base64(&envelope.payload)?;
+    let message = pre_authentication(&envelope.payload_type, &payload);
+    let key = UnparsedPublicKey::new(&ED25519, public_key);
+
+    // other tools could add their signatures, so any of them could be ours
+    let mut verified = false;
+    for signature in &envelope.signatures {
+        let sig = decode_base64(&signature.sig)?;
+        verified |= key.verify(&message, &sig).is_ok();
+    }
+
+    if !verified {
+        return Err(AttestError::InvalidSignature);
+    }
+
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// Hex of the SHA-256 of a hash list
+pub fn sha256_hex(mut reader: impl Read) -> io::Result<String> {
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => context.update(&buffer[..read]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(HEXLOWER.encode(context.finish().as_ref()))
+}
+
+/// DSSE pre-authentication encoding - binds the type to the payload, so neither could be swapped
+fn pre_authentication(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, AttestError> {
+    BASE64
+        .decode(data.as_bytes())
+        .map_err(|_| AttestError::InvalidEncoding)
+}
+
+/// `user@host` from the environment - missing parts are left out
+fn runner() -> String {
+    let user = env::var("USER").or_else(|_| env::var("USERNAME")).ok();
+    let host = env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .ok();
+
+    match (user, host) {
+        (Some(user), Some(host)) => format!("{}@{}", user, host),
+        (user, host) => user.or(host).unwrap_or_else(|| "unknown".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use ring::{rand::SystemRandom, signature::KeyPair};
+
+    use super::*;
+
+    fn generate_key() -> Ed25519KeyPair {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap()
+    }
+
+    fn statement() -> Statement {
+        let file = Subject::file("hibp.txt".to_string(), sha256_hex(&b""[..]).unwrap());
+        let snapshot = UNIX_EPOCH + Duration::from_secs(1_369_000_000);
+        let remote = Subject::remote(
+            "hibp.txt".to_string(),
+            "https://mirror.example/hibp.txt".to_string(),
+            Some(snapshot),
+        );
+
+        let started = UNIX_EPOCH + Duration::from_secs(1_369_353_600);
+        Statement::new(vec![file, remote], Predicate::new(started, 10, 3, true))
+    }
+
+    #[test]
+    fn test_signed() {
+        let key = generate_key();
+        let envelope = serde_json::to_vec(&sign(&statement(), &key).unwrap()).unwrap();
+
+        let verified = verify(&envelope, key.public_key().as_ref()).unwrap();
+        assert_eq!(verified, statement_with(&verified));
+        assert_eq!(verified.predicate.clean, 7);
+        assert_eq!(verified.predicate.started, "2013-05-24T00:00:00Z");
+        assert_eq!(
+            verified.subject[0].digest.as_ref().unwrap().sha256,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_remote_subject() {
+        let json = serde_json::to_value(&statement().subject[1]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "hibp.txt",
+                "uri": "https://mirror.example/hibp.txt",
+                "annotations": {"snapshot": "2013-05-19T21:46:40Z"}
+            })
+        );
+    }
+
+    /// The finish time differs between two created statements
+    fn statement_with(verified: &Statement) -> Statement {
+        let mut expected = statement();
+        expected.predicate.finished = verified.predicate.finished.clone();
+        expected
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = generate_key();
+        let mut envelope = sign(&statement(), &key).unwrap();
+
+        let mut modified = statement();
+        modified.predicate.pwned = 0;
+        envelope.payload = BASE64.encode(&serde_json::to_vec(&modified).unwrap());
+
+        let tampered = serde_json::to_vec(&envelope).unwrap();
+        let result = verify(&tampered, key.public_key().as_ref());
+        assert_matches!(result, Err(AttestError::InvalidSignature));
+
+        let other_key = generate_key();
+        let valid = serde_json::to_vec(&sign(&statement(), &key).unwrap()).unwrap();
+        let result = verify(&valid, other_key.public_key().as_ref());
+        assert_matches!(result, Err(AttestError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_pre_authentication() {
+        assert_eq!(
+            pre_authentication("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    convert::TryInto,
+    error::Error,
+    fmt,
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use data_encoding::HEXUPPER;
+use ring::{
+    error::KeyRejected,
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic::AtomicFile, Sha1Hash};
+
+/// Start of the last line in an answer bundle containing the signature of everything before
+const SIGNATURE_PREFIX: &str = "# ed25519 ";
+
+#[derive(Debug)]
+pub enum BundleError {
+    CsvError(csv::Error),
+    IoError(io::Error),
+    InvalidHash(String),
+    /// Prefixes are shared by many hashes, so they cannot be answered by an exact lookup
+    PrefixOnly,
+    InvalidKey(KeyRejected),
+    MissingSignature,
+    InvalidSignature,
+}
+
+impl Display for BundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::CsvError(err) => write!(f, "{}", err),
+            BundleError::IoError(err) => write!(f, "{}", err),
+            BundleError::InvalidHash(hash) => write!(f, "Invalid SHA-1 hash {}", hash),
+            BundleError::PrefixOnly => write!(f, "Prefix only bundles cannot be answered"),
+            BundleError::InvalidKey(err) => write!(f, "Invalid signing key {}", err),
+            BundleError::MissingSignature => write!(f, "Bundle is not signed"),
+            BundleError::InvalidSignature => write!(f, "Signature doesn't match the bundle"),
+        }
+    }
+}
+
+impl Error for BundleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BundleError::CsvError(source) => Some(source),
+            BundleError::IoError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for BundleError {
+    fn from(e: csv::Error) -> Self {
+        BundleError::CsvError(e)
+    }
+}
+
+impl From<io::Error> for BundleError {
+    fn from(e: io::Error) -> Self {
+        BundleError::IoError(e)
+    }
+}
+
+/// Row of the bundle created by the `hash-only` command
+#[derive(Debug, Deserialize)]
+struct HashRow {
+    label: String,
+    sha1: String,
+}
+
+/// Pwned account of an answer bundle
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Answer {
+    pub label: String,
+    pub sha1: String,
+    /// Highest count of all scanned hash databases - none if it couldn't be parsed and zero if the
+    /// account is clean
+    pub count: Option<u64>,
+}
+
+impl Answer {
+    /// Only included if requested, to prove that the account was checked
+    pub fn is_clean(&self) -> bool {
+        self.count == Some(0)
+    }
+}
+
+/// Read the labels and hashes exported by `hash-only`
+pub fn read_hashes(reader: impl Read) -> Result<Vec<(String, Sha1Hash)>, BundleError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    if reader.headers()?.iter().any(|header| header == "prefix") {
+        return Err(BundleError::PrefixOnly);
+    }
+
+    let mut hashes = Vec::new();
+    for row in reader.deserialize() {
+        let row: HashRow = row?;
+        let hash = decode_hash(&row.sha1)?;
+        hashes.push((row.label, hash));
+    }
+
+    Ok(hashes)
+}
+
+fn decode_hash(hex: &str) -> Result<Sha1Hash, BundleError> {
+    HEXUPPER
+        .decode(hex.to_ascii_uppercase().as_bytes())
+        .ok()
+        .and_then(|bytes| bytes.as_slice().try_into().ok())
+        .ok_or_else(|| BundleError::InvalidHash(hex.to_string()))
+}
+
+/// Serialize the answers and append the signature over them as last line
+pub fn write_answers(answers: &[Answer], key: &Ed25519KeyPair) -> Result<Vec<u8>, BundleError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for answer in answers {
+        writer.serialize(answer)?;
+    }
+
+    // flush is included into_inner
+    let mut bundle = writer
+        .into_inner()
+        .map_err(|err| BundleError::IoError(err.into_error()))?;
+    if answers.is_empty() {
+        // keep the header, so an empty answer is still a valid csv file
+        bundle.extend_from_slice(b"label,sha1,count\n");
+    }
+
+    let signature = key.sign(&bundle);
+    let trailer = format!(
+        "{}{}\n",
+        SIGNATURE_PREFIX,
+        HEXUPPER.encode(signature.as_ref())
+    );
+    bundle.extend_from_slice(trailer.as_bytes());
+    Ok(bundle)
+}
+
+/// Verify the signature of the answer bundle with the public key of the answering machine and
+/// parse the answers
+pub fn read_answers(bundle: &[u8], public_key: &[u8]) -> Result<Vec<Answer>, BundleError> {
+    // the bundle ends with a line break, so search the start of the line before it
+    let content_end = bundle
+        .strip_suffix(b"\n")
+        .and_then(|content| content.iter().rposition(|&byte| byte == b'\n'))
+        .map_or(0, |position| position + 1);
+    let (content, trailer) = bundle.split_at(content_end);
+
+    let signature = std::str::from_utf8(trailer)
+        .ok()
+        .and_then(|trailer| trailer.trim_end().strip_prefix(SIGNATURE_PREFIX))
+        .and_then(|hex| HEXUPPER.decode(hex.as_bytes()).ok())
+        .ok_or(BundleError::MissingSignature)?;
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(content, &signature)
+        .map_err(|_| BundleError::InvalidSignature)?;
+
+    let mut reader = csv::Reader::from_reader(content);
+    let answers: Result<Vec<Answer>, csv::Error> = reader.deserialize().collect();
+    Ok(answers?)
+}
+
+/// Load the signing key of the answering machine. A new one is created on the first use.
+pub fn load_or_create_key(path: &Path) -> Result<Ed25519KeyPair, BundleError> {
+    let pkcs8 = if path.exists() {
+        fs::read(path)?
+    } else {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| BundleError::IoError(io::ErrorKind::Other.into()))?;
+        write_private(path, document.as_ref())?;
+        document.as_ref().to_vec()
+    };
+
+    Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(BundleError::InvalidKey)
+}
+
+/// Write a file only readable by the current user
+pub fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = AtomicFile::create_private(path)?;
+    file.write_all(data)?;
+    file.commit()
+}
+
+/// Hex of the public key that needs to be passed to `apply-answers`
+pub fn public_key_hex(key: &Ed25519KeyPair) -> String {
+    HEXUPPER.encode(key.public_key().as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generate_key() -> Ed25519KeyPair {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap()
+    }
+
+    fn answer(label: &str, count: Option<u64>) -> Answer {
+        Answer {
+            label: label.to_string(),
+            sha1: "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8".to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn test_read_hashes() -> Result<(), BundleError> {
+        let data =
+            "label,sha1\nuser@https://example.com/,5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8\n";
+        let hashes = read_hashes(data.as_bytes())?;
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(hashes[0].0, "user@https://example.com/");
+        assert_eq!(hashes[0].1[..2], [0x5B, 0xAA]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_hashes_invalid() {
+        let prefix = "label,prefix\nuser@https://example.com/,5BAA6\n";
+        assert_matches!(read_hashes(prefix.as_bytes()), Err(BundleError::PrefixOnly));
+
+        let short = "label,sha1\nuser@https://example.com/,5BAA6\n";
+        assert_matches!(
+            read_hashes(short.as_bytes()),
+            Err(BundleError::InvalidHash(_))
+        );
+    }
+
+    #[test]
+    fn test_answers_signed() -> Result<(), BundleError> {
+        let key = generate_key();
+        let answers = vec![answer("a@https://example.com/", Some(5)), answer("b", None)];
+
+        let bundle = write_answers(&answers, &key)?;
+        let read = read_answers(&bundle, key.public_key().as_ref())?;
+        assert_eq!(read, answers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_answers_empty() -> Result<(), BundleError> {
+        let key = generate_key();
+        let bundle = write_answers(&[], &key)?;
+        assert!(read_answers(&bundle, key.public_key().as_ref())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_answers_tampered() -> Result<(), BundleError> {
+        let key = generate_key();
+        let mut bundle = write_answers(&[answer("a", Some(5))], &key)?;
+
+        // the public key of another machine
+        let other = generate_key();
+        assert_matches!(
+            read_answers(&bundle, other.public_key().as_ref()),
+            Err(BundleError::InvalidSignature)
+        );
+
+        bundle[0] = b'L';
+        assert_matches!(
+            read_answers(&bundle, key.public_key().as_ref()),
+            Err(BundleError::InvalidSignature)
+        );
+
+        assert_matches!(
+            read_answers(b"label,sha1,count\n", key.public_key().as_ref()),
+            Err(BundleError::MissingSignature)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Shared flag to abort a long running collection or scan from another thread like a Ctrl-C
+/// handler. The operations check it periodically and return their partial results.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        // relaxed is enough, because no other data is synchronized using this flag
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Result of an operation that could be cancelled - both variants contain the (partial) results
+#[derive(Debug, PartialEq)]
+pub enum Status<T> {
+    Completed(T),
+    Cancelled(T),
+}
+
+impl<T> Status<T> {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Status::Cancelled(_))
+    }
+
+    pub fn into_inner(self) -> T {
+        match self {
+            Status::Completed(inner) | Status::Cancelled(inner) => inner,
+        }
+    }
+
+    /// Convert the results and keep whether it was cancelled
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Status<U> {
+        match self {
+            Status::Completed(inner) => Status::Completed(f(inner)),
+            Status::Cancelled(inner) => Status::Cancelled(f(inner)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cancel_shared() {
+        let token = CancellationToken::default();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_status() {
+        assert!(!Status::Completed(1).is_cancelled());
+
+        let status = Status::Cancelled(1);
+        assert!(status.is_cancelled());
+        assert_eq!(status.into_inner(), 1);
+
+        assert_eq!(Status::Cancelled(1).map(|x| x + 1), Status::Cancelled(2));
+    }
+}
@@ -1,28 +1,118 @@
 use std::{
+    any::Any,
     cmp::Ordering,
-    convert::TryInto,
+    error::Error,
     fmt,
     fmt::{Display, Formatter},
     hash::{Hash, Hasher},
     io::Read,
     thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
-use log::debug;
-use ring::digest::{digest, Digest, SHA1_FOR_LEGACY_USE_ONLY};
+use bstr::ByteSlice;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use csv::ByteRecord;
+use log::{debug, info, warn};
+use md4::{Digest as _, Md4};
+use regex::RegexSet;
 use secstr::SecStr;
-use serde::Deserialize;
 
-use crate::Sha1Hash;
-
-const PASSWORD_BUFFER: usize = 128;
+use crate::{
+    cancel::{CancellationToken, Status},
+    detect,
+    find::{pad_hash, Finding, HashPadded},
+    hasher,
+    health::Strength,
+    metrics::{Amount, Metrics, Phase},
+    normalize::{Normalization, UnicodeForm},
+    pepper::Pepper,
+    redact::REDACTED,
+    NtlmHash, Sha1Hash, NTLM_BYTE_LENGTH, SHA1_BYTE_LENGTH,
+};
 
 #[derive(Debug, Eq)]
 pub struct SavedHash {
     url: String,
     username: String,
     pub password_hash: Sha1Hash,
+    /// Password hash padded for the SIMD comparisons - precomputed once instead of per scan
+    padded: HashPadded,
+    pub strength: Strength,
+    /// Creation, usage and change times if the export includes them (ex: Firefox)
+    pub times: Timestamps,
+    /// Only hashed if an NTLM hash list is scanned
+    pub ntlm_hash: Option<NtlmHash>,
+    /// SHA-1 of the other Unicode forms of a non-ASCII password - only if they are checked
+    pub unicode_forms: Vec<(UnicodeForm, Sha1Hash)>,
+    /// Folders, collections or groups of the entry (ex: Bitwarden, KeePass)
+    tags: Vec<String>,
+    /// Person of the export in a comparison of multiple profiles (`--input NAME=FILE`)
+    profile: Option<String>,
+}
+
+impl SavedHash {
+    /// Hash without the account details - for example from an exported hash bundle
+    pub fn from_hash(password_hash: Sha1Hash) -> Self {
+        SavedHash {
+            url: String::new(),
+            username: String::new(),
+            password_hash,
+            padded: pad_hash(&password_hash),
+            strength: Strength::default(),
+            times: Timestamps::default(),
+            ntlm_hash: None,
+            unicode_forms: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn padded(&self) -> &HashPadded {
+        &self.padded
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn set_profile(&mut self, profile: &str) {
+        self.profile = Some(profile.to_string());
+    }
+
+    #[cfg(test)]
+    pub fn new(url: &str, username: &str, password_hash: Sha1Hash) -> Self {
+        SavedHash {
+            url: url.to_string(),
+            username: username.to_string(),
+            password_hash,
+            padded: pad_hash(&password_hash),
+            strength: Strength::default(),
+            times: Timestamps::default(),
+            ntlm_hash: None,
+            unicode_forms: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
 }
 
 impl Hash for SavedHash {
@@ -55,74 +145,745 @@ impl Display for SavedHash {
     }
 }
 
-pub fn collect_hashes(
-    password_reader: csv::Reader<impl Read>,
-) -> Result<Vec<SavedHash>, csv::Error> {
+/// Time spent waiting on the other stage of the hashing pipeline. A high reader wait means the
+/// hashing is the bottleneck, while idle workers mean that reading the export is.
+#[derive(Debug, Default)]
+struct PipelineStats {
+    /// Reader blocked, because the buffer was full
+    reader_blocked: Duration,
+    /// Sum of all hashing threads waiting for new passwords
+    workers_idle: Duration,
+}
+
+#[derive(Debug)]
+pub enum CollectError {
+    CsvError(csv::Error),
+    /// A hashing thread panicked - its records are missing in the result
+    WorkerPanic(String),
+    /// Fewer hashes than read passwords were received
+    LostRecords {
+        sent: usize,
+        received: usize,
+    },
+    /// The header line has none of the known url, username and password columns
+    UnknownHeader,
+}
+
+impl Display for CollectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectError::CsvError(err) => write!(f, "{}", err),
+            CollectError::WorkerPanic(message) => write!(f, "Hashing thread panicked: {}", message),
+            CollectError::LostRecords { sent, received } => {
+                write!(f, "Only received {} of {} hashed passwords", received, sent)
+            }
+            CollectError::UnknownHeader => write!(
+                f,
+                "Unknown CSV header without url, username and password columns - supported are the \
+                 CSV exports of {} and the JSON exports of Bitwarden",
+                detect::CSV_EXPORTS
+            ),
+        }
+    }
+}
+
+impl Error for CollectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CollectError::CsvError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for CollectError {
+    fn from(e: csv::Error) -> Self {
+        CollectError::CsvError(e)
+    }
+}
+
+/// Hash function of a hash list - the saved passwords are hashed the same way
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha1,
+    /// MD4 of the UTF-16 password used by Windows and Active Directory
+    Ntlm,
+}
+
+/// Settings for hashing the saved passwords
+#[derive(Debug)]
+pub struct HashOptions {
+    /// Number of read passwords that could wait for a free hashing thread before the reader blocks
+    pub pipeline_buffer: usize,
+    pub pepper: Pepper,
+    /// Additionally hash the passwords using NTLM
+    pub ntlm: bool,
+    /// Entries skipped before hashing
+    pub filter: EntryFilter,
+    /// Changes of the passwords before hashing - the exact bytes by default
+    pub normalization: Normalization,
+    /// Additionally hash the NFC and NFD forms of non-ASCII passwords
+    pub unicode_forms: bool,
+}
+
+/// Entries of the export that aren't checked
+#[derive(Debug)]
+pub struct EntryFilter {
+    pub tags: TagFilter,
+    /// Skip entries if the URL or username matches any of them
+    pub exclude_patterns: RegexSet,
+}
+
+impl Default for EntryFilter {
+    fn default() -> Self {
+        EntryFilter {
+            tags: TagFilter::default(),
+            exclude_patterns: RegexSet::empty(),
+        }
+    }
+}
+
+/// Select the entries by their tags. Group paths like `Root/Work` match each of their parts, so
+/// `Work` selects the KeePass group as well as the Bitwarden folder.
+#[derive(Debug, Default)]
+pub struct TagFilter {
+    /// Only entries with one of these tags - all if it's empty
+    pub include: Vec<String>,
+    /// Skip entries with one of these tags, even if they are included
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn allows(&self, tags: &[String]) -> bool {
+        let has_any = |filters: &[String]| {
+            tags.iter().any(|tag| {
+                filters.iter().any(|filter| {
+                    tag.eq_ignore_ascii_case(filter)
+                        || tag.split('/').any(|part| part.eq_ignore_ascii_case(filter))
+                })
+            })
+        };
+
+        (self.include.is_empty() || has_any(&self.include)) && !has_any(&self.exclude)
+    }
+}
+
+/// Saved passwords of an export - CSV is read while hashing, the other formats are parsed at once
+pub enum PasswordSource<R> {
+    Csv(csv::Reader<R>),
+    /// Entries of a JSON export and its size in bytes
+    Parsed(Vec<SavedPassword>, u64),
+}
+
+impl<R> From<csv::Reader<R>> for PasswordSource<R> {
+    fn from(reader: csv::Reader<R>) -> Self {
+        PasswordSource::Csv(reader)
+    }
+}
+
+impl<R: Read> PasswordSource<R> {
+    fn records(&mut self) -> Result<Records<'_, R>, CollectError> {
+        match self {
+            PasswordSource::Csv(reader) => {
+                let columns = Columns::from_headers(reader.byte_headers()?)?;
+                Ok(Records::Csv {
+                    reader,
+                    columns,
+                    // byte records skip the UTF-8 validation and accept passwords in any encoding
+                    buffer: ByteRecord::new(),
+                })
+            }
+            PasswordSource::Parsed(entries, _) => Ok(Records::Parsed(entries.drain(..))),
+        }
+    }
+
+    /// Bytes of the export that were read
+    fn position(&self) -> u64 {
+        match self {
+            PasswordSource::Csv(reader) => reader.position().byte(),
+            PasswordSource::Parsed(_, size) => *size,
+        }
+    }
+}
+
+/// Entries of a source in the export order
+enum Records<'a, R> {
+    Csv {
+        reader: &'a mut csv::Reader<R>,
+        columns: Columns,
+        buffer: ByteRecord,
+    },
+    Parsed(std::vec::Drain<'a, SavedPassword>),
+}
+
+impl<R: Read> Records<'_, R> {
+    fn next(&mut self) -> Result<Option<SavedPassword>, CollectError> {
+        match self {
+            Records::Csv {
+                reader,
+                columns,
+                buffer,
+            } => Ok(reader
+                .read_byte_record(buffer)?
+                .then(|| columns.parse(buffer))),
+            Records::Parsed(entries) => Ok(entries.next()),
+        }
+    }
+}
+
+/// Hash all passwords of the export in parallel
+pub fn collect_hashes<R: Read>(
+    source: impl Into<PasswordSource<R>>,
+    options: &HashOptions,
+    cancel: &CancellationToken,
+    metrics: &mut Metrics,
+) -> Result<Status<Vec<SavedHash>>, CollectError> {
+    let pepper = options.pepper.as_bytes();
+    collect_with(source.into(), options, cancel, metrics, &|password| {
+        hash_pass(pepper, password)
+    })
+}
+
+/// Hash the exports one after another and label the accounts with the profile of the export
+pub fn collect_profiles(
+    password_readers: Vec<(Option<String>, PasswordSource<impl Read>)>,
+    options: &HashOptions,
+    cancel: &CancellationToken,
+    metrics: &mut Metrics,
+) -> Result<Status<Vec<SavedHash>>, CollectError> {
+    let mut hashes = Vec::new();
+    for (profile, reader) in password_readers {
+        let status = collect_hashes(reader, options, cancel, metrics)?;
+        let cancelled = status.is_cancelled();
+
+        let mut collected = status.into_inner();
+        if let Some(profile) = &profile {
+            debug!("Collected {} accounts of {}", collected.len(), profile);
+            for saved in &mut collected {
+                saved.set_profile(profile);
+            }
+        }
+
+        hashes.append(&mut collected);
+        if cancelled {
+            return Ok(Status::Cancelled(hashes));
+        }
+    }
+
+    Ok(Status::Completed(hashes))
+}
+
+fn collect_with(
+    mut source: PasswordSource<impl Read>,
+    options: &HashOptions,
+    cancel: &CancellationToken,
+    metrics: &mut Metrics,
+    hasher: &(dyn Fn(&[u8]) -> Sha1Hash + Sync),
+) -> Result<Status<Vec<SavedHash>>, CollectError> {
+    let pipeline_buffer = options.pipeline_buffer;
+    let ntlm = options.ntlm;
+    let unicode_forms = options.unicode_forms;
     let threads = num_cpus::get();
-    debug!("Started {} hashing threads", threads);
-
-    let (tx, rx) = bounded(PASSWORD_BUFFER);
-    let (done, quit) = unbounded();
-    for _ in 0..threads {
-        let local_rx: Receiver<SavedPassword> = rx.clone();
-        let local_done = done.clone();
-        thread::spawn(move || {
-            for in_record in local_rx {
-                let digest = hash_pass(in_record.password.unsecure());
-                let hash = digest.as_ref();
-
-                let record = SavedHash {
-                    // url, username gets moved in here
-                    url: in_record.url,
-                    username: in_record.username,
-                    // Safety: safe, because sha-1 should fit into an array with that size
-                    password_hash: hash.try_into().unwrap(),
-                };
-
-                // unexpected channel disconnect -> should panic
-                local_done.send(record).unwrap();
+    debug!(
+        "Started {} hashing threads with the {} SHA-1 backend",
+        threads,
+        hasher::BACKEND_NAME
+    );
+    let started = Instant::now();
+
+    let (tx, rx) = bounded(pipeline_buffer);
+    let mut stats = PipelineStats::default();
+
+    // all threads are joined at the end of the scope, so none of them could outlive this function
+    let (read, hashes, panic) = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let local_rx = rx.clone();
+                scope.spawn(move || hash_passwords(local_rx, hasher, ntlm, unicode_forms))
+            })
+            .collect();
+
+        // only the threads keep receiving, so a send fails instead of blocking forever if all of
+        // them panicked
+        drop(rx);
+
+        // read passwords on the current thread - tx is dropped at the end, so the threads finish
+        let read = read_passwords(tx, &mut source, options, &mut stats, cancel);
+        let bytes = source.position();
+        metrics.record(Phase::ReadCsv, started, Amount::Bytes(bytes));
+
+        let mut hashes = Vec::new();
+        let mut panic = None;
+        for worker in workers {
+            match worker.join() {
+                Ok((worker_hashes, idle)) => {
+                    hashes.extend(worker_hashes);
+                    stats.workers_idle += idle;
+                }
+                Err(payload) => panic = panic.or_else(|| Some(panic_message(&*payload))),
             }
+        }
 
-            // drop it explicitly so we could notice the done signal
-            drop(local_done);
+        (read, hashes, panic)
+    });
+
+    if let Some(message) = panic {
+        return Err(CollectError::WorkerPanic(message));
+    }
+
+    let (sent, completed) = read?;
+    metrics.record(Phase::Hashing, started, Amount::Records(sent as u64));
+    debug!(
+        "Pipeline buffer {}: reader blocked {:?}, hashing threads idle {:?}",
+        pipeline_buffer, stats.reader_blocked, stats.workers_idle
+    );
+
+    if hashes.len() != sent {
+        return Err(CollectError::LostRecords {
+            sent,
+            received: hashes.len(),
         });
     }
 
-    // drop the original done, so that all done variants including the clones are dropped
-    drop(done);
+    if completed {
+        Ok(Status::Completed(hashes))
+    } else {
+        Ok(Status::Cancelled(hashes))
+    }
+}
+
+/// Hash the received passwords until the reader is finished. Returns the hashes and the time
+/// spent waiting for new passwords.
+fn hash_passwords(
+    rx: Receiver<SavedPassword>,
+    hasher: &(dyn Fn(&[u8]) -> Sha1Hash + Sync),
+    ntlm: bool,
+    unicode_forms: bool,
+) -> (Vec<SavedHash>, Duration) {
+    let mut hashes = Vec::new();
+    let mut idle = Duration::default();
+    loop {
+        let wait_start = Instant::now();
+        let in_record = match rx.recv() {
+            Ok(in_record) => in_record,
+            // reader finished
+            Err(_) => break,
+        };
+        idle += wait_start.elapsed();
+
+        let password_hash = hasher(in_record.password.unsecure());
+
+        hashes.push(SavedHash {
+            // url, username gets moved in here
+            url: in_record.url,
+            username: in_record.username,
+            password_hash,
+            padded: pad_hash(&password_hash),
+            strength: Strength::estimate(in_record.password.unsecure()),
+            times: in_record.times,
+            ntlm_hash: if ntlm {
+                Some(ntlm_hash(in_record.password.unsecure()))
+            } else {
+                None
+            },
+            unicode_forms: if unicode_forms {
+                hash_unicode_forms(in_record.password.unsecure(), hasher)
+            } else {
+                Vec::new()
+            },
+            tags: in_record.tags,
+            profile: None,
+        });
+    }
+
+    (hashes, idle)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
 
-    // read passwords on the current thread and wait until the receivers are finished
-    read_passwords(tx, password_reader)?;
+/// Times of an entry in the export - each one is missing if the export doesn't include it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timestamps {
+    pub created: Option<SystemTime>,
+    pub last_used: Option<SystemTime>,
+    pub changed: Option<SystemTime>,
+}
 
-    // detect when all done channels are dropped this loop breaks
-    Ok(quit.iter().collect())
+impl Timestamps {
+    /// Time since the current password is used - the creation if it was never changed
+    pub fn password_set(&self) -> Option<SystemTime> {
+        self.changed.or(self.created)
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct SavedPassword {
+pub struct SavedPassword {
     url: String,
     username: String,
     password: SecStr,
+    times: Timestamps,
+    tags: Vec<String>,
+}
+
+impl SavedPassword {
+    /// Entry of an export without times
+    pub fn new(url: String, username: String, password: SecStr, tags: Vec<String>) -> Self {
+        SavedPassword {
+            url,
+            username,
+            password,
+            times: Timestamps::default(),
+            tags,
+        }
+    }
+}
+
+impl fmt::Debug for SavedPassword {
+    /// Only the length of the password, so it never ends up in logs or panic messages
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SavedPassword")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field(
+                "password",
+                &format_args!("{} ({} bytes)", REDACTED, self.password.unsecure().len()),
+            )
+            .field("times", &self.times)
+            .field("tags", &self.tags)
+            .finish()
+    }
 }
 
+/// Headers of the tag columns in the exports - compared ignoring the case
+const TAG_COLUMNS: [&str; 4] = ["folder", "collections", "group", "tags"];
+
+/// Position of the used columns in the export - other columns are ignored
+#[derive(Debug)]
+struct Columns {
+    url: usize,
+    username: usize,
+    password: usize,
+    /// Milliseconds since the epoch - only in Firefox exports
+    created: Option<usize>,
+    last_used: Option<usize>,
+    changed: Option<usize>,
+    /// Comma separated folders, collections or groups
+    tags: Vec<usize>,
+}
+
+impl Columns {
+    fn from_headers(headers: &ByteRecord) -> Result<Self, CollectError> {
+        let (preset, [url, username, password]) =
+            detect::csv_preset(headers).ok_or(CollectError::UnknownHeader)?;
+        info!("Detected a {} CSV export", preset);
+
+        let find = |name: &str| headers.iter().position(|header| header == name.as_bytes());
+        Ok(Columns {
+            url,
+            username,
+            password,
+            created: find("timeCreated"),
+            last_used: find("timeLastUsed"),
+            changed: find("timePasswordChanged"),
+            tags: headers
+                .iter()
+                .enumerate()
+                .filter(|(_, header)| {
+                    TAG_COLUMNS
+                        .iter()
+                        .any(|name| header.eq_ignore_ascii_case(name.as_bytes()))
+                })
+                .map(|(index, _)| index)
+                .collect(),
+        })
+    }
+
+    /// Copy the fields out of the record. The password keeps its exact bytes, because it's not
+    /// guaranteed to be valid UTF-8 and any replacement would change the hash.
+    fn parse(&self, record: &ByteRecord) -> SavedPassword {
+        let field = |index| record.get(index).unwrap_or_default();
+        // invalid or negative values (ex: `-1` of imported entries) are unknown times
+        let time = |column: Option<usize>| {
+            column
+                .and_then(|index| std::str::from_utf8(field(index)).ok()?.parse().ok())
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+        };
+        SavedPassword {
+            url: String::from_utf8_lossy(field(self.url)).into_owned(),
+            username: String::from_utf8_lossy(field(self.username)).into_owned(),
+            password: SecStr::new(field(self.password).to_vec()),
+            times: Timestamps {
+                created: time(self.created),
+                last_used: time(self.last_used),
+                changed: time(self.changed),
+            },
+            tags: self
+                .tags
+                .iter()
+                .flat_map(|&index| split_tags(field(index)))
+                .collect(),
+        }
+    }
+}
+
+/// Multiple collections are separated by commas (ex: Bitwarden organization exports)
+fn split_tags(field: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(field)
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Read all passwords into the channel. Returns the number of sent passwords and false if it was
+/// cancelled before reaching the end.
 fn read_passwords(
     tx: Sender<SavedPassword>,
-    mut file_reader: csv::Reader<impl Read>,
-) -> Result<(), csv::Error> {
-    let headers = file_reader.headers()?.clone();
+    source: &mut PasswordSource<impl Read>,
+    options: &HashOptions,
+    stats: &mut PipelineStats,
+    cancel: &CancellationToken,
+) -> Result<(usize, bool), CollectError> {
+    let mut records = source.records()?;
+    let filter = &options.filter;
+    let normalization = &options.normalization;
+
+    let mut sent = 0;
+    let mut skipped_tags = 0;
+    let mut excluded = 0;
+    let mut suspicious = 0;
+    while let Some(mut record) = records.next()? {
+        if cancel.is_cancelled() {
+            return Ok((sent, false));
+        }
+
+        if !filter.tags.allows(&record.tags) {
+            skipped_tags += 1;
+            continue;
+        }
+
+        let patterns = &filter.exclude_patterns;
+        if patterns.is_match(&record.url) || patterns.is_match(&record.username) {
+            excluded += 1;
+            continue;
+        }
+
+        if normalization.is_suspicious(record.password.unsecure()) {
+            suspicious += 1;
+        }
+        record.password = normalization.apply(record.password);
+
+        let send_start = Instant::now();
+        if tx.send(record).is_err() {
+            // all hashing threads are gone - the caller reports their panic
+            return Ok((sent, true));
+        }
+
+        stats.reader_blocked += send_start.elapsed();
+        sent += 1;
+    }
+
+    if skipped_tags > 0 {
+        info!("Skipped {} entries by their tags", skipped_tags);
+    }
+
+    if excluded > 0 {
+        info!("Excluded {} entries matching the patterns", excluded);
+    }
+
+    if suspicious > 0 {
+        warn!(
+            "{} passwords start or end with whitespace or a byte order mark - if the export added \
+             them, they hide pwned passwords and --normalize trim,bom removes them",
+            suspicious
+        );
+    }
+
+    Ok((sent, true))
+}
+
+/// SHA-1 of the password prefixed by the pepper - plain SHA-1 if the pepper is empty
+fn hash_pass(pepper: &[u8], password_bytes: &[u8]) -> Sha1Hash {
+    hasher::sha1(&[pepper, password_bytes])
+}
+
+/// SHA-1 of each Unicode form that differs from the exported password. ASCII passwords and invalid
+/// UTF-8 have no other forms.
+fn hash_unicode_forms(
+    password_bytes: &[u8],
+    hasher: &(dyn Fn(&[u8]) -> Sha1Hash + Sync),
+) -> Vec<(UnicodeForm, Sha1Hash)> {
+    let password = match std::str::from_utf8(password_bytes) {
+        Ok(password) if !password.is_ascii() => password,
+        _ => return Vec::new(),
+    };
+
+    UnicodeForm::ALL
+        .iter()
+        .filter_map(|&form| {
+            let normalized = form.normalize(password)?;
+            Some((form, hasher(normalized.unsecure())))
+        })
+        .collect()
+}
+
+/// NTLM hash of the password - the pepper is only used for SHA-1
+fn ntlm_hash(password_bytes: &[u8]) -> NtlmHash {
+    // UTF-16LE like Windows and invalid UTF-8 is replaced like the lossy conversion of the
+    // username. Each UTF-8 byte needs at most two bytes in UTF-16, so the buffer never reallocates
+    // and leaves copies of the password behind.
+    let mut encoded = Vec::with_capacity(password_bytes.len() * 2);
+    let mut units = [0; 2];
+    for c in password_bytes.chars() {
+        for unit in c.encode_utf16(&mut units) {
+            encoded.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    let encoded = SecStr::new(encoded);
+    Md4::digest(encoded.unsecure()).into()
+}
+
+/// NTLM hashes of the saved passwords sorted for the search. The findings refer to the position in
+/// this sorted list, so they are mapped back to the position of the saved hash.
+pub struct NtlmHashes {
+    pub hashes: Vec<SavedHash>,
+    original: Vec<usize>,
+}
+
+impl NtlmHashes {
+    pub fn new(saved: &[SavedHash]) -> Self {
+        let mut sorted: Vec<(Sha1Hash, usize)> = saved
+            .iter()
+            .enumerate()
+            .filter_map(|(index, saved)| {
+                let mut padded = [0; SHA1_BYTE_LENGTH];
+                padded[..NTLM_BYTE_LENGTH].copy_from_slice(saved.ntlm_hash.as_ref()?);
+                Some((padded, index))
+            })
+            .collect();
+        sorted.sort_unstable();
+
+        NtlmHashes {
+            hashes: sorted
+                .iter()
+                .map(|&(hash, _)| SavedHash::from_hash(hash))
+                .collect(),
+            original: sorted.into_iter().map(|(_, index)| index).collect(),
+        }
+    }
+
+    pub fn remap(&self, findings: Vec<Finding>) -> Vec<Finding> {
+        findings
+            .into_iter()
+            .map(|finding| Finding {
+                index: self.original[finding.index],
+                ..finding
+            })
+            .collect()
+    }
+}
+
+/// Other Unicode forms of the saved passwords sorted for the search like `NtlmHashes`. The findings
+/// are mapped back to the saved hash and grouped by the form.
+pub struct FormHashes {
+    pub hashes: Vec<SavedHash>,
+    original: Vec<(usize, UnicodeForm)>,
+}
 
-    let mut buffer = csv::StringRecord::new();
-    while file_reader.read_record(&mut buffer)? {
-        let record: SavedPassword = buffer.deserialize(Some(&headers))?;
-        // Safety: unexpected channel disconnect should panic
-        tx.send(record).unwrap();
+impl FormHashes {
+    pub fn new(saved: &[SavedHash]) -> Self {
+        let mut sorted: Vec<(Sha1Hash, usize, UnicodeForm)> = saved
+            .iter()
+            .enumerate()
+            .flat_map(|(index, saved)| {
+                saved
+                    .unicode_forms
+                    .iter()
+                    .map(move |&(form, hash)| (hash, index, form))
+            })
+            .collect();
+        sorted.sort_unstable();
+
+        FormHashes {
+            hashes: sorted
+                .iter()
+                .map(|&(hash, _, _)| SavedHash::from_hash(hash))
+                .collect(),
+            original: sorted
+                .into_iter()
+                .map(|(_, index, form)| (index, form))
+                .collect(),
+        }
     }
 
-    Ok(())
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Findings of each form referring to the saved hashes
+    pub fn remap(&self, findings: Vec<Finding>) -> Vec<(UnicodeForm, Vec<Finding>)> {
+        UnicodeForm::ALL
+            .iter()
+            .map(|&form| {
+                let form_findings = findings
+                    .iter()
+                    .filter(|finding| self.original[finding.index].1 == form)
+                    .map(|finding| Finding {
+                        index: self.original[finding.index].0,
+                        ..*finding
+                    })
+                    .collect();
+                (form, form_findings)
+            })
+            .collect()
+    }
 }
 
-fn hash_pass(password_bytes: &[u8]) -> Digest {
-    digest(&SHA1_FOR_LEGACY_USE_ONLY, password_bytes)
+/// Saved hashes without a match in the local hash databases. Only these are looked up online in
+/// the hybrid mode and the findings are mapped back like `NtlmHashes`.
+pub struct UnmatchedHashes {
+    pub hashes: Vec<SavedHash>,
+    original: Vec<usize>,
+}
+
+impl UnmatchedHashes {
+    /// The saved hashes have to be sorted, so the remaining ones are sorted as well
+    pub fn new<'a>(saved: &[SavedHash], matched: impl IntoIterator<Item = &'a Finding>) -> Self {
+        let mut is_matched = vec![false; saved.len()];
+        for finding in matched {
+            is_matched[finding.index] = true;
+        }
+
+        let original: Vec<usize> = (0..saved.len())
+            .filter(|&index| !is_matched[index])
+            .collect();
+        UnmatchedHashes {
+            hashes: original
+                .iter()
+                .map(|&index| SavedHash::from_hash(saved[index].password_hash))
+                .collect(),
+            original,
+        }
+    }
+
+    pub fn remap(&self, findings: Vec<Finding>) -> Vec<Finding> {
+        findings
+            .into_iter()
+            .map(|finding| Finding {
+                index: self.original[finding.index],
+                ..finding
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -131,37 +892,466 @@ mod test {
 
     use super::*;
 
+    const PIPELINE_BUFFER: usize = 128;
+
+    fn options(pipeline_buffer: usize) -> HashOptions {
+        HashOptions {
+            pipeline_buffer,
+            pepper: Pepper::none(),
+            ntlm: false,
+            filter: EntryFilter::default(),
+            normalization: Normalization::default(),
+            unicode_forms: false,
+        }
+    }
+
     const HASH_EXPECTED: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
 
     #[test]
     fn test_hash() {
-        assert_eq!(HEXLOWER.encode(hash_pass(b"hello").as_ref()), HASH_EXPECTED)
+        assert_eq!(
+            HEXLOWER.encode(hash_pass(b"", b"hello").as_ref()),
+            HASH_EXPECTED
+        )
+    }
+
+    #[test]
+    fn test_hash_peppered() {
+        let hash = hash_pass(&[0xC0, 0xFF, 0xEE], b"hello");
+        assert_eq!(
+            HEXLOWER.encode(hash.as_ref()),
+            "f1dd230b272d50fec5a6e4af52651ed4c9d3b6c7"
+        );
+    }
+
+    #[test]
+    fn test_padded() {
+        let saved = SavedHash::from_hash(hash_pass(b"", b"hello"));
+        let (hash, padding) = saved.padded().split_at(SHA1_BYTE_LENGTH);
+        assert_eq!(hash, saved.password_hash);
+        assert!(padding.iter().all(|&byte| byte == 0));
     }
 
     #[test]
     fn test_hash_failed() {
-        assert_ne!(HEXLOWER.encode(hash_pass(b"fail").as_ref()), HASH_EXPECTED)
+        assert_ne!(
+            HEXLOWER.encode(hash_pass(b"", b"fail").as_ref()),
+            HASH_EXPECTED
+        )
     }
 
     #[test]
-    fn parse_chromium_csv() -> Result<(), csv::Error> {
+    fn test_ntlm_hash() {
+        let expected = "8846f7eaee8fb117ad06bdd830b7586c";
+        assert_eq!(HEXLOWER.encode(&ntlm_hash(b"password")), expected);
+
+        // UTF-16 of the decoded characters instead of the UTF-8 bytes
+        let umlaut = "p\u{e4}ssword".as_bytes();
+        assert_ne!(ntlm_hash(umlaut), ntlm_hash(b"p\xE4ssword"));
+    }
+
+    #[test]
+    fn test_ntlm_sorted() -> Result<(), CollectError> {
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,pass
+https://crates.io/,user,password";
+
+        let mut options = options(PIPELINE_BUFFER);
+        options.ntlm = true;
+        let cancel = CancellationToken::default();
+        let reader = csv::Reader::from_reader(&data[..]);
+        let mut hashes =
+            collect_hashes(reader, &options, &cancel, &mut Metrics::default())?.into_inner();
+        hashes.sort_unstable();
+
+        let ntlm = NtlmHashes::new(&hashes);
+        assert!(ntlm.hashes.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let position = hashes
+            .iter()
+            .position(|saved| saved.url() == "https://crates.io/");
+        let sorted = ntlm
+            .hashes
+            .iter()
+            .position(|saved| saved.password_hash[..NTLM_BYTE_LENGTH] == ntlm_hash(b"password"));
+        let findings = vec![Finding {
+            index: sorted.unwrap(),
+            count: Some(1),
+        }];
+        assert_eq!(ntlm.remap(findings)[0].index, position.unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unmatched_remap() {
+        let hashes = [
+            SavedHash::from_hash([1; SHA1_BYTE_LENGTH]),
+            SavedHash::from_hash([2; SHA1_BYTE_LENGTH]),
+            SavedHash::from_hash([3; SHA1_BYTE_LENGTH]),
+        ];
+        let matched = [Finding {
+            index: 1,
+            count: Some(7),
+        }];
+
+        let unmatched = UnmatchedHashes::new(&hashes, &matched);
+        assert_eq!(unmatched.hashes.len(), 2);
+        assert_eq!(unmatched.hashes[1].password_hash, [3; SHA1_BYTE_LENGTH]);
+
+        let findings = vec![Finding {
+            index: 1,
+            count: Some(2),
+        }];
+        assert_eq!(unmatched.remap(findings)[0].index, 2);
+    }
+
+    #[test]
+    fn test_unicode_forms() -> Result<(), CollectError> {
+        // composed ä, decomposed ä and ASCII
+        let data = "url,username,password
+https://www.rust-lang.org/,user,p\u{e4}ssword
+https://crates.io/,user,pa\u{308}ssword
+https://docs.rs/,user,password";
+
+        let mut options = options(PIPELINE_BUFFER);
+        options.unicode_forms = true;
+        let cancel = CancellationToken::default();
+        let reader = csv::Reader::from_reader(data.as_bytes());
+        let mut hashes =
+            collect_hashes(reader, &options, &cancel, &mut Metrics::default())?.into_inner();
+        hashes.sort_unstable();
+
+        let composed = hash_pass(b"", "p\u{e4}ssword".as_bytes());
+        let decomposed = hash_pass(b"", "pa\u{308}ssword".as_bytes());
+        let forms = |url| {
+            hashes
+                .iter()
+                .find(|saved| saved.url() == url)
+                .unwrap()
+                .unicode_forms
+                .clone()
+        };
+        assert_eq!(
+            forms("https://www.rust-lang.org/"),
+            [(UnicodeForm::Nfd, decomposed)]
+        );
+        assert_eq!(forms("https://crates.io/"), [(UnicodeForm::Nfc, composed)]);
+        assert!(forms("https://docs.rs/").is_empty());
+
+        // a finding of the NFC form belongs to the decomposed password
+        let form_hashes = FormHashes::new(&hashes);
+        assert_eq!(form_hashes.hashes.len(), 2);
+        let sorted = form_hashes
+            .hashes
+            .iter()
+            .position(|saved| saved.password_hash == composed.as_ref())
+            .unwrap();
+        let findings = vec![Finding {
+            index: sorted,
+            count: Some(3),
+        }];
+
+        let position = hashes
+            .iter()
+            .position(|saved| saved.url() == "https://crates.io/")
+            .unwrap();
+        let remapped = form_hashes.remap(findings);
+        assert_eq!(remapped[0].0, UnicodeForm::Nfc);
+        assert_eq!(
+            remapped[0].1,
+            [Finding {
+                index: position,
+                count: Some(3)
+            }]
+        );
+        assert!(remapped[1].1.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_chromium_csv() -> Result<(), CollectError> {
         let data = b"name,url,username,password
 hello,https://www.rust-lang.org/,user,pass";
         validate_parse(data)
     }
 
     #[test]
-    fn parse_firefox_csv() -> Result<(), csv::Error> {
+    fn parse_firefox_csv() -> Result<(), CollectError> {
         // use r#"XYZ"# to escape " inside the string - Warning " are also necessary
         let data = r#""url","username","password","httpRealm","formActionOrigin","guid","timeCreated","timeLastUsed","timePasswordChanged""
 ""https://www.rust-lang.org/","user","pass",,"https://www.rust-lang.org/","{00000000-0000-0000-0000-0000000000000000}","-1","-2","-3""#;
         validate_parse(data.as_bytes())
     }
 
-    fn validate_parse(csv_data: &[u8]) -> Result<(), csv::Error> {
+    #[test]
+    fn parse_firefox_changed() -> Result<(), CollectError> {
+        let data = r#""url","username","password","timeCreated","timeLastUsed","timePasswordChanged"
+"https://www.rust-lang.org/","user","pass","1500000000000","1700000000000","1600000000123"
+"https://www.rust-lang.org/","user","pass","1500000000000","-2","-3""#;
+
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let columns = Columns::from_headers(reader.byte_headers()?)?;
+        let times: Vec<_> = reader
+            .byte_records()
+            .map(|record| record.map(|record| columns.parse(&record).times))
+            .collect::<Result<_, _>>()?;
+
+        let millis = |millis| Some(UNIX_EPOCH + Duration::from_millis(millis));
+        assert_eq!(
+            times,
+            [
+                Timestamps {
+                    created: millis(1_500_000_000_000),
+                    last_used: millis(1_700_000_000_000),
+                    changed: millis(1_600_000_000_123),
+                },
+                Timestamps {
+                    created: millis(1_500_000_000_000),
+                    last_used: None,
+                    changed: None,
+                }
+            ]
+        );
+
+        // never changed passwords are as old as the entry
+        assert_eq!(times[0].password_set(), times[0].changed);
+        assert_eq!(times[1].password_set(), times[1].created);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_cancelled() -> Result<(), CollectError> {
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,pass";
+
+        let cancel = CancellationToken::default();
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &options(PIPELINE_BUFFER),
+            &cancel,
+            &mut Metrics::default(),
+        )?;
+        assert_matches!(status, Status::Completed(ref hashes) if hashes.len() == 1);
+
+        cancel.cancel();
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &options(PIPELINE_BUFFER),
+            &cancel,
+            &mut Metrics::default(),
+        )?;
+        assert_matches!(status, Status::Cancelled(ref hashes) if hashes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_rendezvous_buffer() -> Result<(), CollectError> {
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,pass
+https://crates.io/,user,other";
+
+        // zero capacity hands over each record directly to a waiting thread
+        let cancel = CancellationToken::default();
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &options(0),
+            &cancel,
+            &mut Metrics::default(),
+        )?;
+        assert_matches!(status, Status::Completed(ref hashes) if hashes.len() == 2);
+        Ok(())
+    }
+
+    fn panicking_hash(password: &[u8]) -> Sha1Hash {
+        if password == b"panic" {
+            panic!("injected hashing failure");
+        }
+
+        hash_pass(b"", password)
+    }
+
+    #[test]
+    fn test_collect_worker_panic() {
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,pass
+https://crates.io/,user,panic
+https://docs.rs/,user,other";
+
+        let cancel = CancellationToken::default();
+        let reader = PasswordSource::Csv(csv::Reader::from_reader(&data[..]));
+        let result = collect_with(
+            reader,
+            &options(0),
+            &cancel,
+            &mut Metrics::default(),
+            &panicking_hash,
+        );
+        assert_matches!(result, Err(CollectError::WorkerPanic(ref message)) if message == "injected hashing failure");
+    }
+
+    #[test]
+    fn test_non_utf8_password() -> Result<(), CollectError> {
+        // Latin-1 encoded "pässword" - the exact bytes should be hashed
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,p\xE4ssword";
+
+        let cancel = CancellationToken::default();
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &options(PIPELINE_BUFFER),
+            &cancel,
+            &mut Metrics::default(),
+        )?;
+        let hashes = status.into_inner();
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(
+            hashes[0].password_hash,
+            hash_pass(b"", b"p\xE4ssword").as_ref()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_password() -> Result<(), CollectError> {
+        let data = "url,username,password
+https://www.rust-lang.org/,user,\u{feff}password\t";
+
+        let collect = |normalization| {
+            let options = HashOptions {
+                normalization,
+                ..options(PIPELINE_BUFFER)
+            };
+            let status = collect_hashes(
+                csv::Reader::from_reader(data.as_bytes()),
+                &options,
+                &CancellationToken::default(),
+                &mut Metrics::default(),
+            )?;
+            Ok::<_, CollectError>(status.into_inner()[0].password_hash)
+        };
+
+        let exact = collect(Normalization::default())?;
+        assert_ne!(exact, hash_pass(b"", b"password").as_ref());
+
+        let normalized = collect(Normalization {
+            trim: true,
+            strip_bom: true,
+            nfc: false,
+        })?;
+        assert_eq!(normalized, hash_pass(b"", b"password").as_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let data = b"name,url,username
+rust,https://www.rust-lang.org/,user";
+
+        let cancel = CancellationToken::default();
+        let result = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &options(PIPELINE_BUFFER),
+            &cancel,
+            &mut Metrics::default(),
+        );
+        assert_matches!(result, Err(CollectError::UnknownHeader));
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let headers = ByteRecord::from(vec!["url", "username", "password"]);
+        let record = ByteRecord::from(vec!["https://example.com/", "alice", "fixture-Secret-4711"]);
+        let saved = Columns::from_headers(&headers).unwrap().parse(&record);
+
+        let debug = format!("{:?}", saved);
+        assert!(!debug.contains("fixture-Secret-4711"), "{}", debug);
+        assert!(debug.contains("alice"), "{}", debug);
+    }
+
+    #[test]
+    fn test_tags() -> Result<(), CollectError> {
+        let data = b"url,username,password,Group,collections
+https://www.rust-lang.org/,user,pass,Root/Work,\"Shared, Admins\"
+https://crates.io/,user,other,Root/Archive,
+https://docs.rs/,user,third,,";
+
+        let mut reader = csv::Reader::from_reader(&data[..]);
+        let columns = Columns::from_headers(reader.byte_headers()?)?;
+        let tags: Vec<_> = reader
+            .byte_records()
+            .map(|record| Ok(columns.parse(&record?).tags))
+            .collect::<Result<_, CollectError>>()?;
+        assert_eq!(tags[0], ["Root/Work", "Shared", "Admins"]);
+        assert!(tags[2].is_empty());
+
+        let mut hash_options = options(PIPELINE_BUFFER);
+        hash_options.filter.tags = TagFilter {
+            include: vec!["work".to_string(), "archive".to_string()],
+            exclude: vec!["Admins".to_string()],
+        };
+
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &hash_options,
+            &CancellationToken::default(),
+            &mut Metrics::default(),
+        )?;
+        let hashes = status.into_inner();
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(hashes[0].tags(), ["Root/Archive"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclude_patterns() -> Result<(), CollectError> {
+        let data = b"url,username,password
+https://www.rust-lang.org/,user,pass
+https://10.0.0.1/,admin,other
+https://crates.io/,user-test2,third";
+
+        let mut hash_options = options(PIPELINE_BUFFER);
+        hash_options.filter.exclude_patterns =
+            RegexSet::new([r"-test", r"^https?://\d+\.\d+\.\d+\.\d+"]).unwrap();
+
+        let status = collect_hashes(
+            csv::Reader::from_reader(&data[..]),
+            &hash_options,
+            &CancellationToken::default(),
+            &mut Metrics::default(),
+        )?;
+        let hashes = status.into_inner();
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(hashes[0].url(), "https://www.rust-lang.org/");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let tags = ["Root/Work".to_string()];
+        assert!(TagFilter::default().allows(&tags));
+        assert!(TagFilter::default().allows(&[]));
+
+        let include = TagFilter {
+            include: vec!["Work".to_string()],
+            exclude: Vec::new(),
+        };
+        assert!(include.allows(&tags));
+        assert!(!include.allows(&[]));
+        assert!(!include.allows(&["Workshop".to_string()]));
+
+        let exclude = TagFilter {
+            include: Vec::new(),
+            exclude: vec!["root".to_string()],
+        };
+        assert!(!exclude.allows(&tags));
+        assert!(exclude.allows(&[]));
+    }
+
+    fn validate_parse(csv_data: &[u8]) -> Result<(), CollectError> {
         let mut reader = csv::Reader::from_reader(csv_data);
-        for result in reader.deserialize() {
-            let record: SavedPassword = result?;
+        let columns = Columns::from_headers(reader.byte_headers()?)?;
+        for result in reader.byte_records() {
+            let record = columns.parse(&result?);
             assert_eq!(record.url, "https://www.rust-lang.org/");
             assert_eq!(record.username, "user");
             assert_eq!(record.password, SecStr::from("pass"));
@@ -0,0 +1,117 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{atomic::atomic_write, paths::Location};
+
+const FILE_NAME: &str = "config";
+
+/// Settings written by `setup`. Stored as `key=value` lines like the corpus metadata, so it could be
+/// edited by hand.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// Hash list used without a hash file argument - a path or an URL
+    pub corpus: Option<String>,
+    /// User-Agent of the HTTP requests instead of the default one
+    pub user_agent: Option<String>,
+}
+
+impl Config {
+    /// File in the config directory (ex: `~/.config/pwned-check/config`)
+    pub fn path() -> Option<PathBuf> {
+        Location::Config.user_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Configuration or none if it was never written
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(parse(&content))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut content =
+            "# pwned-check configuration - written by pwned-check setup\n".to_string();
+        if let Some(corpus) = &self.corpus {
+            // unwrap is safe, because writing to a string never fails
+            writeln!(content, "corpus={}", corpus).unwrap();
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            // unwrap is safe, because writing to a string never fails
+            writeln!(content, "user_agent={}", user_agent).unwrap();
+        }
+
+        atomic_write(path, |file| file.write_all(content.as_bytes()))
+    }
+}
+
+fn parse(content: &str) -> Config {
+    let mut config = Config::default();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+
+        let value = Some(value.to_string()).filter(|value| !value.is_empty());
+        // unknown keys are skipped for newer versions
+        match key {
+            "corpus" => config.corpus = value,
+            "user_agent" => config.user_agent = value,
+            _ => {}
+        }
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let path = std::env::temp_dir()
+            .join(format!("pwned-check-config-{}", std::process::id()))
+            .join(FILE_NAME);
+        assert_eq!(Config::read(&path).unwrap(), None);
+
+        let config = Config {
+            corpus: Some("https://example.com/pwned.txt".to_string()),
+            user_agent: Some("acme-audit/2.1 (security@example.com)".to_string()),
+        };
+        config.write(&path).unwrap();
+        assert_eq!(Config::read(&path).unwrap(), Some(config));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("# comment\ncorpus = /data/pwned.fst\nfuture=value\n").corpus,
+            Some("/data/pwned.fst".to_string())
+        );
+        assert_eq!(parse("corpus=\n"), Config::default());
+
+        // the value could contain the separator itself
+        assert_eq!(
+            parse("user_agent = acme-audit/2.1 (+https://example.com/?a=b)\n").user_agent,
+            Some("acme-audit/2.1 (+https://example.com/?a=b)".to_string())
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Calendar date and time in UTC
+#[derive(Debug, PartialEq, Eq)]
+pub struct UtcTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl UtcTime {
+    /// Times before the epoch are clamped to it
+    pub fn from_system(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+        // civil date from the days since the epoch: http://howardhinnant.github.io/date_algorithms.html
+        let shifted = days as i64 + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_shifted = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_shifted + 2) / 5 + 1;
+        let month = if month_shifted < 10 {
+            month_shifted + 3
+        } else {
+            month_shifted - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcTime {
+            year,
+            month,
+            day,
+            hour: day_secs / 3600,
+            minute: day_secs / 60 % 60,
+            second: day_secs % 60,
+        }
+    }
+}
+
+/// RFC 3339 timestamp in UTC (ex: `2013-05-24T00:00:00Z`)
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let utc = UtcTime::from_system(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        utc.year, utc.month, utc.day, utc.hour, utc.minute, utc.second
+    )
+}
+
+/// Calendar date in UTC (ex: `2013-05-24`)
+pub fn format_date(time: SystemTime) -> String {
+    let utc = UtcTime::from_system(time);
+    format!("{:04}-{:02}-{:02}", utc.year, utc.month, utc.day)
+}
+
+/// Calendar date in the local timezone for the text output - the structured outputs stay in UTC
+pub fn format_local_date(time: SystemTime) -> String {
+    format_date(shift(time, local_offset(time)))
+}
+
+/// Move the time by the offset in seconds, so the UTC calendar shows the local date
+fn shift(time: SystemTime, offset: i64) -> SystemTime {
+    let amount = Duration::from_secs(offset.unsigned_abs());
+    if offset >= 0 {
+        time + amount
+    } else {
+        // times before the epoch are clamped when formatting
+        time.checked_sub(amount).unwrap_or(UNIX_EPOCH)
+    }
+}
+
+/// Seconds east of UTC including daylight saving time at the given time
+#[cfg(unix)]
+fn local_offset(time: SystemTime) -> i64 {
+    use std::mem::MaybeUninit;
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs()) as libc::time_t;
+    let mut local = MaybeUninit::<libc::tm>::uninit();
+    // unsafe is safe, because the struct is only read on success
+    unsafe {
+        if libc::localtime_r(&secs, local.as_mut_ptr()).is_null() {
+            return 0;
+        }
+
+        #[allow(clippy::unnecessary_cast)]
+        let offset = local.assume_init().tm_gmtoff as i64;
+        offset
+    }
+}
+
+/// Other platforms show UTC dates
+#[cfg(not(unix))]
+fn local_offset(_time: SystemTime) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rfc3339() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_369_353_600);
+        assert_eq!(format_rfc3339(time), "2013-05-24T00:00:00Z");
+
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_825_599);
+        assert_eq!(format_rfc3339(leap_day), "2000-02-29T11:59:59Z");
+        assert_eq!(format_date(leap_day), "2000-02-29");
+    }
+
+    #[test]
+    fn test_shift() {
+        // late evening in UTC is already the next day east of it
+        let evening = UNIX_EPOCH + Duration::from_secs(1_369_429_200);
+        assert_eq!(format_date(evening), "2013-05-24");
+        assert_eq!(format_date(shift(evening, 4 * 3600)), "2013-05-25");
+        assert_eq!(format_date(shift(evening, -22 * 3600)), "2013-05-23");
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufReader, Read},
+    num::NonZeroU32,
+    path::Path,
+};
+
+use aes_gcm::{
+    aead::{consts::U32, generic_array::GenericArray, Aead, KeyInit, Payload},
+    aes::Aes256,
+    AesGcm,
+};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use data_encoding::{BASE64, HEXLOWER_PERMISSIVE};
+use ring::{hmac, pbkdf2};
+use secstr::SecStr;
+use serde_yaml::Value;
+
+/// Iterations of the key derivation of `ansible-vault`
+const VAULT_ITERATIONS: u32 = 10_000;
+
+/// Prefix of values encrypted by SOPS
+const SOPS_PREFIX: &str = "ENC[AES256_GCM,";
+
+/// SOPS uses 256 bit nonces instead of the usual 96 bit
+type SopsCipher = AesGcm<Aes256, U32>;
+
+type VaultCipher = ctr::Ctr128BE<Aes256>;
+
+#[derive(Debug)]
+pub enum DecryptError {
+    Io(io::Error),
+    /// Key material required to decrypt it (ex: the vault password)
+    MissingKey(&'static str),
+    /// Unsupported or damaged format
+    Format(String),
+    /// Wrong key or the encrypted data was modified
+    Integrity,
+    Age(age::DecryptError),
+}
+
+impl Display for DecryptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::Io(err) => write!(f, "{}", err),
+            DecryptError::MissingKey(key) => write!(f, "Missing {}", key),
+            DecryptError::Format(err) => write!(f, "{}", err),
+            DecryptError::Integrity => write!(f, "Wrong key or modified data"),
+            DecryptError::Age(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for DecryptError {}
+
+impl From<io::Error> for DecryptError {
+    fn from(err: io::Error) -> Self {
+        DecryptError::Io(err)
+    }
+}
+
+impl From<age::DecryptError> for DecryptError {
+    fn from(err: age::DecryptError) -> Self {
+        DecryptError::Age(err)
+    }
+}
+
+/// Key material to decrypt the files in memory - the plaintext is never written to disk
+#[derive(Default)]
+pub struct Keys {
+    /// Password of `ansible-vault`
+    pub vault_password: Option<SecStr>,
+    /// Identities of SOPS files encrypted for age recipients
+    pub age_identities: Vec<Box<dyn age::Identity>>,
+}
+
+impl Keys {
+    /// Vault password of the file like `--vault-password-file` of Ansible - the trailing line break
+    /// isn't part of it
+    pub fn read_vault_password(path: &Path) -> io::Result<SecStr> {
+        let mut content = Vec::new();
+        File::open(path)?.read_to_end(&mut content)?;
+        let password = SecStr::new(content.trim_ascii_end().to_vec());
+        // clear the read content on drop
+        SecStr::new(content);
+        Ok(password)
+    }
+
+    /// Age identities of the key file (ex: `~/.config/sops/age/keys.txt`)
+    pub fn read_age_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>, DecryptError> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(age::IdentityFile::from_buffer(file)?.into_identities()?)
+    }
+}
+
+/// Decrypt a file or value encrypted by `ansible-vault` (format 1.1 or 1.2 with a vault id)
+pub fn decrypt_vault(text: &[u8], password: &SecStr) -> Result<SecStr, DecryptError> {
+    let text = std::str::from_utf8(text)
+        .map_err(|_| DecryptError::Format("Vault isn't ASCII".to_string()))?;
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default().trim();
+    match header.split(';').nth(2) {
+        Some("AES256") => {}
+        _ => {
+            return Err(DecryptError::Format(format!(
+                "Unsupported vault {}",
+                header
+            )))
+        }
+    }
+
+    // the hex is wrapped after 80 characters and inline values could be indented
+    let hex: String = lines.flat_map(str::split_whitespace).collect();
+    let envelope = decode_hex(hex.as_bytes())?;
+    let mut parts = envelope.split(|&byte| byte == b'\n');
+    let mut next_part = || decode_hex(parts.next().unwrap_or_default());
+    let (salt, mac, mut data) = (next_part()?, next_part()?, next_part()?);
+
+    // the derived bytes are the cipher key, the HMAC key and the initial counter
+    let mut derived = [0; 80];
+    // unwrap is safe, because the constant isn't zero
+    let iterations = NonZeroU32::new(VAULT_ITERATIONS).unwrap();
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.unsecure(),
+        &mut derived,
+    );
+    let (key, rest) = derived.split_at(32);
+    let (mac_key, counter) = rest.split_at(32);
+
+    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, mac_key), &data, &mac)
+        .map_err(|_| DecryptError::Integrity)?;
+
+    // unwrap is safe, because the slices have the key and block size
+    let mut cipher = VaultCipher::new_from_slices(key, counter).unwrap();
+    cipher.apply_keystream(&mut data);
+    derived.fill(0);
+
+    let padding = usize::from(data.last().copied().unwrap_or_default());
+    if padding == 0 || padding > 16 || padding > data.len() {
+        return Err(DecryptError::Format("Invalid vault padding".to_string()));
+    }
+
+    data.truncate(data.len() - padding);
+    Ok(SecStr::new(data))
+}
+
+fn decode_hex(hex: &[u8]) -> Result<Vec<u8>, DecryptError> {
+    HEXLOWER_PERMISSIVE
+        .decode(hex)
+        .map_err(|err| DecryptError::Format(format!("Invalid vault {}", err)))
+}
+
+/// Decrypt all values of the SOPS document in place and remove the metadata. Only the data key
+/// encrypted for age recipients is supported.
+pub fn decrypt_sops(
+    document: &mut Value,
+    identities: &[Box<dyn age::Identity>],
+) -> Result<(), DecryptError> {
+    let data_key = sops_data_key(document, identities)?;
+    if let Value::Mapping(entries) = document {
+        entries.remove("sops");
+    }
+
+    decrypt_sops_values(document, &mut Vec::new(), &data_key)
+}
+
+fn sops_data_key(
+    document: &Value,
+    identities: &[Box<dyn age::Identity>],
+) -> Result<SecStr, DecryptError> {
+    if identities.is_empty() {
+        return Err(DecryptError::MissingKey("age identity"));
+    }
+
+    let recipients = document
+        .get("sops")
+        .and_then(|sops| sops.get("age"))
+        .and_then(Value::as_sequence)
+        .ok_or_else(|| DecryptError::Format("Data key isn't encrypted for age".to_string()))?;
+
+    for recipient in recipients {
+        let encrypted = match recipient.get("enc").and_then(Value::as_str) {
+            Some(encrypted) => encrypted,
+            None => continue,
+        };
+
+        let armored = age::armor::ArmoredReader::new(encrypted.as_bytes());
+        let decrypted = age::Decryptor::new(armored)?
+            .decrypt(identities.iter().map(|identity| identity.as_ref()));
+        match decrypted {
+            Ok(mut reader) => {
+                let mut data_key = Vec::new();
+                reader.read_to_end(&mut data_key)?;
+                return Ok(SecStr::new(data_key));
+            }
+            // the data key is encrypted for each recipient
+            Err(age::DecryptError::NoMatchingKeys) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err(DecryptError::MissingKey("matching age identity"))
+}
+
+/// The keys of the path are the additional authenticated data - list items use the path of the
+/// list
+fn decrypt_sops_values(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    data_key: &SecStr,
+) -> Result<(), DecryptError> {
+    match value {
+        Value::Mapping(entries) => {
+            for (key, value) in entries.iter_mut() {
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    Value::Number(key) => key.to_string(),
+                    _ => continue,
+                };
+
+                path.push(key);
+                let decrypted = decrypt_sops_values(value, path, data_key);
+                path.pop();
+                decrypted?;
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                decrypt_sops_values(item, path, data_key)?;
+            }
+        }
+        Value::String(encrypted) if encrypted.starts_with(SOPS_PREFIX) => {
+            let aad = format!("{}:", path.join(":"));
+            *value = decrypt_sops_value(encrypted, &aad, data_key)?;
+        }
+        Value::Tagged(tagged) => decrypt_sops_values(&mut tagged.value, path, data_key)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Decrypt `ENC[AES256_GCM,data:<BASE64>,iv:<BASE64>,tag:<BASE64>,type:<TYPE>]`
+fn decrypt_sops_value(
+    encrypted: &str,
+    aad: &str,
+    data_key: &SecStr,
+) -> Result<Value, DecryptError> {
+    let invalid = || DecryptError::Format(format!("Invalid SOPS value at {}", aad));
+    let fields = encrypted[SOPS_PREFIX.len()..]
+        .strip_suffix(']')
+        .ok_or_else(invalid)?;
+
+    let (mut data, mut iv, mut tag, mut kind) = (None, None, None, None);
+    for field in fields.split(',') {
+        match field.split_once(':') {
+            Some(("data", value)) => data = Some(value),
+            Some(("iv", value)) => iv = Some(value),
+            Some(("tag", value)) => tag = Some(value),
+            Some(("type", value)) => kind = Some(value),
+            _ => {}
+        }
+    }
+
+    let decode = |value: Option<&str>| {
+        BASE64
+            .decode(value.ok_or_else(invalid)?.as_bytes())
+            .map_err(|_| invalid())
+    };
+    let mut ciphertext = decode(data)?;
+    ciphertext.extend(decode(tag)?);
+    let iv = decode(iv)?;
+    if iv.len() != 32 {
+        return Err(invalid());
+    }
+
+    let cipher = SopsCipher::new_from_slice(data_key.unsecure()).map_err(|_| invalid())?;
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: aad.as_bytes(),
+    };
+    let plaintext = cipher
+        .decrypt(GenericArray::from_slice(&iv), payload)
+        .map_err(|_| DecryptError::Integrity)?;
+    let plaintext = String::from_utf8(plaintext).map_err(|_| invalid())?;
+
+    // numbers and booleans keep their type like in the original document
+    Ok(match kind {
+        Some("str") | Some("bytes") | None => Value::String(plaintext),
+        Some(_) => serde_yaml::from_str(&plaintext).unwrap_or(Value::String(plaintext)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, iter};
+
+    use age::secrecy::ExposeSecret;
+
+    use super::*;
+
+    /// Generated by an independent implementation of the ansible-vault format
+    const VAULT: &str = "$ANSIBLE_VAULT;1.1;AES256
+30303031303230333034303530363037303830393061306230633064306530663130313131323133
+3134313531363137313831393161316231633164316531660a393636336339636136353833386265
+39323966633662633363326537363864363464616664643139623865666231323039366232653038
+3037613137363131350a383836396262613964306364636666666364643565306431343864316266
+37623031666637623763396264656238313434336537343233646339633033356634363336386261
+3339356461653433313534646430313233303137306663653230
+";
+
+    const VAULT_ID: &str = "$ANSIBLE_VAULT;1.2;AES256;prod
+30313032303330343035303630373038303930613062306330643065306631303131313231333134
+3135313631373138313931613162316331643165316632300a386362343931653064653133383461
+39393366646164303463333238613962303438323730393232383136363936663532336463343162
+6563386634653063630a643532393964353531396562616664333664383130373137306364373830
+6430
+";
+
+    #[test]
+    fn test_vault() {
+        let password = SecStr::from("vault-secret");
+        let decrypted = decrypt_vault(VAULT.as_bytes(), &password).unwrap();
+        assert_eq!(
+            decrypted.unsecure(),
+            b"db_user: admin\ndb_password: hunter2\n"
+        );
+
+        let decrypted = decrypt_vault(VAULT_ID.as_bytes(), &password).unwrap();
+        assert_eq!(decrypted.unsecure(), b"hunter2");
+
+        assert_matches!(
+            decrypt_vault(VAULT.as_bytes(), &SecStr::from("wrong")),
+            Err(DecryptError::Integrity)
+        );
+        assert_matches!(
+            decrypt_vault(b"$ANSIBLE_VAULT;1.1;AES128\n3030\n", &password),
+            Err(DecryptError::Format(_))
+        );
+    }
+
+    /// Data key of the SOPS values below - encrypted by an independent implementation
+    const DATA_KEY: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    fn sops_document(identity: &age::x25519::Identity) -> String {
+        let recipient = identity.to_public();
+        let encryptor =
+            age::Encryptor::with_recipients(iter::once(&recipient as &dyn age::Recipient)).unwrap();
+        let mut armored = Vec::new();
+        let output =
+            age::armor::ArmoredWriter::wrap_output(&mut armored, age::armor::Format::AsciiArmor)
+                .unwrap();
+        let mut writer = encryptor.wrap_output(output).unwrap();
+        writer.write_all(&DATA_KEY).unwrap();
+        writer.finish().unwrap().finish().unwrap();
+
+        let armored = String::from_utf8(armored)
+            .unwrap()
+            .replace('\n', "\n        ");
+        format!(
+            "database:
+  user: ENC[AES256_GCM,data:qyW+riY=,iv:ZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7fH1+f4CBgoM=,tag:RWkts2E8L2/x4EMb/50yEg==,type:str]
+  password: ENC[AES256_GCM,data:ojS9sy1swQ==,iv:ZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7fH1+f4CBgoM=,tag:YNeDDoxCnqXdVpTDsCPQ2A==,type:str]
+environment:
+  - ENC[AES256_GCM,data:ToTlO3yVula/BI9zORJ81S/427zF,iv:MjM0NTY3ODk6Ozw9Pj9AQUJDREVGR0hJSktMTU5PUFE=,tag:N2CJGHxjcXmcs32hvCqNFg==,type:str]
+pin_password: ENC[AES256_GCM,data:lLi89mEm,iv:CgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSYnKCk=,tag:uVUF+ejpKt8KN5654MCerA==,type:int]
+sops:
+  age:
+    - recipient: {}
+      enc: |
+        {}
+  version: 3.8.1
+",
+            recipient, armored
+        )
+    }
+
+    #[test]
+    fn test_sops() {
+        let identity = age::x25519::Identity::generate();
+        let mut document: Value = serde_yaml::from_str(&sops_document(&identity)).unwrap();
+
+        let identities: Vec<Box<dyn age::Identity>> = vec![Box::new(identity)];
+        decrypt_sops(&mut document, &identities).unwrap();
+
+        let expected: Value = serde_yaml::from_str(
+            "database:
+  user: admin
+  password: hunter2
+environment:
+  - REDIS_PASSWORD=secret
+pin_password: 123456
+",
+        )
+        .unwrap();
+        assert_eq!(document, expected);
+    }
+
+    #[test]
+    fn test_sops_wrong_key() {
+        let identity = age::x25519::Identity::generate();
+        let mut document: Value = serde_yaml::from_str(&sops_document(&identity)).unwrap();
+
+        assert_matches!(
+            decrypt_sops(&mut document, &[]),
+            Err(DecryptError::MissingKey(_))
+        );
+
+        let other: Vec<Box<dyn age::Identity>> = vec![Box::new(age::x25519::Identity::generate())];
+        assert_matches!(
+            decrypt_sops(&mut document, &other),
+            Err(DecryptError::MissingKey(_))
+        );
+
+        // a value moved to another key fails the authentication
+        let data_key = SecStr::new(DATA_KEY.to_vec());
+        let password = document["database"]["password"].as_str().unwrap();
+        assert_matches!(
+            decrypt_sops_value(password, "database:user:", &data_key),
+            Err(DecryptError::Integrity)
+        );
+    }
+
+    #[test]
+    fn test_read_keys() {
+        let dir = std::env::temp_dir().join(format!("pwned-check-keys-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let password_file = dir.join("vault-password");
+        std::fs::write(&password_file, "vault-secret\n").unwrap();
+        let password = Keys::read_vault_password(&password_file).unwrap();
+        assert_eq!(password.unsecure(), b"vault-secret");
+
+        let key_file = dir.join("keys.txt");
+        let identity = age::x25519::Identity::generate();
+        let content = format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        );
+        std::fs::write(&key_file, content).unwrap();
+        assert_eq!(Keys::read_age_identities(&key_file).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use csv::ByteRecord;
+use log::info;
+use secstr::SecStr;
+use serde::Deserialize;
+
+use crate::{
+    collect::{PasswordSource, SavedPassword},
+    encoding::{DecodeReader, PasswordEncoding},
+    redact,
+};
+
+/// Bytes that are read to detect the file type - enough to skip the leading whitespace of JSON
+const SNIFF_LENGTH: u64 = 512;
+
+/// Magic bytes of KeePass 2.x databases - both signatures in little endian
+const KDBX_MAGIC: [u8; 8] = [0x03, 0xD9, 0xA2, 0x9A, 0x67, 0xFB, 0x4B, 0xB5];
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Password managers of the known CSV columns for the error messages
+pub const CSV_EXPORTS: &str =
+    "Chromium, Firefox, LastPass, Dashlane, Bitwarden, KeePassXC, Safari and 1Password";
+
+/// Columns of a known CSV export
+struct CsvPreset {
+    /// Password managers writing these columns
+    name: &'static str,
+    url: &'static str,
+    username: &'static str,
+    password: &'static str,
+}
+
+/// Known CSV exports - the header names are compared exactly
+const CSV_PRESETS: [CsvPreset; 4] = [
+    CsvPreset {
+        name: "Chromium, Firefox, LastPass or Dashlane",
+        url: "url",
+        username: "username",
+        password: "password",
+    },
+    CsvPreset {
+        name: "Bitwarden",
+        url: "login_uri",
+        username: "login_username",
+        password: "login_password",
+    },
+    CsvPreset {
+        name: "KeePassXC or Safari",
+        url: "URL",
+        username: "Username",
+        password: "Password",
+    },
+    CsvPreset {
+        name: "1Password",
+        url: "Url",
+        username: "Username",
+        password: "Password",
+    },
+];
+
+/// Type of the passwords file from its first bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Csv,
+    Json,
+    Kdbx,
+    Sqlite,
+    Zip,
+}
+
+/// Type of the file starting with the given bytes - everything unknown is parsed as CSV
+pub fn sniff(prefix: &[u8]) -> FileKind {
+    if prefix.starts_with(&KDBX_MAGIC) {
+        return FileKind::Kdbx;
+    }
+
+    if prefix.starts_with(SQLITE_MAGIC) {
+        return FileKind::Sqlite;
+    }
+
+    if prefix.starts_with(ZIP_MAGIC) {
+        return FileKind::Zip;
+    }
+
+    let text = prefix.strip_prefix(UTF8_BOM).unwrap_or(prefix);
+    match text.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'{') | Some(b'[') => FileKind::Json,
+        _ => FileKind::Csv,
+    }
+}
+
+/// Name of the matching preset and the positions of the url, username and password columns.
+/// Unknown exports with these columns in another case (ex: `PASSWORD`) are read as generic CSV.
+pub fn csv_preset(headers: &ByteRecord) -> Option<(&'static str, [usize; 3])> {
+    let exact = |name: &str| headers.iter().position(|header| header == name.as_bytes());
+    let preset = CSV_PRESETS.iter().find_map(|preset| {
+        let columns = [
+            exact(preset.url)?,
+            exact(preset.username)?,
+            exact(preset.password)?,
+        ];
+        Some((preset.name, columns))
+    });
+
+    preset.or_else(|| {
+        let similar = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.eq_ignore_ascii_case(name.as_bytes()))
+        };
+        Some((
+            "generic",
+            [similar("url")?, similar("username")?, similar("password")?],
+        ))
+    })
+}
+
+#[derive(Debug)]
+pub enum DetectError {
+    Io(io::Error),
+    /// Detected a file type that cannot be read
+    Unsupported(FileKind),
+    /// Bitwarden export encrypted with the account or a password
+    Encrypted,
+    /// JSON without the items of a Bitwarden export
+    UnknownJson,
+    Json(serde_json::Error),
+}
+
+impl Display for DetectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectError::Io(err) => write!(f, "{}", err),
+            DetectError::Unsupported(FileKind::Kdbx) => write!(
+                f,
+                "Detected a KeePass database (KDBX) - export it as CSV in KeePassXC (Database > Export > CSV File)"
+            ),
+            DetectError::Unsupported(FileKind::Sqlite) => write!(
+                f,
+                "Detected an SQLite database (ex: the Login Data of Chromium) - its passwords are encrypted by the operating system, so export them as CSV in the password settings of the browser"
+            ),
+            DetectError::Unsupported(FileKind::Zip) => write!(
+                f,
+                "Detected a ZIP archive (ex: a 1Password 1PUX export) - export the passwords as CSV instead"
+            ),
+            DetectError::Unsupported(kind) => write!(f, "Detected an unsupported {:?} file", kind),
+            DetectError::Encrypted => write!(
+                f,
+                "Detected an encrypted Bitwarden JSON export - export it unencrypted as JSON or CSV"
+            ),
+            DetectError::UnknownJson => write!(
+                f,
+                "Detected a JSON file without the items of a Bitwarden export - only the JSON exports of Bitwarden are supported"
+            ),
+            DetectError::Json(err) => write!(
+                f,
+                "Invalid Bitwarden JSON export: {}",
+                redact::json_error(err)
+            ),
+        }
+    }
+}
+
+impl Error for DetectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DetectError::Io(source) => Some(source),
+            DetectError::Json(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DetectError {
+    fn from(e: io::Error) -> Self {
+        DetectError::Io(e)
+    }
+}
+
+/// Open the export with the parser of the detected file type, so the users don't have to know
+/// which columns or file type their password manager writes. The file type is sniffed from the
+/// first bytes and the CSV columns from the header line. CSV is read with the given encoding, while
+/// JSON is always UTF-8.
+pub fn open(
+    path: &Path,
+    encoding: PasswordEncoding,
+) -> Result<PasswordSource<DecodeReader<File>>, DetectError> {
+    let mut file = File::open(path)?;
+    let mut prefix = Vec::new();
+    (&mut file).take(SNIFF_LENGTH).read_to_end(&mut prefix)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    match sniff(&prefix) {
+        FileKind::Csv => Ok(PasswordSource::Csv(csv::Reader::from_reader(
+            encoding.decode(file),
+        ))),
+        FileKind::Json => {
+            let size = file.metadata()?.len();
+            // allocated once, so no copies of the passwords are left behind by growing it
+            let mut content = Vec::with_capacity(size as usize);
+            file.read_to_end(&mut content)?;
+            let content = SecStr::new(content);
+
+            let entries = read_bitwarden_json(content.unsecure())?;
+            info!(
+                "Detected a Bitwarden JSON export with {} logins",
+                entries.len()
+            );
+            Ok(PasswordSource::Parsed(entries, size))
+        }
+        kind => Err(DetectError::Unsupported(kind)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenGroup>,
+    /// Only in the exports of organizations
+    #[serde(default)]
+    collections: Vec<BitwardenGroup>,
+    items: Option<Vec<BitwardenItem>>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenGroup {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    folder_id: Option<String>,
+    #[serde(default)]
+    collection_ids: Option<Vec<String>>,
+    /// Missing for secure notes, cards and identities
+    login: Option<BitwardenLogin>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    #[serde(default)]
+    uris: Option<Vec<BitwardenUri>>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+/// Logins with a password of an unencrypted Bitwarden export - the folders and collections are the
+/// tags like in the CSV export
+fn read_bitwarden_json(content: &[u8]) -> Result<Vec<SavedPassword>, DetectError> {
+    // Bitwarden exports are objects - other managers export arrays of entries
+    let text = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+    if text.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'[') {
+        return Err(DetectError::UnknownJson);
+    }
+
+    let export: BitwardenExport = serde_json::from_slice(text).map_err(DetectError::Json)?;
+
+    if export.encrypted {
+        return Err(DetectError::Encrypted);
+    }
+
+    let names: HashMap<&str, &str> = export
+        .folders
+        .iter()
+        .chain(&export.collections)
+        .map(|group| (group.id.as_str(), group.name.as_str()))
+        .collect();
+
+    let items = export.items.ok_or(DetectError::UnknownJson)?;
+    let entries = items
+        .into_iter()
+        .filter_map(|item| {
+            let login = item.login?;
+            let password = login.password?;
+
+            let tags = item
+                .folder_id
+                .iter()
+                .chain(item.collection_ids.iter().flatten())
+                .filter_map(|id| names.get(id.as_str()))
+                .map(|name| name.to_string())
+                .collect();
+            // the first URI is the one of the CSV export
+            let url = login
+                .uris
+                .into_iter()
+                .flatten()
+                .find_map(|uri| uri.uri)
+                .unwrap_or_default();
+            Some(SavedPassword::new(
+                url,
+                login.username.unwrap_or_default(),
+                SecStr::new(password.into_bytes()),
+                tags,
+            ))
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"name,url,username,password\n"), FileKind::Csv);
+        assert_eq!(sniff(b""), FileKind::Csv);
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF\r\n  {\"encrypted\": false"),
+            FileKind::Json
+        );
+        assert_eq!(sniff(b"[{\"url\": \"\"}]"), FileKind::Json);
+
+        let mut kdbx = KDBX_MAGIC.to_vec();
+        kdbx.extend_from_slice(&[0x01, 0x00, 0x04, 0x00]);
+        assert_eq!(sniff(&kdbx), FileKind::Kdbx);
+        assert_eq!(sniff(b"SQLite format 3\0\x10\x00"), FileKind::Sqlite);
+        assert_eq!(sniff(b"PK\x03\x04\x14\x00"), FileKind::Zip);
+    }
+
+    #[test]
+    fn test_csv_presets() {
+        let columns = |header: &str| {
+            let headers = ByteRecord::from(header.split(',').collect::<Vec<_>>());
+            csv_preset(&headers)
+        };
+
+        assert_eq!(
+            columns("name,url,username,password"),
+            Some(("Chromium, Firefox, LastPass or Dashlane", [1, 2, 3]))
+        );
+        assert_eq!(
+            columns("folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp"),
+            Some(("Bitwarden", [7, 8, 9]))
+        );
+        assert_eq!(
+            columns("Group,Title,Username,Password,URL,Notes"),
+            Some(("KeePassXC or Safari", [4, 2, 3]))
+        );
+        assert_eq!(
+            columns("Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes"),
+            Some(("1Password", [1, 2, 3]))
+        );
+        assert_eq!(
+            columns("USERNAME,PASSWORD,URL"),
+            Some(("generic", [2, 0, 1]))
+        );
+
+        assert_eq!(columns("name,url,username"), None);
+        // exports without a header line start with an entry
+        assert_eq!(columns("https://example.com/,alice,hunter2"), None);
+    }
+
+    #[test]
+    fn test_bitwarden_json() {
+        let json = r#"{
+            "encrypted": false,
+            "folders": [{ "id": "f1", "name": "Work" }],
+            "items": [
+                {
+                    "type": 1,
+                    "name": "example.com",
+                    "folderId": "f1",
+                    "login": {
+                        "uris": [{ "match": null, "uri": "https://example.com/login" }],
+                        "username": "alice",
+                        "password": "fixture-Secret-4711",
+                        "totp": null
+                    }
+                },
+                { "type": 2, "name": "Note", "folderId": null, "secureNote": { "type": 0 } },
+                {
+                    "type": 1,
+                    "name": "Passkey only",
+                    "folderId": null,
+                    "login": { "uris": null, "username": "bob", "password": null }
+                },
+                {
+                    "type": 1,
+                    "name": "No URL",
+                    "folderId": "missing",
+                    "collectionIds": null,
+                    "login": { "username": null, "password": "other" }
+                }
+            ]
+        }"#;
+
+        let entries = read_bitwarden_json(json.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let debug = format!("{:?}", entries);
+        assert!(!debug.contains("fixture-Secret-4711"), "{}", debug);
+        assert!(debug.contains("https://example.com/login"), "{}", debug);
+        assert!(debug.contains("alice"), "{}", debug);
+        assert!(debug.contains("\"Work\""), "{}", debug);
+    }
+
+    #[test]
+    fn test_bitwarden_json_rejected() {
+        let encrypted = r#"{"encrypted": true, "passwordProtected": true, "data": "2.abc"}"#;
+        assert_matches!(
+            read_bitwarden_json(encrypted.as_bytes()),
+            Err(DetectError::Encrypted)
+        );
+
+        for unknown in &[r#"[{"url": "https://example.com/"}]"#, r#"{"logins": []}"#] {
+            assert_matches!(
+                read_bitwarden_json(unknown.as_bytes()),
+                Err(DetectError::UnknownJson)
+            );
+        }
+
+        // the rejected password isn't quoted by the errors
+        for invalid in &[
+            r#"{"items": [{"login": {"password": "fixture-Secret-4711""#,
+            r#"{"items": [{"login": {"password": ["fixture-Secret-4711"]}}]}"#,
+        ] {
+            let err = read_bitwarden_json(invalid.as_bytes()).unwrap_err();
+            assert_matches!(err, DetectError::Json(_));
+            assert!(!err.to_string().contains("fixture-Secret-4711"), "{}", err);
+        }
+    }
+}
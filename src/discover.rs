@@ -0,0 +1,127 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "http")]
+use std::io;
+
+use log::debug;
+
+use crate::paths::{self, Location};
+
+/// Environment variable with the configured corpus - either the file itself or a directory
+pub const CORPUS_ENV: &str = "PWNED_CHECK_CORPUS";
+
+/// File name of downloads without an explicit output
+#[cfg(feature = "http")]
+const DEFAULT_CORPUS_NAME: &str = "pwned-passwords-sha1.txt";
+
+/// Files next to the corpus that aren't hash lists themselves
+const SIDECAR_SUFFIXES: [&str; 3] = [".meta", ".prefixes", ".ranges"];
+
+/// Location in the user data directory where the corpus is found without a hash file argument
+#[cfg(feature = "http")]
+pub fn download_path() -> io::Result<PathBuf> {
+    Location::Data
+        .create()
+        .map(|dir| dir.join(DEFAULT_CORPUS_NAME))
+}
+
+/// Locations in the order they are searched - the configured path first
+pub fn search_paths() -> Vec<PathBuf> {
+    env::var_os(CORPUS_ENV)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(Location::Data.user_dir())
+        .chain(paths::system_data_dir())
+        .collect()
+}
+
+/// Previously downloaded or converted corpus from the first location containing one
+pub fn find_corpus(paths: &[PathBuf]) -> Option<PathBuf> {
+    paths.iter().find_map(|path| {
+        if path.is_file() || is_range_directory(path) {
+            return Some(path.clone());
+        }
+
+        let found = newest_corpus(path);
+        debug!("Searched for a corpus in {:?} - found {:?}", path, found);
+        found
+    })
+}
+
+/// Newest hash list of the directory. Converting runs after the download, so an index is preferred
+/// over the list it was created from.
+fn newest_corpus(dir: &Path) -> Option<PathBuf> {
+    let entries = fs::read_dir(dir).ok()?;
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| is_candidate(&entry.path()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|meta| meta.modified()).ok()?;
+            Some((modified, entry.path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Hash lists, indexes and range directories - hidden files are skipped, because they include the
+/// temporary files of unfinished downloads
+fn is_candidate(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    if name.starts_with('.') || SIDECAR_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        return false;
+    }
+
+    path.is_file() || is_range_directory(path)
+}
+
+/// Per-prefix range files of the official downloader
+fn is_range_directory(path: &Path) -> bool {
+    path.join("00000.txt").is_file() || path.join("00000").is_file()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{process, thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_find_corpus() {
+        let base = env::temp_dir().join(format!("pwned-check-discover-{}", process::id()));
+        let empty = base.join("empty");
+        let data = base.join("data");
+        fs::create_dir_all(&empty).unwrap();
+        fs::create_dir_all(data.join("ranges")).unwrap();
+
+        assert_eq!(find_corpus(&[empty.clone(), base.join("missing")]), None);
+
+        fs::write(data.join("pwned-passwords.txt"), "").unwrap();
+        fs::write(data.join(".download.tmp"), "").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(data.join("pwned.fst"), "").unwrap();
+        fs::write(data.join("pwned.fst.meta"), "").unwrap();
+
+        let paths = [empty.clone(), data.clone()];
+        assert_eq!(find_corpus(&paths), Some(data.join("pwned.fst")));
+
+        // an explicitly configured file or range directory is used as is
+        let configured = [data.join("pwned-passwords.txt"), data.join("ranges")];
+        assert_eq!(find_corpus(&configured[..1]), Some(configured[0].clone()));
+
+        // range directories without a range file are skipped
+        assert!(!is_candidate(&configured[1]));
+        fs::write(configured[1].join("00000.txt"), "").unwrap();
+        assert!(is_candidate(&configured[1]));
+        assert_eq!(find_corpus(&configured[1..]), Some(configured[1].clone()));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,719 @@
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Read, Write},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+use log::{debug, error, info};
+use ring::digest::{self, Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
+use ureq::Agent;
+
+use crate::{
+    agent,
+    atomic::{AtomicFile, WriterLock},
+    download::manifest::{RangeDigest, RangeManifest, MANIFEST_SUFFIX},
+    meta::CorpusMeta,
+    progress::{ProgressSink, ProgressUnit},
+};
+
+mod manifest;
+
+/// Bytes downloaded from each mirror to compare their speed
+const PROBE_LENGTH: u64 = 1024 * 1024;
+
+const PARTIAL_CONTENT: u16 = 206;
+
+/// Size of the byte ranges that are fetched and verified separately
+const RANGE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Attempts to fetch a single range - at least one for each mirror
+const RANGE_ATTEMPTS: usize = 3;
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Io(io::Error),
+    /// Source type that cannot be downloaded over HTTP (ex: torrents)
+    UnsupportedSource(String),
+    NoMirror(),
+    /// All mirrors failed before the file was complete
+    Incomplete {
+        downloaded: u64,
+        len: u64,
+    },
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// Range differs from the digest recorded by the previous download of the same snapshot
+    RangeMismatch {
+        offset: u64,
+    },
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Io(err) => write!(f, "{}", err),
+            DownloadError::UnsupportedSource(source) => write!(
+                f,
+                "Cannot download {} - load torrents with a torrent client and verify them with --checksum",
+                source
+            ),
+            DownloadError::NoMirror() => write!(f, "None of the mirrors is reachable"),
+            DownloadError::Incomplete { downloaded, len } => write!(
+                f,
+                "All mirrors failed after {} of {} bytes",
+                downloaded, len
+            ),
+            DownloadError::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch - expected {} got {}", expected, actual)
+            }
+            DownloadError::RangeMismatch { offset } => write!(
+                f,
+                "Range at byte {} differs from the previous download on every attempt - delete the {} file if that one was corrupt",
+                offset, MANIFEST_SUFFIX
+            ),
+        }
+    }
+}
+
+impl Error for DownloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DownloadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> Self {
+        DownloadError::Io(e)
+    }
+}
+
+/// Published digest of the complete file (ex: `sha1:ABC...` from the HIBP download page)
+#[derive(Debug)]
+pub struct Checksum {
+    algorithm: &'static digest::Algorithm,
+    expected: Vec<u8>,
+}
+
+impl Checksum {
+    fn verify(&self, context: Context) -> Result<(), DownloadError> {
+        let actual = context.finish();
+        if actual.as_ref() == self.expected.as_slice() {
+            Ok(())
+        } else {
+            Err(DownloadError::ChecksumMismatch {
+                expected: HEXLOWER_PERMISSIVE.encode(&self.expected),
+                actual: HEXLOWER_PERMISSIVE.encode(actual.as_ref()),
+            })
+        }
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, hex) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Missing algorithm in {} - use sha1:HEX or sha256:HEX", s))?;
+
+        let algorithm = match name.to_ascii_lowercase().as_str() {
+            "sha1" => &SHA1_FOR_LEGACY_USE_ONLY,
+            "sha256" => &SHA256,
+            _ => return Err(format!("Unknown checksum algorithm {}", name)),
+        };
+
+        let expected = HEXLOWER_PERMISSIVE
+            .decode(hex.as_bytes())
+            .map_err(|err| err.to_string())?;
+        if expected.len() != algorithm.output_len {
+            return Err(format!("Invalid length of the {} checksum", name));
+        }
+
+        Ok(Checksum {
+            algorithm,
+            expected,
+        })
+    }
+}
+
+/// URLs of a mirror list - one per line, empty lines and comments starting with `#` are skipped
+pub fn parse_mirror_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Only plain HTTP(S) files are downloaded by this tool
+pub fn check_source(url: &str) -> Result<(), DownloadError> {
+    let http = url.starts_with("http://") || url.starts_with("https://");
+    if http && !url.ends_with(".torrent") {
+        Ok(())
+    } else {
+        Err(DownloadError::UnsupportedSource(url.to_string()))
+    }
+}
+
+/// Reachable mirror with the measured speed
+#[derive(Debug)]
+pub struct Mirror {
+    pub url: String,
+    pub len: u64,
+    /// Bytes per second of the probe
+    throughput: f64,
+    /// Last-Modified header as publishing time of the file
+    modified: Option<SystemTime>,
+}
+
+pub struct Downloader {
+    agent: Agent,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Downloader {
+            agent: agent::agent_builder().build(),
+        }
+    }
+}
+
+impl Downloader {
+    /// Probe all mirrors and order them by their speed. Mirrors with a different file size than
+    /// the fastest one likely have another version and are skipped.
+    pub fn rank_mirrors(&self, urls: &[String]) -> Result<Vec<Mirror>, DownloadError> {
+        let mut mirrors: Vec<Mirror> = urls
+            .iter()
+            .filter_map(|url| match self.probe(url) {
+                Ok(mirror) => {
+                    debug!(
+                        "Mirror {} - {} bytes at {:.2} MB/s",
+                        url,
+                        mirror.len,
+                        mirror.throughput / 1_000_000.0
+                    );
+                    Some(mirror)
+                }
+                Err(err) => {
+                    error!("Skipping mirror {} {}", url, err);
+                    None
+                }
+            })
+            .collect();
+
+        mirrors.sort_by(|a, b| {
+            b.throughput
+                .partial_cmp(&a.throughput)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let len = mirrors.first().ok_or(DownloadError::NoMirror())?.len;
+        mirrors.retain(|mirror| {
+            let same = mirror.len == len;
+            if !same {
+                error!(
+                    "Skipping mirror {} - size {} differs from {}",
+                    mirror.url, mirror.len, len
+                );
+            }
+
+            same
+        });
+
+        Ok(mirrors)
+    }
+
+    fn probe(&self, url: &str) -> Result<Mirror, DownloadError> {
+        let response = self.agent.head(url).call().map_err(io::Error::other)?;
+        let len = response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| io::Error::other("Missing content length"))?;
+        let modified = response.header("Last-Modified").and_then(parse_http_date);
+        if len == 0 {
+            return Err(io::Error::other("Empty file").into());
+        }
+
+        let started = Instant::now();
+        let probe = self
+            .agent
+            .get(url)
+            .set("Range", &format!("bytes=0-{}", PROBE_LENGTH.min(len) - 1))
+            .call()
+            .map_err(io::Error::other)?;
+
+        let read = io::copy(&mut probe.into_reader().take(PROBE_LENGTH), &mut io::sink())?;
+        let throughput = read as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON);
+        Ok(Mirror {
+            url: url.to_string(),
+            len,
+            throughput,
+            modified,
+        })
+    }
+
+    /// Download the file from the fastest mirror and continue with the next one if it fails. The
+    /// file is only moved to the output after the checksum matched. The snapshot time is stored
+    /// in the metadata file next to it and the digest of each range in the range manifest.
+    pub fn download(
+        &self,
+        mirrors: &[Mirror],
+        output: &Path,
+        checksum: Option<&Checksum>,
+        progress: &mut dyn ProgressSink,
+    ) -> Result<(), DownloadError> {
+        let mirror = mirrors.first().ok_or(DownloadError::NoMirror())?;
+
+        // concurrent downloads of the same output would waste the bandwidth and mix the sidecars
+        let _lock = WriterLock::acquire(output)?;
+        let previous = RangeManifest::read(output).unwrap_or_else(|err| {
+            error!(
+                "Ignoring the invalid range manifest of {:?} {}",
+                output, err
+            );
+            None
+        });
+
+        let recorded = previous.as_ref().map_or(&[][..], |previous| {
+            previous.recorded(mirror.len, mirror.modified, RANGE_LENGTH)
+        });
+        if !recorded.is_empty() {
+            info!("Verifying the ranges against the previous download of the same snapshot");
+        }
+
+        // an incomplete or unverified download is deleted, so it's never scanned
+        let mut file = AtomicFile::create(output)?;
+        let digests = fetch(
+            mirror.len,
+            RANGE_LENGTH,
+            mirrors.len(),
+            |index, range| self.open(&mirrors[index], range),
+            recorded,
+            &mut file,
+            checksum,
+            progress,
+        )?;
+        file.commit()?;
+
+        let meta = CorpusMeta {
+            snapshot: mirror.modified.unwrap_or_else(SystemTime::now),
+            source: Some(mirror.url.clone()),
+        };
+        if let Err(err) = meta.write(output) {
+            error!("Failed to write metadata of the download {}", err);
+        }
+
+        let manifest = RangeManifest {
+            len: mirror.len,
+            modified: mirror.modified,
+            range_len: RANGE_LENGTH,
+            digests,
+        };
+        if let Err(err) = manifest.write(output) {
+            error!("Failed to write the range manifest of the download {}", err);
+        }
+
+        Ok(())
+    }
+
+    /// Stream the range - fails if the mirror cannot send only the range
+    fn open(&self, mirror: &Mirror, range: Range<u64>) -> Result<Box<dyn Read>, DownloadError> {
+        let response = self
+            .agent
+            .get(&mirror.url)
+            .set("Range", &format!("bytes={}-{}", range.start, range.end - 1))
+            .call()
+            .map_err(io::Error::other)?;
+
+        let complete = range.start == 0 && range.end == mirror.len;
+        if !complete && response.status() != PARTIAL_CONTENT {
+            return Err(io::Error::other("Mirror doesn't support range requests").into());
+        }
+
+        Ok(Box::new(response.into_reader()))
+    }
+}
+
+/// Parse the preferred HTTP date format (ex: `Sun, 06 Nov 1994 08:49:37 GMT`)
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = value.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|&name| name == month_name)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+
+    let mut time = parts
+        .next()?
+        .split(':')
+        .map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if parts.next() != Some("GMT") || year < 1970 {
+        return None;
+    }
+
+    // days since the epoch: http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    let secs = days * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Copy `len` bytes from the sources in ranges of `range_len`. Each range is read completely into
+/// memory and only written after it's verified: it needs the exact length and the digest recorded
+/// for it by a previous download. A failed range is fetched again from the next source, so
+/// truncated or corrupted responses never reach the output. Returns the digests of all ranges.
+#[allow(clippy::too_many_arguments)]
+fn fetch(
+    len: u64,
+    range_len: u64,
+    sources: usize,
+    mut open: impl FnMut(usize, Range<u64>) -> Result<Box<dyn Read>, DownloadError>,
+    recorded: &[RangeDigest],
+    writer: &mut impl Write,
+    checksum: Option<&Checksum>,
+    progress: &mut dyn ProgressSink,
+) -> Result<Vec<RangeDigest>, DownloadError> {
+    if sources == 0 {
+        return Err(DownloadError::NoMirror());
+    }
+
+    let mut context = checksum.map(|checksum| Context::new(checksum.algorithm));
+    let mut digests = Vec::new();
+    let mut buffer = Vec::new();
+    let mut source = 0;
+    let mut offset = 0;
+
+    progress.start(len, ProgressUnit::Bytes);
+    while offset < len {
+        let range = offset..len.min(offset + range_len);
+        let expected = recorded.get(digests.len());
+
+        let mut mismatch = false;
+        let mut attempts = 0;
+        let digest = loop {
+            if attempts == RANGE_ATTEMPTS.max(sources) {
+                progress.finish();
+                return Err(if mismatch {
+                    DownloadError::RangeMismatch { offset }
+                } else {
+                    DownloadError::Incomplete {
+                        downloaded: offset,
+                        len,
+                    }
+                });
+            }
+
+            attempts += 1;
+            match open(source, range.clone())
+                .and_then(|reader| read_range(reader, &range, &mut buffer))
+            {
+                Ok(digest) if expected.is_none_or(|expected| expected == &digest) => break digest,
+                Ok(_) => {
+                    error!(
+                        "Source {} sent a range at byte {} that differs from the previous download",
+                        source, offset
+                    );
+                    mismatch = true;
+                }
+                Err(err) => error!("Source {} failed at byte {} - {}", source, offset, err),
+            }
+
+            // a flaky connection or proxy could also succeed on the same source later
+            source = (source + 1) % sources;
+        };
+
+        writer.write_all(&buffer)?;
+        if let Some(context) = context.as_mut() {
+            context.update(&buffer);
+        }
+
+        digests.push(digest);
+        offset = range.end;
+        progress.advance(buffer.len() as u64);
+    }
+
+    progress.finish();
+    writer.flush()?;
+    match (checksum, context) {
+        (Some(checksum), Some(context)) => {
+            checksum.verify(context)?;
+            info!("Checksum verified");
+        }
+        _ => info!("Downloaded without verifying a checksum"),
+    }
+
+    Ok(digests)
+}
+
+/// Read the complete range into the buffer - fails if the response is shorter or longer
+fn read_range(
+    reader: Box<dyn Read>,
+    range: &Range<u64>,
+    buffer: &mut Vec<u8>,
+) -> Result<RangeDigest, DownloadError> {
+    let expected = range.end - range.start;
+    buffer.clear();
+
+    // one more byte to detect longer responses
+    let read = reader.take(expected + 1).read_to_end(buffer)? as u64;
+    if read != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Received {} of {} bytes", read, expected),
+        )
+        .into());
+    }
+
+    let mut digest = [0; 32];
+    digest.copy_from_slice(digest::digest(&SHA256, buffer).as_ref());
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::progress::RecordingProgress;
+
+    use super::*;
+
+    const DATA: &[u8] = b"000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n";
+
+    /// Fails after the given number of bytes like an aborted connection
+    struct FailingReader {
+        data: &'static [u8],
+        remaining: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::ErrorKind::ConnectionReset.into());
+            }
+
+            let read = buf.len().min(self.remaining).min(self.data.len());
+            buf[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            self.remaining -= read;
+            Ok(read)
+        }
+    }
+
+    fn sha1_checksum(data: &[u8]) -> Checksum {
+        let digest = digest::digest(&SHA1_FOR_LEGACY_USE_ONLY, data);
+        format!("sha1:{}", HEXLOWER_PERMISSIVE.encode(digest.as_ref()))
+            .parse()
+            .unwrap()
+    }
+
+    /// Bytes of the range that fail after the given number of bytes
+    fn failing(range: Range<u64>, remaining: usize) -> Box<dyn Read> {
+        Box::new(FailingReader {
+            data: &DATA[range.start as usize..range.end as usize],
+            remaining,
+        })
+    }
+
+    #[test]
+    fn test_resume_on_next_source() {
+        let checksum = sha1_checksum(DATA);
+        let mut requests = Vec::new();
+        let mut output = Vec::new();
+        let mut progress = RecordingProgress::default();
+
+        let len = DATA.len() as u64;
+        let result = fetch(
+            len,
+            16,
+            2,
+            |index, range| {
+                requests.push((index, range.start));
+                let remaining = if index == 0 && range.start > 0 {
+                    5
+                } else {
+                    usize::MAX
+                };
+                Ok(failing(range, remaining))
+            },
+            &[],
+            &mut output,
+            Some(&checksum),
+            &mut progress,
+        );
+
+        // the failed range is fetched completely again
+        assert_eq!(result.unwrap().len(), 3);
+        assert_eq!(requests, [(0, 0), (0, 16), (1, 16), (1, 32)]);
+        assert_eq!(output, DATA);
+        assert_eq!(progress.advanced, len);
+    }
+
+    #[test]
+    fn test_recorded_ranges() {
+        let len = DATA.len() as u64;
+        let mut progress = RecordingProgress::default();
+        let recorded = fetch(
+            len,
+            16,
+            1,
+            |_, range| Ok(failing(range, usize::MAX)),
+            &[],
+            &mut Vec::new(),
+            None,
+            &mut progress,
+        )
+        .unwrap();
+
+        // a proxy that corrupts the second range on the first attempt
+        let mut attempts = 0;
+        let mut output = Vec::new();
+        let result = fetch(
+            len,
+            16,
+            1,
+            |_, range| {
+                if range.start == 16 {
+                    attempts += 1;
+                    if attempts == 1 {
+                        return Ok(Box::new(&b"corrupted range!"[..]));
+                    }
+                }
+
+                Ok(failing(range, usize::MAX))
+            },
+            &recorded,
+            &mut output,
+            None,
+            &mut progress,
+        );
+        assert_eq!(result.unwrap(), recorded);
+        assert_eq!(attempts, 2);
+        assert_eq!(output, DATA);
+
+        let result = fetch(
+            len,
+            16,
+            2,
+            |_, range| {
+                let zeros = vec![b'0'; (range.end - range.start) as usize];
+                Ok(Box::new(io::Cursor::new(zeros)))
+            },
+            &recorded,
+            &mut Vec::new(),
+            None,
+            &mut progress,
+        );
+        // every mirror sends the same corrupted range
+        assert_matches!(result, Err(DownloadError::RangeMismatch { offset: 0 }));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let checksum = sha1_checksum(b"other");
+        let mut progress = RecordingProgress::default();
+        let result = fetch(
+            DATA.len() as u64,
+            16,
+            1,
+            |_, range| Ok(failing(range, usize::MAX)),
+            &[],
+            &mut Vec::new(),
+            Some(&checksum),
+            &mut progress,
+        );
+
+        assert_matches!(result, Err(DownloadError::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn test_all_sources_failed() {
+        let mut progress = RecordingProgress::default();
+        let result = fetch(
+            DATA.len() as u64,
+            16,
+            2,
+            |_, range| {
+                let remaining = if range.start == 0 { usize::MAX } else { 5 };
+                Ok(failing(range, remaining))
+            },
+            &[],
+            &mut Vec::new(),
+            None,
+            &mut progress,
+        );
+
+        // only completely verified ranges count
+        assert_matches!(
+            result,
+            Err(DownloadError::Incomplete {
+                downloaded: 16,
+                len: 43
+            })
+        );
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(date, Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+
+        let leap_day = parse_http_date("Tue, 29 Feb 2000 11:59:59 GMT");
+        assert_eq!(
+            leap_day,
+            Some(UNIX_EPOCH + Duration::from_secs(951_825_599))
+        );
+
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let sha256 = format!("SHA256:{}", "AB".repeat(32)).parse::<Checksum>();
+        assert!(sha256.is_ok());
+
+        assert!("sha1:abcd".parse::<Checksum>().is_err());
+        assert!("md5:d41d8cd98f00b204e9800998ecf8427e"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("d41d8cd98f00b204e9800998ecf8427e"
+            .parse::<Checksum>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_sources() {
+        let list =
+            "# official\nhttps://example.com/pwned.7z\n\n  http://mirror.example/pwned.7z  \n";
+        assert_eq!(
+            parse_mirror_list(list),
+            [
+                "https://example.com/pwned.7z",
+                "http://mirror.example/pwned.7z"
+            ]
+        );
+
+        assert!(check_source("https://example.com/pwned.7z").is_ok());
+        assert!(check_source("https://example.com/pwned.7z.torrent").is_err());
+        assert!(check_source("magnet:?xt=urn:btih:abc").is_err());
+    }
+}
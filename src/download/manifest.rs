@@ -0,0 +1,189 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+
+use crate::atomic::atomic_write;
+
+/// Suffix of the range digests next to the downloaded file
+pub const MANIFEST_SUFFIX: &str = ".ranges";
+
+/// SHA-256 digest of a downloaded range
+pub type RangeDigest = [u8; 32];
+
+/// Digests of the byte ranges of a download. They are computed locally while downloading and
+/// recorded next to the file, so updates of the same snapshot detect a corrupted response of a
+/// single range instead of silently replacing a good corpus.
+#[derive(Debug, PartialEq)]
+pub struct RangeManifest {
+    /// Size of the complete file
+    pub len: u64,
+    /// Last-Modified time of the mirror - identifies the snapshot together with the size
+    pub modified: Option<SystemTime>,
+    /// Size of each range except the last one
+    pub range_len: u64,
+    pub digests: Vec<RangeDigest>,
+}
+
+impl RangeManifest {
+    pub fn path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_os_string();
+        path.push(MANIFEST_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// Recorded digests of the download or none if there is no manifest
+    pub fn read(output: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(Self::path(output)) {
+            Ok(content) => parse(&content)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, output: &Path) -> io::Result<()> {
+        let mut content = format!(
+            "# pwned-check range digests\nlen={}\nrange={}\n",
+            self.len, self.range_len
+        );
+
+        // unwrap is safe, because writing to a string never fails
+        if let Some(secs) = self.modified.and_then(to_secs) {
+            writeln!(content, "modified={}", secs).unwrap();
+        }
+
+        for digest in &self.digests {
+            writeln!(content, "{}", HEXLOWER_PERMISSIVE.encode(digest)).unwrap();
+        }
+
+        atomic_write(&Self::path(output), |file| {
+            file.write_all(content.as_bytes())
+        })
+    }
+
+    /// Recorded digests of the ranges - empty if it's another snapshot or split differently
+    pub fn recorded(
+        &self,
+        len: u64,
+        modified: Option<SystemTime>,
+        range_len: u64,
+    ) -> &[RangeDigest] {
+        let same = self.len == len
+            && self.modified.and_then(to_secs) == modified.and_then(to_secs)
+            && self.range_len == range_len;
+        if same {
+            &self.digests
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Seconds precision like the Last-Modified header
+fn to_secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| since.as_secs())
+}
+
+fn parse(content: &str) -> Result<RangeManifest, String> {
+    let mut len = None;
+    let mut range_len = None;
+    let mut modified = None;
+    let mut digests = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parse_number = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid number in {}", line))
+        };
+
+        match line.split_once('=') {
+            Some(("len", value)) => len = Some(parse_number(value)?),
+            Some(("range", value)) => range_len = Some(parse_number(value)?),
+            Some(("modified", value)) => {
+                modified = Some(UNIX_EPOCH + Duration::from_secs(parse_number(value)?))
+            }
+            // unknown keys are skipped for newer versions
+            Some(_) => {}
+            None => {
+                let mut digest = [0; 32];
+                match HEXLOWER_PERMISSIVE.decode_len(line.len()) {
+                    Ok(32) => {}
+                    _ => return Err(format!("Invalid range digest {}", line)),
+                }
+
+                HEXLOWER_PERMISSIVE
+                    .decode_mut(line.as_bytes(), &mut digest)
+                    .map_err(|_| format!("Invalid range digest {}", line))?;
+                digests.push(digest);
+            }
+        }
+    }
+
+    let range_len = range_len.ok_or("Missing range length")?;
+    if range_len == 0 {
+        return Err("Range length must be positive".to_string());
+    }
+
+    Ok(RangeManifest {
+        len: len.ok_or("Missing file length")?,
+        modified,
+        range_len,
+        digests,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let output =
+            std::env::temp_dir().join(format!("pwned-check-ranges-{}.txt", std::process::id()));
+        assert_eq!(RangeManifest::read(&output).unwrap(), None);
+
+        let manifest = RangeManifest {
+            len: 43,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            range_len: 16,
+            digests: vec![[1; 32], [2; 32], [3; 32]],
+        };
+        manifest.write(&output).unwrap();
+        assert_eq!(RangeManifest::read(&output).unwrap(), Some(manifest));
+
+        fs::remove_file(RangeManifest::path(&output)).unwrap();
+    }
+
+    #[test]
+    fn test_recorded() {
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(86_400));
+        let manifest = parse(&format!(
+            "len=20\nrange=10\nmodified=86400\n{}",
+            "ab".repeat(32)
+        ))
+        .unwrap();
+        assert_eq!(manifest.recorded(20, modified, 10), [[0xAB; 32]]);
+
+        // another snapshot or range length
+        assert!(manifest.recorded(21, modified, 10).is_empty());
+        assert!(manifest.recorded(20, None, 10).is_empty());
+        assert!(manifest.recorded(20, modified, 5).is_empty());
+
+        assert!(parse("len=20\nrange=10\nabcd").is_err());
+        assert!(parse("len=20\nrange=0").is_err());
+        assert!(parse("range=10").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    str::FromStr,
+};
+
+/// Character encoding of the exported passwords file. HIBP hashes the UTF-8 bytes of the
+/// passwords as they were stored by the services, so legacy exports are re-encoded to UTF-8 before
+/// hashing. Otherwise a password with non-ASCII characters would never match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordEncoding {
+    Utf8,
+    /// Western single-byte Windows code page - Latin-1 with printable characters in 0x80..0xA0
+    Windows1252,
+    /// ISO-8859-1 - every byte is the Unicode code point with the same value
+    Latin1,
+}
+
+impl PasswordEncoding {
+    pub const NAMES: [&'static str; 3] = ["utf-8", "windows-1252", "latin-1"];
+
+    /// Wrap the reader of the export, so the csv parser always receives UTF-8
+    pub fn decode<R: Read>(self, inner: R) -> DecodeReader<R> {
+        DecodeReader {
+            inner,
+            encoding: self,
+            raw: vec![0; DECODE_CHUNK_SIZE],
+            decoded: Vec::with_capacity(DECODE_CHUNK_SIZE * MAX_UTF8_PER_BYTE),
+            position: 0,
+        }
+    }
+
+    fn decode_byte(self, byte: u8) -> char {
+        match self {
+            PasswordEncoding::Windows1252 if (0x80..0xA0).contains(&byte) => {
+                WINDOWS_1252_HIGH[usize::from(byte - 0x80)]
+            }
+            _ => char::from(byte),
+        }
+    }
+}
+
+impl FromStr for PasswordEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf-8" => Ok(PasswordEncoding::Utf8),
+            "windows-1252" => Ok(PasswordEncoding::Windows1252),
+            "latin-1" => Ok(PasswordEncoding::Latin1),
+            _ => Err(format!("Unknown encoding {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PasswordEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+const DECODE_CHUNK_SIZE: usize = 4 * 1024;
+
+/// Single-byte code points need at most 3 bytes in UTF-8 (ex: € is U+20AC)
+const MAX_UTF8_PER_BYTE: usize = 3;
+
+/// Code points of 0x80..0xA0 - unassigned bytes are mapped to the control character like Latin-1
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// Reader transcoding a single-byte encoding to UTF-8 chunk by chunk
+pub struct DecodeReader<R> {
+    inner: R,
+    encoding: PasswordEncoding,
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.encoding == PasswordEncoding::Utf8 {
+            return self.inner.read(buf);
+        }
+
+        if self.position == self.decoded.len() {
+            let read = self.inner.read(&mut self.raw)?;
+            if read == 0 {
+                return Ok(0);
+            }
+
+            clear(&mut self.decoded);
+            self.decoded.clear();
+            self.position = 0;
+
+            let mut utf8 = [0; 4];
+            for &byte in &self.raw[..read] {
+                let encoded = self.encoding.decode_byte(byte).encode_utf8(&mut utf8);
+                self.decoded.extend_from_slice(encoded.as_bytes());
+            }
+
+            clear(&mut self.raw[..read]);
+        }
+
+        let remaining = &self.decoded[self.position..];
+        let length = remaining.len().min(buf.len());
+        buf[..length].copy_from_slice(&remaining[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+impl<R> Drop for DecodeReader<R> {
+    fn drop(&mut self) {
+        // the buffers contain clear text passwords
+        clear(&mut self.raw);
+        clear(&mut self.decoded);
+    }
+}
+
+fn clear(buffer: &mut [u8]) {
+    for byte in buffer {
+        // Safety: pointer comes from a valid reference - volatile prevents removing the writes
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(encoding: PasswordEncoding, data: &[u8]) -> String {
+        let mut decoded = String::new();
+        encoding.decode(data).read_to_string(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_utf8_unchanged() {
+        assert_eq!(
+            decode(PasswordEncoding::Utf8, "pässwörd€".as_bytes()),
+            "pässwörd€"
+        );
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        // euro sign at 0x80 and œ at 0x9C are not part of Latin-1
+        let data = b"p\xE4\x80ssw\x9Crd";
+        assert_eq!(decode(PasswordEncoding::Windows1252, data), "pä€sswœrd");
+    }
+
+    #[test]
+    fn test_latin1() {
+        let data = b"p\xE4\x80";
+        assert_eq!(decode(PasswordEncoding::Latin1, data), "pä\u{80}");
+    }
+
+    #[test]
+    fn test_larger_than_chunk() {
+        let data = vec![0xE4; DECODE_CHUNK_SIZE * 2 + 1];
+        let decoded = decode(PasswordEncoding::Windows1252, &data);
+        assert_eq!(decoded.chars().count(), data.len());
+        assert!(decoded.chars().all(|c| c == 'ä'));
+    }
+
+    #[test]
+    fn test_parse_names() {
+        for name in &PasswordEncoding::NAMES {
+            assert!(name.parse::<PasswordEncoding>().is_ok(), "{}", name);
+        }
+
+        assert!("ascii".parse::<PasswordEncoding>().is_err());
+    }
+}
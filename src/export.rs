@@ -0,0 +1,71 @@
+use std::io::Write;
+
+use data_encoding::HEXUPPER;
+
+use crate::{collect::SavedHash, pseudonym::Pseudonymizer};
+
+/// Number of exported hex characters in prefix mode - the same as the HIBP range API
+const PREFIX_LENGTH: usize = 5;
+
+/// Write the label and hash of every saved password without any matching. The output could be
+/// taken to another machine holding the hash database. With `prefix_only` only the first
+/// characters are exported, which are shared with many other hashes.
+pub fn write_hashes(
+    writer: impl Write,
+    hashes: &[SavedHash],
+    prefix_only: bool,
+    pseudonyms: &mut Pseudonymizer,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let hash_header = if prefix_only { "prefix" } else { "sha1" };
+    writer.write_record(&["label", hash_header])?;
+
+    for saved in hashes {
+        let hex = HEXUPPER.encode(&saved.password_hash);
+        let hash = if prefix_only {
+            &hex[..PREFIX_LENGTH]
+        } else {
+            &hex
+        };
+
+        writer.write_record(&[pseudonyms.label(saved).as_str(), hash])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn export(prefix_only: bool) -> String {
+        let hashes = [SavedHash::new(
+            "https://example.com/",
+            "user,name",
+            [0xAB; 20],
+        )];
+
+        let mut output = Vec::new();
+        let mut pseudonyms = Pseudonymizer::disabled();
+        write_hashes(&mut output, &hashes, prefix_only, &mut pseudonyms).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_full_hash() {
+        let expected = format!(
+            "label,sha1\n\"user,name@https://example.com/\",{}\n",
+            "AB".repeat(20)
+        );
+        assert_eq!(export(false), expected);
+    }
+
+    #[test]
+    fn test_prefix_only() {
+        assert_eq!(
+            export(true),
+            "label,prefix\n\"user,name@https://example.com/\",ABABA\n"
+        );
+    }
+}
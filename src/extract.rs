@@ -0,0 +1,534 @@
+use bstr::ByteSlice;
+use csv::ReaderBuilder;
+use data_encoding::BASE64;
+use log::{debug, warn};
+use secstr::SecStr;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+#[cfg(feature = "vault")]
+use crate::decrypt::{self, Keys};
+use crate::{hasher, redact, Sha1Hash};
+
+/// Parts of a key that mark its value as password (ex: `DB_PASSWORD`, `smtp_passwd`)
+const PASSWORD_KEYS: [&str; 3] = ["password", "passwd", "pwd"];
+
+/// Header of files and values encrypted by `ansible-vault`
+const VAULT_HEADER: &[u8] = b"$ANSIBLE_VAULT;";
+
+/// Candidate password found in a file that isn't a password manager export
+pub struct Candidate {
+    /// Where it was found - the key (ex: `.env:DB_PASSWORD`) or the line of CSV files
+    /// (ex: `accounts.csv:12`)
+    pub label: String,
+    pub password: SecStr,
+}
+
+impl Candidate {
+    pub fn sha1(&self) -> Sha1Hash {
+        hasher::sha1(&[self.password.unsecure()])
+    }
+}
+
+/// Extracts the candidate passwords of files. Encrypted files are decrypted in memory if the keys
+/// are given and skipped otherwise.
+#[derive(Default)]
+pub struct Extractor {
+    #[cfg(feature = "vault")]
+    pub keys: Keys,
+}
+
+impl Extractor {
+    /// Extract the candidate passwords of the file by its extension:
+    ///
+    /// * CSV files - the column with a password like header
+    /// * YAML and JSON files - all values of Kubernetes Secrets and otherwise the values of
+    ///   password like keys (ex: the `environment` of docker-compose services). Values encrypted
+    ///   by SOPS or inline by `ansible-vault` are decrypted.
+    /// * Files encrypted by `ansible-vault` - the decrypted variables like YAML files
+    /// * Other files like `.env` - the values of password like keys (ex: `PASSWORD=secret`)
+    pub fn extract(&self, name: &str, content: &[u8]) -> Vec<Candidate> {
+        if content.starts_with(VAULT_HEADER) {
+            return match self.decrypt_vault(name, content) {
+                Some(variables) => self.extract_yaml(name, variables.unsecure()),
+                None => Vec::new(),
+            };
+        }
+
+        let lowercase = name.to_ascii_lowercase();
+        if lowercase.ends_with(".csv") {
+            extract_csv(name, content)
+        } else if [".yaml", ".yml", ".json"]
+            .iter()
+            .any(|extension| lowercase.ends_with(extension))
+        {
+            self.extract_yaml(name, content)
+        } else {
+            extract_dotenv(name, content)
+        }
+    }
+
+    /// All documents of the YAML file - a file could contain multiple resources
+    fn extract_yaml(&self, name: &str, content: &[u8]) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for document in serde_yaml::Deserializer::from_slice(content) {
+            let mut document = match Value::deserialize(document) {
+                Ok(document) => document,
+                Err(err) => {
+                    // the following documents cannot be found without the end of this one
+                    debug!(
+                        "Skipping the rest of the invalid YAML file {} {}",
+                        name,
+                        redact::yaml_error(&err)
+                    );
+                    break;
+                }
+            };
+
+            if is_sops(&document) && !self.decrypt_sops(name, &mut document) {
+                continue;
+            }
+
+            if document.get("kind").and_then(Value::as_str) == Some("Secret") {
+                extract_secret(name, &document, &mut candidates);
+            } else {
+                self.extract_keys(name, "", &document, &mut candidates);
+            }
+        }
+
+        candidates
+    }
+
+    /// Values of password like keys anywhere in the document. The lists of docker-compose
+    /// `environment` sections contain `KEY=value` strings instead.
+    fn extract_keys(&self, name: &str, path: &str, value: &Value, candidates: &mut Vec<Candidate>) {
+        match value {
+            Value::Mapping(entries) => {
+                for (key, value) in entries {
+                    let key = match key.as_str() {
+                        Some(key) => key,
+                        None => continue,
+                    };
+
+                    let path = if path.is_empty() {
+                        key.to_string()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+
+                    // every vault encrypted value is a secret regardless of its key
+                    if let Some(encrypted) = vault_value(value) {
+                        let label = format!("{}:{}", name, path);
+                        if let Some(password) = self.decrypt_vault(&label, encrypted.as_bytes()) {
+                            candidates.extend(candidate(name, path.as_bytes(), password));
+                        }
+
+                        continue;
+                    }
+
+                    // unquoted numbers like 123456 are parsed as numbers
+                    let password = match value {
+                        Value::String(password) => Some(SecStr::from(password.as_str())),
+                        Value::Number(password) => Some(SecStr::from(password.to_string())),
+                        _ => None,
+                    };
+
+                    match password {
+                        Some(password) if is_password_key(key.as_bytes()) => {
+                            candidates.extend(candidate(name, path.as_bytes(), password));
+                        }
+                        Some(_) => {}
+                        None => self.extract_keys(name, &path, value, candidates),
+                    }
+                }
+            }
+            Value::Sequence(items) => {
+                for item in items {
+                    match item
+                        .as_str()
+                        .and_then(|item| split_assignment(item.as_bytes()))
+                    {
+                        Some((key, password)) => {
+                            let path = format!("{}.{}", path, String::from_utf8_lossy(key));
+                            let password = SecStr::new(password.to_vec());
+                            candidates.extend(candidate(name, path.as_bytes(), password));
+                        }
+                        None => self.extract_keys(name, path, item, candidates),
+                    }
+                }
+            }
+            Value::Tagged(tagged) => self.extract_keys(name, path, &tagged.value, candidates),
+            _ => {}
+        }
+    }
+
+    /// Plaintext of the file or value encrypted by `ansible-vault` - none if it cannot be
+    /// decrypted
+    #[cfg(feature = "vault")]
+    fn decrypt_vault(&self, label: &str, encrypted: &[u8]) -> Option<SecStr> {
+        let password = match &self.keys.vault_password {
+            Some(password) => password,
+            None => {
+                warn!(
+                    "Skipping the Ansible Vault {} without a vault password",
+                    label
+                );
+                return None;
+            }
+        };
+
+        match decrypt::decrypt_vault(encrypted, password) {
+            Ok(plaintext) => Some(plaintext),
+            Err(err) => {
+                warn!("Cannot decrypt the Ansible Vault {} {}", label, err);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "vault"))]
+    fn decrypt_vault(&self, label: &str, _encrypted: &[u8]) -> Option<SecStr> {
+        warn!(
+            "Skipping the Ansible Vault {} - decryption requires the vault feature",
+            label
+        );
+        None
+    }
+
+    /// Decrypt the values of the SOPS document in place - false if it cannot be decrypted
+    #[cfg(feature = "vault")]
+    fn decrypt_sops(&self, name: &str, document: &mut Value) -> bool {
+        match decrypt::decrypt_sops(document, &self.keys.age_identities) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Cannot decrypt the SOPS file {} {}", name, err);
+                false
+            }
+        }
+    }
+
+    #[cfg(not(feature = "vault"))]
+    fn decrypt_sops(&self, name: &str, _document: &mut Value) -> bool {
+        warn!(
+            "Skipping the SOPS file {} - decryption requires the vault feature",
+            name
+        );
+        false
+    }
+}
+
+/// Whether the key contains one of the password like parts ignoring the case
+pub fn is_password_key(key: &[u8]) -> bool {
+    let key = key.to_ascii_lowercase();
+    PASSWORD_KEYS.iter().any(|part| key.find(part).is_some())
+}
+
+/// Password column of a CSV file with headers - the line numbers start at 1 with the header
+fn extract_csv(name: &str, content: &[u8]) -> Vec<Candidate> {
+    let mut reader = ReaderBuilder::new().flexible(true).from_reader(content);
+    let column = match reader.byte_headers() {
+        Ok(headers) => headers.iter().position(is_password_key),
+        Err(_) => None,
+    };
+
+    let column = match column {
+        Some(column) => column,
+        None => return Vec::new(),
+    };
+
+    reader
+        .byte_records()
+        // invalid records of a committed file shouldn't stop checking the others
+        .filter_map(Result::ok)
+        .filter_map(|record| {
+            let line = record.position().map_or(0, |position| position.line());
+            let password = record.get(column).filter(|password| !password.is_empty())?;
+            Some(Candidate {
+                label: format!("{}:{}", name, line),
+                password: SecStr::new(password.to_vec()),
+            })
+        })
+        .collect()
+}
+
+/// `KEY=value` lines of dotenv files with password like keys. Quotes and an `export` prefix are
+/// removed like the shell would and unquoted values end at a comment.
+fn extract_dotenv(name: &str, content: &[u8]) -> Vec<Candidate> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_ascii();
+            let line = line.strip_prefix(b"export ").unwrap_or(line);
+            let (key, value) = split_assignment(line)?;
+            let value = match unquote(value) {
+                Some(value) => value,
+                // a comment has to be separated by whitespace, because # is a valid character
+                None => value
+                    .find(" #")
+                    .map_or(value, |comment| value[..comment].trim_ascii()),
+            };
+
+            candidate(name, key, SecStr::new(value.to_vec()))
+        })
+        .collect()
+}
+
+/// Key and value of a `KEY=value` assignment with a password like key
+fn split_assignment(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let separator = line.find_byte(b'=')?;
+    let key = line[..separator].trim_ascii();
+    if !is_password_key(key) {
+        return None;
+    }
+
+    Some((key, line[separator + 1..].trim_ascii()))
+}
+
+fn candidate(name: &str, key: &[u8], password: SecStr) -> Option<Candidate> {
+    if password.unsecure().is_empty() {
+        return None;
+    }
+
+    Some(Candidate {
+        label: format!("{}:{}", name, String::from_utf8_lossy(key)),
+        password,
+    })
+}
+
+/// Every value of a Kubernetes Secret - `data` is base64 encoded, while `stringData` isn't
+fn extract_secret(name: &str, secret: &Value, candidates: &mut Vec<Candidate>) {
+    let secret_name = secret
+        .get("metadata")
+        .and_then(|metadata| metadata.get("name"))
+        .and_then(Value::as_str)
+        .unwrap_or("secret");
+
+    for (field, encoded) in [("data", true), ("stringData", false)] {
+        let entries = match secret.get(field).and_then(Value::as_mapping) {
+            Some(entries) => entries,
+            None => continue,
+        };
+
+        for (key, value) in entries {
+            let (key, value) = match (key.as_str(), value.as_str()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+
+            let key = format!("{}/{}", secret_name, key);
+            if !encoded {
+                candidates.extend(candidate(name, key.as_bytes(), SecStr::from(value)));
+                continue;
+            }
+
+            // line breaks are allowed in the encoded value
+            let value: String = value.split_whitespace().collect();
+            match BASE64.decode(value.as_bytes()) {
+                Ok(decoded) => {
+                    candidates.extend(candidate(name, key.as_bytes(), SecStr::new(decoded)))
+                }
+                Err(err) => debug!("Invalid base64 value of {}:{} {}", name, key, err),
+            }
+        }
+    }
+}
+
+/// Whether the document was encrypted by SOPS - its metadata is stored next to the data
+fn is_sops(document: &Value) -> bool {
+    document.get("sops").is_some_and(Value::is_mapping)
+}
+
+/// Encrypted text of an inline vault value (`!vault |`)
+fn vault_value(value: &Value) -> Option<&str> {
+    match value {
+        Value::Tagged(tagged) if tagged.tag == "vault" => tagged.value.as_str(),
+        _ => None,
+    }
+}
+
+/// Value between the quotes - none if it isn't quoted. Anything after the closing quote is a
+/// comment.
+fn unquote(value: &[u8]) -> Option<&[u8]> {
+    let quote = *value
+        .first()
+        .filter(|first| **first == b'"' || **first == b'\'')?;
+    let end = value[1..].find_byte(quote)?;
+    Some(&value[1..=end])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn extracted(name: &str, content: &str) -> Vec<(String, String)> {
+        Extractor::default()
+            .extract(name, content.as_bytes())
+            .into_iter()
+            .map(|candidate| {
+                let password = String::from_utf8(candidate.password.unsecure().to_vec()).unwrap();
+                (candidate.label, password)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dotenv() {
+        let content = "# database\nDB_HOST=localhost\nDB_PASSWORD=\"hunter2 # not a comment\"\n\nexport SMTP_PASSWD = 'secret' # comment\nAPI_PWD=\nUSER=password\nREDIS_PASSWORD=pass#word # comment\n";
+        assert_eq!(
+            extracted(".env", content),
+            vec![
+                (
+                    ".env:DB_PASSWORD".to_string(),
+                    "hunter2 # not a comment".to_string()
+                ),
+                (".env:SMTP_PASSWD".to_string(), "secret".to_string()),
+                (".env:REDIS_PASSWORD".to_string(), "pass#word".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kubernetes_secret() {
+        let content = "apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+data:
+  mode: production
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: database
+type: Opaque
+data:
+  username: YWRtaW4=
+  token: aHVudGVy
+    Mg==
+  broken: '%%%'
+stringData:
+  password: secret
+";
+        assert_eq!(
+            extracted("k8s/secrets.yaml", content),
+            vec![
+                (
+                    "k8s/secrets.yaml:database/username".to_string(),
+                    "admin".to_string()
+                ),
+                (
+                    "k8s/secrets.yaml:database/token".to_string(),
+                    "hunter2".to_string()
+                ),
+                (
+                    "k8s/secrets.yaml:database/password".to_string(),
+                    "secret".to_string()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let content = "services:
+  db:
+    image: postgres
+    environment:
+      POSTGRES_USER: admin
+      POSTGRES_PASSWORD: 123456
+  cache:
+    environment:
+      - REDIS_HOST=cache
+      - REDIS_PASSWORD=hunter2
+";
+        assert_eq!(
+            extracted("docker-compose.yml", content),
+            vec![
+                (
+                    "docker-compose.yml:services.db.environment.POSTGRES_PASSWORD".to_string(),
+                    "123456".to_string()
+                ),
+                (
+                    "docker-compose.yml:services.cache.environment.REDIS_PASSWORD".to_string(),
+                    "hunter2".to_string()
+                )
+            ]
+        );
+
+        assert!(extracted("invalid.yml", "password: [unclosed").is_empty());
+    }
+
+    /// Variables `db_user: admin` and `db_password: hunter2` encrypted with the password
+    /// `vault-secret`
+    const VAULT: &str = "$ANSIBLE_VAULT;1.1;AES256
+30303031303230333034303530363037303830393061306230633064306530663130313131323133
+3134313531363137313831393161316231633164316531660a393636336339636136353833386265
+39323966633662633363326537363864363464616664643139623865666231323039366232653038
+3037613137363131350a383836396262613964306364636666666364643565306431343864316266
+37623031666637623763396264656238313434336537343233646339633033356634363336386261
+3339356461653433313534646430313233303137306663653230
+";
+
+    /// `hunter2` encrypted inline with the same password
+    const INLINE_VAULT: &str = "db_host: localhost
+api_token: !vault |
+  $ANSIBLE_VAULT;1.2;AES256;prod
+  30313032303330343035303630373038303930613062306330643065306631303131313231333134
+  3135313631373138313931613162316331643165316632300a386362343931653064653133383461
+  39393366646164303463333238613962303438323730393232383136363936663532336463343162
+  6563386634653063630a643532393964353531396562616664333664383130373137306364373830
+  6430
+";
+
+    #[cfg(feature = "vault")]
+    #[test]
+    fn test_vault() {
+        let mut extractor = Extractor::default();
+        extractor.keys.vault_password = Some(SecStr::from("vault-secret"));
+
+        let candidates = extractor.extract("group_vars/all/vault", VAULT.as_bytes());
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].label, "group_vars/all/vault:db_password");
+        assert_eq!(candidates[0].password.unsecure(), b"hunter2");
+
+        let candidates = extractor.extract("vars.yml", INLINE_VAULT.as_bytes());
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].label, "vars.yml:api_token");
+        assert_eq!(candidates[0].password.unsecure(), b"hunter2");
+
+        extractor.keys.vault_password = Some(SecStr::from("wrong"));
+        assert!(extractor.extract("vault.yml", VAULT.as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_without_keys() {
+        assert!(extracted("group_vars/all/vault", VAULT).is_empty());
+        assert!(extracted("vars.yml", INLINE_VAULT).is_empty());
+
+        let sops = "password: ENC[AES256_GCM,data:ojS9sy1swQ==,iv:ZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7fH1+f4CBgoM=,tag:YNeDDoxCnqXdVpTDsCPQ2A==,type:str]
+sops:
+  age: []
+";
+        assert!(extracted("secrets.enc.yaml", sops).is_empty());
+    }
+
+    #[test]
+    fn test_csv() {
+        let content = "name,url,username,Password\nExample,https://example.com,user,hunter2\nEmpty,https://example.com,user,\n";
+        assert_eq!(
+            extracted("export.CSV", content),
+            vec![("export.CSV:2".to_string(), "hunter2".to_string())]
+        );
+
+        assert!(extracted("notes.csv", "name,url\nExample,https://example.com\n").is_empty());
+    }
+
+    #[test]
+    fn test_sha1() {
+        let candidate = &Extractor::default().extract(".env", b"PASSWORD=password")[0];
+        assert_eq!(
+            data_encoding::HEXUPPER.encode(&candidate.sha1()),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"
+        );
+    }
+}
@@ -1,66 +1,314 @@
-use std::{cmp::Ordering, fs::File, io, io::BufReader, time::Duration};
+use std::{fmt, io, io::BufReader, str::FromStr};
 
 use bstr::{io::BufReadExt, ByteSlice};
-use log::{debug, error, info};
-use memmap::{Mmap, MmapOptions};
-use packed_simd_2::u8x32;
-use pbr::{ProgressBar, Units};
+use log::{debug, error};
+use memchr::memchr;
 
-use crate::{collect::SavedHash, find::parse::PwnedHash, SHA1_BYTE_LENGTH};
+use crate::{
+    cancel::{CancellationToken, Status},
+    collect::SavedHash,
+    find::{
+        interpolation::find_hash_interpolated,
+        jump::{PrefixTable, MIN_JUMP_DISTANCE},
+        lock::FileState,
+        matcher::{CompareSpace, MatchEvent, Matcher},
+        parse::ParseHashError,
+        range::find_hash_ranged,
+        range_dir::find_hash_in_ranges,
+        simd::LaneWidth,
+        source::Mapped,
+        watch::watch_changes,
+    },
+    progress::{ProgressSink, ProgressUnit},
+    Sha1Hash, SHA1_BYTE_LENGTH,
+};
 
+mod hex;
+#[cfg(feature = "index")]
+pub mod index;
+mod interpolation;
+mod jump;
+mod layout;
+mod lock;
+mod matcher;
 mod parse;
+mod point;
+#[cfg(feature = "index")]
+pub mod prefix;
+mod range;
+mod range_dir;
+mod simd;
+mod source;
+mod watch;
 
 #[cfg(unix)]
 mod advise;
 
+#[cfg(feature = "http")]
+mod api;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "http")]
+mod s3;
+
+#[cfg(feature = "crypto")]
+pub use point::{find_range, RangeEntry, RangePrefix};
+pub use point::{lookup_hash, lookup_hashes, parse_sha1, warm_up};
+pub use source::{open_source, remote_url, Footprint, HashSource, MapOptions};
+#[cfg(unix)]
+pub use watch::install_bus_error_handler;
+
+#[cfg(feature = "http")]
+pub use api::{RangeApi, HIBP_RANGE_API};
+
 const SIMD_WIDTH: usize = 32;
 
 /// Pad the sha-1 hash to the full width of used SIMD instruction
-type HashPadded = [u8; SIMD_WIDTH];
+pub type HashPadded = [u8; SIMD_WIDTH];
+
+/// SIMD-ready representation of the saved hash - the remaining bytes are zero
+pub fn pad_hash(hash: &Sha1Hash) -> HashPadded {
+    let mut padded = [0; SIMD_WIDTH];
+    padded[..SHA1_BYTE_LENGTH].copy_from_slice(hash);
+    padded
+}
+
+/// Saved password that was found in the hash database
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    /// Position of the matched entry in the searched hashes
+    pub index: usize,
+    /// Number of occurrences or none if the count column couldn't be parsed
+    pub count: Option<u64>,
+}
+
+/// Search algorithm over the hash database
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Select the strategy based on the number of saved hashes and the database size
+    Auto,
+    /// Scan the complete database sequentially
+    Linear,
+    /// Scan sequentially, but compare the lines in hex without decoding them
+    Hex,
+    /// Estimate the position of each saved hash - requires memory maps
+    Interpolation,
+}
+
+impl Strategy {
+    pub const NAMES: [&'static str; 4] = ["auto", "linear", "hex", "interpolation"];
+
+    /// Resolve the automatic selection. Each interpolated lookup touches only a few random pages,
+    /// while the linear scan reads everything sequentially. Random access is much slower per byte
+    /// so interpolation only wins if there are a lot more bytes than saved hashes. The sequential
+    /// scan compares in hex space, because skipping the decoding was faster (`Compare` group of
+    /// `cargo bench --bench parse`).
+    fn select(self, hashes: usize, database_size: usize) -> Self {
+        match self {
+            Strategy::Auto if hashes.saturating_mul(INTERPOLATION_BREAK_EVEN) < database_size => {
+                Strategy::Interpolation
+            }
+            Strategy::Auto => Strategy::Hex,
+            selected => selected,
+        }
+    }
+
+    /// Only read the database sequentially - so no range requests
+    fn is_sequential(self) -> bool {
+        matches!(self, Strategy::Linear | Strategy::Hex)
+    }
+
+    /// Representation of the lines for a sequential scan
+    fn compare_space(self) -> CompareSpace {
+        match self {
+            Strategy::Linear | Strategy::Interpolation => CompareSpace::Decoded,
+            Strategy::Auto | Strategy::Hex => CompareSpace::Hex,
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Strategy::Auto),
+            "linear" => Ok(Strategy::Linear),
+            "hex" => Ok(Strategy::Hex),
+            "interpolation" => Ok(Strategy::Interpolation),
+            _ => Err(format!("Unknown strategy {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
-pub fn find_hash(hash_file: &File, hashes: &[SavedHash]) -> Result<(), io::Error> {
+/// Number of sequentially read bytes that take about as long as a single interpolated lookup
+const INTERPOLATION_BREAK_EVEN: usize = 1024 * 1024;
+
+/// Settings for scanning a hash database
+#[derive(Clone, Copy, Debug)]
+pub struct ScanOptions {
+    pub strategy: Strategy,
+    /// Require `HASH:count` lines - otherwise a missing count and additional fields are accepted
+    pub strict: bool,
+    /// Flags for mapping a local database
+    pub mapping: MapOptions,
+    /// Compare the file state in another thread during the scan. Point lookups of the long running
+    /// modes are too short for it - the state is still verified afterwards.
+    pub watch: bool,
+}
+
+pub fn find_hash(
+    source: &(dyn HashSource + Sync),
+    hashes: &[SavedHash],
+    options: ScanOptions,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<Status<Vec<Finding>>, io::Error> {
     if hashes.is_empty() {
-        error!("No stored passwords found");
-        return Ok(());
+        return Ok(Status::Completed(Vec::new()));
+    }
+
+    #[cfg(feature = "index")]
+    if let Some(prefixes) = source.prefixes() {
+        return prefix::find_hash_filtered(prefixes, hashes, |candidates| {
+            scan_source(source, candidates, options, progress, cancel)
+        });
+    }
+
+    scan_source(source, hashes, options, progress, cancel)
+}
+
+fn scan_source(
+    source: &(dyn HashSource + Sync),
+    hashes: &[SavedHash],
+    options: ScanOptions,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<Status<Vec<Finding>>, io::Error> {
+    if let Some(ranges) = source.ranges() {
+        debug!(
+            "Using the ranges - ignoring the {} strategy",
+            options.strategy
+        );
+        let state = source.state()?;
+        let findings = find_hash_in_ranges(ranges, hashes, options.strict, progress, cancel)?;
+        state.verify_unchanged(source)?;
+        return Ok(findings);
     }
 
-    match unsafe { MmapOptions::new().map(&hash_file) } {
+    // # Safety
+    // It's unspecified if another process can modify the file or map and we see the changes.
+    // This could cause unexpected changes for us and end up in a segmentation fault. Furthermore
+    // mapping it to memory hides I/O errors from us. Mandatory locking seems to be not possible on
+    // all platforms: https://users.rust-lang.org/t/how-unsafe-is-mmap/19635/
+    // So we take an advisory lock that cooperating writers respect and detect other writes by
+    // comparing the file state during and after the scan.
+    let _lock = source.lock_shared()?;
+    let state = source.state()?;
+
+    let findings = match source.map_with(options.mapping) {
         Ok(map) => {
-            debug!("Using memory maps - writes to the file or map could cause program crashes");
-            find_hash_mapped(&map, hash_file, hashes)
+            #[cfg(unix)]
+            if let Mapped::File(_) = map {
+                debug!("Using memory maps - truncating the file stops the program");
+            }
+
+            #[cfg(feature = "index")]
+            if let Some(index) = index::open(&map)? {
+                debug!(
+                    "Using the index of {} hashes - ignoring the {} strategy",
+                    index.hashes(),
+                    options.strategy
+                );
+                let findings = watched(source, &state, cancel, options.watch, || {
+                    index::find_hash_indexed(&index, hashes, progress, cancel)
+                });
+
+                state.verify_unchanged(source)?;
+                return Ok(findings);
+            }
+
+            watched(source, &state, cancel, options.watch, || {
+                find_hash_mapped(&map, hashes, options, progress, cancel)
+            })?
+        }
+        Err(err) if source.supports_ranges() && !options.strategy.is_sequential() => {
+            debug!("Using range search - {}", err);
+            find_hash_ranged(
+                source,
+                state.len(),
+                hashes,
+                options.strict,
+                progress,
+                cancel,
+            )?
         }
         Err(err) => {
             error!("Failed to use memory maps using incremental search {}", err);
-            find_hash_file_read(hash_file, hashes)
+            if options.strategy == Strategy::Interpolation {
+                error!("Interpolation search requires memory maps - falling back to linear");
+            }
+
+            let reader = BufReader::new(source.reader()?);
+            find_hash_incrementally(
+                reader,
+                state.len(),
+                hashes,
+                options.strict,
+                options.strategy.compare_space(),
+                progress,
+                cancel,
+            )?
         }
+    };
+
+    state.verify_unchanged(source)?;
+    Ok(findings)
+}
+
+/// Run the scan while watching the source if enabled
+fn watched<T>(
+    source: &(dyn HashSource + Sync),
+    state: &FileState,
+    cancel: &CancellationToken,
+    watch: bool,
+    scan: impl FnOnce() -> T,
+) -> T {
+    if watch {
+        watch_changes(source, state, cancel, scan)
+    } else {
+        scan()
     }
 }
 
-fn find_hash_mapped(map: &Mmap, hash_file: &File, hashes: &[SavedHash]) -> Result<(), io::Error> {
-    // # Safety
-    // It's unspecified if another process can modify the file or map and we see the changes.
-    // This could cause unexpected changes for us and end up in a segmentation fault. Furthermore
-    // mapping it to memory hides I/O errors from us. So we try at least to make the file
-    // read only. Although other processes could overwrite it. Mandatory locking seems to be not
-    // possible on all platforms: https://users.rust-lang.org/t/how-unsafe-is-mmap/19635/
-
-    let did_change = set_readonly(hash_file, true).unwrap_or_else(|err| {
-        error!(
-            "Failed to request read only for the hash database - \
-            program could crash if there are concurrent modifications {}",
-            err
-        );
-        // fallback that we didn't change anything
-        false
-    });
+fn find_hash_mapped(
+    map: &Mapped<'_>,
+    hashes: &[SavedHash],
+    options: ScanOptions,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> io::Result<Status<Vec<Finding>>> {
+    let strategy = options.strategy.select(hashes.len(), map.len());
+    debug!("Using {} search strategy", strategy);
 
     #[cfg(unix)]
-    {
+    if let Mapped::File(map) = map {
         use crate::find::advise::MemoryAdvice;
 
+        let advice = match strategy {
+            Strategy::Interpolation => MemoryAdvice::Random,
+            _ => MemoryAdvice::Sequential,
+        };
+
         // Safety: unsafe cast to mutable - however madvise seems to not change any data
         let ptr = map.as_ptr() as *mut u8;
-        if let Err(err) = advise::madvise(ptr, map.len(), MemoryAdvice::Sequential) {
+        if let Err(err) = advise::madvise(ptr, map.len(), advice) {
             error!(
                 "Failed to advise OS about memory usage - continuing without it {}",
                 err
@@ -68,149 +316,778 @@ fn find_hash_mapped(map: &Mmap, hash_file: &File, hashes: &[SavedHash]) -> Resul
         }
     }
 
-    // blocking - help the compiler with the type
-    let data: &[u8] = &map;
-    find_hash_incrementally(data, map.len() as u64, hashes)?;
-
-    if did_change {
-        if let Err(err) = set_readonly(hash_file, false) {
-            error!(
-                "Failed to restore old readable state - please check the file yourself {}",
-                err
-            )
+    // blocking
+    match strategy {
+        Strategy::Interpolation => {
+            find_hash_interpolated(map, hashes, options.strict, progress, cancel)
         }
+        _ => find_hash_in_memory(
+            map,
+            hashes,
+            options.strict,
+            strategy.compare_space(),
+            progress,
+            cancel,
+        ),
     }
-
-    Ok(())
 }
 
-fn set_readonly(file: &File, read_only: bool) -> Result<bool, io::Error> {
-    file.metadata().and_then(|metadata| {
-        let mut permissions = metadata.permissions();
-        if metadata.permissions().readonly() == read_only {
-            Ok(false)
-        } else {
-            permissions.set_readonly(read_only);
-            file.set_permissions(permissions)?;
-            Ok(true)
-        }
-    })
-}
+/// Linux specific advices for the mapped database
+#[cfg(target_os = "linux")]
+pub(crate) fn advise_linux(map: &[u8], huge_pages: bool) {
+    use crate::find::advise::MemoryAdvice;
 
-fn find_hash_file_read(hash_file: &File, hashes: &[SavedHash]) -> Result<(), io::Error> {
-    #[cfg(unix)]
-    advise::fadvise(hash_file, 0, None, advise::FileAdvice::Sequential);
+    // Safety: unsafe cast to mutable - however madvise seems to not change any data
+    let ptr = map.as_ptr() as *mut u8;
 
-    let reader = BufReader::new(hash_file);
-    let max_length = hash_file.metadata().map_or_else(
-        |err| {
-            error!(
-                "Failed to fetch metadata {:?} - Using unlimited progress bar",
+    // the database is already on disk and could be larger than the memory (ex: 35 GB), so a core
+    // dump of a crash shouldn't include it
+    if let Err(err) = advise::madvise(ptr, map.len(), MemoryAdvice::DontDump) {
+        debug!("Failed to exclude the mapping from core dumps {}", err);
+    }
+
+    if huge_pages {
+        if let Err(err) = advise::madvise(ptr, map.len(), MemoryAdvice::HugePage) {
+            log::warn!(
+                "Transparent huge pages are not supported - continuing without them {}",
                 err
             );
-            0
-        },
-        |metadata| metadata.len(),
-    );
-
-    find_hash_incrementally(reader, max_length, hashes)
+        }
+    }
 }
 
 fn find_hash_incrementally(
     hash_reader: impl BufReadExt,
     max_length: u64,
     hashes: &[SavedHash],
-) -> Result<(), io::Error> {
-    // This effectively makes a copy - However we can expect that there are not many
-    // saved passwords. The memory consumption from multiple copies would then be negligible
-    let mut hashes = hashes.iter().map(|x| {
-        let mut hash_padded: HashPadded = [0; 32];
-        hash_padded[..SHA1_BYTE_LENGTH].copy_from_slice(&x.password_hash);
-        (u8x32::from_slice_unaligned(&hash_padded), x)
-    });
-
-    let mut bar = ProgressBar::new(max_length);
-    bar.set_units(Units::Bytes);
-
-    // limit refresh, because we call add very frequently
-    bar.set_max_refresh_rate(Some(Duration::from_secs(1)));
-
-    // Safety we validated that it's not empty in the first find hash method
-    let mut current_saved = hashes.next().unwrap();
-
-    // re-use hash buffer to reduce the number of allocations
-    let mut record: PwnedHash = PwnedHash::default();
+    strict: bool,
+    space: CompareSpace,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<Status<Vec<Finding>>, io::Error> {
+    progress.start(max_length, ProgressUnit::Bytes);
+    let mut scanner = Scanner::new(hashes, strict, space);
+    let mut cancelled = false;
+    let mut scanned = 0;
     hash_reader
         // reads line-by-line including re-use the allocation
         // so we don't need to convert it to UTF-8 or make an extra allocation
-        .for_byte_line(|line| {
-            bar.add(line.len() as u64);
-
-            if let Err(err) = record.parse_new_hash(line) {
-                // abort because then there are probably more errors
-                error!("Failed to parse hash {:?}", err);
+        .for_byte_line_with_terminator(|line| {
+            // the atomic load is negligible compared to parsing the line
+            if cancel.is_cancelled() {
+                cancelled = true;
                 return Ok(false);
             }
 
-            let pwned = u8x32::from_slice_unaligned(&record.hash_padded);
-
-            // This could also be improved further by re-using the internal eq/lt operations from
-            // simd, but it's good enough
-            loop {
-                // loop through the list of hashes (stored passwords) until you find one that
-                // one that is larger (pwned < current)
-                match pwned.lex_ord().cmp(&current_saved.0.lex_ord()) {
-                    Ordering::Less => {
-                        // pwned < current
-                        // This means we need advance further in the hash database - reading the
-                        // next line
-                        break;
-                    }
-                    Ordering::Equal => {
-                        // found an exact match
-                        match record.parse_count(line).as_ref() {
-                            Ok(count) => {
-                                info!(
-                                    "Your password for the following account {} \
-                                    has been pwned {}x times",
-                                    current_saved.1, count
-                                );
-                            }
-                            Err(err) => {
-                                error!(
-                                    "Failed to parse count number in: {} - {:?}",
-                                    line.to_str().unwrap_or(""),
-                                    err
-                                );
-                                info!("Your password has been pwned {}", current_saved.1);
-                            }
-                        }
-
-                        // Fetch the next stored password, in case the user has duplicate passwords
-                        // that could also match on the current line
-                        match hashes.next() {
-                            Some(next) => {
-                                current_saved = next;
-                            }
-                            None => return Ok(false),
-                        };
-                    }
-                    Ordering::Greater => {
-                        // pwned > current - This means current is not in the hash database
-                        // However the next saved password could - therefore advance further
-                        match hashes.next() {
-                            Some(next) => {
-                                current_saved = next;
-                            }
-                            None => return Ok(false),
-                        };
-                    }
+            // the line break is included, so the scanned bytes are exact
+            progress.advance(line.len() as u64);
+            scanned += line.len() as u64;
+
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            Ok(scanner.process_line(strip_carriage_return(line)))
+        })?;
+
+    if !cancelled {
+        skip_remaining(progress, scanned, max_length);
+    }
+
+    progress.finish();
+    scanner.into_status(cancelled)
+}
+
+/// Number of bytes after which the progress is reported for in-memory data
+const PROGRESS_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Scan in-memory data like a memory map. Compared to reading it incrementally, this splits the
+/// lines in a tight loop using `memchr` without calling a closure for each line. The data can be
+/// accessed at random, so the lines before the prefix of the next saved hash are skipped.
+fn find_hash_in_memory(
+    data: &[u8],
+    hashes: &[SavedHash],
+    strict: bool,
+    space: CompareSpace,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> io::Result<Status<Vec<Finding>>> {
+    find_hash_jumping(
+        data,
+        hashes,
+        strict,
+        space,
+        PrefixTable::new(MIN_JUMP_DISTANCE),
+        progress,
+        cancel,
+    )
+}
+
+fn find_hash_jumping(
+    data: &[u8],
+    hashes: &[SavedHash],
+    strict: bool,
+    space: CompareSpace,
+    mut jumps: PrefixTable,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> io::Result<Status<Vec<Finding>>> {
+    progress.start(data.len() as u64, ProgressUnit::Bytes);
+    let mut scanner = Scanner::new(hashes, strict, space);
+
+    let scan_lines = scan_lines_for(LaneWidth::detect());
+    // Safety: the function only uses the instructions of the detected lane width
+    let cancelled = unsafe { scan_lines(&mut scanner, data, &mut jumps, progress, cancel) };
+
+    progress.finish();
+    scanner.into_status(cancelled)
+}
+
+type ScanLinesFn = unsafe fn(
+    &mut Scanner,
+    &[u8],
+    &mut PrefixTable,
+    &mut dyn ProgressSink,
+    &CancellationToken,
+) -> bool;
+
+/// Line loop compiled for the lane width. The loop is the hot path of the scan and the compares are
+/// inlined into it, so the wide instructions are enabled for the whole loop instead of calling a
+/// compare function for each line (see `Dispatch` in `cargo bench --bench simd`). The rest of the
+/// binary keeps the baseline instructions of the build.
+fn scan_lines_for(width: LaneWidth) -> ScanLinesFn {
+    match width {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        LaneWidth::Bits256 => scan_lines_avx2,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        LaneWidth::Bits512 => scan_lines_avx512,
+        _ => scan_lines_baseline,
+    }
+}
+
+unsafe fn scan_lines_baseline(
+    scanner: &mut Scanner,
+    data: &[u8],
+    jumps: &mut PrefixTable,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> bool {
+    scan_lines(scanner, data, jumps, progress, cancel)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn scan_lines_avx2(
+    scanner: &mut Scanner,
+    data: &[u8],
+    jumps: &mut PrefixTable,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> bool {
+    scan_lines(scanner, data, jumps, progress, cancel)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512bw")]
+unsafe fn scan_lines_avx512(
+    scanner: &mut Scanner,
+    data: &[u8],
+    jumps: &mut PrefixTable,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> bool {
+    scan_lines(scanner, data, jumps, progress, cancel)
+}
+
+/// Feed the lines to the scanner and jump over the lines before the next saved hash. Returns if the
+/// scan was cancelled.
+#[inline(always)]
+fn scan_lines(
+    scanner: &mut Scanner,
+    data: &[u8],
+    jumps: &mut PrefixTable,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> bool {
+    let mut line_start = 0;
+    let mut reported = 0;
+    let mut decided = None;
+    while line_start < data.len() {
+        // only a new saved hash could be further away than the next line
+        if decided != Some(scanner.decided()) {
+            decided = Some(scanner.decided());
+
+            let target = scanner
+                .next_prefix()
+                .and_then(|prefix| jumps.jump(data, line_start, prefix));
+            if let Some(target) = target {
+                progress.advance((line_start - reported) as u64);
+                progress.skip((target - line_start) as u64);
+                line_start = target;
+                reported = target;
+                continue;
+            }
+        }
+
+        // the last line doesn't need a line break
+        let line_end =
+            memchr(b'\n', &data[line_start..]).map_or(data.len(), |pos| line_start + pos);
+        let line = strip_carriage_return(&data[line_start..line_end]);
+        line_start = (line_end + 1).min(data.len());
+
+        if !scanner.process_line(line) {
+            break;
+        }
+
+        if line_start - reported >= PROGRESS_BLOCK_SIZE {
+            progress.advance((line_start - reported) as u64);
+            reported = line_start;
+
+            // check only for each block to keep the loop tight
+            if cancel.is_cancelled() {
+                return true;
+            }
+        }
+    }
+
+    progress.advance((line_start - reported) as u64);
+    skip_remaining(progress, line_start as u64, data.len() as u64);
+    false
+}
+
+/// Report the bytes after an early exit as skipped. The scan stops after the line that decided the
+/// last saved hash or an invalid line, because the remaining lines can't change the results.
+fn skip_remaining(progress: &mut dyn ProgressSink, scanned: u64, total: u64) {
+    if scanned < total {
+        debug!(
+            "Stopped the scan at byte {} of {} - the remaining lines can't change the results",
+            scanned, total
+        );
+        progress.skip(total - scanned);
+    }
+}
+
+/// Remove the windows line ending similar to `for_byte_line`
+fn strip_carriage_return(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Sorted linear search over the hash database that collects the findings of the matcher and logs
+/// its errors
+struct Scanner {
+    matcher: Matcher,
+    findings: Vec<Finding>,
+    /// Line that aborted the scan - the remaining saved hashes are undecided
+    invalid: Option<ParseHashError>,
+}
+
+impl Scanner {
+    fn new(hashes: &[SavedHash], strict: bool, space: CompareSpace) -> Self {
+        let lanes = LaneWidth::detect();
+        debug!("Using SIMD lane width {:?}", lanes);
+
+        Scanner {
+            matcher: Matcher::new(hashes, strict, lanes, space),
+            findings: Vec::new(),
+            invalid: None,
+        }
+    }
+
+    /// All saved hashes are processed or the scan was aborted
+    fn is_finished(&self) -> bool {
+        self.matcher.is_finished()
+    }
+
+    /// Number of saved hashes that are decided by the previous lines
+    fn decided(&self) -> usize {
+        self.matcher.decided()
+    }
+
+    fn next_prefix(&self) -> Option<u8> {
+        self.matcher.next_prefix()
+    }
+
+    /// Compare the next line of the hash database. Returns false if the scan could be stopped,
+    /// because all saved hashes are processed or the line is invalid.
+    #[inline(always)]
+    fn process_line(&mut self, line: &[u8]) -> bool {
+        let findings = &mut self.findings;
+        let invalid = &mut self.invalid;
+        self.matcher.feed(line, |event| match event {
+            MatchEvent::Found(finding) => findings.push(finding),
+            MatchEvent::NotFound(_) => {}
+            MatchEvent::InvalidCount(_, err) => error!(
+                "Failed to parse count number in: {} - {:?}",
+                line.to_str().unwrap_or(""),
+                err
+            ),
+            MatchEvent::InvalidLine(err) => *invalid = Some(err),
+        })
+    }
+
+    /// Findings of the scan - an invalid line fails it even if it was cancelled afterwards
+    fn into_status(mut self, cancelled: bool) -> io::Result<Status<Vec<Finding>>> {
+        if let Some(err) = self.invalid {
+            return Err(parse::invalid_line(err));
+        }
+
+        if cancelled {
+            Ok(Status::Cancelled(self.findings))
+        } else {
+            let mut not_found = 0;
+            self.matcher.finish(|_| not_found += 1);
+            debug!("{} saved hashes are not in the hash database", not_found);
+            Ok(Status::Completed(self.findings))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::TryInto, path::Path};
+
+    use ring::digest;
+
+    use crate::{
+        find::source::{FileSystem, MemoryFileSystem},
+        progress::RecordingProgress,
+        SHA1_BYTE_LENGTH,
+    };
+
+    use super::*;
+
+    const HASH_A: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4";
+    const HASH_B: &str = "00000000A8DAE4228F821FB418F59826079BF368";
+    const HASH_C: &str = "00000000DD7F2A1C68A35673713783CA390C9E93";
+
+    fn saved(hex: &str) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        SavedHash::new("https://example.com/", "user", hash)
+    }
+
+    fn corpus(line_ending: &str, trailing: bool) -> String {
+        let mut data = format!(
+            "{}:4{le}{}:7{le}{}:1",
+            HASH_A,
+            HASH_B,
+            HASH_C,
+            le = line_ending
+        );
+        if trailing {
+            data.push_str(line_ending);
+        }
+
+        data
+    }
+
+    /// Scan in both compare spaces, which have to agree
+    fn scan(data: &str, hashes: &[SavedHash]) -> Vec<Finding> {
+        let scan_in = |space| {
+            let mut progress = RecordingProgress::default();
+            let cancel = CancellationToken::default();
+            find_hash_in_memory(
+                data.as_bytes(),
+                hashes,
+                false,
+                space,
+                &mut progress,
+                &cancel,
+            )
+            .unwrap()
+            .into_inner()
+        };
+
+        let decoded = scan_in(CompareSpace::Decoded);
+        assert_eq!(scan_in(CompareSpace::Hex), decoded);
+        decoded
+    }
+
+    #[test]
+    fn test_in_memory_matches_reader() {
+        let hashes = [saved(HASH_A), saved(HASH_C)];
+        for &line_ending in &["\n", "\r\n"] {
+            for &trailing in &[true, false] {
+                let data = corpus(line_ending, trailing);
+                let expected = vec![
+                    Finding {
+                        index: 0,
+                        count: Some(4),
+                    },
+                    Finding {
+                        index: 1,
+                        count: Some(1),
+                    },
+                ];
+
+                let in_memory = scan(&data, &hashes);
+                assert_eq!(in_memory, expected, "{:?} {}", line_ending, trailing);
+
+                let mut progress = RecordingProgress::default();
+                let cancel = CancellationToken::default();
+                let read = find_hash_incrementally(
+                    data.as_bytes(),
+                    0,
+                    &hashes,
+                    false,
+                    CompareSpace::Hex,
+                    &mut progress,
+                    &cancel,
+                );
+                assert_matches!(read, Ok(Status::Completed(ref findings)) if findings == &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_hash_sources() {
+        let mut file_system = MemoryFileSystem::default();
+        file_system
+            .files
+            .insert("corpus.txt".into(), corpus("\n", true).into_bytes());
+
+        let hashes = [saved(HASH_A), saved(HASH_C)];
+        let mut source = file_system.open(Path::new("corpus.txt")).unwrap();
+        for &mappable in &[true, false] {
+            source.mappable = mappable;
+            for &strategy in &[Strategy::Linear, Strategy::Interpolation] {
+                let options = ScanOptions {
+                    strategy,
+                    strict: false,
+                    mapping: MapOptions::default(),
+                    watch: true,
+                };
+
+                let mut progress = RecordingProgress::default();
+                let cancel = CancellationToken::default();
+                let findings = find_hash(&source, &hashes, options, &mut progress, &cancel)
+                    .unwrap()
+                    .into_inner();
+                let indices: Vec<_> = findings.iter().map(|finding| finding.index).collect();
+                assert_eq!(indices, [0, 1], "{} {:?}", mappable, strategy);
+            }
+        }
+
+        assert!(file_system.open(Path::new("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn test_mapping_flags() {
+        let path = std::env::temp_dir().join(format!("pwned-check-mapping-{}", std::process::id()));
+        std::fs::write(&path, corpus("\n", true)).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let hashes = [saved(HASH_A), saved(HASH_C)];
+        let options = ScanOptions {
+            strategy: Strategy::Interpolation,
+            strict: false,
+            mapping: MapOptions {
+                populate: true,
+                huge_pages: true,
+            },
+            watch: true,
+        };
+
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let findings = find_hash(&file, &hashes, options, &mut progress, &cancel)
+            .unwrap()
+            .into_inner();
+        let indices: Vec<_> = findings.iter().map(|finding| finding.index).collect();
+        assert_eq!(indices, [0, 1]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_saved_hashes() {
+        let hashes = [saved(HASH_B), saved(HASH_B)];
+        let findings = scan(&corpus("\n", true), &hashes);
+        let indices: Vec<_> = findings.iter().map(|finding| finding.index).collect();
+        assert_eq!(indices, [0, 1]);
+    }
+
+    #[test]
+    fn test_missing_counts() {
+        let data = format!("{}\n{}:7:extra\n{}\n", HASH_A, HASH_B, HASH_C);
+        let hashes = [saved(HASH_A), saved(HASH_B)];
+        assert_eq!(
+            scan(&data, &hashes),
+            [
+                Finding {
+                    index: 0,
+                    count: None
+                },
+                Finding {
+                    index: 1,
+                    count: Some(7)
+                }
+            ]
+        );
+
+        // strict mode fails on the first line instead of reporting the hashes as clean
+        for &space in &[CompareSpace::Decoded, CompareSpace::Hex] {
+            let mut progress = RecordingProgress::default();
+            let cancel = CancellationToken::default();
+            let strict = find_hash_in_memory(
+                data.as_bytes(),
+                &hashes,
+                true,
+                space,
+                &mut progress,
+                &cancel,
+            )
+            .unwrap_err();
+            assert_eq!(strict.kind(), io::ErrorKind::InvalidData, "{:?}", space);
+        }
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let mut file_system = MemoryFileSystem::default();
+        let data = format!("{}:4\nnot a hash line\n{}:1\n", HASH_A, HASH_C);
+        file_system
+            .files
+            .insert("corpus.txt".into(), data.into_bytes());
+
+        // the saved hash after the invalid line is undecided, so it's not reported as clean
+        let hashes = [saved(HASH_A), saved(HASH_C)];
+        let mut source = file_system.open(Path::new("corpus.txt")).unwrap();
+        for &mappable in &[true, false] {
+            source.mappable = mappable;
+            for &strategy in &[Strategy::Linear, Strategy::Interpolation] {
+                for &strict in &[true, false] {
+                    let options = ScanOptions {
+                        strategy,
+                        strict,
+                        mapping: MapOptions::default(),
+                        watch: true,
+                    };
+
+                    let mut progress = RecordingProgress::default();
+                    let cancel = CancellationToken::default();
+                    let err =
+                        find_hash(&source, &hashes, options, &mut progress, &cancel).unwrap_err();
+                    assert_eq!(
+                        err.kind(),
+                        io::ErrorKind::InvalidData,
+                        "{} {:?} {}",
+                        mappable,
+                        strategy,
+                        strict
+                    );
                 }
             }
+        }
+    }
 
-            Ok(true)
-        })?;
+    #[test]
+    fn test_strategy_select() {
+        let size = 10 * INTERPOLATION_BREAK_EVEN;
+        assert_eq!(Strategy::Auto.select(1, size), Strategy::Interpolation);
+        assert_eq!(Strategy::Auto.select(100, size), Strategy::Hex);
+        assert_eq!(Strategy::Linear.select(1, size), Strategy::Linear);
+        assert_eq!(
+            Strategy::Interpolation.select(100, size),
+            Strategy::Interpolation
+        );
+    }
+
+    #[test]
+    fn test_strategy_parse() {
+        for name in &Strategy::NAMES {
+            assert!(name.parse::<Strategy>().is_ok());
+        }
+
+        assert!("binary".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_not_found() {
+        let hashes = [saved("FFFFFFFFDD7F2A1C68A35673713783CA390C9E93")];
+        assert!(scan(&corpus("\n", false), &hashes).is_empty());
+    }
+
+    #[test]
+    fn test_jump_same_as_reader() {
+        let mut corpus_hashes: Vec<Sha1Hash> = (0..4_000u32)
+            .map(|x| {
+                let digest = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &x.to_be_bytes());
+                // unwrap is safe, because SHA-1 always has the same length
+                digest.as_ref().try_into().unwrap()
+            })
+            .collect();
+        corpus_hashes.sort_unstable();
+
+        let data: String = corpus_hashes
+            .iter()
+            .enumerate()
+            .map(|(count, hash)| format!("{}:{}\n", data_encoding::HEXUPPER.encode(hash), count))
+            .collect();
+
+        // widely spaced saved hashes and a missing one between them
+        let mut hashes: Vec<SavedHash> = corpus_hashes
+            .iter()
+            .step_by(700)
+            .map(|&hash| SavedHash::from_hash(hash))
+            .collect();
+        hashes.push(SavedHash::from_hash([0x80; SHA1_BYTE_LENGTH]));
+        hashes.sort_unstable();
+
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let jumped = find_hash_jumping(
+            data.as_bytes(),
+            &hashes,
+            false,
+            CompareSpace::Hex,
+            PrefixTable::new(0),
+            &mut progress,
+            &cancel,
+        )
+        .unwrap();
+
+        let read = find_hash_incrementally(
+            data.as_bytes(),
+            0,
+            &hashes,
+            false,
+            CompareSpace::Hex,
+            &mut RecordingProgress::default(),
+            &cancel,
+        )
+        .unwrap();
+        let findings = jumped.into_inner();
+        assert_eq!(findings.len(), hashes.len() - 1);
+        assert_eq!(findings, read.into_inner());
+
+        // the skipped lines still complete the progress
+        assert!(progress.skipped > data.len() as u64 / 2);
+        assert_eq!(progress.advanced, data.len() as u64);
+    }
+
+    #[test]
+    fn test_scan_lines_all_widths() {
+        let mut corpus_hashes: Vec<Sha1Hash> = (0..2_000u32)
+            .map(|x| {
+                let digest = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &x.to_be_bytes());
+                // unwrap is safe, because SHA-1 always has the same length
+                digest.as_ref().try_into().unwrap()
+            })
+            .collect();
+        corpus_hashes.sort_unstable();
+
+        let data: String = corpus_hashes
+            .iter()
+            .enumerate()
+            .map(|(count, hash)| format!("{}:{}\n", data_encoding::HEXUPPER.encode(hash), count))
+            .collect();
+
+        // dense saved hashes, so the pair compares and skipped batches are used
+        let mut hashes: Vec<SavedHash> = corpus_hashes
+            .iter()
+            .step_by(3)
+            .map(|&hash| SavedHash::from_hash(hash))
+            .collect();
+        hashes.push(SavedHash::from_hash([0x80; SHA1_BYTE_LENGTH]));
+        hashes.sort_unstable();
+
+        let cancel = CancellationToken::default();
+        let read = find_hash_incrementally(
+            data.as_bytes(),
+            0,
+            &hashes,
+            false,
+            CompareSpace::Decoded,
+            &mut RecordingProgress::default(),
+            &cancel,
+        )
+        .unwrap()
+        .into_inner();
+        assert_eq!(read.len(), hashes.len() - 1);
 
-    bar.finish();
-    Ok(())
+        for &width in LaneWidth::ALL.iter().filter(|width| width.is_supported()) {
+            let mut scanner = Scanner::new(&hashes, false, CompareSpace::Decoded);
+            let scan_lines = scan_lines_for(width);
+            // Safety: only widths of the running CPU
+            let cancelled = unsafe {
+                scan_lines(
+                    &mut scanner,
+                    data.as_bytes(),
+                    &mut PrefixTable::new(MIN_JUMP_DISTANCE),
+                    &mut RecordingProgress::default(),
+                    &cancel,
+                )
+            };
+
+            assert!(!cancelled);
+            assert_eq!(
+                scanner.into_status(false).unwrap().into_inner(),
+                read,
+                "{:?}",
+                width
+            );
+        }
+    }
+
+    #[test]
+    fn test_progress_complete() {
+        let data = corpus("\n", true);
+        let hashes = [saved(HASH_A)];
+
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        find_hash_in_memory(
+            data.as_bytes(),
+            &hashes,
+            false,
+            CompareSpace::Hex,
+            &mut progress,
+            &cancel,
+        )
+        .unwrap();
+
+        // even if the scan stops early, the complete data should be reported as processed
+        assert_eq!(progress.started, [(data.len() as u64, ProgressUnit::Bytes)]);
+        assert_eq!(progress.advanced, data.len() as u64);
+        assert_eq!(progress.finished, 1);
+
+        // only the first line was scanned
+        let first_line = HASH_A.len() as u64 + 3;
+        assert_eq!(progress.skipped, data.len() as u64 - first_line);
+
+        let mut progress = RecordingProgress::default();
+        find_hash_incrementally(
+            data.as_bytes(),
+            data.len() as u64,
+            &hashes,
+            false,
+            CompareSpace::Decoded,
+            &mut progress,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(progress.advanced, data.len() as u64);
+        assert_eq!(progress.skipped, data.len() as u64 - first_line);
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let hashes = [saved(HASH_C)];
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+
+        let mut progress = RecordingProgress::default();
+        let data = corpus("\n", true);
+        let status = find_hash_incrementally(
+            data.as_bytes(),
+            0,
+            &hashes,
+            false,
+            CompareSpace::Decoded,
+            &mut progress,
+            &cancel,
+        );
+        assert_matches!(status, Ok(Status::Cancelled(ref findings)) if findings.is_empty());
+        assert_eq!(progress.finished, 1);
+    }
 }
@@ -1,15 +1,25 @@
 use std::{error::Error, fmt, fs::File, io, num::NonZeroI64, os::unix::io::AsRawFd};
 
 /// Memory mapped advise type
+///
+/// These are the `madvise` values and not the `POSIX_MADV` ones of `posix_madvise`. Both are equal
+/// on glibc, but `posix_madvise` of musl ignores `DONTNEED` so calling `madvise` directly behaves
+/// the same for static musl builds.
 #[repr(i32)]
 #[allow(dead_code)]
 pub enum MemoryAdvice {
-    Normal = libc::POSIX_MADV_NORMAL,
-    Sequential = libc::POSIX_MADV_SEQUENTIAL,
-    Random = libc::POSIX_MADV_RANDOM,
-    WillNeed = libc::POSIX_MADV_WILLNEED,
-    DontNeed = libc::POSIX_MADV_DONTNEED,
-    // Linux specific entries are missing
+    Normal = libc::MADV_NORMAL,
+    Sequential = libc::MADV_SEQUENTIAL,
+    Random = libc::MADV_RANDOM,
+    WillNeed = libc::MADV_WILLNEED,
+    DontNeed = libc::MADV_DONTNEED,
+    /// Back the mapping with transparent huge pages - fewer TLB misses for large mappings. Only
+    /// effective for file mappings if the filesystem supports large folios.
+    #[cfg(target_os = "linux")]
+    HugePage = libc::MADV_HUGEPAGE,
+    /// Exclude the mapping from core dumps
+    #[cfg(target_os = "linux")]
+    DontDump = libc::MADV_DONTDUMP,
 }
 
 // Windows:
@@ -43,16 +53,53 @@ pub fn madvise<T>(ptr: *mut T, len: usize, advice: MemoryAdvice) -> Result<(), i
     }
 }
 
+/// Fault in each page of the mapped data, so the first lookups don't wait for the disk. Returns the
+/// number of touched pages.
+pub fn touch_pages(data: &[u8]) -> usize {
+    // unsafe is safe, because sysconf only reads a constant of the system
+    let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    };
+
+    let mut pages = 0;
+    for offset in (0..data.len()).step_by(page_size) {
+        // volatile, so the read isn't optimized away although the value is unused
+        // unsafe is safe, because the offset is within the slice
+        unsafe { std::ptr::read_volatile(data.as_ptr().add(offset)) };
+        pages += 1;
+    }
+
+    pages
+}
+
 /// File advise type
-#[repr(i32)]
+///
+/// These are the `posix_fadvise` values. Apple platforms don't have `posix_fadvise`, so they are
+/// translated to the `fcntl` equivalents there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum FileAdvice {
-    Normal = libc::POSIX_FADV_NORMAL,
-    Sequential = libc::POSIX_FADV_SEQUENTIAL,
-    Random = libc::POSIX_FADV_RANDOM,
-    NoReuse = libc::POSIX_FADV_NOREUSE,
-    WillNeed = libc::POSIX_FADV_WILLNEED,
-    DontNeed = libc::POSIX_FADV_DONTNEED,
+    Normal,
+    Sequential,
+    Random,
+    NoReuse,
+    WillNeed,
+    DontNeed,
+}
+
+#[cfg(not(target_vendor = "apple"))]
+impl FileAdvice {
+    fn posix_value(self) -> i32 {
+        match self {
+            FileAdvice::Normal => libc::POSIX_FADV_NORMAL,
+            FileAdvice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            FileAdvice::Random => libc::POSIX_FADV_RANDOM,
+            FileAdvice::NoReuse => libc::POSIX_FADV_NOREUSE,
+            FileAdvice::WillNeed => libc::POSIX_FADV_WILLNEED,
+            FileAdvice::DontNeed => libc::POSIX_FADV_DONTNEED,
+        }
+    }
 }
 
 // Windows has something similar with:
@@ -76,9 +123,7 @@ pub enum FileAdvice {
 pub fn fadvise(file: &File, offset: i64, length: Option<NonZeroI64>, advice: FileAdvice) {
     // Option<NonZero> is memory optimized
 
-    let fd = file.as_raw_fd();
-    let length = length.map_or(0, NonZeroI64::get);
-    let res = unsafe { libc::posix_fadvise(fd, offset, length, advice as i32) };
+    let res = advise_file(file, offset, length.map_or(0, NonZeroI64::get), advice);
 
     // Safety: programming mistakes should panic instead of return an error
     match res {
@@ -86,11 +131,71 @@ pub fn fadvise(file: &File, offset: i64, length: Option<NonZeroI64>, advice: Fil
         libc::EBADF => Err(FAdviseError::EBADF),
         libc::EINVAL => Err(FAdviseError::EINVAL),
         libc::ESPIPE => Err(FAdviseError::ESPIPE),
+        // advise is only a hint - static binaries could run on kernels or emulation layers
+        // (ex: qemu-user, gVisor) that don't implement it
+        libc::ENOSYS | libc::ENOTSUP => Ok(()),
         err => Err(FAdviseError::Unknown(err)),
     }
     .unwrap()
 }
 
+/// Error number of the call or 0
+#[cfg(not(target_vendor = "apple"))]
+fn advise_file(file: &File, offset: i64, length: i64, advice: FileAdvice) -> i32 {
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), offset, length, advice.posix_value()) }
+}
+
+/// Error number of the call or 0. The read-ahead of macOS is only switched per file descriptor
+/// (`F_RDAHEAD`), so the range only applies to `WillNeed`, which reads it ahead (`F_RDADVISE`).
+/// Data that isn't reused bypasses the cache (`F_NOCACHE`). There is no equivalent to drop cached
+/// pages, so `DontNeed` is skipped.
+#[cfg(target_vendor = "apple")]
+fn advise_file(file: &File, offset: i64, length: i64, advice: FileAdvice) -> i32 {
+    let fd = file.as_raw_fd();
+    let ret = match advice {
+        FileAdvice::Normal | FileAdvice::Sequential => {
+            let read_ahead = unsafe { libc::fcntl(fd, libc::F_RDAHEAD, 1) };
+            if read_ahead == -1 {
+                read_ahead
+            } else {
+                unsafe { libc::fcntl(fd, libc::F_NOCACHE, 0) }
+            }
+        }
+        FileAdvice::Random => unsafe { libc::fcntl(fd, libc::F_RDAHEAD, 0) },
+        FileAdvice::NoReuse => unsafe { libc::fcntl(fd, libc::F_NOCACHE, 1) },
+        FileAdvice::WillNeed => {
+            if offset < 0 || length < 0 {
+                return libc::EINVAL;
+            }
+
+            // like posix_fadvise a length of 0 means until the end of the file
+            let length = match length {
+                0 => match file.metadata() {
+                    Ok(metadata) => (metadata.len() as i64).saturating_sub(offset).max(0),
+                    Err(err) => return err.raw_os_error().unwrap_or(libc::EBADF),
+                },
+                length => length,
+            };
+
+            let advisory = libc::radvisory {
+                ra_offset: offset,
+                // larger ranges are only read ahead partially, which is fine for a hint
+                ra_count: length.min(i64::from(libc::c_int::MAX)) as libc::c_int,
+            };
+            unsafe { libc::fcntl(fd, libc::F_RDADVISE, &advisory as *const libc::radvisory) }
+        }
+        FileAdvice::DontNeed => 0,
+    };
+
+    if ret == -1 {
+        io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EBADF)
+    } else {
+        0
+    }
+}
+
 #[derive(Debug)]
 #[allow(clippy::upper_case_acronyms)]
 enum FAdviseError {
@@ -114,9 +219,9 @@ impl Error for FAdviseError {}
 
 #[cfg(test)]
 mod test {
-    use std::{os::unix::io::FromRawFd, panic, ptr};
+    use std::ptr;
 
-    use memmap::MmapOptions;
+    use memmap2::MmapOptions;
 
     use super::*;
 
@@ -135,6 +240,19 @@ mod test {
         madvise(ptr, 8, MemoryAdvice::DontNeed)
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn madvise_linux() {
+        let mmap = MmapOptions::new().len(8).map_anon().unwrap();
+        let ptr = mmap.as_ptr() as *mut u8;
+
+        madvise(ptr, 8, MemoryAdvice::DontDump).unwrap();
+
+        // kernels without transparent huge pages reject it
+        let huge_page = madvise(ptr, 8, MemoryAdvice::HugePage);
+        assert!(huge_page.is_ok() || huge_page.unwrap_err().raw_os_error() == Some(libc::EINVAL));
+    }
+
     #[test]
     fn madvise_not_aligned() {
         let ptr = "test".as_ptr();
@@ -144,6 +262,14 @@ mod test {
         assert_matches!(expected, _res);
     }
 
+    #[test]
+    fn touch_pages_count() {
+        let mmap = MmapOptions::new().len(8192 * 3 + 1).map_anon().unwrap();
+        assert!(touch_pages(&mmap) >= 2);
+        assert_eq!(touch_pages(&[]), 0);
+        assert_eq!(touch_pages(&[1]), 1);
+    }
+
     #[test]
     fn fadvise_success() {
         let file = file!();
@@ -152,7 +278,42 @@ mod test {
     }
 
     #[test]
+    fn fadvise_all_advices() {
+        let file = File::open(file!()).unwrap();
+        for advice in [
+            FileAdvice::WillNeed,
+            FileAdvice::Random,
+            FileAdvice::NoReuse,
+            FileAdvice::DontNeed,
+            FileAdvice::Sequential,
+            FileAdvice::Normal,
+        ] {
+            fadvise(&file, 0, None, advice);
+            fadvise(&file, 16, NonZeroI64::new(64), advice);
+        }
+    }
+
+    #[test]
+    #[cfg(target_vendor = "apple")]
+    fn fadvise_fcntl() {
+        let file = File::open(file!()).unwrap();
+        fadvise(&file, 0, None, FileAdvice::Random);
+        assert_eq!(advise_file(&file, 0, 0, FileAdvice::WillNeed), 0);
+        assert_eq!(
+            advise_file(&file, -1, 0, FileAdvice::WillNeed),
+            libc::EINVAL
+        );
+
+        // switched off again by the normal advice
+        fadvise(&file, 0, None, FileAdvice::NoReuse);
+        fadvise(&file, 0, None, FileAdvice::Normal);
+    }
+
+    #[test]
+    #[cfg(not(target_vendor = "apple"))]
     fn fadvise_pipe_error() {
+        use std::{os::unix::io::FromRawFd, panic};
+
         let mut fds: [libc::c_int; 2] = [0; 2];
         let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
 
@@ -0,0 +1,757 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use bstr::ByteSlice;
+use log::{debug, warn};
+use ureq::Agent;
+
+use crate::{
+    agent,
+    atomic::atomic_write,
+    find::{
+        lock::FileState,
+        range_dir::{self, RangeStore},
+        source::{Footprint, HashSource, Mapped},
+    },
+    paths::Location,
+};
+
+/// Range endpoint of the HIBP API
+pub const HIBP_RANGE_API: &str = "https://api.pwnedpasswords.com/range/";
+
+const OK: u16 = 200;
+
+/// Status code if the client exceeded the rate limit
+const TOO_MANY_REQUESTS: u16 = 429;
+
+/// Attempts of a single range until the rate limit or the server errors are reported
+const ATTEMPTS: usize = 5;
+
+/// Wait time after the first failed attempt - doubled after each further one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest accepted wait time - a stalled scan is worse than a failed one
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Ranges kept in memory for repeated lookups (ex: other Unicode forms) - about 20 KB each
+const CACHED_RANGES: usize = 4096;
+
+/// Response of the transport - error statuses are no errors, because the retries depend on them
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// First value of the header - the name is case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// HTTP client of the range API. It's injectable, so the retries and the cache could be tested
+/// against recorded responses without the network.
+pub trait Transport: Send + Sync {
+    /// GET request - only failed connections or timeouts are errors
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> io::Result<HttpResponse>;
+
+    /// Wait between two attempts - test doubles only record the duration
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Transport using blocking requests of a shared connection pool
+pub struct UreqTransport {
+    agent: Agent,
+}
+
+impl Default for UreqTransport {
+    fn default() -> Self {
+        UreqTransport {
+            // connections are re-used between the requests
+            agent: agent::agent_builder().build(),
+        }
+    }
+}
+
+impl Transport for UreqTransport {
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+        let mut request = self.agent.get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+
+        let response = match request.call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(transport)) => return Err(io::Error::other(transport)),
+        };
+
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let status = response.status();
+
+        let mut body = Vec::new();
+        response.into_reader().read_to_end(&mut body)?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Online range API like `https://api.pwnedpasswords.com/range/` or the range endpoint of the
+/// serve command. Only the first 5 hex characters of the saved hashes are sent. The responses are
+/// padded with fake entries, so even their size doesn't reveal the searched range.
+pub struct RangeApi {
+    /// Base URL including the trailing slash - the prefix is appended
+    url: String,
+    transport: Box<dyn Transport>,
+    /// Ranges without the padding by their prefix
+    cache: Mutex<HashMap<u32, Vec<u8>>>,
+    /// Prefetched range files of this API - none if the cache directory is unknown
+    disk_cache: Option<PathBuf>,
+    footprint: Mutex<Footprint>,
+}
+
+impl RangeApi {
+    /// Client using the prefetched ranges of the cache directory
+    pub fn new(url: &str) -> Self {
+        let api = Self::with_transport(url, Box::<UreqTransport>::default());
+        match Location::Cache.user_dir() {
+            Some(dir) => api.with_disk_cache(dir.join("ranges").join(cache_name(url))),
+            None => api,
+        }
+    }
+
+    /// Client always asking the API, because the prefetched ranges never expire and would miss
+    /// the latest breaches
+    pub fn uncached(url: &str) -> Self {
+        Self::with_transport(url, Box::<UreqTransport>::default())
+    }
+
+    pub fn with_transport(url: &str, transport: Box<dyn Transport>) -> Self {
+        debug!("Using the range API {}", url);
+        RangeApi {
+            url: url.to_string(),
+            transport,
+            cache: Mutex::default(),
+            disk_cache: None,
+            footprint: Mutex::default(),
+        }
+    }
+
+    pub fn with_disk_cache(mut self, dir: PathBuf) -> Self {
+        self.disk_cache = Some(dir);
+        self
+    }
+
+    pub fn disk_cache(&self) -> Option<&PathBuf> {
+        self.disk_cache.as_ref()
+    }
+
+    /// Download the ranges into the cache directory, so later scans of the same passwords don't
+    /// need the network. Ranges that are already cached are skipped unless they are refreshed.
+    pub fn prefetch(&self, prefixes: &[u32], refresh: bool) -> io::Result<Prefetched> {
+        let dir = self.disk_cache.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Cannot find the cache directory")
+        })?;
+        fs::create_dir_all(dir)?;
+
+        let mut prefetched = Prefetched::default();
+        for &prefix in prefixes {
+            let path = range_file(dir, prefix);
+            if !refresh && path.is_file() {
+                prefetched.cached += 1;
+                continue;
+            }
+
+            let lines = self.fetch(prefix)?;
+            atomic_write(&path, |file| file.write_all(&lines))?;
+            prefetched.downloaded += 1;
+            prefetched.bytes += lines.len() as u64;
+        }
+
+        Ok(prefetched)
+    }
+
+    fn record(&self, update: impl FnOnce(&mut Footprint)) {
+        // unwrap is safe, because the updates cannot panic while holding the lock
+        update(&mut self.footprint.lock().unwrap());
+    }
+
+    /// Remaining hash characters and counts of the range without the padding
+    fn fetch(&self, prefix: u32) -> io::Result<Vec<u8>> {
+        self.record(|footprint| footprint.ranges += 1);
+        let response = self.request(prefix)?;
+        let mut lines = Vec::with_capacity(response.body.len());
+        for line in response.body.lines().filter(|line| !is_padding(line)) {
+            lines.extend_from_slice(line);
+            lines.push(b'\n');
+        }
+
+        Ok(lines)
+    }
+
+    /// Response for a single range. Exceeded rate limits, server errors and failed connections are
+    /// retried with an exponential backoff - the rate limit waits as long as the server requests.
+    pub fn request(&self, prefix: u32) -> io::Result<HttpResponse> {
+        let url = format!("{}{:05X}", self.url, prefix);
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            self.record(|footprint| {
+                footprint.requests += 1;
+                // measured on the sent URL instead of trusting the format
+                footprint.hash_chars = footprint.hash_chars.max(url.len() - self.url.len());
+            });
+
+            let result = self.transport.get(&url, &[("Add-Padding", "true")]);
+            match retry_reason(&result, backoff) {
+                Some((wait, reason)) if attempt < ATTEMPTS => {
+                    warn!("{} - retrying {:05X} in {:?}", reason, prefix, wait);
+                    self.transport.sleep(wait);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                _ => {
+                    let response = result?;
+                    if response.status != OK {
+                        return Err(io::Error::other(format!(
+                            "Range API responded with HTTP status {} for {:05X}",
+                            response.status, prefix
+                        )));
+                    }
+
+                    return Ok(response);
+                }
+            }
+        }
+    }
+}
+
+/// Wait time and reason if the attempt could succeed later - none for final responses
+fn retry_reason(
+    result: &io::Result<HttpResponse>,
+    backoff: Duration,
+) -> Option<(Duration, String)> {
+    match result {
+        Ok(response) if response.status == TOO_MANY_REQUESTS => {
+            let wait = retry_after(response).unwrap_or(backoff);
+            Some((wait, "Rate limit of the range API exceeded".to_string()))
+        }
+        Ok(response) if response.status >= 500 => Some((
+            backoff,
+            format!("Range API failed with HTTP status {}", response.status),
+        )),
+        Ok(_) => None,
+        Err(err) => Some((backoff, format!("Range API is unreachable {}", err))),
+    }
+}
+
+/// Wait time requested by the server in seconds
+fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    response
+        .header("Retry-After")
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map(|seconds| Duration::from_secs(seconds).min(MAX_BACKOFF))
+}
+
+/// Fake entries of the padding have a count of 0
+fn is_padding(line: &[u8]) -> bool {
+    line.trim_ascii_end().ends_with(b":0")
+}
+
+/// Number of ranges of a prefetch
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Prefetched {
+    pub downloaded: usize,
+    /// Ranges that were already cached
+    pub cached: usize,
+    /// Size of the downloaded ranges without the padding
+    pub bytes: u64,
+}
+
+/// Directory name of the API in the cache (ex: `api.pwnedpasswords.com_range`)
+fn cache_name(url: &str) -> String {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.trim_end_matches('/').replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+        "_",
+    )
+}
+
+/// Same name as the range files of the official downloader
+fn range_file(dir: &Path, prefix: u32) -> PathBuf {
+    dir.join(format!("{:05X}.txt", prefix))
+}
+
+impl RangeStore for RangeApi {
+    /// Prefetched ranges are preferred over the network
+    fn read_range(&self, prefix: u32) -> io::Result<Vec<u8>> {
+        // unwrap is safe, because the lock is only held for inserts and lookups that cannot panic
+        if let Some(lines) = self.cache.lock().unwrap().get(&prefix) {
+            return Ok(lines.clone());
+        }
+
+        let cached = self
+            .disk_cache
+            .as_ref()
+            .map(|dir| range_file(dir, prefix))
+            .filter(|path| path.is_file());
+        let content = match cached {
+            Some(path) => {
+                debug!("Using the prefetched range {:?}", path);
+                self.record(|footprint| footprint.cached += 1);
+                fs::read(path)?
+            }
+            None => self.fetch(prefix)?,
+        };
+        let lines = range_dir::full_lines(prefix, content.lines());
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() < CACHED_RANGES {
+            cache.insert(prefix, lines.clone());
+        }
+
+        Ok(lines)
+    }
+}
+
+impl HashSource for RangeApi {
+    /// The API has no state, so updates of the corpus during the scan cannot be detected
+    fn state(&self) -> io::Result<FileState> {
+        Ok(FileState::of_len(0))
+    }
+
+    fn map(&self) -> io::Result<Mapped<'_>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The range API cannot be mapped",
+        ))
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The range API can only be queried by prefix",
+        ))
+    }
+
+    fn ranges(&self) -> Option<&dyn RangeStore> {
+        Some(self)
+    }
+
+    fn footprint(&self) -> Option<Footprint> {
+        // unwrap is safe, because the updates cannot panic while holding the lock
+        Some(*self.footprint.lock().unwrap())
+    }
+}
+
+/// URL and headers of a replayed request
+#[cfg(test)]
+pub type RecordedRequest = (String, Vec<(String, String)>);
+
+/// Test double that replays recorded responses in order and records the requests and waits
+#[cfg(test)]
+#[derive(Default)]
+pub struct ReplayTransport {
+    responses: Mutex<std::collections::VecDeque<io::Result<HttpResponse>>>,
+    pub requests: Mutex<Vec<RecordedRequest>>,
+    pub waits: Mutex<Vec<Duration>>,
+}
+
+#[cfg(test)]
+impl ReplayTransport {
+    /// Responses with the status and body - without further headers
+    pub fn recorded(responses: &[(u16, &str)]) -> Self {
+        let transport = ReplayTransport::default();
+        for (status, body) in responses {
+            transport.push(Ok(HttpResponse {
+                status: *status,
+                headers: Vec::new(),
+                body: body.as_bytes().to_vec(),
+            }));
+        }
+
+        transport
+    }
+
+    pub fn push(&self, response: io::Result<HttpResponse>) {
+        self.responses.lock().unwrap().push_back(response);
+    }
+}
+
+#[cfg(test)]
+impl Transport for ReplayTransport {
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((url.to_string(), headers));
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("No recorded response left for {}", url))
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.waits.lock().unwrap().push(duration);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process, sync::Arc};
+
+    use super::*;
+    use crate::{
+        cancel::CancellationToken, collect::SavedHash, find::range_dir::find_hash_in_ranges,
+        find::Finding, progress::SilentProgress, SHA1_BYTE_LENGTH,
+    };
+
+    const URL: &str = "https://api.example.com/range/";
+
+    /// Excerpt of a padded response of the range 5BAA6
+    const RECORDED: &str = "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+        1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+        1E4D5F0E9F1F0E44B9C4F7E2C5B3E28C3B5:0\r\n\
+        1E4E381A8D3B6E1E9E4F2D8B8D3E8F5A1C0:2\r\n";
+
+    /// Recorded range with the prefix and without the padding
+    const FULL_LINES: &str = "5BAA6003D68EB55068C33ACE09247EE4C639306B:3\n\
+        5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\n\
+        5BAA61E4E381A8D3B6E1E9E4F2D8B8D3E8F5A1C0:2\n";
+
+    /// Shares the replay with the API, so the requests could be checked afterwards
+    struct Shared(Arc<ReplayTransport>);
+
+    impl Transport for Shared {
+        fn get(&self, url: &str, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+            self.0.get(url, headers)
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.0.sleep(duration);
+        }
+    }
+
+    fn replayed(transport: ReplayTransport) -> (RangeApi, Arc<ReplayTransport>) {
+        let transport = Arc::new(transport);
+        let api = RangeApi::with_transport(URL, Box::new(Shared(transport.clone())));
+        (api, transport)
+    }
+
+    fn saved(hex: &str) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        SavedHash::from_hash(hash)
+    }
+
+    fn with_retry_after(status: u16, seconds: &str) -> io::Result<HttpResponse> {
+        Ok(HttpResponse {
+            status,
+            headers: vec![("retry-after".to_string(), seconds.to_string())],
+            body: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_padding() {
+        assert!(is_padding(b"0018A45C4D1DEF81644B54AB7F969B88D65:0"));
+        assert!(is_padding(b"0018A45C4D1DEF81644B54AB7F969B88D65:0\r"));
+        assert!(!is_padding(b"0018A45C4D1DEF81644B54AB7F969B88D65:10"));
+        assert!(!is_padding(b"0018A45C4D1DEF81644B54AB7F969B88D65:1"));
+    }
+
+    #[test]
+    fn test_read_range() {
+        let (api, transport) = replayed(ReplayTransport::recorded(&[(200, RECORDED)]));
+        let lines = api.read_range(0x5BAA6).unwrap();
+        assert_eq!(lines.to_str().unwrap(), FULL_LINES);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            *requests,
+            [(
+                format!("{}5BAA6", URL),
+                vec![("Add-Padding".to_string(), "true".to_string())]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let (api, transport) = replayed(ReplayTransport::recorded(&[(200, RECORDED)]));
+        let first = api.read_range(0x5BAA6).unwrap();
+        assert_eq!(api.read_range(0x5BAA6).unwrap(), first);
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let dir = env::temp_dir().join(format!("pwned-check-prefetch-{}", process::id()));
+        let (api, transport) = replayed(ReplayTransport::recorded(&[(200, ""), (200, RECORDED)]));
+        let api = api.with_disk_cache(dir.clone());
+
+        let prefetched = api.prefetch(&[0x00000, 0x5BAA6], false).unwrap();
+        assert_eq!(prefetched.downloaded, 2);
+        assert_eq!(prefetched.cached, 0);
+
+        // the padding isn't stored
+        let stored = fs::read_to_string(dir.join("5BAA6.txt")).unwrap();
+        assert_eq!(stored.lines().count(), 3);
+
+        // cached ranges are only downloaded again if they are refreshed
+        let prefetched = api.prefetch(&[0x5BAA6], false).unwrap();
+        assert_eq!(
+            prefetched,
+            Prefetched {
+                downloaded: 0,
+                cached: 1,
+                bytes: 0
+            }
+        );
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+
+        // without any recorded responses left every request would fail
+        let offline = RangeApi::with_transport(URL, Box::<ReplayTransport>::default())
+            .with_disk_cache(dir.clone());
+        assert_eq!(
+            offline.read_range(0x5BAA6).unwrap().to_str().unwrap(),
+            FULL_LINES
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_name() {
+        assert_eq!(cache_name(HIBP_RANGE_API), "api.pwnedpasswords.com_range");
+        assert_eq!(
+            cache_name("http://127.0.0.1:8080/range/"),
+            "127.0.0.1_8080_range"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let replay = ReplayTransport::default();
+        replay.push(with_retry_after(429, "3"));
+        replay.push(with_retry_after(429, "3600"));
+        replay.push(Ok(HttpResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: RECORDED.as_bytes().to_vec(),
+        }));
+
+        let (api, transport) = replayed(replay);
+        assert!(api.read_range(0x5BAA6).is_ok());
+        assert_eq!(
+            *transport.waits.lock().unwrap(),
+            [Duration::from_secs(3), MAX_BACKOFF]
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let replay = ReplayTransport::recorded(&[(503, "")]);
+        replay.push(Err(io::ErrorKind::TimedOut.into()));
+        replay.push(with_retry_after(429, "soon"));
+        replay.push(Ok(HttpResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: RECORDED.as_bytes().to_vec(),
+        }));
+
+        let (api, transport) = replayed(replay);
+        assert!(api.read_range(0x5BAA6).is_ok());
+        assert_eq!(
+            *transport.waits.lock().unwrap(),
+            [
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footprint() {
+        let (api, _) = replayed(ReplayTransport::recorded(&[(429, ""), (200, RECORDED)]));
+        api.read_range(0x5BAA6).unwrap();
+        api.read_range(0x5BAA6).unwrap();
+        assert_eq!(
+            api.footprint(),
+            Some(Footprint {
+                ranges: 1,
+                cached: 0,
+                requests: 2,
+                hash_chars: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_give_up() {
+        let (api, transport) = replayed(ReplayTransport::recorded(&[(503, ""); ATTEMPTS]));
+        assert!(api.read_range(0x5BAA6).is_err());
+        assert_eq!(transport.requests.lock().unwrap().len(), ATTEMPTS);
+        assert_eq!(transport.waits.lock().unwrap().len(), ATTEMPTS - 1);
+
+        // client errors aren't retried and failed ranges aren't cached
+        let (api, transport) = replayed(ReplayTransport::recorded(&[(400, ""), (200, RECORDED)]));
+        assert!(api.read_range(0x5BAA6).is_err());
+        assert!(transport.waits.lock().unwrap().is_empty());
+        assert!(api.read_range(0x5BAA6).is_ok());
+    }
+
+    #[test]
+    fn test_find() {
+        let (api, _) = replayed(ReplayTransport::recorded(&[(200, RECORDED)]));
+        let hashes = [
+            saved("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"),
+            // padding entries are never reported
+            saved("5BAA61E4D5F0E9F1F0E44B9C4F7E2C5B3E28C3B5"),
+        ];
+        let status = find_hash_in_ranges(
+            &api,
+            &hashes,
+            true,
+            &mut SilentProgress,
+            &CancellationToken::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            status.into_inner(),
+            [Finding {
+                index: 0,
+                count: Some(9545824)
+            }]
+        );
+    }
+}
+
+/// Tests against the live HIBP API - run them with `cargo test --features live-tests -- --ignored`
+#[cfg(all(test, feature = "live-tests"))]
+mod live_test {
+    use super::*;
+    use crate::{
+        cancel::CancellationToken, collect::SavedHash, find::range_dir::find_hash_in_ranges,
+        progress::SilentProgress, SHA1_BYTE_LENGTH,
+    };
+
+    /// SHA-1 of `password` - pwned millions of times
+    const CANARY: &str = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+
+    /// Random hash of the same range that no one ever used
+    const UNUSED: &str = "5BAA6FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+
+    fn saved(hex: &str) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        SavedHash::from_hash(hash)
+    }
+
+    /// Client without the prefetched ranges, so every request reaches the API
+    fn live_api() -> RangeApi {
+        RangeApi::uncached(HIBP_RANGE_API)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_live_headers() {
+        let response = live_api().request(0x5BAA6).unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response
+            .header("Content-Type")
+            .unwrap()
+            .starts_with("text/plain"));
+
+        let body = String::from_utf8(response.body).unwrap();
+        // the padding is only added on request
+        assert!(body.lines().any(|line| is_padding(line.as_bytes())));
+        for line in body.lines() {
+            let (suffix, count) = line.split_once(':').unwrap();
+            assert_eq!(suffix.len(), 35, "{}", line);
+            assert!(count.parse::<u64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_live_parse() {
+        let lines = live_api().read_range(0x5BAA6).unwrap();
+        let lines = String::from_utf8(lines).unwrap();
+        assert!(lines.lines().all(|line| line.starts_with("5BAA6")));
+        assert!(!lines.lines().any(|line| is_padding(line.as_bytes())));
+
+        let (_, count) = lines
+            .lines()
+            .find_map(|line| line.split_once(':').filter(|(hash, _)| *hash == CANARY))
+            .unwrap();
+        assert!(count.parse::<u64>().unwrap() > 1_000_000);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_live_find() {
+        let api = live_api();
+        let hashes = [saved(CANARY), saved(UNUSED)];
+        let status = find_hash_in_ranges(
+            &api,
+            &hashes,
+            true,
+            &mut SilentProgress,
+            &CancellationToken::default(),
+        )
+        .unwrap();
+
+        let findings = status.into_inner();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].index, 0);
+        assert!(findings[0].count.unwrap() > 1_000_000);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_live_burst() {
+        // a scan of a large vault requests hundreds of ranges in a row
+        let api = live_api();
+        for prefix in 0..50 {
+            assert!(
+                !api.read_range(prefix).unwrap().is_empty(),
+                "{:05X}",
+                prefix
+            );
+        }
+    }
+}
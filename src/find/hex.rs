@@ -0,0 +1,220 @@
+use std::cmp::Ordering;
+
+use data_encoding::HEXUPPER;
+use packed_simd_2::{shuffle, u8x16, u8x32};
+
+use crate::Sha1Hash;
+
+/// Number of hex characters of a SHA-1 hash
+pub const HASH_HEX_LENGTH: usize = 40;
+
+/// Uppercase hex of a saved hash for comparisons in hex space
+pub type HashHex = [u8; HASH_HEX_LENGTH];
+
+/// Number of hex characters decoded per instruction
+const BLOCK_HEX_LENGTH: usize = 32;
+
+/// Decode the uppercase hex characters into the output with half their length. Returns false if
+/// there is a character other than `0-9` or `A-F`. The hex has to be at least 32 characters long
+/// (ex: NTLM and SHA-1).
+///
+/// This is called for every line of the hash database, so the fixed size fields are decoded with
+/// SIMD instructions on x86 and ARM. Other targets use `data-encoding`.
+#[inline]
+pub fn decode_upper(hex: &[u8], output: &mut [u8]) -> bool {
+    debug_assert!(hex.len() >= BLOCK_HEX_LENGTH);
+    debug_assert_eq!(output.len(), hex.len() / 2);
+
+    if cfg!(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "arm"
+    )) {
+        decode_blocks(hex, output)
+    } else {
+        // packed_simd only emulates the vectors there
+        HEXUPPER.decode_mut(hex, output).is_ok()
+    }
+}
+
+#[inline]
+fn decode_blocks(hex: &[u8], output: &mut [u8]) -> bool {
+    // the overlapping bytes are decoded to the same value, so writing them twice is fine
+    block_starts(hex.len()).all(|start| {
+        let end = start + BLOCK_HEX_LENGTH;
+        decode_block(&hex[start..end], &mut output[start / 2..end / 2])
+    })
+}
+
+/// Uppercase hex of the hash, which has the same lexicographic order as the bytes
+pub fn encode_upper(hash: &Sha1Hash) -> HashHex {
+    let mut hex = [0; HASH_HEX_LENGTH];
+    HEXUPPER.encode_mut(hash, &mut hex);
+    hex
+}
+
+/// Check if all characters are `0-9` or `A-F` without decoding them. Same length requirement as
+/// `decode_upper`.
+#[inline]
+pub fn is_upper(hex: &[u8]) -> bool {
+    debug_assert!(hex.len() >= BLOCK_HEX_LENGTH);
+    block_starts(hex.len()).all(|start| is_upper_block(block(hex, start)))
+}
+
+/// Lexicographic order of the hex hash of a line to the hex of a saved hash. Shorter lines (ex:
+/// NTLM) are compared as if they are padded with zeros like the decoded hashes.
+#[inline]
+pub fn cmp_upper(line: &[u8], saved: &HashHex) -> Ordering {
+    let order = block_starts(line.len())
+        .map(|start| {
+            block(line, start)
+                .lex_ord()
+                .cmp(&block(saved, start).lex_ord())
+        })
+        .find(|&order| order != Ordering::Equal)
+        .unwrap_or(Ordering::Equal);
+
+    if order == Ordering::Equal && saved[line.len()..].iter().any(|&hex| hex != b'0') {
+        Ordering::Less
+    } else {
+        order
+    }
+}
+
+/// Start of the 32 character blocks - the last one overlaps with the previous one for lengths
+/// like 40 (SHA-1)
+#[inline]
+fn block_starts(length: usize) -> impl Iterator<Item = usize> {
+    let last = length - BLOCK_HEX_LENGTH;
+    (0..last)
+        .step_by(BLOCK_HEX_LENGTH)
+        .chain(std::iter::once(last))
+}
+
+#[inline]
+fn block(hex: &[u8], start: usize) -> u8x32 {
+    u8x32::from_slice_unaligned(&hex[start..start + BLOCK_HEX_LENGTH])
+}
+
+#[inline]
+fn is_upper_block(chars: u8x32) -> bool {
+    // wrapping subtraction - so characters below the range become large and fail the check
+    let is_digit = (chars - u8x32::splat(b'0')).lt(u8x32::splat(10));
+    let is_letter = (chars - u8x32::splat(b'A')).lt(u8x32::splat(6));
+    (is_digit | is_letter).all()
+}
+
+/// Decode 32 hex characters into 16 bytes
+#[inline]
+fn decode_block(hex: &[u8], output: &mut [u8]) -> bool {
+    let chars = u8x32::from_slice_unaligned(hex);
+    if !is_upper_block(chars) {
+        return false;
+    }
+
+    let digits = chars - u8x32::splat(b'0');
+    let letters = chars - u8x32::splat(b'A');
+    let nibbles = digits
+        .lt(u8x32::splat(10))
+        .select(digits, letters + u8x32::splat(10));
+
+    // even characters are the high nibbles of the bytes, odd characters the low ones
+    let high: u8x16 = shuffle!(
+        nibbles,
+        [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30]
+    );
+    let low: u8x16 = shuffle!(
+        nibbles,
+        [1, 3, 5, 7, 9, 11, 13, 15, 17, 19, 21, 23, 25, 27, 29, 31]
+    );
+
+    ((high << 4) | low).write_to_slice_unaligned(output);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    const HASH_A: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4";
+
+    fn decode(hex: &str) -> Option<Vec<u8>> {
+        let mut output = vec![0; hex.len() / 2];
+        if decode_upper(hex.as_bytes(), &mut output) {
+            Some(output)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_decode_lengths() {
+        // SHA-1 with an overlapping block and NTLM with exactly one block
+        let sha1 = HASH_A;
+        let ntlm = "8846F7EAEE8FB117AD06BDD830B7586C";
+        for hex in &[sha1, ntlm] {
+            assert_eq!(decode(hex), Some(HEXUPPER.decode(hex.as_bytes()).unwrap()));
+        }
+
+        let all = "0123456789ABCDEFFEDCBA9876543210";
+        assert_eq!(decode(all), Some(HEXUPPER.decode(all.as_bytes()).unwrap()));
+    }
+
+    #[test]
+    fn test_cmp_upper() {
+        let saved = encode_upper(
+            &HEXUPPER
+                .decode(HASH_A.as_bytes())
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(&saved, HASH_A.as_bytes());
+        assert_eq!(cmp_upper(HASH_A.as_bytes(), &saved), Ordering::Equal);
+
+        // differs only in the overlapping last block
+        let higher = "000000005AD76BD555C1D6D771DE417A4B87E4B5";
+        assert_eq!(cmp_upper(higher.as_bytes(), &saved), Ordering::Greater);
+        let lower = "000000005AD76BD555C1D6D771DE417A4B87E4A4";
+        assert_eq!(cmp_upper(lower.as_bytes(), &saved), Ordering::Less);
+
+        // digits are ordered before the letters like the decoded bytes
+        let letter = "0000000A5AD76BD555C1D6D771DE417A4B87E4B4";
+        assert_eq!(cmp_upper(letter.as_bytes(), &saved), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_cmp_upper_ntlm() {
+        let ntlm = "8846F7EAEE8FB117AD06BDD830B7586C";
+        let padded = format!("{}00000000", ntlm);
+        let mut saved = [0; HASH_HEX_LENGTH];
+        saved.copy_from_slice(padded.as_bytes());
+        assert_eq!(cmp_upper(ntlm.as_bytes(), &saved), Ordering::Equal);
+
+        // a SHA-1 hash with the NTLM hash as prefix is larger
+        saved[HASH_HEX_LENGTH - 1] = b'1';
+        assert_eq!(cmp_upper(ntlm.as_bytes(), &saved), Ordering::Less);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let valid = HASH_A;
+        assert!(decode(valid).is_some());
+        assert!(is_upper(valid.as_bytes()));
+
+        // at each position of both blocks, because they overlap
+        for position in 0..valid.len() {
+            for &invalid in &[b'a', b'f', b'G', b'/', b':', b'@', b' ', 0xC3] {
+                let mut hex = valid.as_bytes().to_vec();
+                hex[position] = invalid;
+
+                let mut output = [0; 20];
+                assert!(!decode_upper(&hex, &mut output), "{} {}", position, invalid);
+                assert!(!is_upper(&hex), "{} {}", position, invalid);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::{self, Read};
+
+use log::debug;
+use ureq::{Agent, Request};
+
+use crate::{
+    agent::agent_builder,
+    find::{lock::FileState, s3::S3Signer, source::HashSource},
+};
+
+/// Status code for responses that contain only the requested range
+const PARTIAL_CONTENT: u16 = 206;
+
+/// Hash database on a static file host (ex: S3, nginx). The database is searched using HTTP range
+/// requests, so only a few kilobytes per saved hash are downloaded instead of the complete file.
+pub struct HttpSource {
+    url: String,
+    agent: Agent,
+    /// Authentication of requests to private S3 objects
+    signer: Option<S3Signer>,
+}
+
+impl HttpSource {
+    /// Fails if the server doesn't report the size of the file
+    pub fn new(url: &str) -> Result<Self, io::Error> {
+        Self::with_signer(url, None)
+    }
+
+    /// Object of a S3 compatible storage (`s3://bucket/key`) using the AWS credential chain
+    pub fn s3(url: &str) -> Result<Self, io::Error> {
+        let signer = S3Signer::from_url(url)?;
+        let endpoint = signer.endpoint();
+        Self::with_signer(&endpoint, Some(signer))
+    }
+
+    fn with_signer(url: &str, signer: Option<S3Signer>) -> Result<Self, io::Error> {
+        let source = HttpSource {
+            url: url.to_string(),
+            // connections are re-used between the requests
+            agent: agent_builder().build(),
+            signer,
+        };
+
+        let state = source.state()?;
+        debug!("Remote hash file {} has {} bytes", url, state.len());
+        Ok(source)
+    }
+
+    fn request(&self, method: &str, range: Option<&str>) -> Request {
+        let mut request = self.agent.request(method, &self.url);
+        if let Some(range) = range {
+            request = request.set("Range", range);
+        }
+
+        match &self.signer {
+            Some(signer) => signer.sign(request, method, range),
+            None => request,
+        }
+    }
+}
+
+impl HashSource for HttpSource {
+    fn state(&self) -> io::Result<FileState> {
+        let response = self.request("HEAD", None).call().map_err(to_io_error)?;
+        response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok())
+            .map(FileState::of_len)
+            .ok_or_else(|| io::Error::other("Missing content length of the remote hash file"))
+    }
+
+    fn map(&self) -> io::Result<crate::find::source::Mapped<'_>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Remote hash files cannot be mapped",
+        ))
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        let response = self.request("GET", None).call().map_err(to_io_error)?;
+        Ok(Box::new(response.into_reader()))
+    }
+
+    fn supports_ranges(&self) -> bool {
+        true
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        // the end of the range is inclusive
+        let range = format!("bytes={}-{}", offset, offset + len as u64 - 1);
+        let response = self
+            .request("GET", Some(&range))
+            .call()
+            .map_err(to_io_error)?;
+
+        // servers without range support respond with the complete file
+        if response.status() != PARTIAL_CONTENT {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Server doesn't support range requests",
+            ));
+        }
+
+        let mut data = Vec::with_capacity(len);
+        response
+            .into_reader()
+            .take(len as u64)
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+pub fn to_io_error(err: ureq::Error) -> io::Error {
+    match err {
+        ureq::Error::Status(code, _) => io::Error::other(format!("HTTP status {}", code)),
+        ureq::Error::Transport(transport) => io::Error::other(transport),
+    }
+}
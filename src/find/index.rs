@@ -0,0 +1,457 @@
+use std::{
+    convert::TryInto,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+};
+
+use bstr::io::BufReadExt;
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+
+pub use in_place::convert_in_place;
+
+use crate::{
+    cancel::{CancellationToken, Status},
+    collect::SavedHash,
+    find::{
+        parse::{hash_hex_length, ParseHashError, PwnedHash},
+        prefix::PrefixFilter,
+        strip_carriage_return, Finding,
+    },
+    progress::{ProgressSink, ProgressUnit},
+    stats::CountHistogram,
+    Sha1Hash, SHA1_BYTE_LENGTH,
+};
+
+mod in_place;
+
+/// Expected size of the index of a text database. The index of the random hashes takes about 85 %
+/// of the text.
+pub fn estimated_len(text_len: u64) -> u64 {
+    text_len - text_len / 8
+}
+
+/// Format versions of the `fst` crate - text databases start with a hex character instead
+const FST_VERSIONS: [u64; 2] = [2, 3];
+
+/// Value of hashes without a count - HIBP counts start at 1
+const UNKNOWN_COUNT: u64 = 0;
+
+#[derive(Debug)]
+pub enum ConvertError {
+    Io(io::Error),
+    /// Invalid line of the text database - the number starts at 1
+    Parse(u64, ParseHashError),
+    /// Hash before the already converted shards - the number starts at 1
+    Unsorted(u64),
+    Index(fst::Error),
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Io(err) => write!(f, "{}", err),
+            ConvertError::Parse(line, err) => write!(f, "Invalid line {}: {}", line, err),
+            ConvertError::Unsorted(line) => write!(f, "Line {} is not sorted by hash", line),
+            ConvertError::Index(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ConvertError {}
+
+impl From<io::Error> for ConvertError {
+    fn from(err: io::Error) -> Self {
+        ConvertError::Io(err)
+    }
+}
+
+impl From<fst::Error> for ConvertError {
+    fn from(err: fst::Error) -> Self {
+        ConvertError::Index(err)
+    }
+}
+
+/// Hash database converted by `convert` - either a single `fst` or one per first hex character if
+/// it was converted in place
+pub struct Index<'a> {
+    shards: Vec<Map<&'a [u8]>>,
+}
+
+impl Index<'_> {
+    pub fn get(&self, hash: &[u8]) -> Option<u64> {
+        let shard = if self.shards.len() == 1 {
+            0
+        } else {
+            usize::from(hash[0] >> 4)
+        };
+
+        self.shards[shard].get(hash)
+    }
+
+    /// Hashes between both bounds (inclusive) with their counts. Both bounds have to share the
+    /// first hex character, because each one could be in another shard.
+    pub fn range(&self, lowest: &Sha1Hash, highest: &Sha1Hash) -> Vec<(Sha1Hash, Option<u64>)> {
+        let shard = if self.shards.len() == 1 {
+            0
+        } else {
+            usize::from(lowest[0] >> 4)
+        };
+
+        let mut stream = self.shards[shard]
+            .range()
+            .ge(lowest)
+            .le(highest)
+            .into_stream();
+        let mut entries = Vec::new();
+        while let Some((key, count)) = stream.next() {
+            // keys are always complete hashes, because they are inserted from the parsed lines
+            if let Ok(hash) = key.try_into() {
+                entries.push((hash, Some(count).filter(|count| *count != UNKNOWN_COUNT)));
+            }
+        }
+
+        entries
+    }
+
+    /// Number of indexed hashes
+    pub fn hashes(&self) -> usize {
+        self.shards.iter().map(Map::len).sum()
+    }
+
+    /// Counts of all indexed hashes - streams the complete index
+    pub fn counts(&self) -> CountHistogram {
+        let mut histogram = CountHistogram::default();
+        for shard in &self.shards {
+            let mut stream = shard.stream();
+            while let Some((_, count)) = stream.next() {
+                histogram.insert(count);
+            }
+        }
+
+        histogram
+    }
+}
+
+/// Whether the data is an index created by `convert` instead of a text database
+pub fn is_index(data: &[u8]) -> bool {
+    data.starts_with(&in_place::SHARDED_MAGIC)
+        || data
+            .get(..8)
+            // unwrap is safe, because the slice has exactly 8 bytes
+            .map(|header| u64::from_le_bytes(header.try_into().unwrap()))
+            .is_some_and(|version| FST_VERSIONS.contains(&version))
+}
+
+/// Open the index if the data is one
+pub fn open(data: &[u8]) -> io::Result<Option<Index<'_>>> {
+    if data.starts_with(&in_place::PARTIAL_MAGIC) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Incomplete index - run the interrupted convert command again",
+        ));
+    }
+
+    if !is_index(data) {
+        return Ok(None);
+    }
+
+    let shards = match in_place::read_header(data)? {
+        Some(ranges) => ranges
+            .into_iter()
+            .map(|range| Map::new(&data[range]))
+            .collect(),
+        None => Map::new(data).map(|map| vec![map]),
+    }
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    Ok(Some(Index { shards }))
+}
+
+/// Look up each saved hash in the index. Each lookup only follows the transitions of the 20 hash
+/// bytes, so it takes microseconds independent of the database size.
+pub fn find_hash_indexed(
+    map: &Index<'_>,
+    hashes: &[SavedHash],
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Status<Vec<Finding>> {
+    progress.start(hashes.len() as u64, ProgressUnit::Items);
+
+    let mut findings = Vec::new();
+    for (index, saved) in hashes.iter().enumerate() {
+        if cancel.is_cancelled() {
+            progress.finish();
+            return Status::Cancelled(findings);
+        }
+
+        if let Some(count) = map.get(&saved.password_hash) {
+            findings.push(Finding {
+                index,
+                count: Some(count).filter(|count| *count != UNKNOWN_COUNT),
+            });
+        }
+
+        progress.advance(1);
+    }
+
+    progress.finish();
+    Status::Completed(findings)
+}
+
+/// Convert a text database sorted by hash into an index with the hash bytes as keys and the counts
+/// as values. Duplicated hashes (ex: concatenated lists) are merged using the highest count.
+/// Returns the number of indexed hashes and their prefixes.
+pub fn convert(
+    reader: impl BufReadExt,
+    len: u64,
+    writer: impl Write,
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+) -> Result<(u64, PrefixFilter), ConvertError> {
+    let mut builder = MapBuilder::new(writer)?;
+    let mut prefixes = PrefixFilter::default();
+    let mut indexed = 0;
+    for_each_hash(reader, len, strict, progress, |hash, count| {
+        // out of order hashes are rejected by the builder
+        builder.insert(hash, count)?;
+        prefixes.insert(&hash);
+        indexed += 1;
+        Ok(())
+    })?;
+
+    builder.finish()?;
+    Ok((indexed, prefixes))
+}
+
+/// Collect the prefixes of a text database that is scanned without converting it
+pub fn collect_prefixes(
+    reader: impl BufReadExt,
+    len: u64,
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+) -> Result<PrefixFilter, ConvertError> {
+    let mut prefixes = PrefixFilter::default();
+    for_each_hash(reader, len, strict, progress, |hash, _| {
+        prefixes.insert(&hash);
+        Ok(())
+    })?;
+
+    Ok(prefixes)
+}
+
+/// Counts of a text database or an index - duplicated hashes of the text are counted once
+pub fn count_hashes(
+    data: &[u8],
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+) -> Result<CountHistogram, ConvertError> {
+    if let Some(index) = open(data)? {
+        return Ok(index.counts());
+    }
+
+    let mut histogram = CountHistogram::default();
+    for_each_hash(data, data.len() as u64, strict, progress, |_, count| {
+        histogram.insert(count);
+        Ok(())
+    })?;
+
+    Ok(histogram)
+}
+
+/// Parse the text database and call `insert` once per hash with its highest count
+fn for_each_hash(
+    reader: impl BufReadExt,
+    len: u64,
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+    mut insert: impl FnMut(Sha1Hash, u64) -> Result<(), ConvertError>,
+) -> Result<(), ConvertError> {
+    progress.start(len, ProgressUnit::Bytes);
+    let mut record = PwnedHash::new(strict);
+    let mut pending: Option<(Sha1Hash, u64)> = None;
+    let mut line_number = 0;
+    let mut failed = None;
+
+    reader.for_byte_line(|line| {
+        line_number += 1;
+        progress.advance(line.len() as u64 + 1);
+
+        let line = strip_carriage_return(line);
+        if line.is_empty() {
+            return Ok(true);
+        }
+
+        let (hash, count) = match parse_line(&mut record, line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                failed = Some(ConvertError::Parse(line_number, err));
+                return Ok(false);
+            }
+        };
+
+        match pending {
+            Some((previous, previous_count)) if previous == hash => {
+                pending = Some((hash, previous_count.max(count)));
+            }
+            Some(previous) => {
+                if let Err(err) = insert(previous.0, previous.1) {
+                    failed = Some(err);
+                    return Ok(false);
+                }
+
+                pending = Some((hash, count));
+            }
+            None => pending = Some((hash, count)),
+        }
+
+        Ok(true)
+    })?;
+
+    progress.finish();
+    if let Some(err) = failed {
+        return Err(err);
+    }
+
+    match pending {
+        Some((hash, count)) => insert(hash, count),
+        None => Ok(()),
+    }
+}
+
+fn parse_line(record: &mut PwnedHash, line: &[u8]) -> Result<(Sha1Hash, u64), ParseHashError> {
+    // the parser expects a hash followed by the end or a separator
+    let hex_length = hash_hex_length(line);
+    if line.len() < hex_length || (line.len() > hex_length && line[hex_length] != b':') {
+        return Err(ParseHashError::InvalidFormat());
+    }
+
+    let strict = record.strict;
+    record.parse_new_hash(line)?;
+    let count = match record.parse_count(line) {
+        Ok(count) => *count,
+        Err(ParseHashError::MissingCount()) if !strict => UNKNOWN_COUNT,
+        Err(_) => return Err(ParseHashError::InvalidFormat()),
+    };
+
+    let mut hash = [0; SHA1_BYTE_LENGTH];
+    hash.copy_from_slice(record.hash());
+    Ok((hash, count))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        find::{find_hash, source::MemorySource, MapOptions, ScanOptions, Strategy},
+        progress::RecordingProgress,
+    };
+
+    const HASH_A: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4";
+    const HASH_B: &str = "00000000A8DAE4228F821FB418F59826079BF368";
+    const HASH_C: &str = "00000000DD7F2A1C68A35673713783CA390C9E93";
+
+    fn saved(hex: &str) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        SavedHash::new("https://example.com/", "user", hash)
+    }
+
+    fn build(text: &str, strict: bool) -> Result<Vec<u8>, ConvertError> {
+        let mut index = Vec::new();
+        let mut progress = RecordingProgress::default();
+        let len = text.len() as u64;
+        convert(text.as_bytes(), len, &mut index, strict, &mut progress)?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_same_as_text() {
+        let text = format!("{}:4\r\n{}:7\r\n{}:1\r\n", HASH_A, HASH_B, HASH_C);
+        let hashes = [saved(HASH_A), saved(HASH_C)];
+        let options = ScanOptions {
+            strategy: Strategy::Auto,
+            strict: false,
+            mapping: MapOptions::default(),
+            watch: true,
+        };
+
+        let mut results = Vec::new();
+        for data in [text.clone().into_bytes(), build(&text, false).unwrap()] {
+            let source = MemorySource {
+                data,
+                mappable: true,
+            };
+            let mut progress = RecordingProgress::default();
+            let cancel = CancellationToken::default();
+            let status = find_hash(&source, &hashes, options, &mut progress, &cancel).unwrap();
+            results.push(status.into_inner());
+        }
+
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[1][1].count, Some(1));
+    }
+
+    #[test]
+    fn test_duplicates_merged() {
+        let text = format!("{}:4\n{}:9\n{}\n", HASH_A, HASH_A, HASH_B);
+        let data = build(&text, false).unwrap();
+        let index = open(&data).unwrap().unwrap();
+
+        assert_eq!(index.hashes(), 2);
+        assert_eq!(index.get(&saved(HASH_A).password_hash), Some(9));
+        assert_eq!(index.get(&saved(HASH_B).password_hash), Some(UNKNOWN_COUNT));
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let text = format!("{}:4\n{}:9\n", HASH_A, HASH_C);
+        let mut progress = RecordingProgress::default();
+        let len = text.len() as u64;
+        let (_, prefixes) =
+            convert(text.as_bytes(), len, Vec::new(), false, &mut progress).unwrap();
+
+        assert!(prefixes.contains(&saved(HASH_B).password_hash));
+        assert!(!prefixes.contains(&[0xFF; SHA1_BYTE_LENGTH]));
+
+        let collected = collect_prefixes(text.as_bytes(), len, false, &mut progress).unwrap();
+        assert_eq!(collected, prefixes);
+    }
+
+    #[test]
+    fn test_count_hashes() {
+        let text = format!("{}:4\n{}:9\n{}:2\n{}\n", HASH_A, HASH_A, HASH_B, HASH_C);
+        let mut progress = RecordingProgress::default();
+        let counted = count_hashes(text.as_bytes(), false, &mut progress).unwrap();
+        assert_eq!(counted.stats().hashes, 2);
+
+        let index = build(&text, false).unwrap();
+        assert_eq!(count_hashes(&index, false, &mut progress).unwrap(), counted);
+    }
+
+    #[test]
+    fn test_rejects_invalid() {
+        let unsorted = format!("{}:4\n{}:7\n", HASH_B, HASH_A);
+        assert!(build(&unsorted, false).is_err());
+
+        let missing = format!("{}:4\n{}\n", HASH_A, HASH_B);
+        assert!(matches!(
+            build(&missing, true),
+            Err(ConvertError::Parse(2, _))
+        ));
+        assert!(build("not a hash\n", false).is_err());
+    }
+
+    #[test]
+    fn test_is_index() {
+        assert!(is_index(&build("", false).unwrap()));
+        assert!(!is_index(HASH_A.as_bytes()));
+        assert!(!is_index(b""));
+        assert_eq!(
+            open(HASH_A.as_bytes()).unwrap().map(|index| index.hashes()),
+            None
+        );
+    }
+}
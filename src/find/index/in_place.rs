@@ -0,0 +1,662 @@
+use std::{
+    convert::TryInto,
+    fmt::Write as _,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use fst::{Map, MapBuilder, Streamer};
+use log::{info, warn};
+use memmap2::{MmapMut, MmapOptions};
+
+use crate::{
+    atomic::atomic_write,
+    find::{
+        index::{estimated_len, parse_line, ConvertError},
+        parse::PwnedHash,
+        prefix::PrefixFilter,
+        strip_carriage_return,
+    },
+    progress::{ProgressSink, ProgressUnit},
+    Sha1Hash,
+};
+
+/// Magic of indexes converted in place - text databases start with a hex character and `fst`
+/// indexes with their version
+pub const SHARDED_MAGIC: [u8; 8] = *b"PWNSHRD1";
+
+/// Magic while the conversion is running - it's replaced by the header once all shards are written,
+/// so an interrupted conversion isn't scanned as an empty text database
+pub const PARTIAL_MAGIC: [u8; 8] = *b"PWNPART1";
+
+/// One `fst` per first hex character of the hashes - each one is a restart point
+const SHARD_COUNT: usize = 16;
+
+/// Magic followed by the offset and length of each shard
+pub const HEADER_LEN: usize = SHARDED_MAGIC.len() + SHARD_COUNT * 16;
+
+/// Suffix of the file next to the output listing the completed shards
+const MANIFEST_SUFFIX: &str = ".manifest";
+
+/// Completed shard as recorded in the manifest
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Shard {
+    /// Position of the first line of the next shard in the text database
+    input_end: u64,
+    /// Lines before the input end for the line numbers of errors
+    lines: u64,
+    offset: u64,
+    len: u64,
+    hashes: u64,
+}
+
+/// Output file written through a memory map. The file is preallocated, so converting doesn't fail
+/// halfway because the disk filled up in the meantime, and it's grown if the estimate was too small.
+struct MmapWriter {
+    file: File,
+    /// Only none while the file is grown
+    map: Option<MmapMut>,
+    position: usize,
+}
+
+impl MmapWriter {
+    fn open(file: File, len: u64, position: usize) -> io::Result<Self> {
+        let mut writer = MmapWriter {
+            file,
+            map: None,
+            position,
+        };
+
+        writer.remap(len)?;
+        Ok(writer)
+    }
+
+    /// Resize the file and map it again - the old map is released first, because Windows cannot
+    /// resize mapped files
+    fn remap(&mut self, len: u64) -> io::Result<()> {
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+
+        preallocate(&self.file, len)?;
+
+        // the output is exclusively written by us - see the advisory lock in convert_in_place
+        self.map = Some(unsafe { MmapOptions::new().len(len as usize).map_mut(&self.file)? });
+        Ok(())
+    }
+
+    fn map(&mut self) -> &mut MmapMut {
+        // unwrap is safe, because the map is only taken during remap
+        self.map.as_mut().unwrap()
+    }
+
+    /// Persist the written range before it's recorded in the manifest
+    fn sync(&mut self, range: Range<usize>) -> io::Result<()> {
+        self.map().flush_range(range.start, range.len())
+    }
+
+    /// Cut the preallocated space after the data
+    fn finish(mut self) -> io::Result<()> {
+        let len = self.position as u64;
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+
+        self.file.set_len(len)?;
+        self.file.sync_all()
+    }
+}
+
+impl Write for MmapWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.position + buf.len();
+        let capacity = self.map().len();
+        if end > capacity {
+            // grow by half of the size to limit the number of remaps
+            self.remap(end.max(capacity + capacity / 2) as u64)?;
+        }
+
+        let position = self.position;
+        self.map()[position..end].copy_from_slice(buf);
+        self.position = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.map().flush()
+    }
+}
+
+/// Reserve the blocks instead of creating a sparse file
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // unsafe is safe, because the descriptor is valid while the file is borrowed
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len as libc::off_t) } {
+        0 => Ok(()),
+        // file systems without support for it
+        libc::EOPNOTSUPP | libc::EINVAL => file.set_len(len),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)?;
+    }
+
+    Ok(())
+}
+
+/// Convert the text database into a sharded index written directly into the output using a memory
+/// map. The output is preallocated with the estimated size instead of buffering it in a temporary
+/// copy. Each completed shard is recorded in the manifest, so a stopped conversion continues with
+/// the next shard. The header is written last - an unfinished output is never used as index.
+pub fn convert_in_place(
+    input: &Path,
+    output: &Path,
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+) -> Result<(u64, PrefixFilter), ConvertError> {
+    let input_file = File::open(input)?;
+    let input_metadata = input_file.metadata()?;
+    let input_len = input_metadata.len();
+    let input_modified = input_metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    let identity = format!(
+        "input_len={}\ninput_modified={}\n",
+        input_len, input_modified
+    );
+
+    let exists = output.exists();
+    let output_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(output)?;
+
+    // locked before the manifest is read, because a finished conversion deletes it. Scans of an
+    // existing index hold a shared lock, so it's never overwritten while it's read.
+    match output_file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Output is converted or scanned by another process",
+            )
+            .into());
+        }
+        Err(TryLockError::Error(err)) => warn!("Failed to lock the output {}", err),
+    }
+
+    let manifest_path = manifest_path(output);
+    let mut shards = match fs::read_to_string(&manifest_path) {
+        Ok(content) if exists => match parse_manifest(&content, &identity) {
+            Some(shards) => {
+                info!("Continuing the conversion after {} shards", shards.len());
+                shards
+            }
+            None => {
+                warn!("Text database changed since the last conversion - starting over");
+                Vec::new()
+            }
+        },
+        Ok(_) => Vec::new(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    if shards.is_empty() {
+        // a crash while writing it must not leave a manifest without the identity of the input
+        atomic_write(&manifest_path, |file| {
+            write!(file, "# pwned-check conversion\n{}", identity)
+        })?;
+    }
+
+    // it's grown if the estimate is too small
+    let estimate =
+        (HEADER_LEN as u64 + estimated_len(input_len)).max(output_file.metadata()?.len());
+    let start = shards
+        .last()
+        .map_or(HEADER_LEN, |shard| (shard.offset + shard.len) as usize);
+    let mut writer = MmapWriter::open(output_file, estimate, start)?;
+    if shards.is_empty() {
+        // an existing index must not be used while it's overwritten
+        writer.map()[..HEADER_LEN].fill(0);
+        writer.map()[..PARTIAL_MAGIC.len()].copy_from_slice(&PARTIAL_MAGIC);
+        writer.sync(0..HEADER_LEN)?;
+    }
+
+    let mut prefixes = PrefixFilter::default();
+    for shard in &shards {
+        let data = &writer.map()[shard.offset as usize..(shard.offset + shard.len) as usize];
+        insert_prefixes(data, &mut prefixes)?;
+    }
+
+    let input_start = shards.last().map_or(0, |shard| shard.input_end);
+    let mut reader = BufReader::new(input_file);
+    reader.seek(SeekFrom::Start(input_start))?;
+
+    progress.start(input_len, ProgressUnit::Bytes);
+    progress.advance(input_start);
+    let mut converter = ShardConverter {
+        manifest: OpenOptions::new().append(true).open(&manifest_path)?,
+        shards: &mut shards,
+        prefixes: &mut prefixes,
+        position: input_start,
+    };
+    converter.convert(reader, &mut writer, strict, progress)?;
+    progress.finish();
+
+    let mut header = [0; HEADER_LEN];
+    header[..SHARDED_MAGIC.len()].copy_from_slice(&SHARDED_MAGIC);
+    for (index, shard) in shards.iter().enumerate() {
+        let position = SHARDED_MAGIC.len() + index * 16;
+        header[position..position + 8].copy_from_slice(&shard.offset.to_le_bytes());
+        header[position + 8..position + 16].copy_from_slice(&shard.len.to_le_bytes());
+    }
+
+    writer.map()[..HEADER_LEN].copy_from_slice(&header);
+    writer.finish()?;
+    fs::remove_file(&manifest_path)?;
+
+    let hashes = shards.iter().map(|shard| shard.hashes).sum();
+    Ok((hashes, prefixes))
+}
+
+/// Shard that is currently built
+struct Building<'w> {
+    index: usize,
+    /// Position of the shard in the output
+    offset: usize,
+    hashes: u64,
+    builder: MapBuilder<&'w mut MmapWriter>,
+}
+
+struct ShardConverter<'a> {
+    manifest: File,
+    shards: &'a mut Vec<Shard>,
+    prefixes: &'a mut PrefixFilter,
+    /// Position in the text database
+    position: u64,
+}
+
+impl ShardConverter<'_> {
+    /// Build the remaining shards. Duplicated hashes are merged using the highest count like the
+    /// normal conversion.
+    fn convert(
+        &mut self,
+        mut reader: impl BufRead,
+        writer: &mut MmapWriter,
+        strict: bool,
+        progress: &mut dyn ProgressSink,
+    ) -> Result<(), ConvertError> {
+        let mut record = PwnedHash::new(strict);
+        let mut line_number = self.shards.last().map_or(0, |shard| shard.lines);
+        let mut line = Vec::new();
+
+        // the writer is moved between the builders, because only one shard is written at a time
+        let mut idle = Some(writer);
+        let mut building: Option<Building<'_>> = None;
+        let mut pending: Option<(Sha1Hash, u64)> = None;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+
+            let line_start = self.position;
+            self.position += read as u64;
+            line_number += 1;
+            progress.advance(read as u64);
+
+            let text = strip_carriage_return(line.strip_suffix(b"\n").unwrap_or(&line));
+            if text.is_empty() {
+                continue;
+            }
+
+            let (hash, count) = parse_line(&mut record, text)
+                .map_err(|err| ConvertError::Parse(line_number, err))?;
+
+            let index = usize::from(hash[0] >> 4);
+            if building.as_ref().map(|shard| shard.index) != Some(index) {
+                if index < self.shards.len() {
+                    return Err(ConvertError::Unsorted(line_number));
+                }
+
+                if let Some(shard) = building.take() {
+                    let shard = self.insert_pending(shard, &mut pending)?;
+                    idle = Some(self.finish(shard, line_start, line_number - 1)?);
+                }
+
+                // unwrap is safe, because the writer is idle between the shards
+                let writer =
+                    self.skip_to(index, idle.take().unwrap(), line_start, line_number - 1)?;
+                building = Some(Building {
+                    index,
+                    offset: writer.position,
+                    hashes: 0,
+                    builder: MapBuilder::new(writer)?,
+                });
+            }
+
+            match pending {
+                Some((previous, previous_count)) if previous == hash => {
+                    pending = Some((hash, previous_count.max(count)));
+                }
+                _ => {
+                    // unwrap is safe, because the shard was started above
+                    let shard = building.take().unwrap();
+                    building = Some(self.insert_pending(shard, &mut pending)?);
+                    pending = Some((hash, count));
+                }
+            }
+        }
+
+        if let Some(shard) = building.take() {
+            let shard = self.insert_pending(shard, &mut pending)?;
+            idle = Some(self.finish(shard, self.position, line_number)?);
+        }
+
+        // unwrap is safe, because all shards are finished
+        self.skip_to(
+            SHARD_COUNT,
+            idle.take().unwrap(),
+            self.position,
+            line_number,
+        )?;
+        Ok(())
+    }
+
+    fn insert_pending<'w>(
+        &mut self,
+        mut shard: Building<'w>,
+        pending: &mut Option<(Sha1Hash, u64)>,
+    ) -> Result<Building<'w>, ConvertError> {
+        if let Some((hash, count)) = pending.take() {
+            // out of order hashes are rejected by the builder
+            shard.builder.insert(hash, count)?;
+            self.prefixes.insert(&hash);
+            shard.hashes += 1;
+        }
+
+        Ok(shard)
+    }
+
+    /// Write the end of the shard and record it in the manifest
+    fn finish<'w>(
+        &mut self,
+        shard: Building<'w>,
+        input_end: u64,
+        lines: u64,
+    ) -> Result<&'w mut MmapWriter, ConvertError> {
+        let writer = shard.builder.into_inner()?;
+        writer.sync(shard.offset..writer.position)?;
+
+        let completed = Shard {
+            input_end,
+            lines,
+            offset: shard.offset as u64,
+            len: (writer.position - shard.offset) as u64,
+            hashes: shard.hashes,
+        };
+        writeln!(
+            self.manifest,
+            "input_end={} lines={} offset={} len={} hashes={}",
+            completed.input_end, completed.lines, completed.offset, completed.len, completed.hashes
+        )?;
+        self.manifest.sync_data()?;
+
+        self.shards.push(completed);
+        Ok(writer)
+    }
+
+    /// Write empty shards for the hex characters without any hash before the index
+    fn skip_to<'w>(
+        &mut self,
+        index: usize,
+        mut writer: &'w mut MmapWriter,
+        input_end: u64,
+        lines: u64,
+    ) -> Result<&'w mut MmapWriter, ConvertError> {
+        while self.shards.len() < index {
+            let shard = Building {
+                index: self.shards.len(),
+                offset: writer.position,
+                hashes: 0,
+                builder: MapBuilder::new(writer)?,
+            };
+            writer = self.finish(shard, input_end, lines)?;
+        }
+
+        Ok(writer)
+    }
+}
+
+/// Shards without any hash are empty indexes
+fn insert_prefixes(data: &[u8], prefixes: &mut PrefixFilter) -> Result<(), ConvertError> {
+    let map = Map::new(data)?;
+    let mut keys = map.keys();
+    while let Some(key) = keys.next() {
+        // unwrap is safe, because all keys are hashes
+        let hash: Sha1Hash = key.try_into().unwrap();
+        prefixes.insert(&hash);
+    }
+
+    Ok(())
+}
+
+fn manifest_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_os_string();
+    path.push(MANIFEST_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Completed shards - none if the manifest belongs to another version of the text database
+fn parse_manifest(content: &str, identity: &str) -> Option<Vec<Shard>> {
+    let mut lines = content.lines().filter(|line| !line.starts_with('#'));
+    let recorded: String = lines
+        .by_ref()
+        .take(2)
+        .fold(String::new(), |mut text, line| {
+            // unwrap is safe, because writing to a string never fails
+            writeln!(text, "{}", line).unwrap();
+            text
+        });
+    if recorded != identity {
+        return None;
+    }
+
+    let mut shards = Vec::new();
+    for line in lines {
+        let mut values = [0; 5];
+        for (value, field) in values.iter_mut().zip(line.split(' ')) {
+            *value = field.split_once('=')?.1.parse().ok()?;
+        }
+
+        let [input_end, lines, offset, len, hashes] = values;
+        shards.push(Shard {
+            input_end,
+            lines,
+            offset,
+            len,
+            hashes,
+        });
+    }
+
+    Some(shards)
+}
+
+/// Offset and length of each shard in the data - none if it's not a sharded index
+pub fn read_header(data: &[u8]) -> io::Result<Option<Vec<Range<usize>>>> {
+    if !data.starts_with(&SHARDED_MAGIC) {
+        return Ok(None);
+    }
+
+    let header = data
+        .get(..HEADER_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Truncated index header"))?;
+    let mut shards = Vec::with_capacity(SHARD_COUNT);
+    for entry in header[SHARDED_MAGIC.len()..].chunks_exact(16) {
+        // unwrap is safe, because the chunks have exactly 16 bytes
+        let offset = u64::from_le_bytes(entry[..8].try_into().unwrap()) as usize;
+        let len = u64::from_le_bytes(entry[8..].try_into().unwrap()) as usize;
+        if offset.checked_add(len).is_none_or(|end| end > data.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Index shard outside of the file",
+            ));
+        }
+
+        shards.push(offset..offset + len);
+    }
+
+    Ok(Some(shards))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{find::index, progress::RecordingProgress};
+
+    const HASH_A: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4";
+    const HASH_B: &str = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+    const HASH_C: &str = "F0000000A8DAE4228F821FB418F59826079BF368";
+
+    fn hash(hex: &str) -> Sha1Hash {
+        let mut hash = [0; 20];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        hash
+    }
+
+    fn paths(name: &str) -> (PathBuf, PathBuf) {
+        let base = env::temp_dir().join(format!("pwned-check-{}-{}", name, process::id()));
+        (base.with_extension("txt"), base.with_extension("idx"))
+    }
+
+    #[test]
+    fn test_convert_in_place() {
+        let (input, output) = paths("in-place");
+        fs::write(
+            &input,
+            format!("{}:4\r\n{}:9\n{}:9\n{}", HASH_A, HASH_B, HASH_B, HASH_C),
+        )
+        .unwrap();
+
+        // a previous index is overwritten
+        fs::write(&output, [1; 2 * HEADER_LEN]).unwrap();
+        let mut progress = RecordingProgress::default();
+        let (hashes, prefixes) = convert_in_place(&input, &output, false, &mut progress).unwrap();
+        assert_eq!(hashes, 3);
+        assert!(prefixes.contains(&hash(HASH_C)));
+        assert!(!manifest_path(&output).exists());
+
+        let data = fs::read(&output).unwrap();
+        let index = index::open(&data).unwrap().unwrap();
+        assert_eq!(index.hashes(), 3);
+        assert_eq!(index.get(&hash(HASH_A)), Some(4));
+        assert_eq!(index.get(&hash(HASH_B)), Some(9));
+        assert_eq!(index.get(&hash(HASH_C)), Some(index::UNKNOWN_COUNT));
+        assert_eq!(index.get(&[0x11; 20]), None);
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_continue() {
+        let (input, output) = paths("in-place-continue");
+        let valid = format!("{}:4\n{}:9\n{}:1\n", HASH_A, HASH_B, HASH_C);
+        let broken = valid.replace(HASH_C, &"X".repeat(40));
+        fs::write(&input, &broken).unwrap();
+        let modified = fs::metadata(&input).unwrap().modified().unwrap();
+
+        let mut progress = RecordingProgress::default();
+        let failed = convert_in_place(&input, &output, false, &mut progress);
+        assert!(matches!(failed, Err(ConvertError::Parse(3, _))));
+
+        // the interrupted index is rejected instead of being scanned as text
+        let data = fs::read(&output).unwrap();
+        assert!(index::open(&data).is_err());
+
+        // the shards before the one of the invalid line are completed
+        let manifest = fs::read_to_string(manifest_path(&output)).unwrap();
+        let identity: String = manifest
+            .lines()
+            .skip(1)
+            .take(2)
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let shards = parse_manifest(&manifest, &identity).unwrap();
+        assert_eq!(shards.len(), 5);
+        assert_eq!(shards[0].hashes, 1);
+        assert_eq!(shards[4].input_end, 41 + 2);
+
+        // same size and modification time like a download that was repaired in place
+        fs::write(&input, &valid).unwrap();
+        File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let (hashes, prefixes) = convert_in_place(&input, &output, false, &mut progress).unwrap();
+        assert_eq!(hashes, 3);
+        assert!(prefixes.contains(&hash(HASH_A)));
+
+        let data = fs::read(&output).unwrap();
+        let index = index::open(&data).unwrap().unwrap();
+        assert_eq!(index.get(&hash(HASH_B)), Some(9));
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_other_input() {
+        let content = "# pwned-check conversion\ninput_len=10\ninput_modified=5\ninput_end=3 lines=1 offset=264 len=20 hashes=1\n";
+        let shards = parse_manifest(content, "input_len=10\ninput_modified=5\n").unwrap();
+        assert_eq!(
+            shards,
+            [Shard {
+                input_end: 3,
+                lines: 1,
+                offset: 264,
+                len: 20,
+                hashes: 1
+            }]
+        );
+
+        assert_eq!(
+            parse_manifest(content, "input_len=11\ninput_modified=5\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_header_bounds() {
+        let mut data = vec![0; HEADER_LEN];
+        data[..SHARDED_MAGIC.len()].copy_from_slice(&SHARDED_MAGIC);
+        assert_eq!(read_header(&data).unwrap().unwrap().len(), SHARD_COUNT);
+
+        data[SHARDED_MAGIC.len() + 15] = 1;
+        assert!(read_header(&data).is_err());
+        assert!(read_header(&data[..20]).is_err());
+        assert_eq!(read_header(HASH_A.as_bytes()).unwrap(), None);
+    }
+}
@@ -0,0 +1,405 @@
+use std::{cmp::Ordering, convert::TryInto, io};
+
+use log::error;
+use memchr::{memchr, memrchr};
+
+use crate::{
+    cancel::{CancellationToken, Status},
+    collect::SavedHash,
+    find::{
+        pad_hash,
+        parse::{self, ParseHashError, PwnedHash},
+        simd::LaneWidth,
+        strip_carriage_return, Finding, HashPadded,
+    },
+    progress::{ProgressSink, ProgressUnit},
+    Sha1Hash,
+};
+
+/// Below this number of bytes the remaining range is scanned linearly, because it's only a few
+/// pages and the lines are then likely already in the cache
+const LOCAL_SCAN_WINDOW: usize = 4 * 1024;
+
+/// Search each saved hash by estimating its position in the hash database. The hashes of the
+/// database are uniformly distributed, so the position is close to `hash / 2^160 * file size`.
+/// The estimation is then repeated inside the narrowed range until the remaining range is small
+/// enough for a local scan.
+pub fn find_hash_interpolated(
+    data: &[u8],
+    hashes: &[SavedHash],
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> io::Result<Status<Vec<Finding>>> {
+    progress.start(hashes.len() as u64, ProgressUnit::Items);
+
+    let lanes = LaneWidth::detect();
+    let mut record = PwnedHash::new(strict);
+    let mut findings = Vec::new();
+
+    // saved hashes are sorted, so the previous position is a lower bound for the next one
+    let mut lower = Bound { offset: 0, key: 0 };
+    for (index, saved) in hashes.iter().enumerate() {
+        if cancel.is_cancelled() {
+            progress.finish();
+            return Ok(Status::Cancelled(findings));
+        }
+
+        let search = Search {
+            data,
+            lanes,
+            target: saved.padded(),
+        };
+
+        match search.locate(lower, &mut record) {
+            Ok((bound, found)) => {
+                lower = bound;
+                if let Some(count) = found {
+                    findings.push(Finding { index, count });
+                }
+            }
+            Err(err) => {
+                // the saved hashes after the line are undecided, so they can't be reported clean
+                progress.finish();
+                return Err(parse::invalid_line(err));
+            }
+        }
+
+        progress.advance(1);
+
+        // the remaining saved hashes are larger than the last line
+        if lower.offset >= data.len() {
+            progress.skip((hashes.len() - index - 1) as u64);
+            break;
+        }
+    }
+
+    progress.finish();
+    Ok(Status::Completed(findings))
+}
+
+/// Offset of the first line with a hash greater or equal to the given one (ex: the start of a
+/// range). Fails if a line couldn't be parsed.
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+pub fn lower_bound(data: &[u8], hash: &Sha1Hash, strict: bool) -> Result<usize, ParseHashError> {
+    let target = pad_hash(hash);
+    let search = Search {
+        data,
+        lanes: LaneWidth::detect(),
+        target: &target,
+    };
+
+    let start = Bound { offset: 0, key: 0 };
+    let mut record = PwnedHash::new(strict);
+    search
+        .locate(start, &mut record)
+        .map(|(bound, _)| bound.offset)
+}
+
+/// Line start with the interpolation key of the line hash
+#[derive(Clone, Copy, Debug)]
+struct Bound {
+    offset: usize,
+    key: u64,
+}
+
+struct Search<'a> {
+    data: &'a [u8],
+    lanes: LaneWidth,
+    target: &'a HashPadded,
+}
+
+type Located = (Bound, Option<Option<u64>>);
+
+impl Search<'_> {
+    /// Search the target starting from the given line start. Returns the new lower bound for the
+    /// next larger target and the count if the hash was found.
+    fn locate(&self, lower: Bound, record: &mut PwnedHash) -> Result<Located, ParseHashError> {
+        // Invariant: only lines starting in the range of lower..upper could contain the target
+        let mut lower = lower;
+        let mut upper = Bound {
+            offset: self.data.len(),
+            key: u64::MAX,
+        };
+
+        let target_key = interpolation_key(self.target);
+        while upper.offset - lower.offset > LOCAL_SCAN_WINDOW {
+            let probe = estimate(lower, upper, target_key);
+            let (start, end) = line_around(self.data, lower.offset, probe);
+            let line = strip_carriage_return(&self.data[start..end]);
+
+            match self.compare(line, record)? {
+                Ordering::Less => {
+                    lower = Bound {
+                        // the last line doesn't need a line break
+                        offset: (end + 1).min(self.data.len()),
+                        key: interpolation_key(&record.hash_padded),
+                    }
+                }
+                Ordering::Greater => {
+                    upper = Bound {
+                        offset: start,
+                        key: interpolation_key(&record.hash_padded),
+                    }
+                }
+                Ordering::Equal => {
+                    let count = parse_count(record, line);
+                    let bound = Bound {
+                        offset: start,
+                        key: target_key,
+                    };
+                    return Ok((bound, Some(count)));
+                }
+            }
+        }
+
+        self.scan_local(lower, upper, record)
+    }
+
+    fn scan_local(
+        &self,
+        lower: Bound,
+        upper: Bound,
+        record: &mut PwnedHash,
+    ) -> Result<Located, ParseHashError> {
+        let mut start = lower.offset;
+        while start < upper.offset {
+            let end = memchr(b'\n', &self.data[start..]).map_or(self.data.len(), |pos| start + pos);
+            let line = strip_carriage_return(&self.data[start..end]);
+
+            let order = self.compare(line, record)?;
+            let bound = Bound {
+                offset: start,
+                key: interpolation_key(&record.hash_padded),
+            };
+            match order {
+                Ordering::Less => start = end + 1,
+                Ordering::Greater => return Ok((bound, None)),
+                Ordering::Equal => return Ok((bound, Some(parse_count(record, line)))),
+            }
+        }
+
+        // all lines in the range are smaller - the next target could only be found afterwards
+        Ok((upper, None))
+    }
+
+    /// Compare the line hash against the target. The parsed line is kept in the given record.
+    fn compare(&self, line: &[u8], record: &mut PwnedHash) -> Result<Ordering, ParseHashError> {
+        record.parse_new_hash(line)?;
+        Ok(self.lanes.cmp(&record.hash_padded, self.target))
+    }
+}
+
+/// Estimate the byte offset of the target key inside the range
+fn estimate(lower: Bound, upper: Bound, target_key: u64) -> usize {
+    let range = (upper.offset - lower.offset) as u128;
+    let position = if upper.key > lower.key {
+        let distance = u128::from(target_key.saturating_sub(lower.key));
+        distance * range / u128::from(upper.key - lower.key)
+    } else {
+        // no spread between both bounds - fallback to binary search
+        range / 2
+    };
+
+    // probe needs to be inside the range otherwise we wouldn't make any progress
+    lower.offset + (position.min(range - 1) as usize)
+}
+
+/// Find the start and end offset of the line containing the given position without line break
+fn line_around(data: &[u8], lower: usize, position: usize) -> (usize, usize) {
+    let start = memrchr(b'\n', &data[lower..position]).map_or(lower, |pos| lower + pos + 1);
+    let end = memchr(b'\n', &data[position..]).map_or(data.len(), |pos| position + pos);
+    (start, end)
+}
+
+/// Use the first bytes of the hash as number for the position estimation
+fn interpolation_key(hash: &HashPadded) -> u64 {
+    // unwrap is safe, because the padded hash is always larger than 8 bytes
+    u64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+fn parse_count(record: &mut PwnedHash, line: &[u8]) -> Option<u64> {
+    match record.parse_count(line) {
+        Ok(count) => Some(*count),
+        Err(ParseHashError::MissingCount()) => None,
+        Err(err) => {
+            error!("Failed to parse count number - {:?}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use data_encoding::HEXUPPER;
+    use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+    use crate::{
+        find::{find_hash_in_memory, matcher::CompareSpace},
+        progress::RecordingProgress,
+        SHA1_BYTE_LENGTH,
+    };
+
+    use super::*;
+
+    fn scan_interpolated(data: &str, hashes: &[SavedHash]) -> Vec<Finding> {
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        find_hash_interpolated(data.as_bytes(), hashes, false, &mut progress, &cancel)
+            .unwrap()
+            .into_inner()
+    }
+
+    fn scan_linear(data: &str, hashes: &[SavedHash]) -> Vec<Finding> {
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        find_hash_in_memory(
+            data.as_bytes(),
+            hashes,
+            false,
+            CompareSpace::Decoded,
+            &mut progress,
+            &cancel,
+        )
+        .unwrap()
+        .into_inner()
+    }
+
+    /// Sorted corpus of uniform hashes with the line number as count
+    fn create_corpus(size: u32, line_ending: &str, trailing: bool) -> (String, Vec<SavedHash>) {
+        let mut hashes: Vec<[u8; SHA1_BYTE_LENGTH]> = (0..size)
+            .map(|x| {
+                digest(&SHA1_FOR_LEGACY_USE_ONLY, &x.to_be_bytes())
+                    .as_ref()
+                    .try_into()
+                    .unwrap()
+            })
+            .collect();
+        hashes.sort_unstable();
+
+        let lines: Vec<String> = hashes
+            .iter()
+            .enumerate()
+            .map(|(count, hash)| format!("{}:{}", HEXUPPER.encode(hash), count))
+            .collect();
+        let mut corpus = lines.join(line_ending);
+        if trailing {
+            corpus.push_str(line_ending);
+        }
+
+        let saved = hashes
+            .iter()
+            .map(|&hash| SavedHash::new("https://example.com/", "user", hash))
+            .collect();
+        (corpus, saved)
+    }
+
+    fn saved(prefix: &[u8]) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        hash[..prefix.len()].copy_from_slice(prefix);
+        SavedHash::new("https://example.com/", "user", hash)
+    }
+
+    fn copy(saved: &SavedHash) -> SavedHash {
+        SavedHash::new("https://example.com/", "user", saved.password_hash)
+    }
+
+    #[test]
+    fn test_all_found() {
+        for &(line_ending, trailing) in
+            &[("\n", true), ("\n", false), ("\r\n", true), ("\r\n", false)]
+        {
+            let (corpus, saved) = create_corpus(2_000, line_ending, trailing);
+            let findings = scan_interpolated(&corpus, &saved);
+
+            assert_eq!(findings.len(), saved.len(), "{:?}", line_ending);
+            for (line, finding) in findings.iter().enumerate() {
+                assert_eq!(finding.index, line);
+                assert_eq!(finding.count, Some(line as u64));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stops_after_last_line() {
+        let (corpus, corpus_saved) = create_corpus(100, "\n", true);
+        let mut hashes = vec![copy(&corpus_saved[0])];
+        hashes.extend((0..4).map(|_| saved(&[0xFF; SHA1_BYTE_LENGTH])));
+
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let status =
+            find_hash_interpolated(corpus.as_bytes(), &hashes, false, &mut progress, &cancel);
+        assert_eq!(status.unwrap().into_inner().len(), 1);
+
+        // only the first larger hash is searched
+        assert_eq!(progress.advanced, hashes.len() as u64);
+        assert_eq!(progress.skipped, 3);
+    }
+
+    #[test]
+    fn test_same_as_linear() {
+        let (corpus, corpus_saved) = create_corpus(5_000, "\n", false);
+
+        // boundaries of the file, the hash prefixes and some members and non members in between
+        let mut hashes = vec![
+            saved(&[]),
+            saved(&[0xFF; SHA1_BYTE_LENGTH]),
+            saved(&[0x7F, 0xFF, 0xFF, 0xFF]),
+            saved(&[0x80]),
+            saved(&[0x0F, 0xFF]),
+            saved(&[0x10]),
+        ];
+        hashes.extend(corpus_saved.into_iter().step_by(97));
+        hashes.sort_unstable();
+
+        let interpolated = scan_interpolated(&corpus, &hashes);
+        let linear = scan_linear(&corpus, &hashes);
+        assert_eq!(interpolated, linear);
+    }
+
+    #[test]
+    fn test_first_and_last_line() {
+        for &line_ending in &["\n", "\r\n"] {
+            for &trailing in &[true, false] {
+                let (corpus, saved) = create_corpus(1_000, line_ending, trailing);
+                let hashes = [copy(&saved[0]), copy(&saved[999])];
+
+                let findings = scan_interpolated(&corpus, &hashes);
+                let counts: Vec<_> = findings.iter().map(|finding| finding.count).collect();
+                assert_eq!(
+                    counts,
+                    [Some(0), Some(999)],
+                    "{:?} {}",
+                    line_ending,
+                    trailing
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_saved() {
+        let (corpus, saved) = create_corpus(1_000, "\n", true);
+        let hashes = [copy(&saved[500]), copy(&saved[500])];
+
+        let findings = scan_interpolated(&corpus, &hashes);
+        assert_eq!(findings.len(), 2);
+    }
+
+    #[test]
+    fn test_estimate_inside_range() {
+        let lower = Bound {
+            offset: 10,
+            key: 100,
+        };
+        let upper = Bound {
+            offset: 20,
+            key: 200,
+        };
+
+        assert_eq!(estimate(lower, upper, 0), 10);
+        assert_eq!(estimate(lower, upper, 150), 15);
+        assert_eq!(estimate(lower, upper, u64::MAX), 19);
+    }
+}
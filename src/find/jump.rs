@@ -0,0 +1,198 @@
+use memchr::memchr;
+
+/// Number of distinct prefixes - the first byte of the hash
+const PREFIXES: usize = 256;
+
+/// Below this number of bytes the remaining range is scanned linearly for the prefix start
+const LOCAL_SCAN_WINDOW: usize = 4 * 1024;
+
+/// Jumps are only searched if the prefix is estimated to start at least this far away - otherwise
+/// reading the few lines is cheaper than the random reads of the search
+pub const MIN_JUMP_DISTANCE: usize = 64 * 1024;
+
+/// Lazily built table of the line offsets where each prefix starts. It skips the lines between
+/// widely spaced saved hashes in a mapped hash database. There is no prebuilt index, so the start
+/// of each prefix (the first two hex characters) is searched the first time the scan needs it.
+/// Known starts narrow the later searches.
+pub struct PrefixTable {
+    /// offset of the first line with a prefix greater or equal to the index
+    starts: [Option<usize>; PREFIXES],
+    min_distance: usize,
+}
+
+impl PrefixTable {
+    pub fn new(min_distance: usize) -> Self {
+        PrefixTable {
+            starts: [None; PREFIXES],
+            min_distance,
+        }
+    }
+
+    /// Offset of the next line that could contain the prefix if it's worth to skip the lines
+    /// before it. The position has to be a line start.
+    pub fn jump(&mut self, data: &[u8], position: usize, prefix: u8) -> Option<usize> {
+        let hex = prefix_hex(prefix);
+
+        // the next line is already in the prefix or after it
+        let line_prefix = data.get(position..position + 2)?;
+        if line_prefix >= &hex[..] {
+            return None;
+        }
+
+        // the hashes are uniformly distributed, so the prefix starts close to its share of the data
+        let estimate = data.len() / PREFIXES * usize::from(prefix);
+        if self.starts[usize::from(prefix)].is_none()
+            && estimate < position.saturating_add(self.min_distance)
+        {
+            return None;
+        }
+
+        Some(self.start_of(data, position, prefix)).filter(|&start| start > position)
+    }
+
+    /// Offset of the first line at or after the position with a prefix greater or equal to the given
+    /// one
+    fn start_of(&mut self, data: &[u8], position: usize, prefix: u8) -> usize {
+        let index = usize::from(prefix);
+        if let Some(start) = self.starts[index] {
+            return start;
+        }
+
+        // the starts of the neighbouring prefixes are bounds of the search
+        let lower = self.starts[..index]
+            .iter()
+            .rev()
+            .find_map(|&start| start)
+            .map_or(position, |start| start.max(position));
+        let upper = self.starts[index + 1..]
+            .iter()
+            .find_map(|&start| start)
+            .unwrap_or(data.len());
+
+        let start = search(data, lower, upper.max(lower), &prefix_hex(prefix));
+        self.starts[index] = Some(start);
+        start
+    }
+}
+
+/// Binary search for the first line starting in lower..upper with a prefix greater or equal to the
+/// given one. Lower has to be a line start.
+fn search(data: &[u8], mut lower: usize, mut upper: usize, hex: &[u8; 2]) -> usize {
+    // Invariant: lines starting before lower have a smaller prefix and lines starting at or after
+    // upper a greater or equal one
+    while upper - lower > LOCAL_SCAN_WINDOW {
+        let middle = lower + (upper - lower) / 2;
+        let start = match memchr(b'\n', &data[middle..upper]) {
+            Some(pos) => middle + pos + 1,
+            None => upper,
+        };
+
+        if start >= upper {
+            // no line starts after the middle, so the prefix could only start before it
+            upper = middle + 1;
+        } else if is_before(data, start, hex) {
+            lower = start;
+        } else {
+            upper = start;
+        }
+    }
+
+    // the remaining range is likely only a page
+    let mut start = lower;
+    while start < upper && is_before(data, start, hex) {
+        start = memchr(b'\n', &data[start..upper]).map_or(upper, |pos| start + pos + 1);
+    }
+
+    start
+}
+
+/// Line at the start has a smaller prefix - empty trailing lines are treated as larger
+fn is_before(data: &[u8], start: usize, hex: &[u8; 2]) -> bool {
+    data.get(start..start + 2)
+        .is_some_and(|line| line < &hex[..])
+}
+
+/// Uppercase hex of the prefix, which has the same order as the prefix itself
+fn prefix_hex(prefix: u8) -> [u8; 2] {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    [
+        DIGITS[usize::from(prefix >> 4)],
+        DIGITS[usize::from(prefix & 0xF)],
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Lines with each prefix repeated the given number of times
+    fn corpus(repeat: usize) -> String {
+        let mut data = String::new();
+        for prefix in 0..=255u8 {
+            for line in 0..repeat {
+                data.push_str(&format!("{:02X}{:038X}:1\n", prefix, line));
+            }
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_prefix_hex() {
+        assert_eq!(&prefix_hex(0x00), b"00");
+        assert_eq!(&prefix_hex(0x9A), b"9A");
+        assert_eq!(&prefix_hex(0xFF), b"FF");
+    }
+
+    #[test]
+    fn test_search_starts() {
+        let data = corpus(50);
+        let line_length = data.find('\n').unwrap() + 1;
+
+        let mut table = PrefixTable::new(0);
+        // in a different order, so the known starts become bounds of the later searches
+        for &prefix in &[0x80, 0x10, 0xFF, 0x81, 0x7F, 0x01] {
+            let start = line_length * 50 * usize::from(prefix);
+            assert_eq!(table.jump(data.as_bytes(), 0, prefix), Some(start));
+            assert_eq!(&data[start..start + 2], format!("{:02X}", prefix));
+        }
+
+        // the first prefix is never skipped and the line is already in the prefix
+        assert_eq!(table.jump(data.as_bytes(), 0, 0x00), None);
+        let start = line_length * 50 * 0x10;
+        assert_eq!(table.jump(data.as_bytes(), start, 0x10), None);
+    }
+
+    #[test]
+    fn test_missing_prefix() {
+        // 0x42 is missing and starts with the next larger prefix
+        let data: String = corpus(20)
+            .lines()
+            .filter(|line| !line.starts_with("42"))
+            .map(|line| format!("{}\r\n", line))
+            .collect();
+
+        let mut table = PrefixTable::new(0);
+        let start = table.jump(data.as_bytes(), 0, 0x42).unwrap();
+        assert!(data[start..].starts_with("43"));
+
+        // no line has a larger or equal prefix, so everything is skipped
+        let data: String = corpus(20)
+            .lines()
+            .filter(|line| !line.starts_with("FF"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let mut table = PrefixTable::new(0);
+        assert_eq!(table.jump(data.as_bytes(), 0, 0xFF), Some(data.len()));
+    }
+
+    #[test]
+    fn test_min_distance() {
+        let data = corpus(5);
+        let mut table = PrefixTable::new(MIN_JUMP_DISTANCE);
+
+        // the whole data is smaller than the minimum distance
+        assert!(data.len() < MIN_JUMP_DISTANCE);
+        assert_eq!(table.jump(data.as_bytes(), 0, 0xF0), None);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{
+    collect::SavedHash,
+    find::{
+        hex::{self, HashHex},
+        matcher::CompareSpace,
+        HashPadded,
+    },
+};
+
+/// Scan-side layout of the sorted saved hashes as structure of arrays. The cursor comparisons only
+/// walk the contiguous padded hashes instead of the large saved hashes with their account
+/// metadata. The position in the arrays is the same as in the saved slice, so a decided hash is
+/// emitted by its position. This keeps the compared data dense in the cache and allows comparing
+/// multiple neighbouring saved hashes against a single line.
+pub struct ScanHashes {
+    /// padded hashes in the sorted order of the saved hashes
+    padded: Vec<HashPadded>,
+    /// uppercase hex of the saved hashes - only for comparisons in hex space
+    hex: Vec<HashHex>,
+}
+
+impl ScanHashes {
+    pub fn new(saved: &[SavedHash], space: CompareSpace) -> Self {
+        // This makes a copy of the hashes - However it's only 32 bytes per account compared to the
+        // hash database
+        let padded = saved.iter().map(|saved| *saved.padded()).collect();
+        let hex = match space {
+            CompareSpace::Decoded => Vec::new(),
+            // encoded once, so the lines don't need to be decoded
+            CompareSpace::Hex => saved
+                .iter()
+                .map(|saved| hex::encode_upper(&saved.password_hash))
+                .collect(),
+        };
+
+        ScanHashes { padded, hex }
+    }
+
+    pub fn len(&self) -> usize {
+        self.padded.len()
+    }
+
+    #[inline]
+    pub fn padded(&self, position: usize) -> &HashPadded {
+        &self.padded[position]
+    }
+
+    /// Uppercase hex of the hash - only available if created for the hex space
+    #[inline]
+    pub fn hex(&self, position: usize) -> &HashHex {
+        &self.hex[position]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SHA1_BYTE_LENGTH;
+
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let saved = [
+            SavedHash::from_hash([1; SHA1_BYTE_LENGTH]),
+            SavedHash::from_hash([2; SHA1_BYTE_LENGTH]),
+            SavedHash::from_hash([3; SHA1_BYTE_LENGTH]),
+        ];
+
+        let layout = ScanHashes::new(&saved, CompareSpace::Hex);
+        assert_eq!(layout.len(), 3);
+        assert_eq!(layout.padded(1), saved[1].padded());
+        assert_eq!(layout.hex(1), b"0202020202020202020202020202020202020202");
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    fs::{File, Metadata},
+    io,
+    time::SystemTime,
+};
+
+use log::error;
+
+use crate::find::source::HashSource;
+
+/// Shared advisory lock on the hash database while it's scanned. Cooperating writers request an
+/// exclusive lock, so they wait until the scan is finished. Other processes could still ignore it,
+/// therefore the file state is additionally compared after the scan.
+pub struct SharedLock<'a> {
+    file: &'a File,
+    locked: bool,
+}
+
+impl<'a> SharedLock<'a> {
+    /// Fails only if a writer holds the lock. File systems without lock support (ex: some network
+    /// shares) are only logged.
+    pub fn acquire(file: &'a File) -> Result<Self, io::Error> {
+        let locked = match try_lock_shared(file) {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "Hash file is currently locked by a writer",
+                ));
+            }
+            Err(err) => {
+                error!(
+                    "Failed to lock the hash database - \
+                    concurrent modifications are only detected after the scan {}",
+                    err
+                );
+                false
+            }
+        };
+
+        Ok(SharedLock { file, locked })
+    }
+}
+
+impl Drop for SharedLock<'_> {
+    fn drop(&mut self) {
+        if self.locked {
+            if let Err(err) = unlock(self.file) {
+                error!("Failed to unlock the hash database {}", err);
+            }
+        }
+    }
+}
+
+/// Size and modification time of the hash database to detect writes during the scan
+#[derive(Debug, PartialEq)]
+pub struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileState {
+    pub fn of(file: &File) -> Result<Self, io::Error> {
+        Ok(Self::of_metadata(&file.metadata()?))
+    }
+
+    pub fn of_metadata(metadata: &Metadata) -> Self {
+        FileState {
+            len: metadata.len(),
+            // not available on all platforms - then only the size is compared
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// State of a source without modification times
+    #[cfg(any(test, feature = "http"))]
+    pub fn of_len(len: u64) -> Self {
+        FileState {
+            len,
+            modified: None,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Fail if the source was changed after this state was taken
+    pub fn verify_unchanged(&self, source: &dyn HashSource) -> Result<(), io::Error> {
+        if source.state()? == *self {
+            Ok(())
+        } else {
+            Err(io::Error::other(
+                "Hash file changed during the scan - results are unreliable",
+            ))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn try_lock_shared(file: &File) -> Result<(), io::Error> {
+    flock(file, libc::LOCK_SH | libc::LOCK_NB)
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) -> Result<(), io::Error> {
+    flock(file, libc::LOCK_UN)
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: i32) -> Result<(), io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let ret = unsafe { libc::flock(file.as_raw_fd(), operation) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        // EWOULDBLOCK is mapped to ErrorKind::WouldBlock
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn try_lock_shared(file: &File) -> Result<(), io::Error> {
+    use std::os::windows::io::AsRawHandle;
+
+    use winapi::{
+        shared::winerror::ERROR_LOCK_VIOLATION,
+        um::{
+            fileapi::LockFileEx,
+            minwinbase::{LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED},
+        },
+    };
+
+    // Safety: zeroed overlapped structure means the lock starts at offset 0
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    // without LOCKFILE_EXCLUSIVE_LOCK it's a shared lock over the complete possible file range
+    let ret = unsafe {
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+
+    if ret != 0 {
+        Ok(())
+    } else {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+            Err(io::ErrorKind::WouldBlock.into())
+        } else {
+            Err(err)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn unlock(file: &File) -> Result<(), io::Error> {
+    use std::os::windows::io::AsRawHandle;
+
+    use winapi::um::fileapi::UnlockFile;
+
+    let ret = unsafe { UnlockFile(file.as_raw_handle() as _, 0, 0, u32::MAX, u32::MAX) };
+    if ret != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn try_lock_shared(_file: &File) -> Result<(), io::Error> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn unlock(_file: &File) -> Result<(), io::Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, io::Write, path::PathBuf};
+
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pwned-check-{}-{}", name, std::process::id()));
+        fs::write(&path, "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n").unwrap();
+        path
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_writer_lock() {
+        let path = temp_file("lock");
+        let reader = File::open(&path).unwrap();
+        let writer = File::open(&path).unwrap();
+
+        {
+            let _first = SharedLock::acquire(&reader).unwrap();
+            // multiple scans could run at the same time
+            let other_reader = File::open(&path).unwrap();
+            assert!(SharedLock::acquire(&other_reader).is_ok());
+
+            let exclusive = flock(&writer, libc::LOCK_EX | libc::LOCK_NB);
+            assert_matches!(exclusive, Err(err) if err.kind() == io::ErrorKind::WouldBlock);
+        }
+
+        // released by the drop
+        flock(&writer, libc::LOCK_EX | libc::LOCK_NB).unwrap();
+        assert_matches!(
+            SharedLock::acquire(&reader),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_detect_change() {
+        let path = temp_file("state");
+        let file = File::open(&path).unwrap();
+
+        let state = FileState::of(&file).unwrap();
+        assert!(state.verify_unchanged(&file).is_ok());
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"00000000A8DAE4228F821FB418F59826079BF368:7\n")
+            .unwrap();
+        assert!(state.verify_unchanged(&file).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
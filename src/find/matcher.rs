@@ -0,0 +1,434 @@
+use std::cmp::Ordering;
+
+use crate::{
+    collect::SavedHash,
+    find::{
+        hex,
+        layout::ScanHashes,
+        parse::{hash_hex_length, ParseHashError, PwnedHash},
+        simd::LaneWidth,
+        Finding,
+    },
+};
+
+/// Number of saved hashes that are skipped at once if the line is larger than all of them
+const BATCH_SIZE: usize = 4;
+
+/// Representation in which the lines are compared against the saved hashes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareSpace {
+    /// Decode the hex of each line and compare the bytes
+    Decoded,
+    /// Compare the hex of each line against the hex encoded saved hashes - the lines are only
+    /// validated
+    Hex,
+}
+
+/// Result of comparing hash database lines against the saved hashes
+#[derive(Debug, PartialEq)]
+pub enum MatchEvent {
+    /// Saved hash is in the hash database
+    Found(Finding),
+    /// Saved hash at this position is not in the hash database
+    NotFound(usize),
+    /// Count of the saved hash at this position is invalid - it's still found, but without count
+    InvalidCount(usize, ParseHashError),
+    /// Line is not a hash line - no further lines are compared
+    InvalidLine(ParseHashError),
+}
+
+/// Sorted linear comparison of the hash database lines against the sorted saved hashes. This only
+/// contains the comparison and never touches files, so reading, progress and logging are up to the
+/// caller.
+pub struct Matcher {
+    /// sorted saved hashes in a cache-friendly layout
+    saved: ScanHashes,
+    /// index of the current saved hash
+    current: usize,
+    lanes: LaneWidth,
+    space: CompareSpace,
+    /// re-use hash buffer to reduce the number of allocations
+    record: PwnedHash,
+    /// set if an invalid line aborted the comparison
+    aborted: bool,
+}
+
+impl Matcher {
+    pub fn new(hashes: &[SavedHash], strict: bool, lanes: LaneWidth, space: CompareSpace) -> Self {
+        Matcher {
+            saved: ScanHashes::new(hashes, space),
+            current: 0,
+            lanes,
+            space,
+            record: PwnedHash::new(strict),
+            aborted: false,
+        }
+    }
+
+    /// All saved hashes are processed or an invalid line aborted the comparison
+    pub fn is_finished(&self) -> bool {
+        self.aborted || self.current >= self.saved.len()
+    }
+
+    /// Number of saved hashes that are found or not found - in the sorted order
+    pub fn decided(&self) -> usize {
+        self.current
+    }
+
+    /// First byte of the next undecided saved hash - lines with a smaller first byte can't decide
+    /// anything
+    pub fn next_prefix(&self) -> Option<u8> {
+        if self.is_finished() {
+            None
+        } else {
+            Some(self.saved.padded(self.current)[0])
+        }
+    }
+
+    /// Compare the next line of the hash database and emit the events of the saved hashes it
+    /// decided. Returns false if no further lines are needed, because all saved hashes are
+    /// processed or the line is invalid.
+    #[inline(always)]
+    pub fn feed(&mut self, line: &[u8], mut emit: impl FnMut(MatchEvent)) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        let parsed = match self.space {
+            CompareSpace::Decoded => self.record.parse_new_hash(line),
+            CompareSpace::Hex => self.record.parse_new_hex(line),
+        };
+
+        if let Err(err) = parsed {
+            // abort because then there are probably more errors
+            self.aborted = true;
+            emit(MatchEvent::InvalidLine(err));
+            return false;
+        }
+
+        // This could also be improved further by re-using the internal eq/lt operations from
+        // simd, but it's good enough
+        let line_hex = &line[..hash_hex_length(line)];
+        while self.current < self.saved.len() {
+            let (orders, compared) = self.cmp_next(line_hex);
+            for &order in &orders[..compared] {
+                let current_index = self.current;
+                // loop through the list of hashes (stored passwords) until you find one that
+                // one that is larger (pwned < current)
+                match order {
+                    Ordering::Less => {
+                        // pwned < current
+                        // This means we need advance further in the hash database - reading the
+                        // next line
+                        return true;
+                    }
+                    Ordering::Equal => {
+                        // found an exact match
+                        let count = match self.record.parse_count(line) {
+                            Ok(count) => Some(*count),
+                            Err(ParseHashError::MissingCount()) => None,
+                            Err(err) => {
+                                emit(MatchEvent::InvalidCount(current_index, err.clone()));
+                                None
+                            }
+                        };
+
+                        emit(MatchEvent::Found(Finding {
+                            index: current_index,
+                            count,
+                        }));
+
+                        // Fetch the next stored password, in case the user has duplicate
+                        // passwords that could also match on the current line
+                        self.current += 1;
+                    }
+                    Ordering::Greater => {
+                        // pwned > current - This means current is not in the hash database
+                        // However the next saved password could - therefore advance further
+                        emit(MatchEvent::NotFound(current_index));
+                        self.current += 1;
+
+                        // In dense regions of large vaults the next saved hashes are likely
+                        // smaller too. Then a whole batch is decided by comparing only its last
+                        // hash. The order of the paired hash is outdated after a skip.
+                        if self.skip_batches(line_hex, &mut emit) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        // no saved hashes left
+        false
+    }
+
+    /// Advance over whole batches of saved hashes that are all smaller than the current line.
+    /// Returns if at least one batch was skipped.
+    #[inline(always)]
+    fn skip_batches(&mut self, line_hex: &[u8], emit: &mut impl FnMut(MatchEvent)) -> bool {
+        let start = self.current;
+        while self.current + BATCH_SIZE <= self.saved.len() {
+            // equal hashes are not skipped, because they need the count of the line
+            let last = self.current + BATCH_SIZE - 1;
+            if self.cmp_saved(line_hex, last) != Ordering::Greater {
+                break;
+            }
+
+            for index in self.current..self.current + BATCH_SIZE {
+                emit(MatchEvent::NotFound(index));
+            }
+
+            self.current += BATCH_SIZE;
+        }
+
+        self.current > start
+    }
+
+    /// Orders of the current line to the current and the following saved hash and how many of
+    /// them were compared. The following one is only compared if the lanes fit both hashes into a
+    /// single instruction (ex: AVX-512) - otherwise it would be wasted for most lines.
+    #[inline(always)]
+    fn cmp_next(&self, line_hex: &[u8]) -> ([Ordering; 2], usize) {
+        let next = self.current + 1;
+        if self.space == CompareSpace::Decoded && next < self.saved.len() {
+            let pair = [self.saved.padded(self.current), self.saved.padded(next)];
+            if let Some(orders) = self.lanes.cmp_pair(&self.record.hash_padded, pair) {
+                return (orders, 2);
+            }
+        }
+
+        let order = self.cmp_saved(line_hex, self.current);
+        ([order, Ordering::Equal], 1)
+    }
+
+    /// Order of the current line to the saved hash at the position
+    #[inline(always)]
+    fn cmp_saved(&self, line_hex: &[u8], position: usize) -> Ordering {
+        match self.space {
+            CompareSpace::Decoded => self
+                .lanes
+                .cmp(&self.record.hash_padded, self.saved.padded(position)),
+            CompareSpace::Hex => hex::cmp_upper(line_hex, self.saved.hex(position)),
+        }
+    }
+
+    /// End of the hash database - the remaining saved hashes are larger than all lines. Nothing is
+    /// emitted after an invalid line, because the remaining hashes are undecided.
+    pub fn finish(&mut self, mut emit: impl FnMut(MatchEvent)) {
+        if self.aborted {
+            return;
+        }
+
+        for index in self.current..self.saved.len() {
+            emit(MatchEvent::NotFound(index));
+        }
+
+        self.current = self.saved.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SHA1_BYTE_LENGTH;
+
+    use super::*;
+
+    /// Hash with the given first and last byte - the remaining bytes are zero
+    fn hash(first: u8, last: u8) -> [u8; SHA1_BYTE_LENGTH] {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        hash[0] = first;
+        hash[SHA1_BYTE_LENGTH - 1] = last;
+        hash
+    }
+
+    const SPACES: [CompareSpace; 2] = [CompareSpace::Decoded, CompareSpace::Hex];
+
+    fn line(hash: &[u8], count: &str) -> Vec<u8> {
+        format!("{}{}", data_encoding::HEXUPPER.encode(hash), count).into_bytes()
+    }
+
+    fn run(matcher: &mut Matcher, lines: &[Vec<u8>]) -> Vec<MatchEvent> {
+        let mut events = Vec::new();
+        for line in lines {
+            if !matcher.feed(line, |event| events.push(event)) {
+                break;
+            }
+        }
+
+        matcher.finish(|event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn test_events() {
+        let hashes = [
+            SavedHash::from_hash(hash(1, 0)),
+            SavedHash::from_hash(hash(2, 0)),
+            SavedHash::from_hash(hash(2, 0)),
+            SavedHash::from_hash(hash(4, 0)),
+            SavedHash::from_hash(hash(9, 0)),
+        ];
+        let lines = [
+            line(&hash(2, 0), ":3"),
+            line(&hash(3, 0), ":1"),
+            line(&hash(4, 0), ":x"),
+        ];
+
+        for &space in &SPACES {
+            let mut matcher = Matcher::new(&hashes, false, LaneWidth::detect(), space);
+            let events = run(&mut matcher, &lines);
+            assert_matches!(
+                events.as_slice(),
+                [
+                    MatchEvent::NotFound(0),
+                    MatchEvent::Found(Finding {
+                        index: 1,
+                        count: Some(3)
+                    }),
+                    MatchEvent::Found(Finding {
+                        index: 2,
+                        count: Some(3)
+                    }),
+                    MatchEvent::InvalidCount(3, ParseHashError::IntError(_)),
+                    MatchEvent::Found(Finding {
+                        index: 3,
+                        count: None
+                    }),
+                    MatchEvent::NotFound(4),
+                ]
+            );
+            assert!(matcher.is_finished());
+            assert!(!matcher.feed(&lines[0], |_| panic!("finished matcher emitted")));
+        }
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let hashes = [
+            SavedHash::from_hash(hash(1, 0)),
+            SavedHash::from_hash(hash(3, 0)),
+        ];
+        let lines = [
+            line(&hash(2, 0), ""),
+            format!("{}:1", "Z".repeat(40)).into_bytes(),
+        ];
+
+        for &space in &SPACES {
+            // strict mode requires the count
+            let mut strict = Matcher::new(&hashes, true, LaneWidth::detect(), space);
+            let events = run(&mut strict, &lines);
+            assert_matches!(
+                events.as_slice(),
+                [MatchEvent::InvalidLine(ParseHashError::MissingCount())]
+            );
+
+            let mut lenient = Matcher::new(&hashes, false, LaneWidth::detect(), space);
+            let events = run(&mut lenient, &lines);
+            assert_matches!(
+                events.as_slice(),
+                [
+                    MatchEvent::NotFound(0),
+                    MatchEvent::InvalidLine(ParseHashError::InvalidFormat())
+                ]
+            );
+            assert!(lenient.is_finished());
+        }
+    }
+
+    #[test]
+    fn test_skip_dense() {
+        // more saved hashes than a batch between the lines - with a duplicate of the second line
+        let hashes: Vec<_> = [1, 2, 3, 4, 5, 6, 7, 8, 8, 9]
+            .iter()
+            .map(|&first| SavedHash::from_hash(hash(first, 0)))
+            .collect();
+        let lines = [line(&hash(1, 0), ":2"), line(&hash(8, 0), ":5")];
+
+        let found = |index| {
+            MatchEvent::Found(Finding {
+                index,
+                count: Some(if index == 0 { 2 } else { 5 }),
+            })
+        };
+        let mut expected = vec![found(0)];
+        expected.extend((1..7).map(MatchEvent::NotFound));
+        expected.extend(vec![found(7), found(8), MatchEvent::NotFound(9)]);
+
+        for &space in &SPACES {
+            for &lanes in &LaneWidth::ALL {
+                let mut matcher = Matcher::new(&hashes, false, lanes, space);
+                let events = run(&mut matcher, &lines);
+                assert_eq!(events, expected, "{:?} {:?}", space, lanes);
+            }
+        }
+    }
+
+    /// Compare every combination of saved hashes and database lines out of a small set of hashes
+    /// against a plain lookup. The hashes only differ in the last byte to exercise the comparison of
+    /// the whole width.
+    #[test]
+    fn test_exhaustive() {
+        let candidates: Vec<_> = (0..5).map(|last| hash(0xAB, last)).collect();
+        for &space in &SPACES {
+            for &lanes in &LaneWidth::ALL {
+                exhaustive_space(&candidates, space, lanes);
+            }
+        }
+    }
+
+    fn exhaustive_space(
+        candidates: &[[u8; SHA1_BYTE_LENGTH]],
+        space: CompareSpace,
+        lanes: LaneWidth,
+    ) {
+        for database_set in 0..1u32 << candidates.len() {
+            let database: Vec<_> = (0..candidates.len())
+                .filter(|bit| database_set & (1 << bit) != 0)
+                .map(|bit| candidates[bit])
+                .collect();
+            let lines: Vec<_> = database
+                .iter()
+                .map(|hash| line(hash, &format!(":{}", hash[SHA1_BYTE_LENGTH - 1])))
+                .collect();
+
+            // saved hashes as multiset - each candidate up to twice
+            for saved_set in 0..3u32.pow(candidates.len() as u32) {
+                let mut saved = Vec::new();
+                let mut remaining = saved_set;
+                for candidate in candidates {
+                    for _ in 0..remaining % 3 {
+                        saved.push(SavedHash::from_hash(*candidate));
+                    }
+                    remaining /= 3;
+                }
+
+                let mut matcher = Matcher::new(&saved, true, lanes, space);
+                let events = run(&mut matcher, &lines);
+
+                let expected: Vec<_> = saved
+                    .iter()
+                    .enumerate()
+                    .map(|(index, saved)| {
+                        let hash = saved.password_hash;
+                        if database.contains(&hash) {
+                            MatchEvent::Found(Finding {
+                                index,
+                                count: Some(u64::from(hash[SHA1_BYTE_LENGTH - 1])),
+                            })
+                        } else {
+                            MatchEvent::NotFound(index)
+                        }
+                    })
+                    .collect();
+
+                assert_eq!(
+                    events, expected,
+                    "{:?} {:?} {:?} {:?}",
+                    database, saved_set, space, lanes
+                );
+                assert!(matcher.is_finished());
+            }
+        }
+    }
+}
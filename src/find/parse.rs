@@ -1,20 +1,41 @@
-use std::{convert::TryFrom, error::Error, fmt, num::ParseIntError};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt, io,
+    num::{IntErrorKind, ParseIntError},
+};
 
-use data_encoding::HEXUPPER;
+use log::warn;
 
 use crate::{
     find::{
-        parse::ParseHashError::{IntError, InvalidFormat},
+        hex::{self, HASH_HEX_LENGTH},
+        parse::ParseHashError::{IntError, InvalidFormat, MissingCount},
         HashPadded,
     },
-    SHA1_BYTE_LENGTH,
+    NTLM_BYTE_LENGTH, SHA1_BYTE_LENGTH,
 };
 
 #[derive(Debug, Default)]
 pub struct PwnedHash {
     pub hash_padded: HashPadded,
     // lazy load, because we only need it on an equal hit
-    pub count: Option<Result<u32, ParseHashError>>,
+    pub count: Option<Result<u64, ParseHashError>>,
+    /// Require exactly `HASH:count` - otherwise a missing count and extra fields are accepted
+    pub strict: bool,
+}
+
+/// Number of hex characters of an NTLM hash
+const NTLM_HEX_LENGTH: usize = NTLM_BYTE_LENGTH * 2;
+
+/// Length of the hash at the start of the line. NTLM lists (ex: HIBP NTLM) have shorter hashes,
+/// which are parsed zero padded to the SHA-1 length, so the searches handle both the same way.
+pub fn hash_hex_length(line: &[u8]) -> usize {
+    match line.get(NTLM_HEX_LENGTH) {
+        None if line.len() == NTLM_HEX_LENGTH => NTLM_HEX_LENGTH,
+        Some(b':') => NTLM_HEX_LENGTH,
+        _ => HASH_HEX_LENGTH,
+    }
 }
 
 impl TryFrom<&[u8]> for PwnedHash {
@@ -35,31 +56,79 @@ impl PwnedHash {
         &self.hash_padded[0..SHA1_BYTE_LENGTH]
     }
 
-    pub fn parse_new_hash(&mut self, line: &[u8]) -> Result<(), ParseHashError> {
-        assert_eq!(&[line[40]], b":");
+    pub fn new(strict: bool) -> Self {
+        PwnedHash {
+            strict,
+            ..PwnedHash::default()
+        }
+    }
 
-        let hash_part = &line[..40];
-        let len = HEXUPPER
-            // panics when our padded array is larger
-            .decode_mut(hash_part, &mut self.hash_padded[..SHA1_BYTE_LENGTH])
-            .map_err(|_| InvalidFormat())?;
-        // verify that the length is not less or higher
-        assert_eq!(len, SHA1_BYTE_LENGTH);
+    pub fn parse_new_hash(&mut self, line: &[u8]) -> Result<(), ParseHashError> {
+        let hex_length = self.check_separator(line)?;
+        let hash_part = &line[..hex_length];
+        let byte_length = hex_length / 2;
+        if !hex::decode_upper(hash_part, &mut self.hash_padded[..byte_length]) {
+            return Err(InvalidFormat());
+        }
+        self.hash_padded[byte_length..SHA1_BYTE_LENGTH].fill(0);
 
         // reset count number if did before
         self.count = None;
         Ok(())
     }
 
-    pub fn parse_count(&mut self, line: &[u8]) -> &Result<u32, ParseHashError> {
+    /// Validate the hash of the line without decoding it - for comparisons in hex space. The
+    /// decoded hash stays untouched.
+    pub fn parse_new_hex(&mut self, line: &[u8]) -> Result<(), ParseHashError> {
+        let hex_length = self.check_separator(line)?;
+        if !hex::is_upper(&line[..hex_length]) {
+            return Err(InvalidFormat());
+        }
+
+        self.count = None;
+        Ok(())
+    }
+
+    /// Length of the hash in the line if the count separator is valid
+    fn check_separator(&self, line: &[u8]) -> Result<usize, ParseHashError> {
+        let hex_length = hash_hex_length(line);
+        if line.len() < hex_length {
+            return Err(InvalidFormat());
+        }
+
+        if line.len() == hex_length {
+            // some corpora only list the hashes without counts
+            if self.strict {
+                return Err(MissingCount());
+            }
+        } else if line[hex_length] != b':' {
+            return Err(InvalidFormat());
+        }
+
+        Ok(hex_length)
+    }
+
+    pub fn parse_count(&mut self, line: &[u8]) -> &Result<u64, ParseHashError> {
         // this has the performance penalty of converting to UTF-8 instead of using ASCII bytes
         // directly. However we likely don't call this method often, so it's negligible
         // otherwise we could use the atoi crate
-        let count_part = &line[41..];
-        let res = std::str::from_utf8(&count_part)
-            .map_err(|_| InvalidFormat())
-            // use Ok(..?) to make use of the automatic error convert instead of map_err
-            .and_then(|s| Ok(s.parse()?));
+        let hex_length = hash_hex_length(line);
+        let res = if line.len() <= hex_length {
+            Err(MissingCount())
+        } else {
+            let mut count_part = &line[hex_length + 1..];
+            if !self.strict {
+                // ignore additional fields after the count
+                count_part = count_part
+                    .split(|&b| b == b':')
+                    .next()
+                    .unwrap_or(count_part);
+            }
+
+            std::str::from_utf8(&count_part)
+                .map_err(|_| InvalidFormat())
+                .and_then(parse_saturating)
+        };
 
         // unwrap is safe here, because just saved the data with some
         self.count = Some(res);
@@ -67,10 +136,25 @@ impl PwnedHash {
     }
 }
 
-#[derive(Debug)]
+/// Parse the count number. A count that is too large for u64 is still a valid number, so it's
+/// capped to the maximum instead of failing the match.
+fn parse_saturating(count: &str) -> Result<u64, ParseHashError> {
+    match count.parse() {
+        Ok(count) => Ok(count),
+        Err(err) if *err.kind() == IntErrorKind::PosOverflow => {
+            warn!("Count number {} is too large - using {}", count, u64::MAX);
+            Ok(u64::MAX)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ParseHashError {
     IntError(ParseIntError),
     InvalidFormat(),
+    /// Line contains only the hash
+    MissingCount(),
 }
 
 impl fmt::Display for ParseHashError {
@@ -88,6 +172,15 @@ impl Error for ParseHashError {
     }
 }
 
+/// Error of a line that isn't a hash line. It fails the scan, because the saved hashes after it
+/// would otherwise be reported as clean.
+pub fn invalid_line(err: ParseHashError) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid line in the hash file {:?}", err),
+    )
+}
+
 // automatically convert ParseIntError in our custom enum type IntError
 impl From<ParseIntError> for ParseHashError {
     fn from(e: ParseIntError) -> Self {
@@ -223,11 +316,13 @@ mod test {
     }
 
     #[test]
-    #[should_panic]
-    #[allow(unused_must_use)]
     fn test_parse_invalid_format() {
         // no ':'
-        PwnedHash::try_from("000000005AD76BD555C1D6D771DE417A4B87E4B4514141".as_bytes());
+        let res = PwnedHash::try_from("000000005AD76BD555C1D6D771DE417A4B87E4B4514141".as_bytes());
+        assert_matches!(res, Err(InvalidFormat()));
+
+        let res = PwnedHash::try_from("not a hash line".as_bytes());
+        assert_matches!(res, Err(InvalidFormat()));
     }
 
     #[test]
@@ -243,6 +338,7 @@ mod test {
         let mut record: PwnedHash = PwnedHash {
             hash_padded: [0; SIMD_WIDTH],
             count: Some(Ok(2)),
+            strict: false,
         };
 
         let bytes_line = TEST_LINE.as_bytes();
@@ -258,6 +354,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_ntlm() {
+        let record: PwnedHash = "32ED87BDB5FDC5E9CBA88547376818D4:23"
+            .as_bytes()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            HEXUPPER.encode(record.hash()),
+            "32ED87BDB5FDC5E9CBA88547376818D400000000"
+        );
+        assert_matches!(record.count.unwrap(), Ok(23));
+
+        let mut record = PwnedHash::new(true);
+        let line = "32ED87BDB5FDC5E9CBA88547376818D4".as_bytes();
+        assert_matches!(record.parse_new_hash(line), Err(MissingCount()));
+    }
+
     #[test]
     fn test_number_parse_error() {
         let bytes_line = INVALID_INT.as_bytes();
@@ -275,6 +388,46 @@ mod test {
         assert_matches!(res, Err(IntError(_)));
     }
 
+    #[test]
+    fn test_large_count() {
+        let bytes_line = "000000005AD76BD555C1D6D771DE417A4B87E4B4:5000000000".as_bytes();
+        let record: PwnedHash = bytes_line.try_into().unwrap();
+        assert_matches!(record.count.unwrap(), Ok(5_000_000_000));
+    }
+
+    #[test]
+    fn test_count_overflow_saturates() {
+        let bytes_line =
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:99999999999999999999999".as_bytes();
+        let record: PwnedHash = bytes_line.try_into().unwrap();
+        assert_matches!(record.count.unwrap(), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn test_missing_count() {
+        let bytes_line = "000000005AD76BD555C1D6D771DE417A4B87E4B4".as_bytes();
+        let record: PwnedHash = bytes_line.try_into().unwrap();
+        assert_eq!(
+            HEXUPPER.encode(record.hash()),
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4"
+        );
+        assert_matches!(record.count.unwrap(), Err(MissingCount()));
+
+        let mut strict = PwnedHash::new(true);
+        assert_matches!(strict.parse_new_hash(bytes_line), Err(MissingCount()));
+    }
+
+    #[test]
+    fn test_extra_fields() {
+        let bytes_line = "000000005AD76BD555C1D6D771DE417A4B87E4B4:4:2020-01-01".as_bytes();
+        let record: PwnedHash = bytes_line.try_into().unwrap();
+        assert_matches!(record.count.unwrap(), Ok(4));
+
+        let mut strict = PwnedHash::new(true);
+        strict.parse_new_hash(bytes_line).unwrap();
+        assert_matches!(strict.parse_count(bytes_line), Err(IntError(_)));
+    }
+
     #[test]
     fn test_invalid_utf() {
         let mut v = vec![];
@@ -0,0 +1,433 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    str::FromStr,
+};
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+use log::{debug, warn};
+use memchr::memchr;
+
+use crate::{
+    cancel::CancellationToken,
+    collect::SavedHash,
+    find::{
+        self, interpolation,
+        parse::{self, ParseHashError, PwnedHash},
+        source::{HashSource, MapOptions},
+        strip_carriage_return, Finding, ScanOptions, Strategy,
+    },
+    progress::SilentProgress,
+    Sha1Hash, SHA1_BYTE_LENGTH,
+};
+
+/// Range of the HIBP API - the first 5 hex characters (20 bits) of the hashes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangePrefix(u32);
+
+impl RangePrefix {
+    /// Number of hex characters
+    pub const LEN: usize = 5;
+
+    /// Range of the hash - only the first 3 bytes are used
+    pub fn of(hash: &[u8]) -> Self {
+        RangePrefix(
+            (u32::from(hash[0]) << 12) | (u32::from(hash[1]) << 4) | (u32::from(hash[2]) >> 4),
+        )
+    }
+
+    pub fn value(self) -> u32 {
+        self.0
+    }
+
+    /// Smallest hash of the range
+    #[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+    fn lowest(self) -> Sha1Hash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        hash[0] = (self.0 >> 12) as u8;
+        hash[1] = (self.0 >> 4) as u8;
+        hash[2] = (self.0 << 4) as u8;
+        hash
+    }
+
+    /// Largest hash of the range
+    #[cfg(feature = "index")]
+    fn highest(self) -> Sha1Hash {
+        let mut hash = [0xFF; SHA1_BYTE_LENGTH];
+        hash[..3].copy_from_slice(&self.lowest()[..3]);
+        hash[2] |= 0x0F;
+        hash
+    }
+
+    #[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+    fn contains(self, hash: &[u8]) -> bool {
+        RangePrefix::of(hash) == self
+    }
+}
+
+impl FromStr for RangePrefix {
+    type Err = String;
+
+    /// Case insensitive like the HIBP API
+    fn from_str(prefix: &str) -> Result<Self, Self::Err> {
+        if prefix.len() != Self::LEN || !prefix.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!(
+                "{} is not a prefix of {} hex characters",
+                prefix,
+                Self::LEN
+            ));
+        }
+
+        // unwrap is safe, because it only contains hex characters
+        Ok(RangePrefix(u32::from_str_radix(prefix, 16).unwrap()))
+    }
+}
+
+impl Display for RangePrefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:05X}", self.0)
+    }
+}
+
+/// Hash of the database with its number of occurrences
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+pub struct RangeEntry {
+    pub hash: Sha1Hash,
+    /// None if the database has no count for it
+    pub count: Option<u64>,
+}
+
+/// Full hash as sent by the clients of the long running modes - case insensitive
+pub fn parse_sha1(hex: &str) -> Option<Sha1Hash> {
+    if hex.len() != SHA1_BYTE_LENGTH * 2 {
+        return None;
+    }
+
+    let mut hash = [0; SHA1_BYTE_LENGTH];
+    HEXLOWER_PERMISSIVE
+        .decode_mut(hex.as_bytes(), &mut hash)
+        .ok()?;
+    Some(hash)
+}
+
+/// Look up a single hash for the long running modes. A single hash always selects a point lookup
+/// (index, interpolation or range file) instead of scanning the database.
+pub fn lookup_hash(
+    source: &(dyn HashSource + Sync),
+    hash: Sha1Hash,
+    strict: bool,
+) -> io::Result<Option<Finding>> {
+    Ok(lookup_hashes(source, &[hash], strict)?.pop().flatten())
+}
+
+/// Finding of each hash in the given order - the index of a finding is the position in `hashes`
+pub fn lookup_hashes(
+    source: &(dyn HashSource + Sync),
+    hashes: &[Sha1Hash],
+    strict: bool,
+) -> io::Result<Vec<Option<Finding>>> {
+    // searched like the saved passwords in sorted order, but without duplicates
+    let mut sorted = hashes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let saved: Vec<_> = sorted.iter().copied().map(SavedHash::from_hash).collect();
+    let options = ScanOptions {
+        strategy: Strategy::Auto,
+        strict,
+        mapping: MapOptions::default(),
+        // a few lookups are over before the first check, so no thread per request
+        watch: false,
+    };
+
+    let findings = find::find_hash(
+        source,
+        &saved,
+        options,
+        &mut SilentProgress,
+        &CancellationToken::default(),
+    )?;
+
+    let mut counts = vec![None; sorted.len()];
+    for finding in findings.into_inner() {
+        counts[finding.index] = Some(finding.count);
+    }
+
+    Ok(hashes
+        .iter()
+        .enumerate()
+        .map(|(index, hash)| {
+            // unwrap is safe, because each hash is in the sorted list
+            let position = sorted.binary_search(hash).unwrap();
+            counts[position].map(|count| Finding { index, count })
+        })
+        .collect())
+}
+
+/// Load the local corpus into the page cache before the first lookup of the long running modes, so
+/// even the first lookups don't wait for the disk. `touch` waits until each page is loaded -
+/// otherwise the OS loads them in the background. A populated mapping is already loaded by the
+/// kernel. Returns the number of warmed bytes - none if the corpus isn't mapped (ex: range
+/// directories or remote lists).
+pub fn warm_up(
+    source: &(dyn HashSource + Sync),
+    mapping: MapOptions,
+    touch: bool,
+) -> io::Result<Option<u64>> {
+    if source.ranges().is_some() {
+        return Ok(None);
+    }
+
+    let _lock = source.lock_shared()?;
+    let map = match source.map_with(mapping) {
+        Ok(map) => map,
+        Err(err) => {
+            debug!("Skipping the warm-up of an unmapped corpus {}", err);
+            return Ok(None);
+        }
+    };
+
+    #[cfg(unix)]
+    if let find::source::Mapped::File(map) = &map {
+        // Safety: unsafe cast to mutable - however madvise seems to not change any data
+        let ptr = map.as_ptr() as *mut u8;
+        if let Err(err) =
+            find::advise::madvise(ptr, map.len(), find::advise::MemoryAdvice::WillNeed)
+        {
+            warn!("Failed to advise OS about the warm-up {}", err);
+        }
+
+        if touch && !mapping.populate {
+            let pages = find::advise::touch_pages(map);
+            debug!("Touched {} pages", pages);
+        }
+    }
+
+    Ok(Some(map.len() as u64))
+}
+
+/// All hashes of the range sorted by hash - the same result as the HIBP range API. Only local
+/// databases are supported, because the range is found by searching the mapped file.
+// only queried by the serve command, which requires ring
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+pub fn find_range(
+    source: &(dyn HashSource + Sync),
+    prefix: RangePrefix,
+    strict: bool,
+) -> io::Result<Vec<RangeEntry>> {
+    if let Some(ranges) = source.ranges() {
+        let lines = ranges.read_range(prefix.value())?;
+        return collect_range(&lines, prefix, strict);
+    }
+
+    let _lock = source.lock_shared()?;
+    let map = source.map().map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Range queries require a local hash file {}", err),
+        )
+    })?;
+
+    #[cfg(feature = "index")]
+    if let Some(index) = find::index::open(&map)? {
+        return Ok(index
+            .range(&prefix.lowest(), &prefix.highest())
+            .into_iter()
+            .map(|(hash, count)| RangeEntry { hash, count })
+            .collect());
+    }
+
+    let start =
+        interpolation::lower_bound(&map, &prefix.lowest(), strict).map_err(parse::invalid_line)?;
+    collect_range(&map[start..], prefix, strict)
+}
+
+/// Parse the lines until the first hash after the range
+#[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+fn collect_range(data: &[u8], prefix: RangePrefix, strict: bool) -> io::Result<Vec<RangeEntry>> {
+    let mut record = PwnedHash::new(strict);
+    let mut entries = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = memchr(b'\n', &data[start..]).map_or(data.len(), |pos| start + pos);
+        let line = strip_carriage_return(&data[start..end]);
+        start = end + 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        record.parse_new_hash(line).map_err(parse::invalid_line)?;
+        if !prefix.contains(&record.hash_padded) {
+            break;
+        }
+
+        let count = match record.parse_count(line) {
+            Ok(count) => Some(*count),
+            Err(ParseHashError::MissingCount()) => None,
+            Err(err) => return Err(parse::invalid_line(err.clone())),
+        };
+
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        hash.copy_from_slice(&record.hash_padded[..SHA1_BYTE_LENGTH]);
+        entries.push(RangeEntry { hash, count });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, process};
+
+    use super::*;
+
+    fn hash(hex: &str) -> Sha1Hash {
+        parse_sha1(hex).unwrap()
+    }
+
+    const CORPUS: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+                          5BAA60000000000000000000000000000000000A:1\r\n\
+                          5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n\
+                          5BAA6FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:2\r\n\
+                          5BAA70000000000000000000000000000000000B:7\r\n";
+
+    #[test]
+    fn test_prefix() {
+        let prefix: RangePrefix = "5baa6".parse().unwrap();
+        assert_eq!(prefix.to_string(), "5BAA6");
+        assert_eq!(RangePrefix::of(&prefix.lowest()), prefix);
+        assert!(prefix.contains(&hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8")));
+        assert!(!prefix.contains(&hash("5BAA70000000000000000000000000000000000B")));
+
+        assert!("5BAA".parse::<RangePrefix>().is_err());
+        assert!("5BAAG".parse::<RangePrefix>().is_err());
+        assert!("+BAA6".parse::<RangePrefix>().is_err());
+
+        assert_eq!(
+            parse_sha1("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"),
+            Some(hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"))
+        );
+        assert_eq!(parse_sha1("5BAA61"), None);
+        assert_eq!(parse_sha1(&"Z".repeat(40)), None);
+    }
+
+    #[test]
+    fn test_find_range() {
+        let path = std::env::temp_dir().join(format!("pwned-check-range-{}", process::id()));
+        fs::write(&path, CORPUS).unwrap();
+        let source = find::open_source(path.as_os_str()).unwrap();
+
+        let range = find_range(&*source, "5BAA6".parse().unwrap(), true).unwrap();
+        let counts: Vec<_> = range.iter().map(|entry| entry.count).collect();
+        assert_eq!(counts, [Some(1), Some(3_861_493), Some(2)]);
+        assert_eq!(
+            range[1].hash,
+            hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8")
+        );
+
+        assert!(find_range(&*source, "00001".parse().unwrap(), true)
+            .unwrap()
+            .is_empty());
+        assert!(find_range(&*source, "FFFFF".parse().unwrap(), true)
+            .unwrap()
+            .is_empty());
+
+        let found = lookup_hash(
+            &*source,
+            hash("5BAA70000000000000000000000000000000000B"),
+            true,
+        );
+        assert_eq!(found.unwrap().and_then(|finding| finding.count), Some(7));
+        assert_eq!(
+            lookup_hash(&*source, [0xFF; SHA1_BYTE_LENGTH], true).unwrap(),
+            None
+        );
+
+        // unsorted with duplicates
+        let batch = [
+            hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"),
+            [0xFF; SHA1_BYTE_LENGTH],
+            hash("000000005AD76BD555C1D6D771DE417A4B87E4B4"),
+            hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"),
+        ];
+        let found: Vec<_> = lookup_hashes(&*source, &batch, true)
+            .unwrap()
+            .into_iter()
+            .map(|finding| finding.map(|finding| (finding.index, finding.count)))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                Some((0, Some(3_861_493))),
+                None,
+                Some((2, Some(4))),
+                Some((3, Some(3_861_493)))
+            ]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_final_range() {
+        let path = std::env::temp_dir().join(format!("pwned-check-final-{}", process::id()));
+        for corpus in &[CORPUS, CORPUS.trim_end(), CORPUS.trim_end_matches('\n')] {
+            fs::write(&path, corpus).unwrap();
+            let source = find::open_source(path.as_os_str()).unwrap();
+
+            let range = find_range(&*source, "5BAA7".parse().unwrap(), true).unwrap();
+            let counts: Vec<_> = range.iter().map(|entry| entry.count).collect();
+            assert_eq!(counts, [Some(7)], "{:?}", corpus);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let path = std::env::temp_dir().join(format!("pwned-check-warm-up-{}", process::id()));
+        fs::write(&path, CORPUS).unwrap();
+        let source = find::open_source(path.as_os_str()).unwrap();
+        let len = Some(CORPUS.len() as u64);
+        assert_eq!(warm_up(&*source, MapOptions::default(), true).unwrap(), len);
+        assert_eq!(
+            warm_up(&*source, MapOptions::default(), false).unwrap(),
+            len
+        );
+
+        let populated = MapOptions {
+            populate: true,
+            huge_pages: false,
+        };
+        assert_eq!(warm_up(&*source, populated, true).unwrap(), len);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "index")]
+    fn test_find_range_indexed() {
+        let path = std::env::temp_dir().join(format!("pwned-check-range-index-{}", process::id()));
+        let mut index = Vec::new();
+        find::index::convert(
+            CORPUS.as_bytes(),
+            CORPUS.len() as u64,
+            &mut index,
+            true,
+            &mut SilentProgress,
+        )
+        .unwrap();
+        fs::write(&path, index).unwrap();
+        let source = find::open_source(path.as_os_str()).unwrap();
+
+        let range = find_range(&*source, "5BAA6".parse().unwrap(), true).unwrap();
+        assert_eq!(range.len(), 3);
+        assert_eq!(range[2].count, Some(2));
+        assert!(find_range(&*source, "FFFFF".parse().unwrap(), true)
+            .unwrap()
+            .is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+}
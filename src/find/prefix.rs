@@ -0,0 +1,277 @@
+use std::{
+    convert::TryInto,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use roaring::RoaringBitmap;
+
+use crate::{
+    atomic::atomic_write,
+    cancel::Status,
+    collect::SavedHash,
+    find::{
+        lock::{FileState, SharedLock},
+        range_dir::RangeStore,
+        source::{Footprint, HashSource, MapOptions, Mapped},
+        Finding,
+    },
+    Sha1Hash,
+};
+
+/// Suffix of the prefix filter next to the hash database
+const SIDECAR_SUFFIX: &str = ".prefixes";
+
+/// Start of the sidecar - changed together with the layout
+const MAGIC: &[u8; 8] = b"PWNPFX01";
+
+/// Number of leading hash bits in the filter - 16 million possible prefixes
+const PREFIX_BITS: u32 = 24;
+
+/// Set of the hash prefixes that exist in a hash database. Saved hashes with another prefix cannot
+/// be in the database, so they are skipped without any lookup. The complete HIBP list contains
+/// every prefix, but smaller custom lists leave most of them empty.
+#[derive(Debug, Default, PartialEq)]
+pub struct PrefixFilter {
+    prefixes: RoaringBitmap,
+}
+
+impl PrefixFilter {
+    pub fn insert(&mut self, hash: &Sha1Hash) {
+        self.prefixes.insert(prefix(hash));
+    }
+
+    pub fn contains(&self, hash: &Sha1Hash) -> bool {
+        self.prefixes.contains(prefix(hash))
+    }
+
+    /// Whether some prefixes are missing - otherwise the filter never skips a hash
+    pub fn is_selective(&self) -> bool {
+        self.prefixes.len() < 1 << PREFIX_BITS
+    }
+
+    /// Filter of the database or none if there is no sidecar or it's outdated
+    pub fn read(corpus: &Path, corpus_len: u64) -> io::Result<Option<Self>> {
+        let path = sidecar_path(corpus);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        // the sidecar is written after the database, so a newer database was replaced since then
+        if fs::metadata(corpus)?.modified().ok() > file.metadata()?.modified().ok() {
+            warn!("Ignoring outdated prefix filter {:?}", path);
+            return Ok(None);
+        }
+
+        let mut reader = BufReader::new(file);
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unknown prefix filter format",
+            ));
+        }
+
+        // unwrap is safe, because the slice has exactly 8 bytes
+        let filtered_len = u64::from_le_bytes(header[8..].try_into().unwrap());
+        if filtered_len != corpus_len {
+            warn!("Ignoring outdated prefix filter {:?}", path);
+            return Ok(None);
+        }
+
+        let prefixes = RoaringBitmap::deserialize_from(reader)?;
+        Ok(Some(PrefixFilter { prefixes }))
+    }
+
+    /// Store the filter next to the database - the length detects later changes of the database
+    pub fn write(&self, corpus: &Path, corpus_len: u64) -> io::Result<()> {
+        atomic_write(&sidecar_path(corpus), |file| {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&corpus_len.to_le_bytes())?;
+            self.prefixes.serialize_into(&mut writer)?;
+            writer.flush()
+        })
+    }
+}
+
+fn prefix(hash: &Sha1Hash) -> u32 {
+    u32::from_be_bytes([0, hash[0], hash[1], hash[2]])
+}
+
+fn sidecar_path(corpus: &Path) -> PathBuf {
+    let mut path = corpus.as_os_str().to_os_string();
+    path.push(SIDECAR_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Hash database with the prefix filter of its sidecar
+pub struct FilteredSource<S> {
+    inner: S,
+    prefixes: PrefixFilter,
+}
+
+impl<S> FilteredSource<S> {
+    pub fn new(inner: S, prefixes: PrefixFilter) -> Self {
+        FilteredSource { inner, prefixes }
+    }
+}
+
+impl<S: HashSource> HashSource for FilteredSource<S> {
+    fn state(&self) -> io::Result<FileState> {
+        self.inner.state()
+    }
+
+    fn map(&self) -> io::Result<Mapped<'_>> {
+        self.inner.map()
+    }
+
+    fn map_with(&self, options: MapOptions) -> io::Result<Mapped<'_>> {
+        self.inner.map_with(options)
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        self.inner.reader()
+    }
+
+    fn lock_shared(&self) -> io::Result<Option<SharedLock<'_>>> {
+        self.inner.lock_shared()
+    }
+
+    fn supports_ranges(&self) -> bool {
+        self.inner.supports_ranges()
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_at(offset, len)
+    }
+
+    fn ranges(&self) -> Option<&dyn RangeStore> {
+        self.inner.ranges()
+    }
+
+    fn footprint(&self) -> Option<Footprint> {
+        self.inner.footprint()
+    }
+
+    fn prefixes(&self) -> Option<&PrefixFilter> {
+        Some(&self.prefixes)
+    }
+}
+
+/// Scan only the saved hashes whose prefix exists. The skipped hashes don't count for the strategy
+/// selection either, so a few remaining hashes prefer the interpolation search.
+pub fn find_hash_filtered(
+    prefixes: &PrefixFilter,
+    hashes: &[SavedHash],
+    scan: impl FnOnce(&[SavedHash]) -> io::Result<Status<Vec<Finding>>>,
+) -> io::Result<Status<Vec<Finding>>> {
+    let candidates: Vec<usize> = (0..hashes.len())
+        .filter(|&index| prefixes.contains(&hashes[index].password_hash))
+        .collect();
+
+    debug!(
+        "Prefix filter skipped {} of {} saved hashes",
+        hashes.len() - candidates.len(),
+        hashes.len()
+    );
+
+    if candidates.is_empty() {
+        return Ok(Status::Completed(Vec::new()));
+    }
+
+    // keeps the sort order, because the candidates are ascending
+    let subset: Vec<SavedHash> = candidates
+        .iter()
+        .map(|&index| SavedHash::from_hash(hashes[index].password_hash))
+        .collect();
+
+    let status = scan(&subset)?;
+    Ok(status.map(|findings| {
+        findings
+            .into_iter()
+            .map(|finding| Finding {
+                index: candidates[finding.index],
+                ..finding
+            })
+            .collect()
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+
+    fn hash(first: u8) -> Sha1Hash {
+        let mut hash = [0xAB; 20];
+        hash[0] = first;
+        hash
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut filter = PrefixFilter::default();
+        filter.insert(&hash(1));
+
+        let mut same_prefix = hash(1);
+        same_prefix[19] = 0;
+        assert!(filter.contains(&same_prefix));
+        assert!(!filter.contains(&hash(2)));
+        assert!(filter.is_selective());
+    }
+
+    #[test]
+    fn test_write_read() {
+        let corpus = env::temp_dir().join(format!("pwned-check-prefix-{}.txt", process::id()));
+        fs::write(&corpus, "corpus").unwrap();
+        assert_eq!(PrefixFilter::read(&corpus, 6).unwrap(), None);
+
+        let mut filter = PrefixFilter::default();
+        filter.insert(&hash(1));
+        filter.write(&corpus, 6).unwrap();
+        assert_eq!(PrefixFilter::read(&corpus, 6).unwrap(), Some(filter));
+
+        // the database changed after the filter was written
+        assert_eq!(PrefixFilter::read(&corpus, 7).unwrap(), None);
+
+        fs::remove_file(sidecar_path(&corpus)).unwrap();
+        fs::remove_file(&corpus).unwrap();
+    }
+
+    #[test]
+    fn test_filtered_scan() {
+        let mut filter = PrefixFilter::default();
+        filter.insert(&hash(2));
+
+        let hashes: Vec<_> = (1..=3)
+            .map(|first| SavedHash::from_hash(hash(first)))
+            .collect();
+        let status = find_hash_filtered(&filter, &hashes, |candidates| {
+            assert_eq!(candidates.len(), 1);
+            Ok(Status::Completed(vec![Finding {
+                index: 0,
+                count: Some(5),
+            }]))
+        })
+        .unwrap();
+
+        assert_eq!(
+            status.into_inner(),
+            [Finding {
+                index: 1,
+                count: Some(5)
+            }]
+        );
+
+        let empty = PrefixFilter::default();
+        let skipped = find_hash_filtered(&empty, &hashes, |_| panic!("Scanned without candidates"));
+        assert!(skipped.unwrap().into_inner().is_empty());
+    }
+}
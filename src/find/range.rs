@@ -0,0 +1,337 @@
+use std::{cmp::Ordering, io};
+
+use log::error;
+use memchr::memchr;
+
+use crate::{
+    cancel::{CancellationToken, Status},
+    collect::SavedHash,
+    find::{
+        parse::{ParseHashError, PwnedHash},
+        simd::LaneWidth,
+        source::HashSource,
+        strip_carriage_return, Finding, HashPadded,
+    },
+    progress::{ProgressSink, ProgressUnit},
+};
+
+/// Below this number of bytes the remaining range is fetched at once and scanned locally
+const LOCAL_SCAN_WINDOW: u64 = 8 * 1024;
+
+/// Bytes fetched around a probe - enough for a few complete lines
+const PROBE_LENGTH: usize = 512;
+
+/// Longest accepted line of the hash database to complete the last line of a range
+const MAX_LINE_LENGTH: u64 = 256;
+
+/// Binary search for each saved hash using small reads at arbitrary offsets. This only needs a few
+/// dozen reads per hash even for the complete database, so it's suited for sources where each
+/// read is a request (ex: HTTP range requests) and downloading everything would take too long.
+pub fn find_hash_ranged(
+    source: &dyn HashSource,
+    len: u64,
+    hashes: &[SavedHash],
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<Status<Vec<Finding>>, io::Error> {
+    progress.start(hashes.len() as u64, ProgressUnit::Items);
+
+    let search = RangeSearch {
+        source,
+        len,
+        lanes: LaneWidth::detect(),
+    };
+
+    let mut record = PwnedHash::new(strict);
+    let mut findings = Vec::new();
+
+    // saved hashes are sorted, so the previous position is a lower bound for the next one
+    let mut lower = 0;
+    for (index, saved) in hashes.iter().enumerate() {
+        if cancel.is_cancelled() {
+            progress.finish();
+            return Ok(Status::Cancelled(findings));
+        }
+
+        match search.locate(lower, saved.padded(), &mut record)? {
+            Located::Found(start, count) => {
+                lower = start;
+                findings.push(Finding { index, count });
+            }
+            Located::Missing(start) => lower = start,
+            Located::Invalid => {
+                progress.finish();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid line in the hash file",
+                ));
+            }
+        }
+
+        progress.advance(1);
+
+        // the remaining saved hashes are larger than the last line - so no further requests
+        if lower >= len {
+            progress.skip((hashes.len() - index - 1) as u64);
+            break;
+        }
+    }
+
+    progress.finish();
+    Ok(Status::Completed(findings))
+}
+
+/// Result of a search with the line start as new lower bound
+enum Located {
+    Found(u64, Option<u64>),
+    Missing(u64),
+    /// Line couldn't be parsed - the database is likely not a hash list
+    Invalid,
+}
+
+struct RangeSearch<'a> {
+    source: &'a dyn HashSource,
+    len: u64,
+    lanes: LaneWidth,
+}
+
+impl RangeSearch<'_> {
+    fn locate(
+        &self,
+        mut lower: u64,
+        target: &HashPadded,
+        record: &mut PwnedHash,
+    ) -> Result<Located, io::Error> {
+        // Invariant: only lines starting in lower..upper could contain the target
+        let mut upper = self.len;
+        while upper - lower > LOCAL_SCAN_WINDOW {
+            let middle = lower + (upper - lower) / 2;
+            let end = (middle + PROBE_LENGTH as u64).min(self.len);
+            let probe = self.source.read_at(middle, (end - middle) as usize)?;
+
+            // the probe starts somewhere inside a line - use the first complete line after it
+            let line_start = match memchr(b'\n', &probe) {
+                Some(pos) => pos + 1,
+                None => {
+                    // no line starts after the middle, so the target could only be before it
+                    upper = middle + 1;
+                    continue;
+                }
+            };
+
+            let line_end = match memchr(b'\n', &probe[line_start..]) {
+                Some(pos) => line_start + pos,
+                // the last line doesn't need a line break
+                None if end == self.len => probe.len(),
+                None => {
+                    error!("Line longer than {} bytes at {}", PROBE_LENGTH, middle);
+                    return Ok(Located::Invalid);
+                }
+            };
+
+            if line_start >= line_end {
+                // empty trailing line
+                upper = middle + 1;
+                continue;
+            }
+
+            let line = strip_carriage_return(&probe[line_start..line_end]);
+            let start = middle + line_start as u64;
+            match self.compare(line, target, record) {
+                Some(Ordering::Less) => lower = middle + line_end as u64 + 1,
+                Some(Ordering::Greater) => upper = start,
+                Some(Ordering::Equal) => {
+                    return Ok(Located::Found(start, parse_count(record, line)))
+                }
+                None => return Ok(Located::Invalid),
+            }
+        }
+
+        self.scan_local(lower, upper, target, record)
+    }
+
+    /// Fetch all lines starting in the range at once and compare them one by one
+    fn scan_local(
+        &self,
+        lower: u64,
+        upper: u64,
+        target: &HashPadded,
+        record: &mut PwnedHash,
+    ) -> Result<Located, io::Error> {
+        if lower >= upper {
+            return Ok(Located::Missing(lower));
+        }
+
+        let end = (upper + MAX_LINE_LENGTH).min(self.len);
+        let data = self.source.read_at(lower, (end - lower) as usize)?;
+        let range = (upper - lower) as usize;
+
+        let mut start = 0;
+        while start < range && start < data.len() {
+            let line_end = memchr(b'\n', &data[start..]).map_or(data.len(), |pos| start + pos);
+            let line = strip_carriage_return(&data[start..line_end]);
+            if line.is_empty() {
+                break;
+            }
+
+            match self.compare(line, target, record) {
+                Some(Ordering::Less) => start = line_end + 1,
+                Some(Ordering::Greater) => return Ok(Located::Missing(lower + start as u64)),
+                Some(Ordering::Equal) => {
+                    let count = parse_count(record, line);
+                    return Ok(Located::Found(lower + start as u64, count));
+                }
+                None => return Ok(Located::Invalid),
+            }
+        }
+
+        Ok(Located::Missing(upper))
+    }
+
+    fn compare(
+        &self,
+        line: &[u8],
+        target: &HashPadded,
+        record: &mut PwnedHash,
+    ) -> Option<Ordering> {
+        if let Err(err) = record.parse_new_hash(line) {
+            error!("Failed to parse hash {:?}", err);
+            return None;
+        }
+
+        Some(self.lanes.cmp(&record.hash_padded, target))
+    }
+}
+
+fn parse_count(record: &mut PwnedHash, line: &[u8]) -> Option<u64> {
+    match record.parse_count(line) {
+        Ok(count) => Some(*count),
+        Err(ParseHashError::MissingCount()) => None,
+        Err(err) => {
+            error!("Failed to parse count number - {:?}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+
+    use data_encoding::HEXUPPER;
+    use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+    use crate::{
+        find::{find_hash_in_memory, matcher::CompareSpace, source::MemorySource},
+        progress::RecordingProgress,
+        SHA1_BYTE_LENGTH,
+    };
+
+    use super::*;
+
+    /// Sorted corpus of uniform hashes with the line number as count
+    fn create_corpus(
+        size: u32,
+        line_ending: &str,
+        trailing: bool,
+    ) -> (MemorySource, Vec<SavedHash>) {
+        let mut hashes: Vec<[u8; SHA1_BYTE_LENGTH]> = (0..size)
+            .map(|x| {
+                digest(&SHA1_FOR_LEGACY_USE_ONLY, &x.to_be_bytes())
+                    .as_ref()
+                    .try_into()
+                    .unwrap()
+            })
+            .collect();
+        hashes.sort_unstable();
+
+        let lines: Vec<String> = hashes
+            .iter()
+            .enumerate()
+            .map(|(count, hash)| format!("{}:{}", HEXUPPER.encode(hash), count))
+            .collect();
+        let mut corpus = lines.join(line_ending);
+        if trailing {
+            corpus.push_str(line_ending);
+        }
+
+        let saved = hashes
+            .iter()
+            .map(|&hash| SavedHash::new("https://example.com/", "user", hash))
+            .collect();
+        let source = MemorySource {
+            data: corpus.into_bytes(),
+            mappable: false,
+        };
+        (source, saved)
+    }
+
+    fn scan_ranged(source: &MemorySource, hashes: &[SavedHash]) -> Vec<Finding> {
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let len = source.data.len() as u64;
+        find_hash_ranged(source, len, hashes, false, &mut progress, &cancel)
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn test_all_found() {
+        for &line_ending in &["\n", "\r\n"] {
+            for &trailing in &[true, false] {
+                let (source, saved) = create_corpus(2_000, line_ending, trailing);
+                let findings = scan_ranged(&source, &saved);
+
+                assert_eq!(
+                    findings.len(),
+                    saved.len(),
+                    "{:?} {}",
+                    line_ending,
+                    trailing
+                );
+                for (line, finding) in findings.iter().enumerate() {
+                    assert_eq!(finding.index, line);
+                    assert_eq!(finding.count, Some(line as u64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_as_linear() {
+        let (source, corpus_saved) = create_corpus(5_000, "\n", true);
+
+        let saved = |prefix: &[u8]| {
+            let mut hash = [0; SHA1_BYTE_LENGTH];
+            hash[..prefix.len()].copy_from_slice(prefix);
+            SavedHash::new("https://example.com/", "user", hash)
+        };
+
+        // boundaries of the file and some members and non members in between
+        let mut hashes = vec![
+            saved(&[]),
+            saved(&[0xFF; SHA1_BYTE_LENGTH]),
+            saved(&[0x7F, 0xFF, 0xFF, 0xFF]),
+            saved(&[0x80]),
+        ];
+        hashes.extend(corpus_saved.into_iter().step_by(97));
+        hashes.sort_unstable();
+
+        let ranged = scan_ranged(&source, &hashes);
+
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let linear = find_hash_in_memory(
+            &source.data,
+            &hashes,
+            false,
+            CompareSpace::Decoded,
+            &mut progress,
+            &cancel,
+        )
+        .unwrap()
+        .into_inner();
+        assert_eq!(ranged, linear);
+    }
+}
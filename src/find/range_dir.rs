@@ -0,0 +1,307 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice;
+use log::debug;
+
+use crate::{
+    cancel::{CancellationToken, Status},
+    collect::SavedHash,
+    find::{
+        lock::FileState,
+        matcher::CompareSpace,
+        point::RangePrefix,
+        source::{HashSource, Mapped},
+        Finding, Scanner,
+    },
+    progress::{ProgressSink, ProgressUnit},
+};
+
+/// Number of 5 hex character prefixes - one file for each
+const PREFIX_COUNT: u32 = 1 << 20;
+
+/// Hash database as downloaded by the official HIBP downloader without merging the ranges. Each
+/// file is named by the first 5 hex characters of the hash and only contains the remaining
+/// characters (ex: `00000.txt` with `0005AD76BD555C1D6D771DE417A4B87E4B4:10`). The full hashes are
+/// reconstructed while reading, so only the files of the saved prefixes are read.
+pub struct RangeDirectory {
+    path: PathBuf,
+    /// Extension of the range files including the dot - empty if they have none
+    extension: &'static str,
+}
+
+impl RangeDirectory {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let extension = [".txt", ""]
+            .iter()
+            .copied()
+            .find(|extension| path.join(format!("00000{}", extension)).is_file())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Directory doesn't contain HIBP range files like 00000.txt",
+                )
+            })?;
+
+        Ok(RangeDirectory {
+            path: path.to_path_buf(),
+            extension,
+        })
+    }
+}
+
+/// Hash database split into the ranges of the 5 hex character prefixes like the HIBP API
+pub trait RangeStore {
+    /// Full hash lines of the range sorted by hash
+    fn read_range(&self, prefix: u32) -> io::Result<Vec<u8>>;
+}
+
+impl RangeStore for RangeDirectory {
+    /// Files are required, because a missing one would silently hide pwned passwords
+    fn read_range(&self, prefix: u32) -> io::Result<Vec<u8>> {
+        let path = self.path.join(format!("{:05X}{}", prefix, self.extension));
+        let content = fs::read(&path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Cannot read range file {:?} {}", path, err),
+            )
+        })?;
+
+        Ok(full_lines(prefix, content.lines()))
+    }
+}
+
+/// Prepend the prefix to each line of the range that only contains the remaining hash characters
+pub fn full_lines<'a>(prefix: u32, lines: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let prefix = format!("{:05X}", prefix);
+    let mut full = Vec::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        full.extend_from_slice(prefix.as_bytes());
+        full.extend_from_slice(line);
+        full.push(b'\n');
+    }
+
+    full
+}
+
+impl HashSource for RangeDirectory {
+    /// Adding or replacing range files updates the modification time of the directory
+    fn state(&self) -> io::Result<FileState> {
+        fs::metadata(&self.path).map(|metadata| FileState::of_metadata(&metadata))
+    }
+
+    fn map(&self) -> io::Result<Mapped<'_>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// All ranges merged like the single file of the downloader
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(RangeReader {
+            directory: self,
+            next: 0,
+            buffer: Vec::new(),
+            position: 0,
+        }))
+    }
+
+    fn ranges(&self) -> Option<&dyn RangeStore> {
+        Some(self)
+    }
+}
+
+struct RangeReader<'a> {
+    directory: &'a RangeDirectory,
+    /// Prefix of the next range file
+    next: u32,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Read for RangeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.next == PREFIX_COUNT {
+                return Ok(0);
+            }
+
+            self.buffer = self.directory.read_range(self.next)?;
+            self.position = 0;
+            self.next += 1;
+        }
+
+        let remaining = &self.buffer[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// First 20 bits of the hash - the name of its range file
+fn range_prefix(saved: &SavedHash) -> u32 {
+    RangePrefix::of(&saved.password_hash).value()
+}
+
+/// Read only the ranges of the saved hashes. The sorted saved hashes share a range if they
+/// have the same prefix, so each range is read at most once.
+pub fn find_hash_in_ranges(
+    directory: &dyn RangeStore,
+    hashes: &[SavedHash],
+    strict: bool,
+    progress: &mut dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> io::Result<Status<Vec<Finding>>> {
+    progress.start(hashes.len() as u64, ProgressUnit::Items);
+    // only the few lines of the read ranges are compared, so decoding them doesn't matter
+    let mut scanner = Scanner::new(hashes, strict, CompareSpace::Decoded);
+
+    let mut ranges = 0;
+    let mut group_end = 0;
+    for group in hashes.chunk_by(|a, b| range_prefix(a) == range_prefix(b)) {
+        if cancel.is_cancelled() {
+            progress.finish();
+            return scanner.into_status(true);
+        }
+
+        // the hashes of the next groups are larger than all lines of this range
+        group_end += group.len();
+        let lines = directory.read_range(range_prefix(&group[0]))?;
+        for line in lines.lines() {
+            if !scanner.process_line(line) || scanner.decided() >= group_end {
+                break;
+            }
+        }
+
+        ranges += 1;
+        progress.advance(group.len() as u64);
+
+        // all saved hashes are decided or an invalid line aborted the comparison
+        if scanner.is_finished() {
+            break;
+        }
+    }
+
+    debug!("Read {} ranges", ranges);
+    progress.finish();
+    scanner.into_status(false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{progress::RecordingProgress, SHA1_BYTE_LENGTH};
+
+    fn saved(hex: &str) -> SavedHash {
+        let mut hash = [0; SHA1_BYTE_LENGTH];
+        data_encoding::HEXUPPER
+            .decode_mut(hex.as_bytes(), &mut hash)
+            .unwrap();
+        SavedHash::from_hash(hash)
+    }
+
+    fn create_directory(name: &str, ranges: &[(&str, &str)]) -> PathBuf {
+        let path = env::temp_dir().join(format!("pwned-check-{}-{}", name, process::id()));
+        fs::create_dir_all(&path).unwrap();
+        for (prefix, content) in ranges {
+            fs::write(path.join(format!("{}.txt", prefix)), content).unwrap();
+        }
+
+        path
+    }
+
+    #[test]
+    fn test_find_in_ranges() {
+        let path = create_directory(
+            "ranges",
+            &[
+                ("00000", "0005AD76BD555C1D6D771DE417A4B87E4B4:10\r\n000A8DAE4228F821FB418F59826079BF368:4\r\n"),
+                ("FFFFF", "FFF0F48B0C6D4C0E1FB1B01F1C30CA2B9A1:7"),
+            ],
+        );
+
+        let hashes = [
+            saved("000000005AD76BD555C1D6D771DE417A4B87E4B4"),
+            saved("0000000DD7F2A1C68A35673713783CA390C9E93F"),
+            saved("FFFFFFFF0F48B0C6D4C0E1FB1B01F1C30CA2B9A1"),
+        ];
+
+        let directory = RangeDirectory::open(&path).unwrap();
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+        let status =
+            find_hash_in_ranges(&directory, &hashes, true, &mut progress, &cancel).unwrap();
+        assert_eq!(
+            status.into_inner(),
+            [
+                Finding {
+                    index: 0,
+                    count: Some(10)
+                },
+                Finding {
+                    index: 2,
+                    count: Some(7)
+                }
+            ]
+        );
+
+        // a missing range would hide the matches of its prefix
+        let missing = [saved("12345FFF0F48B0C6D4C0E1FB1B01F1C30CA2B9A1")];
+        let result = find_hash_in_ranges(&directory, &missing, true, &mut progress, &cancel);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_range_stops_early() {
+        let path = create_directory(
+            "ranges-early",
+            &[
+                // the lines after the last saved hash of the range are not parsed
+                ("00000", "0005AD76BD555C1D6D771DE417A4B87E4B4:10\nGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGG:1\n"),
+                ("11111", "GGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGG:1"),
+            ],
+        );
+
+        let hashes = [
+            saved("000000005AD76BD555C1D6D771DE417A4B87E4B4"),
+            saved("11111FFF0F48B0C6D4C0E1FB1B01F1C30CA2B9A1"),
+            saved("12345FFF0F48B0C6D4C0E1FB1B01F1C30CA2B9A1"),
+        ];
+
+        let directory = RangeDirectory::open(&path).unwrap();
+        let mut progress = RecordingProgress::default();
+        let cancel = CancellationToken::default();
+
+        // the invalid range fails before requesting the missing one, which would be NotFound
+        let result = find_hash_in_ranges(&directory, &hashes, true, &mut progress, &cancel);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reconstructed() {
+        let path = create_directory(
+            "ranges-read",
+            &[("00000", "0005AD76BD555C1D6D771DE417A4B87E4B4:10\r\n")],
+        );
+        let directory = RangeDirectory::open(&path).unwrap();
+
+        let mut reader = directory.reader().unwrap();
+        let mut first = vec![0; 44];
+        reader.read_exact(&mut first).unwrap();
+        assert_eq!(first, b"000000005AD76BD555C1D6D771DE417A4B87E4B4:10\n");
+
+        // the other ranges are missing in the test
+        assert!(reader.read(&mut first).is_err());
+        fs::remove_dir_all(&path).unwrap();
+
+        assert!(RangeDirectory::open(&env::temp_dir().join("pwned-check-no-ranges")).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::{cmp::Ordering, convert::TryInto};
+
+#[cfg(not(any(target_arch = "aarch64", target_arch = "arm")))]
+use packed_simd_2::u8x16;
+use packed_simd_2::u8x32;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use packed_simd_2::u8x64;
+
+use crate::find::{HashPadded, SIMD_WIDTH};
+
+/// Width of the SIMD registers used for the padded hash compares. The padded hash representation
+/// stays the same, only the number of hashes per instruction differs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LaneWidth {
+    /// 16 byte lanes like NEON or SSE - the padded hash is compared in two halves. On ARM each half
+    /// is compared as big-endian integer, because NEON has no cheap lexicographic reduction.
+    Bits128,
+    /// 32 byte lanes like AVX2 - exactly one padded hash per instruction
+    Bits256,
+    /// 64 byte lanes like AVX-512 - two saved hashes are compared against a line at once
+    Bits512,
+}
+
+impl LaneWidth {
+    pub const ALL: [LaneWidth; 3] = [LaneWidth::Bits128, LaneWidth::Bits256, LaneWidth::Bits512];
+
+    /// Widest lane width of the running CPU - the binary is usually built without
+    /// `target-cpu=native`
+    pub fn detect() -> Self {
+        // unwrap is safe, because 128 bit lanes are always supported
+        *Self::ALL
+            .iter()
+            .rev()
+            .find(|width| width.is_supported())
+            .unwrap()
+    }
+
+    /// The compare functions of this width can run on the current CPU
+    pub fn is_supported(self) -> bool {
+        match self {
+            LaneWidth::Bits128 => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            LaneWidth::Bits256 => is_x86_feature_detected!("avx2"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            LaneWidth::Bits512 => is_x86_feature_detected!("avx512bw"),
+            // NEON registers are 128 bit wide, emulating 256 bit would need two instructions anyway
+            #[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+            LaneWidth::Bits256 | LaneWidth::Bits512 => false,
+            // packed_simd emulates the vectors there, so a single compare is the simplest
+            #[cfg(not(any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "aarch64",
+                target_arch = "arm"
+            )))]
+            LaneWidth::Bits256 => true,
+            #[cfg(not(any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "aarch64",
+                target_arch = "arm"
+            )))]
+            LaneWidth::Bits512 => false,
+        }
+    }
+
+    /// Lexicographic order of both padded hashes. Always inlined, so it uses the instructions of
+    /// the calling scan (see `find::scan_lines_for`).
+    #[inline(always)]
+    pub fn cmp(self, left: &HashPadded, right: &HashPadded) -> Ordering {
+        match self {
+            LaneWidth::Bits128 => cmp_halves(left, right),
+            LaneWidth::Bits256 | LaneWidth::Bits512 => cmp_full(left, right),
+        }
+    }
+
+    /// Order of the line to both saved hashes using a single instruction - none if the lanes are
+    /// too narrow for it and two compares are needed anyway
+    #[inline(always)]
+    pub fn cmp_pair(self, line: &HashPadded, saved: [&HashPadded; 2]) -> Option<[Ordering; 2]> {
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            LaneWidth::Bits512 => Some(cmp_pair_wide(line, saved)),
+            _ => None,
+        }
+    }
+}
+
+const HALF_WIDTH: usize = SIMD_WIDTH / 2;
+
+#[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+#[inline(always)]
+fn cmp_halves(left: &HashPadded, right: &HashPadded) -> Ordering {
+    cmp_integer_halves(left, right)
+}
+
+#[cfg(not(any(target_arch = "aarch64", target_arch = "arm")))]
+#[inline(always)]
+fn cmp_halves(left: &HashPadded, right: &HashPadded) -> Ordering {
+    let left_head = u8x16::from_slice_unaligned(&left[..HALF_WIDTH]);
+    let right_head = u8x16::from_slice_unaligned(&right[..HALF_WIDTH]);
+    left_head
+        .lex_ord()
+        .cmp(&right_head.lex_ord())
+        .then_with(|| {
+            let left_tail = u8x16::from_slice_unaligned(&left[HALF_WIDTH..]);
+            let right_tail = u8x16::from_slice_unaligned(&right[HALF_WIDTH..]);
+            left_tail.lex_ord().cmp(&right_tail.lex_ord())
+        })
+}
+
+/// Big-endian integers have the same order as the bytes. On aarch64 this compiles to a pair of
+/// loads, byte reversals and a compare with carry for each half.
+#[cfg_attr(
+    not(any(target_arch = "aarch64", target_arch = "arm")),
+    allow(dead_code)
+)]
+#[inline(always)]
+fn cmp_integer_halves(left: &HashPadded, right: &HashPadded) -> Ordering {
+    let half = |hash: &HashPadded, start: usize| {
+        // unwrap is safe, because the padded hash contains exactly two halves
+        u128::from_be_bytes(hash[start..start + HALF_WIDTH].try_into().unwrap())
+    };
+
+    half(left, 0)
+        .cmp(&half(right, 0))
+        .then_with(|| half(left, HALF_WIDTH).cmp(&half(right, HALF_WIDTH)))
+}
+
+#[inline(always)]
+fn cmp_full(left: &HashPadded, right: &HashPadded) -> Ordering {
+    let left = u8x32::from_slice_unaligned(left);
+    let right = u8x32::from_slice_unaligned(right);
+    left.lex_ord().cmp(&right.lex_ord())
+}
+
+/// Compare the line broadcast to both halves of a 512 bit register against two saved hashes. The
+/// first different byte of each half decides the order.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+fn cmp_pair_wide(line: &HashPadded, saved: [&HashPadded; 2]) -> [Ordering; 2] {
+    let mut packed_line = [0; 2 * SIMD_WIDTH];
+    packed_line[..SIMD_WIDTH].copy_from_slice(line);
+    packed_line[SIMD_WIDTH..].copy_from_slice(line);
+
+    let mut packed_saved = [0; 2 * SIMD_WIDTH];
+    packed_saved[..SIMD_WIDTH].copy_from_slice(saved[0]);
+    packed_saved[SIMD_WIDTH..].copy_from_slice(saved[1]);
+
+    // one bit per lane that is set if the bytes differ
+    let not_equal = !u8x64::from_slice_unaligned(&packed_line)
+        .eq(u8x64::from_slice_unaligned(&packed_saved))
+        .bitmask();
+
+    let order = |index: usize| {
+        let half_mask = (not_equal >> (index * SIMD_WIDTH)) & u64::from(u32::MAX);
+        if half_mask == 0 {
+            Ordering::Equal
+        } else {
+            let lane = half_mask.trailing_zeros() as usize;
+            line[lane].cmp(&saved[index][lane])
+        }
+    };
+
+    [order(0), order(1)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn padded(prefix: &[u8]) -> HashPadded {
+        let mut hash = [0; SIMD_WIDTH];
+        hash[..prefix.len()].copy_from_slice(prefix);
+        hash
+    }
+
+    #[test]
+    fn test_detect_supported() {
+        assert!(LaneWidth::detect().is_supported());
+        assert!(LaneWidth::Bits128.is_supported());
+    }
+
+    #[test]
+    fn test_cmp_all_widths() {
+        let low = padded(&[1, 2, 3]);
+        // differs only in the second half to test the split compare
+        let mut high = low;
+        high[HALF_WIDTH + 2] = 1;
+
+        // outside of the dispatched scans the wide vectors are emulated, so all widths can run
+        for &width in &LaneWidth::ALL {
+            assert_eq!(width.cmp(&low, &high), Ordering::Less, "{:?}", width);
+            assert_eq!(width.cmp(&high, &low), Ordering::Greater, "{:?}", width);
+            assert_eq!(width.cmp(&low, &low), Ordering::Equal, "{:?}", width);
+        }
+    }
+
+    #[test]
+    fn test_cmp_pair() {
+        let line = padded(&[5, 5]);
+        let lower = padded(&[5, 4, 0xFF]);
+        let higher = padded(&[6]);
+
+        for &width in &LaneWidth::ALL {
+            // narrower lanes need two instructions, so there is no pair compare
+            let paired = width == LaneWidth::Bits512
+                && cfg!(any(target_arch = "x86", target_arch = "x86_64"));
+            let expected = paired.then_some([Ordering::Greater, Ordering::Less]);
+            assert_eq!(
+                width.cmp_pair(&line, [&lower, &higher]),
+                expected,
+                "{:?}",
+                width
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn test_cmp_pair_wide() {
+        let line = padded(&[1, 2, 3]);
+        // differs only in the last byte of the second half
+        let mut last = line;
+        last[SIMD_WIDTH - 1] = 1;
+
+        assert_eq!(
+            cmp_pair_wide(&line, [&line, &last]),
+            [Ordering::Equal, Ordering::Less]
+        );
+        assert_eq!(
+            cmp_pair_wide(&last, [&line, &last]),
+            [Ordering::Greater, Ordering::Equal]
+        );
+    }
+
+    #[test]
+    fn test_cmp_integer_halves() {
+        let low = padded(&[1, 2, 3]);
+        let mut high = low;
+        high[HALF_WIDTH + 2] = 1;
+
+        assert_eq!(cmp_integer_halves(&low, &high), Ordering::Less);
+        assert_eq!(cmp_integer_halves(&high, &low), Ordering::Greater);
+        assert_eq!(cmp_integer_halves(&high, &high), Ordering::Equal);
+
+        // highest byte decides even if all others are larger
+        let mut first = padded(&[2]);
+        let second = padded(&[1, 0xFF, 0xFF]);
+        assert_eq!(cmp_integer_halves(&first, &second), Ordering::Greater);
+        first[0] = 1;
+        assert_eq!(cmp_integer_halves(&first, &second), Ordering::Less);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{self, Read},
+    ops::Deref,
+    path::Path,
+};
+
+use memmap2::{Mmap, MmapOptions};
+
+#[cfg(feature = "index")]
+use log::{debug, warn};
+
+#[cfg(feature = "index")]
+use crate::find::prefix::{FilteredSource, PrefixFilter};
+use crate::find::{
+    lock::{FileState, SharedLock},
+    range_dir::{RangeDirectory, RangeStore},
+};
+
+/// Opens hash databases by their path
+pub trait FileSystem {
+    type Source: HashSource + Sync;
+
+    fn open(&self, path: &Path) -> io::Result<Self::Source>;
+}
+
+/// Hash databases on the local disk
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    type Source = File;
+
+    fn open(&self, path: &Path) -> io::Result<File> {
+        File::open(path)
+    }
+}
+
+/// Open a hash database on the local disk, on a static web server if the path is a HTTP(S) URL or
+/// in an object storage for `s3://bucket/key` URLs. Directories are read as downloaded ranges and
+/// URLs ending with `/range/` are queried like the HIBP range API.
+pub fn open_source(path: &OsStr) -> io::Result<Box<dyn HashSource + Sync>> {
+    #[cfg(feature = "http")]
+    if let Some(url) = remote_url(path) {
+        let http = url.starts_with("http://") || url.starts_with("https://");
+        if http && url.ends_with("/range/") {
+            return Ok(Box::new(crate::find::api::RangeApi::new(url)));
+        }
+
+        if http {
+            return Ok(Box::new(crate::find::http::HttpSource::new(url)?));
+        }
+
+        if url.starts_with("s3://") {
+            return Ok(Box::new(crate::find::http::HttpSource::s3(url)?));
+        }
+    }
+
+    if Path::new(path).is_dir() {
+        return Ok(Box::new(RangeDirectory::open(Path::new(path))?));
+    }
+
+    let file = OsFileSystem.open(Path::new(path))?;
+
+    #[cfg(feature = "index")]
+    match PrefixFilter::read(Path::new(path), file.metadata()?.len()) {
+        Ok(Some(prefixes)) => {
+            debug!("Using the prefix filter of {:?}", path);
+            return Ok(Box::new(FilteredSource::new(file, prefixes)));
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Ignoring the invalid prefix filter of {:?} {}", path, err),
+    }
+
+    Ok(Box::new(file))
+}
+
+/// URL of a hash database on a web server or in an object storage - none for local paths
+pub fn remote_url(path: &OsStr) -> Option<&str> {
+    let schemes = ["http://", "https://", "s3://"];
+    path.to_str()
+        .filter(|url| schemes.iter().any(|scheme| url.starts_with(scheme)))
+}
+
+/// Read access to a single hash database. The finder only uses this interface, so it could be
+/// tested against in-memory databases and other sources could be added later.
+pub trait HashSource {
+    /// Size and modification time to detect changes during the scan
+    fn state(&self) -> io::Result<FileState>;
+
+    /// Complete database in memory - required for the interpolation search
+    fn map(&self) -> io::Result<Mapped<'_>>;
+
+    /// Like `map`, but with the flags of the user - only sources backed by a local file use them
+    fn map_with(&self, _options: MapOptions) -> io::Result<Mapped<'_>> {
+        self.map()
+    }
+
+    /// Sequential reader if the database couldn't be mapped
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>>;
+
+    /// Shared lock for the duration of the scan - none if the source cannot be modified
+    fn lock_shared(&self) -> io::Result<Option<SharedLock<'_>>> {
+        Ok(None)
+    }
+
+    /// Whether `read_at` is supported - then the database is searched without reading everything
+    fn supports_ranges(&self) -> bool {
+        false
+    }
+
+    /// Read `len` bytes starting at `offset` - less only at the end of the database
+    fn read_at(&self, _offset: u64, _len: usize) -> io::Result<Vec<u8>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Ranges of the database - then only the ranges of the saved prefixes are read
+    fn ranges(&self) -> Option<&dyn RangeStore> {
+        None
+    }
+
+    /// Requests sent to an online API so far - none for all other sources
+    fn footprint(&self) -> Option<Footprint> {
+        None
+    }
+
+    /// Prefixes that exist in the database - none if every saved hash has to be looked up
+    #[cfg(feature = "index")]
+    fn prefixes(&self) -> Option<&PrefixFilter> {
+        None
+    }
+}
+
+/// Data about the saved hashes that left the machine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Footprint {
+    /// Distinct ranges requested from the API
+    pub ranges: usize,
+    /// Ranges read from the prefetch cache instead of the API
+    pub cached: usize,
+    /// All requests including the retries
+    pub requests: usize,
+    /// Most hash characters sent in a single request
+    pub hash_chars: usize,
+}
+
+impl Footprint {
+    /// Footprint of multiple sources
+    pub fn merge(self, other: Footprint) -> Footprint {
+        Footprint {
+            ranges: self.ranges + other.ranges,
+            cached: self.cached + other.cached,
+            requests: self.requests + other.requests,
+            hash_chars: self.hash_chars.max(other.hash_chars),
+        }
+    }
+}
+
+/// Flags for memory mapping a local hash database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    /// Pre-fault the whole database while mapping (MAP_POPULATE on Linux) - only useful if the
+    /// machine has enough memory to keep the complete corpus in the page cache
+    pub populate: bool,
+    /// Ask Linux to back the mapping with transparent huge pages
+    pub huge_pages: bool,
+}
+
+/// Mapped content of a hash database
+pub enum Mapped<'a> {
+    /// Memory map of a file - pages are loaded on access and support advices to the OS
+    File(Mmap),
+    /// Only constructed by in-memory sources of the tests yet
+    #[allow(dead_code)]
+    Memory(&'a [u8]),
+}
+
+impl Deref for Mapped<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Mapped::File(map) => map,
+            Mapped::Memory(data) => data,
+        }
+    }
+}
+
+impl HashSource for File {
+    fn state(&self) -> io::Result<FileState> {
+        FileState::of(self)
+    }
+
+    fn map(&self) -> io::Result<Mapped<'_>> {
+        self.map_with(MapOptions::default())
+    }
+
+    fn map_with(&self, options: MapOptions) -> io::Result<Mapped<'_>> {
+        let mut mmap_options = MmapOptions::new();
+        if options.populate {
+            // ignored on other platforms than Linux
+            mmap_options.populate();
+        }
+
+        // Safety: see the safety note of find_hash for concurrent modifications
+        let map = unsafe { mmap_options.map(self) }?;
+
+        #[cfg(target_os = "linux")]
+        crate::find::advise_linux(&map, options.huge_pages);
+
+        Ok(Mapped::File(map))
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        #[cfg(unix)]
+        crate::find::advise::fadvise(self, 0, None, crate::find::advise::FileAdvice::Sequential);
+
+        Ok(Box::new(self))
+    }
+
+    fn lock_shared(&self) -> io::Result<Option<SharedLock<'_>>> {
+        SharedLock::acquire(self).map(Some)
+    }
+}
+
+/// Hash database kept in memory
+#[cfg(test)]
+pub struct MemorySource {
+    pub data: Vec<u8>,
+    /// Simulate a remote source that cannot be mapped but supports range reads
+    pub mappable: bool,
+}
+
+#[cfg(test)]
+impl HashSource for MemorySource {
+    fn state(&self) -> io::Result<FileState> {
+        Ok(FileState::of_len(self.data.len() as u64))
+    }
+
+    fn map(&self) -> io::Result<Mapped<'_>> {
+        if self.mappable {
+            Ok(Mapped::Memory(&self.data))
+        } else {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(&self.data[..]))
+    }
+
+    fn supports_ranges(&self) -> bool {
+        !self.mappable
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let start = (offset as usize).min(self.data.len());
+        let end = start.saturating_add(len).min(self.data.len());
+        Ok(self.data[start..end].to_vec())
+    }
+}
+
+/// Virtual file system of in-memory hash databases
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryFileSystem {
+    pub files: std::collections::HashMap<std::path::PathBuf, Vec<u8>>,
+}
+
+#[cfg(test)]
+impl FileSystem for MemoryFileSystem {
+    type Source = MemorySource;
+
+    fn open(&self, path: &Path) -> io::Result<MemorySource> {
+        self.files
+            .get(path)
+            .map(|data| MemorySource {
+                data: data.clone(),
+                mappable: true,
+            })
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(unix)]
+use std::sync::Once;
+use std::{thread, time::Duration};
+
+use crossbeam_channel::RecvTimeoutError;
+use log::error;
+
+use crate::{
+    cancel::CancellationToken,
+    find::{lock::FileState, source::HashSource},
+};
+
+/// Interval for comparing the state of the mapped hash database
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Run the scan over the mapped hash database while another thread compares the source state
+/// periodically. The scan is cancelled as soon as a change is detected, before it reaches pages
+/// behind a truncated end. The caller reports the change afterwards using `verify_unchanged`.
+pub fn watch_changes<T>(
+    source: &(dyn HashSource + Sync),
+    state: &FileState,
+    cancel: &CancellationToken,
+    scan: impl FnOnce() -> T,
+) -> T {
+    let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            // the sender is dropped after the scan
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(WATCH_INTERVAL) {
+                if source.state().map_or(true, |current| current != *state) {
+                    error!("Hash file changed during the scan - stopping");
+                    cancel.cancel();
+                    break;
+                }
+            }
+        });
+
+        let result = scan();
+        drop(stop);
+        result
+    })
+}
+
+/// Exit code if the hash database was truncated while reading it - `EX_IOERR` of sysexits
+#[cfg(unix)]
+const BUS_ERROR_EXIT_CODE: i32 = 74;
+
+/// Replaces the crash from reading pages behind the end of a truncated file (SIGBUS) with an
+/// error message. The scan cannot continue after the signal, so the process still exits. This
+/// covers truncations faster than the watch interval. The handler is process-wide, so it's
+/// installed once for all threads instead of around each scan.
+#[cfg(unix)]
+pub fn install_bus_error_handler() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        // Safety: all fields of sigaction are valid if zeroed
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_bus_error as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+        let ret = unsafe {
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGBUS, &action, std::ptr::null_mut())
+        };
+
+        if ret != 0 {
+            error!(
+                "Failed to install the SIGBUS handler {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    });
+}
+
+#[cfg(unix)]
+extern "C" fn on_bus_error(_signal: libc::c_int) {
+    // only async-signal-safe functions are allowed here - no allocations or locks by the logger
+    const MESSAGE: &[u8] = b"Hash file was truncated while reading it - aborted\n";
+    unsafe {
+        libc::write(
+            libc::STDERR_FILENO,
+            MESSAGE.as_ptr() as *const libc::c_void,
+            MESSAGE.len(),
+        );
+        libc::_exit(BUS_ERROR_EXIT_CODE);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, fs::File, io::Write, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_cancel_on_change() {
+        let path = std::env::temp_dir().join(format!("pwned-check-watch-{}", std::process::id()));
+        fs::write(&path, "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let state = FileState::of(&file).unwrap();
+
+        let cancel = CancellationToken::default();
+        let cancelled = watch_changes(&file, &state, &cancel, || {
+            fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .write_all(b"00000000A8DAE4228F821FB418F59826079BF368:7\n")
+                .unwrap();
+
+            let started = Instant::now();
+            while !cancel.is_cancelled() && started.elapsed() < 50 * WATCH_INTERVAL {
+                thread::sleep(WATCH_INTERVAL / 10);
+            }
+
+            cancel.is_cancelled()
+        });
+
+        assert!(cancelled);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let path = std::env::temp_dir().join(format!("pwned-check-same-{}", std::process::id()));
+        fs::write(&path, "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let state = FileState::of(&file).unwrap();
+
+        let cancel = CancellationToken::default();
+        let result = watch_changes(&file, &state, &cancel, || {
+            thread::sleep(2 * WATCH_INTERVAL);
+            5
+        });
+
+        assert_eq!(result, 5);
+        assert!(!cancel.is_cancelled());
+        fs::remove_file(path).unwrap();
+    }
+}
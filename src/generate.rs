@@ -0,0 +1,160 @@
+use std::{
+    convert::TryInto,
+    io::{self, Write},
+};
+
+use crate::{hasher, Sha1Hash};
+use data_encoding::HEXUPPER;
+
+/// Parameters of a synthetic hash database with a matching passwords export
+#[derive(Debug)]
+pub struct TestData {
+    /// Number of lines in the hash database
+    pub lines: usize,
+    /// Same seed creates the same files
+    pub seed: u64,
+    /// Number of accounts in the exported passwords
+    pub accounts: usize,
+    /// Part of the accounts that use a password of the database
+    pub pwned_fraction: f64,
+}
+
+impl TestData {
+    /// Write the sorted `HASH:count` database. All lines are kept in memory for sorting - 20 bytes
+    /// per line.
+    pub fn write_corpus(&self, writer: impl Write) -> io::Result<()> {
+        let mut hashes: Vec<Sha1Hash> = (0..self.lines)
+            .map(|index| self.pwned_hash(index))
+            .collect();
+        hashes.sort_unstable();
+
+        let mut writer = io::BufWriter::new(writer);
+        for hash in &hashes {
+            writeln!(writer, "{}:{}", HEXUPPER.encode(hash), count(hash))?;
+        }
+
+        writer.flush()
+    }
+
+    /// Write a passwords export in the Chromium format. The pwned accounts are spread evenly.
+    pub fn write_passwords(&self, writer: impl Write) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(&["name", "url", "username", "password"])?;
+
+        let pwned_accounts = (self.accounts as f64 * self.pwned_fraction).round() as usize;
+        for account in 0..self.accounts {
+            // steps of the pwned accounts over all accounts like drawing a line
+            let is_pwned = (account + 1) * pwned_accounts / self.accounts
+                > account * pwned_accounts / self.accounts;
+
+            let password = if is_pwned && self.lines > 0 {
+                let line = self.select_line(account);
+                self.pwned_password(line)
+            } else {
+                format!("clean-{}-{}", self.seed, account)
+            };
+
+            writer.write_record(&[
+                format!("site{}.example", account),
+                format!("https://site{}.example/", account),
+                format!("user{}", account),
+                password,
+            ])?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn pwned_password(&self, line: usize) -> String {
+        format!("pwned-{}-{}", self.seed, line)
+    }
+
+    fn pwned_hash(&self, line: usize) -> Sha1Hash {
+        sha1(self.pwned_password(line).as_bytes())
+    }
+
+    /// Pseudo random line of the database for the account
+    fn select_line(&self, account: usize) -> usize {
+        let hash = sha1(format!("select-{}-{}", self.seed, account).as_bytes());
+        let value = u64::from_be_bytes(hash[..8].try_into().unwrap());
+        (value % self.lines as u64) as usize
+    }
+}
+
+fn sha1(data: &[u8]) -> Sha1Hash {
+    hasher::sha1(&[data])
+}
+
+/// Count derived from the hash, so it's stable too
+fn count(hash: &Sha1Hash) -> u32 {
+    u32::from_be_bytes(hash[16..].try_into().unwrap()) % 1_000_000 + 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_data(seed: u64) -> TestData {
+        TestData {
+            lines: 1_000,
+            seed,
+            accounts: 50,
+            pwned_fraction: 0.2,
+        }
+    }
+
+    fn generate(data: &TestData) -> (String, String) {
+        let mut corpus = Vec::new();
+        data.write_corpus(&mut corpus).unwrap();
+
+        let mut passwords = Vec::new();
+        data.write_passwords(&mut passwords).unwrap();
+        (
+            String::from_utf8(corpus).unwrap(),
+            String::from_utf8(passwords).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(generate(&test_data(1)), generate(&test_data(1)));
+        assert_ne!(generate(&test_data(1)), generate(&test_data(2)));
+    }
+
+    #[test]
+    fn test_corpus_sorted() {
+        let (corpus, _) = generate(&test_data(1));
+        let lines: Vec<&str> = corpus.lines().collect();
+        assert_eq!(lines.len(), 1_000);
+
+        let mut sorted = lines.clone();
+        sorted.sort_unstable();
+        assert_eq!(lines, sorted);
+        assert!(lines
+            .iter()
+            .all(|line| line.len() > 41 && &line[40..41] == ":"));
+    }
+
+    #[test]
+    fn test_pwned_fraction() {
+        let data = test_data(1);
+        let (corpus, passwords) = generate(&data);
+
+        let mut reader = csv::Reader::from_reader(passwords.as_bytes());
+        let mut pwned = 0;
+        let mut accounts = 0;
+        for record in reader.records() {
+            let record = record.unwrap();
+            let hash = HEXUPPER.encode(&sha1(record[3].as_bytes()));
+            if corpus.contains(&hash) {
+                pwned += 1;
+            }
+
+            accounts += 1;
+        }
+
+        assert_eq!(accounts, 50);
+        assert_eq!(pwned, 10);
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(feature = "crypto")]
+use std::convert::TryInto;
+
+use crate::Sha1Hash;
+
+/// Implementation of SHA-1 for the hashing of the passwords. The backend is selected at compile
+/// time:
+///
+/// * `sha1-openssl`: OpenSSL of the system
+/// * `sha1-smol`: small pure Rust implementation without assembly
+/// * otherwise ring (`crypto`)
+///
+/// If multiple features are enabled, the first one of the list wins. Builds without ring need one
+/// of the other backends.
+pub trait Hasher {
+    /// Name of the backend in diagnostics
+    const NAME: &'static str;
+
+    /// SHA-1 of the concatenated parts - used to prefix the password with the pepper without
+    /// copying it
+    fn sha1(parts: &[&[u8]]) -> Sha1Hash;
+}
+
+/// Assembly optimized implementation of ring
+// only used by tests if another backend is selected
+#[cfg(feature = "crypto")]
+#[cfg_attr(any(feature = "sha1-smol", feature = "sha1-openssl"), allow(dead_code))]
+pub struct Ring;
+
+#[cfg(feature = "crypto")]
+impl Hasher for Ring {
+    const NAME: &'static str = "ring";
+
+    fn sha1(parts: &[&[u8]]) -> Sha1Hash {
+        let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+        for part in parts {
+            context.update(part);
+        }
+
+        // unwrap is safe, because sha-1 should fit into an array with that size
+        context.finish().as_ref().try_into().unwrap()
+    }
+}
+
+#[cfg(feature = "sha1-smol")]
+pub struct Smol;
+
+#[cfg(feature = "sha1-smol")]
+impl Hasher for Smol {
+    const NAME: &'static str = "sha1_smol";
+
+    fn sha1(parts: &[&[u8]]) -> Sha1Hash {
+        let mut context = sha1_smol::Sha1::new();
+        for part in parts {
+            context.update(part);
+        }
+
+        context.digest().bytes()
+    }
+}
+
+#[cfg(feature = "sha1-openssl")]
+pub struct OpenSsl;
+
+#[cfg(feature = "sha1-openssl")]
+impl Hasher for OpenSsl {
+    const NAME: &'static str = "openssl";
+
+    fn sha1(parts: &[&[u8]]) -> Sha1Hash {
+        let mut context = openssl::sha::Sha1::new();
+        for part in parts {
+            context.update(part);
+        }
+
+        context.finish()
+    }
+}
+
+/// Backend selected by the features of this build
+#[cfg(feature = "sha1-openssl")]
+pub type Backend = OpenSsl;
+
+#[cfg(all(feature = "sha1-smol", not(feature = "sha1-openssl")))]
+pub type Backend = Smol;
+
+#[cfg(all(
+    feature = "crypto",
+    not(any(feature = "sha1-smol", feature = "sha1-openssl"))
+))]
+pub type Backend = Ring;
+
+#[cfg(not(any(feature = "crypto", feature = "sha1-smol", feature = "sha1-openssl")))]
+compile_error!("Select a SHA-1 backend: crypto, sha1-smol or sha1-openssl");
+
+/// Name of the selected backend
+pub const BACKEND_NAME: &str = <Backend as Hasher>::NAME;
+
+/// SHA-1 of the concatenated parts using the selected backend
+pub fn sha1(parts: &[&[u8]]) -> Sha1Hash {
+    Backend::sha1(parts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use data_encoding::HEXLOWER;
+
+    fn check<H: Hasher>() {
+        assert_eq!(
+            HEXLOWER.encode(&H::sha1(&[b"hello"])),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+            "{}",
+            H::NAME
+        );
+
+        // parts are hashed as if they were concatenated
+        assert_eq!(H::sha1(&[b"he", b"", b"llo"]), H::sha1(&[b"hello"]));
+        assert_eq!(
+            HEXLOWER.encode(&H::sha1(&[])),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            "{}",
+            H::NAME
+        );
+    }
+
+    #[test]
+    fn test_backends() {
+        #[cfg(feature = "crypto")]
+        check::<Ring>();
+        #[cfg(feature = "sha1-smol")]
+        check::<Smol>();
+        #[cfg(feature = "sha1-openssl")]
+        check::<OpenSsl>();
+    }
+}
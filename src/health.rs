@@ -0,0 +1,426 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    time::{Duration, SystemTime},
+};
+
+use bstr::ByteSlice;
+use log::info;
+
+use crate::{collect::SavedHash, find::Finding, report::Corpus};
+
+/// Passwords below this entropy are counted as weak - roughly the strength of a random 10
+/// character password using letters and digits
+const WEAK_ENTROPY_BITS: f64 = 60.0;
+
+/// Passwords unchanged for longer are counted as stale
+const STALE_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Weights of the health score components in points. The age is only included if the export
+/// contains change dates.
+const PWNED_WEIGHT: f64 = 40.0;
+const REUSE_WEIGHT: f64 = 25.0;
+const WEAK_WEIGHT: f64 = 25.0;
+const STALE_WEIGHT: f64 = 10.0;
+
+const LENGTH_BUCKETS: [(&str, u32); 5] = [
+    ("0-7", 8),
+    ("8-11", 12),
+    ("12-15", 16),
+    ("16-19", 20),
+    ("20+", u32::MAX),
+];
+
+const ENTROPY_BUCKETS: [(&str, f64); 5] = [
+    ("very weak (<28 bits)", 28.0),
+    ("weak (28-35 bits)", 36.0),
+    ("reasonable (36-59 bits)", WEAK_ENTROPY_BITS),
+    ("strong (60-127 bits)", 128.0),
+    ("very strong (128+ bits)", f64::INFINITY),
+];
+
+const AGE_BUCKETS: [(&str, Duration); 4] = [
+    ("<90 days", Duration::from_secs(90 * 24 * 60 * 60)),
+    ("90 days-1 year", STALE_AGE),
+    ("1-2 years", Duration::from_secs(2 * 365 * 24 * 60 * 60)),
+    ("2+ years", Duration::MAX),
+];
+
+/// Strength of a password estimated before its clear text is dropped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Strength {
+    /// Number of characters - invalid UTF-8 sequences count as one character each
+    pub length: u32,
+    /// Number of possible characters of the used character classes
+    pub pool: u32,
+}
+
+impl Strength {
+    pub fn estimate(password: &[u8]) -> Self {
+        let (mut lower, mut upper, mut digit, mut symbol, mut other) =
+            (false, false, false, false, false);
+
+        let mut length = 0;
+        for c in password.chars() {
+            length += 1;
+            match c {
+                'a'..='z' => lower = true,
+                'A'..='Z' => upper = true,
+                '0'..='9' => digit = true,
+                ' '..='~' => symbol = true,
+                _ => other = true,
+            }
+        }
+
+        let pool = [
+            (lower, 26),
+            (upper, 26),
+            (digit, 10),
+            (symbol, 33),
+            (other, 100),
+        ]
+        .iter()
+        .filter(|(used, _)| *used)
+        .map(|(_, size)| size)
+        .sum();
+
+        Strength { length, pool }
+    }
+
+    /// Upper bound of the entropy in bits assuming randomly chosen characters from the used
+    /// character classes. Dictionary words are rated much too strong, but the pwned check covers
+    /// the common ones.
+    pub fn entropy(&self) -> f64 {
+        if self.pool == 0 {
+            0.0
+        } else {
+            f64::from(self.length) * f64::from(self.pool).log2()
+        }
+    }
+}
+
+/// Aggregated health of all saved passwords
+#[derive(Debug, PartialEq)]
+pub struct Health {
+    pub accounts: usize,
+    pub pwned: usize,
+    /// Accounts sharing their password with another account
+    pub reused: usize,
+    pub weak: usize,
+    pub lengths: [usize; LENGTH_BUCKETS.len()],
+    pub entropies: [usize; ENTROPY_BUCKETS.len()],
+    /// Accounts per age bucket - only counts accounts with a known change or creation date
+    pub ages: [usize; AGE_BUCKETS.len()],
+    pub stale: usize,
+    pub unknown_age: usize,
+}
+
+impl Health {
+    /// Evaluate the sorted saved hashes with the findings of all hash databases
+    pub fn evaluate(
+        hashes: &[SavedHash],
+        results: &[(Corpus<'_>, Vec<Finding>)],
+        now: SystemTime,
+    ) -> Self {
+        let mut pwned = vec![false; hashes.len()];
+        for (_, findings) in results {
+            for finding in findings {
+                pwned[finding.index] = true;
+            }
+        }
+
+        let mut health = Health {
+            accounts: hashes.len(),
+            pwned: pwned.iter().filter(|&&pwned| pwned).count(),
+            reused: 0,
+            weak: 0,
+            lengths: [0; LENGTH_BUCKETS.len()],
+            entropies: [0; ENTROPY_BUCKETS.len()],
+            ages: [0; AGE_BUCKETS.len()],
+            stale: 0,
+            unknown_age: 0,
+        };
+
+        for (index, saved) in hashes.iter().enumerate() {
+            // sorted, so the same passwords are next to each other
+            let same_as = |other: Option<&SavedHash>| {
+                other.is_some_and(|other| other.password_hash == saved.password_hash)
+            };
+            if same_as(index.checked_sub(1).map(|prev| &hashes[prev]))
+                || same_as(hashes.get(index + 1))
+            {
+                health.reused += 1;
+            }
+
+            let strength = saved.strength;
+            let length = LENGTH_BUCKETS
+                .iter()
+                .position(|&(_, below)| strength.length < below);
+            // unwrap is safe, because the last bucket has no upper limit
+            health.lengths[length.unwrap()] += 1;
+
+            let entropy = strength.entropy();
+            let bucket = ENTROPY_BUCKETS
+                .iter()
+                .position(|&(_, below)| entropy < below);
+            health.entropies[bucket.unwrap()] += 1;
+            if entropy < WEAK_ENTROPY_BITS {
+                health.weak += 1;
+            }
+
+            match saved.times.password_set() {
+                Some(changed) => {
+                    let age = now.duration_since(changed).unwrap_or_default();
+                    let bucket = AGE_BUCKETS.iter().position(|&(_, below)| age < below);
+                    health.ages[bucket.unwrap_or(AGE_BUCKETS.len() - 1)] += 1;
+                    if age >= STALE_AGE {
+                        health.stale += 1;
+                    }
+                }
+                None => health.unknown_age += 1,
+            }
+        }
+
+        health
+    }
+
+    /// Single number from 0 (all passwords pwned, reused and weak) to 100 to track improvements
+    pub fn score(&self) -> u8 {
+        if self.accounts == 0 {
+            return 100;
+        }
+
+        let ratio = |count: usize, total: usize| count as f64 / total as f64;
+        let mut components = vec![
+            (PWNED_WEIGHT, ratio(self.pwned, self.accounts)),
+            (REUSE_WEIGHT, ratio(self.reused, self.accounts)),
+            (WEAK_WEIGHT, ratio(self.weak, self.accounts)),
+        ];
+
+        let known_age = self.accounts - self.unknown_age;
+        if known_age > 0 {
+            components.push((STALE_WEIGHT, ratio(self.stale, known_age)));
+        }
+
+        let max: f64 = components.iter().map(|(weight, _)| weight).sum();
+        let points: f64 = components
+            .iter()
+            .map(|(weight, bad)| weight * (1.0 - bad))
+            .sum();
+        (points / max * 100.0).round() as u8
+    }
+
+    /// Print a compact text dashboard
+    pub fn print(&self) {
+        info!("Vault health score: {}/100", self.score());
+        info!(
+            "Pwned: {}, reused: {}, weak: {} of {} accounts",
+            self.pwned, self.reused, self.weak, self.accounts
+        );
+
+        let total = self.accounts.max(1);
+        info!("Password length:");
+        for ((label, _), &count) in LENGTH_BUCKETS.iter().zip(&self.lengths) {
+            info!("  {:>24} {}", label, bar(count, total));
+        }
+
+        info!("Estimated entropy:");
+        for ((label, _), &count) in ENTROPY_BUCKETS.iter().zip(&self.entropies) {
+            info!("  {:>24} {}", label, bar(count, total));
+        }
+
+        if self.unknown_age < self.accounts {
+            info!("Last password change:");
+            for ((label, _), &count) in AGE_BUCKETS.iter().zip(&self.ages) {
+                info!("  {:>24} {}", label, bar(count, total));
+            }
+        }
+
+        if self.unknown_age > 0 {
+            info!("Unknown age: {} accounts", self.unknown_age);
+        }
+    }
+
+    /// Standalone HTML page of the dashboard
+    pub fn write_html(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut tables = String::new();
+        let lengths = LENGTH_BUCKETS.iter().map(|(label, _)| *label);
+        html_table(
+            &mut tables,
+            "Password length",
+            lengths,
+            &self.lengths,
+            self.accounts,
+        );
+
+        let entropies = ENTROPY_BUCKETS.iter().map(|(label, _)| *label);
+        html_table(
+            &mut tables,
+            "Estimated entropy",
+            entropies,
+            &self.entropies,
+            self.accounts,
+        );
+
+        if self.unknown_age < self.accounts {
+            let ages = AGE_BUCKETS.iter().map(|(label, _)| *label);
+            html_table(
+                &mut tables,
+                "Last password change",
+                ages,
+                &self.ages,
+                self.accounts,
+            );
+        }
+
+        write!(
+            writer,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Vault health</title>\
+            <style>body{{font-family:sans-serif}}td{{padding:2px 8px}}\
+            .bar{{background:#c33;height:1em;min-width:1px}}</style></head><body>\
+            <h1>Vault health score: {}/100</h1>\
+            <p>Pwned: {}, reused: {}, weak: {} of {} accounts</p>{}</body></html>\n",
+            self.score(),
+            self.pwned,
+            self.reused,
+            self.weak,
+            self.accounts,
+            tables
+        )
+    }
+}
+
+/// Histogram as table with bars relative to the total
+fn html_table<'a>(
+    out: &mut String,
+    title: &str,
+    labels: impl Iterator<Item = &'a str>,
+    counts: &[usize],
+    total: usize,
+) {
+    // unwrap is safe, because writing to a string never fails
+    write!(out, "<h2>{}</h2><table>", title).unwrap();
+    for (label, &count) in labels.zip(counts) {
+        let percent = count as f64 / total.max(1) as f64 * 100.0;
+        write!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width:{:.1}%\"></div></td></tr>",
+            label, count, percent
+        )
+        .unwrap();
+    }
+
+    out.push_str("</table>");
+}
+
+/// Text bar of the count relative to the total
+fn bar(count: usize, total: usize) -> String {
+    const WIDTH: usize = 30;
+    let filled = (count * WIDTH + total / 2) / total;
+    format!("{:<width$} {}", "#".repeat(filled), count, width = WIDTH)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn saved(hash_byte: u8, password: &str, changed_days_ago: Option<u64>) -> SavedHash {
+        let mut saved = SavedHash::new("https://example.com/", "user", [hash_byte; 20]);
+        saved.strength = Strength::estimate(password.as_bytes());
+        saved.times.changed =
+            changed_days_ago.map(|days| UNIX_EPOCH + Duration::from_secs((1000 - days) * DAY));
+        saved
+    }
+
+    #[test]
+    fn test_estimate() {
+        assert_eq!(Strength::estimate(b""), Strength::default());
+
+        let digits = Strength::estimate(b"1234");
+        assert_eq!(digits.length, 4);
+        assert!((digits.entropy() - 4.0 * 10f64.log2()).abs() < 1e-9);
+
+        // non-ASCII characters count once
+        let umlauts = Strength::estimate("äöü".as_bytes());
+        assert_eq!(umlauts.length, 3);
+
+        let mixed = Strength::estimate(b"Correct-Horse-7");
+        assert!(mixed.entropy() > WEAK_ENTROPY_BITS);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let hashes = [
+            saved(1, "password", Some(10)),
+            saved(2, "Tr0ub4dor&3-extended", Some(500)),
+            saved(2, "Tr0ub4dor&3-extended", None),
+            saved(3, "x7#Kq9!vLm2$Pw8@Zr4", Some(800)),
+        ];
+        let results = [(
+            Corpus {
+                name: "hibp.txt",
+                snapshot: None,
+                form: None,
+                newly_pwned: false,
+            },
+            vec![Finding {
+                index: 0,
+                count: Some(5),
+            }],
+        )];
+
+        let now = UNIX_EPOCH + Duration::from_secs(1000 * DAY);
+        let health = Health::evaluate(&hashes, &results, now);
+        assert_eq!(health.accounts, 4);
+        assert_eq!(health.pwned, 1);
+        assert_eq!(health.reused, 2);
+        assert_eq!(health.weak, 1);
+        assert_eq!(health.lengths, [0, 1, 0, 1, 2]);
+        assert_eq!(health.ages, [1, 0, 1, 1]);
+        assert_eq!(health.stale, 2);
+        assert_eq!(health.unknown_age, 1);
+
+        // 40 * 3/4 + 25 * 2/4 + 25 * 3/4 + 10 * 1/3 = 64.58 of 100 points
+        assert_eq!(health.score(), 65);
+
+        let mut html = Vec::new();
+        health.write_html(&mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("Vault health score: 65/100"));
+        assert!(html.contains("Last password change"));
+    }
+
+    #[test]
+    fn test_score_bounds() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000 * DAY);
+        assert_eq!(Health::evaluate(&[], &[], now).score(), 100);
+
+        let strong = [saved(1, "x7#Kq9!vLm2$Pw8@Zr4", None)];
+        assert_eq!(Health::evaluate(&strong, &[], now).score(), 100);
+
+        let weak = [saved(1, "abc", None), saved(1, "abc", None)];
+        let results = [(
+            Corpus {
+                name: "hibp.txt",
+                snapshot: None,
+                form: None,
+                newly_pwned: false,
+            },
+            vec![
+                Finding {
+                    index: 0,
+                    count: None,
+                },
+                Finding {
+                    index: 1,
+                    count: None,
+                },
+            ],
+        )];
+        assert_eq!(Health::evaluate(&weak, &results, now).score(), 0);
+    }
+}
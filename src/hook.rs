@@ -0,0 +1,103 @@
+use std::{
+    io,
+    process::{Command, Output},
+};
+
+use regex::RegexSet;
+
+/// Files that could contain credentials by accident - matched against the path in the repository
+pub const DEFAULT_PATTERNS: [&str; 6] = [
+    r"(^|/)\.env(\.[^/]*)?$",
+    r"\.env$",
+    r"(?i)\.csv$",
+    // Kubernetes Secrets, docker-compose and SOPS files
+    r"(?i)\.ya?ml$",
+    r"(?i)\.(enc|sops)\.json$",
+    // Ansible Vault files of the recommended layout (ex: group_vars/all/vault)
+    r"(^|/)vault$",
+];
+
+/// File with the content that is checked - for staged files the version of the git index, because
+/// the commit contains it even if the working tree differs
+pub struct CheckedFile {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+/// Added, copied, modified or renamed files of the git index that match one of the patterns
+pub fn staged_files(patterns: &RegexSet) -> io::Result<Vec<CheckedFile>> {
+    let output = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "--diff-filter=ACMR",
+        "-z",
+    ])?;
+
+    String::from_utf8_lossy(&output)
+        .split('\0')
+        .filter(|path| !path.is_empty() && patterns.is_match(path))
+        .map(|path| {
+            Ok(CheckedFile {
+                path: path.to_string(),
+                content: git(&["show", &format!(":{}", path)])?,
+            })
+        })
+        .collect()
+}
+
+/// Standard output of the git command - fails if git isn't installed or exits with an error
+fn git(args: &[&str]) -> io::Result<Vec<u8>> {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = Command::new("git").args(args).output()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "git {} {}: {}",
+            args[0],
+            status,
+            // only the error without the usage that follows it
+            String::from_utf8_lossy(&stderr)
+                .lines()
+                .next()
+                .unwrap_or_default()
+        )));
+    }
+
+    Ok(stdout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_patterns() {
+        let patterns = RegexSet::new(DEFAULT_PATTERNS).unwrap();
+        for path in [
+            ".env",
+            "app/.env.production",
+            "config/prod.env",
+            "exports/Vault.CSV",
+            "docker-compose.yml",
+            "k8s/secrets.yaml",
+            "secrets.enc.json",
+            "group_vars/all/vault",
+        ] {
+            assert!(patterns.is_match(path), "{}", path);
+        }
+
+        for path in [
+            "src/env.rs",
+            ".envrc",
+            "README.md",
+            "csv/readme.txt",
+            "package.json",
+            "vault/main.go",
+        ] {
+            assert!(!patterns.is_match(path), "{}", path);
+        }
+    }
+}
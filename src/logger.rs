@@ -1,23 +1,56 @@
-use log::{Level, LevelFilter, Metadata, Record};
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    io::{self, Write},
+    panic::{self, PanicHookInfo},
+    sync::Mutex,
+    thread,
+};
 
-struct SimpleLogger;
+use log::{error, Level, LevelFilter, Metadata, Record};
+
+type Capture = Box<dyn Fn(String) + Send>;
+
+/// Receiver of the messages instead of the terminal - used while the TUI owns the screen
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+#[derive(Default)]
+struct SimpleLogger {
+    /// The standard output is reserved for a structured report
+    stderr_only: bool,
+}
 
 impl log::Log for SimpleLogger {
-    fn enabled(&self, _: &Metadata<'_>) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // verbose output of dependencies (ex: each HTTP request) would hide our own messages
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME")) || metadata.level() <= Level::Warn
     }
 
     fn log(&self, record: &Record<'_>) {
         if self.enabled(record.metadata()) {
+            // unwrap is safe, because the sink never panics while holding the lock
+            if let Some(capture) = CAPTURE.lock().unwrap().as_ref() {
+                return match record.level() {
+                    Level::Error | Level::Info | Level::Warn => capture(record.args().to_string()),
+                    _ => capture(format!("Verbose: {}", record.args())),
+                };
+            }
+
             match record.level() {
                 Level::Error => eprintln!("{}", record.args()),
+                Level::Info | Level::Warn if self.stderr_only => eprintln!("{}", record.args()),
                 Level::Info | Level::Warn => println!("{}", record.args()),
+                _ if self.stderr_only => eprintln!("Verbose: {}", record.args()),
                 _ => println!("Verbose: {}", record.args()),
             }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        // nothing to do if the terminal is already closed
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+    }
 }
 
 fn set_verbose_level(verbose: bool) {
@@ -29,21 +62,110 @@ fn set_verbose_level(verbose: bool) {
     log::set_max_level(max_level);
 }
 
-pub fn set_logger(verbose: bool) {
+/// Messages are written to the standard error with `stderr_only`
+pub fn set_logger(verbose: bool, stderr_only: bool) {
     // Safety: safe, because we set it globally once
-    log::set_boxed_logger(Box::new(SimpleLogger)).unwrap();
+    log::set_boxed_logger(Box::new(SimpleLogger { stderr_only })).unwrap();
 
     set_verbose_level(verbose);
+    panic::set_hook(Box::new(log_panic));
+}
+
+/// Panics of the worker threads would otherwise only be printed to the standard error, which is
+/// hidden by the TUI or lost in unattended runs
+fn log_panic(info: &PanicHookInfo<'_>) {
+    let thread = thread::current();
+    let location = info
+        .location()
+        .map_or_else(String::new, |location| format!(" at {}", location));
+
+    // respects RUST_BACKTRACE like the default hook
+    let backtrace = Backtrace::capture();
+    let backtrace = match backtrace.status() {
+        BacktraceStatus::Captured => Some(backtrace),
+        _ => None,
+    };
+
+    error!(
+        "{}",
+        panic_message(thread.name(), &location, info.payload(), backtrace.as_ref())
+    );
+    log::logger().flush();
+}
+
+fn panic_message(
+    thread: Option<&str>,
+    location: &str,
+    payload: &(dyn Any + Send),
+    backtrace: Option<&Backtrace>,
+) -> String {
+    let reason = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+
+    let mut message = format!(
+        "Thread '{}' panicked{}: {}",
+        thread.unwrap_or("<unnamed>"),
+        location,
+        reason
+    );
+    match backtrace {
+        Some(backtrace) => message.push_str(&format!("\nStack backtrace:\n{}", backtrace)),
+        None => message.push_str(
+            "\nRun with the environment variable RUST_BACKTRACE=1 to include a backtrace",
+        ),
+    }
+
+    message
+}
+
+/// Send all messages to the sink instead of the terminal until `release` is called
+#[cfg(feature = "tui")]
+pub fn capture(sink: impl Fn(String) + Send + 'static) {
+    // unwrap is safe, because the sink never panics while holding the lock
+    *CAPTURE.lock().unwrap() = Some(Box::new(sink));
+}
+
+/// Print the messages to the terminal again
+#[cfg(feature = "tui")]
+pub fn release() {
+    // unwrap is safe, because the sink never panics while holding the lock
+    CAPTURE.lock().unwrap().take();
 }
 
 #[cfg(test)]
 mod test {
+    use log::Log;
+
     use super::*;
 
     fn is_allowed(level: Level) -> bool {
         level <= log::max_level()
     }
 
+    #[test]
+    fn test_dependency_filtered() {
+        let metadata = |target, level| Metadata::builder().target(target).level(level).build();
+
+        let logger = SimpleLogger::default();
+        assert!(logger.enabled(&metadata("pwned_check::find", Level::Debug)));
+        assert!(logger.enabled(&metadata("ureq::unit", Level::Warn)));
+        assert!(!logger.enabled(&metadata("ureq::unit", Level::Debug)));
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let message = panic_message(Some("main"), " at src/main.rs:1:1", &"failed", None);
+        assert!(message.starts_with("Thread 'main' panicked at src/main.rs:1:1: failed\n"));
+
+        let payload = String::from("index out of bounds");
+        let message = panic_message(None, "", &payload, Some(&Backtrace::force_capture()));
+        assert!(message.starts_with("Thread '<unnamed>' panicked: index out of bounds\n"));
+        assert!(message.contains("Stack backtrace:"));
+    }
+
     #[test]
     fn test_not_verbose() {
         set_verbose_level(false);
@@ -1,91 +1,3721 @@
 #![feature(assert_matches)]
 
-use std::{fs::File, io::Read};
+#[cfg(feature = "crypto")]
+use std::net::{SocketAddr, TcpListener};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+#[cfg(any(feature = "crypto", feature = "index"))]
+use std::{fs::File, io::Write};
 
-use clap::{crate_description, crate_name, crate_version, App, Arg};
-use log::{debug, error, info};
-use ring::digest::SHA1_OUTPUT_LEN;
+use clap::{crate_description, crate_name, crate_version, App, AppSettings, Arg, ArgMatches};
+#[cfg(feature = "crypto")]
+use data_encoding::HEXUPPER;
+use log::{debug, error, info, warn};
+use rayon::slice::ParallelSliceMut;
+use regex::{Regex, RegexSet};
+#[cfg(feature = "crypto")]
+use ring::signature::Ed25519KeyPair;
+#[cfg(feature = "vault-kv")]
+use secstr::SecStr;
+
+use crate::{
+    atomic::atomic_write,
+    cancel::{CancellationToken, Status},
+    collect::{
+        EntryFilter, FormHashes, HashAlgorithm, HashOptions, NtlmHashes, PasswordSource, SavedHash,
+        TagFilter, UnmatchedHashes,
+    },
+    encoding::PasswordEncoding,
+    find::{Footprint, HashSource, MapOptions, ScanOptions, Strategy},
+    generate::TestData,
+    health::Health,
+    meta::CorpusMeta,
+    metrics::{Amount, Metrics, Phase},
+    normalize::Normalization,
+    paging::PagingStats,
+    pepper::Pepper,
+    profiles::PwnedLimits,
+    progress::{CountingProgress, ProgressSink, TerminalProgress},
+    pseudonym::Pseudonymizer,
+    report::{
+        AccountReport, Corpus, RemediationFormat, ReportFormat, ReportOptions, StructuredReport,
+    },
+    sample::{Estimate, Sample},
+    stats::CorpusStats,
+};
+#[cfg(feature = "crypto")]
+use crate::{
+    attest::{Predicate, Statement, Subject},
+    bundle::Answer,
+    metrics::LatencyHistogram,
+};
 
 const PASSWORD_KEY: &str = "passwords_file";
 const HASH_KEY: &str = "hash_file";
 const VERBOSE_KEY: &str = "verbose";
+const STRATEGY_KEY: &str = "strategy";
+const STRICT_KEY: &str = "strict";
+const SHOW_CLEAN_KEY: &str = "show_clean";
+const NO_COLLAPSE_KEY: &str = "no_collapse";
+const INPUT_KEY: &str = "input";
+const INPUT_DIR_KEY: &str = "input_dir";
+const MAX_PWNED_KEY: &str = "max_pwned";
+const REPORT_FORMAT_KEY: &str = "report_format";
+const REPORTER_EXEC_KEY: &str = "reporter_exec";
+#[cfg(feature = "tui")]
+const TUI_KEY: &str = "tui";
+const HEALTH_KEY: &str = "health";
+const HEALTH_HTML_KEY: &str = "health_html";
+const REMEDIATION_KEY: &str = "remediation";
+const REMEDIATION_FORMAT_KEY: &str = "remediation_format";
+const TIMEOUT_KEY: &str = "timeout";
+const USER_AGENT_KEY: &str = "user_agent";
+const ONLINE_FALLBACK_KEY: &str = "online_fallback";
+const HYBRID_KEY: &str = "hybrid";
+const MAX_CORPUS_AGE_KEY: &str = "max_corpus_age";
+const PIPELINE_BUFFER_KEY: &str = "pipeline_buffer";
+const ENCODING_KEY: &str = "password_encoding";
+const NORMALIZE_KEY: &str = "normalize";
+const UNICODE_FORMS_KEY: &str = "check_unicode_forms";
+const PREFIX_KEY: &str = "prefix";
+const OUTPUT_KEY: &str = "output";
+#[cfg(feature = "crypto")]
+const BUNDLE_KEY: &str = "bundle";
+#[cfg(feature = "crypto")]
+const SIGNING_KEY_KEY: &str = "signing_key";
+#[cfg(feature = "crypto")]
+const PUBLIC_KEY_KEY: &str = "public_key";
+#[cfg(feature = "crypto")]
+const ANONYMIZE_KEY: &str = "anonymize";
+const PEPPER_KEY: &str = "pepper";
+const NTLM_KEY: &str = "ntlm";
+#[cfg(feature = "crypto")]
+const ATTEST_KEY: &str = "attest";
+#[cfg(feature = "crypto")]
+const SIGN_REPORT_KEY: &str = "sign_report";
+#[cfg(feature = "crypto")]
+const REPORT_SIGNATURE_KEY: &str = "report_signature";
+#[cfg(feature = "crypto")]
+const REPORT_KEY: &str = "report";
+#[cfg(feature = "crypto")]
+const SIGNATURE_KEY: &str = "signature";
+const INCLUDE_TAG_KEY: &str = "include_tag";
+const EXCLUDE_TAG_KEY: &str = "exclude_tag";
+const EXCLUDE_PATTERN_KEY: &str = "exclude_pattern";
+const SAMPLE_KEY: &str = "sample";
+const LINES_KEY: &str = "lines";
+const SEED_KEY: &str = "seed";
+const ACCOUNTS_KEY: &str = "accounts";
+const PWNED_FRACTION_KEY: &str = "pwned_fraction";
+const MIRROR_KEY: &str = "mirror";
+const MIRROR_LIST_KEY: &str = "mirror_list";
+const CHECKSUM_KEY: &str = "checksum";
+#[cfg(feature = "index")]
+const PREFIXES_ONLY_KEY: &str = "prefixes_only";
+#[cfg(feature = "index")]
+const MMAP_KEY: &str = "mmap";
+const HUGE_PAGES_KEY: &str = "huge_pages";
+const POPULATE_KEY: &str = "populate";
+#[cfg(any(feature = "http", feature = "index"))]
+const FORCE_KEY: &str = "force";
+const CALLER_KEY: &str = "caller";
+#[cfg(feature = "crypto")]
+const LISTEN_KEY: &str = "listen";
+#[cfg(feature = "crypto")]
+const UNIX_SOCKET_KEY: &str = "unix";
+#[cfg(feature = "crypto")]
+const TOKEN_FILE_KEY: &str = "token_file";
+#[cfg(feature = "crypto")]
+const RATE_LIMIT_KEY: &str = "rate_limit";
+#[cfg(feature = "crypto")]
+const MAX_COUNT_KEY: &str = "max_count";
+#[cfg(feature = "crypto")]
+const NO_WARM_UP_KEY: &str = "no_warm_up";
+const STAGED_KEY: &str = "staged";
+const FILES_KEY: &str = "files";
+const PATTERN_KEY: &str = "pattern";
+#[cfg(feature = "vault")]
+const VAULT_PASSWORD_FILE_KEY: &str = "vault_password_file";
+#[cfg(feature = "vault")]
+const AGE_KEY_FILE_KEY: &str = "age_key_file";
+#[cfg(feature = "vault-kv")]
+const VAULT_ADDRESS_KEY: &str = "address";
+#[cfg(feature = "vault-kv")]
+const MOUNT_KEY: &str = "mount";
+#[cfg(feature = "vault-kv")]
+const KV_VERSION_KEY: &str = "kv_version";
+#[cfg(feature = "vault-kv")]
+const SECRET_PREFIX_KEY: &str = "secret_prefix";
+#[cfg(feature = "vault-kv")]
+const FIELD_KEY: &str = "field";
+#[cfg(feature = "vault-kv")]
+const VAULT_TOKEN_FILE_KEY: &str = "vault_token_file";
+#[cfg(feature = "vault-kv")]
+const ROLE_ID_KEY: &str = "role_id";
+#[cfg(feature = "vault-kv")]
+const SECRET_ID_FILE_KEY: &str = "secret_id_file";
+#[cfg(feature = "vault-kv")]
+const CONFIRM_COUNT_KEY: &str = "confirm_count";
+#[cfg(feature = "vault-kv")]
+const ACKNOWLEDGE_KEY: &str = "acknowledge";
+#[cfg(feature = "http")]
+const API_KEY: &str = "api";
+#[cfg(feature = "http")]
+const REFRESH_KEY: &str = "refresh";
+
+const HASH_ONLY_COMMAND: &str = "hash-only";
+#[cfg(feature = "crypto")]
+const ANSWER_COMMAND: &str = "answer";
+#[cfg(feature = "crypto")]
+const APPLY_ANSWERS_COMMAND: &str = "apply-answers";
+const GENERATE_COMMAND: &str = "gen-test-data";
+const DOWNLOAD_COMMAND: &str = "download";
+#[cfg(feature = "http")]
+const SETUP_COMMAND: &str = "setup";
+#[cfg(feature = "http")]
+const PREFETCH_COMMAND: &str = "prefetch";
+const SCHEMA_COMMAND: &str = "schema";
+const PATHS_COMMAND: &str = "paths";
+const NATIVE_HOST_COMMAND: &str = "native-host";
+#[cfg(feature = "crypto")]
+const SERVE_COMMAND: &str = "serve";
+#[cfg(feature = "crypto")]
+const ATTEST_VERIFY_COMMAND: &str = "attest-verify";
+#[cfg(feature = "crypto")]
+const REPORT_VERIFY_COMMAND: &str = "report-verify";
+const HOOK_COMMAND: &str = "hook";
+#[cfg(feature = "index")]
+const CONVERT_COMMAND: &str = "convert";
+#[cfg(feature = "index")]
+const STATS_COMMAND: &str = "stats";
+#[cfg(feature = "vault-kv")]
+const VAULT_KV_COMMAND: &str = "vault-kv";
+
+/// Number of read passwords that could wait for a free hashing thread
+const DEFAULT_PIPELINE_BUFFER: &str = "128";
+
+/// Exit code if the run was stopped by the timeout - same as the coreutils `timeout` command
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Exit codes of the hook and vault-kv commands - any failure blocks the commit like grep
+/// distinguishes no match and errors. The main check uses them too, so CI pipelines could tell a
+/// scan exceeding --max-pwned from a scan that couldn't run.
+const PWNED_EXIT_CODE: i32 = 1;
+const CHECK_FAILED_EXIT_CODE: i32 = 2;
+
+/// Result of the main check for its exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CheckOutcome {
+    Completed,
+    /// More accounts are pwned than allowed by --max-pwned
+    Exceeded,
+    /// The scan couldn't run or was aborted - the report is missing or incomplete
+    Failed,
+}
+
+const SHA1_BYTE_LENGTH: usize = 20;
+
+type Sha1Hash = [u8; SHA1_BYTE_LENGTH];
+
+/// Length of the MD4 based hashes used by Windows and Active Directory
+const NTLM_BYTE_LENGTH: usize = 16;
+
+type NtlmHash = [u8; NTLM_BYTE_LENGTH];
+
+fn main() {
+    let matches = create_cli_options().get_matches();
+
+    // unwrap is safe, because it has a default value restricted to the possible values
+    let format: ReportFormat = matches.value_of_t(REPORT_FORMAT_KEY).unwrap();
+
+    let verbose = matches.is_present(VERBOSE_KEY);
+
+    // the standard output of the native host is the channel to the browser
+    let stderr_only =
+        format.is_structured() || matches.subcommand_name() == Some(NATIVE_HOST_COMMAND);
+    logger::set_logger(verbose, stderr_only);
+
+    // the mapped hash lists of all commands and threads share the process-wide handler
+    #[cfg(unix)]
+    find::install_bus_error_handler();
+
+    #[cfg(any(feature = "http", feature = "vault-kv"))]
+    if let Some(user_agent) = matches
+        .value_of(USER_AGENT_KEY)
+        .map(str::to_string)
+        .or_else(|| read_config()?.user_agent)
+    {
+        debug!("Using the User-Agent {}", user_agent);
+        agent::set_user_agent(user_agent);
+    }
+
+    match matches.subcommand() {
+        Some((SCHEMA_COMMAND, _)) => return print!("{}", report::SCHEMA),
+        Some((PATHS_COMMAND, _)) => return print_paths(),
+        Some((NATIVE_HOST_COMMAND, sub_matches)) => return native_host(sub_matches),
+        #[cfg(feature = "crypto")]
+        Some((SERVE_COMMAND, sub_matches)) => return serve_api(sub_matches),
+        Some((HASH_ONLY_COMMAND, sub_matches)) => return hash_only(sub_matches),
+        #[cfg(feature = "crypto")]
+        Some((ANSWER_COMMAND, sub_matches)) => return answer(sub_matches),
+        #[cfg(feature = "crypto")]
+        Some((APPLY_ANSWERS_COMMAND, sub_matches)) => return apply_answers(sub_matches),
+        #[cfg(feature = "crypto")]
+        Some((ATTEST_VERIFY_COMMAND, sub_matches)) => return attest_verify(sub_matches),
+        #[cfg(feature = "crypto")]
+        Some((REPORT_VERIFY_COMMAND, sub_matches)) => return report_verify(sub_matches),
+        Some((HOOK_COMMAND, sub_matches)) => return hook(sub_matches),
+        #[cfg(feature = "vault-kv")]
+        Some((VAULT_KV_COMMAND, sub_matches)) => return vault_kv(sub_matches),
+        Some((GENERATE_COMMAND, sub_matches)) => return generate_test_data(sub_matches),
+        #[cfg(feature = "http")]
+        Some((DOWNLOAD_COMMAND, sub_matches)) => return download(sub_matches),
+        #[cfg(feature = "http")]
+        Some((SETUP_COMMAND, _)) => return setup(),
+        #[cfg(feature = "http")]
+        Some((PREFETCH_COMMAND, sub_matches)) => return prefetch(sub_matches),
+        #[cfg(feature = "index")]
+        Some((CONVERT_COMMAND, sub_matches)) => return convert(sub_matches),
+        #[cfg(feature = "index")]
+        Some((STATS_COMMAND, sub_matches)) => return write_stats(sub_matches),
+        _ => {}
+    }
+
+    // unwrap is safe, because the values are checked by the validator
+    let mut labeled: Vec<(String, OsString)> = matches
+        .values_of(INPUT_KEY)
+        .into_iter()
+        .flatten()
+        .map(|value| {
+            let (name, path) = parse_input(value).unwrap();
+            (name.to_string(), OsString::from(path))
+        })
+        .collect();
+    for directory in matches.values_of_os(INPUT_DIR_KEY).into_iter().flatten() {
+        match directory_inputs(Path::new(directory)) {
+            Ok(inputs) if inputs.is_empty() => {
+                error!("No exports found in the input directory {:?}", directory);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+            Ok(inputs) => labeled.extend(inputs),
+            Err(err) => {
+                error!("Cannot read the input directory {:?} {}", directory, err);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+        }
+    }
+
+    let inputs: Vec<(Option<&str>, &OsStr)> = if labeled.is_empty() {
+        // unwrap is safe here, because the argument is required without a subcommand or inputs
+        vec![(None, matches.value_of_os(PASSWORD_KEY).unwrap())]
+    } else {
+        labeled
+            .iter()
+            .map(|(name, path)| (Some(name.as_str()), path.as_os_str()))
+            .collect()
+    };
+
+    let profiles: Vec<String> = labeled.iter().map(|(name, _)| name.clone()).collect();
+    if let Some(name) = profiles
+        .iter()
+        .enumerate()
+        .find_map(|(index, name)| profiles[..index].contains(name).then_some(name))
+    {
+        error!("The profile {} is given multiple times", name);
+        process::exit(CHECK_FAILED_EXIT_CODE);
+    }
+
+    // unwrap is safe, because the values are checked by the validator
+    let pwned_limits = PwnedLimits::new(
+        matches
+            .values_of(MAX_PWNED_KEY)
+            .into_iter()
+            .flatten()
+            .map(|value| parse_limit(value).unwrap()),
+    );
+    if let Some(name) = pwned_limits.unknown_profile(&profiles) {
+        error!("The limit of {} doesn't match any input", name);
+        process::exit(CHECK_FAILED_EXIT_CODE);
+    }
+
+    // all positional arguments are hash lists if the inputs are labeled
+    let positional_corpus = if labeled.is_empty() {
+        None
+    } else {
+        matches.value_of_os(PASSWORD_KEY)
+    };
+
+    let discovered = if matches.is_present(HASH_KEY)
+        || positional_corpus.is_some()
+        || matches.is_present(NTLM_KEY)
+    {
+        None
+    } else {
+        match default_corpus() {
+            Some(corpus) => Some(corpus),
+            None => process::exit(CHECK_FAILED_EXIT_CODE),
+        }
+    };
+
+    let hash_paths: Vec<(&OsStr, HashAlgorithm)> = positional_corpus
+        .into_iter()
+        .chain(matches.values_of_os(HASH_KEY).into_iter().flatten())
+        .chain(discovered.as_deref())
+        .map(|path| (path, HashAlgorithm::Sha1))
+        .chain(
+            matches
+                .values_of_os(NTLM_KEY)
+                .into_iter()
+                .flatten()
+                .map(|path| (path, HashAlgorithm::Ntlm)),
+        )
+        .collect();
+
+    // unwrap is safe, because it has a default value restricted to the possible values
+    let strategy: Strategy = matches.value_of_t(STRATEGY_KEY).unwrap();
+    let options = ScanOptions {
+        strategy,
+        strict: matches.is_present(STRICT_KEY),
+        mapping: MapOptions {
+            populate: matches.is_present(POPULATE_KEY),
+            huge_pages: matches.is_present(HUGE_PAGES_KEY),
+        },
+        watch: true,
+    };
+
+    // unwrap is safe, because the value is checked by the validator
+    let timeout = matches
+        .value_of(TIMEOUT_KEY)
+        .map(|value| parse_duration(value).unwrap());
+
+    // unwrap is safe, because it has a default value restricted to the possible values
+    let encoding: PasswordEncoding = matches.value_of_t(ENCODING_KEY).unwrap();
+
+    // unwrap is safe, because it has a default value checked by the validator
+    let pipeline_buffer: usize = matches.value_of_t(PIPELINE_BUFFER_KEY).unwrap();
+
+    debug!("Using passwords files: {:?}", inputs);
+    debug!("Using hash files: {:?}", hash_paths);
+
+    #[cfg(feature = "http")]
+    let online_fallback = matches.is_present(ONLINE_FALLBACK_KEY);
+    #[cfg(not(feature = "http"))]
+    let online_fallback = false;
+
+    #[cfg_attr(not(feature = "http"), allow(unused_mut, unused_variables))]
+    let (mut hash_files, fallback) =
+        match open_hash_files(hash_paths, online_fallback, max_corpus_age(&matches)) {
+            Some(opened) => opened,
+            None => process::exit(CHECK_FAILED_EXIT_CODE),
+        };
+
+    // after the local files, so their matches are known
+    #[cfg(feature = "http")]
+    if matches.is_present(HYBRID_KEY) {
+        if fallback {
+            info!("Skipping the hybrid lookup, because the online range API is already used");
+        } else {
+            hash_files.push(HashFile::online(true));
+        }
+    }
+
+    let mut pseudonyms = match load_pseudonymizer(&matches) {
+        Some(pseudonyms) => pseudonyms,
+        None => process::exit(CHECK_FAILED_EXIT_CODE),
+    };
+
+    let pepper = match load_pepper(&matches) {
+        Some(pepper) => pepper,
+        None => process::exit(CHECK_FAILED_EXIT_CODE),
+    };
+
+    #[cfg(feature = "crypto")]
+    let attest_key = match matches.value_of_os(SIGNING_KEY_KEY).map(Path::new) {
+        Some(key_path) => match bundle::load_or_create_key(key_path) {
+            Ok(key) => {
+                info!(
+                    "Public key for attest-verify: {}",
+                    bundle::public_key_hex(&key)
+                );
+                Some(key)
+            }
+            Err(err) => {
+                error!("Cannot load signing key {:?} {}", key_path, err);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+        },
+        None => None,
+    };
+
+    #[cfg(feature = "crypto")]
+    let report_key = match matches.value_of_os(SIGN_REPORT_KEY).map(Path::new) {
+        // the signature covers the JSON document - the other formats have no canonical form
+        Some(_) if format != ReportFormat::Json => {
+            error!("Signing the report requires --report-format json");
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+        Some(key_path) => match bundle::load_or_create_key(key_path) {
+            Ok(key) => {
+                info!(
+                    "Public key for report-verify: {}",
+                    bundle::public_key_hex(&key)
+                );
+                Some(key)
+            }
+            Err(err) => {
+                error!("Cannot load report signing key {:?} {}", key_path, err);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+        },
+        None => None,
+    };
+
+    let cancel = CancellationToken::default();
+    #[cfg(feature = "ctrl-c")]
+    {
+        let handler_token = cancel.clone();
+        if let Err(err) = ctrlc::set_handler(move || handler_token.cancel()) {
+            error!("Failed to register Ctrl-C handler {}", err);
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    let tui = if matches.is_present(TUI_KEY) {
+        match tui::Tui::start(cancel.clone()) {
+            Ok(tui) => Some(tui),
+            Err(err) => {
+                error!("Cannot start the interactive interface {}", err);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+        }
+    } else {
+        None
+    };
+
+    // set by the timer, so a cancellation using Ctrl-C isn't reported as timeout
+    let timed_out = Arc::new(AtomicBool::new(false));
+    if let Some(timeout) = timeout {
+        let timer_token = cancel.clone();
+        let timer_flag = timed_out.clone();
+        // detached, because the process exits anyway after the run
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            timer_flag.store(true, Ordering::Relaxed);
+            timer_token.cancel();
+        });
+    }
+
+    let readers: Option<Vec<_>> = inputs
+        .iter()
+        .map(
+            |(profile, path)| match detect::open(Path::new(path), encoding) {
+                Ok(source) => Some((profile.map(str::to_string), source)),
+                Err(err) => {
+                    error!("Cannot access password file {:?} {}", path, err);
+                    None
+                }
+            },
+        )
+        .collect();
+
+    // failed to open one of the exports
+    let mut outcome = CheckOutcome::Failed;
+    if let Some(readers) = readers {
+        let reporters: Vec<_> = matches
+            .values_of(REPORTER_EXEC_KEY)
+            .into_iter()
+            .flatten()
+            .collect();
+        let report_options = ReportOptions {
+            format,
+            show_clean: matches.is_present(SHOW_CLEAN_KEY),
+            collapse_variants: !matches.is_present(NO_COLLAPSE_KEY),
+            health: matches.is_present(HEALTH_KEY) || matches.is_present(HEALTH_HTML_KEY),
+            health_html: matches.value_of_os(HEALTH_HTML_KEY).map(Path::new),
+            // unwrap is safe, because the format has a default and is one of the possible values
+            remediation: matches.value_of_os(REMEDIATION_KEY).map(|path| {
+                (
+                    Path::new(path),
+                    matches.value_of_t(REMEDIATION_FORMAT_KEY).unwrap(),
+                )
+            }),
+            reporters: &reporters,
+            #[cfg(feature = "tui")]
+            tui: tui.as_ref(),
+            #[cfg(feature = "crypto")]
+            attest: matches
+                .value_of_os(ATTEST_KEY)
+                .map(Path::new)
+                .zip(attest_key.as_ref()),
+            #[cfg(feature = "crypto")]
+            sign_report: matches
+                .value_of_os(REPORT_SIGNATURE_KEY)
+                .map(Path::new)
+                .zip(report_key.as_ref()),
+            // unwrap is safe, because the value is checked by the validator
+            sample: matches
+                .value_of(SAMPLE_KEY)
+                .map(|value| Sample::random(parse_percent(value).unwrap())),
+            pwned_limits: &pwned_limits,
+        };
+
+        let hashing = HashOptions {
+            pipeline_buffer,
+            pepper,
+            ntlm: hash_files
+                .iter()
+                .any(|file| file.algorithm == HashAlgorithm::Ntlm),
+            filter: entry_filter(&matches),
+            normalization: normalization(&matches),
+            unicode_forms: matches.is_present(UNICODE_FORMS_KEY),
+        };
+
+        outcome = run(
+            readers,
+            &hash_files,
+            options,
+            hashing,
+            report_options,
+            &mut pseudonyms,
+            &cancel,
+        );
+        #[cfg(feature = "crypto")]
+        save_mapping(&matches, &pseudonyms);
+    }
+
+    #[cfg(feature = "tui")]
+    if let Some(tui) = tui {
+        if let Err(err) = tui.wait() {
+            error!("Interactive interface failed {}", err);
+        }
+    }
+
+    let timed_out = timed_out.load(Ordering::Relaxed);
+    let code = exit_code(outcome, timed_out);
+    if let Some(timeout) = timeout.filter(|_| code == Some(TIMEOUT_EXIT_CODE)) {
+        error!("Stopped after reaching the timeout of {:?}", timeout);
+    }
+
+    if let Some(code) = code {
+        process::exit(code);
+    }
+}
+
+/// Exit code of the outcome - none for a successful run. The timeout is only reported if the timer
+/// cancelled the run and not Ctrl-C.
+fn exit_code(outcome: CheckOutcome, timed_out: bool) -> Option<i32> {
+    match outcome {
+        CheckOutcome::Completed => None,
+        CheckOutcome::Exceeded => Some(PWNED_EXIT_CODE),
+        // a run that completed just before the timer fired keeps its outcome
+        CheckOutcome::Failed if timed_out => Some(TIMEOUT_EXIT_CODE),
+        CheckOutcome::Failed => Some(CHECK_FAILED_EXIT_CODE),
+    }
+}
+
+/// Export the hashes of the saved passwords without scanning any hash database
+fn hash_only(matches: &ArgMatches) {
+    // unwrap is safe, because the argument is required
+    let passwords_file = matches.value_of_os(PASSWORD_KEY).unwrap();
+
+    // unwrap is safe, because it has a default value restricted to the possible values
+    let encoding: PasswordEncoding = matches.value_of_t(ENCODING_KEY).unwrap();
+    let prefix_only = matches.is_present(PREFIX_KEY);
+
+    let mut pseudonyms = match load_pseudonymizer(matches) {
+        Some(pseudonyms) => pseudonyms,
+        None => return,
+    };
+
+    let pepper = match load_pepper(matches) {
+        Some(pepper) => pepper,
+        None => return,
+    };
+
+    let source = match detect::open(Path::new(passwords_file), encoding) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("Cannot access password file {}", err);
+            return;
+        }
+    };
+
+    let hashing = HashOptions {
+        // unwrap is safe, because the default value is a valid number
+        pipeline_buffer: DEFAULT_PIPELINE_BUFFER.parse().unwrap(),
+        pepper,
+        ntlm: false,
+        filter: entry_filter(matches),
+        normalization: normalization(matches),
+        unicode_forms: false,
+    };
+
+    let cancel = CancellationToken::default();
+    let mut metrics = Metrics::default();
+    let collected = collect::collect_hashes(source, &hashing, &cancel, &mut metrics);
+
+    // zero the pepper as soon as it's not needed anymore
+    drop(hashing);
+    let mut hashes = match collected {
+        Ok(status) => status.into_inner(),
+        Err(err) => {
+            error!("Failed to collect saved passwords: {}", err);
+            return;
+        }
+    };
+
+    // sorted output doesn't reveal the order of the export
+    hashes.par_sort_unstable();
+
+    let written = match matches.value_of_os(OUTPUT_KEY) {
+        Some(path) => atomic_write(Path::new(path), |file| {
+            export::write_hashes(file, &hashes, prefix_only, &mut pseudonyms)
+        }),
+        None => export::write_hashes(io::stdout().lock(), &hashes, prefix_only, &mut pseudonyms),
+    };
+
+    match written {
+        Ok(()) => debug!("Exported {} hashes", hashes.len()),
+        Err(err) => error!("Failed to write hashes {}", err),
+    }
+
+    #[cfg(feature = "crypto")]
+    save_mapping(matches, &pseudonyms);
+}
+
+/// Write a synthetic hash database and a matching passwords export into the output directory
+fn generate_test_data(matches: &ArgMatches) {
+    // unwrap is safe, because the values are required or have defaults checked by the validators
+    let output = Path::new(matches.value_of_os(OUTPUT_KEY).unwrap());
+    let data = TestData {
+        lines: matches.value_of_t(LINES_KEY).unwrap(),
+        seed: matches.value_of_t(SEED_KEY).unwrap(),
+        accounts: matches.value_of_t(ACCOUNTS_KEY).unwrap(),
+        pwned_fraction: matches.value_of_t(PWNED_FRACTION_KEY).unwrap(),
+    };
+
+    if let Err(err) = fs::create_dir_all(output) {
+        error!("Cannot create output directory {:?} {}", output, err);
+        return;
+    }
+
+    let corpus_path = output.join("corpus.txt");
+    if let Err(err) = atomic_write(&corpus_path, |file| data.write_corpus(file)) {
+        error!("Cannot write hash database {:?} {}", corpus_path, err);
+        return;
+    }
+
+    let passwords_path = output.join("passwords.csv");
+    let written = atomic_write(&passwords_path, |file| data.write_passwords(file));
+    match written {
+        Ok(()) => info!("Generated {:?} and {:?}", corpus_path, passwords_path),
+        Err(err) => error!("Cannot write passwords {:?} {}", passwords_path, err),
+    }
+}
+
+/// Convert a text hash database into an FST index and write the prefix filter next to the result
+#[cfg(feature = "index")]
+fn convert(matches: &ArgMatches) {
+    // unwrap is safe, because the argument is required
+    let input = Path::new(matches.value_of_os(HASH_KEY).unwrap());
+    let strict = matches.is_present(STRICT_KEY);
+
+    if matches.is_present(PREFIXES_ONLY_KEY) {
+        let file = match File::open(input) {
+            Ok(file) => file,
+            Err(err) => {
+                error!("Cannot access hash file {:?} {}", input, err);
+                return;
+            }
+        };
+
+        let len = file.metadata().map_or(0, |metadata| metadata.len());
+        let mut progress = TerminalProgress::default();
+        match find::index::collect_prefixes(io::BufReader::new(file), len, strict, &mut progress) {
+            Ok(prefixes) => write_prefixes(&prefixes, input),
+            Err(err) => error!("Failed to read {:?} {}", input, err),
+        }
+
+        return;
+    }
+
+    // unwrap is safe, because it's required without the prefixes only flag
+    let output = Path::new(matches.value_of_os(OUTPUT_KEY).unwrap());
+    convert_index(
+        input,
+        output,
+        strict,
+        matches.is_present(MMAP_KEY),
+        matches.is_present(FORCE_KEY),
+    );
+}
+
+/// Convert the hash list into an index including its prefix filter - false if it failed
+#[cfg(feature = "index")]
+fn convert_index(input: &Path, output: &Path, strict: bool, mmap: bool, force: bool) -> bool {
+    let file = match File::open(input) {
+        Ok(file) => file,
+        Err(err) => {
+            error!("Cannot access hash file {:?} {}", input, err);
+            return false;
+        }
+    };
+
+    // held until the prefix filter is written, so it always belongs to the index
+    let _lock = match atomic::WriterLock::acquire(output) {
+        Ok(lock) => lock,
+        Err(err) => {
+            error!("Cannot convert into {:?} {}", output, err);
+            return false;
+        }
+    };
+
+    let len = file.metadata().map_or(0, |metadata| metadata.len());
+    let mut required = find::index::estimated_len(len);
+    if mmap {
+        // a stopped conversion already allocated its output
+        let allocated = fs::metadata(output).map_or(0, |metadata| metadata.len());
+        required = required.saturating_sub(allocated);
+    }
+
+    if !has_space(output, required, force) {
+        return false;
+    }
+
+    let mut progress = TerminalProgress::default();
+    let converted = if mmap {
+        find::index::convert_in_place(input, output, strict, &mut progress)
+    } else {
+        atomic_write(output, |file_out| {
+            let mut writer = io::BufWriter::new(file_out);
+            let converted = find::index::convert(
+                io::BufReader::new(file),
+                len,
+                &mut writer,
+                strict,
+                &mut progress,
+            )?;
+            writer.flush()?;
+            Ok(converted)
+        })
+    };
+
+    match converted {
+        Ok((hashes, prefixes)) => {
+            let size = fs::metadata(output).map_or(0, |metadata| metadata.len());
+            info!(
+                "Indexed {} hashes into {:?} - {:.0} % of the list size",
+                hashes,
+                output,
+                size as f64 * 100.0 / len.max(1) as f64
+            );
+            write_prefixes(&prefixes, output);
+            write_corpus_stats(output, strict);
+            true
+        }
+        Err(err) => {
+            error!("Failed to convert {:?} {}", input, err);
+            false
+        }
+    }
+}
+
+/// Store the prefix filter next to the hash database if it could skip any saved hashes
+#[cfg(feature = "index")]
+fn write_prefixes(prefixes: &find::prefix::PrefixFilter, corpus: &Path) {
+    if !prefixes.is_selective() {
+        info!("Every hash prefix exists - a prefix filter wouldn't skip any saved hashes");
+        return;
+    }
+
+    let len = fs::metadata(corpus).map_or(0, |metadata| metadata.len());
+    match prefixes.write(corpus, len) {
+        Ok(()) => info!("Wrote the prefix filter of {:?}", corpus),
+        Err(err) => error!("Failed to write the prefix filter of {:?} {}", corpus, err),
+    }
+}
+
+/// Count the hashes of the database by their counts and store the distribution next to it
+#[cfg(feature = "index")]
+fn write_stats(matches: &ArgMatches) {
+    // unwrap is safe, because the argument is required
+    let corpus = Path::new(matches.value_of_os(HASH_KEY).unwrap());
+    write_corpus_stats(corpus, matches.is_present(STRICT_KEY));
+}
+
+#[cfg(feature = "index")]
+fn write_corpus_stats(corpus: &Path, strict: bool) {
+    let file = match File::open(corpus) {
+        Ok(file) => file,
+        Err(err) => {
+            error!("Cannot access hash file {:?} {}", corpus, err);
+            return;
+        }
+    };
+
+    let map = match file.map() {
+        Ok(map) => map,
+        Err(err) => {
+            error!("Cannot access hash file {:?} {}", corpus, err);
+            return;
+        }
+    };
+
+    let mut progress = TerminalProgress::default();
+    let stats = match find::index::count_hashes(&map, strict, &mut progress) {
+        Ok(histogram) => histogram.stats(),
+        Err(err) => {
+            error!("Failed to count the hashes of {:?} {}", corpus, err);
+            return;
+        }
+    };
+
+    if stats.thresholds.is_empty() {
+        info!(
+            "No hashes with a count - the summary cannot compare the matches to the distribution"
+        );
+        return;
+    }
+
+    match stats.write(corpus) {
+        Ok(()) => info!("Wrote the count statistics of {:?}", corpus),
+        Err(err) => error!("Failed to write the statistics of {:?} {}", corpus, err),
+    }
+}
+
+/// Download a hash database from the fastest of the mirrors and verify its checksum
+#[cfg(feature = "http")]
+fn download(matches: &ArgMatches) {
+    let output = match matches.value_of_os(OUTPUT_KEY) {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => match discover::download_path() {
+            Ok(output) => output,
+            Err(err) => {
+                error!("Cannot create the data directory {}", err);
+                return;
+            }
+        },
+    };
+    let output = output.as_path();
+
+    let mut urls: Vec<String> = matches
+        .values_of(MIRROR_KEY)
+        .map_or_else(Vec::new, |values| values.map(str::to_string).collect());
+    if let Some(list_path) = matches.value_of_os(MIRROR_LIST_KEY) {
+        match fs::read_to_string(list_path) {
+            Ok(content) => urls.extend(download::parse_mirror_list(&content)),
+            Err(err) => {
+                error!("Cannot read mirror list {:?} {}", list_path, err);
+                return;
+            }
+        }
+    }
+
+    if let Some(err) = urls
+        .iter()
+        .find_map(|url| download::check_source(url).err())
+    {
+        error!("{}", err);
+        return;
+    }
+
+    let checksum: Option<download::Checksum> = match matches.value_of(CHECKSUM_KEY) {
+        // unwrap is safe, because the value is checked by the validator
+        Some(checksum) => Some(checksum.parse().unwrap()),
+        None => {
+            error!("No --checksum given - the integrity of the download isn't verified");
+            None
+        }
+    };
+
+    download_corpus(
+        &urls,
+        output,
+        checksum.as_ref(),
+        matches.is_present(FORCE_KEY),
+    );
+}
+
+/// Download the ranges of the saved passwords from the range API into the cache directory, so the
+/// same export could be checked again without the network
+#[cfg(feature = "http")]
+fn prefetch(matches: &ArgMatches) {
+    // unwrap is safe, because the argument is required
+    let passwords_file = matches.value_of_os(PASSWORD_KEY).unwrap();
+    // unwrap is safe, because it has a default value
+    let url = matches.value_of(API_KEY).unwrap();
+
+    // unwrap is safe, because it has a default value restricted to the possible values
+    let encoding: PasswordEncoding = matches.value_of_t(ENCODING_KEY).unwrap();
+    let source = match detect::open(Path::new(passwords_file), encoding) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("Cannot access password file {}", err);
+            return;
+        }
+    };
+
+    let hashing = HashOptions {
+        // unwrap is safe, because the default value is a valid number
+        pipeline_buffer: DEFAULT_PIPELINE_BUFFER.parse().unwrap(),
+        // the API only contains the plain hashes
+        pepper: Pepper::none(),
+        ntlm: false,
+        filter: entry_filter(matches),
+        normalization: normalization(matches),
+        unicode_forms: false,
+    };
+
+    let cancel = CancellationToken::default();
+    let collected = collect::collect_hashes(source, &hashing, &cancel, &mut Metrics::default());
+    let hashes = match collected {
+        Ok(status) => status.into_inner(),
+        Err(err) => {
+            error!("Failed to collect saved passwords: {}", err);
+            return;
+        }
+    };
+
+    let mut prefixes: Vec<u32> = hashes
+        .iter()
+        .map(|saved| find::RangePrefix::of(&saved.password_hash).value())
+        .collect();
+    prefixes.sort_unstable();
+    prefixes.dedup();
+
+    let api = find::RangeApi::new(url);
+    let dir = match api.disk_cache() {
+        Some(dir) => dir.clone(),
+        None => {
+            error!("Cannot find the cache directory for the ranges");
+            return;
+        }
+    };
+
+    info!(
+        "Prefetching {} ranges for {} accounts into {:?}",
+        prefixes.len(),
+        hashes.len(),
+        dir
+    );
+    match api.prefetch(&prefixes, matches.is_present(REFRESH_KEY)) {
+        Ok(prefetched) => {
+            info!(
+                "Downloaded {} ranges ({} KB) - {} were already cached",
+                prefetched.downloaded,
+                prefetched.bytes.div_ceil(1024),
+                prefetched.cached
+            );
+            info!(
+                "Check offline with: pwned-check {} {}",
+                passwords_file.to_string_lossy(),
+                url
+            );
+        }
+        Err(err) => error!("Failed to prefetch the ranges {}", err),
+    }
+}
+
+/// Download from the fastest of the mirrors - false if it failed
+#[cfg(feature = "http")]
+fn download_corpus(
+    urls: &[String],
+    output: &Path,
+    checksum: Option<&download::Checksum>,
+    force: bool,
+) -> bool {
+    let downloader = download::Downloader::default();
+    let mirrors = match downloader.rank_mirrors(urls) {
+        Ok(mirrors) => mirrors,
+        Err(err) => {
+            error!("Download failed {}", err);
+            return false;
+        }
+    };
+
+    if !has_space(output, mirrors[0].len, force) {
+        return false;
+    }
+
+    info!("Downloading from {}", mirrors[0].url);
+    let mut progress = TerminalProgress::default();
+    match downloader.download(&mirrors, output, checksum, &mut progress) {
+        Ok(()) => {
+            info!("Downloaded {:?}", output);
+            true
+        }
+        Err(err) => {
+            error!("Download failed {}", err);
+            false
+        }
+    }
+}
+
+/// Guide new users through choosing and preparing the hash list. The choice is written to the
+/// config, so the regular checks only need the password export.
+#[cfg(feature = "http")]
+fn setup() {
+    let answers = {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        match setup::ask(&mut stdin.lock(), &mut stdout.lock()) {
+            Ok(answers) => answers,
+            Err(err) => {
+                error!("Setup aborted {}", err);
+                return;
+            }
+        }
+    };
+
+    let config_path = match config::Config::path() {
+        Some(path) => path,
+        None => {
+            error!("Cannot find the home directory - cannot write the config");
+            return;
+        }
+    };
+
+    let corpus = match answers.corpus {
+        setup::CorpusChoice::Download {
+            mirror,
+            checksum,
+            index,
+        } => match prepare_download(mirror, checksum.as_ref(), index) {
+            Some(corpus) => corpus,
+            None => return,
+        },
+        setup::CorpusChoice::Remote(url) => url,
+        setup::CorpusChoice::Existing(path) => path.to_string_lossy().into_owned(),
+    };
+
+    // the other settings are kept if setup runs again
+    let config = config::Config {
+        corpus: Some(corpus),
+        ..read_config().unwrap_or_default()
+    };
+    if let Err(err) = config.write(&config_path) {
+        error!("Failed to write the config {:?} {}", config_path, err);
+        return;
+    }
+
+    info!("Wrote the config {:?}", config_path);
+    info!(
+        "Setup finished - run this command weekly after exporting your passwords from {:?}:",
+        answers.manager
+    );
+    info!("    {}", setup::weekly_command(&answers.export));
+    info!("Delete the export afterwards, because it contains your passwords in clear text");
+}
+
+/// Download the list into the data directory and optionally index it - the path of the corpus
+#[cfg(feature = "http")]
+fn prepare_download(
+    mirror: String,
+    checksum: Option<&download::Checksum>,
+    index: bool,
+) -> Option<String> {
+    let output = match discover::download_path() {
+        Ok(output) => output,
+        Err(err) => {
+            error!("Cannot create the data directory {}", err);
+            return None;
+        }
+    };
+
+    if !download_corpus(&[mirror], &output, checksum, false) {
+        return None;
+    }
+
+    #[cfg(feature = "index")]
+    if index {
+        let index_path = output.with_extension("fst");
+        if !convert_index(&output, &index_path, false, false, false) {
+            return None;
+        }
+
+        info!(
+            "The list {:?} could be deleted to save space - only the index is used",
+            output
+        );
+        return Some(index_path.to_string_lossy().into_owned());
+    }
+
+    #[cfg(not(feature = "index"))]
+    let _ = index;
+
+    Some(output.to_string_lossy().into_owned())
+}
+
+/// Fail early if the output doesn't fit on the disk instead of running out of space at the end
+#[cfg(any(feature = "http", feature = "index"))]
+fn has_space(output: &Path, required: u64, force: bool) -> bool {
+    match space::check_space(output, required) {
+        Ok(()) => true,
+        Err(err) if force => {
+            warn!("{} - continuing because of --force", err);
+            true
+        }
+        Err(err) => {
+            error!(
+                "{} - free some space, choose another output or start anyway with --force",
+                err
+            );
+            false
+        }
+    }
+}
+
+/// Pseudonymizer of the anonymize option - none if the key couldn't be loaded
+#[cfg(feature = "crypto")]
+fn load_pseudonymizer(matches: &ArgMatches) -> Option<Pseudonymizer> {
+    let key_path = match matches.value_of_os(ANONYMIZE_KEY) {
+        Some(key_path) => Path::new(key_path),
+        None => return Some(Pseudonymizer::disabled()),
+    };
+
+    match Pseudonymizer::load_or_create(key_path) {
+        Ok(pseudonyms) => Some(pseudonyms),
+        Err(err) => {
+            error!("Cannot load anonymization key {:?} {}", key_path, err);
+            None
+        }
+    }
+}
+
+/// Anonymization requires ring
+#[cfg(not(feature = "crypto"))]
+fn load_pseudonymizer(_matches: &ArgMatches) -> Option<Pseudonymizer> {
+    Some(Pseudonymizer::disabled())
+}
+
+fn load_pepper(matches: &ArgMatches) -> Option<Pepper> {
+    let value = match matches.value_of_os(PEPPER_KEY) {
+        Some(value) => value,
+        None => return Some(Pepper::none()),
+    };
+
+    match Pepper::load(value) {
+        Ok(pepper) => Some(pepper),
+        Err(err) => {
+            // the argument itself could be the secret
+            error!("Cannot load pepper {}", err);
+            None
+        }
+    }
+}
+
+fn entry_filter(matches: &ArgMatches) -> EntryFilter {
+    let values = |key| -> Vec<String> {
+        matches
+            .values_of(key)
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect()
+    };
+
+    EntryFilter {
+        tags: TagFilter {
+            include: values(INCLUDE_TAG_KEY),
+            exclude: values(EXCLUDE_TAG_KEY),
+        },
+        // unwrap is safe, because the patterns are checked by the validator
+        exclude_patterns: RegexSet::new(values(EXCLUDE_PATTERN_KEY)).unwrap(),
+    }
+}
+
+fn normalization(matches: &ArgMatches) -> Normalization {
+    // unwrap is safe, because the values are restricted to the possible values
+    Normalization::from_names(matches.values_of(NORMALIZE_KEY).into_iter().flatten()).unwrap()
+}
+
+/// Store the pseudonyms next to the key, so the shared report could be de-anonymized later
+#[cfg(feature = "crypto")]
+fn save_mapping(matches: &ArgMatches, pseudonyms: &Pseudonymizer) {
+    if let Some(key_path) = matches.value_of_os(ANONYMIZE_KEY) {
+        let mut mapping_path = key_path.to_os_string();
+        mapping_path.push(".mapping.csv");
+        if let Err(err) = pseudonyms.write_mapping(Path::new(&mapping_path)) {
+            error!("Cannot write pseudonym mapping {:?} {}", mapping_path, err);
+        }
+    }
+}
+
+/// Check the hashes of a bundle created by `hash-only` on the machine holding the hash databases
+/// and write the signed matches
+#[cfg(feature = "crypto")]
+fn answer(matches: &ArgMatches) {
+    // unwrap is safe, because the arguments are required
+    let bundle_path = matches.value_of_os(BUNDLE_KEY).unwrap();
+    let hash_paths: Vec<&OsStr> = matches.values_of_os(HASH_KEY).unwrap().collect();
+    let key_path = Path::new(matches.value_of_os(SIGNING_KEY_KEY).unwrap());
+    let output = matches.value_of_os(OUTPUT_KEY).unwrap();
+    let options = ScanOptions {
+        strategy: Strategy::Auto,
+        strict: matches.is_present(STRICT_KEY),
+        mapping: MapOptions::default(),
+        watch: true,
+    };
+
+    let mut entries = match File::open(bundle_path)
+        .map_err(bundle::BundleError::from)
+        .and_then(bundle::read_hashes)
+    {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("Cannot read hash bundle {}", err);
+            return;
+        }
+    };
+
+    let key = match bundle::load_or_create_key(key_path) {
+        Ok(key) => key,
+        Err(err) => {
+            error!("Cannot load signing key {:?} {}", key_path, err);
+            return;
+        }
+    };
+    info!(
+        "Public key for apply-answers: {}",
+        bundle::public_key_hex(&key)
+    );
+
+    // labels stay at the same index as the hash
+    entries.sort_unstable_by_key(|&(_, hash)| hash);
+    let hashes: Vec<SavedHash> = entries
+        .iter()
+        .map(|&(_, hash)| SavedHash::from_hash(hash))
+        .collect();
+
+    // highest count of each entry - outer none if not found at all
+    let mut counts: Vec<Option<Option<u64>>> = vec![None; entries.len()];
+    let cancel = CancellationToken::default();
+    let mut progress = TerminalProgress::default();
+    for path in hash_paths {
+        read_snapshot(path, max_corpus_age(matches));
+        let findings = find::open_source(path)
+            .and_then(|source| find::find_hash(&*source, &hashes, options, &mut progress, &cancel));
+
+        match findings {
+            Ok(status) => {
+                for finding in status.into_inner() {
+                    let count = counts[finding.index].get_or_insert(None);
+                    *count = (*count).max(finding.count);
+                }
+            }
+            Err(err) => {
+                error!("Cannot scan hash file {:?} {}", path, err);
+                return;
+            }
+        }
+    }
+
+    // clean accounts are marked with a zero count
+    let show_clean = matches.is_present(SHOW_CLEAN_KEY);
+    let answers: Vec<Answer> = entries
+        .into_iter()
+        .zip(counts)
+        .filter_map(|((label, hash), count)| {
+            let count = if show_clean {
+                Some(count.unwrap_or(Some(0)))
+            } else {
+                count
+            };
+
+            count.map(|count| Answer {
+                label,
+                sha1: HEXUPPER.encode(&hash),
+                count,
+            })
+        })
+        .collect();
+
+    match bundle::write_answers(&answers, &key) {
+        Ok(data) => match atomic_write(Path::new(output), |file| file.write_all(&data)) {
+            Ok(()) => info!(
+                "Wrote {} pwned accounts to the answer bundle",
+                answers.iter().filter(|answer| !answer.is_clean()).count()
+            ),
+            Err(err) => error!("Cannot write answer bundle {}", err),
+        },
+        Err(err) => error!("Cannot create answer bundle {}", err),
+    }
+}
+
+/// Verify the answer bundle and report the pwned accounts on the original machine
+#[cfg(feature = "crypto")]
+fn apply_answers(matches: &ArgMatches) {
+    // unwrap is safe, because the arguments are required
+    let bundle_path = matches.value_of_os(BUNDLE_KEY).unwrap();
+    let public_key = matches.value_of(PUBLIC_KEY_KEY).unwrap();
+
+    let public_key = match HEXUPPER.decode(public_key.to_ascii_uppercase().as_bytes()) {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            error!("Invalid public key {}", err);
+            return;
+        }
+    };
+
+    let answers = match fs::read(bundle_path)
+        .map_err(bundle::BundleError::from)
+        .and_then(|data| bundle::read_answers(&data, &public_key))
+    {
+        Ok(answers) => answers,
+        Err(err) => {
+            error!("Cannot apply answer bundle {}", err);
+            return;
+        }
+    };
+
+    let mut pwned = 0;
+    let mut clean = 0;
+    for answer in &answers {
+        if answer.is_clean() {
+            debug!("Clean account {}", answer.label);
+            clean += 1;
+        } else {
+            report::print_account(&answer.label, answer.count, "");
+            pwned += 1;
+        }
+    }
+
+    if clean > 0 {
+        report::print_clean_summary(clean, answers.len());
+    }
+
+    info!("Finished - {} pwned accounts", pwned);
+}
+
+/// Verify the attestation of a run and print the recorded summary
+#[cfg(feature = "crypto")]
+fn attest_verify(matches: &ArgMatches) {
+    // unwrap is safe, because the arguments are required
+    let attestation_path = matches.value_of_os(ATTEST_KEY).unwrap();
+    let public_key = matches.value_of(PUBLIC_KEY_KEY).unwrap();
+
+    let public_key = match HEXUPPER.decode(public_key.to_ascii_uppercase().as_bytes()) {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            error!("Invalid public key {}", err);
+            return;
+        }
+    };
+
+    let statement = match fs::read(attestation_path) {
+        Ok(data) => match attest::verify(&data, &public_key) {
+            Ok(statement) => statement,
+            Err(err) => {
+                error!("Invalid attestation {}", err);
+                return;
+            }
+        },
+        Err(err) => {
+            error!("Cannot read attestation {:?} {}", attestation_path, err);
+            return;
+        }
+    };
+
+    let predicate = &statement.predicate;
+    info!(
+        "Valid attestation of {} {} run by {} from {} to {}",
+        predicate.tool, predicate.version, predicate.runner, predicate.started, predicate.finished
+    );
+
+    for subject in &statement.subject {
+        match (&subject.digest, &subject.uri) {
+            (Some(digest), _) => info!("Hash list {} sha256:{}", subject.name, digest.sha256),
+            (None, Some(uri)) => {
+                let snapshot = subject.annotations.as_ref().map(|notes| &notes.snapshot);
+                info!(
+                    "Hash list {} from {} (snapshot {})",
+                    subject.name,
+                    uri,
+                    snapshot.map_or("unknown", String::as_str)
+                );
+            }
+            (None, None) => warn!("Hash list {} without a digest or URL", subject.name),
+        }
+    }
+
+    info!(
+        "Checked {} accounts - {} pwned, {} clean",
+        predicate.checked, predicate.pwned, predicate.clean
+    );
+
+    if !predicate.complete {
+        warn!("The run was cancelled before all hash lists were scanned");
+    }
+}
+
+/// Verify the signature of a stored JSON report
+#[cfg(feature = "crypto")]
+fn report_verify(matches: &ArgMatches) {
+    // unwrap is safe, because the arguments are required
+    let report_path = matches.value_of_os(REPORT_KEY).unwrap();
+    let signature_path = matches.value_of_os(SIGNATURE_KEY).unwrap();
+    let public_key = matches.value_of(PUBLIC_KEY_KEY).unwrap();
+
+    let public_key = match HEXUPPER.decode(public_key.to_ascii_uppercase().as_bytes()) {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            error!("Invalid public key {}", err);
+            return;
+        }
+    };
+
+    let canonical = match fs::read(report_path) {
+        Ok(data) => match report::canonicalize(&data) {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                error!("Invalid JSON report {}", err);
+                return;
+            }
+        },
+        Err(err) => {
+            error!("Cannot read report {:?} {}", report_path, err);
+            return;
+        }
+    };
+
+    let signature = match fs::read_to_string(signature_path) {
+        Ok(signature) => signature,
+        Err(err) => {
+            error!("Cannot read signature {:?} {}", signature_path, err);
+            return;
+        }
+    };
+
+    match report::verify(&canonical, &signature, &public_key) {
+        Ok(comment) => info!(
+            "Valid signature of the report ({})",
+            comment.replace('\t', " ")
+        ),
+        Err(err) => error!("Invalid report signature {}", err),
+    }
+}
+
+/// Parse a duration like `90`, `90s`, `15m`, `2h` or `1d` - plain numbers are seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|err| format!("Invalid duration {}: {}", value, err))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Unknown duration unit {} - use s, m, h or d", unit)),
+    };
+
+    let seconds = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Duration {} is too large", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Split a labeled input like `alice=alice.csv` into the profile name and the path
+fn parse_input(value: &str) -> Result<(&str, &str), String> {
+    match value.split_once('=') {
+        Some((name, path)) if !name.trim().is_empty() && !path.is_empty() => {
+            Ok((name.trim(), path))
+        }
+        _ => Err(format!("Invalid input {} - expected NAME=FILE", value)),
+    }
+}
+
+/// Split a limit like `alice=2` into the profile name and the number - without a name it's the
+/// limit of all inputs together
+fn parse_limit(value: &str) -> Result<(Option<&str>, usize), String> {
+    let (name, max) = match value.split_once('=') {
+        Some((name, max)) => (Some(name.trim()).filter(|name| !name.is_empty()), max),
+        None => (None, value),
+    };
+
+    match max.trim().parse() {
+        Ok(max) if name.is_some() || !value.contains('=') => Ok((name, max)),
+        _ => Err(format!("Invalid limit {} - expected N or NAME=N", value)),
+    }
+}
+
+/// Labeled inputs of the exports in the directory named by their file stem (ex: one export per
+/// workstation) - hidden files and subdirectories are skipped
+fn directory_inputs(directory: &Path) -> io::Result<Vec<(String, OsString)>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+        if hidden || !path.is_file() {
+            continue;
+        }
+
+        if let Some(stem) = path.file_stem() {
+            inputs.push((stem.to_string_lossy().into_owned(), path.into_os_string()));
+        }
+    }
+
+    // the directory order depends on the file system
+    inputs.sort_unstable();
+    Ok(inputs)
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        Ok(percent) => Err(format!("Percent {} is not above 0 and up to 100", percent)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        Ok(fraction) => Err(format!("Fraction {} is not between 0 and 1", fraction)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn max_corpus_age(matches: &ArgMatches) -> Duration {
+    // unwrap is safe, because it has a default value checked by the validator
+    parse_duration(matches.value_of(MAX_CORPUS_AGE_KEY).unwrap()).unwrap()
+}
+
+/// Warn if the metadata of the hash database records a snapshot older than the maximum age
+/// Publishing time of the hash file from its metadata - warns if it's older than the maximum age
+fn read_snapshot(path: &OsStr, max_age: Duration) -> Option<SystemTime> {
+    match CorpusMeta::read(Path::new(path)) {
+        Ok(Some(meta)) => {
+            let age = meta.age(SystemTime::now());
+            if age > max_age {
+                warn!(
+                    "Hash file {:?} is {} days old and misses newer leaks - update it using pwned-check download",
+                    path,
+                    age.as_secs() / (24 * 60 * 60)
+                );
+            }
+
+            Some(meta.snapshot)
+        }
+        Ok(None) => {
+            debug!(
+                "No metadata for hash file {:?} - cannot check its age",
+                path
+            );
+            None
+        }
+        Err(err) => {
+            error!("Cannot read metadata of hash file {:?} {}", path, err);
+            None
+        }
+    }
+}
+
+/// Open the hash files of the scan. If the online fallback is enabled, stale or missing SHA-1
+/// files are replaced by the range API - only once, so it isn't queried twice for every hash.
+/// Returns if the range API is used as fallback or none if a hash file cannot be accessed.
+#[cfg_attr(not(feature = "http"), allow(unused_variables, unused_mut))]
+fn open_hash_files(
+    hash_paths: Vec<(&OsStr, HashAlgorithm)>,
+    online_fallback: bool,
+    max_age: Duration,
+) -> Option<(Vec<HashFile>, bool)> {
+    let paths: Vec<_> = hash_paths.iter().map(|&(path, _)| path).collect();
+    let names = corpus_names(&paths);
+
+    let mut hash_files = Vec::with_capacity(hash_paths.len());
+    let mut fallback = false;
+    for ((path, algorithm), name) in hash_paths.into_iter().zip(names) {
+        let snapshot = read_snapshot(path, max_age);
+        let opened = find::open_source(path);
+
+        // the range API only serves SHA-1 hashes
+        #[cfg(feature = "http")]
+        if algorithm == HashAlgorithm::Sha1 && online_fallback {
+            if let Some(reason) = fallback_reason(&opened, snapshot, max_age, SystemTime::now()) {
+                warn!(
+                    "Hash file {:?} {} - falling back to the online range API {} for this run",
+                    path,
+                    reason,
+                    find::HIBP_RANGE_API
+                );
+
+                if !fallback {
+                    hash_files.push(HashFile::online(false));
+                    fallback = true;
+                }
+
+                continue;
+            }
+        }
+
+        match opened {
+            Ok(source) => hash_files.push(HashFile {
+                name,
+                url: find::remote_url(path).map(str::to_string),
+                algorithm,
+                snapshot,
+                stats: read_stats(path),
+                source,
+                newly_pwned: false,
+            }),
+            Err(err) => {
+                error!("Cannot access hash file {:?} {}", path, err);
+                return None;
+            }
+        }
+    }
+
+    Some((hash_files, fallback))
+}
+
+/// Why the hash file should be replaced by the range API - none if it's usable
+#[cfg(feature = "http")]
+fn fallback_reason<T>(
+    opened: &io::Result<T>,
+    snapshot: Option<SystemTime>,
+    max_age: Duration,
+    now: SystemTime,
+) -> Option<String> {
+    if let Err(err) = opened {
+        return Some(format!("cannot be accessed ({})", err));
+    }
+
+    let age = now.duration_since(snapshot?).unwrap_or_default();
+    (age > max_age).then(|| format!("is {} days old", age.as_secs() / (24 * 60 * 60)))
+}
+
+fn read_stats(path: &OsStr) -> Option<CorpusStats> {
+    match CorpusStats::read(Path::new(path)) {
+        Ok(stats) => {
+            if stats.is_none() {
+                debug!(
+                    "No statistics for hash file {:?} - write them using pwned-check stats",
+                    path
+                );
+            }
+
+            stats
+        }
+        Err(err) => {
+            error!("Cannot read statistics of hash file {:?} {}", path, err);
+            None
+        }
+    }
+}
+
+/// Short name of the hash database used in the report
+fn corpus_name(path: &OsStr) -> String {
+    let path = Path::new(path);
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Unique short names of the hash databases. The parent directories are kept until the names
+/// differ (ex: `a/pwned.txt` and `b/pwned.txt`) and the same path given twice is numbered.
+fn corpus_names(paths: &[&OsStr]) -> Vec<String> {
+    let components: Vec<Vec<_>> = paths
+        .iter()
+        .map(|path| Path::new(path).iter().collect())
+        .collect();
+    let name = |position: usize, depth: usize| {
+        let parts = &components[position];
+        parts[parts.len().saturating_sub(depth)..]
+            .iter()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    let mut depths = vec![1; paths.len()];
+    let mut names: Vec<_> = (0..paths.len()).map(|position| name(position, 1)).collect();
+    loop {
+        let mut extended = false;
+        for position in 0..names.len() {
+            let duplicates = names
+                .iter()
+                .filter(|&other| *other == names[position])
+                .count();
+            if duplicates > 1 && depths[position] < components[position].len() {
+                depths[position] += 1;
+                extended = true;
+            }
+        }
+
+        if !extended {
+            break;
+        }
+
+        names = (0..paths.len())
+            .map(|position| name(position, depths[position]))
+            .collect();
+    }
+
+    for position in 1..names.len() {
+        let previous = names[..position]
+            .iter()
+            .filter(|&other| *other == names[position])
+            .count();
+        if previous > 0 {
+            names[position] = format!("{} ({})", names[position], previous + 1);
+        }
+    }
+
+    names
+}
+
+fn create_cli_options<'help>() -> App<'help> {
+    let app = App::new(crate_name!())
+        .about(crate_description!())
+        .version(crate_version!())
+        // the subcommands don't need the hash lists
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            App::new(HASH_ONLY_COMMAND)
+                .about("Only export the SHA-1 of each saved password to check it on another machine")
+                .arg(
+                    Arg::new(PASSWORD_KEY)
+                        .about("Sets passwords csv input list")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(PREFIX_KEY)
+                        .long("prefix")
+                        .about("Only export the first 5 hex characters of each hash"),
+                )
+                .arg(
+                    Arg::new(OUTPUT_KEY)
+                        .short('o')
+                        .long("output")
+                        .about("Write to the file instead of the standard output")
+                        .takes_value(true),
+                )
+                .arg(encoding_arg())
+                .arg(normalize_arg()),
+        )
+        .subcommand(
+            App::new(GENERATE_COMMAND)
+                .about("Generate a synthetic hash list with a matching passwords file for testing")
+                .arg(
+                    Arg::new(OUTPUT_KEY)
+                        .about("Output directory for corpus.txt and passwords.csv")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(LINES_KEY)
+                        .long("lines")
+                        .about("Number of lines in the hash list")
+                        .takes_value(true)
+                        .required(true)
+                        .validator(|value| value.parse::<usize>().map_err(|err| err.to_string())),
+                )
+                .arg(
+                    Arg::new(SEED_KEY)
+                        .long("seed")
+                        .about("The same seed always generates the same files")
+                        .takes_value(true)
+                        .default_value("0")
+                        .validator(|value| value.parse::<u64>().map_err(|err| err.to_string())),
+                )
+                .arg(
+                    Arg::new(ACCOUNTS_KEY)
+                        .long("accounts")
+                        .about("Number of accounts in the passwords file")
+                        .takes_value(true)
+                        .default_value("100")
+                        .validator(|value| value.parse::<usize>().map_err(|err| err.to_string())),
+                )
+                .arg(
+                    Arg::new(PWNED_FRACTION_KEY)
+                        .long("pwned-fraction")
+                        .about("Part of the accounts using a password of the hash list (0 - 1)")
+                        .takes_value(true)
+                        .default_value("0.1")
+                        .validator(parse_fraction),
+                ),
+        )
+        .subcommand(
+            App::new(SCHEMA_COMMAND)
+                .about("Print the JSON Schema of the json and ndjson report formats"),
+        )
+        .subcommand(
+            App::new(PATHS_COMMAND)
+                .about("Print the per-user directories for the config, corpora, cache and state of this platform"),
+        )
+        .subcommand(
+            App::new(NATIVE_HOST_COMMAND)
+                .about("Answer the password checks of a browser extension using native messaging against the local corpus")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    // origin of the extension and the parent window passed by the browsers
+                    Arg::new(CALLER_KEY)
+                        .multiple(true)
+                        .allow_hyphen_values(true)
+                        .hidden(true),
+                ),
+        )
+        .subcommand(hook_command())
+        .arg(
+            Arg::new(PASSWORD_KEY)
+                .about("Sets passwords export - CSV or Bitwarden JSON, the format is detected. With --input or --input-dir it's the first hash list instead")
+                .required_unless_present_any(&[INPUT_KEY, INPUT_DIR_KEY])
+                .index(1),
+        )
+        .arg(
+            Arg::new(INPUT_KEY)
+                .long("input")
+                .value_name("NAME=FILE")
+                .about("Compare the passwords exports of multiple people in one scan (ex: --input alice=alice.csv --input bob=bob.csv) - reports each person and the passwords shared between them")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| parse_input(value).map(|_| ())),
+        )
+        .arg(
+            Arg::new(INPUT_DIR_KEY)
+                .long("input-dir")
+                .value_name("DIR")
+                .about("Add each export in the directory as labeled input named by its file name without the extension (ex: a fleet audit with one export per workstation)")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new(MAX_PWNED_KEY)
+                .long("max-pwned")
+                .value_name("[NAME=]N")
+                .about("Exit with 1 if more than N accounts are pwned - of all inputs together or of the labeled input NAME (ex: --max-pwned 10 --max-pwned alice=0)")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| parse_limit(value).map(|_| ())),
+        )
+        .arg(
+            Arg::new(HASH_KEY)
+                .about("SHA-1 hash lists sorted by hash - multiple lists are scanned one after another. Searched in $PWNED_CHECK_CORPUS, the configured corpus and the data directories of pwned-check paths if omitted")
+                .multiple(true)
+                .index(2),
+        )
+        .arg(
+            Arg::new(NTLM_KEY)
+                .long("ntlm")
+                .value_name("hash_file")
+                .about("NTLM hash list sorted by hash (ex: Active Directory audits) - scanned together with the SHA-1 lists")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new(UNICODE_FORMS_KEY)
+                .long("check-unicode-forms")
+                .about("Additionally check the composed (NFC) and decomposed (NFD) form of non-ASCII passwords, because services could have stored either of them"),
+        )
+        .arg(
+            Arg::new(STRATEGY_KEY)
+                .long("strategy")
+                .about("Search algorithm over the hash lists")
+                .takes_value(true)
+                .possible_values(&Strategy::NAMES)
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new(HUGE_PAGES_KEY)
+                .long("huge-pages")
+                .about("Back the mapped hash lists with transparent huge pages on Linux - only faster on filesystems with large folios (ex: XFS)"),
+        )
+        .arg(
+            Arg::new(POPULATE_KEY)
+                .long("populate")
+                .about("Pre-fault the mapped hash lists on Linux before searching - requires enough memory for the complete lists"),
+        )
+        .arg(
+            Arg::new(SAMPLE_KEY)
+                .long("sample")
+                .value_name("percent")
+                .about("Only check a random part of the accounts (ex: 5) for a quick estimate of the pwned ones")
+                .takes_value(true)
+                .validator(parse_percent),
+        )
+        .arg(
+            Arg::new(TIMEOUT_KEY)
+                .long("timeout")
+                .about("Stop after the duration (ex: 90s, 15m, 2h) and report partial results")
+                .takes_value(true)
+                .validator(parse_duration),
+        )
+        .arg(encoding_arg())
+        .arg(normalize_arg())
+        .arg(
+            Arg::new(HEALTH_KEY)
+                .long("health")
+                .about("Summarize password lengths, entropy, reuse, pwned ratio and age as a health score"),
+        )
+        .arg(
+            Arg::new(HEALTH_HTML_KEY)
+                .long("health-html")
+                .about("Write the health summary as HTML page to the file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(REMEDIATION_KEY)
+                .long("remediation")
+                .value_name("FILE")
+                .about("Write the pwned entries as CSV that could be imported into the password manager - in a \"Rotate me\" folder and tagged needs_rotation")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(REMEDIATION_FORMAT_KEY)
+                .long("remediation-format")
+                .about("Import format of the remediation file")
+                .takes_value(true)
+                .possible_values(&RemediationFormat::NAMES)
+                .default_value("bitwarden"),
+        )
+        .arg(
+            Arg::new(REPORT_FORMAT_KEY)
+                .long("report-format")
+                .about("Format of the pwned accounts - json and ndjson reserve the standard output for the report, see the schema command")
+                .takes_value(true)
+                .possible_values(&ReportFormat::NAMES)
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new(REPORTER_EXEC_KEY)
+                .long("reporter-exec")
+                .about("Run the shell command and stream the NDJSON events to its standard input (ex: ticket creation)")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new(PIPELINE_BUFFER_KEY)
+                .long("pipeline-buffer")
+                .about("Read passwords waiting for a hashing thread - tune using the verbose wait times")
+                .takes_value(true)
+                .default_value(DEFAULT_PIPELINE_BUFFER)
+                .validator(|value| value.parse::<usize>().map_err(|err| err.to_string())),
+        )
+        .arg(
+            Arg::new(VERBOSE_KEY)
+                .short('v')
+                .long("verbose")
+                .about("Verbose output")
+                .global(true),
+        )
+        .arg(
+            Arg::new(MAX_CORPUS_AGE_KEY)
+                .long("max-corpus-age")
+                .about("Warn if the snapshot of a downloaded hash list is older (ex: 90d)")
+                .takes_value(true)
+                .default_value("90d")
+                .validator(parse_duration)
+                .global(true),
+        )
+        .arg(
+            Arg::new(STRICT_KEY)
+                .long("strict")
+                .about("Reject hash list lines without a count or with additional fields")
+                .global(true),
+        )
+        .arg(
+            Arg::new(SHOW_CLEAN_KEY)
+                .long("show-clean")
+                .about("Include accounts without a match as evidence that they were checked - only listed in the verbose or bundle output")
+                .global(true),
+        )
+        .arg(
+            Arg::new(NO_COLLAPSE_KEY)
+                .long("no-collapse")
+                .about("Report each entry separately instead of collapsing the subdomains of a site with identical credentials"),
+        )
+        .arg(
+            Arg::new(PEPPER_KEY)
+                .long("pepper")
+                .value_name("hex|file")
+                .about("Hash SHA-1(pepper || password) for internal lists - hex encoded or a file containing it")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new(INCLUDE_TAG_KEY)
+                .long("include-tag")
+                .value_name("tag")
+                .about("Only check entries with the folder, collection or group (ex: Work) - could be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new(EXCLUDE_TAG_KEY)
+                .long("exclude-tag")
+                .value_name("tag")
+                .about("Skip entries with the folder, collection or group (ex: Archive) - could be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new(EXCLUDE_PATTERN_KEY)
+                .long("exclude-pattern")
+                .value_name("regex")
+                .about("Skip entries if the URL or username matches the regex (ex: -test) - could be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| Regex::new(value).map(|_| ()))
+                // patterns like -test would be parsed as unknown options otherwise
+                .allow_hyphen_values(true)
+                .global(true),
+        );
+
+    // signatures, checksums and pseudonyms require ring
+    #[cfg(feature = "crypto")]
+    let app = app
+        .subcommand(
+            App::new(ANSWER_COMMAND)
+                .about("Check a hash-only bundle against the hash lists and sign the matches")
+                .arg(
+                    Arg::new(BUNDLE_KEY)
+                        .about("Bundle created by hash-only")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(HASH_KEY)
+                        .about("SHA-1 hash lists sorted by hash")
+                        .required(true)
+                        .multiple(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new(SIGNING_KEY_KEY)
+                        .long("signing-key")
+                        .about("Private key file of this machine - created if it doesn't exist")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(OUTPUT_KEY)
+                        .short('o')
+                        .long("output")
+                        .about("Answer bundle file")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new(APPLY_ANSWERS_COMMAND)
+                .about("Verify an answer bundle and report the pwned accounts")
+                .arg(
+                    Arg::new(BUNDLE_KEY)
+                        .about("Bundle created by answer")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(PUBLIC_KEY_KEY)
+                        .long("public-key")
+                        .about("Public key printed by answer on the other machine")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new(ATTEST_VERIFY_COMMAND)
+                .about("Verify the signed attestation of a run and print its summary")
+                .arg(
+                    Arg::new(ATTEST_KEY)
+                        .about("Attestation written by --attest")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(PUBLIC_KEY_KEY)
+                        .long("public-key")
+                        .about("Public key printed by the attested run")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new(REPORT_VERIFY_COMMAND)
+                .about("Verify the signature of a stored JSON report")
+                .arg(
+                    Arg::new(REPORT_KEY)
+                        .about("JSON report of a run with --sign-report - reformatting it keeps the signature valid")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(SIGNATURE_KEY)
+                        .long("signature")
+                        .about("Signature written by --report-signature")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new(PUBLIC_KEY_KEY)
+                        .long("public-key")
+                        .about("Public key printed by the signed run")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new(SERVE_COMMAND)
+                .about("Serve a local HTTP API (POST /check with a SHA-1 hash or prefix) against the opened corpus")
+                .arg(
+                    Arg::new(HASH_KEY)
+                        .about("SHA-1 hash list, index or range directory - the configured or discovered corpus if omitted")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(LISTEN_KEY)
+                        .long("listen")
+                        .value_name("ADDRESS")
+                        .about("Address and port of the API [default: 127.0.0.1:8080] - only reachable from this machine by default")
+                        .takes_value(true)
+                        .validator(|value| value.parse::<SocketAddr>()),
+                )
+                .arg(
+                    Arg::new(UNIX_SOCKET_KEY)
+                        .long("unix")
+                        .value_name("SOCKET")
+                        .about("Only listen on this unix socket - access is granted by the permissions of the file")
+                        .takes_value(true)
+                        .conflicts_with(LISTEN_KEY),
+                )
+                .arg(
+                    Arg::new(TOKEN_FILE_KEY)
+                        .long("token-file")
+                        .value_name("FILE")
+                        .about("Require one of the bearer tokens of the file with name=token lines")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(RATE_LIMIT_KEY)
+                        .long("rate-limit")
+                        .value_name("REQUESTS")
+                        .about("Maximum requests per minute of each token or otherwise of each client address")
+                        .takes_value(true)
+                        .validator(|value| match value.parse::<u32>() {
+                            Ok(0) => Err("Requires at least one request".to_string()),
+                            Ok(_) => Ok(()),
+                            Err(err) => Err(err.to_string()),
+                        }),
+                )
+                .arg(
+                    Arg::new(MAX_COUNT_KEY)
+                        .long("max-count")
+                        .value_name("COUNT")
+                        .about("The password policy webhook (POST /policy) denies passwords found more often")
+                        .takes_value(true)
+                        .default_value("0")
+                        .validator(|value| value.parse::<u64>().map_err(|err| err.to_string())),
+                )
+                .arg(
+                    Arg::new(NO_WARM_UP_KEY)
+                        .long("no-warm-up")
+                        .about("Don't load the corpus into the page cache before listening (ex: corpora larger than the memory)"),
+                )
+                .arg(
+                    Arg::new(POPULATE_KEY)
+                        .long("populate")
+                        .about("Let the kernel pre-fault the whole corpus during the warm-up - faster than touching each page, but requires enough memory for the corpus")
+                        .conflicts_with(NO_WARM_UP_KEY),
+                ),
+        )
+        .arg(
+            Arg::new(ATTEST_KEY)
+                .long("attest")
+                .about("Write a signed in-toto attestation of the run (hash list digests, counts, runner) - verify it using attest-verify")
+                .takes_value(true)
+                .requires(SIGNING_KEY_KEY),
+        )
+        .arg(
+            Arg::new(SIGNING_KEY_KEY)
+                .long("signing-key")
+                .about("Private key file signing the attestation - created if it doesn't exist")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(SIGN_REPORT_KEY)
+                .long("sign-report")
+                .about("Private key file signing the canonical JSON report - created if it doesn't exist. Verify the stored report using report-verify")
+                .takes_value(true)
+                .requires(REPORT_SIGNATURE_KEY),
+        )
+        .arg(
+            Arg::new(REPORT_SIGNATURE_KEY)
+                .long("report-signature")
+                .about("Write the detached signature of the report in the minisign format to this file")
+                .takes_value(true)
+                .requires(SIGN_REPORT_KEY),
+        )
+        .arg(
+            Arg::new(ANONYMIZE_KEY)
+                .long("anonymize")
+                .about("Replace URLs and usernames with pseudonyms using the local key file - created if missing")
+                .takes_value(true)
+                .global(true),
+        )
+        // the remediation file would contain the original values
+        .mut_arg(REMEDIATION_KEY, |arg| arg.conflicts_with(ANONYMIZE_KEY));
+
+    #[cfg(any(feature = "http", feature = "vault-kv"))]
+    let app = app.arg(
+        Arg::new(USER_AGENT_KEY)
+            .long("user-agent")
+            .value_name("agent")
+            .about("User-Agent of the HTTP requests instead of the config or the default with the version (ex: acme-audit/1.0 (security@example.com))")
+            .takes_value(true)
+            .global(true),
+    );
+
+    #[cfg(feature = "http")]
+    let app = app
+        .arg(
+            Arg::new(ONLINE_FALLBACK_KEY)
+                .long("online-fallback")
+                .about("Query the HIBP range API for this run if a SHA-1 hash file cannot be opened or is older than --max-corpus-age (ex: unmounted data disk)"),
+        )
+        .arg(
+            Arg::new(HYBRID_KEY)
+                .long("hybrid")
+                .about("Additionally look up the passwords without a match in the local hash files online and report them as newly pwned since the snapshot")
+                // the API only contains the plain hashes
+                .conflicts_with(PEPPER_KEY),
+        )
+        .subcommand(download_command())
+        .subcommand(
+            App::new(SETUP_COMMAND)
+                .about("Guided first setup - choose and prepare the hash list and print the command for the regular checks"),
+        )
+        .subcommand(prefetch_command());
+
+    #[cfg(feature = "index")]
+    let app = app.subcommand(convert_command()).subcommand(
+        App::new(STATS_COMMAND)
+            .about("Write the count distribution of a hash list or index next to it - the summary then compares the matched passwords to it")
+            .arg(
+                Arg::new(HASH_KEY)
+                    .about("SHA-1 hash list or index")
+                    .required(true)
+                    .index(1),
+            ),
+    );
+
+    #[cfg(feature = "vault-kv")]
+    let app = app.subcommand(vault_kv_command());
+
+    // clap already disables colors if the output isn't a terminal - https://no-color.org
+    let app = if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        app.setting(AppSettings::ColorNever)
+    } else {
+        app
+    };
+
+    #[cfg(feature = "tui")]
+    let app = app.arg(
+        Arg::new(TUI_KEY)
+            .long("tui")
+            .about("Interactive interface with live progress, search and browsing of the findings after the scan"),
+    );
+
+    app
+}
+
+fn hook_command<'help>() -> App<'help> {
+    let app = App::new(HOOK_COMMAND)
+        .about("Block a commit if the staged credential files (ex: .env, CSV exports) contain pwned passwords - for git pre-commit hooks")
+        .arg(
+            Arg::new(STAGED_KEY)
+                .long("staged")
+                .about("Check the staged version of the files that match the patterns")
+                .required_unless_present(FILES_KEY),
+        )
+        .arg(
+            Arg::new(FILES_KEY)
+                .about("Check these files instead of the staged ones - independent of the patterns")
+                .multiple(true)
+                .conflicts_with(STAGED_KEY)
+                .index(1),
+        )
+        .arg(
+            Arg::new(HASH_KEY)
+                .long("corpus")
+                .value_name("LIST")
+                .about("SHA-1 hash list, index or range directory - the configured or discovered corpus if omitted")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(PATTERN_KEY)
+                .long("pattern")
+                .value_name("REGEX")
+                .about("Check staged paths that match the regex instead of the default credential files - could be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|value| Regex::new(value).map(|_| ())),
+        );
+
+    #[cfg(feature = "vault")]
+    let app = app
+        .arg(
+            Arg::new(VAULT_PASSWORD_FILE_KEY)
+                .long("vault-password-file")
+                .value_name("FILE")
+                .about("Decrypt Ansible Vault files and values with the password of this file")
+                .env("ANSIBLE_VAULT_PASSWORD_FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(AGE_KEY_FILE_KEY)
+                .long("age-key-file")
+                .value_name("FILE")
+                .about("Decrypt SOPS files with the age identities of this file - the default key file of SOPS if omitted")
+                .env("SOPS_AGE_KEY_FILE")
+                .takes_value(true),
+        );
+
+    app
+}
+
+#[cfg(feature = "vault-kv")]
+fn vault_kv_command<'help>() -> App<'help> {
+    App::new(VAULT_KV_COMMAND)
+        .about("Audit the password fields of the secrets in a HashiCorp Vault KV engine - reported by the secret path")
+        .arg(
+            Arg::new(SECRET_PREFIX_KEY)
+                .about("Path prefix or secret below the mount - all secrets if omitted")
+                .index(1),
+        )
+        .arg(
+            Arg::new(VAULT_ADDRESS_KEY)
+                .long("address")
+                .value_name("URL")
+                .about("Address of the Vault server (ex: https://vault.example.com:8200)")
+                .env("VAULT_ADDR")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new(MOUNT_KEY)
+                .long("mount")
+                .value_name("PATH")
+                .about("Mount path of the KV secrets engine")
+                .default_value("secret")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(KV_VERSION_KEY)
+                .long("kv-version")
+                .about("Version of the KV secrets engine")
+                .possible_values(&["1", "2"])
+                .default_value("2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(FIELD_KEY)
+                .long("field")
+                .value_name("NAME")
+                .about("Check this field of the secrets instead of the fields with password like names - could be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new(VAULT_TOKEN_FILE_KEY)
+                .long("token-file")
+                .value_name("FILE")
+                .about("Vault token of this file - VAULT_TOKEN or the token of vault login if omitted")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(ROLE_ID_KEY)
+                .long("role-id")
+                .value_name("ID")
+                .about("Login using the AppRole auth method with this role id")
+                .env("VAULT_ROLE_ID")
+                .takes_value(true)
+                .conflicts_with(VAULT_TOKEN_FILE_KEY),
+        )
+        .arg(
+            Arg::new(SECRET_ID_FILE_KEY)
+                .long("secret-id-file")
+                .value_name("FILE")
+                .about("Secret id of the AppRole login - VAULT_SECRET_ID if omitted")
+                .takes_value(true)
+                .requires(ROLE_ID_KEY),
+        )
+        .arg(
+            Arg::new(CONFIRM_COUNT_KEY)
+                .long("confirm-count")
+                .value_name("N")
+                .about("Safety mode for production stores: refuse to read more than N secrets or check more than N credentials (password fields) without --acknowledge and print the pwned secrets without their counts")
+                .takes_value(true)
+                .validator(|value| value.parse::<usize>().map(|_| ())),
+        )
+        .arg(
+            Arg::new(ACKNOWLEDGE_KEY)
+                .long("acknowledge")
+                .about("Check all secrets and credentials even if there are more than --confirm-count")
+                .requires(CONFIRM_COUNT_KEY),
+        )
+        .arg(
+            Arg::new(HASH_KEY)
+                .long("corpus")
+                .value_name("LIST")
+                .about("SHA-1 hash list, index or range directory - the configured or discovered corpus if omitted")
+                .takes_value(true),
+        )
+}
+
+#[cfg(feature = "http")]
+fn prefetch_command<'help>() -> App<'help> {
+    App::new(PREFETCH_COMMAND)
+        .about("Download only the ranges of the saved passwords from the range API - later scans with the same API URL use them without the network")
+        .arg(
+            Arg::new(PASSWORD_KEY)
+                .about("Sets passwords csv input list")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::new(API_KEY)
+                .long("api")
+                .value_name("URL")
+                .about("Range API including the trailing /range/ - the same URL is the hash list of the later scans")
+                .takes_value(true)
+                .default_value(find::HIBP_RANGE_API)
+                .validator(|value| {
+                    let http = value.starts_with("http://") || value.starts_with("https://");
+                    if http && value.ends_with("/range/") {
+                        Ok(())
+                    } else {
+                        Err(format!("Invalid range API {} - expected a HTTP(S) URL ending with /range/", value))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(REFRESH_KEY)
+                .long("refresh")
+                .about("Download the already cached ranges again to include the newest breaches"),
+        )
+        .arg(encoding_arg())
+        .arg(normalize_arg())
+}
+
+#[cfg(feature = "http")]
+fn download_command<'help>() -> App<'help> {
+    App::new(DOWNLOAD_COMMAND)
+        .about("Download a hash list from the fastest mirror and verify the published checksum")
+        .arg(
+            Arg::new(OUTPUT_KEY)
+                .about("Output file - only created after a successful verification. Defaults to the user data directory, where it's found without a hash file argument")
+                .index(1),
+        )
+        .arg(
+            Arg::new(MIRROR_KEY)
+                .long("mirror")
+                .about("HTTP(S) URL of the file - the download continues on the next mirror if one fails")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .required_unless_present(MIRROR_LIST_KEY),
+        )
+        .arg(
+            Arg::new(MIRROR_LIST_KEY)
+                .long("mirror-list")
+                .about("File with one mirror URL per line")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(CHECKSUM_KEY)
+                .long("checksum")
+                .about("Published digest of the file (sha1:HEX or sha256:HEX)")
+                .takes_value(true)
+                .validator(|value| value.parse::<download::Checksum>().map(|_| ())),
+        )
+        .arg(force_arg())
+}
+
+#[cfg(feature = "index")]
+fn convert_command<'help>() -> App<'help> {
+    App::new(CONVERT_COMMAND)
+        .about("Convert a hash list sorted by hash into an index for microsecond lookups - scan the index instead of the list")
+        .arg(
+            Arg::new(HASH_KEY)
+                .about("SHA-1 hash list sorted by hash")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::new(OUTPUT_KEY)
+                .about("Index file - only created after a successful conversion")
+                .required_unless_present(PREFIXES_ONLY_KEY)
+                .index(2),
+        )
+        .arg(
+            Arg::new(PREFIXES_ONLY_KEY)
+                .long("prefixes-only")
+                .about("Only write the prefix filter next to the hash list - skips saved hashes without a lookup for small lists")
+                .conflicts_with(OUTPUT_KEY),
+        )
+        .arg(
+            Arg::new(MMAP_KEY)
+                .long("mmap")
+                .about("Write directly into the preallocated output using a memory map instead of a temporary copy - a stopped conversion continues with the next of 16 parts")
+                .conflicts_with(PREFIXES_ONLY_KEY),
+        )
+        .arg(force_arg())
+}
+
+#[cfg(any(feature = "http", feature = "index"))]
+fn force_arg<'help>() -> Arg<'help> {
+    Arg::new(FORCE_KEY)
+        .long("force")
+        .about("Start even if the estimated size exceeds the free disk space")
+}
+
+fn encoding_arg<'help>() -> Arg<'help> {
+    Arg::new(ENCODING_KEY)
+        .long("password-encoding")
+        .about("Encoding of the passwords file - re-encoded to UTF-8 before hashing")
+        .takes_value(true)
+        .possible_values(&PasswordEncoding::NAMES)
+        .default_value("utf-8")
+}
+
+fn normalize_arg<'help>() -> Arg<'help> {
+    Arg::new(NORMALIZE_KEY)
+        .long("normalize")
+        .value_name("step")
+        .about("Change the passwords before hashing, because the exact bytes are hashed by default - trim whitespace, strip a byte order mark or compose Unicode characters (NFC)")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .use_delimiter(true)
+        .possible_values(&Normalization::NAMES)
+}
+
+/// Resolved directories - XDG variables change them on every platform
+fn print_paths() {
+    for &location in &paths::Location::ALL {
+        match location.user_dir() {
+            Some(dir) => println!("{}: {}", location, dir.display()),
+            None => println!("{}: unknown home directory", location),
+        }
+    }
+
+    if let Some(dir) = paths::system_data_dir() {
+        println!("system data: {}", dir.display());
+    }
+}
+
+/// Long running lookup process started by the browser. The corpus is opened once and each request
+/// is a point lookup, so answers take milliseconds.
+fn native_host(matches: &ArgMatches) {
+    if let Some(caller) = matches.values_of(CALLER_KEY) {
+        debug!("Started by {:?}", caller.collect::<Vec<_>>());
+    }
+
+    // browsers don't pass arguments, so only the configured or discovered corpus is used
+    let corpus = match default_corpus() {
+        Some(corpus) => corpus,
+        None => return,
+    };
+
+    if corpus.to_string_lossy().contains("://") {
+        error!(
+            "The native host only uses local corpora - {:?} would send the hash prefixes to the network",
+            corpus
+        );
+        return;
+    }
+
+    let source = match find::open_source(&corpus) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("Cannot access hash file {:?} {}", corpus, err);
+            return;
+        }
+    };
+
+    // the browser waits for the first answer, so the OS loads the pages in the background
+    warm_up(&*source, MapOptions::default(), false);
+
+    let strict = matches.is_present(STRICT_KEY);
+    let served = native_host::serve(&mut io::stdin().lock(), &mut io::stdout().lock(), |hash| {
+        find::lookup_hash(&*source, hash, strict)
+    });
+
+    if let Err(err) = served {
+        error!("Native messaging failed {}", err);
+    }
+}
+
+/// Check the candidate passwords of credential files against the corpus - exits with a failure if
+/// the commit should be blocked
+fn hook(matches: &ArgMatches) {
+    let files = if matches.is_present(STAGED_KEY) {
+        // unwrap is safe, because each pattern is checked by the validator
+        let patterns = match matches.values_of(PATTERN_KEY) {
+            Some(patterns) => RegexSet::new(patterns),
+            None => RegexSet::new(hook::DEFAULT_PATTERNS),
+        }
+        .unwrap();
+
+        match hook::staged_files(&patterns) {
+            Ok(files) => files,
+            Err(err) => {
+                error!("Cannot read the staged files {}", err);
+                process::exit(CHECK_FAILED_EXIT_CODE);
+            }
+        }
+    } else {
+        let mut files = Vec::new();
+        // unwrap is safe, because the files are required without the staged flag
+        for path in matches.values_of(FILES_KEY).unwrap() {
+            match fs::read(path) {
+                Ok(content) => files.push(hook::CheckedFile {
+                    path: path.to_string(),
+                    content,
+                }),
+                Err(err) => {
+                    error!("Cannot read {:?} {}", path, err);
+                    process::exit(CHECK_FAILED_EXIT_CODE);
+                }
+            }
+        }
+
+        files
+    };
+
+    #[cfg(feature = "vault")]
+    let extractor = extract::Extractor {
+        keys: match decryption_keys(matches) {
+            Some(keys) => keys,
+            None => process::exit(CHECK_FAILED_EXIT_CODE),
+        },
+    };
+    #[cfg(not(feature = "vault"))]
+    let extractor = extract::Extractor::default();
+
+    let candidates: Vec<_> = files
+        .iter()
+        .flat_map(|file| extractor.extract(&file.path, &file.content))
+        .collect();
+    debug!(
+        "Extracted {} candidate passwords from {} files",
+        candidates.len(),
+        files.len()
+    );
+    if candidates.is_empty() {
+        return;
+    }
+
+    let mut pwned = 0;
+    for (candidate, finding) in candidates
+        .iter()
+        .zip(lookup_candidates(matches, &candidates))
+    {
+        let finding = match finding {
+            Some(finding) => finding,
+            None => continue,
+        };
+
+        pwned += 1;
+        match finding.count {
+            Some(count) => error!(
+                "{} contains a password that has been pwned {}x times",
+                candidate.label, count
+            ),
+            None => error!("{} contains a pwned password", candidate.label),
+        }
+    }
+
+    if pwned > 0 {
+        error!(
+            "Blocked the commit - change the {} pwned passwords and keep the credentials out of the repository",
+            pwned
+        );
+        process::exit(PWNED_EXIT_CODE);
+    }
+
+    info!(
+        "None of the {} passwords in {} files are pwned",
+        candidates.len(),
+        files.len()
+    );
+}
+
+/// Findings of the candidate passwords in the given or default corpus - exits with a failure if
+/// it cannot be searched
+fn lookup_candidates(
+    matches: &ArgMatches,
+    candidates: &[extract::Candidate],
+) -> Vec<Option<find::Finding>> {
+    let corpus = match matches.value_of_os(HASH_KEY) {
+        Some(corpus) => corpus.to_os_string(),
+        None => match default_corpus() {
+            Some(corpus) => corpus,
+            None => process::exit(CHECK_FAILED_EXIT_CODE),
+        },
+    };
+
+    let found = find::open_source(&corpus).and_then(|source| {
+        let hashes: Vec<_> = candidates.iter().map(extract::Candidate::sha1).collect();
+        find::lookup_hashes(&*source, &hashes, matches.is_present(STRICT_KEY))
+    });
+    match found {
+        Ok(findings) => findings,
+        Err(err) => {
+            error!("Cannot search hash file {:?} {}", corpus, err);
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+    }
+}
+
+/// Audit the password fields of the secrets in a HashiCorp Vault KV engine - exits with a failure
+/// if any is pwned
+#[cfg(feature = "vault-kv")]
+fn vault_kv(matches: &ArgMatches) {
+    let auth = match vault_auth(matches) {
+        Ok(auth) => auth,
+        Err(err) => {
+            error!("{}", err);
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+    };
+
+    // unwrap is safe, because the address is required and the others have valid defaults
+    let address = matches.value_of(VAULT_ADDRESS_KEY).unwrap();
+    let mount = matches.value_of(MOUNT_KEY).unwrap();
+    let version: vault_kv::KvVersion = matches.value_of_t(KV_VERSION_KEY).unwrap();
+    let client = match vault_kv::VaultClient::login(address, mount, version, auth) {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Cannot login to Vault {} {}", address, err);
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+    };
+
+    let prefix = matches.value_of(SECRET_PREFIX_KEY).unwrap_or_default();
+    let fields: Vec<String> = matches
+        .values_of(FIELD_KEY)
+        .map_or_else(Vec::new, |values| values.map(str::to_string).collect());
+    let secrets = match vault_kv::list_secrets(&client, client.mount(), prefix) {
+        Ok(secrets) => secrets,
+        Err(err) => {
+            error!("Cannot list the secrets of {}/{} {}", mount, prefix, err);
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+    };
+
+    // unwrap is safe, because the value is checked by the validator
+    let confirm = matches
+        .value_of(CONFIRM_COUNT_KEY)
+        .map(|count| vault_kv::ConfirmCount {
+            limit: count.parse().unwrap(),
+            acknowledged: matches.is_present(ACKNOWLEDGE_KEY),
+        });
+    if let Some(confirm) = confirm {
+        // nothing was read yet, so the refused run doesn't show up as reads in the audit log
+        if !confirm.allows(secrets.paths.len()) {
+            error!(
+                "Refusing to read {} secrets of {}/{} - more than --confirm-count {}. Narrow the prefix or add --acknowledge to check all of them",
+                secrets.paths.len(),
+                mount,
+                prefix,
+                confirm.limit
+            );
+            process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+    }
+
+    let scan = vault_kv::read_secrets(&client, client.mount(), &secrets.paths, &fields);
+    if secrets.skipped + scan.skipped > 0 {
+        warn!(
+            "Skipped {} secrets or directories that couldn't be read",
+            secrets.skipped + scan.skipped
+        );
+    }
+
+    let candidates = scan.candidates;
+    if candidates.is_empty() {
+        info!("No password fields in {} secrets", scan.secrets);
+        return;
+    }
+
+    // a secret could contain multiple password fields, so the credentials are limited too
+    if let Some(confirm) = confirm.filter(|confirm| !confirm.allows(candidates.len())) {
+        error!(
+            "Refusing to check {} credentials of {} secrets - more than --confirm-count {}. Narrow the fields or add --acknowledge to check all of them",
+            candidates.len(),
+            scan.secrets,
+            confirm.limit
+        );
+        process::exit(CHECK_FAILED_EXIT_CODE);
+    }
+
+    let mut pwned = 0;
+    for (candidate, finding) in candidates
+        .iter()
+        .zip(lookup_candidates(matches, &candidates))
+    {
+        let finding = match finding {
+            Some(finding) => finding,
+            None => continue,
+        };
+
+        pwned += 1;
+        // the count reveals how common the password is
+        match finding.count {
+            Some(count) if confirm.is_none() => {
+                error!("{} has been pwned {}x times", candidate.label, count)
+            }
+            _ => error!("{} is pwned", candidate.label),
+        }
+    }
+
+    if pwned > 0 {
+        error!(
+            "{} of the {} passwords in {} secrets are pwned - rotate them",
+            pwned,
+            candidates.len(),
+            scan.secrets
+        );
+        process::exit(PWNED_EXIT_CODE);
+    }
+
+    info!(
+        "None of the {} passwords in {} secrets are pwned",
+        candidates.len(),
+        scan.secrets
+    );
+}
+
+/// AppRole login if a role id is given and otherwise the token of the file, `VAULT_TOKEN` or the
+/// token helper file of the Vault CLI
+#[cfg(feature = "vault-kv")]
+fn vault_auth(matches: &ArgMatches) -> Result<vault_kv::Auth, String> {
+    let read_secret = |path: &OsStr| {
+        fs::read(path)
+            .map(|content| SecStr::new(content.trim_ascii().to_vec()))
+            .map_err(|err| format!("Cannot read {:?} {}", path, err))
+    };
+
+    if let Some(role_id) = matches.value_of(ROLE_ID_KEY) {
+        let secret_id = match matches.value_of_os(SECRET_ID_FILE_KEY) {
+            Some(path) => read_secret(path)?,
+            None => match env::var("VAULT_SECRET_ID") {
+                Ok(secret_id) => SecStr::from(secret_id),
+                Err(_) => return Err("Missing the secret id of the AppRole login - use --secret-id-file or VAULT_SECRET_ID".to_string()),
+            },
+        };
+
+        return Ok(vault_kv::Auth::AppRole {
+            role_id: role_id.to_string(),
+            secret_id,
+        });
+    }
+
+    if let Some(path) = matches.value_of_os(VAULT_TOKEN_FILE_KEY) {
+        return read_secret(path).map(vault_kv::Auth::Token);
+    }
+
+    if let Ok(token) = env::var("VAULT_TOKEN") {
+        return Ok(vault_kv::Auth::Token(SecStr::from(token)));
+    }
+
+    // written by `vault login`
+    match env::var_os("HOME").map(|home| Path::new(&home).join(".vault-token")) {
+        Some(path) if path.exists() => read_secret(path.as_os_str()).map(vault_kv::Auth::Token),
+        _ => Err(
+            "No Vault token - use --token-file, VAULT_TOKEN, vault login or an AppRole".to_string(),
+        ),
+    }
+}
+
+/// Vault password and age identities of the given files - none if a file cannot be read
+#[cfg(feature = "vault")]
+fn decryption_keys(matches: &ArgMatches) -> Option<decrypt::Keys> {
+    let mut keys = decrypt::Keys::default();
+    if let Some(path) = matches.value_of_os(VAULT_PASSWORD_FILE_KEY) {
+        match decrypt::Keys::read_vault_password(Path::new(path)) {
+            Ok(password) => keys.vault_password = Some(password),
+            Err(err) => {
+                error!("Cannot read the vault password file {:?} {}", path, err);
+                return None;
+            }
+        }
+    }
+
+    // SOPS looks for keys.txt of the sops/age directory in the user config directory
+    let age_key_file = match matches.value_of_os(AGE_KEY_FILE_KEY) {
+        Some(path) => Some(Path::new(path).to_path_buf()),
+        None => paths::Location::Config
+            .user_dir()
+            .and_then(|dir| Some(dir.parent()?.join("sops").join("age").join("keys.txt")))
+            .filter(|path| path.exists()),
+    };
+
+    if let Some(path) = age_key_file {
+        match decrypt::Keys::read_age_identities(&path) {
+            Ok(identities) => keys.age_identities = identities,
+            Err(err) => {
+                error!("Cannot read the age key file {:?} {}", path, err);
+                return None;
+            }
+        }
+    }
+
+    Some(keys)
+}
+
+/// Long running HTTP API for other services (ex: checking passwords when they are set)
+#[cfg(feature = "crypto")]
+fn serve_api(matches: &ArgMatches) {
+    let corpus = match matches.value_of_os(HASH_KEY) {
+        Some(corpus) => corpus.to_os_string(),
+        None => match default_corpus() {
+            Some(corpus) => corpus,
+            None => return,
+        },
+    };
+
+    if corpus.to_string_lossy().contains("://") {
+        error!(
+            "The API requires a local corpus for point lookups - download {:?} first",
+            corpus
+        );
+        return;
+    }
+
+    let source = match find::open_source(&corpus) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("Cannot access hash file {:?} {}", corpus, err);
+            return;
+        }
+    };
+
+    let tokens = match matches.value_of_os(TOKEN_FILE_KEY) {
+        Some(path) => match serve::Tokens::read(Path::new(path)) {
+            Ok(tokens) => Some(tokens),
+            Err(err) => {
+                error!("Cannot read the token file {:?} {}", path, err);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let listener = match serve_listener(matches, tokens.is_some()) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Cannot listen {}", err);
+            return;
+        }
+    };
+
+    let server = serve::Server {
+        api: serve::Api {
+            source: &*source,
+            strict: matches.is_present(STRICT_KEY),
+            // unwrap is safe, because it has a default value checked by the validator
+            max_count: matches.value_of_t(MAX_COUNT_KEY).unwrap(),
+            latency: LatencyHistogram::default(),
+        },
+        tokens,
+        // only fails if it's missing, because the value is checked by the validator
+        rate_limit: matches
+            .value_of_t(RATE_LIMIT_KEY)
+            .ok()
+            .map(serve::RateLimiter::new),
+    };
+
+    if !matches.is_present(NO_WARM_UP_KEY) {
+        let mapping = MapOptions {
+            populate: matches.is_present(POPULATE_KEY),
+            huge_pages: false,
+        };
+        warm_up(&*source, mapping, true);
+    }
+
+    info!("Serving {:?}", corpus);
+    serve::serve(listener, &server);
+}
+
+/// Load the corpus into the page cache, so the first lookups are as fast as the later ones
+fn warm_up(source: &(dyn find::HashSource + Sync), mapping: MapOptions, touch: bool) {
+    let started = Instant::now();
+    match find::warm_up(source, mapping, touch) {
+        Ok(Some(bytes)) if touch => info!(
+            "Loaded {} MB of the corpus in {:?}",
+            bytes / 1_000_000,
+            started.elapsed()
+        ),
+        Ok(_) => {}
+        Err(err) => warn!("Failed to warm up the corpus {}", err),
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn serve_listener(matches: &ArgMatches, authenticated: bool) -> io::Result<serve::Listener> {
+    #[cfg(unix)]
+    if let Some(path) = matches.value_of_os(UNIX_SOCKET_KEY) {
+        use std::os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        };
+
+        let path = Path::new(path);
+        if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{:?} is served by another process", path),
+                ));
+            }
+
+            // left behind by a killed server
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        // the group (ex: of the CI runners) could connect, but nobody else
+        fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+        info!("Listening on unix socket {:?}", path);
+        return Ok(serve::Listener::Unix(listener));
+    }
+
+    #[cfg(not(unix))]
+    if matches.is_present(UNIX_SOCKET_KEY) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix sockets are not supported on this platform",
+        ));
+    }
+
+    let listen: SocketAddr = matches
+        .value_of_t(LISTEN_KEY)
+        .unwrap_or_else(|_| SocketAddr::from(([127, 0, 0, 1], 8080)));
+    if !listen.ip().is_loopback() && !authenticated {
+        warn!(
+            "Anyone who can reach {} could query the corpus - consider a --token-file",
+            listen
+        );
+    }
+
+    let listener = TcpListener::bind(listen)?;
+    info!("Listening on http://{}/check", listen);
+    Ok(serve::Listener::Tcp(listener))
+}
+
+/// Corpus of the setup or the newest one in the data directories. The environment variable takes
+/// precedence over the config.
+fn default_corpus() -> Option<OsString> {
+    if env::var_os(discover::CORPUS_ENV).is_none() {
+        if let Some(corpus) = configured_corpus() {
+            info!("Using the configured corpus {}", corpus);
+            return Some(corpus.into());
+        }
+    }
+
+    let search_paths = discover::search_paths();
+    match discover::find_corpus(&search_paths) {
+        Some(corpus) => {
+            info!("Using the corpus {:?}", corpus);
+            Some(corpus.into_os_string())
+        }
+        None => {
+            error!(
+                "No hash file given and no corpus found in {:?} - run pwned-check setup or download one using pwned-check download",
+                search_paths
+            );
+            None
+        }
+    }
+}
+
+fn configured_corpus() -> Option<String> {
+    read_config()?.corpus
+}
+
+/// Config written by setup - none if it was never written or cannot be read
+fn read_config() -> Option<config::Config> {
+    let path = config::Config::path()?;
+    match config::Config::read(&path) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Ignoring the config {:?} {}", path, err);
+            None
+        }
+    }
+}
+
+/// Opened hash database with its name in the report
+struct HashFile {
+    name: String,
+    algorithm: HashAlgorithm,
+    /// Publishing time from the metadata - none if it's unknown
+    snapshot: Option<SystemTime>,
+    /// Count distribution from the sidecar - none if it wasn't written by the stats command
+    stats: Option<CorpusStats>,
+    source: Box<dyn HashSource + Sync>,
+    /// Location of online and remote sources - none for local files
+    #[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+    url: Option<String>,
+    /// Only the hashes without a match in the previous files are looked up (hybrid mode)
+    newly_pwned: bool,
+}
+
+impl HashFile {
+    /// HIBP range API - only the hash prefixes leave the machine. The hybrid lookup skips the
+    /// prefetched ranges, because they could be older than the local list.
+    #[cfg(feature = "http")]
+    fn online(newly_pwned: bool) -> Self {
+        let api = if newly_pwned {
+            find::RangeApi::uncached(find::HIBP_RANGE_API)
+        } else {
+            find::RangeApi::new(find::HIBP_RANGE_API)
+        };
+
+        HashFile {
+            name: corpus_name(OsStr::new(find::HIBP_RANGE_API)),
+            algorithm: HashAlgorithm::Sha1,
+            snapshot: None,
+            stats: None,
+            source: Box::new(api),
+            url: Some(find::HIBP_RANGE_API.to_string()),
+            newly_pwned,
+        }
+    }
+}
+
+fn run(
+    password_readers: Vec<(Option<String>, PasswordSource<impl Read>)>,
+    hash_files: &[HashFile],
+    options: ScanOptions,
+    hashing: HashOptions,
+    report_options: ReportOptions<'_>,
+    pseudonyms: &mut Pseudonymizer,
+    cancel: &CancellationToken,
+) -> CheckOutcome {
+    #[cfg(feature = "crypto")]
+    let run_started = SystemTime::now();
+    let mut metrics = Metrics::default();
+    let profiles: Vec<String> = password_readers
+        .iter()
+        .filter_map(|(profile, _)| profile.clone())
+        .collect();
+    let collected = collect::collect_profiles(password_readers, &hashing, cancel, &mut metrics);
+    let hashing_ntlm = hashing.ntlm;
+    let hashing_forms = hashing.unicode_forms;
+
+    // zero the pepper as soon as it's not needed anymore
+    drop(hashing);
+    match collected {
+        Ok(Status::Cancelled(hashes)) => {
+            // nothing was scanned yet, so the incomplete report has no checked accounts and only
+            // counts the hashed ones
+            error!(
+                "Cancelled while reading saved passwords - {} accounts were hashed, but none of them was checked",
+                hashes.len()
+            );
+            if report_options.needs_events() {
+                let report = StructuredReport::new(&[], &[], 0, false, pseudonyms)
+                    .with_unchecked(hashes.len());
+                publish_report(&report, &report_options);
+            }
+
+            CheckOutcome::Failed
+        }
+        Ok(Status::Completed(mut hashes)) => {
+            info!("Finished hashing");
+
+            if hashes.is_empty() {
+                error!("No stored passwords found");
+                return CheckOutcome::Failed;
+            }
+
+            let population = hashes.len();
+            if let Some(sample) = report_options.sample {
+                hashes = sample.select(hashes);
+                warn!(
+                    "Sampled {} of {} accounts - the results only cover the sample and the totals are an ESTIMATE",
+                    hashes.len(),
+                    population
+                );
+            }
+
+            // unstable is slightly faster than the normal search - we don't care about mixed equal
+            // entries so lets use this. Parallel for breach-response inputs with millions of hashes.
+            let sort_started = Instant::now();
+            hashes.par_sort_unstable();
+            metrics.record(
+                Phase::Sort,
+                sort_started,
+                Amount::Records(hashes.len() as u64),
+            );
+            info!("Sorted");
+
+            // the NTLM hashes are in another order, so they are searched separately
+            let ntlm = if hashing_ntlm {
+                Some(NtlmHashes::new(&hashes))
+            } else {
+                None
+            };
+
+            // same for the other Unicode forms - only the SHA-1 lists are searched for them
+            let forms = if hashing_forms {
+                Some(FormHashes::new(&hashes)).filter(|forms| !forms.is_empty())
+            } else {
+                None
+            };
+
+            // the passwords are hashed only once and then compared against each database
+            let mut progress = progress_sink(&report_options);
+            let mut results = Vec::with_capacity(hash_files.len());
+            let mut cancelled = false;
+            let mut bytes_scanned = None;
+            // highest count of each hash file by its position - the names could be equal
+            let mut worst = vec![None; hash_files.len()];
+            for (position, file) in hash_files.iter().enumerate() {
+                debug!("Scanning {:?} hash file {}", file.algorithm, file.name);
+                let scan_started = Instant::now();
+                let paging_started = PagingStats::current();
+                let source = &*file.source;
+                let mut counting = CountingProgress::new(&mut *progress);
+                let found = match (file.algorithm, &ntlm) {
+                    (HashAlgorithm::Ntlm, Some(ntlm)) => {
+                        find::find_hash(source, &ntlm.hashes, options, &mut counting, cancel)
+                            .map(|status| status.map(|findings| ntlm.remap(findings)))
+                    }
+                    _ if file.newly_pwned => {
+                        let matched = results.iter().flat_map(|(_, findings)| findings);
+                        let unmatched = UnmatchedHashes::new(&hashes, matched);
+                        info!(
+                            "Looking up {} hashes without a local match in {}",
+                            unmatched.hashes.len(),
+                            file.name
+                        );
+                        find::find_hash(source, &unmatched.hashes, options, &mut counting, cancel)
+                            .map(|status| status.map(|findings| unmatched.remap(findings)))
+                    }
+                    _ => find::find_hash(source, &hashes, options, &mut counting, cancel),
+                };
+
+                match found {
+                    Ok(status) => {
+                        let size = source.state().map_or(0, |state| state.len());
+                        // lookups don't scan, so their throughput is relative to the database
+                        let scanned = counting.bytes();
+                        metrics.record(
+                            Phase::Scan,
+                            scan_started,
+                            Amount::Bytes(scanned.unwrap_or(size)),
+                        );
+                        bytes_scanned = add_scanned(bytes_scanned, scanned);
+                        if let Some((started, now)) = paging_started.zip(PagingStats::current()) {
+                            debug!("{}", now.since(&started).of_scan(size));
+                        }
+                        cancelled = status.is_cancelled();
+                        let corpus = Corpus {
+                            name: &file.name,
+                            snapshot: file.snapshot,
+                            form: None,
+                            newly_pwned: file.newly_pwned,
+                        };
+                        let findings = status.into_inner();
+                        worst[position] = worst[position].max(worst_count(&findings));
+                        results.push((corpus, findings));
+                        if cancelled {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        error!("Aborted: {}", err);
+                        return CheckOutcome::Failed;
+                    }
+                };
+
+                // the online lookup only covers the exported form
+                if let (HashAlgorithm::Sha1, Some(forms), false) =
+                    (file.algorithm, &forms, file.newly_pwned)
+                {
+                    debug!("Scanning {} other Unicode forms", forms.hashes.len());
+                    let mut counting = CountingProgress::new(&mut *progress);
+                    match find::find_hash(source, &forms.hashes, options, &mut counting, cancel) {
+                        Ok(status) => {
+                            bytes_scanned = add_scanned(bytes_scanned, counting.bytes());
+                            cancelled = status.is_cancelled();
+                            for (form, findings) in forms.remap(status.into_inner()) {
+                                let corpus = Corpus {
+                                    name: &file.name,
+                                    snapshot: file.snapshot,
+                                    form: Some(form),
+                                    newly_pwned: false,
+                                };
+                                worst[position] = worst[position].max(worst_count(&findings));
+                                results.push((corpus, findings));
+                            }
+
+                            if cancelled {
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            error!("Aborted: {}", err);
+                            return CheckOutcome::Failed;
+                        }
+                    }
+                }
+            }
+
+            if hash_files.len() > 1 {
+                debug!("Scanned all hash files in {:?}", metrics.total(Phase::Scan));
+            }
+
+            let footprint = hash_files
+                .iter()
+                .filter_map(|file| file.source.footprint())
+                .reduce(Footprint::merge);
+            if let Some(footprint) = footprint {
+                debug!(
+                    "Range API: {} ranges requested in {} requests, {} read from the prefetch cache - at most {} of {} hash characters left the machine",
+                    footprint.ranges,
+                    footprint.requests,
+                    footprint.cached,
+                    footprint.hash_chars,
+                    SHA1_BYTE_LENGTH * 2
+                );
+            }
+
+            let mut reports = report::merge(&hashes, &results, report_options.show_clean);
+            if report_options.collapse_variants {
+                reports = report::collapse_variants(reports);
+            }
+
+            // the collapsed variants are still separate accounts
+            let pwned: usize = reports
+                .iter()
+                .filter(|report| !report.is_clean())
+                .map(AccountReport::entries)
+                .sum();
+            // partial scans would underestimate the pwned accounts
+            let estimate = report_options
+                .sample
+                .filter(|_| !cancelled)
+                .map(|_| Estimate::new(population, hashes.len(), pwned));
+
+            let summaries = profiles::summarize(&profiles, &hashes, &reports);
+            if report_options.is_text() {
+                report::print(&reports, hash_files.len() > 1, pseudonyms);
+                print_worst(hash_files, &worst);
+                if !profiles.is_empty() {
+                    profiles::print(&summaries, &hashes, &reports, pseudonyms);
+                }
+
+                if hash_files.iter().any(|file| file.newly_pwned) {
+                    let newly_pwned: usize = reports
+                        .iter()
+                        .filter(|report| report.is_newly_pwned())
+                        .map(AccountReport::entries)
+                        .sum();
+                    info!(
+                        "{} of {} pwned accounts are newly pwned since the snapshot of the local hash files",
+                        newly_pwned, pwned
+                    );
+                }
+
+                if footprint.is_some() {
+                    report::print_attribution();
+                }
+            }
+
+            if report_options.needs_events() {
+                let corpora: Vec<_> = results
+                    .iter()
+                    .filter(|(corpus, _)| corpus.form.is_none())
+                    .map(|(corpus, _)| corpus.name)
+                    .collect();
+                let report =
+                    StructuredReport::new(&corpora, &reports, hashes.len(), !cancelled, pseudonyms)
+                        .with_estimate(estimate.clone())
+                        .with_network(footprint)
+                        .with_bytes_scanned(bytes_scanned)
+                        .with_inputs(&summaries, report_options.pwned_limits);
+                publish_report(&report, &report_options);
+            }
+            if report_options.health {
+                report_health(&hashes, &results, report_options.health_html);
+            }
+
+            if let Some((path, format)) = report_options.remediation {
+                match atomic_write(path, |file| {
+                    report::write_remediation(file, &reports, format)
+                }) {
+                    Ok(entries) => info!("Wrote {} entries to rotate to {:?}", entries, path),
+                    Err(err) => error!("Cannot write the remediation file {:?} {}", path, err),
+                }
+            }
+
+            if let Some(estimate) = &estimate {
+                estimate.print();
+            }
+
+            #[cfg(feature = "crypto")]
+            if let Some((path, key)) = report_options.attest {
+                let predicate = Predicate::new(run_started, hashes.len(), pwned, !cancelled);
+
+                // only the scanned lists are evidence of the run - the other forms are no extra lists
+                let lists = results
+                    .iter()
+                    .filter(|(corpus, _)| corpus.form.is_none())
+                    .count();
+                let scanned = &hash_files[..lists];
+                write_attestation(path, key, scanned, predicate);
+            }
+
+            let exceeded = report_options.pwned_limits.check(&summaries, pwned);
+            if cancelled {
+                // the missing results could be pwned, so the run must not pass as clean
+                error!("Cancelled - the results above are incomplete");
+                return CheckOutcome::Failed;
+            }
+
+            info!("Finished");
+            if exceeded {
+                CheckOutcome::Exceeded
+            } else {
+                CheckOutcome::Completed
+            }
+        }
+        Err(err) => {
+            error!("Failed to collect saved passwords: {}", err);
+            CheckOutcome::Failed
+        }
+    }
+}
+
+/// Sum of the scanned bytes - None if all hash files were only looked up
+fn add_scanned(total: Option<u64>, scanned: Option<u64>) -> Option<u64> {
+    match (total, scanned) {
+        (Some(total), Some(scanned)) => Some(total + scanned),
+        (total, scanned) => total.or(scanned),
+    }
+}
+
+/// Highest count of the findings - none if no count is known
+fn worst_count(findings: &[find::Finding]) -> Option<u64> {
+    findings.iter().filter_map(|finding| finding.count).max()
+}
+
+/// Compare the highest count of each hash database with its distribution to show the urgency
+fn print_worst(hash_files: &[HashFile], worst: &[Option<u64>]) {
+    for (file, &count) in hash_files.iter().zip(worst) {
+        if let Some((stats, count)) = file.stats.as_ref().zip(count) {
+            stats.print_worst(&file.name, count);
+        }
+    }
+}
 
-const SHA1_BYTE_LENGTH: usize = SHA1_OUTPUT_LEN;
+/// Sign the summary of the run together with the digests of the scanned hash lists
+#[cfg(feature = "crypto")]
+fn write_attestation(
+    path: &Path,
+    key: &Ed25519KeyPair,
+    hash_files: &[HashFile],
+    predicate: Predicate,
+) {
+    let mut subjects = Vec::with_capacity(hash_files.len());
+    for file in hash_files {
+        // reading them again would download them
+        if let Some(url) = &file.url {
+            let subject = Subject::remote(file.name.clone(), url.clone(), file.snapshot);
+            subjects.push(subject);
+            continue;
+        }
 
-type Sha1Hash = [u8; SHA1_BYTE_LENGTH];
+        debug!("Computing the digest of hash file {}", file.name);
+        match file.source.reader().and_then(attest::sha256_hex) {
+            Ok(sha256) => subjects.push(Subject::file(file.name.clone(), sha256)),
+            Err(err) => {
+                error!(
+                    "Cannot compute the digest of hash file {} {}",
+                    file.name, err
+                );
+                return;
+            }
+        }
+    }
 
-fn main() {
-    let matches = create_cli_options().get_matches();
+    let statement = Statement::new(subjects, predicate);
+    let written = attest::sign(&statement, key)
+        .map_err(io::Error::other)
+        .and_then(|envelope| {
+            atomic_write(path, |file| {
+                serde_json::to_writer_pretty(file, &envelope).map_err(io::Error::other)
+            })
+        });
 
-    // unwrap is safe here, because the two arguments are required
-    let passwords_file = matches.value_of_os(PASSWORD_KEY).unwrap();
-    let hash_file = matches.value_of_os(HASH_KEY).unwrap();
+    match written {
+        Ok(()) => info!("Wrote attestation to {:?}", path),
+        Err(err) => error!("Cannot write attestation {:?} {}", path, err),
+    }
+}
 
-    let verbose = matches.is_present(VERBOSE_KEY);
-    logger::set_logger(verbose);
-
-    debug!("Using passwords file: {:?}", passwords_file);
-    debug!("Using hash file: {:?}", hash_file);
-
-    let hash_file = File::open(hash_file);
-    let reader = csv::Reader::from_path(passwords_file);
-    match hash_file {
-        Err(err) => error!("Cannot access password file {}", err),
-        Ok(file) => match reader {
-            Err(err) => error!("Cannot access hash file {}", err),
-            Ok(reader) => run(reader, file),
-        },
+/// Sign the canonical form of the JSON report, so the stored report could be proven unmodified
+#[cfg(feature = "crypto")]
+fn write_report_signature(path: &Path, key: &Ed25519KeyPair, report: &StructuredReport<'_>) {
+    let written = report
+        .canonical_json()
+        .map_err(io::Error::other)
+        .and_then(|canonical| {
+            let signature = report::sign(&canonical, key);
+            atomic_write(path, |file| file.write_all(signature.as_bytes()))
+        });
+
+    match written {
+        Ok(()) => info!("Wrote report signature to {:?}", path),
+        Err(err) => error!("Cannot write report signature {:?} {}", path, err),
     }
 }
 
-fn create_cli_options<'help>() -> App<'help> {
-    App::new(crate_name!())
-        .about(crate_description!())
-        .version(crate_version!())
-        .arg(
-            Arg::new(PASSWORD_KEY)
-                .about("Sets passwords csv input list")
-                .required(true)
-                .index(1),
-        )
-        .arg(
-            Arg::new(HASH_KEY)
-                .about("SHA-1 hash list sorted by hash")
-                .required(true)
-                .index(2),
-        )
-        .arg(
-            Arg::new(VERBOSE_KEY)
-                .short('v')
-                .long("verbose")
-                .about("Verbose output"),
-        )
+/// Progress bar that doesn't interfere with the report output
+fn progress_sink(report_options: &ReportOptions<'_>) -> Box<dyn ProgressSink> {
+    #[cfg(feature = "tui")]
+    if let Some(tui) = report_options.tui {
+        return Box::new(tui.progress());
+    }
+
+    if report_options.format.is_structured() {
+        Box::new(TerminalProgress::on_stderr())
+    } else {
+        Box::new(TerminalProgress::default())
+    }
 }
 
-fn run(password_reader: csv::Reader<impl Read>, hash_file: File) {
-    match collect::collect_hashes(password_reader) {
-        Ok(mut hashes) => {
-            info!("Finished hashing");
+/// Hand the report to the structured output, the signature, the interface and the reporters
+fn publish_report(report: &StructuredReport<'_>, report_options: &ReportOptions<'_>) {
+    if report_options.format.is_structured() {
+        write_structured(report_options.format, report);
+    }
 
-            // unstable is slightly faster than the normal search - we don't care about mixed equal
-            // entries so lets use this
-            hashes.sort_unstable();
-            info!("Sorted");
+    #[cfg(feature = "crypto")]
+    if let Some((path, key)) = report_options.sign_report {
+        write_report_signature(path, key, report);
+    }
 
-            match find::find_hash(&hash_file, &hashes) {
-                Ok(()) => info!("Finished"),
-                Err(err) => error!("Aborted: {}", err),
-            };
-        }
-        Err(err) => {
-            error!("Failed parse saved passwords: {:?}", err);
+    #[cfg(feature = "tui")]
+    if let Some(tui) = report_options.tui {
+        tui.report(report);
+    }
+
+    for command in report_options.reporters {
+        if let Err(err) = reporter::run_reporter(command, report) {
+            error!("Reporter {} failed: {}", command, err);
         }
+    }
+}
+
+/// Write the report to the standard output
+fn write_structured(format: ReportFormat, report: &StructuredReport<'_>) {
+    let stdout = io::stdout();
+    let result = match format {
+        ReportFormat::Ndjson => report.write_ndjson(stdout.lock()),
+        _ => report.write_json(stdout.lock()),
     };
+
+    if let Err(err) = result {
+        error!("Failed to write the report {}", err);
+    }
+}
+
+/// Print the vault health summary and write it as HTML page if requested
+fn report_health(
+    hashes: &[SavedHash],
+    results: &[(Corpus<'_>, Vec<find::Finding>)],
+    html: Option<&Path>,
+) {
+    let health = Health::evaluate(hashes, results, SystemTime::now());
+    health.print();
+
+    if let Some(path) = html {
+        match atomic_write(path, |file| health.write_html(file)) {
+            Ok(()) => info!("Wrote health summary to {:?}", path),
+            Err(err) => error!("Cannot write health summary {:?} {}", path, err),
+        }
+    }
 }
 
+#[cfg(any(feature = "http", feature = "vault-kv"))]
+mod agent;
+mod atomic;
+#[cfg(feature = "crypto")]
+mod attest;
+#[cfg(feature = "crypto")]
+mod bundle;
+mod cancel;
 mod collect;
+mod config;
+mod date;
+#[cfg(feature = "vault")]
+mod decrypt;
+mod detect;
+mod discover;
+#[cfg(feature = "http")]
+mod download;
+mod encoding;
+mod export;
+mod extract;
 mod find;
+mod generate;
+mod hasher;
+mod health;
+mod hook;
 mod logger;
+mod meta;
+mod metrics;
+mod native_host;
+mod normalize;
+mod paging;
+mod paths;
+mod pepper;
+mod profiles;
+mod progress;
+mod pseudonym;
+mod redact;
+mod report;
+mod reporter;
+mod sample;
+#[cfg(feature = "crypto")]
+mod serve;
+#[cfg(feature = "http")]
+mod setup;
+#[cfg(any(feature = "http", feature = "index"))]
+mod space;
+mod stats;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "vault-kv")]
+mod vault_kv;
 
 #[cfg(test)]
 mod test {
@@ -107,6 +3737,970 @@ mod test {
         assert!(matches.is_ok(), "CLI parse result {:?}", matches);
     }
 
+    #[test]
+    fn test_multiple_hash_files() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "def.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+
+        let hash_files: Vec<_> = matches.values_of(HASH_KEY).unwrap().collect();
+        assert_eq!(hash_files, ["abc.txt", "def.txt"]);
+    }
+
+    #[test]
+    fn test_strategy() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(matches.value_of_t(STRATEGY_KEY), Ok(Strategy::Auto));
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--strategy",
+            "interpolation",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(
+            matches.value_of_t(STRATEGY_KEY),
+            Ok(Strategy::Interpolation)
+        );
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--strategy",
+            "unknown",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_strict() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(!matches.is_present(STRICT_KEY));
+
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "--strict"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(matches.is_present(STRICT_KEY));
+    }
+
+    #[test]
+    fn test_show_clean() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "--show-clean"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(matches.is_present(SHOW_CLEAN_KEY));
+    }
+
+    #[test]
+    fn test_report_format() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(
+            matches.value_of_t(REPORT_FORMAT_KEY),
+            Ok(ReportFormat::Text)
+        );
+
+        let args = ["pwned-check", "schema"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(matches.subcommand(), Some((SCHEMA_COMMAND, _)));
+    }
+
+    #[test]
+    fn test_native_host_caller() {
+        // Chrome passes the origin and on Windows the parent window, Firefox the manifest and id
+        for caller in [
+            &["chrome-extension://abcdef/", "--parent-window=0"][..],
+            &[
+                "/usr/lib/mozilla/native-messaging-hosts/pwned_check.json",
+                "check@example.com",
+            ],
+        ] {
+            let args = ["pwned-check", NATIVE_HOST_COMMAND].iter().chain(caller);
+            let matches = create_cli_options().try_get_matches_from(args).unwrap();
+            let sub_matches = matches.subcommand_matches(NATIVE_HOST_COMMAND).unwrap();
+            assert_eq!(sub_matches.values_of(CALLER_KEY).unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_health() {
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--health-html",
+            "health.html",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(!matches.is_present(HEALTH_KEY));
+        assert_eq!(matches.value_of(HEALTH_HTML_KEY), Some("health.html"));
+    }
+
+    #[test]
+    fn test_remediation() {
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--remediation",
+            "rotate.csv",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(REMEDIATION_KEY), Some("rotate.csv"));
+        assert_matches!(
+            matches.value_of_t(REMEDIATION_FORMAT_KEY),
+            Ok(RemediationFormat::Bitwarden)
+        );
+
+        // the entries have to be imported with the real URLs and usernames
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--remediation",
+            "rotate.csv",
+            "--anonymize",
+            "key",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_user_agent() {
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--user-agent",
+            "acme-audit/1.0",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(USER_AGENT_KEY), Some("acme-audit/1.0"));
+
+        // also after the subcommands of the online mode
+        let args = [
+            "pwned-check",
+            "prefetch",
+            "./xyz.txt",
+            "--user-agent",
+            "acme-audit/1.0",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(USER_AGENT_KEY), Some("acme-audit/1.0"));
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_fallback_reason() {
+        let now = SystemTime::now();
+        let max_age = Duration::from_secs(90 * 24 * 60 * 60);
+        let fresh = now - Duration::from_secs(24 * 60 * 60);
+        let stale = now - Duration::from_secs(100 * 24 * 60 * 60);
+
+        let missing: io::Result<()> = Err(io::Error::from(io::ErrorKind::NotFound));
+        assert_matches!(fallback_reason(&missing, None, max_age, now), Some(_));
+
+        assert_eq!(fallback_reason(&Ok(()), Some(fresh), max_age, now), None);
+        assert_eq!(
+            fallback_reason(&Ok(()), Some(stale), max_age, now),
+            Some("is 100 days old".to_string())
+        );
+
+        // the age of files without metadata is unknown
+        assert_eq!(fallback_reason(&Ok(()), None, max_age, now), None);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "--timeout", "15m"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(TIMEOUT_KEY), Some("15m"));
+
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "--timeout", "soon"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_max_corpus_age() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(
+            max_corpus_age(&matches),
+            Duration::from_secs(90 * 24 * 60 * 60)
+        );
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--max-corpus-age",
+            "1d",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(max_corpus_age(&matches), Duration::from_secs(24 * 60 * 60));
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--max-corpus-age",
+            "old",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_password_encoding() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(matches.value_of_t(ENCODING_KEY), Ok(PasswordEncoding::Utf8));
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--password-encoding",
+            "windows-1252",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(
+            matches.value_of_t(ENCODING_KEY),
+            Ok(PasswordEncoding::Windows1252)
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(normalization(&matches), Normalization::default());
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--normalize",
+            "trim,bom",
+            "--normalize",
+            "nfc",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(
+            normalization(&matches),
+            Normalization {
+                trim: true,
+                strip_bom: true,
+                nfc: true,
+            }
+        );
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--normalize",
+            "lower",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_pipeline_buffer() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(matches.value_of_t::<usize>(PIPELINE_BUFFER_KEY), Ok(128));
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--pipeline-buffer",
+            "0",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_matches!(matches.value_of_t::<usize>(PIPELINE_BUFFER_KEY), Ok(0));
+
+        let args = [
+            "pwned-check",
+            "./xyz.txt",
+            "abc.txt",
+            "--pipeline-buffer",
+            "-1",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(24 * 60 * 60)));
+
+        assert_matches!(parse_duration(""), Err(_));
+        assert_matches!(parse_duration("m"), Err(_));
+        assert_matches!(parse_duration("5w"), Err(_));
+        assert_matches!(parse_duration("-5s"), Err(_));
+        assert_matches!(parse_duration("999999999999999999d"), Err(_));
+        assert_matches!(parse_duration("99999999999999999999"), Err(_));
+    }
+
+    #[test]
+    fn test_input() {
+        let args = [
+            "pwned-check",
+            "hibp.txt",
+            "--input",
+            "alice=alice.csv",
+            "--input",
+            "bob=exports/bob=old.csv",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let inputs: Vec<_> = matches
+            .values_of(INPUT_KEY)
+            .unwrap()
+            .map(|value| parse_input(value).unwrap())
+            .collect();
+        assert_eq!(
+            inputs,
+            [("alice", "alice.csv"), ("bob", "exports/bob=old.csv")]
+        );
+
+        // the passwords file isn't required with labeled inputs
+        let args = ["pwned-check", "--input", "alice=alice.csv"];
+        assert!(create_cli_options().try_get_matches_from(&args).is_ok());
+
+        for invalid in ["alice.csv", "=alice.csv", "alice="] {
+            let args = ["pwned-check", "--input", invalid];
+            assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+        }
+    }
+
+    #[test]
+    fn test_input_dir() {
+        let directory = std::env::temp_dir().join(format!("pwned-check-inputs-{}", process::id()));
+        fs::create_dir_all(directory.join("archive")).unwrap();
+        for name in &[
+            "ws-02.csv",
+            "ws-01.csv",
+            ".ws-03.csv.swp",
+            "archive/ws-04.csv",
+        ] {
+            fs::write(directory.join(name), "name,url,username,password\n").unwrap();
+        }
+
+        let inputs = directory_inputs(&directory).unwrap();
+        let names: Vec<_> = inputs.iter().map(|(name, _)| name.as_str()).collect();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(names, ["ws-01", "ws-02"]);
+        assert_eq!(Path::new(&inputs[0].1), directory.join("ws-01.csv"));
+
+        // the passwords file isn't required with an input directory
+        let args = ["pwned-check", "--input-dir", "exports"];
+        assert!(create_cli_options().try_get_matches_from(&args).is_ok());
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        assert_eq!(parse_limit("10"), Ok((None, 10)));
+        assert_eq!(parse_limit("alice=0"), Ok((Some("alice"), 0)));
+        assert_eq!(parse_limit(" ws-01 = 3"), Ok((Some("ws-01"), 3)));
+
+        for invalid in ["", "-1", "alice", "=2", "alice=", "alice=two"] {
+            assert!(parse_limit(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("5"), Ok(5.0));
+        assert_eq!(parse_percent("0.5%"), Ok(0.5));
+        assert_eq!(parse_percent("100"), Ok(100.0));
+
+        assert_matches!(parse_percent("0"), Err(_));
+        assert_matches!(parse_percent("101"), Err(_));
+        assert_matches!(parse_percent("five"), Err(_));
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_fallback_online_once() {
+        // the metadata of the fixture is older than the maximum age
+        let stale = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/internal.txt");
+        let hash_paths = vec![
+            (stale.as_os_str(), HashAlgorithm::Sha1),
+            (stale.as_os_str(), HashAlgorithm::Sha1),
+        ];
+
+        let max_age = Duration::from_secs(90 * 24 * 60 * 60);
+        let (hash_files, fallback) = open_hash_files(hash_paths, true, max_age).unwrap();
+        assert!(fallback);
+        assert_eq!(hash_files.len(), 1);
+        assert_eq!(hash_files[0].url.as_deref(), Some(find::HIBP_RANGE_API));
+        assert!(!hash_files[0].newly_pwned);
+    }
+
+    /// Export that cancels the run once it's read - like a timeout while reading the exports
+    struct CancellingReader<'a> {
+        data: &'a [u8],
+        cancel: Option<&'a CancellationToken>,
+    }
+
+    impl Read for CancellingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(cancel) = self.cancel {
+                cancel.cancel();
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_timeout_while_reading() {
+        let data = b"url,username,password\nhttps://example.com/,user,pass\n";
+        let cancel = CancellationToken::default();
+        // the first export is read completely, the second one is cancelled
+        let readers = vec![
+            (Some("ws-01".to_string()), None),
+            (Some("ws-02".to_string()), Some(&cancel)),
+        ]
+        .into_iter()
+        .map(|(profile, cancel)| {
+            let reader = CancellingReader { data, cancel };
+            (profile, csv::Reader::from_reader(reader).into())
+        })
+        .collect();
+
+        let options = ScanOptions {
+            strategy: Strategy::Auto,
+            strict: false,
+            mapping: MapOptions::default(),
+            watch: false,
+        };
+        let hashing = HashOptions {
+            pipeline_buffer: 1,
+            pepper: Pepper::none(),
+            ntlm: false,
+            filter: EntryFilter::default(),
+            normalization: Normalization::default(),
+            unicode_forms: false,
+        };
+
+        let output = std::env::temp_dir().join(format!("pwned-check-timeout-{}", process::id()));
+        let reporters = [format!("cat > '{}'", output.display())];
+        let reporters: Vec<_> = reporters.iter().map(String::as_str).collect();
+        let pwned_limits = PwnedLimits::default();
+        let report_options = ReportOptions {
+            format: ReportFormat::Text,
+            show_clean: false,
+            collapse_variants: true,
+            health: false,
+            health_html: None,
+            remediation: None,
+            reporters: &reporters,
+            #[cfg(feature = "tui")]
+            tui: None,
+            #[cfg(feature = "crypto")]
+            attest: None,
+            #[cfg(feature = "crypto")]
+            sign_report: None,
+            sample: None,
+            pwned_limits: &pwned_limits,
+        };
+
+        let outcome = run(
+            readers,
+            &[],
+            options,
+            hashing,
+            report_options,
+            &mut Pseudonymizer::disabled(),
+            &cancel,
+        );
+        assert_eq!(outcome, CheckOutcome::Failed);
+        assert_eq!(exit_code(outcome, true), Some(TIMEOUT_EXIT_CODE));
+        // Ctrl-C cancels the same token without the timer flag
+        assert_eq!(exit_code(outcome, false), Some(CHECK_FAILED_EXIT_CODE));
+        // a run that completed just before the timer fired
+        assert_eq!(exit_code(CheckOutcome::Completed, true), None);
+
+        let events = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+        // unwrap is safe, because the finish event is always written
+        let finish: serde_json::Value =
+            serde_json::from_str(events.lines().last().unwrap()).unwrap();
+        let summary = &finish["summary"];
+        assert_eq!(summary["complete"], false);
+        assert_eq!(summary["checked"], 0);
+        assert_eq!(summary["unchecked"], 1);
+    }
+
+    #[test]
+    fn test_corpus_name() {
+        assert_eq!(corpus_name(OsStr::new("/data/hibp.txt")), "hibp.txt");
+        assert_eq!(corpus_name(OsStr::new("internal.txt")), "internal.txt");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_corpus_names_unique() {
+        let names = |paths: &[&str]| {
+            let paths: Vec<_> = paths.iter().map(OsStr::new).collect();
+            corpus_names(&paths)
+        };
+
+        assert_eq!(
+            names(&["/data/hibp.txt", "internal.txt"]),
+            ["hibp.txt", "internal.txt"]
+        );
+        assert_eq!(
+            names(&["a/pwned.txt", "b/pwned.txt", "c/hibp.txt"]),
+            ["a/pwned.txt", "b/pwned.txt", "hibp.txt"]
+        );
+        assert_eq!(
+            names(&["x/a/pwned.txt", "y/a/pwned.txt", "pwned.txt"]),
+            ["x/a/pwned.txt", "y/a/pwned.txt", "pwned.txt"]
+        );
+        assert_eq!(
+            names(&["pwned.txt", "pwned.txt"]),
+            ["pwned.txt", "pwned.txt (2)"]
+        );
+    }
+
+    #[test]
+    fn test_hash_only() {
+        let args = ["pwned-check", "hash-only", "./xyz.txt", "--prefix", "-v"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(matches.is_present(VERBOSE_KEY));
+
+        let sub_matches = matches.subcommand_matches(HASH_ONLY_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(PASSWORD_KEY), Some("./xyz.txt"));
+        assert!(sub_matches.is_present(PREFIX_KEY));
+        assert_matches!(
+            sub_matches.value_of_t(ENCODING_KEY),
+            Ok(PasswordEncoding::Utf8)
+        );
+
+        let args = ["pwned-check", "hash-only"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_prefetch() {
+        let args = ["pwned-check", "prefetch", "passwords.csv", "--refresh"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(PREFETCH_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(API_KEY), Some(find::HIBP_RANGE_API));
+        assert!(sub_matches.is_present(REFRESH_KEY));
+
+        // the API path is appended, so only the range endpoint is accepted
+        for api in [
+            "http://localhost:8080/range/",
+            "https://example.com/hibp/range/",
+        ] {
+            let args = ["pwned-check", "prefetch", "passwords.csv", "--api", api];
+            assert!(create_cli_options().try_get_matches_from(&args).is_ok());
+        }
+
+        let args = [
+            "pwned-check",
+            "prefetch",
+            "passwords.csv",
+            "--api",
+            "https://example.com/",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_answer() {
+        let args = [
+            "pwned-check",
+            "answer",
+            "hashes.csv",
+            "abc.txt",
+            "def.txt",
+            "--signing-key",
+            "key.pk8",
+            "-o",
+            "answer.csv",
+            "--strict",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(ANSWER_COMMAND).unwrap();
+        let hash_files: Vec<_> = sub_matches.values_of(HASH_KEY).unwrap().collect();
+        assert_eq!(hash_files, ["abc.txt", "def.txt"]);
+        assert!(sub_matches.is_present(STRICT_KEY));
+        assert!(!sub_matches.is_present(SHOW_CLEAN_KEY));
+
+        // signing key is required
+        let args = ["pwned-check", "answer", "hashes.csv", "abc.txt", "-o", "a"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+
+        let args = ["pwned-check", "apply-answers", "answer.csv"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_anonymize() {
+        let args = ["pwned-check", "./xyz.txt", "abc.txt", "--anonymize", "key"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(ANONYMIZE_KEY), Some("key"));
+
+        let args = [
+            "pwned-check",
+            "hash-only",
+            "./xyz.txt",
+            "--anonymize",
+            "key",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(HASH_ONLY_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(ANONYMIZE_KEY), Some("key"));
+    }
+
+    #[test]
+    fn test_gen_test_data() {
+        let args = ["pwned-check", "gen-test-data", "out", "--lines", "1000"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(GENERATE_COMMAND).unwrap();
+        assert_matches!(sub_matches.value_of_t::<usize>(LINES_KEY), Ok(1000));
+        assert_matches!(sub_matches.value_of_t::<u64>(SEED_KEY), Ok(0));
+
+        let args = ["pwned-check", "gen-test-data", "out"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+
+        let args = [
+            "pwned-check",
+            "gen-test-data",
+            "out",
+            "--lines",
+            "10",
+            "--pwned-fraction",
+            "1.5",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[cfg(feature = "index")]
+    #[test]
+    fn test_convert() {
+        let args = ["pwned-check", "convert", "list.txt", "list.fst"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(CONVERT_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(OUTPUT_KEY), Some("list.fst"));
+
+        let args = ["pwned-check", "convert", "list.txt", "--prefixes-only"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(CONVERT_COMMAND).unwrap();
+        assert!(sub_matches.is_present(PREFIXES_ONLY_KEY));
+
+        let args = ["pwned-check", "convert", "list.txt", "list.fst", "--mmap"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(CONVERT_COMMAND).unwrap();
+        assert!(sub_matches.is_present(MMAP_KEY));
+
+        let args = ["pwned-check", "convert", "list.txt"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+
+        let args = [
+            "pwned-check",
+            "convert",
+            "a.txt",
+            "b.fst",
+            "--prefixes-only",
+        ];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_hook() {
+        let args = ["pwned-check", "hook", "--staged", "--pattern", "secrets/"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(HOOK_COMMAND).unwrap();
+        assert!(sub_matches.is_present(STAGED_KEY));
+        assert_eq!(sub_matches.values_of(PATTERN_KEY).unwrap().count(), 1);
+
+        let args = ["pwned-check", "hook", ".env", "--corpus", "list.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(HOOK_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(FILES_KEY), Some(".env"));
+        assert_eq!(sub_matches.value_of(HASH_KEY), Some("list.txt"));
+
+        for args in [
+            &["pwned-check", "hook"][..],
+            &["pwned-check", "hook", "--staged", ".env"],
+            &["pwned-check", "hook", "--staged", "--pattern", "("],
+        ] {
+            assert_matches!(create_cli_options().try_get_matches_from(args), Err(_));
+        }
+    }
+
+    #[cfg(feature = "vault")]
+    #[test]
+    fn test_hook_keys() {
+        let args = [
+            "pwned-check",
+            "hook",
+            "--staged",
+            "--vault-password-file",
+            "vault-password",
+            "--age-key-file",
+            "keys.txt",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(HOOK_COMMAND).unwrap();
+        assert_eq!(
+            sub_matches.value_of(VAULT_PASSWORD_FILE_KEY),
+            Some("vault-password")
+        );
+        assert_eq!(sub_matches.value_of(AGE_KEY_FILE_KEY), Some("keys.txt"));
+    }
+
+    #[cfg(feature = "vault-kv")]
+    #[test]
+    fn test_vault_kv() {
+        let args = [
+            "pwned-check",
+            "vault-kv",
+            "apps/",
+            "--address",
+            "https://vault.example.com:8200",
+            "--kv-version",
+            "1",
+            "--field",
+            "api_key",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(VAULT_KV_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(SECRET_PREFIX_KEY), Some("apps/"));
+        assert_eq!(sub_matches.value_of(MOUNT_KEY), Some("secret"));
+        assert_eq!(
+            sub_matches
+                .value_of_t::<vault_kv::KvVersion>(KV_VERSION_KEY)
+                .ok(),
+            Some(vault_kv::KvVersion::V1)
+        );
+        assert_eq!(sub_matches.value_of(FIELD_KEY), Some("api_key"));
+
+        for args in [
+            &[
+                "pwned-check",
+                "vault-kv",
+                "--address",
+                "http://localhost:8200",
+                "--kv-version",
+                "3",
+            ][..],
+            &[
+                "pwned-check",
+                "vault-kv",
+                "--address",
+                "http://localhost:8200",
+                "--secret-id-file",
+                "secret-id",
+            ],
+            // the acknowledgment is only meaningful with a limit
+            &[
+                "pwned-check",
+                "vault-kv",
+                "--address",
+                "http://localhost:8200",
+                "--acknowledge",
+            ],
+        ] {
+            assert_matches!(create_cli_options().try_get_matches_from(args), Err(_));
+        }
+
+        let args = [
+            "pwned-check",
+            "vault-kv",
+            "--address",
+            "http://localhost:8200",
+            "--confirm-count",
+            "50",
+            "--acknowledge",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(VAULT_KV_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(CONFIRM_COUNT_KEY), Some("50"));
+        assert!(sub_matches.is_present(ACKNOWLEDGE_KEY));
+    }
+
+    #[cfg(feature = "index")]
+    #[test]
+    fn test_stats() {
+        let args = ["pwned-check", "stats", "list.txt", "--strict"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(STATS_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(HASH_KEY), Some("list.txt"));
+        assert!(sub_matches.is_present(STRICT_KEY));
+
+        let args = ["pwned-check", "stats"];
+        assert_matches!(create_cli_options().try_get_matches_from(&args), Err(_));
+    }
+
+    #[test]
+    fn test_ntlm() {
+        let args = [
+            "pwned-check",
+            "ad.csv",
+            "--ntlm",
+            "a.txt",
+            "--ntlm",
+            "b.txt",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let ntlm_files: Vec<_> = matches.values_of(NTLM_KEY).unwrap().collect();
+        assert_eq!(ntlm_files, ["a.txt", "b.txt"]);
+        assert!(!matches.is_present(HASH_KEY));
+
+        let args = ["pwned-check", "ad.csv", "sha1.txt", "--ntlm", "ntlm.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(HASH_KEY), Some("sha1.txt"));
+    }
+
+    #[test]
+    fn test_tags() {
+        let args = [
+            "pwned-check",
+            "hash-only",
+            "in.csv",
+            "--include-tag",
+            "Work",
+            "--include-tag",
+            "Shared",
+            "--exclude-tag",
+            "Archive",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(HASH_ONLY_COMMAND).unwrap();
+        let filter = entry_filter(sub_matches);
+        assert_eq!(filter.tags.include, ["Work", "Shared"]);
+        assert_eq!(filter.tags.exclude, ["Archive"]);
+        assert!(filter.exclude_patterns.is_empty());
+    }
+
+    #[test]
+    fn test_exclude_pattern() {
+        let args = [
+            "pwned-check",
+            "in.csv",
+            "hash.txt",
+            "--exclude-pattern",
+            "-test",
+            "--exclude-pattern",
+            r"^https?://\d+\.",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let filter = entry_filter(&matches);
+        assert_eq!(filter.exclude_patterns.len(), 2);
+        assert!(filter.exclude_patterns.is_match("https://10.0.0.1/"));
+
+        let args = [
+            "pwned-check",
+            "in.csv",
+            "hash.txt",
+            "--exclude-pattern",
+            "(",
+        ];
+        assert!(create_cli_options().try_get_matches_from(&args).is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_attest() {
+        let args = ["pwned-check", "in.csv", "hash.txt", "--attest", "run.json"];
+        assert!(create_cli_options().try_get_matches_from(&args).is_err());
+
+        let args = [
+            "pwned-check",
+            "in.csv",
+            "hash.txt",
+            "--attest",
+            "run.json",
+            "--signing-key",
+            "audit.key",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(matches.value_of(ATTEST_KEY), Some("run.json"));
+
+        let args = ["pwned-check", "attest-verify", "run.json"];
+        assert!(create_cli_options().try_get_matches_from(&args).is_err());
+
+        let args = [
+            "pwned-check",
+            "attest-verify",
+            "run.json",
+            "--public-key",
+            "ab",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(ATTEST_VERIFY_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(ATTEST_KEY), Some("run.json"));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_sign_report() {
+        let args = [
+            "pwned-check",
+            "in.csv",
+            "hash.txt",
+            "--sign-report",
+            "report.key",
+        ];
+        assert!(create_cli_options().try_get_matches_from(&args).is_err());
+
+        let args = [
+            "pwned-check",
+            "in.csv",
+            "hash.txt",
+            "--report-format",
+            "json",
+            "--sign-report",
+            "report.key",
+            "--report-signature",
+            "report.json.minisig",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert_eq!(
+            matches.value_of(REPORT_SIGNATURE_KEY),
+            Some("report.json.minisig")
+        );
+
+        let args = [
+            "pwned-check",
+            "report-verify",
+            "report.json",
+            "--public-key",
+            "ab",
+        ];
+        assert!(create_cli_options().try_get_matches_from(&args).is_err());
+
+        let args = [
+            "pwned-check",
+            "report-verify",
+            "report.json",
+            "--signature",
+            "report.json.minisig",
+            "--public-key",
+            "ab",
+        ];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        let sub_matches = matches.subcommand_matches(REPORT_VERIFY_COMMAND).unwrap();
+        assert_eq!(sub_matches.value_of(REPORT_KEY), Some("report.json"));
+    }
+
     #[test]
     fn test_failed_parse() {
         let args = ["pwned-check", "./xyz.txt", "abc.txt", "--non-existing-flag"];
@@ -117,11 +4711,16 @@ mod test {
 
     #[test]
     fn test_missing_file() {
-        let args = ["pwned-check", "file.txt"];
+        let args = ["pwned-check"];
         let matches = create_cli_options().try_get_matches_from(&args);
         assert_matches!(matches, Err(_));
+    }
 
-        let matches = create_cli_options().try_get_matches_from(&args[..1]);
-        assert_matches!(matches, Err(_));
+    #[test]
+    fn test_discovered_corpus() {
+        // the hash file is searched for if it's omitted
+        let args = ["pwned-check", "file.txt"];
+        let matches = create_cli_options().try_get_matches_from(&args).unwrap();
+        assert!(!matches.is_present(HASH_KEY));
     }
 }
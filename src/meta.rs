@@ -0,0 +1,131 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::atomic::atomic_write;
+
+/// Suffix of the metadata file next to the hash database
+const SIDECAR_SUFFIX: &str = ".meta";
+
+/// Metadata of a hash database written by the commands that create it (ex: `download`). Stored as
+/// `key=value` lines next to the database, so it could be inspected and edited by hand.
+#[derive(Debug, PartialEq)]
+pub struct CorpusMeta {
+    /// Publishing time of the database - not the time it was downloaded
+    pub snapshot: SystemTime,
+    /// Where the database was downloaded from
+    pub source: Option<String>,
+}
+
+impl CorpusMeta {
+    /// Metadata of the database or none if there is no sidecar file
+    pub fn read(corpus: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(sidecar_path(corpus)) {
+            Ok(content) => parse(&content)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, corpus: &Path) -> io::Result<()> {
+        let snapshot = self
+            .snapshot
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        let mut content = format!("# pwned-check corpus metadata\nsnapshot={}\n", snapshot);
+        if let Some(source) = &self.source {
+            // unwrap is safe, because writing to a string never fails
+            writeln!(content, "source={}", source).unwrap();
+        }
+
+        atomic_write(&sidecar_path(corpus), |file| {
+            file.write_all(content.as_bytes())
+        })
+    }
+
+    /// Time since the snapshot - zero if it's in the future
+    pub fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.snapshot).unwrap_or_default()
+    }
+}
+
+fn sidecar_path(corpus: &Path) -> PathBuf {
+    let mut path = corpus.as_os_str().to_os_string();
+    path.push(SIDECAR_SUFFIX);
+    PathBuf::from(path)
+}
+
+fn parse(content: &str) -> Result<CorpusMeta, String> {
+    let mut snapshot = None;
+    let mut source = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // unknown keys are skipped for newer versions
+        match line.split_once('=') {
+            Some(("snapshot", secs)) => {
+                let secs = secs
+                    .parse()
+                    .map_err(|_| format!("Invalid snapshot time {}", secs))?;
+                snapshot = Some(UNIX_EPOCH + Duration::from_secs(secs));
+            }
+            Some(("source", url)) => source = Some(url.to_string()),
+            _ => {}
+        }
+    }
+
+    Ok(CorpusMeta {
+        snapshot: snapshot.ok_or("Missing snapshot time")?,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let corpus =
+            std::env::temp_dir().join(format!("pwned-check-meta-{}.txt", std::process::id()));
+        assert_eq!(CorpusMeta::read(&corpus).unwrap(), None);
+
+        let meta = CorpusMeta {
+            snapshot: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            source: Some("https://example.com/pwned.txt".to_string()),
+        };
+        meta.write(&corpus).unwrap();
+        assert_eq!(CorpusMeta::read(&corpus).unwrap(), Some(meta));
+
+        fs::remove_file(sidecar_path(&corpus)).unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        let meta = parse("snapshot=86400\nfuture=value\n").unwrap();
+        assert_eq!(meta.snapshot, UNIX_EPOCH + Duration::from_secs(86_400));
+        assert_eq!(meta.source, None);
+
+        assert!(parse("source=https://example.com\n").is_err());
+        assert!(parse("snapshot=yesterday\n").is_err());
+    }
+
+    #[test]
+    fn test_age() {
+        let meta = parse("snapshot=0").unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(3 * 86_400);
+        assert_eq!(meta.age(now), Duration::from_secs(3 * 86_400));
+
+        let future = parse("snapshot=999999").unwrap();
+        assert_eq!(future.age(now), Duration::from_secs(0));
+    }
+}
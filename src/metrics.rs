@@ -0,0 +1,311 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+/// Separately timed step of a run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Parsing the exported passwords file - overlaps with hashing
+    ReadCsv,
+    /// Hashing all passwords including waiting for the reader
+    Hashing,
+    Sort,
+    /// Scanning a single hash database
+    Scan,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::ReadCsv => "CSV read",
+            Phase::Hashing => "Hashing",
+            Phase::Sort => "Sort",
+            Phase::Scan => "Scan",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Processed amount of a phase for the throughput
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Amount {
+    Bytes(u64),
+    Records(u64),
+}
+
+/// Duration and processed amount of a finished phase
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhaseMetric {
+    pub phase: Phase,
+    pub duration: Duration,
+    pub amount: Amount,
+}
+
+impl PhaseMetric {
+    /// Processed amount per second
+    pub fn throughput(&self) -> f64 {
+        let amount = match self.amount {
+            Amount::Bytes(amount) | Amount::Records(amount) => amount,
+        };
+
+        // prevent infinity for phases faster than the timer resolution
+        amount as f64 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for PhaseMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let throughput = self.throughput();
+        match self.amount {
+            Amount::Bytes(bytes) => write!(
+                f,
+                "{}: {:?} - {} bytes at {:.2} MB/s",
+                self.phase,
+                self.duration,
+                bytes,
+                throughput / 1_000_000.0
+            ),
+            Amount::Records(records) => write!(
+                f,
+                "{}: {:?} - {} records at {:.0} records/s",
+                self.phase, self.duration, records, throughput
+            ),
+        }
+    }
+}
+
+/// Timings of all phases of a run in the order they finished
+#[derive(Debug, Default)]
+pub struct Metrics {
+    phases: Vec<PhaseMetric>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, phase: Phase, started: Instant, amount: Amount) {
+        let metric = PhaseMetric {
+            phase,
+            duration: started.elapsed(),
+            amount,
+        };
+
+        debug!("{}", metric);
+        self.phases.push(metric);
+    }
+
+    /// Summed duration of all phases of the type - ex: the scans of multiple hash databases
+    pub fn total(&self, phase: Phase) -> Duration {
+        self.phases
+            .iter()
+            .filter(|metric| metric.phase == phase)
+            .map(|metric| metric.duration)
+            .sum()
+    }
+}
+
+/// Sub-buckets per power of two - the percentiles are within 12.5% of the real latency
+const SUB_BUCKETS: u64 = 8;
+
+/// Covers latencies up to 2^50 microseconds
+const LATENCY_BUCKETS: usize = (SUB_BUCKETS + 48 * SUB_BUCKETS) as usize;
+
+/// Latencies of the point lookups in the long running modes (ex: serve and native-host). The
+/// buckets grow exponentially, so the memory stays constant regardless of the number of lookups.
+/// Recording is lock-free for concurrent workers.
+pub struct LatencyHistogram {
+    buckets: Vec<AtomicU64>,
+    max: AtomicU64,
+}
+
+/// Percentiles of the recorded lookups
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatencySummary {
+    pub lookups: u64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: (0..LATENCY_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let index = bucket_index(micros).min(LATENCY_BUCKETS - 1);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Time the lookup
+    pub fn time<T>(&self, lookup: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = lookup();
+        self.record(started.elapsed());
+        result
+    }
+
+    /// Percentiles are the upper bound of their bucket, so they never understate the latency
+    pub fn summary(&self) -> LatencySummary {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let lookups = counts.iter().sum();
+        let max = self.max.load(Ordering::Relaxed);
+
+        let percentile = |quantile: f64| {
+            let rank = (lookups as f64 * quantile).ceil().max(1.0) as u64;
+            let mut seen = 0;
+            let index = counts
+                .iter()
+                .position(|&count| {
+                    seen += count;
+                    seen >= rank
+                })
+                .unwrap_or(0);
+
+            Duration::from_micros(bucket_upper_bound(index).min(max))
+        };
+
+        LatencySummary {
+            lookups,
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+            max: Duration::from_micros(max),
+        }
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lookups - p50 {:?} p99 {:?} max {:?}",
+            self.lookups, self.p50, self.p99, self.max
+        )
+    }
+}
+
+/// Exact below 8 microseconds - afterwards 8 buckets per power of two
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+
+    let power = u64::from(63 - micros.leading_zeros());
+    let shift = power - 3;
+    let sub = (micros >> shift) & (SUB_BUCKETS - 1);
+    (SUB_BUCKETS + shift * SUB_BUCKETS + sub) as usize
+}
+
+/// Largest latency of the bucket in microseconds
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+
+    let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let sub = (index - SUB_BUCKETS) % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub + 1) << shift) - 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metric(phase: Phase, millis: u64, amount: Amount) -> PhaseMetric {
+        PhaseMetric {
+            phase,
+            duration: Duration::from_millis(millis),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_throughput() {
+        let scan = metric(Phase::Scan, 500, Amount::Bytes(50_000_000));
+        assert!((scan.throughput() - 100_000_000.0).abs() < 1.0);
+        assert_eq!(
+            scan.to_string(),
+            "Scan: 500ms - 50000000 bytes at 100.00 MB/s"
+        );
+
+        let sort = metric(Phase::Sort, 2_000, Amount::Records(10));
+        assert_eq!(sort.to_string(), "Sort: 2s - 10 records at 5 records/s");
+
+        let instant = metric(Phase::Hashing, 0, Amount::Records(10));
+        assert!(instant.throughput().is_finite());
+    }
+
+    #[test]
+    fn test_latency_buckets() {
+        for micros in [0, 7, 8, 9, 15, 16, 100, 999, 1_000, 123_456, 1 << 40] {
+            let index = bucket_index(micros);
+            assert!(micros <= bucket_upper_bound(index), "{}", micros);
+            assert!(
+                index == 0 || micros > bucket_upper_bound(index - 1),
+                "{}",
+                micros
+            );
+        }
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let histogram = LatencyHistogram::default();
+        for _ in 0..98 {
+            histogram.record(Duration::from_micros(100));
+        }
+        histogram.record(Duration::from_micros(5_000));
+        histogram.record(Duration::from_millis(20));
+
+        let summary = histogram.summary();
+        assert_eq!(summary.lookups, 100);
+        // 100 is within the bucket 96..=103
+        assert_eq!(summary.p50, Duration::from_micros(103));
+        assert!(summary.p99 >= Duration::from_micros(5_000));
+        assert!(summary.p99 < Duration::from_micros(5_000 * 9 / 8));
+        assert_eq!(summary.max, Duration::from_millis(20));
+        assert_eq!(histogram.time(|| 42), 42);
+
+        assert_eq!(
+            LatencyHistogram::default().summary(),
+            LatencySummary {
+                lookups: 0,
+                p50: Duration::ZERO,
+                p99: Duration::ZERO,
+                max: Duration::ZERO,
+            }
+        );
+    }
+
+    #[test]
+    fn test_total() {
+        let mut metrics = Metrics::default();
+        metrics
+            .phases
+            .push(metric(Phase::Scan, 10, Amount::Bytes(1)));
+        metrics
+            .phases
+            .push(metric(Phase::Sort, 5, Amount::Records(1)));
+        metrics
+            .phases
+            .push(metric(Phase::Scan, 20, Amount::Bytes(1)));
+
+        assert_eq!(metrics.total(Phase::Scan), Duration::from_millis(30));
+    }
+}
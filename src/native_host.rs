@@ -0,0 +1,271 @@
+use std::{
+    convert::TryInto,
+    io::{self, Read, Write},
+};
+
+use log::{debug, info};
+use secstr::SecStr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    find::{self, Finding},
+    hasher,
+    metrics::LatencyHistogram,
+    redact, Sha1Hash,
+};
+
+/// Passwords are far smaller - a larger message is a broken or hostile caller
+const MAX_REQUEST_LEN: u32 = 64 * 1024;
+
+/// Question of the extension - either the password or its SHA-1 hash
+#[derive(Deserialize)]
+struct Request {
+    /// Returned unchanged, so the extension could match the answers of parallel requests
+    #[serde(default)]
+    id: Value,
+    password: Option<String>,
+    /// Hex encoded hash if the extension hashes the password itself
+    sha1: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct Response {
+    #[serde(skip_serializing_if = "Value::is_null")]
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pwned: Option<bool>,
+    /// Occurrences in the corpus - none if it's not pwned or the count is unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Answer the requests of a browser extension using the native messaging protocol until the
+/// browser closes the pipe. Each message is JSON prefixed by its length in the native byte order.
+/// Lookups only use the local corpus, so the password never leaves the machine. Returns the number
+/// of answered requests.
+pub fn serve(
+    input: &mut impl Read,
+    output: &mut impl Write,
+    mut lookup: impl FnMut(Sha1Hash) -> io::Result<Option<Finding>>,
+) -> io::Result<usize> {
+    let latency = LatencyHistogram::default();
+    let mut timed = |hash| latency.time(|| lookup(hash));
+    let mut answered = 0;
+    while let Some(message) = read_message(input)? {
+        let response = answer(&message, &mut timed);
+        if let Some(error) = &response.error {
+            debug!("Rejected request {}", error);
+        }
+
+        write_message(output, &response)?;
+        answered += 1;
+    }
+
+    info!(
+        "Browser closed the connection after {} requests - {}",
+        answered,
+        latency.summary()
+    );
+    Ok(answered)
+}
+
+fn answer(
+    message: &SecStr,
+    lookup: &mut impl FnMut(Sha1Hash) -> io::Result<Option<Finding>>,
+) -> Response {
+    let request: Request = match serde_json::from_slice(message.unsecure()) {
+        Ok(request) => request,
+        // the message of type errors would quote the password
+        Err(err) => {
+            return rejected(
+                Value::Null,
+                format!("Invalid request {}", redact::json_error(&err)),
+            )
+        }
+    };
+
+    let hash = match (request.password, request.sha1) {
+        (Some(password), None) => {
+            // moved without copying, so it's cleared after hashing
+            let password = SecStr::new(password.into_bytes());
+            hasher::sha1(&[password.unsecure()])
+        }
+        (None, Some(hex)) => match find::parse_sha1(&hex) {
+            Some(hash) => hash,
+            // not echoed, because it could be a password in the wrong field
+            None => {
+                return rejected(
+                    request.id,
+                    "Invalid SHA-1 hash - requires 40 hex characters".to_string(),
+                )
+            }
+        },
+        _ => {
+            return rejected(
+                request.id,
+                "Requires either a password or a sha1 field".to_string(),
+            )
+        }
+    };
+
+    match lookup(hash) {
+        Ok(finding) => Response {
+            id: request.id,
+            pwned: Some(finding.is_some()),
+            count: finding.and_then(|finding| finding.count),
+            error: None,
+        },
+        Err(err) => rejected(request.id, format!("Lookup failed {}", err)),
+    }
+}
+
+fn rejected(id: Value, error: String) -> Response {
+    Response {
+        id,
+        error: Some(error),
+        ..Response::default()
+    }
+}
+
+/// Body of the next message or none if the browser closed the pipe. It's kept in a `SecStr`,
+/// because it contains the password.
+fn read_message(input: &mut impl Read) -> io::Result<Option<SecStr>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let len = u32::from_ne_bytes(len);
+    if len > MAX_REQUEST_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Message of {} bytes exceeds the limit of {} bytes",
+                len, MAX_REQUEST_LEN
+            ),
+        ));
+    }
+
+    let mut message = SecStr::new(vec![0; len as usize]);
+    input.read_exact(message.unsecure_mut())?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, response: &Response) -> io::Result<()> {
+    let body = serde_json::to_vec(response)?;
+    // unwrap is safe, because the response only contains the id of a request within the limit
+    let len: u32 = body.len().try_into().unwrap();
+    output.write_all(&len.to_ne_bytes())?;
+    output.write_all(&body)?;
+    // the browser waits for the answer, while the pipe stays open
+    output.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(json: &str) -> Vec<u8> {
+        let mut message = (json.len() as u32).to_ne_bytes().to_vec();
+        message.extend_from_slice(json.as_bytes());
+        message
+    }
+
+    fn responses(mut output: &[u8]) -> Vec<Value> {
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            responses.push(serde_json::from_slice(message.unsecure()).unwrap());
+        }
+
+        responses
+    }
+
+    #[test]
+    fn test_serve() {
+        // SHA-1 of "password"
+        let pwned = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+        let mut input = message(r#"{"id": 1, "password": "password"}"#);
+        input.extend(message(&format!(
+            r#"{{"id": "b", "sha1": "{}"}}"#,
+            pwned.to_lowercase()
+        )));
+        input.extend(message(r#"{"password": "correct horse battery staple"}"#));
+
+        let mut output = Vec::new();
+        let answered = serve(&mut input.as_slice(), &mut output, |hash| {
+            Ok((find::parse_sha1(pwned) == Some(hash)).then_some(Finding {
+                index: 0,
+                count: Some(42),
+            }))
+        })
+        .unwrap();
+
+        assert_eq!(answered, 3);
+        assert_eq!(
+            responses(&output),
+            [
+                serde_json::json!({"id": 1, "pwned": true, "count": 42}),
+                serde_json::json!({"id": "b", "pwned": true, "count": 42}),
+                serde_json::json!({"pwned": false}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejected() {
+        let mut input = message("not json");
+        input.extend(message(r#"{"id": 2, "sha1": "abc"}"#));
+        input.extend(message(r#"{"id": 3}"#));
+        input.extend(message(r#"{"id": 4, "password": "a", "sha1": "b"}"#));
+
+        let mut output = Vec::new();
+        serve(&mut input.as_slice(), &mut output, |_| {
+            Err(io::Error::other("unreachable"))
+        })
+        .unwrap();
+
+        let responses = responses(&output);
+        assert_eq!(responses.len(), 4);
+        assert!(responses
+            .iter()
+            .all(|response| response["error"].is_string() && response.get("pwned").is_none()));
+        assert_eq!(responses[1]["id"], 2);
+    }
+
+    #[test]
+    fn test_rejected_redacted() {
+        // a numeric PIN fails the type check and a password in the wrong field the hash check
+        let mut input = message(r#"{"id": 1, "password": 4711}"#);
+        input.extend(message(r#"{"id": 2, "sha1": "fixture-Secret-4711"}"#));
+
+        let mut output = Vec::new();
+        serve(&mut input.as_slice(), &mut output, |_| {
+            Err(io::Error::other("unreachable"))
+        })
+        .unwrap();
+
+        let responses = responses(&output);
+        assert_eq!(responses.len(), 2);
+        for response in &responses {
+            let error = response["error"].as_str().unwrap();
+            assert!(!error.contains("4711"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_message_limit() {
+        let input = (MAX_REQUEST_LEN + 1).to_ne_bytes();
+        let err = read_message(&mut &input[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // closed in the middle of a message
+        let mut truncated = message(r#"{"password": "secret"}"#);
+        truncated.truncate(10);
+        assert!(read_message(&mut truncated.as_slice()).is_err());
+    }
+}
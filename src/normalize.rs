@@ -0,0 +1,222 @@
+use secstr::SecStr;
+use std::fmt;
+
+use unicode_normalization::{is_nfc_quick, is_nfd_quick, IsNormalized, UnicodeNormalization};
+
+/// UTF-8 encoded byte order mark - some exports prefix the first field with it
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Optional changes of the exported passwords before hashing. By default the exact bytes are
+/// hashed, because the services received them like that. Padding added by an export changes the
+/// hash however and hides a pwned password.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Remove ASCII whitespace at the start and the end
+    pub trim: bool,
+    /// Remove a leading byte order mark
+    pub strip_bom: bool,
+    /// Compose the characters (Unicode NFC) like most keyboards and input methods do
+    pub nfc: bool,
+}
+
+impl Normalization {
+    pub const NAMES: [&'static str; 3] = ["trim", "bom", "nfc"];
+
+    /// Enable the steps by their names - unknown names are rejected
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut normalization = Normalization::default();
+        for name in names {
+            match name {
+                "trim" => normalization.trim = true,
+                "bom" => normalization.strip_bom = true,
+                "nfc" => normalization.nfc = true,
+                _ => return Err(format!("Unknown normalization {}", name)),
+            }
+        }
+
+        Ok(normalization)
+    }
+
+    /// Normalized copy of the password - the original is returned if nothing changed
+    pub fn apply(&self, password: SecStr) -> SecStr {
+        let mut bytes = password.unsecure();
+        if self.strip_bom {
+            bytes = bytes.strip_prefix(BOM).unwrap_or(bytes);
+        }
+
+        if self.trim {
+            bytes = bytes.trim_ascii();
+        }
+
+        let composed = if self.nfc {
+            std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|password| UnicodeForm::Nfc.normalize(password))
+        } else {
+            None
+        };
+        match composed {
+            Some(composed) => composed,
+            None if bytes.len() == password.unsecure().len() => password,
+            None => SecStr::new(bytes.to_vec()),
+        }
+    }
+
+    /// Whether the exact password looks like the export changed it - with whitespace or a byte order
+    /// mark that isn't removed by the enabled steps
+    pub fn is_suspicious(&self, password: &[u8]) -> bool {
+        let starts_with_bom = password.starts_with(BOM);
+        if starts_with_bom && !self.strip_bom {
+            return true;
+        }
+
+        let password = if starts_with_bom {
+            &password[BOM.len()..]
+        } else {
+            password
+        };
+
+        // a password only of whitespace is more likely a placeholder than padding
+        !self.trim
+            && !password.trim_ascii().is_empty()
+            && password.trim_ascii().len() != password.len()
+    }
+}
+
+/// Unicode normalization form - services could have stored a password with non-ASCII characters in
+/// either of them depending on the keyboard or input method of the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum UnicodeForm {
+    /// Composed characters (ex: `ä` as a single code point)
+    Nfc,
+    /// Decomposed characters (ex: `a` followed by a combining diaeresis)
+    Nfd,
+}
+
+impl UnicodeForm {
+    pub const ALL: [UnicodeForm; 2] = [UnicodeForm::Nfc, UnicodeForm::Nfd];
+
+    /// Password in this form - none if it's already in it
+    pub fn normalize(self, password: &str) -> Option<SecStr> {
+        let quick = match self {
+            UnicodeForm::Nfc => is_nfc_quick(password.chars()),
+            UnicodeForm::Nfd => is_nfd_quick(password.chars()),
+        };
+        if quick == IsNormalized::Yes {
+            return None;
+        }
+
+        // both forms need at most three times the bytes, so the buffer never reallocates and
+        // leaves copies of the password behind
+        let mut normalized = String::with_capacity(password.len() * 3);
+        match self {
+            UnicodeForm::Nfc => normalized.extend(password.nfc()),
+            UnicodeForm::Nfd => normalized.extend(password.nfd()),
+        }
+
+        let normalized = SecStr::from(normalized);
+        if normalized.unsecure() == password.as_bytes() {
+            return None;
+        }
+
+        Some(normalized)
+    }
+}
+
+impl fmt::Display for UnicodeForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnicodeForm::Nfc => write!(f, "NFC"),
+            UnicodeForm::Nfd => write!(f, "NFD"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(normalization: Normalization, password: &str) -> Vec<u8> {
+        normalization
+            .apply(SecStr::from(password))
+            .unsecure()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_exact_by_default() {
+        let exact = Normalization::default();
+        assert_eq!(
+            apply(exact, " \u{feff}pass word\t"),
+            " \u{feff}pass word\t".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let all = Normalization::from_names(Normalization::NAMES.iter().copied()).unwrap();
+        assert_eq!(apply(all, "\u{feff} password \r\n"), b"password");
+        assert_eq!(apply(all, "pass word"), b"pass word");
+
+        // decomposed ä
+        let nfc = Normalization::from_names(vec!["nfc"]).unwrap();
+        assert_eq!(apply(nfc, "pa\u{0308}sswort"), "pässwort".as_bytes());
+        assert_eq!(apply(nfc, "pässwort"), "pässwort".as_bytes());
+
+        // invalid UTF-8 keeps its exact bytes
+        let password = SecStr::new(b"pa\xFFss".to_vec());
+        assert_eq!(nfc.apply(password).unsecure(), b"pa\xFFss");
+
+        let bom = Normalization::from_names(vec!["bom"]).unwrap();
+        assert_eq!(apply(bom, "\u{feff} password"), b" password");
+
+        assert!(Normalization::from_names(vec!["lowercase"]).is_err());
+    }
+
+    #[test]
+    fn test_unicode_forms() {
+        let normalize = |form: UnicodeForm, password| {
+            form.normalize(password)
+                .map(|normalized| String::from_utf8(normalized.unsecure().to_vec()).unwrap())
+        };
+
+        assert_eq!(
+            normalize(UnicodeForm::Nfd, "pässwort").as_deref(),
+            Some("pa\u{0308}sswort")
+        );
+        assert_eq!(normalize(UnicodeForm::Nfc, "pässwort"), None);
+        assert_eq!(
+            normalize(UnicodeForm::Nfc, "pa\u{0308}sswort").as_deref(),
+            Some("pässwort")
+        );
+        assert_eq!(normalize(UnicodeForm::Nfd, "pa\u{0308}sswort"), None);
+
+        assert_eq!(normalize(UnicodeForm::Nfc, "password"), None);
+        assert_eq!(normalize(UnicodeForm::Nfd, "password"), None);
+    }
+
+    #[test]
+    fn test_suspicious() {
+        let exact = Normalization::default();
+        assert!(exact.is_suspicious(b"password "));
+        assert!(exact.is_suspicious(b"\tpassword"));
+        assert!(exact.is_suspicious("\u{feff}password".as_bytes()));
+        assert!(!exact.is_suspicious(b"pass word"));
+        assert!(!exact.is_suspicious(b"   "));
+        assert!(!exact.is_suspicious(b""));
+
+        let trim = Normalization {
+            trim: true,
+            ..Normalization::default()
+        };
+        assert!(!trim.is_suspicious(b"password "));
+        assert!(trim.is_suspicious("\u{feff}password".as_bytes()));
+
+        let bom = Normalization {
+            strip_bom: true,
+            ..Normalization::default()
+        };
+        assert!(!bom.is_suspicious("\u{feff}password".as_bytes()));
+        assert!(bom.is_suspicious("\u{feff}password ".as_bytes()));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+};
+
+use log::debug;
+
+/// Page faults and disk reads of the process - the difference of two snapshots shows if a scan
+/// waited for the disk or was served from the page cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PagingStats {
+    /// Faults that required reading the page from the disk
+    pub major_faults: u64,
+    /// Faults of pages that were already in the page cache
+    pub minor_faults: u64,
+    /// Bytes fetched from the storage layer - only available on Linux
+    pub disk_read: Option<u64>,
+}
+
+impl PagingStats {
+    /// Current counters of the process - none if the platform doesn't provide them
+    pub fn current() -> Option<Self> {
+        match read_stats() {
+            Ok(stats) => Some(stats),
+            Err(err) => {
+                debug!("Cannot read the paging statistics {}", err);
+                None
+            }
+        }
+    }
+
+    /// Counters since the earlier snapshot
+    pub fn since(&self, earlier: &PagingStats) -> PagingStats {
+        PagingStats {
+            major_faults: self.major_faults.saturating_sub(earlier.major_faults),
+            minor_faults: self.minor_faults.saturating_sub(earlier.minor_faults),
+            disk_read: self
+                .disk_read
+                .zip(earlier.disk_read)
+                .map(|(now, before)| now.saturating_sub(before)),
+        }
+    }
+
+    /// Summary for a scan of a database with `scanned` bytes
+    pub fn of_scan(&self, scanned: u64) -> ScanPaging {
+        ScanPaging {
+            stats: *self,
+            scanned,
+        }
+    }
+}
+
+/// Paging statistics of a single scan
+pub struct ScanPaging {
+    stats: PagingStats,
+    scanned: u64,
+}
+
+impl Display for ScanPaging {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Paging: {} major faults, {} minor faults",
+            self.stats.major_faults, self.stats.minor_faults
+        )?;
+
+        if let Some(disk_read) = self.stats.disk_read {
+            // the rest was either in the page cache or skipped by the interpolation search
+            write!(
+                f,
+                " - {} MB of the {} MB database read from disk",
+                disk_read / 1_000_000,
+                self.scanned / 1_000_000
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn read_stats() -> io::Result<PagingStats> {
+    use std::mem::MaybeUninit;
+
+    let mut usage = MaybeUninit::<libc::rusage>::uninit();
+    // unsafe is safe, because the struct is only read on success
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        usage.assume_init()
+    };
+
+    Ok(PagingStats {
+        major_faults: usage.ru_majflt as u64,
+        minor_faults: usage.ru_minflt as u64,
+        disk_read: disk_read(),
+    })
+}
+
+#[cfg(not(unix))]
+fn read_stats() -> io::Result<PagingStats> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Bytes read from the storage including the faults of memory maps - `rchar` would only count
+/// read calls and include the ones served by the page cache
+#[cfg(target_os = "linux")]
+fn disk_read() -> Option<u64> {
+    match std::fs::read_to_string("/proc/self/io") {
+        Ok(content) => parse_io(&content),
+        Err(err) => {
+            // restricted by some container runtimes
+            debug!("Cannot read /proc/self/io {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn disk_read() -> Option<u64> {
+    None
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_io(content: &str) -> Option<u64> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| *key == "read_bytes")
+        .and_then(|(_, value)| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_io() {
+        let content = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+                       read_bytes: 61440\nwrite_bytes: 0\ncancelled_write_bytes: 0\n";
+        assert_eq!(parse_io(content), Some(61440));
+        assert_eq!(parse_io("rchar: 1\n"), None);
+    }
+
+    #[test]
+    fn test_since() {
+        let earlier = PagingStats {
+            major_faults: 2,
+            minor_faults: 10,
+            disk_read: Some(4_000_000),
+        };
+        let now = PagingStats {
+            major_faults: 5,
+            minor_faults: 110,
+            disk_read: Some(104_000_000),
+        };
+
+        let delta = now.since(&earlier);
+        assert_eq!(
+            delta,
+            PagingStats {
+                major_faults: 3,
+                minor_faults: 100,
+                disk_read: Some(100_000_000),
+            }
+        );
+        assert_eq!(
+            delta.of_scan(300_000_000).to_string(),
+            "Paging: 3 major faults, 100 minor faults - 100 MB of the 300 MB database read from disk"
+        );
+
+        let unknown = PagingStats {
+            disk_read: None,
+            ..now
+        };
+        assert_eq!(unknown.since(&earlier).disk_read, None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_current() {
+        let stats = PagingStats::current().unwrap();
+        assert!(stats.minor_faults > 0);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    env,
+    ffi::OsString,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::PathBuf,
+};
+
+/// Directory name below the platform directories
+const APP_DIR: &str = "pwned-check";
+
+/// Per-user locations of the application. The XDG base directory variables are honoured on every
+/// platform if they are set to an absolute path. Otherwise the platform conventions are used:
+/// the XDG defaults below the home directory on Linux and BSD, `~/Library/Application Support`
+/// and `~/Library/Caches` on macOS and the Known Folders (ex: `%APPDATA%`) on Windows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// Settings like the config of the setup
+    Config,
+    /// Downloaded and converted corpora
+    Data,
+    /// Data that could be deleted at any time, because it's fetched again
+    Cache,
+    /// Data of previous runs that should persist, but isn't worth backing up
+    State,
+}
+
+impl Location {
+    pub const ALL: [Location; 4] = [
+        Location::Config,
+        Location::Data,
+        Location::Cache,
+        Location::State,
+    ];
+
+    /// Directory of the application - none if the home directory is unknown
+    pub fn user_dir(self) -> Option<PathBuf> {
+        resolve(self, |name| env::var_os(name))
+    }
+
+    /// Directory of the application including its parents
+    pub fn create(self) -> io::Result<PathBuf> {
+        let dir = self.user_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot find the home directory for the {} location", self),
+            )
+        })?;
+
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn xdg_variable(self) -> &'static str {
+        match self {
+            Location::Config => "XDG_CONFIG_HOME",
+            Location::Data => "XDG_DATA_HOME",
+            Location::Cache => "XDG_CACHE_HOME",
+            Location::State => "XDG_STATE_HOME",
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Location::Config => "config",
+            Location::Data => "data",
+            Location::Cache => "cache",
+            Location::State => "state",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// System wide corpora shared between users (ex: provisioned by the package manager)
+pub fn system_data_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    let base = Some(PathBuf::from("/Library/Application Support"));
+
+    #[cfg(windows)]
+    let base = windows::known_folder(&winapi::um::knownfolders::FOLDERID_ProgramData);
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let base = Some(PathBuf::from("/var/lib"));
+
+    base.map(|base| base.join(APP_DIR))
+}
+
+/// Explicitly configured XDG directory first - otherwise the platform default
+fn resolve(location: Location, var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var(location.xdg_variable())
+        .map(PathBuf::from)
+        .filter(|base| base.is_absolute())
+        .map(|base| base.join(APP_DIR))
+        .or_else(|| platform_dir(location, &var))
+}
+
+/// XDG defaults below the home directory
+#[cfg(not(any(target_os = "macos", windows)))]
+fn platform_dir(location: Location, var: &impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let base = match location {
+        Location::Config => ".config",
+        Location::Data => ".local/share",
+        Location::Cache => ".cache",
+        Location::State => ".local/state",
+    };
+
+    var("HOME").map(|home| PathBuf::from(home).join(base).join(APP_DIR))
+}
+
+/// Config, data and state share the Application Support directory, so the data is in its own
+/// directory. Otherwise the config could be mistaken for a corpus.
+#[cfg(target_os = "macos")]
+fn platform_dir(location: Location, var: &impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let library = PathBuf::from(var("HOME")?).join("Library");
+    let support = library.join("Application Support").join(APP_DIR);
+    Some(match location {
+        Location::Config => support,
+        Location::Data => support.join("data"),
+        Location::Cache => library.join("Caches").join(APP_DIR),
+        Location::State => support.join("state"),
+    })
+}
+
+/// Only the config roams with the profile - corpora are too large for it
+#[cfg(windows)]
+fn platform_dir(location: Location, _var: &impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    use winapi::um::knownfolders::{FOLDERID_LocalAppData, FOLDERID_RoamingAppData};
+
+    match location {
+        Location::Config => {
+            windows::known_folder(&FOLDERID_RoamingAppData).map(|base| base.join(APP_DIR))
+        }
+        local => windows::known_folder(&FOLDERID_LocalAppData)
+            .map(|base| base.join(APP_DIR).join(local.to_string())),
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf, ptr, slice};
+
+    use winapi::{
+        shared::{guiddef::GUID, winerror::S_OK},
+        um::{combaseapi::CoTaskMemFree, shlobj::SHGetKnownFolderPath, winnt::PWSTR},
+    };
+
+    /// Current location of the folder - it could be redirected by the user or a policy
+    pub fn known_folder(id: &GUID) -> Option<PathBuf> {
+        let mut path: PWSTR = ptr::null_mut();
+
+        // unsafe is safe, because the returned string is null terminated and always freed
+        unsafe {
+            let result = SHGetKnownFolderPath(id, 0, ptr::null_mut(), &mut path);
+            let folder = if result == S_OK {
+                let len = (0..).take_while(|&index| *path.add(index) != 0).count();
+                Some(PathBuf::from(OsString::from_wide(slice::from_raw_parts(
+                    path, len,
+                ))))
+            } else {
+                None
+            };
+
+            CoTaskMemFree(path as _);
+            folder
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xdg_variables() {
+        let var = |name: &str| match name {
+            "XDG_CONFIG_HOME" => Some(OsString::from("/etc/xdg-user")),
+            "XDG_CACHE_HOME" => Some(OsString::from("relative/cache")),
+            "HOME" => Some(OsString::from("/home/alice")),
+            _ => None,
+        };
+
+        assert_eq!(
+            resolve(Location::Config, var),
+            Some(PathBuf::from("/etc/xdg-user/pwned-check"))
+        );
+
+        // relative paths are invalid according to the specification
+        assert_eq!(
+            resolve(Location::Cache, var),
+            platform_dir(Location::Cache, &var)
+        );
+        assert_ne!(
+            resolve(Location::Cache, var),
+            Some(PathBuf::from("relative/cache/pwned-check"))
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn test_xdg_defaults() {
+        let var = |name: &str| match name {
+            "HOME" => Some(OsString::from("/home/alice")),
+            _ => None,
+        };
+
+        let dirs: Vec<_> = Location::ALL
+            .iter()
+            .map(|&location| resolve(location, var).unwrap())
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                PathBuf::from("/home/alice/.config/pwned-check"),
+                PathBuf::from("/home/alice/.local/share/pwned-check"),
+                PathBuf::from("/home/alice/.cache/pwned-check"),
+                PathBuf::from("/home/alice/.local/state/pwned-check"),
+            ]
+        );
+
+        assert_eq!(resolve(Location::Data, |_| None), None);
+    }
+}
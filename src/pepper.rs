@@ -0,0 +1,137 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt::{self, Debug, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+use secstr::SecStr;
+
+/// Secret prefix of each password for internal corpora that store SHA-1(pepper || password). It's
+/// kept in a `SecStr`, so the memory is zeroed once it's dropped, and it's never printed.
+pub struct Pepper {
+    secret: SecStr,
+}
+
+impl Pepper {
+    /// Plain SHA-1 hashes like the HIBP lists
+    pub fn none() -> Self {
+        Pepper {
+            secret: SecStr::new(Vec::new()),
+        }
+    }
+
+    /// Hex encoded pepper or a file containing it. Files keep the pepper out of the shell history
+    /// and the process list.
+    pub fn load(value: &OsStr) -> Result<Self, PepperError> {
+        let path = Path::new(value);
+        if path.is_file() {
+            let content = SecStr::new(fs::read(path)?);
+            return Self::decode(content.unsecure().trim_ascii());
+        }
+
+        value
+            .to_str()
+            .ok_or(PepperError::InvalidHex)
+            .and_then(|hex| Self::decode(hex.trim().as_bytes()))
+    }
+
+    fn decode(hex: &[u8]) -> Result<Self, PepperError> {
+        let len = HEXLOWER_PERMISSIVE
+            .decode_len(hex.len())
+            .map_err(|_| PepperError::InvalidHex)?;
+
+        // decoded in place, so there is no copy that isn't zeroed
+        let mut secret = SecStr::new(vec![0; len]);
+        HEXLOWER_PERMISSIVE
+            .decode_mut(hex, secret.unsecure_mut())
+            .map_err(|_| PepperError::InvalidHex)?;
+
+        if secret.unsecure().is_empty() {
+            return Err(PepperError::Empty);
+        }
+
+        Ok(Pepper { secret })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.secret.unsecure()
+    }
+}
+
+impl Debug for Pepper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Pepper(***)")
+    }
+}
+
+/// Errors never include the pepper itself
+#[derive(Debug)]
+pub enum PepperError {
+    Io(io::Error),
+    InvalidHex,
+    Empty,
+}
+
+impl Display for PepperError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PepperError::Io(err) => write!(f, "{}", err),
+            PepperError::InvalidHex => write!(f, "Neither a file nor valid hex"),
+            PepperError::Empty => write!(f, "Empty value"),
+        }
+    }
+}
+
+impl Error for PepperError {}
+
+impl From<io::Error> for PepperError {
+    fn from(err: io::Error) -> Self {
+        PepperError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn test_load_hex() {
+        let pepper = Pepper::load(OsStr::new("C0ffee")).unwrap();
+        assert_eq!(pepper.as_bytes(), [0xC0, 0xFF, 0xEE]);
+        assert!(Pepper::none().as_bytes().is_empty());
+
+        assert_matches!(
+            Pepper::load(OsStr::new("xyz")),
+            Err(PepperError::InvalidHex)
+        );
+        assert_matches!(
+            Pepper::load(OsStr::new("abc")),
+            Err(PepperError::InvalidHex)
+        );
+        assert_matches!(Pepper::load(OsStr::new("")), Err(PepperError::Empty));
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = env::temp_dir().join(format!("pwned-check-pepper-{}", process::id()));
+        fs::write(&path, "c0ffee\n").unwrap();
+
+        let pepper = Pepper::load(path.as_os_str()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(pepper.as_bytes(), [0xC0, 0xFF, 0xEE]);
+    }
+
+    #[test]
+    fn test_redacted() {
+        let pepper = Pepper::load(OsStr::new("c0ffee")).unwrap();
+        assert!(!format!("{:?}", pepper).contains("c0ffee"));
+
+        let err = Pepper::load(OsStr::new("secret")).unwrap_err();
+        assert!(!err.to_string().contains("secret"));
+    }
+}
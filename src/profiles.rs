@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use log::{error, info, warn};
+
+use crate::{collect::SavedHash, pseudonym::Pseudonymizer, report::AccountReport, Sha1Hash};
+
+/// Accounts of a single person in the comparison
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProfileSummary<'a> {
+    pub name: &'a str,
+    pub accounts: usize,
+    pub pwned: usize,
+}
+
+/// Password saved in the vaults of multiple people - a breach of one account exposes the accounts
+/// of the others
+#[derive(Debug)]
+pub struct SharedPassword<'a> {
+    /// All accounts with the password across the profiles
+    pub accounts: Vec<&'a SavedHash>,
+    /// Highest count of the matches - none if it's clean or the count is unknown
+    pub max_count: Option<u64>,
+    pub pwned: bool,
+}
+
+/// Number of accounts and pwned accounts of each profile in the given order
+pub fn summarize<'a>(
+    profiles: &'a [String],
+    hashes: &[SavedHash],
+    reports: &[AccountReport<'_>],
+) -> Vec<ProfileSummary<'a>> {
+    profiles
+        .iter()
+        .map(|name| {
+            let of_profile = |saved: &SavedHash| saved.profile() == Some(name.as_str());
+            let pwned = reports
+                .iter()
+                .filter(|report| !report.is_clean())
+                .flat_map(|report| {
+                    std::iter::once(report.saved).chain(report.variants.iter().copied())
+                })
+                .filter(|saved| of_profile(saved))
+                .count();
+            ProfileSummary {
+                name,
+                accounts: hashes.iter().filter(|saved| of_profile(saved)).count(),
+                pwned,
+            }
+        })
+        .collect()
+}
+
+/// Highest numbers of pwned accounts before the check fails - of all inputs together and of each
+/// single input
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PwnedLimits {
+    total: Option<usize>,
+    profiles: HashMap<String, usize>,
+}
+
+impl PwnedLimits {
+    /// Limits like `--max-pwned 10 --max-pwned alice=0` - a later limit of the same input wins
+    pub fn new<'a>(limits: impl IntoIterator<Item = (Option<&'a str>, usize)>) -> Self {
+        let mut result = PwnedLimits::default();
+        for (profile, max) in limits {
+            match profile {
+                Some(profile) => {
+                    result.profiles.insert(profile.to_string(), max);
+                }
+                None => result.total = Some(max),
+            }
+        }
+
+        result
+    }
+
+    /// Limit of the profile or of all inputs together if it's none
+    pub fn of(&self, profile: Option<&str>) -> Option<usize> {
+        match profile {
+            Some(profile) => self.profiles.get(profile).copied(),
+            None => self.total,
+        }
+    }
+
+    pub fn is_exceeded(&self, profile: Option<&str>, pwned: usize) -> bool {
+        self.of(profile).is_some_and(|max| pwned > max)
+    }
+
+    /// Name of a limited profile that isn't one of the inputs
+    pub fn unknown_profile<'a>(&'a self, profiles: &[String]) -> Option<&'a str> {
+        self.profiles
+            .keys()
+            .find(|name| !profiles.contains(name))
+            .map(String::as_str)
+    }
+
+    /// Log the exceeded limits and return if any was exceeded
+    pub fn check(&self, summaries: &[ProfileSummary<'_>], pwned: usize) -> bool {
+        let mut exceeded = false;
+        for summary in summaries {
+            if self.is_exceeded(Some(summary.name), summary.pwned) {
+                // unwrap is safe, because only limited profiles are exceeded
+                error!(
+                    "Profile {} has {} pwned accounts - above the limit of {}",
+                    summary.name,
+                    summary.pwned,
+                    self.of(Some(summary.name)).unwrap()
+                );
+                exceeded = true;
+            }
+        }
+
+        if let Some(max) = self.total.filter(|&max| pwned > max) {
+            error!(
+                "{} pwned accounts in total - above the limit of {}",
+                pwned, max
+            );
+            exceeded = true;
+        }
+
+        exceeded
+    }
+}
+
+/// Passwords that appear in more than one profile - clean ones are included, because the reuse is
+/// a risk on its own. The pwned passwords are listed first.
+pub fn shared_passwords<'a>(
+    hashes: &'a [SavedHash],
+    reports: &[AccountReport<'_>],
+) -> Vec<SharedPassword<'a>> {
+    let mut by_hash: HashMap<Sha1Hash, Vec<&'a SavedHash>> = HashMap::new();
+    for saved in hashes.iter().filter(|saved| saved.profile().is_some()) {
+        by_hash.entry(saved.password_hash).or_default().push(saved);
+    }
+
+    let findings: HashMap<Sha1Hash, Option<u64>> = reports
+        .iter()
+        .filter(|report| !report.is_clean())
+        .map(|report| (report.saved.password_hash, report.max_count))
+        .collect();
+
+    let mut shared: Vec<_> = by_hash
+        .into_iter()
+        .filter(|(_, accounts)| {
+            let first = accounts[0].profile();
+            accounts.iter().any(|saved| saved.profile() != first)
+        })
+        .map(|(hash, mut accounts)| {
+            accounts.sort_by(|a, b| (a.profile(), a.url()).cmp(&(b.profile(), b.url())));
+            let finding = findings.get(&hash);
+            SharedPassword {
+                accounts,
+                max_count: finding.copied().flatten(),
+                pwned: finding.is_some(),
+            }
+        })
+        .collect();
+
+    // the most urgent first and otherwise in a stable order
+    shared.sort_by(|a, b| {
+        (b.pwned, b.max_count)
+            .cmp(&(a.pwned, a.max_count))
+            .then_with(|| a.accounts[0].url().cmp(b.accounts[0].url()))
+    });
+    shared
+}
+
+/// Print the breakdown of each person followed by the passwords shared between them
+pub fn print(
+    summaries: &[ProfileSummary<'_>],
+    hashes: &[SavedHash],
+    reports: &[AccountReport<'_>],
+    pseudonyms: &mut Pseudonymizer,
+) {
+    for summary in summaries {
+        info!(
+            "Profile {}: {} of {} accounts are pwned",
+            summary.name, summary.pwned, summary.accounts
+        );
+    }
+
+    let pwned: usize = summaries.iter().map(|summary| summary.pwned).sum();
+    info!(
+        "All profiles: {} of {} accounts are pwned",
+        pwned,
+        hashes.len()
+    );
+
+    let shared = shared_passwords(hashes, reports);
+    if shared.is_empty() {
+        info!("No password is shared between the profiles");
+        return;
+    }
+
+    warn!("{} passwords are shared between the profiles", shared.len());
+    for password in shared {
+        let accounts: Vec<_> = password
+            .accounts
+            .iter()
+            .map(|saved| {
+                // unwrap is safe, because only accounts of profiles are compared
+                format!("{} ({})", saved.profile().unwrap(), pseudonyms.label(saved))
+            })
+            .collect();
+        let status = match (password.pwned, password.max_count) {
+            (true, Some(count)) => format!("pwned {}x times", count),
+            (true, None) => "pwned - unknown count".to_string(),
+            (false, _) => "not pwned".to_string(),
+        };
+
+        warn!("Shared password {}: {}", status, accounts.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{find::Finding, report};
+
+    fn saved(profile: &str, url: &str, hash_byte: u8) -> SavedHash {
+        let mut saved = SavedHash::new(url, "user", [hash_byte; 20]);
+        saved.set_profile(profile);
+        saved
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut hashes = vec![
+            saved("alice", "https://mail.example/", 1),
+            saved("alice", "https://bank.example/", 2),
+            saved("bob", "https://shop.example/", 1),
+            saved("bob", "https://forum.example/", 3),
+            saved("bob", "https://games.example/", 4),
+            saved("alice", "https://news.example/", 4),
+        ];
+        hashes.sort_unstable();
+
+        let index = |url: &str| hashes.iter().position(|saved| saved.url() == url).unwrap();
+        let findings = vec![
+            Finding {
+                index: index("https://mail.example/"),
+                count: Some(10),
+            },
+            Finding {
+                index: index("https://shop.example/"),
+                count: Some(10),
+            },
+            Finding {
+                index: index("https://forum.example/"),
+                count: None,
+            },
+        ];
+        let corpus = report::Corpus {
+            name: "hibp.txt",
+            snapshot: None,
+            form: None,
+            newly_pwned: false,
+        };
+        let reports = report::merge(&hashes, &[(corpus, findings)], false);
+
+        let profiles = ["alice".to_string(), "bob".to_string()];
+        assert_eq!(
+            summarize(&profiles, &hashes, &reports),
+            [
+                ProfileSummary {
+                    name: "alice",
+                    accounts: 3,
+                    pwned: 1
+                },
+                ProfileSummary {
+                    name: "bob",
+                    accounts: 3,
+                    pwned: 2
+                }
+            ]
+        );
+
+        let shared = shared_passwords(&hashes, &reports);
+        let shared: Vec<_> = shared
+            .iter()
+            .map(|password| {
+                let urls: Vec<_> = password.accounts.iter().map(|saved| saved.url()).collect();
+                (urls, password.pwned, password.max_count)
+            })
+            .collect();
+        assert_eq!(
+            shared,
+            [
+                (
+                    vec!["https://mail.example/", "https://shop.example/"],
+                    true,
+                    Some(10)
+                ),
+                (
+                    vec!["https://news.example/", "https://games.example/"],
+                    false,
+                    None
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = PwnedLimits::new(vec![
+            (None, 5),
+            (Some("alice"), 2),
+            (Some("alice"), 0),
+            (Some("bob"), 3),
+        ]);
+        assert_eq!(limits.of(None), Some(5));
+        assert_eq!(limits.of(Some("alice")), Some(0));
+        assert_eq!(limits.of(Some("carol")), None);
+
+        assert!(limits.is_exceeded(Some("alice"), 1));
+        assert!(!limits.is_exceeded(Some("bob"), 3));
+        assert!(!limits.is_exceeded(Some("carol"), 100));
+
+        let summaries = [
+            ProfileSummary {
+                name: "alice",
+                accounts: 4,
+                pwned: 0,
+            },
+            ProfileSummary {
+                name: "bob",
+                accounts: 9,
+                pwned: 3,
+            },
+        ];
+        assert!(!limits.check(&summaries, 3));
+        // the total alone fails the check
+        assert!(limits.check(&summaries, 6));
+        assert!(PwnedLimits::new(vec![(Some("bob"), 2)]).check(&summaries, 3));
+        assert!(!PwnedLimits::default().check(&summaries, 100));
+
+        let profiles = ["alice".to_string(), "bob".to_string()];
+        assert_eq!(limits.unknown_profile(&profiles), None);
+        assert_eq!(limits.unknown_profile(&profiles[..1]), Some("bob"));
+    }
+}
@@ -0,0 +1,221 @@
+#[cfg(feature = "progress-bar")]
+use std::{
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
+#[cfg(feature = "progress-bar")]
+use pbr::{ProgressBar, Units};
+
+/// Unit of the reported progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressUnit {
+    Bytes,
+    /// Number of processed entries like saved hashes
+    Items,
+}
+
+/// Receiver of the scan progress. This allows to display the progress in other ways than a
+/// terminal bar, like a GUI or logging only every few percent.
+pub trait ProgressSink {
+    /// Start a new scan with the given total - 0 if the total is unknown
+    fn start(&mut self, total: u64, unit: ProgressUnit);
+
+    /// Processed the given number of units since the last call
+    fn advance(&mut self, delta: u64);
+
+    /// The scan stopped early, because the remaining units couldn't change the results. They
+    /// count as processed for the displayed progress.
+    fn skip(&mut self, delta: u64) {
+        self.advance(delta);
+    }
+
+    /// The current scan is completed
+    fn finish(&mut self);
+}
+
+/// Ignores all progress (ex: single lookups)
+#[derive(Debug, Default)]
+pub struct SilentProgress;
+
+#[cfg(not(feature = "progress-bar"))]
+impl SilentProgress {
+    pub fn on_stderr() -> Self {
+        SilentProgress
+    }
+}
+
+impl ProgressSink for SilentProgress {
+    fn start(&mut self, _: u64, _: ProgressUnit) {}
+
+    fn advance(&mut self, _: u64) {}
+
+    fn finish(&mut self) {}
+}
+
+/// Forwards the progress and counts the bytes that were actually processed by the sequential
+/// scans. Skipped bytes and lookups (ex: index or interpolation search) are not counted.
+pub struct CountingProgress<'a> {
+    inner: &'a mut dyn ProgressSink,
+    /// unit of the current scan
+    unit: ProgressUnit,
+    /// None if no sequential scan was started
+    bytes: Option<u64>,
+}
+
+impl<'a> CountingProgress<'a> {
+    pub fn new(inner: &'a mut dyn ProgressSink) -> Self {
+        CountingProgress {
+            inner,
+            unit: ProgressUnit::Items,
+            bytes: None,
+        }
+    }
+
+    /// Processed bytes of all sequential scans - None if the hashes were only looked up
+    pub fn bytes(&self) -> Option<u64> {
+        self.bytes
+    }
+}
+
+impl ProgressSink for CountingProgress<'_> {
+    fn start(&mut self, total: u64, unit: ProgressUnit) {
+        self.unit = unit;
+        if unit == ProgressUnit::Bytes {
+            self.bytes.get_or_insert(0);
+        }
+
+        self.inner.start(total, unit);
+    }
+
+    fn advance(&mut self, delta: u64) {
+        if let (ProgressUnit::Bytes, Some(bytes)) = (self.unit, &mut self.bytes) {
+            *bytes += delta;
+        }
+
+        self.inner.advance(delta);
+    }
+
+    fn skip(&mut self, delta: u64) {
+        self.inner.skip(delta);
+    }
+
+    fn finish(&mut self) {
+        self.inner.finish();
+    }
+}
+
+/// Builds without the progress bar feature run silently
+#[cfg(not(feature = "progress-bar"))]
+pub type TerminalProgress = SilentProgress;
+
+/// Progress bar printed to the terminal - silent if the output is redirected
+#[cfg(feature = "progress-bar")]
+#[derive(Default)]
+pub struct TerminalProgress {
+    bar: Option<ProgressBar<Box<dyn Write>>>,
+    /// The standard output is reserved for a structured report
+    stderr: bool,
+}
+
+#[cfg(feature = "progress-bar")]
+impl TerminalProgress {
+    pub fn on_stderr() -> Self {
+        TerminalProgress {
+            bar: None,
+            stderr: true,
+        }
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressSink for TerminalProgress {
+    fn start(&mut self, total: u64, unit: ProgressUnit) {
+        // the redrawn line would corrupt piped logs (ex: tee or cron mails) with carriage returns
+        let output: Box<dyn Write> = if self.stderr && io::stderr().is_terminal() {
+            Box::new(io::stderr())
+        } else if !self.stderr && io::stdout().is_terminal() {
+            Box::new(io::stdout())
+        } else {
+            return;
+        };
+
+        let mut bar = ProgressBar::on(output, total);
+        if unit == ProgressUnit::Bytes {
+            bar.set_units(Units::Bytes);
+        }
+
+        // limit refresh, because we call add very frequently
+        bar.set_max_refresh_rate(Some(Duration::from_secs(1)));
+        self.bar = Some(bar);
+    }
+
+    fn advance(&mut self, delta: u64) {
+        if let Some(bar) = &mut self.bar {
+            bar.add(delta);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(mut bar) = self.bar.take() {
+            bar.finish();
+        }
+    }
+}
+
+/// Records all progress events for verification in tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct RecordingProgress {
+    pub started: Vec<(u64, ProgressUnit)>,
+    pub advanced: u64,
+    /// part of the advanced units
+    pub skipped: u64,
+    pub finished: usize,
+}
+
+#[cfg(test)]
+impl ProgressSink for RecordingProgress {
+    fn start(&mut self, total: u64, unit: ProgressUnit) {
+        self.started.push((total, unit));
+    }
+
+    fn advance(&mut self, delta: u64) {
+        self.advanced += delta;
+    }
+
+    fn skip(&mut self, delta: u64) {
+        self.advanced += delta;
+        self.skipped += delta;
+    }
+
+    fn finish(&mut self) {
+        self.finished += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_counting_progress() {
+        let mut recording = RecordingProgress::default();
+        let mut counting = CountingProgress::new(&mut recording);
+        counting.start(2, ProgressUnit::Items);
+        counting.advance(2);
+        counting.finish();
+        assert_eq!(counting.bytes(), None);
+
+        counting.start(100, ProgressUnit::Bytes);
+        counting.advance(40);
+        counting.skip(60);
+        counting.finish();
+        assert_eq!(counting.bytes(), Some(40));
+
+        // the skipped bytes are still complete for the displayed progress
+        assert_eq!(recording.advanced, 102);
+        assert_eq!(recording.skipped, 60);
+        assert_eq!(recording.finished, 2);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "crypto")]
+use std::{fs, io, path::Path};
+
+use data_encoding::HEXUPPER;
+#[cfg(feature = "crypto")]
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::collect::SavedHash;
+#[cfg(feature = "crypto")]
+use crate::{atomic::atomic_write, bundle::write_private};
+
+/// Length of the local HMAC key in bytes
+#[cfg(feature = "crypto")]
+const KEY_LENGTH: usize = 32;
+
+/// Number of HMAC bytes in a pseudonym - enough to be unique for a password export
+const PSEUDONYM_BYTES: usize = 6;
+
+/// Replaces URLs and usernames in the outputs with stable pseudonyms. The same key always produces
+/// the same pseudonym, so reports of different runs could be compared. Without the key or the
+/// mapping file the original values cannot be recovered.
+pub struct Pseudonymizer {
+    /// None if anonymization is disabled
+    key: Option<Key>,
+    /// Pseudonym to the original value of all labels used in this run
+    mapping: BTreeMap<String, String>,
+}
+
+impl Pseudonymizer {
+    /// Keep the original values
+    pub fn disabled() -> Self {
+        Pseudonymizer {
+            key: None,
+            mapping: BTreeMap::new(),
+        }
+    }
+
+    /// Load the local key or create a new random one on the first use
+    #[cfg(feature = "crypto")]
+    pub fn load_or_create(key_path: &Path) -> io::Result<Self> {
+        let key = if key_path.exists() {
+            fs::read(key_path)?
+        } else {
+            let mut key = vec![0; KEY_LENGTH];
+            SystemRandom::new()
+                .fill(&mut key)
+                .map_err(|_| io::Error::other("No random source"))?;
+            write_private(key_path, &key)?;
+            key
+        };
+
+        Ok(Pseudonymizer {
+            key: Some(hmac::Key::new(hmac::HMAC_SHA256, &key)),
+            mapping: BTreeMap::new(),
+        })
+    }
+
+    /// Label of the account used in all outputs
+    pub fn label(&mut self, saved: &SavedHash) -> String {
+        if self.key.is_none() {
+            return saved.to_string();
+        }
+
+        let (username, url) = self.account(saved);
+        format!("{}@{}", username, url)
+    }
+
+    /// Username and URL of the account for the structured outputs
+    pub fn account(&mut self, saved: &SavedHash) -> (String, String) {
+        let username = self.pseudonym("user", saved.username());
+        let url = self.pseudonym("site", saved.url());
+        (username, url)
+    }
+
+    fn pseudonym(&mut self, kind: &str, value: &str) -> String {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return value.to_string(),
+        };
+
+        let pseudonym = format!("{}-{}", kind, HEXUPPER.encode(&tag(key, kind, value)));
+        self.mapping
+            .entry(pseudonym.clone())
+            .or_insert_with(|| value.to_string());
+        pseudonym
+    }
+
+    /// Merge the pseudonyms of this run into the mapping file to de-anonymize reports locally
+    #[cfg(feature = "crypto")]
+    pub fn write_mapping(&self, path: &Path) -> Result<(), csv::Error> {
+        if self.key.is_none() {
+            return Ok(());
+        }
+
+        let mut mapping = BTreeMap::new();
+        if path.exists() {
+            let mut reader = csv::Reader::from_path(path)?;
+            for row in reader.deserialize() {
+                let (pseudonym, original): (String, String) = row?;
+                mapping.insert(pseudonym, original);
+            }
+        }
+
+        mapping.extend(
+            self.mapping
+                .iter()
+                .map(|(pseudonym, original)| (pseudonym.clone(), original.clone())),
+        );
+
+        atomic_write(path, |file| {
+            let mut writer = csv::Writer::from_writer(file);
+            writer.write_record(&["pseudonym", "original"])?;
+            for row in &mapping {
+                writer.serialize(row)?;
+            }
+
+            writer.flush()?;
+            Ok(())
+        })
+    }
+}
+
+/// HMAC key of the pseudonyms - builds without ring cannot enable the anonymization
+#[cfg(feature = "crypto")]
+type Key = hmac::Key;
+
+#[cfg(not(feature = "crypto"))]
+type Key = std::convert::Infallible;
+
+#[cfg(feature = "crypto")]
+fn tag(key: &Key, kind: &str, value: &str) -> [u8; PSEUDONYM_BYTES] {
+    // the kind is part of the message, so a username equal to an URL gets another pseudonym
+    let mut context = hmac::Context::with_key(key);
+    context.update(kind.as_bytes());
+    context.update(&[0]);
+    context.update(value.as_bytes());
+
+    let mut pseudonym = [0; PSEUDONYM_BYTES];
+    pseudonym.copy_from_slice(&context.sign().as_ref()[..PSEUDONYM_BYTES]);
+    pseudonym
+}
+
+#[cfg(not(feature = "crypto"))]
+fn tag(key: &Key, _kind: &str, _value: &str) -> [u8; PSEUDONYM_BYTES] {
+    match *key {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "crypto")]
+    fn with_key(key: &[u8]) -> Pseudonymizer {
+        Pseudonymizer {
+            key: Some(hmac::Key::new(hmac::HMAC_SHA256, key)),
+            mapping: BTreeMap::new(),
+        }
+    }
+
+    fn saved(username: &str) -> SavedHash {
+        SavedHash::new("https://example.com/", username, [0; 20])
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut pseudonyms = Pseudonymizer::disabled();
+        assert_eq!(
+            pseudonyms.label(&saved("user")),
+            "user@https://example.com/"
+        );
+        assert!(pseudonyms.mapping.is_empty());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_stable() {
+        let first = with_key(b"key").label(&saved("user"));
+        let second = with_key(b"key").label(&saved("user"));
+        assert_eq!(first, second);
+        assert!(!first.contains("example.com"));
+
+        assert_ne!(first, with_key(b"other").label(&saved("user")));
+        assert_ne!(first, with_key(b"key").label(&saved("other")));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_mapping() {
+        let mut pseudonyms = with_key(b"key");
+        let label = pseudonyms.label(&saved("user"));
+        let (username, url) = label.split_at(label.find('@').unwrap());
+
+        assert!(username.starts_with("user-"));
+        assert_eq!(pseudonyms.mapping[username], "user");
+        assert_eq!(pseudonyms.mapping[&url[1..]], "https://example.com/");
+    }
+}
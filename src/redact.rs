@@ -0,0 +1,74 @@
+use serde_json::error::Category;
+
+/// Placeholder of a redacted value in the debug output and in error messages. The passwords
+/// themselves are kept in `SecStr`, but the structures around them and the errors of the parsers
+/// reading them could still print them.
+pub const REDACTED: &str = "***";
+
+/// Kind and position of a JSON error. The message itself isn't used, because type errors quote the
+/// rejected value (ex: ``invalid type: integer `1234`, expected a string``).
+pub fn json_error(err: &serde_json::Error) -> String {
+    let kind = match err.classify() {
+        Category::Io => "Read error",
+        Category::Syntax => "Invalid JSON",
+        Category::Data => "Unexpected field type or value",
+        Category::Eof => "Unexpected end of JSON",
+    };
+
+    format!("{} at line {} column {}", kind, err.line(), err.column())
+}
+
+/// Position of a YAML error without the message for the same reason as `json_error`
+pub fn yaml_error(err: &serde_yaml::Error) -> String {
+    match err.location() {
+        Some(location) => format!(
+            "Invalid YAML at line {} column {}",
+            location.line(),
+            location.column()
+        ),
+        None => "Invalid YAML".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Known fixture password that must never appear in the output
+    const FIXTURE_PASSWORD: &str = "fixture-Secret-4711";
+
+    /// Expects numbers, so the password is rejected as value of the wrong type
+    type Login = BTreeMap<String, u32>;
+
+    #[test]
+    fn test_json_error_redacted() {
+        let json = format!(r#"{{"password": "{}"}}"#, FIXTURE_PASSWORD);
+        let err = serde_json::from_str::<Login>(&json).unwrap_err();
+
+        // the original message quotes the password
+        assert!(err.to_string().contains(FIXTURE_PASSWORD));
+        let redacted = json_error(&err);
+        assert!(!redacted.contains(FIXTURE_PASSWORD), "{}", redacted);
+        assert_eq!(
+            redacted,
+            "Unexpected field type or value at line 1 column 34"
+        );
+    }
+
+    #[test]
+    fn test_yaml_error_redacted() {
+        let yaml = format!("password: {}\n", FIXTURE_PASSWORD);
+        let err = serde_yaml::from_str::<Login>(&yaml).unwrap_err();
+
+        assert!(err.to_string().contains(FIXTURE_PASSWORD));
+        let redacted = yaml_error(&err);
+        assert!(!redacted.contains(FIXTURE_PASSWORD), "{}", redacted);
+        assert!(
+            redacted.starts_with("Invalid YAML at line 1"),
+            "{}",
+            redacted
+        );
+    }
+}
@@ -0,0 +1,668 @@
+use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr, time::SystemTime};
+
+use log::{debug, info};
+#[cfg(feature = "crypto")]
+use ring::signature::Ed25519KeyPair;
+
+#[cfg(feature = "tui")]
+use crate::tui::Tui;
+use crate::{
+    collect::{SavedHash, Timestamps},
+    date,
+    find::Finding,
+    normalize::UnicodeForm,
+    profiles::PwnedLimits,
+    pseudonym::Pseudonymizer,
+    sample::Sample,
+    Sha1Hash,
+};
+
+#[cfg(feature = "crypto")]
+pub use json::canonicalize;
+#[cfg(feature = "tui")]
+pub use json::{Account, Event, Match, Summary};
+pub use json::{StructuredReport, SCHEMA};
+pub use remediation::{write_remediation, RemediationFormat};
+#[cfg(feature = "crypto")]
+pub use signature::{sign, verify};
+
+mod json;
+mod remediation;
+#[cfg(feature = "crypto")]
+mod signature;
+
+/// Common second level domains of country code domains (ex: `co.uk`, `com.au`) - the registrable
+/// domain is the label below them
+const COUNTRY_SECOND_LEVELS: [&str; 10] = [
+    "ac", "co", "com", "edu", "gov", "ltd", "ne", "net", "or", "org",
+];
+
+/// Credit of the Pwned Passwords data - shown whenever the online range API was queried
+pub const HIBP_ATTRIBUTION: &str =
+    "Password data by Have I Been Pwned (https://haveibeenpwned.com/Passwords)";
+
+/// Output format of the pwned accounts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human readable log lines
+    #[default]
+    Text,
+    /// Single JSON document written after the scan
+    Json,
+    /// One JSON event per line
+    Ndjson,
+}
+
+impl ReportFormat {
+    pub const NAMES: [&'static str; 3] = ["text", "json", "ndjson"];
+
+    /// The standard output is reserved for the report
+    pub fn is_structured(self) -> bool {
+        self != ReportFormat::Text
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "ndjson" => Ok(ReportFormat::Ndjson),
+            _ => Err(format!("Unknown report format {}", s)),
+        }
+    }
+}
+
+/// What is reported besides the pwned accounts
+#[derive(Clone, Copy, Debug)]
+pub struct ReportOptions<'a> {
+    pub format: ReportFormat,
+    /// Include accounts without a match
+    pub show_clean: bool,
+    /// Report the entries of the same site with identical credentials once
+    pub collapse_variants: bool,
+    /// Print the vault health summary
+    pub health: bool,
+    /// Additionally write the health summary as HTML page
+    pub health_html: Option<&'a Path>,
+    /// Write the pwned entries as import file of the password manager
+    pub remediation: Option<(&'a Path, RemediationFormat)>,
+    /// Commands receiving the NDJSON events
+    pub reporters: &'a [&'a str],
+    /// Interactive interface replacing the text report
+    #[cfg(feature = "tui")]
+    pub tui: Option<&'a Tui>,
+    /// Write a signed attestation of the run to the file
+    #[cfg(feature = "crypto")]
+    pub attest: Option<(&'a Path, &'a Ed25519KeyPair)>,
+    /// Write a detached signature of the JSON report to the file
+    #[cfg(feature = "crypto")]
+    pub sign_report: Option<(&'a Path, &'a Ed25519KeyPair)>,
+    /// Only check a random part of the accounts and estimate the pwned ones
+    pub sample: Option<Sample>,
+    /// Fail the run if more accounts are pwned
+    pub pwned_limits: &'a PwnedLimits,
+}
+
+impl ReportOptions<'_> {
+    /// The report is printed as log lines
+    pub fn is_text(&self) -> bool {
+        #[cfg(feature = "tui")]
+        if self.tui.is_some() {
+            return false;
+        }
+
+        self.format == ReportFormat::Text
+    }
+
+    /// The report events are used by a structured output, a reporter or the interface
+    pub fn needs_events(&self) -> bool {
+        !self.is_text() || !self.reporters.is_empty()
+    }
+}
+
+/// Scanned hash database
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Corpus<'a> {
+    pub name: &'a str,
+    /// Publishing time from the metadata - none if it's unknown
+    pub snapshot: Option<SystemTime>,
+    /// Other Unicode form of the passwords searched in the database - none for the exported ones
+    pub form: Option<UnicodeForm>,
+    /// Online lookup of the hashes without a match in the local databases (hybrid mode) - its
+    /// matches were added after the local snapshots
+    pub newly_pwned: bool,
+}
+
+/// Match of an account in a single hash database
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Provenance<'a> {
+    pub corpus: Corpus<'a>,
+    /// None if the line had no count
+    pub count: Option<u64>,
+}
+
+impl Display for Provenance<'_> {
+    /// Ex: `hibp.txt 42x snapshot 2024-05-01` or `hibp.txt (NFD form) 42x`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.corpus.name)?;
+        if let Some(form) = self.corpus.form {
+            write!(f, " ({} form)", form)?;
+        }
+
+        match self.count {
+            Some(count) => write!(f, " {}x", count)?,
+            None => write!(f, " unknown count")?,
+        }
+
+        if let Some(snapshot) = self.corpus.snapshot {
+            write!(f, " snapshot {}", date::format_date(snapshot))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Merged results of all scanned hash databases for a single saved account
+#[derive(Debug)]
+pub struct AccountReport<'a> {
+    pub saved: &'a SavedHash,
+    /// Matches in the scan order of the hash databases - empty if it's clean
+    pub matches: Vec<Provenance<'a>>,
+    /// Highest count of all matches - none if no count could be parsed
+    pub max_count: Option<u64>,
+    /// Other entries of the same site with identical credentials (ex: `login.example.com` and
+    /// `www.example.com`) that are reported by this one
+    pub variants: Vec<&'a SavedHash>,
+}
+
+impl AccountReport<'_> {
+    /// Password wasn't found in any hash database
+    pub fn is_clean(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    /// Number of saved entries including the collapsed variants
+    pub fn entries(&self) -> usize {
+        1 + self.variants.len()
+    }
+
+    /// Only found by the online lookup of the hybrid mode, so it's missing in the local snapshots
+    pub fn is_newly_pwned(&self) -> bool {
+        self.matches
+            .iter()
+            .any(|provenance| provenance.corpus.newly_pwned)
+    }
+}
+
+/// Merge the findings of each hash database into one entry per account. The result keeps the
+/// order of the given hashes. Accounts without any match are only included with `include_clean`.
+pub fn merge<'a>(
+    hashes: &'a [SavedHash],
+    results: &[(Corpus<'a>, Vec<Finding>)],
+    include_clean: bool,
+) -> Vec<AccountReport<'a>> {
+    let mut merged: Vec<Option<AccountReport<'a>>> = hashes.iter().map(|_| None).collect();
+    for (corpus, findings) in results {
+        for finding in findings {
+            let entry = merged[finding.index].get_or_insert_with(|| AccountReport {
+                saved: &hashes[finding.index],
+                matches: Vec::new(),
+                max_count: None,
+                variants: Vec::new(),
+            });
+
+            entry.matches.push(Provenance {
+                corpus: *corpus,
+                count: finding.count,
+            });
+            entry.max_count = entry.max_count.max(finding.count);
+        }
+    }
+
+    if include_clean {
+        merged
+            .into_iter()
+            .zip(hashes)
+            .map(|(entry, saved)| {
+                entry.unwrap_or(AccountReport {
+                    saved,
+                    matches: Vec::new(),
+                    max_count: None,
+                    variants: Vec::new(),
+                })
+            })
+            .collect()
+    } else {
+        merged.into_iter().flatten().collect()
+    }
+}
+
+/// Collapse the entries of the same site with identical username and password into the first of
+/// them. Browsers save a separate entry for each subdomain, so long-lived profiles contain dozens
+/// of them for a single account. Entries without a site (ex: notes) and entries of different
+/// profiles are kept.
+pub fn collapse_variants(reports: Vec<AccountReport<'_>>) -> Vec<AccountReport<'_>> {
+    type VariantKey = (Option<String>, String, String, Sha1Hash);
+    let mut first_of: HashMap<VariantKey, usize> = HashMap::new();
+    let mut collapsed: Vec<AccountReport<'_>> = Vec::with_capacity(reports.len());
+    for report in reports {
+        let saved = report.saved;
+        let site = match site(saved.url()) {
+            Some(site) => site,
+            None => {
+                collapsed.push(report);
+                continue;
+            }
+        };
+
+        let key = (
+            saved.profile().map(str::to_string),
+            site,
+            saved.username().to_lowercase(),
+            saved.password_hash,
+        );
+        match first_of.get(&key) {
+            Some(&first) => collapsed[first].variants.push(saved),
+            None => {
+                first_of.insert(key, collapsed.len());
+                collapsed.push(report);
+            }
+        }
+    }
+
+    collapsed
+}
+
+/// Registrable domain of the URL (ex: `example.com` for `https://login.example.com:8443/auth`)
+/// approximated without the public suffix list. Second level domains below country codes like
+/// `co.uk` are kept. Other schemes than HTTP (ex: `androidapp://`) are compared as a whole.
+fn site(url: &str) -> Option<String> {
+    let url = url.trim().to_lowercase();
+    let host = match url.split_once("://") {
+        Some(("http", rest)) | Some(("https", rest)) => rest,
+        Some(_) => return Some(url),
+        // some exports save only the domain
+        None => url.as_str(),
+    };
+
+    let host = host.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    // IPv6 addresses are compared with the brackets and without the port
+    let host = match host.strip_prefix('[') {
+        Some(address) => return address.split(']').next().map(str::to_string),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+
+    let host = host.trim_end_matches('.');
+    if host.is_empty() {
+        return None;
+    }
+
+    let labels: Vec<_> = host.split('.').collect();
+    let is_address = labels
+        .iter()
+        .all(|label| label.bytes().all(|byte| byte.is_ascii_digit()));
+    if is_address || labels.len() <= 2 {
+        return Some(host.to_string());
+    }
+
+    let country = labels[labels.len() - 1];
+    let second = labels[labels.len() - 2];
+    let country_second_level = country.len() == 2 && COUNTRY_SECOND_LEVELS.contains(&second);
+    let parts = if country_second_level { 3 } else { 2 };
+    Some(labels[labels.len() - parts..].join("."))
+}
+
+/// Print the report of each pwned account. The matching databases with their count and snapshot
+/// are only listed if there were multiple ones. Clean accounts are only summarized to keep the
+/// report short - the verbose output lists them.
+pub fn print(reports: &[AccountReport<'_>], list_corpora: bool, pseudonyms: &mut Pseudonymizer) {
+    let (clean, pwned): (Vec<_>, Vec<_>) = reports.iter().partition(|report| report.is_clean());
+    for report in &pwned {
+        let mut source = if list_corpora {
+            let matches: Vec<_> = report.matches.iter().map(ToString::to_string).collect();
+            format!(" (found in: {})", matches.join(", "))
+        } else {
+            // the other Unicode forms are named even for a single hash database
+            let forms: Vec<_> = report
+                .matches
+                .iter()
+                .filter_map(|provenance| provenance.corpus.form)
+                .map(|form| form.to_string())
+                .collect();
+            if forms.is_empty() {
+                String::new()
+            } else {
+                format!(" (found as {} form)", forms.join(", "))
+            }
+        };
+
+        let tags = report.saved.tags();
+        if !tags.is_empty() {
+            source.push_str(&format!(" (tags: {})", tags.join(", ")));
+        }
+
+        if let Some(profile) = report.saved.profile() {
+            source.push_str(&format!(" (profile: {})", profile));
+        }
+
+        if let Some(times) = format_times(&report.saved.times) {
+            source.push_str(&format!(" ({})", times));
+        }
+
+        if report.is_newly_pwned() {
+            source.push_str(" (newly pwned since your snapshot)");
+        }
+
+        if !report.variants.is_empty() {
+            let urls: Vec<_> = report
+                .variants
+                .iter()
+                .map(|variant| pseudonyms.account(variant).1)
+                .collect();
+            source.push_str(&format!(" (also saved for: {})", urls.join(", ")));
+        }
+
+        let label = pseudonyms.label(report.saved);
+        print_account(&label, report.max_count, &source);
+    }
+
+    if !clean.is_empty() {
+        for report in &clean {
+            debug!("Clean account {}", pseudonyms.label(report.saved));
+        }
+
+        let entries = |reports: &[&AccountReport<'_>]| -> usize {
+            reports.iter().map(|report| report.entries()).sum()
+        };
+        let clean_entries = entries(&clean);
+        print_clean_summary(clean_entries, clean_entries + entries(&pwned));
+    }
+}
+
+/// Local dates of the entry to tell how old the password is (ex: `changed 2021-04-01, last used
+/// 2024-05-02`) - none if the export has no times
+fn format_times(times: &Timestamps) -> Option<String> {
+    let set = match (times.changed, times.created) {
+        (Some(changed), _) => Some(format!("changed {}", date::format_local_date(changed))),
+        (None, Some(created)) => Some(format!("created {}", date::format_local_date(created))),
+        (None, None) => None,
+    };
+    let used = times
+        .last_used
+        .map(|used| format!("last used {}", date::format_local_date(used)));
+
+    let parts: Vec<_> = set.into_iter().chain(used).collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(", "))
+    }
+}
+
+/// Footer of the text report if the online range API was queried
+pub fn print_attribution() {
+    info!("{}", HIBP_ATTRIBUTION);
+}
+
+/// Print the number of checked accounts without a match
+pub fn print_clean_summary(clean: usize, checked: usize) {
+    info!("{} of {} checked accounts are clean", clean, checked);
+}
+
+/// Print a single pwned account with the given suffix
+pub fn print_account(account: &dyn Display, count: Option<u64>, suffix: &str) {
+    match count {
+        Some(count) => info!(
+            "Your password for the following account {} has been pwned {}x times{}",
+            account, count, suffix
+        ),
+        None => info!(
+            "Your password for the following account {} has been pwned - unknown count{}",
+            account, suffix
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn saved(hash_byte: u8) -> SavedHash {
+        SavedHash::new("https://example.com/", "user", [hash_byte; 20])
+    }
+
+    fn corpus(name: &str) -> Corpus<'_> {
+        Corpus {
+            name,
+            snapshot: None,
+            form: None,
+            newly_pwned: false,
+        }
+    }
+
+    #[test]
+    fn test_format_parse() {
+        for name in &ReportFormat::NAMES {
+            assert!(name.parse::<ReportFormat>().is_ok());
+        }
+
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_merge_max_count() {
+        let hashes = [saved(1), saved(2), saved(3)];
+        let results = [
+            (
+                Corpus {
+                    name: "hibp.txt",
+                    snapshot: Some(UNIX_EPOCH + Duration::from_secs(1_714_521_600)),
+                    form: None,
+                    newly_pwned: false,
+                },
+                vec![
+                    Finding {
+                        index: 0,
+                        count: Some(5),
+                    },
+                    Finding {
+                        index: 2,
+                        count: None,
+                    },
+                ],
+            ),
+            (
+                corpus("internal.txt"),
+                vec![Finding {
+                    index: 0,
+                    count: Some(10),
+                }],
+            ),
+        ];
+
+        let merged = merge(&hashes, &results, false);
+        assert_eq!(merged.len(), 2);
+
+        assert_eq!(merged[0].saved, &hashes[0]);
+        assert_eq!(merged[0].max_count, Some(10));
+        let matches: Vec<_> = merged[0].matches.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            matches,
+            ["hibp.txt 5x snapshot 2024-05-01", "internal.txt 10x"]
+        );
+
+        assert_eq!(merged[1].saved, &hashes[2]);
+        assert_eq!(
+            merged[1].matches[0].to_string(),
+            "hibp.txt unknown count snapshot 2024-05-01"
+        );
+        assert_eq!(merged[1].max_count, None);
+    }
+
+    #[test]
+    fn test_unicode_form_provenance() {
+        let provenance = Provenance {
+            corpus: Corpus {
+                form: Some(UnicodeForm::Nfd),
+                ..corpus("hibp.txt")
+            },
+            count: Some(3),
+        };
+        assert_eq!(provenance.to_string(), "hibp.txt (NFD form) 3x");
+    }
+
+    #[test]
+    fn test_newly_pwned() {
+        let hashes = [saved(1), saved(2)];
+        let results = [
+            (
+                corpus("hibp.txt"),
+                vec![Finding {
+                    index: 0,
+                    count: Some(5),
+                }],
+            ),
+            (
+                Corpus {
+                    newly_pwned: true,
+                    ..corpus("range")
+                },
+                vec![Finding {
+                    index: 1,
+                    count: Some(2),
+                }],
+            ),
+        ];
+
+        let merged = merge(&hashes, &results, false);
+        assert!(!merged[0].is_newly_pwned());
+        assert!(merged[1].is_newly_pwned());
+    }
+
+    #[test]
+    fn test_merge_empty() {
+        let hashes = [saved(1)];
+        let results = [(corpus("hibp.txt"), vec![])];
+        assert!(merge(&hashes, &results, false).is_empty());
+    }
+
+    #[test]
+    fn test_merge_clean() {
+        let hashes = [saved(1), saved(2)];
+        let results = [(
+            corpus("hibp.txt"),
+            vec![Finding {
+                index: 1,
+                count: Some(3),
+            }],
+        )];
+
+        let merged = merge(&hashes, &results, true);
+        assert_eq!(merged.len(), 2);
+
+        assert_eq!(merged[0].saved, &hashes[0]);
+        assert!(merged[0].is_clean());
+        assert_eq!(merged[0].max_count, None);
+        assert!(!merged[1].is_clean());
+    }
+
+    #[test]
+    fn test_site() {
+        for (url, expected) in [
+            (
+                "https://login.example.com:8443/auth?next=/",
+                Some("example.com"),
+            ),
+            ("http://user@WWW.Example.com./", Some("example.com")),
+            ("example.com", Some("example.com")),
+            ("https://accounts.shop.co.uk/", Some("shop.co.uk")),
+            ("https://a.b.example.de/", Some("example.de")),
+            ("http://192.168.1.10:8080/admin", Some("192.168.1.10")),
+            ("https://[::1]:8443/", Some("::1")),
+            (
+                "androidapp://com.example.app",
+                Some("androidapp://com.example.app"),
+            ),
+            ("", None),
+            ("https:///path", None),
+        ] {
+            assert_eq!(site(url).as_deref(), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_collapse_variants() {
+        let hashes = [
+            SavedHash::new("https://login.example.com/", "user", [1; 20]),
+            SavedHash::new("https://other.org/", "user", [1; 20]),
+            SavedHash::new("https://www.example.com/account", "User", [1; 20]),
+            SavedHash::new("https://example.com/", "admin", [1; 20]),
+            SavedHash::new("https://example.com/", "user", [2; 20]),
+            SavedHash::new("", "user", [1; 20]),
+            SavedHash::new("", "user", [1; 20]),
+        ];
+        let findings = (0..hashes.len())
+            .map(|index| Finding {
+                index,
+                count: Some(3),
+            })
+            .collect();
+        let results = [(corpus("hibp.txt"), findings)];
+
+        let collapsed = collapse_variants(merge(&hashes, &results, false));
+        // saved hashes are equal by their password only
+        let entry = |saved: &SavedHash| format!("{}@{}", saved.username(), saved.url());
+        let reported: Vec<_> = collapsed.iter().map(|report| entry(report.saved)).collect();
+        assert_eq!(
+            reported,
+            [
+                "user@https://login.example.com/",
+                "user@https://other.org/",
+                "admin@https://example.com/",
+                "user@https://example.com/",
+                "user@",
+                "user@"
+            ]
+        );
+
+        let variants: Vec<_> = collapsed[0]
+            .variants
+            .iter()
+            .map(|saved| entry(saved))
+            .collect();
+        assert_eq!(variants, ["User@https://www.example.com/account"]);
+        assert_eq!(collapsed[0].entries(), 2);
+        assert!(collapsed[1..]
+            .iter()
+            .all(|report| report.variants.is_empty()));
+    }
+
+    #[test]
+    fn test_format_times() {
+        let mut times = Timestamps::default();
+        assert_eq!(format_times(&times), None);
+
+        let created = UNIX_EPOCH + Duration::from_secs(1_369_396_800);
+        let used = UNIX_EPOCH + Duration::from_secs(1_714_564_800);
+        times.created = Some(created);
+        times.last_used = Some(used);
+        let expected = format!(
+            "created {}, last used {}",
+            date::format_local_date(created),
+            date::format_local_date(used)
+        );
+        assert_eq!(format_times(&times), Some(expected));
+
+        // the change replaces the creation, because it dates the current password
+        times.changed = Some(used);
+        times.last_used = None;
+        let expected = format!("changed {}", date::format_local_date(used));
+        assert_eq!(format_times(&times), Some(expected));
+    }
+}
@@ -0,0 +1,804 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+#[cfg(feature = "crypto")]
+use serde_json::Value;
+
+use crate::{
+    date,
+    find::Footprint,
+    profiles::{ProfileSummary, PwnedLimits},
+    pseudonym::Pseudonymizer,
+    report::{AccountReport, Provenance, HIBP_ATTRIBUTION},
+    sample::Estimate,
+    SHA1_BYTE_LENGTH,
+};
+
+/// Version of the JSON and NDJSON formats. Every incompatible change increases it, so parsers can
+/// reject outputs they don't understand.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema of the document - `#/$defs/event` describes a single NDJSON line
+pub const SCHEMA: &str = include_str!("schema.json");
+
+/// Account entry of the structured outputs
+#[derive(Clone, Debug, Serialize)]
+pub struct Account {
+    pub url: String,
+    pub username: String,
+    pub pwned: bool,
+    pub count: Option<u64>,
+    pub corpora: Vec<String>,
+    /// Folders, collections or groups of the entry in the export
+    pub tags: Vec<String>,
+    /// Count and snapshot of each matching hash database
+    pub matches: Vec<Match>,
+    /// URLs of the other entries of the same site with identical credentials
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<String>,
+    /// Person of the export in a comparison of multiple profiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// RFC 3339 times of the entry in UTC - only exports like Firefox include them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+    /// Only found by the online lookup of the hybrid mode - omitted otherwise
+    #[serde(skip_serializing_if = "is_false")]
+    pub newly_pwned: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+/// Provenance of a finding in the structured outputs
+#[derive(Clone, Debug, Serialize)]
+pub struct Match {
+    pub corpus: String,
+    /// RFC 3339 publishing time of the hash database
+    pub snapshot: Option<String>,
+    pub count: Option<u64>,
+    /// Unicode form of the password that matched - omitted for the exported password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub form: Option<String>,
+}
+
+impl From<&Provenance<'_>> for Match {
+    fn from(provenance: &Provenance<'_>) -> Self {
+        Match {
+            corpus: provenance.corpus.name.to_string(),
+            snapshot: provenance.corpus.snapshot.map(date::format_rfc3339),
+            count: provenance.count,
+            form: provenance.corpus.form.map(|form| form.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub checked: usize,
+    pub pwned: usize,
+    pub clean: usize,
+    /// False if the scan was cancelled
+    pub complete: bool,
+    /// Accounts that were hashed, but not compared before the run was cancelled - omitted if all
+    /// hashed accounts were compared
+    #[serde(skip_serializing_if = "is_zero")]
+    pub unchecked: usize,
+    /// Extrapolation if only a sample was checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<Estimate>,
+    /// Requests of the online range APIs - omitted if none was scanned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    /// Bytes read by the sequential scans - omitted if the hashes were only looked up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_scanned: Option<u64>,
+    /// Allowed pwned accounts of all inputs together - omitted without a limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pwned: Option<usize>,
+    /// More accounts are pwned than allowed - omitted otherwise
+    #[serde(skip_serializing_if = "is_false")]
+    pub exceeded: bool,
+}
+
+/// Summary of a single input in a scan of multiple exports
+#[derive(Clone, Debug, Serialize)]
+pub struct InputSummary {
+    pub checked: usize,
+    pub pwned: usize,
+    pub clean: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pwned: Option<usize>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub exceeded: bool,
+}
+
+/// Findings of a single input - the same entries are also part of the accounts of all inputs
+#[derive(Clone, Debug, Serialize)]
+pub struct Input {
+    pub name: String,
+    pub summary: InputSummary,
+    pub accounts: Vec<Account>,
+}
+
+/// Network footprint of the online range APIs
+#[derive(Clone, Debug, Serialize)]
+pub struct Network {
+    pub ranges_requested: usize,
+    /// Ranges read from the prefetch cache instead
+    pub ranges_cached: usize,
+    /// All requests including the retries
+    pub requests: usize,
+    /// Most hash characters sent in a single request
+    pub hash_prefix_length: usize,
+    pub full_hashes_sent: bool,
+    /// Credit of the API operator - should be kept when the report is shown to others
+    pub attribution: &'static str,
+}
+
+impl From<Footprint> for Network {
+    fn from(footprint: Footprint) -> Self {
+        Network {
+            ranges_requested: footprint.ranges,
+            ranges_cached: footprint.cached,
+            requests: footprint.requests,
+            hash_prefix_length: footprint.hash_chars,
+            full_hashes_sent: footprint.hash_chars >= SHA1_BYTE_LENGTH * 2,
+            attribution: HIBP_ATTRIBUTION,
+        }
+    }
+}
+
+/// Single line of the NDJSON output
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Start {
+        corpora: &'a [&'a str],
+        accounts: usize,
+    },
+    Account {
+        account: &'a Account,
+    },
+    /// Summary of a single input - its accounts are the account events with the profile
+    Input {
+        name: &'a str,
+        summary: &'a InputSummary,
+    },
+    Finish {
+        summary: &'a Summary,
+    },
+}
+
+#[derive(Serialize)]
+struct Versioned<T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    content: T,
+}
+
+impl<T> Versioned<T> {
+    fn new(content: T) -> Self {
+        Versioned {
+            schema_version: SCHEMA_VERSION,
+            content,
+        }
+    }
+}
+
+/// Results of a run in the structured formats
+#[derive(Debug, Serialize)]
+pub struct StructuredReport<'a> {
+    corpora: &'a [&'a str],
+    accounts: Vec<Account>,
+    /// Omitted for a single export
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<Input>,
+    summary: Summary,
+}
+
+impl<'a> StructuredReport<'a> {
+    /// The labels are resolved once, so both formats use the same pseudonyms
+    pub fn new(
+        corpora: &'a [&'a str],
+        reports: &[AccountReport<'_>],
+        checked: usize,
+        complete: bool,
+        pseudonyms: &mut Pseudonymizer,
+    ) -> Self {
+        let accounts: Vec<_> = reports
+            .iter()
+            .map(|report| {
+                let (username, url) = pseudonyms.account(report.saved);
+                let times = report.saved.times;
+                Account {
+                    url,
+                    username,
+                    pwned: !report.is_clean(),
+                    count: report.max_count,
+                    corpora: report
+                        .matches
+                        .iter()
+                        .map(|found| found.corpus.name.to_string())
+                        // the other Unicode forms are matches in the same hash database
+                        .fold(Vec::new(), |mut corpora, name| {
+                            if !corpora.contains(&name) {
+                                corpora.push(name);
+                            }
+                            corpora
+                        }),
+                    tags: report.saved.tags().to_vec(),
+                    matches: report.matches.iter().map(Match::from).collect(),
+                    variants: report
+                        .variants
+                        .iter()
+                        .map(|variant| pseudonyms.account(variant).1)
+                        .collect(),
+                    profile: report.saved.profile().map(str::to_string),
+                    created: times.created.map(date::format_rfc3339),
+                    last_used: times.last_used.map(date::format_rfc3339),
+                    changed: times.changed.map(date::format_rfc3339),
+                    newly_pwned: report.is_newly_pwned(),
+                }
+            })
+            .collect();
+
+        // the summary counts the saved entries including the collapsed variants
+        let pwned = accounts
+            .iter()
+            .filter(|account| account.pwned)
+            .map(|account| 1 + account.variants.len())
+            .sum();
+        StructuredReport {
+            corpora,
+            accounts,
+            inputs: Vec::new(),
+            summary: Summary {
+                checked,
+                pwned,
+                clean: checked - pwned,
+                complete,
+                unchecked: 0,
+                estimate: None,
+                network: None,
+                bytes_scanned: None,
+                max_pwned: None,
+                exceeded: false,
+            },
+        }
+    }
+
+    /// Add the accounts that were read, but not compared after a cancellation while reading
+    pub fn with_unchecked(mut self, accounts: usize) -> Self {
+        self.summary.unchecked = accounts;
+        self
+    }
+
+    /// Label the results as sample
+    pub fn with_estimate(mut self, estimate: Option<Estimate>) -> Self {
+        self.summary.estimate = estimate;
+        self
+    }
+
+    /// Add the requests of the online sources
+    pub fn with_network(mut self, footprint: Option<Footprint>) -> Self {
+        self.summary.network = footprint.map(Network::from);
+        self
+    }
+
+    /// Add the bytes of the hash databases that were actually scanned
+    pub fn with_bytes_scanned(mut self, bytes: Option<u64>) -> Self {
+        self.summary.bytes_scanned = bytes;
+        self
+    }
+
+    /// Nest the accounts of each input below its summary and apply the limits
+    pub fn with_inputs(mut self, summaries: &[ProfileSummary<'_>], limits: &PwnedLimits) -> Self {
+        self.inputs = summaries
+            .iter()
+            .map(|summary| Input {
+                name: summary.name.to_string(),
+                summary: InputSummary {
+                    checked: summary.accounts,
+                    pwned: summary.pwned,
+                    clean: summary.accounts - summary.pwned,
+                    max_pwned: limits.of(Some(summary.name)),
+                    exceeded: limits.is_exceeded(Some(summary.name), summary.pwned),
+                },
+                accounts: self
+                    .accounts
+                    .iter()
+                    .filter(|account| account.profile.as_deref() == Some(summary.name))
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+
+        self.summary.max_pwned = limits.of(None);
+        self.summary.exceeded = limits.is_exceeded(None, self.summary.pwned);
+        self
+    }
+
+    /// Events in the order of the NDJSON output
+    pub fn events(&self) -> impl Iterator<Item = Event<'_>> {
+        let start = Event::Start {
+            corpora: self.corpora,
+            accounts: self.summary.checked,
+        };
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|account| Event::Account { account });
+        let inputs = self.inputs.iter().map(|input| Event::Input {
+            name: &input.name,
+            summary: &input.summary,
+        });
+        let finish = Event::Finish {
+            summary: &self.summary,
+        };
+
+        std::iter::once(start)
+            .chain(accounts)
+            .chain(inputs)
+            .chain(std::iter::once(finish))
+    }
+
+    pub fn write_json(&self, mut writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &Versioned::new(self))?;
+        writeln!(writer)
+    }
+
+    /// JSON document without whitespace and with sorted keys - the form that is hashed and signed,
+    /// so the formatting of the stored report doesn't matter
+    #[cfg(feature = "crypto")]
+    pub fn canonical_json(&self) -> serde_json::Result<Vec<u8>> {
+        Ok(canonical(&serde_json::to_value(Versioned::new(self))?))
+    }
+
+    /// Write one event per line
+    pub fn write_ndjson(&self, mut writer: impl Write) -> io::Result<()> {
+        for event in self.events() {
+            write_event(&mut writer, &event)?;
+        }
+
+        writer.flush()
+    }
+}
+
+/// Write a single NDJSON line
+pub fn write_event(mut writer: impl Write, event: &Event<'_>) -> io::Result<()> {
+    serde_json::to_writer(&mut writer, &Versioned::new(event))?;
+    writeln!(writer)
+}
+
+/// Canonical form of a stored JSON report
+#[cfg(feature = "crypto")]
+pub fn canonicalize(document: &[u8]) -> serde_json::Result<Vec<u8>> {
+    Ok(canonical(&serde_json::from_slice(document)?))
+}
+
+#[cfg(feature = "crypto")]
+fn canonical(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+/// The keys are sorted explicitly, because the map order depends on the features of serde_json
+#[cfg(feature = "crypto")]
+fn write_canonical(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Array(items) => {
+            out.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(b',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(b']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+
+            out.push(b'{');
+            for (index, (key, item)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(b',');
+                }
+                // unwrap is safe, because writing into memory cannot fail
+                serde_json::to_writer(&mut *out, key).unwrap();
+                out.push(b':');
+                write_canonical(item, out);
+            }
+            out.push(b'}');
+        }
+        // unwrap is safe, because writing into memory cannot fail
+        scalar => serde_json::to_writer(&mut *out, scalar).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        collect::SavedHash,
+        find::Finding,
+        normalize::UnicodeForm,
+        profiles,
+        report::{self, Corpus},
+    };
+
+    /// Minimal validator for the keywords used in our schema
+    fn validate(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return validate(root, &root["$defs"][name], value, path);
+        }
+
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                return Err(format!("{}: expected {} got {}", path, expected, value));
+            }
+        }
+
+        if let Some(variants) = schema["enum"].as_array() {
+            if !variants.contains(value) {
+                return Err(format!("{}: {} is not one of {:?}", path, value, variants));
+            }
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types: Vec<_> = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                other => other.as_str().into_iter().collect(),
+            };
+
+            let matches = types.iter().any(|kind| match *kind {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "integer" => value.is_u64() || value.is_i64(),
+                "boolean" => value.is_boolean(),
+                "null" => value.is_null(),
+                _ => false,
+            });
+            if !matches {
+                return Err(format!("{}: {} is not of type {:?}", path, value, types));
+            }
+        }
+
+        if let (Some(minimum), Some(number)) = (schema["minimum"].as_f64(), value.as_f64()) {
+            if number < minimum {
+                return Err(format!("{}: {} is below {}", path, number, minimum));
+            }
+        }
+
+        if let Some(variants) = schema["oneOf"].as_array() {
+            let valid = variants
+                .iter()
+                .filter(|variant| validate(root, variant, value, path).is_ok())
+                .count();
+            if valid != 1 {
+                return Err(format!("{}: matches {} variants", path, valid));
+            }
+        }
+
+        if let Some(object) = value.as_object() {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                // unwrap is safe, because required only contains strings
+                if !object.contains_key(required.as_str().unwrap()) {
+                    return Err(format!("{}: missing {}", path, required));
+                }
+            }
+
+            for (key, field) in object {
+                let field_path = format!("{}/{}", path, key);
+                match schema["properties"].get(key) {
+                    Some(property) => validate(root, property, field, &field_path)?,
+                    None if schema["additionalProperties"] == Value::Bool(false) => {
+                        return Err(format!("{}: unknown property", field_path));
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (index, item) in array.iter().enumerate() {
+                validate(root, items, item, &format!("{}/{}", path, index))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn schema() -> Value {
+        serde_json::from_str(SCHEMA).unwrap()
+    }
+
+    fn assert_valid(schema: &Value, value: &Value) {
+        if let Err(err) = validate(schema, schema, value, "") {
+            panic!("{} in {}", err, value);
+        }
+    }
+
+    fn sample(show_clean: bool, pseudonyms: &mut Pseudonymizer) -> Vec<u8> {
+        let mut firefox = SavedHash::new("https://example.org/", "admin", [2; 20]);
+        firefox.times.changed = Some(UNIX_EPOCH + Duration::from_secs(1_609_459_200));
+        let hashes = [
+            SavedHash::new("https://example.com/", "user", [1; 20]),
+            firefox,
+        ];
+        let results = [
+            (
+                Corpus {
+                    name: "hibp.txt",
+                    snapshot: Some(UNIX_EPOCH + Duration::from_secs(1_714_521_600)),
+                    form: None,
+                    newly_pwned: false,
+                },
+                vec![Finding {
+                    index: 0,
+                    count: Some(42),
+                }],
+            ),
+            (
+                Corpus {
+                    name: "internal.txt",
+                    snapshot: None,
+                    form: None,
+                    newly_pwned: false,
+                },
+                vec![Finding {
+                    index: 0,
+                    count: None,
+                }],
+            ),
+            (
+                Corpus {
+                    name: "hibp.txt",
+                    snapshot: None,
+                    form: Some(UnicodeForm::Nfd),
+                    newly_pwned: false,
+                },
+                vec![Finding {
+                    index: 0,
+                    count: Some(3),
+                }],
+            ),
+        ];
+
+        let reports = report::merge(&hashes, &results, show_clean);
+        let corpora = ["hibp.txt", "internal.txt"];
+        let report = StructuredReport::new(&corpora, &reports, hashes.len(), true, pseudonyms)
+            .with_estimate(Some(Estimate::new(20, hashes.len(), 1)))
+            .with_network(Some(Footprint {
+                ranges: 2,
+                cached: 0,
+                requests: 3,
+                hash_chars: 5,
+            }))
+            .with_bytes_scanned(Some(1024));
+
+        let mut output = Vec::new();
+        report.write_json(&mut output).unwrap();
+        report.write_ndjson(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_unchecked_valid() {
+        let report = StructuredReport::new(&[], &[], 0, false, &mut Pseudonymizer::disabled())
+            .with_unchecked(3);
+        let mut output = Vec::new();
+        report.write_json(&mut output).unwrap();
+
+        let document: Value = serde_json::from_slice(&output).unwrap();
+        assert_valid(&schema(), &document);
+        assert_eq!(document["summary"]["checked"], 0);
+        assert_eq!(document["summary"]["unchecked"], 3);
+        assert_eq!(document["summary"]["complete"], false);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let schema = schema();
+        assert_eq!(schema["$defs"]["schema_version"]["const"], SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_json_valid() {
+        let schema = schema();
+        let output = sample(true, &mut Pseudonymizer::disabled());
+        let mut values = serde_json::Deserializer::from_slice(&output).into_iter::<Value>();
+
+        // unwrap is safe, because the document is written first
+        let document = values.next().unwrap().unwrap();
+        assert_valid(&schema, &document);
+        assert_eq!(document["summary"]["pwned"], 1);
+        assert_eq!(document["summary"]["clean"], 1);
+        assert_eq!(document["summary"]["estimate"]["population"], 20);
+        assert_eq!(document["summary"].get("unchecked"), None);
+        assert_eq!(document["summary"]["network"]["hash_prefix_length"], 5);
+        assert_eq!(document["summary"]["network"]["full_hashes_sent"], false);
+        assert_eq!(
+            document["summary"]["network"]["attribution"],
+            HIBP_ATTRIBUTION
+        );
+        assert_eq!(
+            document["accounts"][0],
+            json!({
+                "url": "https://example.com/",
+                "username": "user",
+                "pwned": true,
+                "count": 42,
+                "corpora": ["hibp.txt", "internal.txt"],
+                "tags": [],
+                "matches": [
+                    { "corpus": "hibp.txt", "snapshot": "2024-05-01T00:00:00Z", "count": 42 },
+                    { "corpus": "internal.txt", "snapshot": null, "count": null },
+                    { "corpus": "hibp.txt", "snapshot": null, "count": 3, "form": "NFD" },
+                ],
+            })
+        );
+        assert_eq!(document["accounts"][1]["count"], Value::Null);
+        assert_eq!(document["accounts"][1]["changed"], "2021-01-01T00:00:00Z");
+        assert_eq!(document["accounts"][1].get("created"), None);
+    }
+
+    #[test]
+    fn test_ndjson_valid() {
+        let schema = json!({ "$ref": "#/$defs/event", "$defs": schema()["$defs"] });
+        let output = sample(false, &mut Pseudonymizer::disabled());
+
+        // skip the pretty printed document
+        let text = String::from_utf8(output).unwrap();
+        let ndjson = &text[text.find("\n}\n").unwrap() + 3..];
+        let events: Vec<Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 3);
+        for event in &events {
+            assert_valid(&schema, event);
+        }
+
+        assert_eq!(events[0]["event"], "start");
+        assert_eq!(events[1]["account"]["username"], "user");
+        assert_eq!(events[2]["summary"]["complete"], true);
+    }
+
+    #[test]
+    fn test_inputs_valid() {
+        let schema = schema();
+        let mut hashes = Vec::new();
+        for (profile, url, hash) in &[
+            ("alice", "https://mail.example/", 1),
+            ("alice", "https://bank.example/", 2),
+            ("bob", "https://shop.example/", 1),
+        ] {
+            let mut saved = SavedHash::new(url, "user", [*hash; 20]);
+            saved.set_profile(profile);
+            hashes.push(saved);
+        }
+
+        let corpus = Corpus {
+            name: "hibp.txt",
+            snapshot: None,
+            form: None,
+            newly_pwned: false,
+        };
+        let findings = [0, 2]
+            .iter()
+            .map(|&index| Finding {
+                index,
+                count: Some(7),
+            })
+            .collect();
+        let reports = report::merge(&hashes, &[(corpus, findings)], true);
+
+        let profiles = ["alice".to_string(), "bob".to_string()];
+        let summaries = profiles::summarize(&profiles, &hashes, &reports);
+        let limits = PwnedLimits::new(vec![(None, 2), (Some("alice"), 0)]);
+        let corpora = ["hibp.txt"];
+        let report = StructuredReport::new(
+            &corpora,
+            &reports,
+            hashes.len(),
+            true,
+            &mut Pseudonymizer::disabled(),
+        )
+        .with_inputs(&summaries, &limits);
+
+        let mut output = Vec::new();
+        report.write_json(&mut output).unwrap();
+        let document: Value = serde_json::from_slice(&output).unwrap();
+        assert_valid(&schema, &document);
+
+        // the total is within the limit, but alice isn't
+        assert_eq!(document["summary"]["pwned"], 2);
+        assert_eq!(document["summary"]["max_pwned"], 2);
+        assert_eq!(document["summary"].get("exceeded"), None);
+        assert_eq!(document["accounts"].as_array().unwrap().len(), 3);
+
+        let alice = &document["inputs"][0];
+        assert_eq!(alice["name"], "alice");
+        assert_eq!(
+            alice["summary"],
+            json!({ "checked": 2, "pwned": 1, "clean": 1, "max_pwned": 0, "exceeded": true })
+        );
+        assert_eq!(alice["accounts"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            document["inputs"][1]["summary"],
+            json!({ "checked": 1, "pwned": 1, "clean": 0 })
+        );
+
+        let event_schema = json!({ "$ref": "#/$defs/event", "$defs": schema["$defs"] });
+        let mut output = Vec::new();
+        report.write_ndjson(&mut output).unwrap();
+        let events: Vec<Value> = output
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        for event in &events {
+            assert_valid(&event_schema, event);
+        }
+
+        // start, three accounts, two inputs and the finish
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[4]["event"], "input");
+        assert_eq!(events[5]["name"], "bob");
+        assert_eq!(events[6]["summary"]["max_pwned"], 2);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_canonical() {
+        let pretty = "{\n  \"b\": [1, {\"y\": null, \"x\": \"\u{e4}\"}],\n  \"a\": 0.5\n}\n";
+        let reordered = r#"{"a":0.5, "b":[1,{"x":"\u00e4","y":null}]}"#;
+
+        let canonical = canonicalize(pretty.as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8(canonical.clone()).unwrap(),
+            "{\"a\":0.5,\"b\":[1,{\"x\":\"\u{e4}\",\"y\":null}]}"
+        );
+        assert_eq!(canonicalize(reordered.as_bytes()).unwrap(), canonical);
+    }
+
+    #[test]
+    fn test_validator_rejects() {
+        let schema = schema();
+        let invalid = json!({
+            "schema_version": 2,
+            "corpora": [],
+            "accounts": [],
+            "summary": { "checked": 0, "pwned": 0, "clean": 0, "complete": true },
+        });
+        assert!(validate(&schema, &schema, &invalid, "").is_err());
+
+        let unknown = json!({
+            "schema_version": 1,
+            "corpora": [],
+            "accounts": [],
+            "summary": { "checked": 0, "pwned": 0, "clean": 0, "complete": true, "extra": 1 },
+        });
+        assert!(validate(&schema, &schema, &unknown, "").is_err());
+    }
+}
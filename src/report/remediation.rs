@@ -0,0 +1,203 @@
+use std::{io::Write, str::FromStr};
+
+use crate::{collect::SavedHash, report::AccountReport};
+
+/// Folder of the imported entries - the original folder is kept below it
+const ROTATE_FOLDER: &str = "Rotate me";
+
+/// Tag or custom field of the imported entries
+const ROTATION_TAG: &str = "needs_rotation";
+
+/// CSV import format of the password manager
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemediationFormat {
+    /// Bitwarden CSV with a custom field, because it has no tags
+    Bitwarden,
+    /// KeePassXC CSV with the group path and tags columns
+    KeePassXc,
+}
+
+impl RemediationFormat {
+    pub const NAMES: [&'static str; 2] = ["bitwarden", "keepassxc"];
+
+    fn header(self) -> &'static [&'static str] {
+        match self {
+            RemediationFormat::Bitwarden => &[
+                "folder",
+                "favorite",
+                "type",
+                "name",
+                "notes",
+                "fields",
+                "reprompt",
+                "login_uri",
+                "login_username",
+                "login_password",
+                "login_totp",
+            ],
+            RemediationFormat::KeePassXc => &[
+                "Group", "Title", "Username", "Password", "URL", "Notes", "Tags",
+            ],
+        }
+    }
+
+    fn record(self, entry: &SavedHash, notes: &str) -> Vec<String> {
+        // the passwords are never kept after hashing - the entries are reminders next to the
+        // original ones
+        let title = super::site(entry.url()).unwrap_or_else(|| entry.url().to_string());
+        match self {
+            RemediationFormat::Bitwarden => vec![
+                folder(entry),
+                String::new(),
+                "login".to_string(),
+                title,
+                notes.to_string(),
+                format!("{}: true", ROTATION_TAG),
+                "0".to_string(),
+                entry.url().to_string(),
+                entry.username().to_string(),
+                String::new(),
+                String::new(),
+            ],
+            RemediationFormat::KeePassXc => vec![
+                format!("Root/{}", folder(entry)),
+                title,
+                entry.username().to_string(),
+                String::new(),
+                entry.url().to_string(),
+                notes.to_string(),
+                ROTATION_TAG.to_string(),
+            ],
+        }
+    }
+}
+
+impl FromStr for RemediationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitwarden" => Ok(RemediationFormat::Bitwarden),
+            "keepassxc" => Ok(RemediationFormat::KeePassXc),
+            _ => Err(format!("Unknown remediation format {}", s)),
+        }
+    }
+}
+
+/// Folder below the rotation folder - the first folder of the entry is kept to find it again
+fn folder(entry: &SavedHash) -> String {
+    // KeePass group paths start with the root group
+    match entry.tags().first() {
+        Some(tag) => format!(
+            "{}/{}",
+            ROTATE_FOLDER,
+            tag.strip_prefix("Root/").unwrap_or(tag)
+        ),
+        None => ROTATE_FOLDER.to_string(),
+    }
+}
+
+/// Write the pwned entries including the collapsed variants as CSV that could be imported into the
+/// password manager. Returns the number of written entries.
+pub fn write_remediation(
+    writer: impl Write,
+    reports: &[AccountReport<'_>],
+    format: RemediationFormat,
+) -> Result<usize, csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(format.header())?;
+
+    let mut written = 0;
+    for report in reports.iter().filter(|report| !report.is_clean()) {
+        let notes = match report.max_count {
+            Some(count) => format!("Pwned {}x times - change the password", count),
+            None => "Pwned - change the password".to_string(),
+        };
+
+        // each variant is a separate entry in the manager
+        for entry in std::iter::once(report.saved).chain(report.variants.iter().copied()) {
+            writer.write_record(format.record(entry, &notes))?;
+            written += 1;
+        }
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::report::{Corpus, Provenance};
+
+    use super::*;
+
+    const CORPUS: Corpus<'static> = Corpus {
+        name: "corpus.txt",
+        snapshot: None,
+        form: None,
+        newly_pwned: false,
+    };
+
+    fn pwned<'a>(saved: &'a SavedHash, variants: Vec<&'a SavedHash>) -> AccountReport<'a> {
+        AccountReport {
+            saved,
+            matches: vec![Provenance {
+                corpus: CORPUS,
+                count: Some(42),
+            }],
+            max_count: Some(42),
+            variants,
+        }
+    }
+
+    fn export(reports: &[AccountReport<'_>], format: RemediationFormat) -> String {
+        let mut output = Vec::new();
+        write_remediation(&mut output, reports, format).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_bitwarden() {
+        let saved = SavedHash::new("https://login.example.com/", "alice", [1; 20])
+            .with_tags(vec!["Work".to_string()]);
+        let variant = SavedHash::new("https://www.example.com/", "alice", [1; 20]);
+        let clean = SavedHash::new("https://example.org/", "bob", [2; 20]);
+        let reports = [
+            pwned(&saved, vec![&variant]),
+            AccountReport {
+                saved: &clean,
+                matches: Vec::new(),
+                max_count: None,
+                variants: Vec::new(),
+            },
+        ];
+
+        let csv = export(&reports, RemediationFormat::Bitwarden);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp");
+        assert_eq!(lines[1], "Rotate me/Work,,login,example.com,Pwned 42x times - change the password,needs_rotation: true,0,https://login.example.com/,alice,,");
+        assert_eq!(lines[2], "Rotate me,,login,example.com,Pwned 42x times - change the password,needs_rotation: true,0,https://www.example.com/,alice,,");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_keepassxc() {
+        let saved = SavedHash::new("https://example.com/", "user,name", [1; 20])
+            .with_tags(vec!["Root/Archive".to_string()]);
+        let csv = export(&[pwned(&saved, Vec::new())], RemediationFormat::KeePassXc);
+        assert_eq!(
+            csv,
+            "Group,Title,Username,Password,URL,Notes,Tags\n\
+             Root/Rotate me/Archive,example.com,\"user,name\",,https://example.com/,Pwned 42x times - change the password,needs_rotation\n"
+        );
+    }
+
+    #[test]
+    fn test_format_parse() {
+        for name in &RemediationFormat::NAMES {
+            assert!(name.parse::<RemediationFormat>().is_ok());
+        }
+
+        assert!("lastpass".parse::<RemediationFormat>().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use data_encoding::{BASE64, HEXLOWER};
+use ring::{
+    digest::{digest, SHA256},
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+
+/// Signature algorithm of minisign for Ed25519 over the message itself
+const ALGORITHM: &[u8; 2] = b"Ed";
+
+const KEY_ID_LENGTH: usize = 8;
+const SIGNATURE_LENGTH: usize = 64;
+
+const UNTRUSTED_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+
+#[derive(Debug)]
+pub enum SignatureError {
+    /// Not in the minisign signature format
+    Malformed,
+    UnsupportedAlgorithm,
+    /// Signature was created by another key
+    KeyMismatch,
+    InvalidSignature,
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => write!(f, "Malformed signature file"),
+            SignatureError::UnsupportedAlgorithm => write!(f, "Unsupported signature algorithm"),
+            SignatureError::KeyMismatch => write!(f, "Signed by a different key"),
+            SignatureError::InvalidSignature => write!(f, "Signature doesn't match the report"),
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+/// Detached signature of the canonical report in the minisign format. The trusted comment records
+/// the signing time and the SHA-256 of the canonical report.
+pub fn sign(canonical: &[u8], key: &Ed25519KeyPair) -> String {
+    let signature = key.sign(canonical);
+
+    let mut signature_line = ALGORITHM.to_vec();
+    signature_line.extend_from_slice(&key_id(key.public_key().as_ref()));
+    signature_line.extend_from_slice(signature.as_ref());
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let trusted_comment = format!("timestamp:{}\tsha256:{}", timestamp, sha256_hex(canonical));
+
+    // the global signature covers the comment too, so it cannot be swapped
+    let mut global = signature.as_ref().to_vec();
+    global.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = key.sign(&global);
+
+    format!(
+        "{}signature from pwned-check report key\n{}\n{}{}\n{}\n",
+        UNTRUSTED_PREFIX,
+        BASE64.encode(&signature_line),
+        TRUSTED_PREFIX,
+        trusted_comment,
+        BASE64.encode(global_signature.as_ref())
+    )
+}
+
+/// Verify the signature file of the canonical report and return the trusted comment
+pub fn verify(
+    canonical: &[u8],
+    signature_file: &str,
+    public_key: &[u8],
+) -> Result<String, SignatureError> {
+    let mut lines = signature_file.lines();
+    let (untrusted, signature_line, trusted, global_line) =
+        match (lines.next(), lines.next(), lines.next(), lines.next()) {
+            (Some(untrusted), Some(signature), Some(trusted), Some(global)) => {
+                (untrusted, signature, trusted, global)
+            }
+            _ => return Err(SignatureError::Malformed),
+        };
+
+    let trusted_comment = trusted
+        .strip_prefix(TRUSTED_PREFIX)
+        .filter(|_| untrusted.starts_with(UNTRUSTED_PREFIX))
+        .ok_or(SignatureError::Malformed)?;
+
+    let signature_line = decode_base64(signature_line)?;
+    if signature_line.len() != ALGORITHM.len() + KEY_ID_LENGTH + SIGNATURE_LENGTH {
+        return Err(SignatureError::Malformed);
+    }
+
+    let (algorithm, rest) = signature_line.split_at(ALGORITHM.len());
+    let (id, signature) = rest.split_at(KEY_ID_LENGTH);
+    if algorithm != ALGORITHM {
+        return Err(SignatureError::UnsupportedAlgorithm);
+    }
+
+    if id != key_id(public_key) {
+        return Err(SignatureError::KeyMismatch);
+    }
+
+    let key = UnparsedPublicKey::new(&ED25519, public_key);
+    key.verify(canonical, signature)
+        .map_err(|_| SignatureError::InvalidSignature)?;
+
+    let mut global = signature.to_vec();
+    global.extend_from_slice(trusted_comment.as_bytes());
+    key.verify(&global, &decode_base64(global_line)?)
+        .map_err(|_| SignatureError::InvalidSignature)?;
+
+    Ok(trusted_comment.to_string())
+}
+
+/// Hex of the SHA-256 of the canonical report - equal reports have the same digest
+fn sha256_hex(canonical: &[u8]) -> String {
+    HEXLOWER.encode(digest(&SHA256, canonical).as_ref())
+}
+
+/// Key number of minisign - derived from the public key, because the key files have no id
+fn key_id(public_key: &[u8]) -> [u8; KEY_ID_LENGTH] {
+    let mut id = [0; KEY_ID_LENGTH];
+    id.copy_from_slice(&digest(&SHA256, public_key).as_ref()[..KEY_ID_LENGTH]);
+    id
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, SignatureError> {
+    BASE64
+        .decode(data.trim().as_bytes())
+        .map_err(|_| SignatureError::Malformed)
+}
+
+#[cfg(test)]
+mod test {
+    use ring::rand::SystemRandom;
+
+    use super::*;
+
+    fn generate_key() -> Ed25519KeyPair {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_signed() {
+        let key = generate_key();
+        let report = br#"{"accounts":[],"schema_version":1}"#;
+        let signature = sign(report, &key);
+        assert!(signature.starts_with("untrusted comment: "));
+
+        let comment = verify(report, &signature, key.public_key().as_ref()).unwrap();
+        assert!(comment.starts_with("timestamp:"));
+        assert!(comment.ends_with(&format!("sha256:{}", sha256_hex(report))));
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = generate_key();
+        let public_key = key.public_key().as_ref();
+        let report = br#"{"accounts":[],"schema_version":1}"#;
+        let signature = sign(report, &key);
+
+        let modified = br#"{"accounts":[],"schema_version":2}"#;
+        let result = verify(modified, &signature, public_key);
+        assert_matches!(result, Err(SignatureError::InvalidSignature));
+
+        let comment = signature.replace("timestamp:", "timestamp:1");
+        let result = verify(report, &comment, public_key);
+        assert_matches!(result, Err(SignatureError::InvalidSignature));
+
+        let other_key = generate_key();
+        let result = verify(report, &signature, other_key.public_key().as_ref());
+        assert_matches!(result, Err(SignatureError::KeyMismatch));
+
+        let result = verify(report, "untrusted comment: empty\n", public_key);
+        assert_matches!(result, Err(SignatureError::Malformed));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, BufWriter},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use log::debug;
+
+use crate::report::StructuredReport;
+
+#[derive(Debug)]
+pub enum ReporterError {
+    Io(io::Error),
+    /// The reporter exited with a failure
+    Exit(ExitStatus),
+}
+
+impl Display for ReporterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReporterError::Io(err) => write!(f, "{}", err),
+            ReporterError::Exit(status) => write!(f, "{}", status),
+        }
+    }
+}
+
+impl Error for ReporterError {}
+
+impl From<io::Error> for ReporterError {
+    fn from(err: io::Error) -> Self {
+        ReporterError::Io(err)
+    }
+}
+
+/// Run the external reporter using the shell and stream the NDJSON events of the report to its
+/// standard input. The input is closed after the finish event and the reporter is expected to
+/// exit successfully. Its output is forwarded to our standard error, so it never mixes with a
+/// structured report.
+pub fn run_reporter(command: &str, report: &StructuredReport<'_>) -> Result<(), ReporterError> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(io::stderr())
+        .spawn()?;
+
+    // unwrap is safe, because the input is piped
+    let stdin = child.stdin.take().unwrap();
+    let written = report.write_ndjson(BufWriter::new(stdin));
+
+    // the input is already closed here, so the reporter could see the end of the events
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(ReporterError::Exit(status));
+    }
+
+    match written {
+        // the reporter is free to stop reading if it doesn't need the remaining events
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+            debug!("Reporter {} stopped reading the events", command);
+            Ok(())
+        }
+        written => Ok(written?),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::{
+        collect::SavedHash,
+        find::Finding,
+        pseudonym::Pseudonymizer,
+        report::{self, Corpus},
+    };
+
+    fn with_report(test: impl FnOnce(&StructuredReport<'_>)) {
+        let hashes = [SavedHash::new("https://example.com/", "user", [1; 20])];
+        let results = [(
+            Corpus {
+                name: "hibp.txt",
+                snapshot: None,
+                form: None,
+                newly_pwned: false,
+            },
+            vec![Finding {
+                index: 0,
+                count: Some(3),
+            }],
+        )];
+
+        let reports = report::merge(&hashes, &results, false);
+        let corpora = ["hibp.txt"];
+        let mut pseudonyms = Pseudonymizer::disabled();
+        test(&StructuredReport::new(
+            &corpora,
+            &reports,
+            hashes.len(),
+            true,
+            &mut pseudonyms,
+        ));
+    }
+
+    #[test]
+    fn test_events_received() {
+        let output = env::temp_dir().join(format!("pwned-check-reporter-{}", std::process::id()));
+        with_report(|report| {
+            let command = format!("cat > '{}'", output.display());
+            run_reporter(&command, report).unwrap();
+        });
+
+        let received = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        let events: Vec<_> = received.lines().collect();
+        assert_eq!(events.len(), 3);
+        assert!(events[1].contains(r#""event":"account""#));
+    }
+
+    #[test]
+    fn test_failed_reporter() {
+        with_report(|report| {
+            let result = run_reporter("exit 3", report);
+            assert!(matches!(result, Err(ReporterError::Exit(status)) if status.code() == Some(3)));
+        });
+    }
+
+    #[test]
+    fn test_stopped_reading() {
+        with_report(|report| {
+            assert!(run_reporter("head -n 1 > /dev/null", report).is_ok());
+        });
+    }
+}
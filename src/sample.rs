@@ -0,0 +1,155 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use log::info;
+use serde::Serialize;
+
+/// Z-score of the 95% confidence interval
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// Random part of the saved accounts for a quick estimate. Only the scan takes hours on the huge
+/// lists, so all passwords are hashed and the sample is drawn afterwards with an exact size.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    /// Part of the accounts between 0 (exclusive) and 100
+    percent: f64,
+    seed: u64,
+}
+
+impl Sample {
+    /// Different sample on each run
+    pub fn random(percent: f64) -> Self {
+        // the sample doesn't need to be unpredictable - the random keys of the std hash maps are
+        // enough and don't require a crypto library
+        let seed = RandomState::new().build_hasher().finish();
+        Sample::new(percent, seed)
+    }
+
+    pub fn new(percent: f64, seed: u64) -> Self {
+        Sample { percent, seed }
+    }
+
+    /// Number of sampled entries - at least one, so small vaults still check something
+    fn size(&self, population: usize) -> usize {
+        let size = (population as f64 * self.percent / 100.0).ceil() as usize;
+        size.clamp(population.min(1), population)
+    }
+
+    /// Randomly select the sample without duplicates - the order of the result is random too
+    pub fn select<T>(&self, mut entries: Vec<T>) -> Vec<T> {
+        let size = self.size(entries.len());
+
+        // partial Fisher-Yates shuffle - the first entries become the sample
+        let mut state = self.seed;
+        for index in 0..size {
+            let remaining = (entries.len() - index) as u64;
+            let pick = index + (split_mix(&mut state) % remaining) as usize;
+            entries.swap(index, pick);
+        }
+
+        entries.truncate(size);
+        entries
+    }
+}
+
+/// Small and fast generator for the shuffle: https://prng.di.unimi.it/splitmix64.c
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Pwned accounts of the whole vault extrapolated from the sample
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Estimate {
+    /// Accounts before the sampling
+    pub population: usize,
+    pub sampled: usize,
+    /// Pwned accounts of the sample
+    pub pwned: usize,
+    /// 95% confidence interval of the pwned accounts in the vault
+    pub pwned_low: usize,
+    pub pwned_high: usize,
+}
+
+impl Estimate {
+    /// Wilson score interval - stays within 0 and the population even for a few or no matches.
+    /// It ignores that the sample is drawn without replacement, so the interval is conservative.
+    pub fn new(population: usize, sampled: usize, pwned: usize) -> Self {
+        let (low, high) = if sampled == 0 {
+            (0.0, 1.0)
+        } else {
+            let n = sampled as f64;
+            let ratio = pwned as f64 / n;
+            let z2 = CONFIDENCE_Z * CONFIDENCE_Z;
+            let center = ratio + z2 / (2.0 * n);
+            let margin = CONFIDENCE_Z * (ratio * (1.0 - ratio) / n + z2 / (4.0 * n * n)).sqrt();
+            let denominator = 1.0 + z2 / n;
+            (
+                (center - margin) / denominator,
+                (center + margin) / denominator,
+            )
+        };
+
+        // the accounts of the sample are known exactly
+        let unsampled = (population - sampled) as f64;
+        Estimate {
+            population,
+            sampled,
+            pwned,
+            pwned_low: pwned + (low.max(0.0) * unsampled).floor() as usize,
+            pwned_high: pwned + (high.min(1.0) * unsampled).ceil() as usize,
+        }
+    }
+
+    pub fn print(&self) {
+        info!(
+            "ESTIMATE from a sample of {} of {} accounts: {} pwned in the sample - between {} and {} pwned accounts in the vault (95% confidence)",
+            self.sampled, self.population, self.pwned, self.pwned_low, self.pwned_high
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let entries: Vec<usize> = (0..1000).collect();
+        let sample = Sample::new(10.0, 42);
+
+        let mut selected = sample.select(entries.clone());
+        assert_eq!(selected.len(), 100);
+        assert_eq!(selected, sample.select(entries.clone()));
+        assert_ne!(selected, Sample::new(10.0, 43).select(entries));
+
+        selected.sort_unstable();
+        selected.dedup();
+        assert_eq!(selected.len(), 100);
+
+        assert_eq!(sample.select(vec![1, 2, 3]).len(), 1);
+        assert!(sample.select(Vec::<u8>::new()).is_empty());
+        assert_eq!(Sample::new(100.0, 1).select(vec![1, 2, 3]).len(), 3);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let estimate = Estimate::new(10_000, 1000, 100);
+        assert!(estimate.pwned_low < 1000 && estimate.pwned_high > 1000);
+        assert!(estimate.pwned_low > 700 && estimate.pwned_high < 1300);
+
+        // a complete sample is exact
+        let complete = Estimate::new(500, 500, 20);
+        assert_eq!((complete.pwned_low, complete.pwned_high), (20, 20));
+
+        // no matches still leave an upper bound
+        let none = Estimate::new(10_000, 100, 0);
+        assert_eq!(none.pwned_low, 0);
+        assert!(none.pwned_high > 0 && none.pwned_high < 10_000);
+    }
+}
@@ -0,0 +1,1168 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use data_encoding::HEXUPPER;
+use log::{debug, info, warn};
+use ring::rand::{SecureRandom, SystemRandom};
+use secstr::SecStr;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    find::{self, Finding, HashSource, RangeEntry, RangePrefix},
+    metrics::LatencyHistogram,
+    redact::{self, REDACTED},
+    Sha1Hash,
+};
+
+pub use access::{RateLimiter, Tokens};
+
+mod access;
+mod policy;
+
+/// Largest accepted request body - fits a batch of the maximum size
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// Hashes of a single `POST /check-many` request
+const MAX_BATCH_LEN: usize = 10_000;
+
+/// Padded range responses have a random number of entries within these bounds like the HIBP API
+const PADDED_RANGE_LEN: (u16, u16) = (800, 1000);
+
+/// Largest accepted request line or header
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+const MAX_HEADERS: usize = 64;
+
+/// Idle keep-alive connections are closed afterwards - each one occupies a worker meanwhile, so the
+/// timeout is short. It applies to every read, so slow clients can still send larger bodies.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parsed HTTP/1.1 request
+#[derive(PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Names are lowercase
+    pub headers: Vec<(String, String)>,
+    /// Kept in a `SecStr`, because it could contain a password
+    pub body: SecStr,
+    /// The client wants to send further requests over the same connection
+    pub keep_alive: bool,
+}
+
+impl fmt::Debug for Request {
+    /// The body and the credentials of the client are redacted
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let headers: Vec<_> = self
+            .headers
+            .iter()
+            .map(|(name, value)| match name.as_str() {
+                "authorization" | "cookie" | "proxy-authorization" => (name.as_str(), REDACTED),
+                _ => (name.as_str(), value.as_str()),
+            })
+            .collect();
+
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("headers", &headers)
+            .field(
+                "body",
+                &format_args!("{} ({} bytes)", REDACTED, self.body.unsecure().len()),
+            )
+            .field("keep_alive", &self.keep_alive)
+            .finish()
+    }
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    Io(io::Error),
+    /// The connection is closed after answering with the status
+    Invalid(u16, &'static str),
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Io(err) => write!(f, "{}", err),
+            RequestError::Invalid(status, message) => write!(f, "{} {}", status, message),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+impl From<io::Error> for RequestError {
+    fn from(err: io::Error) -> Self {
+        RequestError::Io(err)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// Additional headers (ex: Retry-After)
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &impl Serialize) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            // unwrap is safe, because the responses only contain strings and numbers
+            body: serde_json::to_string(body).unwrap(),
+        }
+    }
+
+    pub fn text(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: "text/plain",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Response::json(status, &ErrorResponse { error: message })
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+}
+
+/// Body of `POST /check` - the prefix keeps the complete hash on the client (k-anonymity)
+#[derive(Deserialize)]
+struct CheckRequest {
+    sha1: Option<String>,
+    prefix: Option<String>,
+}
+
+/// Body of `POST /check-many` for audit jobs
+#[derive(Deserialize)]
+struct CheckManyRequest {
+    sha1: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CheckManyResponse {
+    /// Same order as the request
+    results: Vec<CheckManyResult>,
+}
+
+#[derive(Serialize)]
+struct CheckManyResult {
+    sha1: String,
+    #[serde(flatten)]
+    result: CheckResponse,
+}
+
+#[derive(Serialize)]
+struct CheckResponse {
+    pwned: bool,
+    /// Occurrences in the corpus - none if it's not pwned or the count is unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+}
+
+/// Answer of `GET /metrics` - the latencies only include the lookups without the HTTP handling
+#[derive(Serialize)]
+struct MetricsResponse {
+    lookups: u64,
+    p50_us: u128,
+    p99_us: u128,
+    max_us: u128,
+}
+
+#[derive(Serialize)]
+struct RangeResponse {
+    prefix: String,
+    matches: Vec<RangeMatch>,
+}
+
+#[derive(Serialize)]
+struct RangeMatch {
+    /// Remaining 35 hex characters of the hash
+    suffix: String,
+    count: Option<u64>,
+}
+
+/// Lookups of the HTTP API against the opened corpus
+pub struct Api<'a> {
+    pub source: &'a (dyn HashSource + Sync),
+    pub strict: bool,
+    /// Passwords found more often are denied by the policy webhook
+    pub max_count: u64,
+    pub latency: LatencyHistogram,
+}
+
+impl Api<'_> {
+    pub fn handle(&self, request: &Request) -> Response {
+        let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+
+        if let Some(prefix) = path.strip_prefix("/range/") {
+            return match request.method.as_str() {
+                "GET" => self.hibp_range(prefix, query, request.header("add-padding")),
+                _ => Response::error(405, "Only GET is allowed"),
+            };
+        }
+
+        match (request.method.as_str(), path) {
+            ("GET", "/metrics") => self.metrics(),
+            ("POST", "/check") => self.check(request.body.unsecure()),
+            ("POST", "/check-many") => self.check_many(request.body.unsecure()),
+            ("POST", "/policy") => self.policy(request.body.unsecure()),
+            (_, "/check") | (_, "/check-many") | (_, "/policy") => {
+                Response::error(405, "Only POST is allowed")
+            }
+            _ => Response::error(
+                404,
+                "Unknown path - use POST /check, POST /check-many, POST /policy, GET /range/<prefix> or GET /metrics",
+            ),
+        }
+    }
+
+    fn check(&self, body: &[u8]) -> Response {
+        let request: CheckRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(err) => return Response::error(400, &format!("Invalid request {}", err)),
+        };
+
+        match (request.sha1, request.prefix) {
+            (Some(hex), None) => match find::parse_sha1(&hex) {
+                Some(hash) => match self.lookup_hash(hash) {
+                    Ok(finding) => Response::json(
+                        200,
+                        &CheckResponse {
+                            pwned: finding.is_some(),
+                            count: finding.and_then(|finding| finding.count),
+                        },
+                    ),
+                    Err(err) => lookup_failed(err),
+                },
+                None => Response::error(400, "sha1 requires 40 hex characters"),
+            },
+            (None, Some(prefix)) => match prefix.parse::<RangePrefix>() {
+                Ok(prefix) => self.range(prefix),
+                Err(err) => Response::error(400, &err),
+            },
+            _ => Response::error(400, "Requires either a sha1 or a prefix field"),
+        }
+    }
+
+    fn metrics(&self) -> Response {
+        let summary = self.latency.summary();
+        Response::json(
+            200,
+            &MetricsResponse {
+                lookups: summary.lookups,
+                p50_us: summary.p50.as_micros(),
+                p99_us: summary.p99.as_micros(),
+                max_us: summary.max.as_micros(),
+            },
+        )
+    }
+
+    /// Webhook of identity providers before a password is set
+    fn policy(&self, body: &[u8]) -> Response {
+        let request: policy::PolicyRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            // the message of type errors would quote the password
+            Err(err) => {
+                return Response::error(
+                    400,
+                    &format!("Invalid request {}", redact::json_error(&err)),
+                )
+            }
+        };
+
+        let hash = match request.hash() {
+            Ok(hash) => hash,
+            Err(err) => return Response::error(400, err),
+        };
+
+        match self.lookup_hash(hash) {
+            Ok(finding) => Response::json(
+                200,
+                &policy::decide(finding.map(|finding| finding.count), self.max_count),
+            ),
+            Err(err) => lookup_failed(err),
+        }
+    }
+
+    fn check_many(&self, body: &[u8]) -> Response {
+        let request: CheckManyRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(err) => return Response::error(400, &format!("Invalid request {}", err)),
+        };
+
+        if request.sha1.len() > MAX_BATCH_LEN {
+            return Response::error(
+                413,
+                &format!("Requests are limited to {} hashes", MAX_BATCH_LEN),
+            );
+        }
+
+        let mut hashes = Vec::with_capacity(request.sha1.len());
+        for (position, hex) in request.sha1.iter().enumerate() {
+            match find::parse_sha1(hex) {
+                Some(hash) => hashes.push(hash),
+                None => {
+                    return Response::error(
+                        400,
+                        &format!("sha1 at position {} requires 40 hex characters", position),
+                    )
+                }
+            }
+        }
+
+        match self
+            .latency
+            .time(|| find::lookup_hashes(self.source, &hashes, self.strict))
+        {
+            Ok(findings) => Response::json(
+                200,
+                &CheckManyResponse {
+                    results: request
+                        .sha1
+                        .into_iter()
+                        .zip(findings)
+                        .map(|(sha1, finding)| CheckManyResult {
+                            sha1,
+                            result: CheckResponse {
+                                pwned: finding.is_some(),
+                                count: finding.and_then(|finding| finding.count),
+                            },
+                        })
+                        .collect(),
+                },
+            ),
+            Err(err) => lookup_failed(err),
+        }
+    }
+
+    /// Same format as `https://api.pwnedpasswords.com/range/<prefix>`, so the existing clients
+    /// could use the local corpus: `SUFFIX:COUNT` lines separated by CRLF
+    fn hibp_range(&self, prefix: &str, query: &str, padding: Option<&str>) -> Response {
+        if query.split('&').any(|param| param == "mode=ntlm") {
+            return Response::error(400, "Only SHA-1 ranges are served");
+        }
+
+        let prefix = match prefix.parse::<RangePrefix>() {
+            Ok(prefix) => prefix,
+            Err(err) => return Response::error(400, &err),
+        };
+
+        let entries = match self.find_range(prefix) {
+            Ok(entries) => entries,
+            Err(err) => return lookup_failed(err),
+        };
+
+        let mut lines: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                // HIBP always has a count - lists without it report each hash as seen once
+                (
+                    HEXUPPER.encode(&entry.hash)[RangePrefix::LEN..].to_string(),
+                    entry.count.unwrap_or(1),
+                )
+            })
+            .collect();
+
+        if padding.is_some_and(|value| value.eq_ignore_ascii_case("true")) {
+            if let Err(err) = pad_range(&mut lines) {
+                warn!("Cannot generate the padding {:?}", err);
+                return Response::error(500, "Cannot generate the padding");
+            }
+        }
+
+        let body: Vec<_> = lines
+            .iter()
+            .map(|(suffix, count)| format!("{}:{}", suffix, count))
+            .collect();
+        Response::text(200, body.join("\r\n"))
+    }
+
+    fn lookup_hash(&self, hash: Sha1Hash) -> io::Result<Option<Finding>> {
+        self.latency
+            .time(|| find::lookup_hash(self.source, hash, self.strict))
+    }
+
+    fn find_range(&self, prefix: RangePrefix) -> io::Result<Vec<RangeEntry>> {
+        self.latency
+            .time(|| find::find_range(self.source, prefix, self.strict))
+    }
+
+    fn range(&self, prefix: RangePrefix) -> Response {
+        match self.find_range(prefix) {
+            Ok(entries) => Response::json(
+                200,
+                &RangeResponse {
+                    prefix: prefix.to_string(),
+                    matches: entries
+                        .into_iter()
+                        .map(|entry| RangeMatch {
+                            suffix: HEXUPPER.encode(&entry.hash)[RangePrefix::LEN..].to_string(),
+                            count: entry.count,
+                        })
+                        .collect(),
+                },
+            ),
+            Err(err) => lookup_failed(err),
+        }
+    }
+}
+
+/// Fill the range with random suffixes of count 0 like the `Add-Padding` header of the HIBP API.
+/// Otherwise the size of the encrypted response would reveal the requested range.
+fn pad_range(lines: &mut Vec<(String, u64)>) -> Result<(), ring::error::Unspecified> {
+    let random = SystemRandom::new();
+    let mut len = [0; 2];
+    random.fill(&mut len)?;
+
+    let (min, max) = PADDED_RANGE_LEN;
+    let target = usize::from(min + u16::from_ne_bytes(len) % (max - min + 1));
+    let mut suffix = [0; 18];
+    while lines.len() < target {
+        random.fill(&mut suffix)?;
+        lines.push((HEXUPPER.encode(&suffix)[1..].to_string(), 0));
+    }
+
+    lines.sort_unstable();
+    Ok(())
+}
+
+fn lookup_failed(err: io::Error) -> Response {
+    warn!("Lookup failed {}", err);
+    Response::error(500, "Lookup failed")
+}
+
+/// API with the access control for shared servers
+pub struct Server<'a> {
+    pub api: Api<'a>,
+    /// Requests without one of the tokens are rejected if it's set
+    pub tokens: Option<Tokens>,
+    /// Limit per token name or otherwise per client address
+    pub rate_limit: Option<RateLimiter>,
+}
+
+impl Server<'_> {
+    /// Response with the name of the authenticated token
+    fn respond(&self, request: &Request, client: &str) -> (Response, Option<&str>) {
+        let user = match &self.tokens {
+            Some(tokens) => {
+                let token = request
+                    .header("authorization")
+                    .and_then(|value| value.strip_prefix("Bearer "));
+                match token.and_then(|token| tokens.authenticate(token.trim())) {
+                    Some(name) => Some(name),
+                    None => {
+                        // failed attempts count against the address, so tokens cannot be guessed
+                        // at full speed either
+                        let mut response = self.limit(client).unwrap_or_else(|| {
+                            Response::error(401, "Requires a valid bearer token")
+                        });
+                        response
+                            .headers
+                            .push(("WWW-Authenticate", "Bearer".to_string()));
+                        return (response, None);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let key = user.map_or_else(|| client.to_string(), |name| format!("token:{}", name));
+        match self.limit(&key) {
+            Some(response) => (response, user),
+            None => (self.api.handle(request), user),
+        }
+    }
+
+    /// Rejection if the client exceeded the rate limit
+    fn limit(&self, key: &str) -> Option<Response> {
+        let retry_after = self.rate_limit.as_ref()?.acquire(key).err()?;
+        let mut response = Response::error(429, "Rate limit exceeded");
+        response.headers.push((
+            "Retry-After",
+            // rounded up, so the next request is allowed
+            (retry_after.as_secs() + 1).to_string(),
+        ));
+        Some(response)
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    /// Access is restricted by the permissions of the socket file
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Listener {
+    fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Connection::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener
+                .accept()
+                .map(|(stream, _)| Connection::Unix(stream)),
+        }
+    }
+}
+
+impl Connection {
+    fn handle(self, server: &Server<'_>, busy: impl Fn() -> bool) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => {
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                let client = stream.peer_addr()?.ip().to_string();
+                handle_connection(&stream, &stream, &client, server, busy)
+            }
+            #[cfg(unix)]
+            Connection::Unix(stream) => {
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                handle_connection(&stream, &stream, "unix", server, busy)
+            }
+        }
+    }
+}
+
+/// Answer the requests of the listener until the process is stopped. The connections are
+/// distributed over a worker per CPU, while the corpus stays opened and in the page cache. Keep-alive
+/// connections are closed after the response while others are waiting for a worker.
+pub fn serve(listener: Listener, server: &Server<'_>) {
+    let workers = num_cpus::get();
+    let (sender, receiver) = crossbeam_channel::bounded::<Connection>(workers * 4);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let receiver = receiver.clone();
+            scope.spawn(move || {
+                for connection in receiver.iter() {
+                    if let Err(err) = connection.handle(server, || !receiver.is_empty()) {
+                        debug!("Connection failed {}", err);
+                    }
+                }
+            });
+        }
+
+        loop {
+            match listener.accept() {
+                // only fails if all workers panicked
+                Ok(connection) => {
+                    if sender.send(connection).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => warn!("Failed to accept a connection {}", err),
+            }
+        }
+
+        drop(sender);
+    });
+
+    info!("Stopped serving");
+}
+
+fn handle_connection(
+    reader: impl Read,
+    mut writer: impl Write,
+    client: &str,
+    server: &Server<'_>,
+    busy: impl Fn() -> bool,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    loop {
+        let started = Instant::now();
+        let request = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(RequestError::Io(err)) => return Err(err),
+            Err(RequestError::Invalid(status, message)) => {
+                log_access(client, None, None, status, started);
+                return write_response(&mut writer, &Response::error(status, message), false);
+            }
+        };
+
+        let (response, user) = server.respond(&request, client);
+        log_access(client, user, Some(&request), response.status, started);
+
+        let keep_alive = request.keep_alive && !busy();
+        write_response(&mut writer, &response, keep_alive)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Structured line per request with `key=value` fields - the body is never logged, because it
+/// contains the hashes of the clients
+fn log_access(
+    client: &str,
+    user: Option<&str>,
+    request: Option<&Request>,
+    status: u16,
+    started: Instant,
+) {
+    info!(
+        "access client={} user={} method={:?} path={:?} status={} duration_us={}",
+        client,
+        user.unwrap_or("-"),
+        request.map_or("-", |request| request.method.as_str()),
+        request.map_or("-", |request| request.path.as_str()),
+        status,
+        started.elapsed().as_micros()
+    );
+}
+
+/// Next request of the connection - none if the client closed it
+pub fn read_request(reader: &mut impl BufRead) -> Result<Option<Request>, RequestError> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    let mut parts = line.split(' ');
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version), None) if version.starts_with("HTTP/1.") => {
+            (method, path, version)
+        }
+        _ => return Err(RequestError::Invalid(400, "Invalid request line")),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(RequestError::Invalid(
+            400,
+            "Connection closed in the headers",
+        ))?;
+        if line.is_empty() {
+            break;
+        }
+
+        if headers.len() == MAX_HEADERS {
+            return Err(RequestError::Invalid(431, "Too many headers"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(RequestError::Invalid(400, "Invalid header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers,
+        body: SecStr::new(Vec::new()),
+        keep_alive: false,
+    };
+
+    if request.header("transfer-encoding").is_some() {
+        return Err(RequestError::Invalid(
+            411,
+            "Only bodies with a Content-Length are supported",
+        ));
+    }
+
+    let len = match request.header("content-length") {
+        Some(len) => len
+            .parse::<usize>()
+            .map_err(|_| RequestError::Invalid(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+
+    if len > MAX_BODY_LEN {
+        return Err(RequestError::Invalid(413, "Request body is too large"));
+    }
+
+    request.body = SecStr::new(vec![0; len]);
+    reader.read_exact(request.body.unsecure_mut())?;
+
+    // HTTP/1.1 keeps the connection by default and HTTP/1.0 closes it
+    let connection = request.header("connection").map(str::to_ascii_lowercase);
+    request.keep_alive = match connection.as_deref() {
+        Some("close") => false,
+        Some("keep-alive") => true,
+        _ => version == "HTTP/1.1",
+    };
+
+    Ok(Some(request))
+}
+
+/// Line without the line break - none at the end of the input
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, RequestError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    if !line.ends_with(b"\n") {
+        return Err(if line.len() > MAX_LINE_LEN {
+            RequestError::Invalid(431, "Request line or header is too large")
+        } else {
+            RequestError::Invalid(400, "Connection closed in the middle of a line")
+        });
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| RequestError::Invalid(400, "Request is not valid UTF-8"))
+}
+
+fn write_response(
+    writer: &mut impl Write,
+    response: &Response,
+    keep_alive: bool,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        if keep_alive { "keep-alive" } else { "close" },
+    )?;
+    for (name, value) in &response.headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+
+    write!(writer, "\r\n{}", response.body)?;
+    writer.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf, process};
+
+    use super::*;
+
+    const CORPUS: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+                          5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n\
+                          5BAA6FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:2\n";
+
+    /// Removes the temporary corpus at the end of the test
+    struct TempCorpus(PathBuf);
+
+    impl Drop for TempCorpus {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Api of a temporary corpus file named after the test
+    fn api(name: &str) -> (Api<'static>, TempCorpus) {
+        let path =
+            std::env::temp_dir().join(format!("pwned-check-serve-{}-{}", name, process::id()));
+        fs::write(&path, CORPUS).unwrap();
+        let corpus = TempCorpus(path);
+
+        // leaked, because the api borrows the source until the end of the test
+        let source = Box::leak(find::open_source(corpus.0.as_os_str()).unwrap());
+        let api = Api {
+            source,
+            strict: true,
+            max_count: 0,
+            latency: LatencyHistogram::default(),
+        };
+        (api, corpus)
+    }
+
+    fn post(path: &str, body: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: SecStr::from(body),
+            keep_alive: true,
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let mut input = "POST /check HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}\
+                         GET /check HTTP/1.0\r\n\r\n"
+            .as_bytes();
+
+        let first = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(first.method, "POST");
+        assert_eq!(first.header("HOST"), Some("localhost"));
+        assert_eq!(first.body.unsecure(), b"{}");
+        assert!(first.keep_alive);
+
+        let second = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(second.path, "/check");
+        assert!(!second.keep_alive);
+        assert!(read_request(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_request_debug_redacted() {
+        let mut request = post("/policy", r#"{"password": "fixture-Secret-4711"}"#);
+        request.headers.push((
+            "authorization".to_string(),
+            "Bearer fixture-token".to_string(),
+        ));
+
+        let debug = format!("{:?}", request);
+        assert!(!debug.contains("fixture-Secret-4711"), "{}", debug);
+        assert!(!debug.contains("fixture-token"), "{}", debug);
+        assert!(debug.contains("/policy"), "{}", debug);
+    }
+
+    #[test]
+    fn test_invalid_request() {
+        let status = |input: &str| match read_request(&mut input.as_bytes()) {
+            Err(RequestError::Invalid(status, _)) => status,
+            other => panic!("Unexpected {:?}", other),
+        };
+
+        assert_eq!(status("POST /check\r\n\r\n"), 400);
+        assert_eq!(status("POST /check HTTP/1.1\r\nHost\r\n\r\n"), 400);
+        assert_eq!(
+            status(&format!(
+                "POST /check HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY_LEN + 1
+            )),
+            413
+        );
+        assert_eq!(
+            status("POST /check HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            411
+        );
+        assert_eq!(
+            status(&format!("GET /{} HTTP/1.1\r\n", "a".repeat(MAX_LINE_LEN))),
+            431
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let (api, _corpus) = api("check");
+
+        let response = api.handle(&post(
+            "/check",
+            r#"{"sha1": "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"}"#,
+        ));
+        assert_eq!(response.body, r#"{"pwned":true,"count":3861493}"#);
+
+        let response = api.handle(&post(
+            "/check",
+            &format!(r#"{{"sha1": "{}"}}"#, "F".repeat(40)),
+        ));
+        assert_eq!(response.body, r#"{"pwned":false}"#);
+
+        let response = api.handle(&post("/check", r#"{"prefix": "5baa6"}"#));
+        assert_eq!(
+            response.body,
+            r#"{"prefix":"5BAA6","matches":[{"suffix":"1E4C9B93F3F0682250B6CF8331B7EE68FD8","count":3861493},{"suffix":"FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF","count":2}]}"#
+        );
+
+        let statuses: Vec<_> = [
+            post("/check", r#"{"sha1": "5baa6"}"#),
+            post("/check", r#"{"prefix": "5baa"}"#),
+            post("/check", "{}"),
+            post("/check", "password"),
+            post("/other", "{}"),
+            Request {
+                method: "GET".to_string(),
+                ..post("/check", "")
+            },
+        ]
+        .iter()
+        .map(|request| api.handle(request).status)
+        .collect();
+        assert_eq!(statuses, [400, 400, 400, 400, 404, 405]);
+    }
+
+    #[test]
+    fn test_policy() {
+        let (mut api, _corpus) = api("policy");
+
+        let keycloak = post(
+            "/policy",
+            r#"{"type": "password", "value": "password", "temporary": false}"#,
+        );
+        assert_eq!(
+            api.handle(&keycloak).body,
+            r#"{"allowed":false,"count":3861493,"reason":"Password was found 3861493 times in known data breaches"}"#
+        );
+        assert_eq!(
+            api.handle(&post("/policy", r#"{"password": "correct horse"}"#))
+                .body,
+            r#"{"allowed":true}"#
+        );
+
+        api.max_count = 5_000_000;
+        assert_eq!(
+            api.handle(&keycloak).body,
+            r#"{"allowed":true,"count":3861493}"#
+        );
+
+        // invalid JSON isn't echoed, because it could contain the password
+        let response = api.handle(&post("/policy", r#"{"password": "secret"#));
+        assert_eq!(response.status, 400);
+        assert!(!response.body.contains("secret"));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (api, _corpus) = api("metrics");
+
+        api.handle(&post(
+            "/check",
+            &format!(r#"{{"sha1": "{}"}}"#, "F".repeat(40)),
+        ));
+        api.handle(&post("/check", r#"{"prefix": "5BAA6"}"#));
+        // rejected before the lookup
+        api.handle(&post("/check", r#"{"sha1": "5BAA6"}"#));
+
+        let response = api.handle(&Request {
+            method: "GET".to_string(),
+            ..post("/metrics", "")
+        });
+        let metrics: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(metrics["lookups"], 2);
+        assert!(metrics["p50_us"].as_u64() <= metrics["p99_us"].as_u64());
+        assert!(metrics["p99_us"].as_u64() <= metrics["max_us"].as_u64());
+    }
+
+    #[test]
+    fn test_check_many() {
+        let (api, _corpus) = api("batch");
+
+        let response = api.handle(&post(
+            "/check-many",
+            &format!(
+                r#"{{"sha1": ["5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8", "{}"]}}"#,
+                "F".repeat(40)
+            ),
+        ));
+        assert_eq!(
+            response.body,
+            format!(
+                r#"{{"results":[{{"sha1":"5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8","pwned":true,"count":3861493}},{{"sha1":"{}","pwned":false}}]}}"#,
+                "F".repeat(40)
+            )
+        );
+
+        let too_many = serde_json::json!({ "sha1": vec!["F".repeat(40); MAX_BATCH_LEN + 1] });
+        let statuses: Vec<_> = [
+            post("/check-many", r#"{"sha1": []}"#),
+            post("/check-many", r#"{"sha1": ["5baa6"]}"#),
+            post("/check-many", &too_many.to_string()),
+        ]
+        .iter()
+        .map(|request| api.handle(request).status)
+        .collect();
+        assert_eq!(statuses, [200, 400, 413]);
+    }
+
+    #[test]
+    fn test_hibp_range() {
+        let (api, _corpus) = api("hibp");
+        let get = |path: &str| Request {
+            method: "GET".to_string(),
+            ..post(path, "")
+        };
+
+        let response = api.handle(&get("/range/5baa6"));
+        assert_eq!(response.content_type, "text/plain");
+        assert_eq!(
+            response.body,
+            "1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\nFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:2"
+        );
+        assert_eq!(api.handle(&get("/range/00001")).body, "");
+
+        let mut padded = get("/range/5BAA6");
+        padded
+            .headers
+            .push(("add-padding".to_string(), "true".to_string()));
+        let body = api.handle(&padded).body;
+        let lines: Vec<_> = body.split("\r\n").collect();
+        assert!((800..=1000).contains(&lines.len()));
+        assert!(lines.contains(&"1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493"));
+        assert_eq!(
+            lines.iter().filter(|line| line.ends_with(":0")).count(),
+            lines.len() - 2
+        );
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let statuses: Vec<_> = [
+            get("/range/5BAA"),
+            get("/range/5BAA6?mode=ntlm"),
+            post("/range/5BAA6", ""),
+        ]
+        .iter()
+        .map(|request| api.handle(request).status)
+        .collect();
+        assert_eq!(statuses, [400, 400, 405]);
+    }
+
+    #[test]
+    fn test_access() {
+        let (api, _corpus) = api("access");
+        let server = Server {
+            api,
+            tokens: Some("ci=0123456789abcdef0123".parse().unwrap()),
+            rate_limit: Some(RateLimiter::new(2)),
+        };
+
+        let mut request = post("/check", r#"{"prefix": "00000"}"#);
+        let (response, user) = server.respond(&request, "10.0.0.1");
+        assert_eq!((response.status, user), (401, None));
+        assert_eq!(
+            response.headers,
+            [("WWW-Authenticate", "Bearer".to_string())]
+        );
+
+        request.headers.push((
+            "authorization".to_string(),
+            "Bearer 0123456789abcdef0123".to_string(),
+        ));
+        let statuses: Vec<_> = (0..3)
+            .map(|_| server.respond(&request, "10.0.0.1"))
+            .map(|(response, user)| (response.status, user))
+            .collect();
+        assert_eq!(
+            statuses,
+            [(200, Some("ci")), (200, Some("ci")), (429, Some("ci"))]
+        );
+
+        // the failed attempt used one request of the address
+        request.headers.clear();
+        assert_eq!(server.respond(&request, "10.0.0.1").0.status, 401);
+        let (response, _) = server.respond(&request, "10.0.0.1");
+        assert_eq!(response.status, 429);
+        assert_eq!(response.headers[0].0, "Retry-After");
+    }
+
+    #[test]
+    fn test_serve() {
+        let (api, _corpus) = api("tcp");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let server = Server {
+                api,
+                tokens: None,
+                rate_limit: None,
+            };
+            serve(Listener::Tcp(listener), &server)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let body = r#"{"sha1": "000000005AD76BD555C1D6D771DE417A4B87E4B4"}"#;
+        for connection in ["keep-alive", "close"] {
+            write!(
+                stream,
+                "POST /check HTTP/1.1\r\nConnection: {}\r\nContent-Length: {}\r\n\r\n{}",
+                connection,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+
+        let mut responses = String::new();
+        stream.read_to_string(&mut responses).unwrap();
+        assert_eq!(responses.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(responses.ends_with(r#"{"pwned":true,"count":4}"#));
+    }
+
+    #[test]
+    fn test_busy_closes_keep_alive() {
+        let (api, _corpus) = api("busy");
+        let server = Server {
+            api,
+            tokens: None,
+            rate_limit: None,
+        };
+
+        let body = r#"{"sha1": "000000005AD76BD555C1D6D771DE417A4B87E4B4"}"#;
+        let request = format!(
+            "POST /check HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let requests = request.repeat(2);
+
+        let mut responses = Vec::new();
+        handle_connection(requests.as_bytes(), &mut responses, "test", &server, || {
+            false
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&responses)
+                .matches("200 OK")
+                .count(),
+            2
+        );
+
+        // other connections are waiting, so the first response already closes the connection
+        let mut responses = Vec::new();
+        handle_connection(requests.as_bytes(), &mut responses, "test", &server, || {
+            true
+        })
+        .unwrap();
+        let responses = String::from_utf8(responses).unwrap();
+        assert_eq!(responses.matches("200 OK").count(), 1);
+        assert!(responses.contains("Connection: close"));
+    }
+}
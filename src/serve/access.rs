@@ -0,0 +1,229 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ring::{
+    constant_time::verify_slices_are_equal,
+    digest::{digest, Digest, SHA256},
+};
+use secstr::SecStr;
+
+/// Shorter tokens could be guessed within the rate limit
+const MIN_TOKEN_LEN: usize = 16;
+
+/// Full buckets and afterwards the least recently used ones are dropped above it, so clients with
+/// changing addresses cannot exhaust the memory
+const MAX_CLIENTS: usize = 10_000;
+
+/// Named API tokens - the name identifies the client in the access log and the rate limit. Only
+/// the SHA-256 digests of the tokens are kept in memory.
+pub struct Tokens {
+    digests: Vec<(String, Digest)>,
+}
+
+impl Tokens {
+    /// Read `name=token` lines of the file, so the tokens don't show up in the process list
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let content = SecStr::new(fs::read(path)?);
+        let content = std::str::from_utf8(content.unsecure()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Token file is not valid UTF-8")
+        })?;
+
+        content
+            .parse()
+            .map_err(|err: String| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Name of the token. The digests have the same length and each one is compared in constant
+    /// time, so the response time doesn't reveal how much of a token matched.
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        let presented = digest(&SHA256, token.as_bytes());
+        let mut found = None;
+        for (name, expected) in &self.digests {
+            if verify_slices_are_equal(expected.as_ref(), presented.as_ref()).is_ok() {
+                found = Some(name.as_str());
+            }
+        }
+
+        found
+    }
+}
+
+impl FromStr for Tokens {
+    type Err = String;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut digests: Vec<(String, Digest)> = Vec::new();
+        for (number, line) in content.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // the line isn't included in the errors, because it contains the token
+            let (name, token) = line
+                .split_once('=')
+                .map(|(name, token)| (name.trim(), token.trim()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| format!("Line {} is not name=token", number + 1))?;
+
+            if token.len() < MIN_TOKEN_LEN {
+                return Err(format!(
+                    "Token {} is shorter than {} characters",
+                    name, MIN_TOKEN_LEN
+                ));
+            }
+
+            if digests.iter().any(|(existing, _)| existing == name) {
+                return Err(format!("Token name {} is used twice", name));
+            }
+
+            digests.push((name.to_string(), digest(&SHA256, token.as_bytes())));
+        }
+
+        if digests.is_empty() {
+            return Err("Token file contains no tokens".to_string());
+        }
+
+        Ok(Tokens { digests })
+    }
+}
+
+/// Token bucket per client - a client could send a burst of the limit and afterwards the requests
+/// are spread over the minute
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a request from the bucket of the client - otherwise the time until the next one
+    pub fn acquire(&self, client: &str) -> Result<(), Duration> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    fn acquire_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let per_second = capacity / 60.0;
+        let refilled = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.available + elapsed * per_second).min(capacity)
+        };
+
+        // unwrap is safe, because the lock is never held while panicking
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| refilled(bucket) < capacity);
+            if buckets.len() >= MAX_CLIENTS {
+                // unwrap is safe, because the map is full
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(client, _)| client.clone())
+                    .unwrap();
+                buckets.remove(&oldest);
+            }
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            available: capacity,
+            updated: now,
+        });
+
+        bucket.available = refilled(bucket);
+        bucket.updated = now;
+        if bucket.available >= 1.0 {
+            bucket.available -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.available) / per_second,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Tokens, String> {
+        content.parse()
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens =
+            parse("# CI jobs\nci = 0123456789abcdef0123\n\nmonitoring=fedcba9876543210fedc\n")
+                .unwrap();
+        assert_eq!(tokens.authenticate("0123456789abcdef0123"), Some("ci"));
+        assert_eq!(
+            tokens.authenticate("fedcba9876543210fedc"),
+            Some("monitoring")
+        );
+        assert_eq!(tokens.authenticate("0123456789abcdef012"), None);
+        assert_eq!(tokens.authenticate(""), None);
+
+        assert!(parse("ci=short").is_err());
+        assert!(parse("0123456789abcdef0123").is_err());
+        assert!(parse("=0123456789abcdef0123").is_err());
+        assert!(parse("ci=0123456789abcdef0123\nci=fedcba9876543210fedc").is_err());
+        assert!(parse("# empty\n").is_err());
+
+        // the token never shows up in the error
+        let err = parse("ci=secret-token").err().unwrap();
+        assert!(!err.contains("secret-token"));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert_eq!(limiter.acquire_at("a", start), Ok(()));
+        assert_eq!(limiter.acquire_at("a", start), Ok(()));
+
+        let retry = limiter.acquire_at("a", start).unwrap_err();
+        assert_eq!(retry.as_secs(), 30);
+
+        // other clients have their own bucket
+        assert_eq!(limiter.acquire_at("b", start), Ok(()));
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(limiter.acquire_at("a", later), Ok(()));
+        assert!(limiter.acquire_at("a", later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_clients() {
+        let limiter = RateLimiter::new(1);
+        let start = Instant::now();
+        for client in 0..MAX_CLIENTS {
+            let now = start + Duration::from_millis(client as u64);
+            assert_eq!(limiter.acquire_at(&client.to_string(), now), Ok(()));
+        }
+
+        // none of the buckets is full again, so the least recently used one is dropped
+        let now = start + Duration::from_secs(30);
+        assert_eq!(limiter.acquire_at("new", now), Ok(()));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_CLIENTS);
+        assert!(!buckets.contains_key("0"));
+        assert!(buckets.contains_key("1"));
+    }
+}
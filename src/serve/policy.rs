@@ -0,0 +1,110 @@
+use secstr::SecStr;
+use serde::{Deserialize, Serialize};
+
+use crate::{find, hasher, Sha1Hash};
+
+/// Body of `POST /policy` sent by the identity provider before a password is set. The `value`
+/// alias matches the credential representation of Keycloak (`{"type": "password", "value": ..}`).
+#[derive(Deserialize)]
+pub struct PolicyRequest {
+    #[serde(alias = "value", alias = "newPassword")]
+    password: Option<SecStr>,
+    /// Hex encoded hash if the provider hashes the password itself
+    sha1: Option<String>,
+}
+
+impl PolicyRequest {
+    /// Hash of the new password - an error if the request has neither or both fields
+    pub fn hash(&self) -> Result<Sha1Hash, &'static str> {
+        match (&self.password, &self.sha1) {
+            (Some(password), None) => Ok(hasher::sha1(&[password.unsecure()])),
+            (None, Some(hex)) => find::parse_sha1(hex).ok_or("sha1 requires 40 hex characters"),
+            _ => Err("Requires either a password or a sha1 field"),
+        }
+    }
+}
+
+/// Decision of the webhook - the reason could be shown to the user
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PolicyResponse {
+    pub allowed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Deny passwords found more often than the threshold. NIST 800-63B requires checking new
+/// passwords against known breaches, so the default of 0 denies any pwned password.
+pub fn decide(found: Option<Option<u64>>, max_count: u64) -> PolicyResponse {
+    match found {
+        None => PolicyResponse {
+            allowed: true,
+            count: None,
+            reason: None,
+        },
+        // a list without counts has no threshold to compare against
+        Some(None) => PolicyResponse {
+            allowed: false,
+            count: None,
+            reason: Some("Password was found in known data breaches".to_string()),
+        },
+        Some(Some(count)) => PolicyResponse {
+            allowed: count <= max_count,
+            count: Some(count),
+            reason: (count > max_count)
+                .then(|| format!("Password was found {} times in known data breaches", count)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hash(json: &str) -> Result<Sha1Hash, &'static str> {
+        serde_json::from_str::<PolicyRequest>(json).unwrap().hash()
+    }
+
+    #[test]
+    fn test_request() {
+        // SHA-1 of "password"
+        let expected = find::parse_sha1("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+        assert_eq!(hash(r#"{"password": "password"}"#).ok(), expected);
+        assert_eq!(
+            hash(r#"{"type": "password", "value": "password", "temporary": false}"#).ok(),
+            expected
+        );
+        assert_eq!(
+            hash(r#"{"sha1": "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"}"#).ok(),
+            expected
+        );
+
+        assert!(hash(r#"{"sha1": "5baa6"}"#).is_err());
+        assert!(hash(r#"{"password": "a", "sha1": "b"}"#).is_err());
+        assert!(hash("{}").is_err());
+    }
+
+    #[test]
+    fn test_decide() {
+        assert!(decide(None, 0).allowed);
+        assert!(!decide(Some(None), 100).allowed);
+
+        let denied = decide(Some(Some(3)), 0);
+        assert!(!denied.allowed);
+        assert_eq!(denied.count, Some(3));
+        assert_eq!(
+            denied.reason.as_deref(),
+            Some("Password was found 3 times in known data breaches")
+        );
+
+        assert_eq!(
+            decide(Some(Some(3)), 3),
+            PolicyResponse {
+                allowed: true,
+                count: Some(3),
+                reason: None,
+            }
+        );
+    }
+}
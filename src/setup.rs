@@ -0,0 +1,342 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::download::{self, Checksum};
+
+/// Where the hashes are checked
+#[derive(Debug)]
+pub enum CorpusChoice {
+    /// Download the complete list once and check locally
+    Download {
+        mirror: String,
+        checksum: Option<Checksum>,
+        /// Convert the downloaded list into an index
+        index: bool,
+    },
+    /// Hash list on a static file host searched using range requests
+    Remote(String),
+    /// Previously downloaded hash list, index or range directory
+    Existing(PathBuf),
+}
+
+/// Password managers with exports in the expected CSV format (url, username and password columns)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Manager {
+    Chromium,
+    Firefox,
+    LastPass,
+    Other,
+}
+
+impl Manager {
+    const ALL: [Manager; 4] = [
+        Manager::Chromium,
+        Manager::Firefox,
+        Manager::LastPass,
+        Manager::Other,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Manager::Chromium => "Chrome, Edge, Brave or another Chromium browser",
+            Manager::Firefox => "Firefox",
+            Manager::LastPass => "LastPass",
+            Manager::Other => "Other - a CSV file with url, username and password columns",
+        }
+    }
+
+    fn export_steps(self) -> &'static str {
+        match self {
+            Manager::Chromium => {
+                "Open chrome://password-manager/settings and click on Export passwords"
+            }
+            Manager::Firefox => {
+                "Open about:logins, click on the menu (...) and select Export Logins"
+            }
+            Manager::LastPass => "Open Advanced Options > Export > LastPass CSV File in the vault",
+            Manager::Other => {
+                "Export the passwords as CSV with the columns url, username and password"
+            }
+        }
+    }
+
+    /// File name of the export by default
+    fn export_name(self) -> &'static str {
+        match self {
+            Manager::Chromium => "Chrome Passwords.csv",
+            Manager::Firefox => "logins.csv",
+            Manager::LastPass => "lastpass_export.csv",
+            Manager::Other => "passwords.csv",
+        }
+    }
+}
+
+/// Collected answers of the setup
+#[derive(Debug)]
+pub struct Answers {
+    pub corpus: CorpusChoice,
+    pub manager: Manager,
+    /// Location of the password export for the weekly check
+    pub export: PathBuf,
+}
+
+/// Ask the questions on the terminal until each one has a valid answer. Fails if the input ends.
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<Answers> {
+    let mut prompt = Prompt { input, output };
+    writeln!(
+        prompt.output,
+        "Welcome to pwned-check! This setup chooses the hash list and prints the command for your \
+         regular checks."
+    )?;
+
+    let modes = [
+        "Offline - download the complete list once (about 35 GB) and check locally",
+        "Online - search a list on your own web server or S3 bucket using range requests",
+        "Existing - use a list that you already downloaded",
+    ];
+    let corpus = match prompt.choose("Where should your passwords be checked?", &modes)? {
+        0 => ask_download(&mut prompt)?,
+        1 => CorpusChoice::Remote(prompt.ask_valid(
+            "URL of the hash list (https:// or s3://)",
+            None,
+            |url| {
+                if url.starts_with("https://")
+                    || url.starts_with("http://")
+                    || url.starts_with("s3://")
+                {
+                    Ok(url.to_string())
+                } else {
+                    Err("Only http(s):// and s3:// URLs are supported".to_string())
+                }
+            },
+        )?),
+        _ => CorpusChoice::Existing(prompt.ask_valid(
+            "Path of the hash list, index or range directory",
+            None,
+            |path| {
+                if Path::new(path).exists() {
+                    Ok(PathBuf::from(path))
+                } else {
+                    Err(format!("{} doesn't exist", path))
+                }
+            },
+        )?),
+    };
+
+    let labels: Vec<_> = Manager::ALL.iter().map(|manager| manager.label()).collect();
+    let manager = Manager::ALL[prompt.choose("Which password manager do you use?", &labels)?];
+    writeln!(prompt.output, "{}", manager.export_steps())?;
+
+    let default_export = default_export_dir().join(manager.export_name());
+    let export = prompt.ask_valid(
+        "Where do you save the export?",
+        default_export.to_str(),
+        |path| Ok(PathBuf::from(path)),
+    )?;
+
+    Ok(Answers {
+        corpus,
+        manager,
+        export,
+    })
+}
+
+fn ask_download(prompt: &mut Prompt<'_, impl BufRead, impl Write>) -> io::Result<CorpusChoice> {
+    let mirror = prompt.ask_valid(
+        "Mirror URL of the unpacked SHA-1 list sorted by hash",
+        None,
+        |url| {
+            download::check_source(url)
+                .map(|_| url.to_string())
+                .map_err(|err| err.to_string())
+        },
+    )?;
+
+    let checksum = prompt.ask_valid(
+        "Published checksum of the list (sha1:HEX or sha256:HEX) - empty to skip the verification",
+        Some(""),
+        |checksum| {
+            if checksum.is_empty() {
+                Ok(None)
+            } else {
+                checksum.parse().map(Some)
+            }
+        },
+    )?;
+
+    #[cfg(feature = "index")]
+    let index = prompt.confirm(
+        "Convert the list into an index for checks in seconds? It needs about 7/8 of the list size",
+        true,
+    )?;
+
+    #[cfg(not(feature = "index"))]
+    let index = false;
+
+    Ok(CorpusChoice::Download {
+        mirror,
+        checksum,
+        index,
+    })
+}
+
+/// Downloads directory of the user or the working directory
+fn default_export_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(|home| Path::new(&home).join("Downloads"))
+        .filter(|downloads| downloads.is_dir())
+        .unwrap_or_default()
+}
+
+/// Command of the regular checks - the corpus is found through the config
+pub fn weekly_command(export: &Path) -> String {
+    format!("pwned-check {}", shell_quote(&export.to_string_lossy()))
+}
+
+/// Quote the argument for POSIX shells if it contains special characters
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-~+=:,".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+struct Prompt<'a, R, W> {
+    input: &'a mut R,
+    output: &'a mut W,
+}
+
+impl<R: BufRead, W: Write> Prompt<'_, R, W> {
+    /// Trimmed answer or the default if it's empty - repeated until it's valid
+    fn ask_valid<T>(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> io::Result<T> {
+        loop {
+            match default {
+                Some(default) if !default.is_empty() => {
+                    write!(self.output, "{} [{}]: ", question, default)?
+                }
+                _ => write!(self.output, "{}: ", question)?,
+            }
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Input ended before the setup finished",
+                ));
+            }
+
+            let answer = match (line.trim(), default) {
+                ("", Some(default)) => default,
+                ("", None) => continue,
+                (answer, _) => answer,
+            };
+
+            match parse(answer) {
+                Ok(value) => return Ok(value),
+                Err(err) => writeln!(self.output, "{}", err)?,
+            }
+        }
+    }
+
+    /// Position of the selected option
+    fn choose(&mut self, question: &str, options: &[&str]) -> io::Result<usize> {
+        writeln!(self.output, "{}", question)?;
+        for (number, option) in options.iter().enumerate() {
+            writeln!(self.output, "  {}) {}", number + 1, option)?;
+        }
+
+        self.ask_valid("Select a number", Some("1"), |answer| {
+            match answer.parse::<usize>() {
+                Ok(number) if (1..=options.len()).contains(&number) => Ok(number - 1),
+                _ => Err(format!("Select a number from 1 to {}", options.len())),
+            }
+        })
+    }
+
+    #[cfg(feature = "index")]
+    fn confirm(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        let default = if default { "y" } else { "n" };
+        self.ask_valid(question, Some(default), |answer| {
+            match answer.to_ascii_lowercase().as_str() {
+                "y" | "yes" => Ok(true),
+                "n" | "no" => Ok(false),
+                _ => Err("Answer with y or n".to_string()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn answers(input: &str) -> (io::Result<Answers>, String) {
+        let mut output = Vec::new();
+        let answers = ask(&mut input.as_bytes(), &mut output);
+        (answers, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_remote() {
+        let (answers, output) = answers("2\nftp://example.com\ns3://bucket/pwned.txt\n3\n\n");
+        let answers = answers.unwrap();
+        assert_matches!(answers.corpus, CorpusChoice::Remote(ref url) if url == "s3://bucket/pwned.txt");
+        assert_eq!(answers.manager, Manager::LastPass);
+        assert!(answers.export.ends_with("lastpass_export.csv"));
+
+        // invalid answers are asked again
+        assert!(output.contains("Only http(s):// and s3:// URLs are supported"));
+        assert!(output.contains(Manager::LastPass.export_steps()));
+    }
+
+    #[test]
+    fn test_download() {
+        let checksum = format!("sha1:{}", "ab".repeat(20));
+        let input = format!(
+            "\nhttps://example.com/pwned.torrent\nhttps://example.com/pwned.txt\nsha1:abc\n{}\nmaybe\nn\n9\n1\n/tmp/export.csv\n",
+            checksum
+        );
+
+        let answers = answers(&input).0.unwrap();
+        assert_matches!(
+            answers.corpus,
+            CorpusChoice::Download {
+                ref mirror,
+                checksum: Some(_),
+                index: false,
+            } if mirror == "https://example.com/pwned.txt"
+        );
+        assert_eq!(answers.manager, Manager::Chromium);
+        assert_eq!(answers.export, Path::new("/tmp/export.csv"));
+    }
+
+    #[test]
+    fn test_input_ended() {
+        let (answers, _) = answers("3\n/missing/pwned.txt\n");
+        assert_eq!(answers.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_weekly_command() {
+        assert_eq!(
+            weekly_command(Path::new("/home/alice/logins.csv")),
+            "pwned-check /home/alice/logins.csv"
+        );
+        assert_eq!(
+            weekly_command(Path::new("/home/alice/Chrome Passwords.csv")),
+            "pwned-check '/home/alice/Chrome Passwords.csv'"
+        );
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// Reserve for other writers like logs and the metadata files next to the output
+const RESERVE: u64 = 64 * MEBIBYTE;
+
+/// The file system of the output doesn't have enough free space for the estimated size
+#[derive(Debug, PartialEq)]
+pub struct InsufficientSpace {
+    pub dir: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+impl Display for InsufficientSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Not enough disk space in {:?} - about {} MiB required, but only {} MiB available",
+            self.dir,
+            self.required / MEBIBYTE,
+            self.available / MEBIBYTE
+        )
+    }
+}
+
+impl Error for InsufficientSpace {}
+
+/// Fail early if writing the output would run out of space after hours of work. If the free space
+/// cannot be determined, it's assumed to be enough.
+pub fn check_space(output: &Path, required: u64) -> Result<(), InsufficientSpace> {
+    let dir = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match available_space(dir) {
+        Ok(available) => verify(dir, required, available),
+        Err(err) => {
+            debug!("Cannot determine the free space of {:?} {}", dir, err);
+            Ok(())
+        }
+    }
+}
+
+fn verify(dir: &Path, required: u64, available: u64) -> Result<(), InsufficientSpace> {
+    let required = required.saturating_add(RESERVE);
+    if required <= available {
+        return Ok(());
+    }
+
+    Err(InsufficientSpace {
+        dir: dir.to_path_buf(),
+        required,
+        available,
+    })
+}
+
+/// Bytes available to unprivileged users - the reserved blocks of root are excluded
+#[cfg(unix)]
+fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // unsafe is safe, because the path is null terminated and the struct is only read on success
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        stat.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::{iter, os::windows::ffi::OsStrExt, ptr};
+
+    use winapi::um::{fileapi::GetDiskFreeSpaceExW, winnt::ULARGE_INTEGER};
+
+    let path: Vec<u16> = dir.as_os_str().encode_wide().chain(iter::once(0)).collect();
+    let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+
+    // unsafe is safe, because the path is null terminated and the other outputs are optional
+    let ret = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if ret == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { *available.QuadPart() })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_dir: &Path) -> io::Result<u64> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let dir = Path::new("data");
+        assert_eq!(verify(dir, MEBIBYTE, RESERVE + MEBIBYTE), Ok(()));
+        assert_eq!(
+            verify(dir, MEBIBYTE, MEBIBYTE),
+            Err(InsufficientSpace {
+                dir: dir.to_path_buf(),
+                required: RESERVE + MEBIBYTE,
+                available: MEBIBYTE,
+            })
+        );
+        assert!(verify(dir, u64::MAX, u64::MAX - 1).is_err());
+    }
+
+    #[test]
+    fn test_check_space() {
+        let output = std::env::temp_dir().join("pwned-check-space.txt");
+        assert!(available_space(output.parent().unwrap()).unwrap() > 0);
+        assert!(check_space(&output, 0).is_ok());
+        assert!(check_space(&output, u64::MAX).is_err());
+
+        // relative outputs without a directory are in the working directory
+        assert!(check_space(Path::new("output.txt"), u64::MAX).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use crate::atomic::atomic_write;
+
+/// Suffix of the count statistics next to the hash database
+const SIDECAR_SUFFIX: &str = ".stats";
+
+/// Shares of the most common hashes in percent - the sidecar stores the lowest count of each share
+const TOP_PERCENTS: [f64; 6] = [0.001, 0.01, 0.1, 1.0, 10.0, 50.0];
+
+/// Number of hashes per count of a hash database - only counted by the commands of the index
+/// feature
+#[cfg_attr(not(feature = "index"), allow(dead_code))]
+#[derive(Debug, Default, PartialEq)]
+pub struct CountHistogram {
+    hashes: BTreeMap<u64, u64>,
+}
+
+#[cfg_attr(not(feature = "index"), allow(dead_code))]
+impl CountHistogram {
+    /// Record a hash with its count - hashes without a count (zero) are skipped
+    pub fn insert(&mut self, count: u64) {
+        if count > 0 {
+            *self.hashes.entry(count).or_default() += 1;
+        }
+    }
+
+    /// Lowest count of each top share. Shares with less than a single hash are skipped, because
+    /// every match of a small database would be in them.
+    pub fn stats(&self) -> CorpusStats {
+        let hashes: u64 = self.hashes.values().sum();
+        let mut thresholds = Vec::with_capacity(TOP_PERCENTS.len());
+
+        let mut ranked = 0;
+        let mut threshold = None;
+        let mut counts = self.hashes.iter().rev();
+        for &percent in &TOP_PERCENTS {
+            // in thousandths of a percent, because the shares aren't exact as floats
+            let share = u128::from(hashes) * (percent * 1000.0).round() as u128;
+            if share < 100_000 {
+                continue;
+            }
+
+            let rank = share.div_ceil(100_000) as u64;
+            while ranked < rank {
+                // unwrap is safe, because the rank never exceeds the number of hashes
+                let (&count, &with_count) = counts.next().unwrap();
+                ranked += with_count;
+                threshold = Some(count);
+            }
+
+            // unwrap is safe, because at least one hash was ranked
+            thresholds.push((percent, threshold.unwrap()));
+        }
+
+        CorpusStats { hashes, thresholds }
+    }
+}
+
+/// Count distribution of a hash database written by the `stats` and `convert` commands. Stored as
+/// `key=value` lines next to the database like the metadata.
+#[derive(Debug, PartialEq)]
+pub struct CorpusStats {
+    /// Number of hashes with a count
+    pub hashes: u64,
+    /// Lowest count of the most common hashes by their share in percent - smallest share first
+    pub thresholds: Vec<(f64, u64)>,
+}
+
+impl CorpusStats {
+    /// Statistics of the database or none if there is no sidecar or it's outdated
+    pub fn read(corpus: &Path) -> io::Result<Option<Self>> {
+        let path = sidecar_path(corpus);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        // the sidecar is written after the database, so a newer database was replaced since then
+        if fs::metadata(corpus)?.modified().ok() > fs::metadata(&path)?.modified().ok() {
+            warn!("Ignoring outdated statistics {:?}", path);
+            return Ok(None);
+        }
+
+        parse(&content)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    #[cfg_attr(not(feature = "index"), allow(dead_code))]
+    pub fn write(&self, corpus: &Path) -> io::Result<()> {
+        let mut content = format!("# pwned-check corpus statistics\nhashes={}\n", self.hashes);
+        for (percent, count) in &self.thresholds {
+            // unwrap is safe, because writing to a string never fails
+            writeln!(content, "top_{}={}", percent, count).unwrap();
+        }
+
+        atomic_write(&sidecar_path(corpus), |file| {
+            file.write_all(content.as_bytes())
+        })
+    }
+
+    /// Smallest share of the most common hashes that includes the count - none if it's less common
+    /// than all of them
+    pub fn top_percent(&self, count: u64) -> Option<f64> {
+        self.thresholds
+            .iter()
+            .find(|(_, threshold)| count >= *threshold)
+            .map(|(percent, _)| *percent)
+    }
+
+    /// Print how the highest count of the matches compares to the database
+    pub fn print_worst(&self, corpus: &str, count: u64) {
+        match self.top_percent(count) {
+            Some(percent) => info!(
+                "Your worst password ({}x times) is in the top {} % most common passwords of {}",
+                count, percent, corpus
+            ),
+            None => info!(
+                "Your worst password ({}x times) is less common than most passwords of {}",
+                count, corpus
+            ),
+        }
+    }
+}
+
+fn sidecar_path(corpus: &Path) -> PathBuf {
+    let mut path = corpus.as_os_str().to_os_string();
+    path.push(SIDECAR_SUFFIX);
+    PathBuf::from(path)
+}
+
+fn parse(content: &str) -> Result<CorpusStats, String> {
+    let mut hashes = None;
+    let mut thresholds = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // unknown keys are skipped for newer versions
+        match line.split_once('=') {
+            Some(("hashes", value)) => {
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number of hashes {}", value))?;
+                hashes = Some(value);
+            }
+            Some((key, value)) if key.starts_with("top_") => {
+                let percent = key["top_".len()..]
+                    .parse()
+                    .map_err(|_| format!("Invalid share {}", key))?;
+                let count = value
+                    .parse()
+                    .map_err(|_| format!("Invalid count {}", value))?;
+                thresholds.push((percent, count));
+            }
+            _ => {}
+        }
+    }
+
+    thresholds.sort_by(|(a, _): &(f64, u64), (b, _)| a.total_cmp(b));
+    Ok(CorpusStats {
+        hashes: hashes.ok_or("Missing number of hashes")?,
+        thresholds,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn histogram(counts: impl IntoIterator<Item = u64>) -> CountHistogram {
+        let mut histogram = CountHistogram::default();
+        for count in counts {
+            histogram.insert(count);
+        }
+
+        histogram
+    }
+
+    #[test]
+    fn test_stats() {
+        // 100.000 hashes with the counts 1 to 100.000
+        let stats = histogram(1..=100_000).stats();
+        assert_eq!(stats.hashes, 100_000);
+        assert_eq!(
+            stats.thresholds,
+            vec![
+                (0.001, 100_000),
+                (0.01, 99_991),
+                (0.1, 99_901),
+                (1.0, 99_001),
+                (10.0, 90_001),
+                (50.0, 50_001)
+            ]
+        );
+
+        assert_eq!(stats.top_percent(100_000), Some(0.001));
+        assert_eq!(stats.top_percent(99_995), Some(0.01));
+        assert_eq!(stats.top_percent(60_000), Some(50.0));
+        assert_eq!(stats.top_percent(50_000), None);
+    }
+
+    #[test]
+    fn test_small_corpus() {
+        // unknown counts are skipped and shares of less than one hash are left out
+        let stats = histogram(vec![0, 0, 5, 3, 3, 1, 1, 1, 1, 1, 1, 1]).stats();
+        assert_eq!(stats.hashes, 10);
+        assert_eq!(stats.thresholds, vec![(10.0, 5), (50.0, 1)]);
+        assert_eq!(stats.top_percent(4), Some(50.0));
+
+        let empty = histogram(vec![0]).stats();
+        assert_eq!(empty.thresholds, vec![]);
+        assert_eq!(empty.top_percent(1), None);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let corpus =
+            std::env::temp_dir().join(format!("pwned-check-stats-{}.txt", std::process::id()));
+        fs::write(&corpus, "").unwrap();
+        assert_eq!(CorpusStats::read(&corpus).unwrap(), None);
+
+        let stats = histogram(1..=1000).stats();
+        stats.write(&corpus).unwrap();
+        assert_eq!(CorpusStats::read(&corpus).unwrap(), Some(stats));
+
+        fs::remove_file(sidecar_path(&corpus)).unwrap();
+        fs::remove_file(&corpus).unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        let stats = parse("hashes=10\ntop_50=1\ntop_10=5\nfuture=value\n").unwrap();
+        assert_eq!(stats.thresholds, vec![(10.0, 5), (50.0, 1)]);
+
+        assert!(parse("top_10=5\n").is_err());
+        assert!(parse("hashes=10\ntop_ten=5\n").is_err());
+        assert!(parse("hashes=10\ntop_10=many\n").is_err());
+    }
+}
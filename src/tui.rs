@@ -0,0 +1,750 @@
+use std::{
+    collections::VecDeque,
+    io::{self, IsTerminal, Stderr},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+
+use crate::{
+    cancel::CancellationToken,
+    logger,
+    progress::{ProgressSink, ProgressUnit},
+    report::{Account, Event as ReportEvent, StructuredReport, Summary},
+};
+
+/// Passwords found this often are part of every dictionary attack
+const CRITICAL_COUNT: u64 = 10_000;
+const HIGH_COUNT: u64 = 100;
+
+/// Refresh interval of the screen while waiting for key presses
+const TICK: Duration = Duration::from_millis(100);
+
+/// Kept log messages - older ones are dropped
+const LOG_LINES: usize = 100;
+
+/// Lines moved by page up or down
+const PAGE: usize = 10;
+
+/// Number of progress updates per scan - advancing by each line would flood the channel
+const PROGRESS_STEPS: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Severity {
+    Critical,
+    High,
+    Low,
+    Clean,
+}
+
+impl Severity {
+    /// Order of the toggle keys 1 - 4
+    const ALL: [Severity; 4] = [
+        Severity::Critical,
+        Severity::High,
+        Severity::Low,
+        Severity::Clean,
+    ];
+
+    /// Accounts with an unknown count are rated low, because the lists without counts are usually
+    /// small internal ones
+    fn of(account: &Account) -> Self {
+        match account.count {
+            _ if !account.pwned => Severity::Clean,
+            Some(count) if count >= CRITICAL_COUNT => Severity::Critical,
+            Some(count) if count >= HIGH_COUNT => Severity::High,
+            _ => Severity::Low,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::High => "high",
+            Severity::Low => "low",
+            Severity::Clean => "clean",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Severity::Critical => Color::Red,
+            Severity::High => Color::LightRed,
+            Severity::Low => Color::Yellow,
+            Severity::Clean => Color::Green,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Message {
+    Log(String),
+    ScanStart(u64, ProgressUnit),
+    Advance(u64),
+    ScanFinish,
+    Start {
+        corpora: Vec<String>,
+        accounts: usize,
+    },
+    Account(Account),
+    Finish(Summary),
+}
+
+/// Interactive interface drawn on the standard error, so a structured report could still be
+/// redirected. It runs in its own thread and receives the progress, log messages and report events.
+#[derive(Debug)]
+pub struct Tui {
+    sender: Sender<Message>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Tui {
+    /// Take over the terminal. The log messages are shown inside the interface until `wait`.
+    pub fn start(cancel: CancellationToken) -> io::Result<Self> {
+        if !io::stderr().is_terminal() {
+            return Err(io::Error::other("The standard error is not a terminal"));
+        }
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let log_sender = sender.clone();
+        logger::capture(move |message| {
+            // the interface could already be closed by the user
+            let _ = log_sender.send(Message::Log(message));
+        });
+
+        let thread = thread::spawn(move || show(&receiver, &cancel));
+        Ok(Tui { sender, thread })
+    }
+
+    pub fn progress(&self) -> TuiProgress {
+        TuiProgress {
+            sender: self.sender.clone(),
+            threshold: 1,
+            pending: 0,
+        }
+    }
+
+    /// Send the events of the report for browsing
+    pub fn report(&self, report: &StructuredReport<'_>) {
+        for event in report.events() {
+            let message = match event {
+                ReportEvent::Start { corpora, accounts } => Message::Start {
+                    corpora: corpora.iter().map(|name| name.to_string()).collect(),
+                    accounts,
+                },
+                ReportEvent::Account { account } => Message::Account(account.clone()),
+                // the accounts already show their profile
+                ReportEvent::Input { .. } => continue,
+                ReportEvent::Finish { summary } => Message::Finish(summary.clone()),
+            };
+
+            self.send(message);
+        }
+    }
+
+    /// Wait until the user closes the interface and restore the terminal
+    pub fn wait(self) -> io::Result<()> {
+        logger::release();
+
+        // signals the interface that no more events will follow
+        drop(self.sender);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Interface crashed")))
+    }
+
+    fn send(&self, message: Message) {
+        // the interface could already be closed by the user
+        let _ = self.sender.send(message);
+    }
+}
+
+/// Forwards the scan progress to the interface
+pub struct TuiProgress {
+    sender: Sender<Message>,
+    /// Collected units before an update is sent
+    threshold: u64,
+    pending: u64,
+}
+
+impl ProgressSink for TuiProgress {
+    fn start(&mut self, total: u64, unit: ProgressUnit) {
+        self.threshold = (total / PROGRESS_STEPS).max(1);
+        self.pending = 0;
+        let _ = self.sender.send(Message::ScanStart(total, unit));
+    }
+
+    fn advance(&mut self, delta: u64) {
+        self.pending += delta;
+        if self.pending >= self.threshold {
+            let _ = self.sender.send(Message::Advance(self.pending));
+            self.pending = 0;
+        }
+    }
+
+    fn finish(&mut self) {
+        let _ = self.sender.send(Message::Advance(self.pending));
+        let _ = self.sender.send(Message::ScanFinish);
+        self.pending = 0;
+    }
+}
+
+fn show(receiver: &Receiver<Message>, cancel: &CancellationToken) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+    let result = event_loop(&mut terminal, receiver, cancel);
+
+    // restore the terminal even if drawing failed
+    disable_raw_mode()?;
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    receiver: &Receiver<Message>,
+    cancel: &CancellationToken,
+) -> io::Result<()> {
+    let mut app = App::default();
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(message) => app.apply(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    app.closed = true;
+                    break;
+                }
+            }
+        }
+
+        terminal.draw(|frame| app.draw(frame))?;
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && app.handle_key(key, cancel) == Action::Quit {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Quit,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    total: u64,
+    done: u64,
+    unit: Option<ProgressUnit>,
+}
+
+#[derive(Debug, Default)]
+struct App {
+    progress: Progress,
+    /// Number of started scans - one for each hash list
+    scans: usize,
+    corpora: Vec<String>,
+    checked: usize,
+    accounts: Vec<Account>,
+    summary: Option<Summary>,
+    log: VecDeque<String>,
+    filter: String,
+    searching: bool,
+    hidden: Vec<Severity>,
+    list: ListState,
+    /// No more events will follow - quitting doesn't cancel anything
+    closed: bool,
+    cancelled: bool,
+}
+
+impl App {
+    fn apply(&mut self, message: Message) {
+        match message {
+            Message::Log(line) => {
+                if self.log.len() == LOG_LINES {
+                    self.log.pop_front();
+                }
+
+                self.log.push_back(line);
+            }
+            Message::ScanStart(total, unit) => {
+                self.scans += 1;
+                self.progress = Progress {
+                    total,
+                    done: 0,
+                    unit: Some(unit),
+                };
+            }
+            Message::Advance(delta) => self.progress.done += delta,
+            Message::ScanFinish => self.progress.done = self.progress.total,
+            Message::Start { corpora, accounts } => {
+                self.corpora = corpora;
+                self.checked = accounts;
+            }
+            Message::Account(account) => {
+                self.accounts.push(account);
+                if self.list.selected().is_none() {
+                    self.list.select(Some(0));
+                }
+            }
+            Message::Finish(summary) => self.summary = Some(summary),
+        }
+    }
+
+    /// Accounts matching the search and the shown severities
+    fn visible(&self) -> Vec<&Account> {
+        let filter = self.filter.to_lowercase();
+        self.accounts
+            .iter()
+            .filter(|account| !self.hidden.contains(&Severity::of(account)))
+            .filter(|account| {
+                account.url.to_lowercase().contains(&filter)
+                    || account.username.to_lowercase().contains(&filter)
+                    || account
+                        .tags
+                        .iter()
+                        .any(|tag| tag.to_lowercase().contains(&filter))
+            })
+            .collect()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, cancel: &CancellationToken) -> Action {
+        if self.searching {
+            match key.code {
+                KeyCode::Char(char) => self.filter.push(char),
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.searching = false;
+                }
+                KeyCode::Enter => self.searching = false,
+                _ => {}
+            }
+
+            self.list.select(Some(0));
+            return Action::Continue;
+        }
+
+        let interrupt = key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL;
+        match key.code {
+            _ if interrupt || key.code == KeyCode::Char('q') || key.code == KeyCode::Esc => {
+                if self.closed {
+                    return Action::Quit;
+                }
+
+                // keep the interface open to browse the partial results
+                cancel.cancel();
+                self.cancelled = true;
+            }
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Char(digit @ '1'..='4') => {
+                let severity = Severity::ALL[digit as usize - '1' as usize];
+                match self.hidden.iter().position(|hidden| *hidden == severity) {
+                    Some(index) => {
+                        self.hidden.remove(index);
+                    }
+                    None => self.hidden.push(severity),
+                }
+
+                self.list.select(Some(0));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::PageUp => self.scroll(-(PAGE as isize)),
+            KeyCode::PageDown => self.scroll(PAGE as isize),
+            KeyCode::Home | KeyCode::Char('g') => self.list.select(Some(0)),
+            KeyCode::End | KeyCode::Char('G') => self.scroll(isize::MAX),
+            _ => {}
+        }
+
+        Action::Continue
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let last = self.visible().len().saturating_sub(1);
+        let current = self.list.selected().unwrap_or(0);
+        let next = current.saturating_add_signed(delta).min(last);
+        self.list.select(Some(next));
+    }
+
+    fn draw(&mut self, frame: &mut Frame<'_>) {
+        let [progress_area, list_area, log_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(self.gauge(), progress_area);
+
+        let visible = self.visible();
+        let title = if self.filter.is_empty() {
+            format!(" Findings {} of {} ", visible.len(), self.accounts.len())
+        } else {
+            format!(
+                " Findings {} of {} matching \"{}\" ",
+                visible.len(),
+                self.accounts.len(),
+                self.filter
+            )
+        };
+
+        // owned items, because the selection is updated while rendering
+        let items: Vec<_> = visible.into_iter().map(account_item).collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let height = log_area.height.saturating_sub(2) as usize;
+        let lines: Vec<_> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(height))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        let log =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Log "));
+        frame.render_widget(log, log_area);
+
+        frame.render_widget(Paragraph::new(self.help()), help_area);
+    }
+
+    fn gauge(&self) -> Gauge<'_> {
+        let Progress { total, done, unit } = self.progress;
+        let ratio = if total == 0 {
+            0.0
+        } else {
+            (done as f64 / total as f64).min(1.0)
+        };
+
+        let label = match (&self.summary, unit) {
+            (
+                Some(Summary {
+                    estimate: Some(estimate),
+                    complete: true,
+                    ..
+                }),
+                _,
+            ) => format!(
+                "Estimate - {} to {} of {} accounts pwned (sample of {})",
+                estimate.pwned_low, estimate.pwned_high, estimate.population, estimate.sampled
+            ),
+            (Some(summary), _) if summary.complete => format!(
+                "Finished - {} of {} accounts pwned in {}",
+                summary.pwned,
+                summary.checked,
+                self.corpora.join(", ")
+            ),
+            (Some(summary), _) => format!(
+                "Cancelled - {} of {} accounts pwned in the scanned part",
+                summary.pwned, summary.checked
+            ),
+            _ if self.cancelled => "Cancelling...".to_string(),
+            (None, Some(ProgressUnit::Bytes)) => format!(
+                "Scan {} - {} of {} MiB",
+                self.scans,
+                done >> 20,
+                total >> 20
+            ),
+            (None, Some(ProgressUnit::Items)) => {
+                format!("Scan {} - {} of {} hashes", self.scans, done, total)
+            }
+            (None, None) => "Reading saved passwords...".to_string(),
+        };
+
+        let ratio = if self.summary.is_some() { 1.0 } else { ratio };
+        Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(" Progress "))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio)
+            .label(label)
+    }
+
+    fn help(&self) -> Line<'_> {
+        if self.searching {
+            return Line::from(format!(
+                "Search: {}_  (Enter to apply, Esc to clear)",
+                self.filter
+            ));
+        }
+
+        let quit = if self.closed { "q quit" } else { "q cancel" };
+        let mut spans = vec![Span::raw(format!("{}  / search  ", quit))];
+        for (index, severity) in Severity::ALL.iter().enumerate() {
+            let mut style = Style::default().fg(severity.color());
+            if self.hidden.contains(severity) {
+                style = style.add_modifier(Modifier::CROSSED_OUT | Modifier::DIM);
+            }
+
+            spans.push(Span::styled(
+                format!("{} {}  ", index + 1, severity.name()),
+                style,
+            ));
+        }
+
+        spans.push(Span::raw("↑↓ PgUp PgDn scroll"));
+        Line::from(spans)
+    }
+}
+
+fn account_item(account: &Account) -> ListItem<'static> {
+    let severity = Severity::of(account);
+    let count = match account.count {
+        Some(count) => format!("{}x", count),
+        None if account.pwned => "unknown count".to_string(),
+        None => String::new(),
+    };
+
+    ListItem::new(Line::from(vec![
+        Span::styled(
+            format!("{:<9}", severity.name()),
+            Style::default().fg(severity.color()),
+        ),
+        Span::raw(format!("{}@{}  ", account.username, account.url)),
+        Span::styled(count, Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(format!("  {}", provenance(account))),
+        Span::styled(
+            format!("  {}", account.tags.join(", ")),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    ]))
+}
+
+/// Matching lists with their snapshot date - the count per list is only shown if it differs from
+/// the single count of the account
+fn provenance(account: &Account) -> String {
+    let found: Vec<_> = account
+        .matches
+        .iter()
+        .map(|found| {
+            let mut text = found.corpus.clone();
+            if let Some(form) = &found.form {
+                text.push_str(&format!(" ({} form)", form));
+            }
+
+            if account.matches.len() > 1 {
+                match found.count {
+                    Some(count) => text.push_str(&format!(" {}x", count)),
+                    None => text.push_str(" unknown count"),
+                }
+            }
+
+            // the RFC 3339 time starts with the date
+            if let Some(snapshot) = &found.snapshot {
+                text.push_str(&format!(" ({})", &snapshot[..snapshot.len().min(10)]));
+            }
+
+            text
+        })
+        .collect();
+
+    found.join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::Match;
+
+    fn account(username: &str, count: Option<u64>) -> Account {
+        Account {
+            url: "https://example.com/".to_string(),
+            username: username.to_string(),
+            pwned: count != Some(0),
+            count: count.filter(|count| *count > 0),
+            corpora: vec!["hibp.txt".to_string()],
+            tags: Vec::new(),
+            matches: vec![Match {
+                corpus: "hibp.txt".to_string(),
+                snapshot: None,
+                count,
+                form: None,
+            }],
+            variants: Vec::new(),
+            profile: None,
+            created: None,
+            last_used: None,
+            changed: None,
+            newly_pwned: false,
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn app() -> App {
+        let mut app = App::default();
+        for (username, count) in [
+            ("admin", Some(50_000)),
+            ("user", Some(150)),
+            ("guest", None),
+            ("clean", Some(0)),
+        ] {
+            app.apply(Message::Account(account(username, count)));
+        }
+
+        app
+    }
+
+    fn usernames(app: &App) -> Vec<&str> {
+        app.visible()
+            .iter()
+            .map(|account| account.username.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_severity() {
+        let app = app();
+        let severities: Vec<_> = app.accounts.iter().map(Severity::of).collect();
+        assert_eq!(
+            severities,
+            [
+                Severity::Critical,
+                Severity::High,
+                Severity::Low,
+                Severity::Clean
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toggle_severity() {
+        let mut app = app();
+        let cancel = CancellationToken::default();
+
+        app.handle_key(key(KeyCode::Char('4')), &cancel);
+        app.handle_key(key(KeyCode::Char('1')), &cancel);
+        assert_eq!(usernames(&app), ["user", "guest"]);
+
+        app.handle_key(key(KeyCode::Char('1')), &cancel);
+        assert_eq!(usernames(&app), ["admin", "user", "guest"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut app = app();
+        let cancel = CancellationToken::default();
+
+        for code in [
+            KeyCode::Char('/'),
+            KeyCode::Char('A'),
+            KeyCode::Char('d'),
+            KeyCode::Enter,
+        ] {
+            app.handle_key(key(code), &cancel);
+        }
+
+        assert!(!app.searching);
+        assert_eq!(usernames(&app), ["admin"]);
+
+        // keys are used for the search until it's applied
+        app.handle_key(key(KeyCode::Char('/')), &cancel);
+        app.handle_key(key(KeyCode::Char('q')), &cancel);
+        assert!(!cancel.is_cancelled());
+
+        app.handle_key(key(KeyCode::Esc), &cancel);
+        assert_eq!(app.visible().len(), 4);
+    }
+
+    #[test]
+    fn test_scroll_bounds() {
+        let mut app = app();
+        let cancel = CancellationToken::default();
+
+        app.handle_key(key(KeyCode::PageDown), &cancel);
+        assert_eq!(app.list.selected(), Some(3));
+
+        app.handle_key(key(KeyCode::Up), &cancel);
+        app.handle_key(key(KeyCode::PageUp), &cancel);
+        assert_eq!(app.list.selected(), Some(0));
+    }
+
+    #[test]
+    fn test_quit_cancels_running_scan() {
+        let mut app = app();
+        let cancel = CancellationToken::default();
+
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('q')), &cancel),
+            Action::Continue
+        );
+        assert!(cancel.is_cancelled());
+
+        app.closed = true;
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('q')), &cancel),
+            Action::Quit
+        );
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut pwned = account("admin", Some(50_000));
+        assert_eq!(provenance(&pwned), "hibp.txt");
+
+        pwned.matches.push(Match {
+            corpus: "leaks.txt".to_string(),
+            snapshot: Some("2024-05-01T00:00:00Z".to_string()),
+            count: None,
+            form: None,
+        });
+        assert_eq!(
+            provenance(&pwned),
+            "hibp.txt 50000x, leaks.txt unknown count (2024-05-01)"
+        );
+    }
+
+    #[test]
+    fn test_progress_batched() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut progress = TuiProgress {
+            sender,
+            threshold: 1,
+            pending: 0,
+        };
+
+        progress.start(10_000, ProgressUnit::Bytes);
+        for _ in 0..10_000 {
+            progress.advance(1);
+        }
+        progress.finish();
+
+        let mut app = App::default();
+        let messages: Vec<_> = receiver.try_iter().collect();
+        assert!(messages.len() < 1100);
+        for message in messages {
+            app.apply(message);
+        }
+
+        assert_eq!(app.progress.done, 10_000);
+        assert_eq!(app.scans, 1);
+    }
+}
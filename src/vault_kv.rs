@@ -0,0 +1,469 @@
+use std::{io, str::FromStr};
+
+use log::{debug, warn};
+use secstr::SecStr;
+use serde_json::{json, Map, Value};
+use ureq::Agent;
+
+use crate::{
+    agent,
+    extract::{self, Candidate},
+};
+
+/// Status of missing secrets and empty directories
+const NOT_FOUND: u16 = 404;
+
+/// Version of the KV secrets engine - the paths of the API differ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KvVersion {
+    V1,
+    /// Versioned secrets with the data and metadata below separate paths
+    V2,
+}
+
+impl FromStr for KvVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "1" => Ok(KvVersion::V1),
+            "2" => Ok(KvVersion::V2),
+            _ => Err(format!("Unknown KV version {}", value)),
+        }
+    }
+}
+
+/// Authentication of the client
+pub enum Auth {
+    Token(SecStr),
+    /// Login using the AppRole auth method mounted at `approle`
+    AppRole {
+        role_id: String,
+        secret_id: SecStr,
+    },
+}
+
+/// Secrets that could be enumerated like the KV engine
+pub trait KvStore {
+    /// Keys below the directory - sub directories end with a slash
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+
+    /// Fields of the latest version of the secret - empty if it doesn't exist
+    fn read(&self, path: &str) -> io::Result<Map<String, Value>>;
+}
+
+/// Client of a KV secrets engine mount using the HTTP API of HashiCorp Vault
+pub struct VaultClient {
+    /// Address without the trailing slash (ex: `https://vault.example.com:8200`)
+    address: String,
+    mount: String,
+    version: KvVersion,
+    agent: Agent,
+    token: SecStr,
+}
+
+impl VaultClient {
+    /// Client with the given token or the token of the AppRole login
+    pub fn login(address: &str, mount: &str, version: KvVersion, auth: Auth) -> io::Result<Self> {
+        let address = address.trim_end_matches('/').to_string();
+        // connections are re-used between the requests
+        let agent = agent::agent_builder().build();
+        let token = match auth {
+            Auth::Token(token) => token,
+            Auth::AppRole { role_id, secret_id } => {
+                let body = json!({
+                    "role_id": role_id,
+                    "secret_id": String::from_utf8_lossy(secret_id.unsecure()),
+                });
+                let response: Value = agent
+                    .post(&format!("{}/v1/auth/approle/login", address))
+                    .send_json(body)
+                    .map_err(to_io_error)?
+                    .into_json()?;
+                let token = response
+                    .pointer("/auth/client_token")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| io::Error::other("Missing client token of the AppRole login"))?;
+                SecStr::from(token)
+            }
+        };
+
+        Ok(VaultClient {
+            address,
+            mount: mount.trim_matches('/').to_string(),
+            version,
+            agent,
+            token,
+        })
+    }
+
+    pub fn mount(&self) -> &str {
+        &self.mount
+    }
+
+    /// Response body of the API path - none if it doesn't exist
+    fn get(&self, path: &str) -> io::Result<Option<Value>> {
+        let token = String::from_utf8_lossy(self.token.unsecure());
+        let response = self
+            .agent
+            .get(&format!("{}/v1/{}", self.address, path))
+            .set("X-Vault-Token", &token)
+            .call();
+        match response {
+            Ok(response) => Ok(Some(response.into_json()?)),
+            Err(ureq::Error::Status(NOT_FOUND, _)) => Ok(None),
+            Err(err) => Err(to_io_error(err)),
+        }
+    }
+}
+
+impl KvStore for VaultClient {
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let path = match self.version {
+            KvVersion::V1 => format!("{}/{}?list=true", self.mount, dir),
+            KvVersion::V2 => format!("{}/metadata/{}?list=true", self.mount, dir),
+        };
+
+        let body = self.get(&path)?;
+        let keys = body
+            .as_ref()
+            .and_then(|body| body.pointer("/data/keys"))
+            .and_then(Value::as_array)
+            .map_or_else(Vec::new, |keys| {
+                keys.iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            });
+        Ok(keys)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Map<String, Value>> {
+        let (path, fields) = match self.version {
+            KvVersion::V1 => (format!("{}/{}", self.mount, path), "/data"),
+            KvVersion::V2 => (format!("{}/data/{}", self.mount, path), "/data/data"),
+        };
+
+        // the latest version of KV v2 has no data if it was deleted
+        let body = self.get(&path)?;
+        Ok(body
+            .as_ref()
+            .and_then(|body| body.pointer(fields))
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Safety mode of `--confirm-count` against noisy audits of production stores. Both the read
+/// secrets and the checked credentials are limited, because a secret could contain multiple
+/// password fields.
+#[derive(Clone, Copy, Debug)]
+pub struct ConfirmCount {
+    pub limit: usize,
+    /// Continue even above the limit (`--acknowledge`)
+    pub acknowledged: bool,
+}
+
+impl ConfirmCount {
+    /// The number of secrets or credentials is within the limit or acknowledged
+    pub fn allows(self, count: usize) -> bool {
+        self.acknowledged || count <= self.limit
+    }
+}
+
+/// Candidate passwords of the enumerated secrets
+#[derive(Default)]
+pub struct KvScan {
+    pub candidates: Vec<Candidate>,
+    /// Number of found secrets including the unreadable ones
+    pub secrets: usize,
+    /// Secrets that couldn't be read (ex: denied by the policy)
+    pub skipped: usize,
+}
+
+/// Paths of the secrets below the prefix
+#[derive(Default)]
+pub struct SecretPaths {
+    pub paths: Vec<String>,
+    /// Directories that couldn't be listed (ex: denied by the policy)
+    pub skipped: usize,
+}
+
+/// Paths of all secrets below the prefix without reading them. Only a failure to list the prefix
+/// itself fails, so a policy that denies some of the directories doesn't stop the audit.
+pub fn list_secrets(store: &dyn KvStore, mount: &str, prefix: &str) -> io::Result<SecretPaths> {
+    let prefix = prefix.trim_matches('/');
+    let dir = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", prefix)
+    };
+
+    let mut secrets = SecretPaths::default();
+    let keys = store.list(&dir)?;
+    if keys.is_empty() && !prefix.is_empty() {
+        // the prefix could be a single secret instead of a directory
+        secrets.paths.push(prefix.to_string());
+    }
+
+    walk(store, mount, &dir, keys, &mut secrets);
+    Ok(secrets)
+}
+
+fn walk(store: &dyn KvStore, mount: &str, dir: &str, keys: Vec<String>, secrets: &mut SecretPaths) {
+    for key in keys {
+        let path = format!("{}{}", dir, key);
+        if !key.ends_with('/') {
+            secrets.paths.push(path);
+            continue;
+        }
+
+        match store.list(&path) {
+            Ok(keys) => walk(store, mount, &path, keys, secrets),
+            Err(err) => {
+                warn!("Cannot list the secrets of {}/{} {}", mount, path, err);
+                secrets.skipped += 1;
+            }
+        }
+    }
+}
+
+/// Candidate passwords of the secrets labeled by their path (ex: `secret/apps/db:password`). The
+/// given fields are passwords or otherwise the fields with password like names. Unreadable secrets
+/// are skipped.
+pub fn read_secrets(
+    store: &dyn KvStore,
+    mount: &str,
+    paths: &[String],
+    fields: &[String],
+) -> KvScan {
+    let mut scan = KvScan::default();
+    for path in paths {
+        read_secret(store, mount, path, fields, &mut scan);
+    }
+
+    scan
+}
+
+fn read_secret(store: &dyn KvStore, mount: &str, path: &str, names: &[String], scan: &mut KvScan) {
+    let fields = match store.read(path) {
+        Ok(fields) => fields,
+        Err(err) => {
+            warn!("Cannot read the secret {}/{} {}", mount, path, err);
+            scan.secrets += 1;
+            scan.skipped += 1;
+            return;
+        }
+    };
+
+    if fields.is_empty() {
+        debug!("Secret {}/{} has no data", mount, path);
+        return;
+    }
+
+    scan.secrets += 1;
+    for (field, value) in fields {
+        let selected = if names.is_empty() {
+            extract::is_password_key(field.as_bytes())
+        } else {
+            names.iter().any(|name| name.eq_ignore_ascii_case(&field))
+        };
+
+        let password = match value {
+            Value::String(password) => SecStr::from(password),
+            Value::Number(password) => SecStr::from(password.to_string()),
+            _ => continue,
+        };
+
+        if selected && !password.unsecure().is_empty() {
+            scan.candidates.push(Candidate {
+                label: format!("{}/{}:{}", mount, path, field),
+                password,
+            });
+        }
+    }
+}
+
+fn to_io_error(err: ureq::Error) -> io::Error {
+    match err {
+        ureq::Error::Status(code, response) => {
+            // Vault explains the failure (ex: permission denied)
+            let errors = response.into_json::<Value>().ok().and_then(|body| {
+                let errors: Vec<_> = body
+                    .get("errors")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect();
+                Some(errors.join(", "))
+            });
+
+            match errors {
+                Some(errors) if !errors.is_empty() => {
+                    io::Error::other(format!("HTTP status {} {}", code, errors))
+                }
+                _ => io::Error::other(format!("HTTP status {}", code)),
+            }
+        }
+        ureq::Error::Transport(transport) => io::Error::other(transport),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Secrets by their path - listing and reading like the KV engine
+    struct MemoryStore {
+        secrets: BTreeMap<&'static str, Value>,
+        denied: &'static str,
+    }
+
+    impl KvStore for MemoryStore {
+        fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+            if dir.starts_with(self.denied) {
+                return Err(io::Error::other("HTTP status 403 permission denied"));
+            }
+
+            let mut keys: Vec<String> = self
+                .secrets
+                .keys()
+                .filter_map(|path| path.strip_prefix(dir))
+                .map(|rest| match rest.find('/') {
+                    Some(end) => rest[..=end].to_string(),
+                    None => rest.to_string(),
+                })
+                .collect();
+            keys.dedup();
+            Ok(keys)
+        }
+
+        fn read(&self, path: &str) -> io::Result<Map<String, Value>> {
+            if path.starts_with(self.denied) {
+                return Err(io::Error::other("HTTP status 403 permission denied"));
+            }
+
+            let fields = self.secrets.get(path).and_then(Value::as_object);
+            Ok(fields.cloned().unwrap_or_default())
+        }
+    }
+
+    fn store() -> MemoryStore {
+        let mut secrets = BTreeMap::new();
+        secrets.insert(
+            "apps/db",
+            json!({"username": "admin", "password": "hunter2", "port": 5432}),
+        );
+        secrets.insert(
+            "apps/cache/redis",
+            json!({"redis_pwd": 123456, "empty_password": ""}),
+        );
+        secrets.insert(
+            "apps/api",
+            json!({"token": "abc", "nested": {"password": "x"}}),
+        );
+        secrets.insert("private/root", json!({"password": "secret"}));
+        MemoryStore {
+            secrets,
+            denied: "private/",
+        }
+    }
+
+    fn labels(scan: &KvScan) -> Vec<(&str, &[u8])> {
+        scan.candidates
+            .iter()
+            .map(|candidate| (candidate.label.as_str(), candidate.password.unsecure()))
+            .collect()
+    }
+
+    fn scan(prefix: &str, fields: &[String]) -> KvScan {
+        let store = store();
+        let secrets = list_secrets(&store, "secret", prefix).unwrap();
+        read_secrets(&store, "secret", &secrets.paths, fields)
+    }
+
+    #[test]
+    fn test_list() {
+        let secrets = list_secrets(&store(), "secret", "").unwrap();
+        assert_eq!(
+            secrets.paths,
+            vec!["apps/api", "apps/cache/redis", "apps/db"]
+        );
+        assert_eq!(secrets.skipped, 1);
+
+        // a single secret instead of a directory
+        let secrets = list_secrets(&store(), "secret", "/apps/db").unwrap();
+        assert_eq!(secrets.paths, vec!["apps/db"]);
+
+        assert!(list_secrets(&store(), "secret", "private").is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let result = scan("", &[]);
+        assert_eq!(
+            labels(&result),
+            vec![
+                ("secret/apps/cache/redis:redis_pwd", &b"123456"[..]),
+                ("secret/apps/db:password", b"hunter2")
+            ]
+        );
+        assert_eq!(result.secrets, 3);
+        assert_eq!(result.skipped, 0);
+
+        let fields = ["TOKEN".to_string(), "username".to_string()];
+        assert_eq!(
+            labels(&scan("apps/", &fields)),
+            vec![
+                ("secret/apps/api:token", &b"abc"[..]),
+                ("secret/apps/db:username", b"admin")
+            ]
+        );
+
+        let denied = ["private/root".to_string(), "apps/missing".to_string()];
+        let result = read_secrets(&store(), "secret", &denied, &[]);
+        assert!(result.candidates.is_empty());
+        assert_eq!(result.secrets, 1);
+        assert_eq!(result.skipped, 1);
+    }
+
+    #[test]
+    fn test_confirm_count() {
+        let refused = ConfirmCount {
+            limit: 2,
+            acknowledged: false,
+        };
+        let store = store();
+        let secrets = list_secrets(&store, "secret", "").unwrap();
+        assert!(!refused.allows(secrets.paths.len()));
+
+        // a single secret with more credentials than the limit
+        let single = ConfirmCount {
+            limit: 1,
+            ..refused
+        };
+        let fields = ["username".to_string(), "password".to_string()];
+        let secrets = list_secrets(&store, "secret", "apps/db").unwrap();
+        assert!(single.allows(secrets.paths.len()));
+        let scan = read_secrets(&store, "secret", &secrets.paths, &fields);
+        assert!(!single.allows(scan.candidates.len()));
+
+        let acknowledged = ConfirmCount {
+            acknowledged: true,
+            ..single
+        };
+        assert!(acknowledged.allows(scan.candidates.len()));
+        assert!(acknowledged.allows(usize::MAX));
+    }
+
+    #[test]
+    fn test_kv_version() {
+        assert_eq!("1".parse(), Ok(KvVersion::V1));
+        assert_eq!("2".parse(), Ok(KvVersion::V2));
+        assert!("3".parse::<KvVersion>().is_err());
+    }
+}
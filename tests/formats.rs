@@ -0,0 +1,217 @@
+//! Golden tests of the exported formats against the real binary. Each fixture contains a few
+//! anonymized accounts and the expected report lines are stored in `tests/golden`.
+
+use std::{ffi::OsStr, fs, path::PathBuf};
+
+use assert_cmd::Command;
+
+const REPORT_PREFIX: &str = "Your password";
+
+fn fixture(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect()
+}
+
+fn golden(name: &str) -> String {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect();
+    fs::read_to_string(path).unwrap()
+}
+
+/// Run the binary against the fixtures and only keep the report lines
+fn report(export: &str, corpora: &[&str], options: &[&str]) -> String {
+    let corpora: Vec<_> = corpora.iter().map(|corpus| fixture(corpus)).collect();
+    report_paths(export, &corpora, options)
+}
+
+/// Like `report`, but for hash files outside of the fixtures (ex: converted indexes). Only the
+/// report lines are kept, because the other messages are written to stdout too.
+fn report_paths(export: &str, corpora: &[impl AsRef<OsStr>], options: &[&str]) -> String {
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg(fixture(export))
+        .args(corpora)
+        .args(options)
+        // the dates of the Firefox entries are shown in the local timezone
+        .env("TZ", "UTC")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let stdout = String::from_utf8(output).unwrap();
+    stdout
+        // the progress bar redraws the line using carriage returns
+        .split(&['\n', '\r'][..])
+        .filter(|line| line.starts_with(REPORT_PREFIX))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn assert_golden(export: &str, corpora: &[&str], expected: &str) {
+    assert_eq!(report(export, corpora, &[]), golden(expected), "{}", export);
+}
+
+#[test]
+fn test_chromium() {
+    assert_golden("chromium.csv", &["corpus.txt"], "chromium.txt");
+}
+
+#[test]
+fn test_firefox() {
+    assert_golden("firefox.csv", &["corpus.txt"], "firefox.txt");
+}
+
+#[test]
+fn test_lastpass() {
+    assert_golden("lastpass.csv", &["corpus.txt"], "lastpass.txt");
+}
+
+#[test]
+fn test_bitwarden_json() {
+    assert_golden("bitwarden.json", &["corpus.txt"], "bitwarden.txt");
+}
+
+#[test]
+fn test_keepassxc() {
+    assert_golden("keepassxc.csv", &["corpus.txt"], "keepassxc.txt");
+}
+
+#[test]
+fn test_dashlane() {
+    assert_golden("dashlane.csv", &["corpus.txt"], "dashlane.txt");
+}
+
+#[test]
+fn test_safari() {
+    assert_golden("safari.csv", &["corpus.txt"], "safari.txt");
+}
+
+#[test]
+fn test_1password() {
+    assert_golden("1password.csv", &["corpus.txt"], "1password.txt");
+}
+
+#[test]
+fn test_bitwarden_csv() {
+    assert_golden("bitwarden.csv", &["corpus.txt"], "bitwarden_csv.txt");
+}
+
+#[test]
+fn test_unsupported_detected() {
+    use std::{env, process};
+
+    // KeePass database header - the passwords in it are encrypted
+    let path = env::temp_dir().join(format!("pwned-check-detect-{}.kdbx", process::id()));
+    fs::write(&path, b"\x03\xD9\xA2\x9A\x67\xFB\x4B\xB5\x01\x00\x04\x00").unwrap();
+
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg(&path)
+        .arg(fixture("corpus.txt"))
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut combined = String::from_utf8(output.stdout).unwrap();
+    combined.push_str(&String::from_utf8(output.stderr).unwrap());
+    assert!(
+        combined.contains("Detected a KeePass database (KDBX) - export it as CSV"),
+        "{}",
+        combined
+    );
+    assert!(!combined.contains(REPORT_PREFIX), "{}", combined);
+}
+
+#[test]
+fn test_multiple_corpora() {
+    assert_golden(
+        "chromium.csv",
+        &["corpus.txt", "internal.txt"],
+        "chromium_multiple.txt",
+    );
+}
+
+#[test]
+fn test_strategies_same_report() {
+    for strategy in &["linear", "hex", "interpolation"] {
+        let report = report("firefox.csv", &["corpus.txt"], &["--strategy", strategy]);
+        assert_eq!(report, golden("firefox.txt"), "{}", strategy);
+    }
+}
+
+#[test]
+fn test_final_record() {
+    // the last line is the hash of bob's password
+    for corpus in &["corpus_no_newline.txt", "corpus_crlf.txt"] {
+        for strategy in &["linear", "hex", "interpolation"] {
+            let report = report("chromium.csv", &[corpus], &["--strategy", strategy]);
+            assert_eq!(report, golden("chromium.txt"), "{} {}", corpus, strategy);
+        }
+    }
+}
+
+#[test]
+fn test_piped_without_progress() {
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg(fixture("chromium.csv"))
+        .arg(fixture("corpus.txt"))
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // the progress bar is only drawn on terminals
+    let stdout = String::from_utf8(output).unwrap();
+    assert!(!stdout.contains('\r'), "{:?}", stdout);
+    assert!(stdout.lines().any(|line| line.starts_with(REPORT_PREFIX)));
+}
+
+#[cfg(feature = "index")]
+#[test]
+fn test_index_same_report() {
+    use std::{env, process};
+
+    let index = env::temp_dir().join(format!("pwned-check-index-{}.fst", process::id()));
+    Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg("convert")
+        .arg(fixture("corpus.txt"))
+        .arg(&index)
+        .assert()
+        .success();
+
+    let report = report_paths("firefox.csv", &[&index], &[]);
+    fs::remove_file(&index).unwrap();
+    assert_eq!(report, golden("firefox.txt"));
+}
+
+#[cfg(feature = "index")]
+#[test]
+fn test_index_final_record() {
+    use std::{env, process};
+
+    for corpus in &["corpus_no_newline.txt", "corpus_crlf.txt"] {
+        let index = env::temp_dir().join(format!(
+            "pwned-check-final-{}-{}.fst",
+            process::id(),
+            corpus
+        ));
+        Command::cargo_bin("pwned-check")
+            .unwrap()
+            .arg("convert")
+            .arg(fixture(corpus))
+            .arg(&index)
+            .assert()
+            .success();
+
+        let report = report_paths("chromium.csv", &[&index], &[]);
+        fs::remove_file(&index).unwrap();
+        assert_eq!(report, golden("chromium.txt"), "{}", corpus);
+    }
+}
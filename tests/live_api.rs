@@ -0,0 +1,51 @@
+//! Scans against the live HIBP range API. They depend on the network and the current data of the
+//! API, so they are ignored by default: `cargo test --features live-tests -- --ignored`
+#![cfg(feature = "live-tests")]
+
+use std::path::PathBuf;
+
+use assert_cmd::Command;
+
+const API_URL: &str = "https://api.pwnedpasswords.com/range/";
+
+const REPORT_PREFIX: &str = "Your password";
+
+#[test]
+#[ignore]
+fn test_live_scan() {
+    let export: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "chromium.csv",
+    ]
+    .iter()
+    .collect();
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg(export)
+        .arg(API_URL)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // the counts grow with each update of the API, so only the accounts are compared
+    let stdout = String::from_utf8(output).unwrap();
+    let reported: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.starts_with(REPORT_PREFIX))
+        .collect();
+    for account in [
+        "alice@https://example.com/",
+        "bob@https://shop.example.net/",
+    ] {
+        assert!(
+            reported.iter().any(|line| line.contains(account)),
+            "{} missing in {:?}",
+            account,
+            reported
+        );
+    }
+}
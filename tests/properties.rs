@@ -0,0 +1,374 @@
+//! Property tests of the glue code in `main.rs` against the real binary. Random exports and
+//! corpora are generated for fixed seeds, so a failure could be reproduced using the printed seed.
+//! The exit code, the JSON summary and the text report have to agree with the generated matches.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use assert_cmd::Command;
+use data_encoding::HEXUPPER;
+use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use serde_json::Value;
+
+const SEEDS: u64 = 8;
+
+const STRATEGIES: [&str; 3] = ["linear", "hex", "interpolation"];
+
+/// Exit codes of the hook command - also of a check above --max-pwned and a failed check
+const PWNED_EXIT_CODE: i32 = 1;
+const CHECK_FAILED_EXIT_CODE: i32 = 2;
+
+/// Generated export with the expected highest count of each pwned account
+struct Fixture {
+    directory: PathBuf,
+    accounts: usize,
+    /// `username@url` of the pwned accounts and their count
+    pwned: BTreeMap<String, u64>,
+}
+
+impl Fixture {
+    fn generate(name: &str, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let directory =
+            env::temp_dir().join(format!("pwned-check-{}-{}-{}", name, process::id(), seed));
+        fs::create_dir_all(&directory).unwrap();
+
+        // a few passwords are reused by multiple accounts
+        let passwords: Vec<String> = (0..rng.gen_range(1..20))
+            .map(|_| {
+                let length = rng.gen_range(8..16);
+                (&mut rng)
+                    .sample_iter(&Alphanumeric)
+                    .take(length)
+                    .map(char::from)
+                    .collect()
+            })
+            .collect();
+
+        // each password is either pwned with a random count or clean
+        let counts: Vec<Option<u64>> = passwords
+            .iter()
+            .map(|_| rng.gen_bool(0.5).then(|| rng.gen_range(1..1_000_000)))
+            .collect();
+
+        let accounts = rng.gen_range(1..30);
+        let mut export = String::from("name,url,username,password\n");
+        let mut pwned = BTreeMap::new();
+        for account in 0..accounts {
+            let password = rng.gen_range(0..passwords.len());
+            // separate sites, so no accounts are collapsed as variants
+            let url = format!("https://site{}.example/", account);
+            let username = format!("user{}", account);
+            export.push_str(&format!(
+                "site{},{},{},{}\n",
+                account, url, username, passwords[password]
+            ));
+
+            if let Some(count) = counts[password] {
+                pwned.insert(format!("{}@{}", username, url), count);
+            }
+        }
+
+        // the unrelated hashes are the larger part of the corpus like the real one
+        let mut lines: Vec<(String, u64)> = passwords
+            .iter()
+            .zip(&counts)
+            .filter_map(|(password, count)| Some((sha1_hex(password.as_bytes()), (*count)?)))
+            .collect();
+        for _ in 0..rng.gen_range(0..500) {
+            let noise: [u8; 8] = rng.gen();
+            lines.push((sha1_hex(&noise), rng.gen_range(1..1_000)));
+        }
+        lines.sort_unstable();
+
+        let corpus: String = lines
+            .iter()
+            .map(|(hash, count)| format!("{}:{}\n", hash, count))
+            .collect();
+
+        fs::write(directory.join("export.csv"), export).unwrap();
+        fs::write(directory.join("corpus.txt"), corpus).unwrap();
+        Fixture {
+            directory,
+            accounts,
+            pwned,
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    HEXUPPER.encode(digest(&SHA1_FOR_LEGACY_USE_ONLY, data).as_ref())
+}
+
+/// Run the check of the export and return the exit code and standard output
+fn check(fixture: &Fixture, options: &[&str]) -> (Option<i32>, String) {
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .args(options)
+        .arg(fixture.path("export.csv"))
+        .arg(fixture.path("corpus.txt"))
+        .output()
+        .unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+/// Accounts and their counts of the text report lines
+fn parse_text(stdout: &str) -> BTreeMap<String, u64> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Your password for the following account "))
+        .map(|line| {
+            let (account, count) = line.split_once(" has been pwned ").unwrap();
+            let count = count.strip_suffix("x times").unwrap().parse().unwrap();
+            (account.to_string(), count)
+        })
+        .collect()
+}
+
+#[test]
+fn test_reports_agree() {
+    for seed in 0..SEEDS {
+        let fixture = Fixture::generate("reports", seed);
+        let strategy = STRATEGIES[seed as usize % STRATEGIES.len()];
+
+        // the check itself only fails on errors - pwned accounts are the expected result
+        let (code, stdout) = check(&fixture, &["--strategy", strategy]);
+        assert_eq!(code, Some(0), "seed {}", seed);
+        assert_eq!(parse_text(&stdout), fixture.pwned, "seed {}", seed);
+
+        let (code, stdout) = check(
+            &fixture,
+            &["--strategy", strategy, "--report-format", "json"],
+        );
+        assert_eq!(code, Some(0), "seed {}", seed);
+
+        let report: Value = serde_json::from_str(&stdout).unwrap();
+        let summary = &report["summary"];
+        assert_eq!(summary["checked"], fixture.accounts, "seed {}", seed);
+        assert_eq!(summary["pwned"], fixture.pwned.len(), "seed {}", seed);
+        assert_eq!(
+            summary["clean"],
+            fixture.accounts - fixture.pwned.len(),
+            "seed {}",
+            seed
+        );
+        assert_eq!(summary["complete"], true, "seed {}", seed);
+
+        // without --show-clean only the pwned accounts are listed
+        let accounts: BTreeMap<String, u64> = report["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|account| {
+                assert_eq!(account["pwned"], true, "seed {}", seed);
+                let name = format!(
+                    "{}@{}",
+                    account["username"].as_str().unwrap(),
+                    account["url"].as_str().unwrap()
+                );
+                (name, account["count"].as_u64().unwrap())
+            })
+            .collect();
+        assert_eq!(accounts, fixture.pwned, "seed {}", seed);
+
+        // sequential scans can't read more than the corpus
+        if let Some(scanned) = summary["bytes_scanned"].as_u64() {
+            let size = fs::metadata(fixture.path("corpus.txt")).unwrap().len();
+            assert!(scanned <= size, "seed {}", seed);
+        }
+    }
+}
+
+#[test]
+fn test_show_clean_lists_all() {
+    for seed in 0..SEEDS {
+        let fixture = Fixture::generate("clean", seed);
+        let (code, stdout) = check(&fixture, &["--report-format", "json", "--show-clean"]);
+        assert_eq!(code, Some(0), "seed {}", seed);
+
+        let report: Value = serde_json::from_str(&stdout).unwrap();
+        let accounts = report["accounts"].as_array().unwrap();
+        assert_eq!(accounts.len(), fixture.accounts, "seed {}", seed);
+
+        let pwned = accounts
+            .iter()
+            .filter(|account| account["pwned"] == true)
+            .count();
+        assert_eq!(report["summary"]["pwned"], pwned, "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_max_pwned_exit_code() {
+    for seed in 0..SEEDS {
+        let fixture = Fixture::generate("limit", seed);
+        let pwned = fixture.pwned.len();
+
+        // the limit itself is still allowed
+        let limit = pwned.to_string();
+        let (code, _) = check(&fixture, &["--max-pwned", &limit]);
+        assert_eq!(code, Some(0), "seed {}", seed);
+
+        if let Some(below) = pwned.checked_sub(1) {
+            let limit = below.to_string();
+            let (code, stdout) = check(
+                &fixture,
+                &["--max-pwned", &limit, "--report-format", "json"],
+            );
+            assert_eq!(code, Some(PWNED_EXIT_CODE), "seed {}", seed);
+
+            let report: Value = serde_json::from_str(&stdout).unwrap();
+            assert_eq!(report["summary"]["max_pwned"], below, "seed {}", seed);
+            assert_eq!(report["summary"]["exceeded"], true, "seed {}", seed);
+        }
+    }
+}
+
+#[test]
+fn test_failed_check_exit_code() {
+    for seed in 0..SEEDS {
+        let fixture = Fixture::generate("failed", seed);
+
+        // a scan that couldn't run must not pass the limit of a CI gate
+        let output = Command::cargo_bin("pwned-check")
+            .unwrap()
+            .args(["--max-pwned", "1000"])
+            .arg(fixture.path("export.csv"))
+            .arg(fixture.path("missing.txt"))
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(CHECK_FAILED_EXIT_CODE),
+            "seed {}",
+            seed
+        );
+
+        let output = Command::cargo_bin("pwned-check")
+            .unwrap()
+            .arg(fixture.path("missing.csv"))
+            .arg(fixture.path("corpus.txt"))
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(CHECK_FAILED_EXIT_CODE),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+#[cfg(all(unix, feature = "ctrl-c"))]
+fn test_cancelled_exit_code() {
+    use std::io::{BufRead, BufReader};
+
+    use assert_cmd::cargo::CommandCargoExt;
+
+    let fixture = Fixture::generate("cancel", 0);
+
+    // all lines are in the range of the saved hash (5BAA6...), so the linear scan can't jump over
+    // them and takes long enough to be interrupted
+    fs::write(
+        fixture.path("export.csv"),
+        "name,url,username,password\nexample,https://example.com/,user,password\n",
+    )
+    .unwrap();
+    let corpus: String = (0..200_000u64)
+        .map(|line| format!("5B{:038X}:1\n", line))
+        .collect();
+    fs::write(fixture.path("corpus.txt"), corpus).unwrap();
+
+    let mut child = process::Command::cargo_bin("pwned-check")
+        .unwrap()
+        .args(["--strategy", "linear"])
+        .arg(fixture.path("export.csv"))
+        .arg(fixture.path("corpus.txt"))
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the scan starts after sorting the saved hashes - the progress is logged to the standard output
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while stdout.read_line(&mut line).unwrap() > 0 && !line.contains("Sorted") {
+        line.clear();
+    }
+
+    let killed = process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    // incomplete results must not pass a CI gate as clean
+    assert!(stderr.contains("incomplete"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(CHECK_FAILED_EXIT_CODE));
+}
+
+fn hook(fixture: &Fixture, files: &[&Path]) -> (Option<i32>, String) {
+    let output = Command::cargo_bin("pwned-check")
+        .unwrap()
+        .arg("hook")
+        .arg("--corpus")
+        .arg(fixture.path("corpus.txt"))
+        .args(files)
+        .output()
+        .unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_hook_exit_codes() {
+    for seed in 0..SEEDS {
+        let fixture = Fixture::generate("hook", seed);
+        let export = fixture.path("export.csv");
+
+        let (code, stderr) = hook(&fixture, &[&export]);
+        let blocked = stderr
+            .lines()
+            .filter(|line| line.contains("contains a password that has been pwned"))
+            .count();
+        assert_eq!(blocked, fixture.pwned.len(), "seed {}", seed);
+
+        let expected = if fixture.pwned.is_empty() {
+            0
+        } else {
+            PWNED_EXIT_CODE
+        };
+        assert_eq!(code, Some(expected), "seed {}", seed);
+
+        // files that can't be checked block the commit as well
+        let missing = fixture.path("missing.csv");
+        let (code, _) = hook(&fixture, &[&export, &missing]);
+        assert_eq!(code, Some(CHECK_FAILED_EXIT_CODE), "seed {}", seed);
+    }
+}
@@ -0,0 +1,101 @@
+//! The plaintext passwords of the fixtures must never appear in any output of the real binary -
+//! neither in the reports nor in the verbose logs or the error messages of rejected requests.
+
+use std::{env, fs, path::PathBuf, process};
+
+use assert_cmd::Command;
+
+/// Passwords of the fixture exports that are specific enough to be searched in the output
+const FIXTURE_PASSWORDS: [&str; 3] = ["correct horse battery staple", "hunter2", "letmein"];
+
+fn fixture(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect()
+}
+
+/// Standard output and error combined, because the logs are written to both
+fn run(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    let mut combined = String::from_utf8(output.stdout).unwrap();
+    combined.push_str(&String::from_utf8(output.stderr).unwrap());
+    combined
+}
+
+fn assert_redacted(output: &str, context: &str) {
+    for password in &FIXTURE_PASSWORDS {
+        assert!(
+            !output.contains(password),
+            "{:?} appeared in the output of {}",
+            password,
+            context
+        );
+    }
+}
+
+#[test]
+fn test_reports_redacted() {
+    let options: [&[&str]; 4] = [
+        &["-v", "--show-clean"],
+        &["-v", "--show-clean", "--report-format", "json"],
+        &["-v", "--show-clean", "--report-format", "ndjson"],
+        &["-v", "--health"],
+    ];
+
+    for export in &["chromium.csv", "lastpass.csv", "bitwarden.json"] {
+        for options in &options {
+            let output = run(Command::cargo_bin("pwned-check")
+                .unwrap()
+                .arg(fixture(export))
+                .arg(fixture("corpus.txt"))
+                .args(*options));
+            assert_redacted(&output, &format!("{} {:?}", export, options));
+        }
+    }
+}
+
+#[test]
+fn test_hook_redacted() {
+    let directory = env::temp_dir().join(format!("pwned-check-redaction-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let dotenv = directory.join(".env");
+    fs::write(
+        &dotenv,
+        "DB_PASSWORD=hunter2\nSMTP_PASSWORD=correct horse battery staple\n",
+    )
+    .unwrap();
+
+    let output = run(Command::cargo_bin("pwned-check")
+        .unwrap()
+        .args(["-v", "hook", "--corpus"])
+        .arg(fixture("corpus.txt"))
+        .arg(&dotenv));
+    fs::remove_dir_all(&directory).unwrap();
+    assert_redacted(&output, "hook");
+}
+
+/// Length prefixed message of the native messaging protocol
+fn message(json: &str) -> Vec<u8> {
+    let mut message = (json.len() as u32).to_ne_bytes().to_vec();
+    message.extend_from_slice(json.as_bytes());
+    message
+}
+
+#[test]
+fn test_native_host_redacted() {
+    // valid requests, a password in the hash field and a password of the wrong type
+    let mut input = message(r#"{"id": 1, "password": "hunter2"}"#);
+    input.extend(message(r#"{"id": 2, "sha1": "letmein"}"#));
+    input.extend(message(
+        r#"{"id": 3, "password": {"value": "correct horse battery staple"}}"#,
+    ));
+
+    let output = run(Command::cargo_bin("pwned-check")
+        .unwrap()
+        .args(["-v", "native-host"])
+        .env("PWNED_CHECK_CORPUS", fixture("corpus.txt"))
+        .write_stdin(input));
+    assert_redacted(&output, "native-host");
+    // all three were answered - the invalid one without its id
+    assert!(output.contains("after 3 requests"), "{}", output);
+}